@@ -0,0 +1,204 @@
+//! Single order book operations at three book sizes.
+//!
+//! Each book holds 1k, 100k or 1M resting orders, ten to a level, half on
+//! each side of 100.00 on a 0.01 grid. `insert_resting` fills an empty book
+//! to that size. The rest time one operation against a full book, putting
+//! it back afterwards, untimed, so every sample sees the same book:
+//!
+//! - `insert_no_match` rests an order at a random level, touch included;
+//! - `cancel_by_id` cancels a random resting order;
+//! - `sweep` sends a buy that takes the best 1, 10 or 100 ask levels.
+//!
+//! Throughput is reported in orders, or in levels swept. Run with
+//! `cargo bench --bench book_operations`.
+
+use clob_backend::engine::{Order, OrderBook, OrderId, PriceGrid, Side};
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use rand::{rngs::StdRng, seq::index, Rng, SeedableRng};
+use rust_decimal::Decimal;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+/// Resting orders in each book
+const DEPTHS: [usize; 3] = [1_000, 100_000, 1_000_000];
+
+/// Resting orders per price level
+const PER_LEVEL: usize = 10;
+
+/// Operations timed per batch before the book is restored
+const BATCH: usize = 1_000;
+
+/// Ask levels a sweep takes
+const SWEEPS: [usize; 3] = [1, 10, 100];
+
+fn empty_book() -> OrderBook {
+    OrderBook::new("BTC/USD").with_price_grid(PriceGrid::new(Decimal::new(1, 2)))
+}
+
+/// Price of the `level`th level out from the touch on `side`, in cents
+fn cents(side: Side, level: usize) -> i64 {
+    match side {
+        Side::Buy => 10_000 - level as i64,
+        Side::Sell => 10_001 + level as i64,
+    }
+}
+
+/// `depth` orders of one lot, alternating sides, filling each side's levels
+/// from the touch outwards
+fn resting_orders(depth: usize) -> Vec<Order> {
+    (0..depth)
+        .map(|i| {
+            let side = if i % 2 == 0 { Side::Buy } else { Side::Sell };
+            let level = i / 2 / PER_LEVEL;
+            Order::new(side, Decimal::new(cents(side, level), 2), Decimal::ONE)
+        })
+        .collect()
+}
+
+/// A full book and the ids of its orders, oldest first
+fn full_book(depth: usize) -> (OrderBook, Vec<(OrderId, Side, Decimal)>) {
+    let mut book = empty_book();
+    let mut ids = Vec::with_capacity(depth);
+    for order in resting_orders(depth) {
+        ids.push((order.id, order.side, order.price));
+        book.add_order(order);
+    }
+    (book, ids)
+}
+
+fn insert_resting(c: &mut Criterion) {
+    let mut group = c.benchmark_group("insert_resting");
+    group.sample_size(10);
+    for depth in DEPTHS {
+        let orders = resting_orders(depth);
+        group.throughput(Throughput::Elements(depth as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(depth), &orders, |b, orders| {
+            b.iter_batched(
+                || orders.clone(),
+                |orders| {
+                    let mut book = empty_book();
+                    for order in orders {
+                        book.add_order(order);
+                    }
+                    book
+                },
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+fn insert_no_match(c: &mut Criterion) {
+    let mut group = c.benchmark_group("insert_no_match");
+    group.throughput(Throughput::Elements(1));
+    for depth in DEPTHS {
+        let (mut book, _) = full_book(depth);
+        let levels = depth / 2 / PER_LEVEL;
+        let mut rng = StdRng::seed_from_u64(1);
+        group.bench_function(BenchmarkId::from_parameter(depth), |b| {
+            b.iter_custom(|iters| {
+                let mut elapsed = Duration::ZERO;
+                let mut done = 0;
+                while done < iters {
+                    let batch: Vec<Order> = (0..BATCH)
+                        .map(|_| {
+                            let side = if rng.gen_bool(0.5) { Side::Buy } else { Side::Sell };
+                            let price = Decimal::new(cents(side, rng.gen_range(0..levels)), 2);
+                            Order::new(side, price, Decimal::ONE)
+                        })
+                        .collect();
+                    let ids: Vec<OrderId> = batch.iter().map(|o| o.id).collect();
+
+                    let start = Instant::now();
+                    for order in batch {
+                        book.add_order(order);
+                    }
+                    elapsed += start.elapsed();
+
+                    for id in ids {
+                        book.cancel_order(id);
+                    }
+                    done += BATCH as u64;
+                }
+                elapsed.mul_f64(iters as f64 / done as f64)
+            })
+        });
+    }
+    group.finish();
+}
+
+fn cancel_by_id(c: &mut Criterion) {
+    let mut group = c.benchmark_group("cancel_by_id");
+    group.throughput(Throughput::Elements(1));
+    for depth in DEPTHS {
+        let (mut book, mut ids) = full_book(depth);
+        let mut rng = StdRng::seed_from_u64(2);
+        group.bench_function(BenchmarkId::from_parameter(depth), |b| {
+            b.iter_custom(|iters| {
+                let mut elapsed = Duration::ZERO;
+                let mut done = 0;
+                while done < iters {
+                    let picks = index::sample(&mut rng, ids.len(), BATCH.min(ids.len()));
+
+                    let start = Instant::now();
+                    for pick in picks.iter() {
+                        black_box(book.cancel_order(ids[pick].0));
+                    }
+                    elapsed += start.elapsed();
+
+                    // Back at the end of their levels' queues, under new ids
+                    for pick in picks.iter() {
+                        let (_, side, price) = ids[pick];
+                        let order = Order::new(side, price, Decimal::ONE);
+                        ids[pick].0 = order.id;
+                        book.add_order(order);
+                    }
+                    done += picks.len() as u64;
+                }
+                elapsed.mul_f64(iters as f64 / done as f64)
+            })
+        });
+    }
+    group.finish();
+}
+
+fn sweep(c: &mut Criterion) {
+    let mut group = c.benchmark_group("sweep");
+    for depth in DEPTHS {
+        let (mut book, _) = full_book(depth);
+        let levels = depth / 2 / PER_LEVEL;
+        for swept in SWEEPS.into_iter().filter(|&k| k <= levels) {
+            group.throughput(Throughput::Elements(swept as u64));
+            let id = BenchmarkId::new(format!("{swept}_levels"), depth);
+            group.bench_function(id, |b| {
+                b.iter_custom(|iters| {
+                    let mut elapsed = Duration::ZERO;
+                    for _ in 0..iters {
+                        let limit = Decimal::new(cents(Side::Sell, swept - 1), 2);
+                        let taker = Order::new(Side::Buy, limit, Decimal::from(swept * PER_LEVEL));
+
+                        let start = Instant::now();
+                        black_box(book.match_order_outcome(taker));
+                        elapsed += start.elapsed();
+
+                        for level in 0..swept {
+                            for _ in 0..PER_LEVEL {
+                                book.add_order(Order::new(
+                                    Side::Sell,
+                                    Decimal::new(cents(Side::Sell, level), 2),
+                                    Decimal::ONE,
+                                ));
+                            }
+                        }
+                    }
+                    elapsed
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, insert_resting, insert_no_match, cancel_by_id, sweep);
+criterion_main!(benches);
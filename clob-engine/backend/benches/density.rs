@@ -0,0 +1,65 @@
+//! Matching and depth reads at different book densities.
+//!
+//! Each workload sends 200k seeded limit orders priced uniformly within
+//! `width` ticks of a mid that random-walks a tick at a time, and reads ten
+//! levels of depth on each side after every order, as the engine does when
+//! it publishes a book update. Narrow widths keep every level within a few
+//! hundred ticks of the touch; wide ones spread the book far beyond it.
+//!
+//! Against plain `BTreeMap` sides the ladder is level at width 50 and
+//! slower beyond it, most of all on the widest book, where the thin area
+//! around the touch keeps emptying the window and re-centring it.
+//!
+//! Run with `cargo bench --bench density`; `--save-baseline` /
+//! `--baseline` compare two trees.
+
+use clob_backend::engine::{MatchOutcome, Order, OrderBook, PriceGrid, Side};
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rust_decimal::Decimal;
+use std::hint::black_box;
+
+/// Orders per iteration
+const ORDERS: usize = 200_000;
+
+/// Half-widths, in 0.01 ticks, of the band orders are priced in
+const WIDTHS: [i64; 3] = [50, 500, 50_000];
+
+fn workload(width: i64) -> Vec<Order> {
+    let mut rng = StdRng::seed_from_u64(7);
+    let mut mid: i64 = 10_000_000;
+    (0..ORDERS)
+        .map(|_| {
+            mid += rng.gen_range(-1..=1);
+            let side = if rng.gen_bool(0.5) { Side::Buy } else { Side::Sell };
+            let price = Decimal::new(mid + rng.gen_range(-width..=width), 2);
+            Order::new(side, price, Decimal::from(rng.gen_range(1..=10)))
+        })
+        .collect()
+}
+
+fn run(orders: Vec<Order>) -> OrderBook {
+    let mut book = OrderBook::new("BTC/USD").with_price_grid(PriceGrid::new(Decimal::new(1, 2)));
+    let mut outcome = MatchOutcome::default();
+    for order in orders {
+        book.match_order_into(order, &mut outcome);
+        black_box(&outcome);
+        black_box((book.bid_depth(10), book.ask_depth(10)));
+    }
+    book
+}
+
+fn density(c: &mut Criterion) {
+    let mut group = c.benchmark_group("density");
+    group.sample_size(10);
+    for width in WIDTHS {
+        let orders = workload(width);
+        group.bench_function(format!("width_{width}_ticks"), |b| {
+            b.iter_batched(|| orders.clone(), run, BatchSize::LargeInput)
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, density);
+criterion_main!(benches);
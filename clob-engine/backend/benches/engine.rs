@@ -0,0 +1,126 @@
+//! The full `process_order` path at three book sizes.
+//!
+//! Each engine is filled through `process_command` with 1k, 100k or 1M
+//! resting orders, laid out as in the `book_operations` bench, then timed
+//! on pairs of orders: a lot resting at the best ask and a buy that takes
+//! the oldest lot there, so the book keeps its size. Every pair runs
+//! validation, matching, the snapshot update and the broadcast of its
+//! events, with no subscribers and with 64 that never read. A broadcast
+//! send stores one copy of the event however many receivers there are, so
+//! this measures the engine's side only; each subscriber pays its own
+//! clone when it receives.
+//!
+//! `order_stream` sends 100k of the same orders through `process_batch`
+//! in batches of 1, 64 and 256, as `run` takes them off a busy queue. A
+//! batch of one publishes the book after every order, as the engine did
+//! before batching.
+//!
+//! Throughput is reported in orders. Run with `cargo bench --bench engine`.
+
+use clob_backend::engine::{EngineBuilder, EngineCommand, MatchingEngine, OrderRequest, SequencedEvent, Side};
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use rust_decimal::Decimal;
+use tokio::sync::broadcast;
+
+/// Resting orders in each book
+const DEPTHS: [usize; 3] = [1_000, 100_000, 1_000_000];
+
+/// Resting orders per price level
+const PER_LEVEL: usize = 10;
+
+/// Idle receivers held in the "many subscribers" case
+const SUBSCRIBERS: [usize; 2] = [0, 64];
+
+/// Best ask, in cents
+const BEST_ASK: i64 = 10_001;
+
+/// Orders in each `order_stream` run
+const STREAM: usize = 100_000;
+
+/// Commands per batch in `order_stream`; 1 publishes after every order
+const BATCH_SIZES: [usize; 3] = [1, 64, 256];
+
+/// An engine holding `depth` resting orders, and `subscribers` receivers
+/// on its event stream
+fn full_engine(depth: usize, subscribers: usize) -> (MatchingEngine, Vec<broadcast::Receiver<SequencedEvent>>) {
+    let (mut engine, handle) = EngineBuilder::new("BTC/USD").tick_size(Decimal::new(1, 2)).build();
+    for i in 0..depth {
+        let level = (i / 2 / PER_LEVEL) as i64;
+        let (side, cents) = if i % 2 == 0 {
+            (Side::Buy, BEST_ASK - 1 - level)
+        } else {
+            (Side::Sell, BEST_ASK + level)
+        };
+        engine.process_command(EngineCommand::Submit(OrderRequest::new(
+            side,
+            Decimal::new(cents, 2),
+            Decimal::ONE,
+        )));
+    }
+    let receivers = (0..subscribers).map(|_| handle.subscribe()).collect();
+    (engine, receivers)
+}
+
+fn process_order(c: &mut Criterion) {
+    let mut group = c.benchmark_group("process_order");
+    group.throughput(Throughput::Elements(2));
+    let best_ask = Decimal::new(BEST_ASK, 2);
+    for depth in DEPTHS {
+        for subscribers in SUBSCRIBERS {
+            let (mut engine, _receivers) = full_engine(depth, subscribers);
+            let id = BenchmarkId::new(format!("{subscribers}_subscribers"), depth);
+            group.bench_function(id, |b| {
+                b.iter(|| {
+                    engine.process_command(EngineCommand::Submit(OrderRequest::new(
+                        Side::Sell,
+                        best_ask,
+                        Decimal::ONE,
+                    )));
+                    engine.process_command(EngineCommand::Submit(OrderRequest::new(
+                        Side::Buy,
+                        best_ask,
+                        Decimal::ONE,
+                    )));
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+/// `orders` submissions that alternate a lot resting at the best ask and
+/// a buy taking it, so the book ends at the size it started
+fn order_stream(orders: usize) -> Vec<EngineCommand> {
+    let best_ask = Decimal::new(BEST_ASK, 2);
+    (0..orders)
+        .map(|i| {
+            let side = if i % 2 == 0 { Side::Sell } else { Side::Buy };
+            EngineCommand::Submit(OrderRequest::new(side, best_ask, Decimal::ONE))
+        })
+        .collect()
+}
+
+fn process_batch(c: &mut Criterion) {
+    let mut group = c.benchmark_group("order_stream");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(STREAM as u64));
+    let (mut engine, _receivers) = full_engine(1_000, 0);
+    for batch_size in BATCH_SIZES {
+        group.bench_function(BenchmarkId::new("batch", batch_size), |b| {
+            b.iter_batched(
+                || order_stream(STREAM),
+                |commands| {
+                    let mut commands = commands.into_iter().peekable();
+                    while commands.peek().is_some() {
+                        engine.process_batch(commands.by_ref().take(batch_size));
+                    }
+                },
+                BatchSize::PerIteration,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, process_order, process_batch);
+criterion_main!(benches);
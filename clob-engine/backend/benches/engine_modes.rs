@@ -0,0 +1,183 @@
+//! Submit-to-trade latency with the engine on a runtime worker or on a
+//! thread of its own, while HTTP clients keep the server busy.
+//!
+//! Each mode serves one engine over HTTP on a four-worker runtime.
+//! `LOAD_CLIENTS` connections post pairs of orders at 90 that trade with
+//! each other, each waiting for its response before the next. A probe
+//! posts a buy at 1000 every millisecond on its own connection; a large
+//! ask at 101 keeps the ask side from emptying, so every probe trades,
+//! and its latency runs from writing the request to a subscriber seeing
+//! the trade. After a warm-up, `PROBES` are timed and the percentiles
+//! printed with the load's order rate. The pinned mode is only run on
+//! machines with more than one core.
+//!
+//! This is a plain binary rather than a criterion bench, since the tail
+//! is what's compared. Run with `cargo bench --bench engine_modes`.
+
+use clob_backend::api;
+use clob_backend::engine::{EngineBuilder, EngineEvent, EngineMode, OrderRequest, Side};
+use rust_decimal::Decimal;
+use std::future::IntoFuture;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::sync::broadcast::error::RecvError;
+
+/// Connections posting the background load
+const LOAD_CLIENTS: usize = 32;
+
+/// Probes timed per mode, after `WARM_UP` untimed ones
+const PROBES: usize = 2_000;
+const WARM_UP: usize = 200;
+
+fn main() {
+    let cores = std::thread::available_parallelism().map_or(1, usize::from);
+    let mut modes = vec![("task", EngineMode::Task), ("thread", EngineMode::Thread { core: None })];
+    if cores > 1 {
+        modes.push(("pinned thread", EngineMode::Thread { core: Some(cores - 1) }));
+    }
+    println!("{cores} core(s), {LOAD_CLIENTS} load connections, {PROBES} probes per mode");
+    println!("{:<14} {:>9} {:>9} {:>9} {:>9} {:>12}", "mode", "p50", "p90", "p99", "max", "load orders/s");
+    for (name, mode) in modes {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(4)
+            .enable_all()
+            .build()
+            .unwrap();
+        let (mut latencies, load_rate) = runtime.block_on(measure(mode));
+        latencies.sort();
+        let at = |q: f64| latencies[((latencies.len() - 1) as f64 * q) as usize];
+        println!(
+            "{:<14} {:>9.1?} {:>9.1?} {:>9.1?} {:>9.1?} {:>12.0}",
+            name,
+            at(0.5),
+            at(0.9),
+            at(0.99),
+            latencies[latencies.len() - 1],
+            load_rate
+        );
+    }
+}
+
+/// Probe latencies under load for an engine in `mode`, and the load's rate
+async fn measure(mode: EngineMode) -> (Vec<Duration>, f64) {
+    let (engine, handle) = EngineBuilder::new("BTC/USD").mode(mode).event_buffer_size(1 << 16).build();
+    let handle = Arc::new(handle);
+    tokio::spawn(engine.run());
+    handle
+        .submit_order(OrderRequest::new(Side::Sell, Decimal::from(101), Decimal::from(1_000_000_000)))
+        .await
+        .unwrap();
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(axum::serve(listener, api::router(handle.clone())).into_future());
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let posted = Arc::new(AtomicU64::new(0));
+    let load: Vec<_> = (0..LOAD_CLIENTS)
+        .map(|_| {
+            let (stop, posted) = (stop.clone(), posted.clone());
+            tokio::spawn(async move {
+                let mut connection = connect(&addr).await;
+                while !stop.load(Ordering::Relaxed) {
+                    for side in ["sell", "buy"] {
+                        post(&mut connection, &order_json(side, 90, None)).await;
+                    }
+                    posted.fetch_add(2, Ordering::Relaxed);
+                }
+            })
+        })
+        .collect();
+
+    let mut events = handle.subscribe();
+    let mut probe = connect(&addr).await;
+    let mut latencies = Vec::with_capacity(PROBES);
+    let started = Instant::now();
+    let posted_before = posted.load(Ordering::Relaxed);
+    for n in 0..WARM_UP + PROBES {
+        let client_order_id = format!("probe-{n}");
+        let sent = Instant::now();
+        write_post(&mut probe, &order_json("buy", 1000, Some(&client_order_id))).await;
+        loop {
+            match events.recv().await {
+                Ok(event) => {
+                    if let EngineEvent::Trade(trade) = &event.event {
+                        if trade.taker_client_order_id.as_deref() == Some(client_order_id.as_str()) {
+                            break;
+                        }
+                    }
+                }
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => panic!("engine stopped"),
+            }
+        }
+        let latency = sent.elapsed();
+        read_response(&mut probe).await;
+        if n >= WARM_UP {
+            latencies.push(latency);
+        }
+        tokio::time::sleep(Duration::from_millis(1)).await;
+    }
+    let load_rate = (posted.load(Ordering::Relaxed) - posted_before) as f64 / started.elapsed().as_secs_f64();
+
+    stop.store(true, Ordering::Relaxed);
+    for client in load {
+        client.await.unwrap();
+    }
+    handle.shutdown().await;
+    (latencies, load_rate)
+}
+
+fn order_json(side: &str, price: u32, client_order_id: Option<&str>) -> String {
+    match client_order_id {
+        Some(id) => format!(r#"{{"side":"{side}","price":"{price}","quantity":"1","client_order_id":"{id}"}}"#),
+        None => format!(r#"{{"side":"{side}","price":"{price}","quantity":"1"}}"#),
+    }
+}
+
+async fn connect(addr: &std::net::SocketAddr) -> BufReader<TcpStream> {
+    let stream = TcpStream::connect(addr).await.unwrap();
+    stream.set_nodelay(true).unwrap();
+    BufReader::new(stream)
+}
+
+/// Post an order and wait for the response
+async fn post(connection: &mut BufReader<TcpStream>, body: &str) {
+    write_post(connection, body).await;
+    read_response(connection).await;
+}
+
+async fn write_post(connection: &mut BufReader<TcpStream>, body: &str) {
+    let request = format!(
+        "POST /api/orders HTTP/1.1\r\nHost: bench\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+        body.len(),
+        body
+    );
+    connection.get_mut().write_all(request.as_bytes()).await.unwrap();
+}
+
+/// Read one response off a kept-alive connection, checking it succeeded
+async fn read_response(connection: &mut BufReader<TcpStream>) {
+    let mut line = String::new();
+    connection.read_line(&mut line).await.unwrap();
+    assert!(line.starts_with("HTTP/1.1 2"), "order refused: {line}");
+    let mut length = 0;
+    loop {
+        line.clear();
+        connection.read_line(&mut line).await.unwrap();
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse().unwrap();
+            }
+        }
+    }
+    let mut body = vec![0; length];
+    connection.read_exact(&mut body).await.unwrap();
+}
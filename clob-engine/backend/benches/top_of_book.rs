@@ -0,0 +1,59 @@
+//! Top-of-book reads on a deep book.
+//!
+//! `cached` reads the prices `OrderBook` keeps in step with its levels;
+//! `tree` walks to the first and last key of a `BTreeMap` with the same
+//! levels, which is what every read cost before the cache. With the tree
+//! hot in cache the two are close; the cached read stays flat as the book
+//! grows and doesn't touch the tree at all. Run with
+//! `cargo bench --bench top_of_book`.
+
+use clob_backend::engine::{Order, OrderBook, Side};
+use criterion::{criterion_group, criterion_main, Criterion};
+use rust_decimal::Decimal;
+use std::collections::BTreeMap;
+use std::hint::black_box;
+
+/// Resting orders on each side
+const DEPTH: i64 = 100_000;
+
+/// A book with `DEPTH` bids and `DEPTH` asks, one per cent apart
+fn deep_book() -> OrderBook {
+    let mut book = OrderBook::new("BTC/USD");
+    for i in 0..DEPTH {
+        book.add_order(Order::new(Side::Buy, Decimal::new(1_000_000 - i, 2), Decimal::ONE));
+        book.add_order(Order::new(Side::Sell, Decimal::new(1_000_001 + i, 2), Decimal::ONE));
+    }
+    book
+}
+
+fn top_of_book(c: &mut Criterion) {
+    let mut book = deep_book();
+    let tree: BTreeMap<Decimal, ()> = (0..DEPTH).map(|i| (Decimal::new(1_000_000 - i, 2), ())).collect();
+
+    let mut group = c.benchmark_group("best_bid_ask");
+    group.bench_function("cached", |b| {
+        b.iter(|| black_box((black_box(&book).best_bid(), black_box(&book).best_ask())))
+    });
+    group.bench_function("tree", |b| {
+        b.iter(|| {
+            let tree = black_box(&tree);
+            black_box((tree.keys().next_back().copied(), tree.keys().next().copied()))
+        })
+    });
+    group.finish();
+
+    // The engine's per-order pattern: rest an order inside the spread, read
+    // the top for the broadcast, then take it out again
+    c.bench_function("add_read_cancel_at_top", |b| {
+        b.iter(|| {
+            let order = Order::new(Side::Buy, Decimal::new(1_000_000, 2) + Decimal::new(5, 3), Decimal::ONE);
+            let id = order.id;
+            book.add_order(order);
+            black_box((book.best_visible_bid(), book.best_visible_ask()));
+            book.cancel_order(id);
+        })
+    });
+}
+
+criterion_group!(benches, top_of_book);
+criterion_main!(benches);
@@ -0,0 +1,302 @@
+//! Operational controls for the matching engine.
+
+use crate::engine::{EngineHandle, RateLimit};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// Response for an admin command
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AdminResponse {
+    pub success: bool,
+    pub message: String,
+}
+
+/// New book update settings; a field left out keeps its value
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct BookUpdatesRequest {
+    /// Levels per side each update carries, at least 1
+    pub depth_levels: Option<usize>,
+    /// Least time between updates; 0 sends every one
+    pub min_update_interval_ms: Option<u64>,
+}
+
+/// A new rate limit for one owner, or the default when `owner_id` is left
+/// out; leaving out `per_second` lifts the limit
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct RateLimitRequest {
+    pub owner_id: Option<String>,
+    /// Orders a second, at least 1
+    pub per_second: Option<u32>,
+    /// Most orders sent at once, at least 1; a second's worth if left out
+    pub burst: Option<u32>,
+}
+
+/// Map the result of handing a command to the engine to a response
+fn accepted<E>(result: Result<(), E>, message: &str) -> (StatusCode, Json<AdminResponse>) {
+    match result {
+        Ok(_) => (
+            StatusCode::ACCEPTED,
+            Json(AdminResponse {
+                success: true,
+                message: message.to_string(),
+            }),
+        ),
+        Err(_) => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(AdminResponse {
+                success: false,
+                message: "Engine unavailable".to_string(),
+            }),
+        ),
+    }
+}
+
+/// Engage an owner's kill switch: cancel its orders and block new ones.
+/// The engine broadcasts `kill_switch_engaged` once applied.
+#[utoipa::path(
+    post,
+    path = "/api/admin/kill/{owner_id}",
+    tag = "admin",
+    params(("owner_id" = String, Path, description = "Owner to block")),
+    responses(
+        (status = 202, description = "Handed to the engine", body = AdminResponse),
+        (status = 503, description = "Engine unavailable", body = AdminResponse),
+    ),
+    security(("api_key" = []))
+)]
+pub async fn engage_kill_switch(
+    State(handle): State<Arc<EngineHandle>>,
+    Path(owner_id): Path<String>,
+) -> impl IntoResponse {
+    tracing::warn!(owner_id = %owner_id, "Kill switch requested");
+    accepted(handle.kill_switch(owner_id).await, "Kill switch request submitted")
+}
+
+/// Release an owner's kill switch so it may trade again
+#[utoipa::path(
+    delete,
+    path = "/api/admin/kill/{owner_id}",
+    tag = "admin",
+    params(("owner_id" = String, Path, description = "Owner to unblock")),
+    responses(
+        (status = 202, description = "Handed to the engine", body = AdminResponse),
+        (status = 503, description = "Engine unavailable", body = AdminResponse),
+    ),
+    security(("api_key" = []))
+)]
+pub async fn release_kill_switch(
+    State(handle): State<Arc<EngineHandle>>,
+    Path(owner_id): Path<String>,
+) -> impl IntoResponse {
+    tracing::warn!(owner_id = %owner_id, "Kill switch release requested");
+    accepted(handle.release_kill_switch(owner_id).await, "Kill switch release submitted")
+}
+
+/// Void an executed trade. The engine broadcasts `trade_busted`, or
+/// `bust_rejected` if the trade is unknown or already busted.
+#[utoipa::path(
+    post,
+    path = "/api/admin/bust/{trade_id}",
+    tag = "admin",
+    params(("trade_id" = Uuid, Path, description = "Trade to void")),
+    responses(
+        (status = 202, description = "Handed to the engine", body = AdminResponse),
+        (status = 503, description = "Engine unavailable", body = AdminResponse),
+    ),
+    security(("api_key" = []))
+)]
+pub async fn bust_trade(
+    State(handle): State<Arc<EngineHandle>>,
+    Path(trade_id): Path<Uuid>,
+) -> impl IntoResponse {
+    tracing::warn!(trade_id = %trade_id, "Trade bust requested");
+    accepted(handle.bust_trade(handle.ids.lookup(trade_id)).await, "Trade bust request submitted")
+}
+
+/// Change how much depth book updates carry and how often they are sent.
+/// Takes effect from the engine's next update.
+#[utoipa::path(
+    put,
+    path = "/api/admin/book-updates",
+    tag = "admin",
+    request_body = BookUpdatesRequest,
+    responses(
+        (status = 202, description = "Handed to the engine", body = AdminResponse),
+        (status = 400, description = "depth_levels of 0", body = AdminResponse),
+        (status = 503, description = "Engine unavailable", body = AdminResponse),
+    ),
+    security(("api_key" = []))
+)]
+pub async fn set_book_updates(
+    State(handle): State<Arc<EngineHandle>>,
+    Json(req): Json<BookUpdatesRequest>,
+) -> impl IntoResponse {
+    if req.depth_levels == Some(0) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(AdminResponse {
+                success: false,
+                message: "depth_levels must be at least 1".to_string(),
+            }),
+        );
+    }
+    tracing::warn!(
+        depth_levels = ?req.depth_levels,
+        min_update_interval_ms = ?req.min_update_interval_ms,
+        "Book update settings change requested"
+    );
+    let interval = req.min_update_interval_ms.map(Duration::from_millis);
+    accepted(
+        handle.set_book_updates(req.depth_levels, interval).await,
+        "Book update settings submitted",
+    )
+}
+
+/// Change how fast an owner, or every owner without a limit of its own,
+/// may send orders. Orders over the limit are rejected as `RATE_LIMITED`.
+#[utoipa::path(
+    put,
+    path = "/api/admin/rate-limits",
+    tag = "admin",
+    request_body = RateLimitRequest,
+    responses(
+        (status = 202, description = "Handed to the engine", body = AdminResponse),
+        (status = 400, description = "per_second or burst of 0", body = AdminResponse),
+        (status = 503, description = "Engine unavailable", body = AdminResponse),
+    ),
+    security(("api_key" = []))
+)]
+pub async fn set_rate_limit(
+    State(handle): State<Arc<EngineHandle>>,
+    Json(req): Json<RateLimitRequest>,
+) -> impl IntoResponse {
+    let limit = req
+        .per_second
+        .map(|per_second| RateLimit::per_second(per_second).with_burst(req.burst.unwrap_or(per_second)));
+    if limit.is_some_and(|limit| limit.per_second == 0 || limit.burst == 0) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(AdminResponse {
+                success: false,
+                message: "per_second and burst must be at least 1".to_string(),
+            }),
+        );
+    }
+    tracing::warn!(owner_id = ?req.owner_id, limit = ?limit, "Rate limit change requested");
+    accepted(handle.set_rate_limit(req.owner_id, limit).await, "Rate limit submitted")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::test_support::{next_of_type, request, start_stack};
+    use crate::engine::ManualClock;
+    use axum::http::StatusCode;
+    use serde_json::json;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_kill_switch_blocks_until_released() {
+        let (app, mut ws) = start_stack(Arc::new(ManualClock::new(chrono::Utc::now()))).await;
+        let order = json!({ "owner_id": "rogue", "side": "buy", "price": "100", "quantity": "1" });
+        request(&app, "POST", "/api/orders", Some(order.clone())).await;
+
+        let (status, _) = request(&app, "POST", "/api/admin/kill/rogue", None).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        let cancelled = next_of_type(&mut ws, "order_cancelled").await;
+        assert_eq!(cancelled["reason"], "KILL_SWITCH");
+        let engaged = next_of_type(&mut ws, "kill_switch_engaged").await;
+        assert_eq!(engaged["owner_id"], "rogue");
+        assert_eq!(engaged["cancelled"], 1);
+
+        request(&app, "POST", "/api/orders", Some(order.clone())).await;
+        let rejected = next_of_type(&mut ws, "order_rejected").await;
+        assert_eq!(rejected["reason"], "OWNER_BLOCKED");
+
+        let (status, _) = request(&app, "DELETE", "/api/admin/kill/rogue", None).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        next_of_type(&mut ws, "kill_switch_released").await;
+        request(&app, "POST", "/api/orders", Some(order)).await;
+        let book = next_of_type(&mut ws, "order_book").await;
+        assert_eq!(book["best_bid"], "100");
+    }
+
+    #[tokio::test]
+    async fn test_bust_trade_once() {
+        let (app, mut ws) = start_stack(Arc::new(ManualClock::new(chrono::Utc::now()))).await;
+        for side in ["sell", "buy"] {
+            let order = json!({ "side": side, "price": "100", "quantity": "1" });
+            request(&app, "POST", "/api/orders", Some(order)).await;
+        }
+        let trade = next_of_type(&mut ws, "trade").await;
+        let uri = format!("/api/admin/bust/{}", trade["trade_id"].as_str().unwrap());
+
+        let (status, _) = request(&app, "POST", &uri, None).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        let busted = next_of_type(&mut ws, "trade_busted").await;
+        assert_eq!(busted["trade_id"], trade["trade_id"]);
+        assert_eq!(busted["quantity"], "1");
+
+        request(&app, "POST", &uri, None).await;
+        let rejected = next_of_type(&mut ws, "bust_rejected").await;
+        assert_eq!(rejected["reason"], "UNKNOWN_TRADE");
+    }
+
+    #[tokio::test]
+    async fn test_book_update_settings_change_at_runtime() {
+        let (app, mut ws) = start_stack(Arc::new(ManualClock::new(chrono::Utc::now()))).await;
+        for price in ["99", "98"] {
+            let order = json!({ "side": "buy", "price": price, "quantity": "1" });
+            request(&app, "POST", "/api/orders?confirm=true", Some(order)).await;
+        }
+        next_of_type(&mut ws, "order_book").await;
+        assert_eq!(next_of_type(&mut ws, "order_book").await["bids"].as_array().unwrap().len(), 2);
+
+        let (status, _) = request(&app, "PUT", "/api/admin/book-updates", Some(json!({ "depth_levels": 0 }))).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        // The new depth is published straight away
+        let (status, _) = request(&app, "PUT", "/api/admin/book-updates", Some(json!({ "depth_levels": 1 }))).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        let book = next_of_type(&mut ws, "order_book").await;
+        assert_eq!(book["bids"], json!([["99", "1", "1"]]));
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_rejects_orders_over_it() {
+        let (app, mut ws) = start_stack(Arc::new(ManualClock::new(chrono::Utc::now()))).await;
+        let body = json!({ "owner_id": "flood", "per_second": 0 });
+        let (status, _) = request(&app, "PUT", "/api/admin/rate-limits", Some(body)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let body = json!({ "owner_id": "flood", "per_second": 1 });
+        let (status, _) = request(&app, "PUT", "/api/admin/rate-limits", Some(body)).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+
+        // The clock stands still, so the bucket never refills
+        let order = json!({ "owner_id": "flood", "side": "buy", "price": "100", "quantity": "1" });
+        let (status, _) = request(&app, "POST", "/api/orders?confirm=true", Some(order.clone())).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, body) = request(&app, "POST", "/api/orders?confirm=true", Some(order.clone())).await;
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(body["code"], "RATE_LIMITED");
+        assert_eq!(next_of_type(&mut ws, "order_rejected").await["reason"], "RATE_LIMITED");
+
+        // Other owners are not limited, and lifting the limit frees this one
+        let other = json!({ "owner_id": "calm", "side": "buy", "price": "100", "quantity": "1" });
+        let (status, _) = request(&app, "POST", "/api/orders?confirm=true", Some(other)).await;
+        assert_eq!(status, StatusCode::OK);
+        let body = json!({ "owner_id": "flood" });
+        request(&app, "PUT", "/api/admin/rate-limits", Some(body)).await;
+        let (status, _) = request(&app, "POST", "/api/orders?confirm=true", Some(order)).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+}
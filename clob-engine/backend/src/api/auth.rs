@@ -0,0 +1,466 @@
+//! API key authentication.
+//!
+//! Callers present a key in the `X-API-Key` header. Keys are configured in
+//! a TOML file named by `CLOB_API_KEYS_FILE`, stored only as their SHA-256
+//! hashes, each with the owner it acts for and the scopes it is granted.
+//! Without a key file every endpoint is open, as before keys existed.
+//!
+//! Routes need one scope each: `read` for an owner's orders, `trade` to
+//! submit, modify and cancel, `admin` for operational controls. Market data
+//! needs no key unless the file sets `public_market_data = false`, and the
+//! health check and symbol list never do. A missing or unknown key is a
+//! 401; a key without the route's scope is a 403.
+
+use axum::{
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Environment variable naming the API key file; unset leaves the API open
+pub const API_KEYS_FILE_VAR: &str = "CLOB_API_KEYS_FILE";
+
+/// Header carrying the caller's key
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// What a key may do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Scope {
+    /// See an owner's orders, and market data when that is not public
+    Read,
+    /// Submit, modify and cancel orders and quotes
+    Trade,
+    /// Operational controls, and acting for any owner
+    Admin,
+}
+
+impl Scope {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Scope::Read => "read",
+            Scope::Trade => "trade",
+            Scope::Admin => "admin",
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Scope {
+    type Err = KeyStoreError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "read" => Ok(Scope::Read),
+            "trade" => Ok(Scope::Trade),
+            "admin" => Ok(Scope::Admin),
+            _ => Err(KeyStoreError::Invalid(format!("unknown scope {s:?}"))),
+        }
+    }
+}
+
+/// Whoever a request's key belongs to, attached to the request once the
+/// key is accepted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Principal {
+    /// Owner the key acts for
+    pub owner_id: String,
+    pub scopes: BTreeSet<Scope>,
+}
+
+impl Principal {
+    pub fn new(owner_id: impl Into<String>, scopes: impl IntoIterator<Item = Scope>) -> Self {
+        Self {
+            owner_id: owner_id.into(),
+            scopes: scopes.into_iter().collect(),
+        }
+    }
+
+    /// Whether the key was granted `scope`. Scopes are granted one by one:
+    /// `admin` does not bring `trade` with it.
+    pub fn allows(&self, scope: Scope) -> bool {
+        self.scopes.contains(&scope)
+    }
+
+    /// Whether the key may see `owner_id`'s orders: its owner's, or any
+    /// with an admin key
+    pub fn sees(&self, owner_id: Option<&str>) -> bool {
+        self.allows(Scope::Admin) || owner_id == Some(self.owner_id.as_str())
+    }
+}
+
+/// The owner a request acts for. Without keys configured, and for admin
+/// keys, it is whoever the request names. Any other key acts for its own
+/// owner, and may only name that one: `Err` holds why it may not act for
+/// the owner named.
+pub fn acting_owner(caller: Option<Extension<Principal>>, named: Option<String>) -> Result<Option<String>, String> {
+    let caller = match caller {
+        Some(Extension(caller)) if !caller.allows(Scope::Admin) => caller,
+        _ => return Ok(named),
+    };
+    match named.filter(|id| !id.trim().is_empty()) {
+        None => Ok(Some(caller.owner_id)),
+        Some(named) if named == caller.owner_id => Ok(Some(named)),
+        Some(named) => Err(format!("API key for {} cannot act for {named}", caller.owner_id)),
+    }
+}
+
+/// Why the API key file could not be loaded
+#[derive(Debug, thiserror::Error)]
+pub enum KeyStoreError {
+    #[error("cannot read {path}: {source}")]
+    Io { path: String, source: std::io::Error },
+    #[error("invalid TOML: {0}")]
+    Toml(String),
+    #[error("invalid API key: {0}")]
+    Invalid(String),
+}
+
+/// SHA-256 of a key, in lowercase hex, as the key file holds it
+pub fn hash_key(key: &str) -> String {
+    format!("{:x}", Sha256::digest(key.as_bytes()))
+}
+
+/// The configured keys, by hash
+#[derive(Debug, Clone)]
+pub struct KeyStore {
+    keys: HashMap<String, Principal>,
+    public_market_data: bool,
+}
+
+impl KeyStore {
+    /// No keys, with market data public
+    pub fn new() -> Self {
+        Self {
+            keys: HashMap::new(),
+            public_market_data: true,
+        }
+    }
+
+    /// The keys in `CLOB_API_KEYS_FILE`, or `None` when it is not set
+    pub fn load() -> Result<Option<Self>, KeyStoreError> {
+        let Ok(path) = std::env::var(API_KEYS_FILE_VAR) else {
+            return Ok(None);
+        };
+        let source = std::fs::read_to_string(&path).map_err(|source| KeyStoreError::Io { path, source })?;
+        Self::from_toml(&source).map(Some)
+    }
+
+    /// Parse a key file of `[[keys]]` tables.
+    ///
+    /// ```toml
+    /// # Market data needs a read key too
+    /// public_market_data = false
+    ///
+    /// # The key `alice-secret`
+    /// [[keys]]
+    /// owner_id = "alice"
+    /// sha256 = "0c848abb03307b06cf70cd4e29c157dc81af5e94ab3eb1d0c59a120269572376"
+    /// scopes = ["read", "trade"]
+    /// ```
+    pub fn from_toml(source: &str) -> Result<Self, KeyStoreError> {
+        let document = source
+            .parse::<toml_edit::DocumentMut>()
+            .map_err(|e| KeyStoreError::Toml(e.to_string()))?;
+        let mut store = Self::new();
+        if let Some(public) = document.get("public_market_data") {
+            store.public_market_data = public
+                .as_bool()
+                .ok_or_else(|| KeyStoreError::Toml("`public_market_data` must be true or false".to_string()))?;
+        }
+        let Some(keys) = document.get("keys") else {
+            return Ok(store);
+        };
+        let keys = keys
+            .as_array_of_tables()
+            .ok_or_else(|| KeyStoreError::Toml("`keys` must be an array of tables".to_string()))?;
+        for (index, table) in keys.iter().enumerate() {
+            let text = |field: &str| {
+                table
+                    .get(field)
+                    .and_then(|value| value.as_str())
+                    .map(str::to_string)
+                    .ok_or_else(|| KeyStoreError::Invalid(format!("keys[{index}] needs a string `{field}`")))
+            };
+            let owner_id = text("owner_id")?;
+            let hash = text("sha256")?.to_lowercase();
+            if hash.len() != 64 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(KeyStoreError::Invalid(format!("keys[{index}].sha256 is not a SHA-256 hex digest")));
+            }
+            let scopes = table
+                .get("scopes")
+                .and_then(|value| value.as_array())
+                .ok_or_else(|| KeyStoreError::Invalid(format!("keys[{index}] needs a `scopes` array")))?
+                .iter()
+                .map(|scope| scope.as_str().unwrap_or_default().parse())
+                .collect::<Result<Vec<Scope>, _>>()?;
+            if store.keys.insert(hash, Principal::new(owner_id, scopes)).is_some() {
+                return Err(KeyStoreError::Invalid(format!("keys[{index}] repeats an earlier key")));
+            }
+        }
+        Ok(store)
+    }
+
+    /// Accept `key` for `principal`
+    pub fn insert(&mut self, key: &str, principal: Principal) {
+        self.keys.insert(hash_key(key), principal);
+    }
+
+    /// Whether market data is served without a key
+    pub fn public_market_data(&self) -> bool {
+        self.public_market_data
+    }
+
+    pub fn set_public_market_data(&mut self, public: bool) {
+        self.public_market_data = public;
+    }
+
+    /// Whoever `key` belongs to
+    pub fn resolve(&self, key: &str) -> Option<&Principal> {
+        self.resolve_hash(&hash_key(key))
+    }
+
+    /// Whoever the key with [`hash_key`] `hash` belongs to
+    pub fn resolve_hash(&self, hash: &str) -> Option<&Principal> {
+        self.keys.get(hash)
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+}
+
+impl Default for KeyStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Middleware letting through only requests whose key has `scope`, with
+/// its [`Principal`] attached. Open when the server has no key store.
+pub async fn require_scope(
+    State(scope): State<Scope>,
+    keys: Option<Extension<Arc<KeyStore>>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    match keys {
+        Some(Extension(keys)) => authorize(&keys, scope, request, next).await,
+        None => next.run(request).await,
+    }
+}
+
+/// Middleware for market data: open unless the key store makes it need
+/// the `read` scope
+pub async fn market_data_access(keys: Option<Extension<Arc<KeyStore>>>, request: Request, next: Next) -> Response {
+    match keys {
+        Some(Extension(keys)) if !keys.public_market_data() => authorize(&keys, Scope::Read, request, next).await,
+        _ => next.run(request).await,
+    }
+}
+
+async fn authorize(keys: &KeyStore, scope: Scope, mut request: Request, next: Next) -> Response {
+    let refused = |status: StatusCode, message: String| {
+        (status, Json(serde_json::json!({ "success": false, "message": message }))).into_response()
+    };
+    let Some(key) = request.headers().get(API_KEY_HEADER) else {
+        return refused(StatusCode::UNAUTHORIZED, "X-API-Key header is required".to_string());
+    };
+    let Some(principal) = key.to_str().ok().and_then(|key| keys.resolve(key)) else {
+        return refused(StatusCode::UNAUTHORIZED, "Invalid API key".to_string());
+    };
+    if !principal.allows(scope) {
+        return refused(StatusCode::FORBIDDEN, format!("API key lacks the {scope} scope"));
+    }
+    request.extensions_mut().insert(principal.clone());
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::test_support::{request_with_headers, spawn_app};
+    use crate::engine::EngineBuilder;
+    use axum::Router;
+    use serde_json::{json, Value};
+
+    fn keys() -> KeyStore {
+        let mut keys = KeyStore::new();
+        keys.insert("alice-key", Principal::new("alice", [Scope::Read, Scope::Trade]));
+        keys.insert("viewer-key", Principal::new("viewer", [Scope::Read]));
+        keys.insert("ops-key", Principal::new("ops", [Scope::Read, Scope::Trade, Scope::Admin]));
+        keys
+    }
+
+    fn app(keys: KeyStore) -> Router {
+        spawn_app(EngineBuilder::new("BTC/USD")).layer(Extension(Arc::new(keys)))
+    }
+
+    async fn call(
+        app: &Router,
+        method: &str,
+        uri: &str,
+        key: Option<&str>,
+        body: Option<Value>,
+    ) -> (StatusCode, Value) {
+        let headers: Vec<_> = key.map(|key| (API_KEY_HEADER, key)).into_iter().collect();
+        request_with_headers(app, method, uri, &headers, body).await
+    }
+
+    #[tokio::test]
+    async fn test_routes_need_a_key_with_their_scope() {
+        let app = app(keys());
+        let order = || Some(json!({ "side": "buy", "price": "100", "quantity": "1" }));
+
+        let (status, body) = call(&app, "POST", "/api/orders", None, order()).await;
+        assert_eq!((status, body["success"].as_bool()), (StatusCode::UNAUTHORIZED, Some(false)));
+        let (status, _) = call(&app, "POST", "/api/orders", Some("guessed-key"), order()).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, body) = call(&app, "POST", "/api/orders", Some("viewer-key"), order()).await;
+        assert_eq!((status, &body["message"]), (StatusCode::FORBIDDEN, &json!("API key lacks the trade scope")));
+        let (status, _) = call(&app, "POST", "/api/orders", Some("alice-key"), order()).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+
+        // Reading orders needs a key too, market data does not
+        let (status, _) = call(&app, "GET", "/api/orders", None, None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        for uri in ["/api/health", "/api/symbols", "/api/orderbook", "/api/ticker", "/api/BTC-USD/trades"] {
+            let (status, _) = call(&app, "GET", uri, None, None).await;
+            assert_eq!(status, StatusCode::OK, "{uri}");
+        }
+
+        // Admin routes need the admin scope, whatever else a key has
+        let (status, _) = call(&app, "POST", "/api/admin/kill/rogue", Some("alice-key"), None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, _) = call(&app, "POST", "/api/BTC-USD/admin/kill/rogue", Some("ops-key"), None).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        let (status, _) = call(&app, "POST", "/api/simulation", Some("viewer-key"), Some(json!({}))).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_keys_act_for_their_owner() {
+        let app = app(keys());
+        let order = |owner: Option<&str>, price: &str| {
+            let mut order = json!({ "side": "buy", "price": price, "quantity": "1" });
+            if let Some(owner) = owner {
+                order["owner_id"] = json!(owner);
+            }
+            Some(order)
+        };
+        let submit = "/api/orders?confirm=true";
+        let (status, body) = call(&app, "POST", submit, Some("alice-key"), order(None, "100")).await;
+        assert_eq!(status, StatusCode::OK);
+        let alices = body["order_id"].as_str().unwrap().to_string();
+        let (status, _) = call(&app, "POST", submit, Some("alice-key"), order(Some("bob"), "99")).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        // An admin key trades for whoever it names
+        let (status, body) = call(&app, "POST", submit, Some("ops-key"), order(Some("bob"), "99")).await;
+        assert_eq!(status, StatusCode::OK);
+        let bobs = body["order_id"].as_str().unwrap().to_string();
+
+        let owners = |body: Value| -> Vec<String> {
+            let orders = body["orders"].as_array().unwrap().iter();
+            orders.map(|order| order["owner_id"].as_str().unwrap().to_string()).collect()
+        };
+        let (_, body) = call(&app, "GET", "/api/orders", Some("alice-key"), None).await;
+        assert_eq!(owners(body), ["alice"]);
+        let (status, _) = call(&app, "GET", "/api/orders?owner_id=bob", Some("alice-key"), None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (_, body) = call(&app, "GET", "/api/orders", Some("ops-key"), None).await;
+        assert_eq!(owners(body), ["bob", "alice"]);
+        let (status, _) = call(&app, "GET", &format!("/api/orders/{alices}"), Some("viewer-key"), None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = call(&app, "GET", &format!("/api/orders/{alices}"), Some("alice-key"), None).await;
+        assert_eq!(status, StatusCode::OK);
+
+        // Other owners' orders cannot be cancelled, one by one or all at once
+        let (status, _) = call(&app, "DELETE", &format!("/api/orders/{bobs}"), Some("alice-key"), None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let amend = || Some(json!({ "quantity": "2" }));
+        let (status, _) = call(&app, "PATCH", &format!("/api/orders/{bobs}"), Some("alice-key"), amend()).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, _) = call(&app, "PATCH", &format!("/api/orders/{alices}"), Some("alice-key"), amend()).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        let (status, _) = call(&app, "DELETE", "/api/orders?owner_id=bob", Some("alice-key"), None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, _) = call(&app, "DELETE", "/api/orders", Some("alice-key"), None).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        let (status, _) = call(&app, "DELETE", &format!("/api/orders/{bobs}"), Some("ops-key"), None).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_quotes_default_to_the_keys_owner() {
+        let app = app(keys());
+        let quote = || Some(json!({ "bid_price": "99", "bid_qty": "1", "ask_price": "101", "ask_qty": "1" }));
+        let (status, body) = call(&app, "POST", "/api/quotes", Some("alice-key"), quote()).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        let (_, listed) = call(&app, "GET", "/api/orders", Some("alice-key"), None).await;
+        let ids: Vec<_> = listed["orders"].as_array().unwrap().iter().map(|order| &order["order_id"]).collect();
+        assert_eq!(ids, [&body["bid_order_id"], &body["ask_order_id"]]);
+
+        // An admin key acts for nobody in particular, so it has to name the owner
+        let (status, _) = call(&app, "POST", "/api/quotes", Some("ops-key"), quote()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_market_data_can_need_a_key() {
+        let mut keys = keys();
+        keys.set_public_market_data(false);
+        let app = app(keys);
+        let (status, _) = call(&app, "GET", "/api/orderbook", None, None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, _) = call(&app, "GET", "/api/orderbook", Some("viewer-key"), None).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = call(&app, "GET", "/api/health", None, None).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[test]
+    fn test_key_file_holds_hashes() {
+        let source = format!(
+            "public_market_data = false\n\n\
+             [[keys]]\nowner_id = \"alice\"\nsha256 = \"{}\"\nscopes = [\"read\", \"trade\"]\n\n\
+             [[keys]]\nowner_id = \"ops\"\nsha256 = \"{}\"\nscopes = [\"Admin\"]\n",
+            hash_key("alice-secret"),
+            hash_key("ops-secret").to_uppercase(),
+        );
+        let keys = KeyStore::from_toml(&source).unwrap();
+        assert!(!keys.public_market_data());
+        assert_eq!(keys.resolve("alice-secret"), Some(&Principal::new("alice", [Scope::Read, Scope::Trade])));
+        assert_eq!(keys.resolve("ops-secret"), Some(&Principal::new("ops", [Scope::Admin])));
+        assert_eq!(keys.resolve(&hash_key("alice-secret")), None);
+        assert!(KeyStore::from_toml("").unwrap().public_market_data());
+
+        let hash = hash_key("key");
+        let key = format!("[[keys]]\nowner_id = \"a\"\nsha256 = \"{hash}\"\nscopes = []\n");
+        for invalid in [
+            format!("[[keys]]\nowner_id = \"a\"\nsha256 = \"{hash}\"\nscopes = [\"write\"]"),
+            "[[keys]]\nowner_id = \"a\"\nsha256 = \"abc\"\nscopes = []".to_string(),
+            format!("[[keys]]\nsha256 = \"{hash}\"\nscopes = []"),
+            format!("[[keys]]\nowner_id = \"a\"\nsha256 = \"{hash}\""),
+            // The same key twice
+            format!("{key}{key}"),
+            "keys = 1".to_string(),
+        ] {
+            assert!(KeyStore::from_toml(&invalid).is_err(), "{invalid}");
+        }
+    }
+}
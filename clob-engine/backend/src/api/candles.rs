@@ -0,0 +1,468 @@
+//! REST API for OHLCV candles.
+//!
+//! A task per engine folds its trades into candles of each interval, kept
+//! in memory a fixed number per interval. Candles are aligned to the Unix
+//! epoch: a one-minute candle covers `hh:mm:00.000` to `hh:mm:59.999`.
+//! Older candles than memory holds are aggregated from the trade journal
+//! when the server keeps one.
+
+use crate::api::openapi::{CandleInterval, ErrorResponse};
+use crate::engine::{EngineEvent, EngineHandle, Trade};
+use crate::persistence::{CandleArchive, CandleRecord};
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast::error::RecvError;
+use utoipa::{IntoParams, ToSchema};
+
+/// Candle intervals kept, by the name `?interval=` gives them, with their
+/// length in seconds
+pub const CANDLE_INTERVALS: [(&str, i64); 3] = [("1m", 60), ("5m", 300), ("1h", 3600)];
+
+/// Candles each interval keeps in memory
+pub const CANDLE_CAPACITY: usize = 1000;
+
+/// Intervals covered when `?limit=` is not given
+pub const DEFAULT_CANDLE_LIMIT: usize = 500;
+
+/// Most intervals one request covers, whatever `?limit=` asks for
+pub const MAX_CANDLE_LIMIT: usize = 1000;
+
+/// One interval's trading
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Candle {
+    /// Start of the interval, a multiple of its length since the epoch
+    pub start: DateTime<Utc>,
+    pub open: Decimal,
+    pub high: Decimal,
+    pub low: Decimal,
+    pub close: Decimal,
+    /// Base quantity traded
+    pub volume: Decimal,
+    pub trades: u64,
+}
+
+impl Candle {
+    fn opened(start: DateTime<Utc>, trade: &Trade) -> Self {
+        Self {
+            start,
+            open: trade.price,
+            high: trade.price,
+            low: trade.price,
+            close: trade.price,
+            volume: trade.quantity,
+            trades: 1,
+        }
+    }
+
+    fn add(&mut self, trade: &Trade) {
+        self.high = self.high.max(trade.price);
+        self.low = self.low.min(trade.price);
+        self.close = trade.price;
+        self.volume += trade.quantity;
+        self.trades += 1;
+    }
+
+    /// A quiet interval, carrying the last close forward
+    fn carried(start: DateTime<Utc>, close: Decimal) -> Self {
+        Self {
+            start,
+            open: close,
+            high: close,
+            low: close,
+            close,
+            volume: Decimal::ZERO,
+            trades: 0,
+        }
+    }
+}
+
+impl From<CandleRecord> for Candle {
+    fn from(record: CandleRecord) -> Self {
+        Self {
+            start: record.start,
+            open: record.open,
+            high: record.high,
+            low: record.low,
+            close: record.close,
+            volume: record.volume,
+            trades: record.trades.max(0) as u64,
+        }
+    }
+}
+
+/// Start of the `secs` long interval `timestamp` falls in
+fn interval_start(timestamp: DateTime<Utc>, secs: i64) -> DateTime<Utc> {
+    let start = timestamp.timestamp().div_euclid(secs) * secs;
+    Utc.timestamp_opt(start, 0).single().unwrap_or(timestamp)
+}
+
+/// One interval's candles
+#[derive(Debug)]
+struct Series {
+    secs: i64,
+    /// Oldest first
+    candles: VecDeque<Candle>,
+    /// Start of the oldest interval memory has every trade of
+    complete_from: DateTime<Utc>,
+}
+
+/// An engine's recent candles, kept by a task following its events.
+/// Busted trades are taken back out of their candle's volume and count;
+/// its prices are left as they were printed.
+#[derive(Debug)]
+pub struct CandleAggregator {
+    series: Mutex<Vec<Series>>,
+    capacity: usize,
+}
+
+impl CandleAggregator {
+    /// Start keeping `handle`'s last `capacity` candles of each interval.
+    /// Candles before now are only partly seen. Needs a Tokio runtime; the
+    /// task ends once the engine has stopped.
+    pub fn spawn(handle: &EngineHandle, capacity: usize) -> Arc<Self> {
+        let since = handle.clock.now();
+        let series = CANDLE_INTERVALS
+            .iter()
+            .map(|&(_, secs)| {
+                let start = interval_start(since, secs);
+                Series {
+                    secs,
+                    candles: VecDeque::new(),
+                    complete_from: if start == since { start } else { start + Duration::seconds(secs) },
+                }
+            })
+            .collect();
+        let aggregator = Arc::new(Self { series: Mutex::new(series), capacity });
+        let (mut events, keeper) = (handle.subscribe(), aggregator.clone());
+        let symbol = handle.config.symbol.clone();
+        tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => keeper.record(&event.event),
+                    Err(RecvError::Lagged(missed)) => {
+                        tracing::warn!(%symbol, missed, "Candles fell behind; trades in between are missing")
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+        aggregator
+    }
+
+    fn record(&self, event: &EngineEvent) {
+        let mut all = self.series.lock().unwrap();
+        for series in all.iter_mut() {
+            match event {
+                EngineEvent::Trade(trade) => {
+                    let start = interval_start(trade.timestamp, series.secs);
+                    match series.candles.binary_search_by_key(&start, |candle| candle.start) {
+                        Ok(index) => series.candles[index].add(trade),
+                        Err(index) => series.candles.insert(index, Candle::opened(start, trade)),
+                    }
+                    if series.candles.len() > self.capacity {
+                        if let Some(dropped) = series.candles.pop_front() {
+                            let next = dropped.start + Duration::seconds(series.secs);
+                            series.complete_from = series.complete_from.max(next);
+                        }
+                    }
+                }
+                EngineEvent::TradeBusted(trade) => {
+                    let start = interval_start(trade.timestamp, series.secs);
+                    if let Ok(index) = series.candles.binary_search_by_key(&start, |candle| candle.start) {
+                        let candle = &mut series.candles[index];
+                        candle.volume -= trade.quantity;
+                        candle.trades = candle.trades.saturating_sub(1);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// The `secs` long candles in memory starting from `from`, oldest
+    /// first, and the start of the oldest interval memory has in full
+    fn candles(&self, secs: i64, from: DateTime<Utc>) -> (Vec<Candle>, DateTime<Utc>) {
+        let all = self.series.lock().unwrap();
+        match all.iter().find(|series| series.secs == secs) {
+            Some(series) => {
+                let candles = series.candles.iter().filter(|candle| candle.start >= from).copied().collect();
+                (candles, series.complete_from)
+            }
+            None => (Vec::new(), from),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CandlesQuery {
+    /// `1m`, `5m` or `1h`
+    #[param(value_type = Option<CandleInterval>)]
+    pub interval: Option<String>,
+    /// Intervals to cover, at most 1000
+    pub limit: Option<usize>,
+    /// `omit` to leave out intervals without trades, or `carry` to fill
+    /// them with the last close
+    pub empty: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CandleRow {
+    pub start: DateTime<Utc>,
+    pub open: String,
+    pub high: String,
+    pub low: String,
+    pub close: String,
+    pub volume: String,
+    pub trades: u64,
+    /// False for the interval still in progress
+    pub complete: bool,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CandlesResponse {
+    pub symbol: String,
+    pub interval: String,
+    pub candles: Vec<CandleRow>,
+}
+
+/// Candles over the last `?limit=` intervals of `?interval=`, oldest
+/// first, up to and including the one in progress. `?limit=` defaults to
+/// 500 and is capped at 1000. Intervals without trades are left out, or
+/// with `?empty=carry` filled with the last close.
+#[utoipa::path(
+    get,
+    path = "/api/candles",
+    tag = "market data",
+    params(CandlesQuery),
+    responses(
+        (status = 200, description = "Candles, oldest first", body = CandlesResponse),
+        (status = 400, description = "Invalid interval or empty", body = ErrorResponse),
+        (status = 503, description = "Trade journal unavailable", body = ErrorResponse),
+    ),
+    security((), ("api_key" = []))
+)]
+pub async fn get_candles(
+    State(handle): State<Arc<EngineHandle>>,
+    Extension(aggregator): Extension<Arc<CandleAggregator>>,
+    archive: Option<Extension<Arc<dyn CandleArchive>>>,
+    Query(query): Query<CandlesQuery>,
+) -> Response {
+    let failure = |status: StatusCode, message: String| {
+        (status, Json(serde_json::json!({ "success": false, "message": message }))).into_response()
+    };
+    let name = query.interval.unwrap_or_else(|| CANDLE_INTERVALS[0].0.to_string());
+    let Some(&(_, secs)) = CANDLE_INTERVALS.iter().find(|(interval, _)| *interval == name) else {
+        let names: Vec<_> = CANDLE_INTERVALS.iter().map(|(interval, _)| *interval).collect();
+        return failure(StatusCode::BAD_REQUEST, format!("interval must be one of {}", names.join(", ")));
+    };
+    let carry = match query.empty.as_deref() {
+        None | Some("omit") => false,
+        Some("carry") => true,
+        Some(_) => return failure(StatusCode::BAD_REQUEST, "empty must be 'omit' or 'carry'".to_string()),
+    };
+    let limit = query.limit.unwrap_or(DEFAULT_CANDLE_LIMIT).clamp(1, MAX_CANDLE_LIMIT);
+
+    let now = handle.clock.now();
+    let current = interval_start(now, secs);
+    let from = current - Duration::seconds(secs * (limit as i64 - 1));
+    let (mut candles, complete_from) = aggregator.candles(secs, from);
+    if let Some(Extension(archive)) = archive.filter(|_| from < complete_from) {
+        match archive.candles(&handle.config.symbol, secs, from, complete_from).await {
+            Ok(records) => {
+                // The journal has the intervals memory only saw part of
+                candles.retain(|candle| candle.start >= complete_from);
+                candles.splice(0..0, records.into_iter().map(Candle::from));
+            }
+            Err(e) => {
+                tracing::error!("Failed to read candles for {}: {e}", handle.config.symbol);
+                return failure(StatusCode::SERVICE_UNAVAILABLE, "Trade journal unavailable".to_string());
+            }
+        }
+    }
+    if carry {
+        candles = carried_forward(candles, secs, current);
+    }
+
+    let step = Duration::seconds(secs);
+    let candles = candles
+        .into_iter()
+        .map(|candle| CandleRow {
+            start: candle.start,
+            open: candle.open.to_string(),
+            high: candle.high.to_string(),
+            low: candle.low.to_string(),
+            close: candle.close.to_string(),
+            volume: candle.volume.to_string(),
+            trades: candle.trades,
+            complete: candle.start + step <= now,
+        })
+        .collect();
+    Json(CandlesResponse { symbol: handle.config.symbol.clone(), interval: name, candles }).into_response()
+}
+
+/// `candles` with every interval from the first of them to `current`,
+/// the ones without trades filled with the close before them
+fn carried_forward(candles: Vec<Candle>, secs: i64, current: DateTime<Utc>) -> Vec<Candle> {
+    let step = Duration::seconds(secs);
+    let mut filled: Vec<Candle> = Vec::new();
+    let mut candles = candles.into_iter().peekable();
+    let Some(mut start) = candles.peek().map(|candle| candle.start) else {
+        return filled;
+    };
+    while start <= current {
+        match candles.next_if(|candle| candle.start == start) {
+            Some(candle) => filled.push(candle),
+            None => {
+                let close = filled.last().map(|candle| candle.close).unwrap_or_default();
+                filled.push(Candle::carried(start, close));
+            }
+        }
+        start += step;
+    }
+    filled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::router;
+    use crate::api::test_support::{request, start_stack};
+    use crate::engine::{EngineBuilder, ManualClock};
+    use axum::Router;
+    use futures::future::BoxFuture;
+    use rust_decimal_macros::dec;
+    use serde_json::{json, Value};
+
+    fn at(h: u32, m: u32, s: u32, ms: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2030, 1, 1, h, m, s).unwrap() + Duration::milliseconds(ms as i64)
+    }
+
+    async fn trade(app: &Router, price: &str, quantity: &str) {
+        for side in ["sell", "buy"] {
+            let order = json!({ "side": side, "price": price, "quantity": quantity });
+            request(app, "POST", "/api/orders?confirm=true", Some(order)).await;
+        }
+    }
+
+    /// `uri`'s candles once the aggregator has counted `trades` trades
+    async fn candles_once(app: &Router, uri: &str, trades: u64) -> Vec<Value> {
+        tokio::time::timeout(std::time::Duration::from_secs(1), async {
+            loop {
+                let (status, body) = request(app, "GET", uri, None).await;
+                assert_eq!(status, StatusCode::OK);
+                let candles = body["candles"].as_array().unwrap().clone();
+                if candles.iter().map(|candle| candle["trades"].as_u64().unwrap()).sum::<u64>() == trades {
+                    return candles;
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .expect("candles never caught up")
+    }
+
+    fn ohlcv(candle: &Value) -> [&str; 5] {
+        ["open", "high", "low", "close", "volume"].map(|field| candle[field].as_str().unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_candles_align_to_the_epoch() {
+        let clock = Arc::new(ManualClock::new(at(0, 0, 30, 0)));
+        let (app, _ws) = start_stack(clock.clone()).await;
+        for (time, price) in [(at(0, 0, 40, 0), "100"), (at(0, 1, 0, 0), "105"), (at(0, 1, 59, 999), "98")] {
+            clock.set(time);
+            trade(&app, price, "1").await;
+        }
+        clock.set(at(0, 4, 0, 0));
+        trade(&app, "101", "1").await;
+        clock.set(at(0, 4, 20, 0));
+
+        let candles = candles_once(&app, "/api/candles?interval=1m", 4).await;
+        let starts: Vec<_> = candles.iter().map(|candle| candle["start"].as_str().unwrap()).collect();
+        assert_eq!(starts, ["2030-01-01T00:00:00Z", "2030-01-01T00:01:00Z", "2030-01-01T00:04:00Z"]);
+        assert_eq!(ohlcv(&candles[1]), ["105", "105", "98", "98", "2"]);
+        let complete: Vec<_> = candles.iter().map(|candle| candle["complete"].as_bool().unwrap()).collect();
+        assert_eq!(complete, [true, true, false]);
+
+        // Quiet minutes carry the close forward
+        let candles = candles_once(&app, "/api/candles?interval=1m&empty=carry", 4).await;
+        assert_eq!(candles.len(), 5);
+        assert_eq!((ohlcv(&candles[2]), &candles[2]["trades"]), (["98", "98", "98", "98", "0"], &json!(0)));
+        assert_eq!(candles[3]["start"], "2030-01-01T00:03:00Z");
+
+        let candles = candles_once(&app, "/api/candles?interval=5m&limit=500", 4).await;
+        assert_eq!(candles.len(), 1);
+        assert_eq!((ohlcv(&candles[0]), &candles[0]["complete"]), (["100", "105", "98", "101", "4"], &json!(false)));
+        let candles = candles_once(&app, "/api/candles?interval=1h", 4).await;
+        assert_eq!(candles[0]["start"], "2030-01-01T00:00:00Z");
+
+        // Only the last two minutes
+        let candles = candles_once(&app, "/api/candles?interval=1m&limit=2", 1).await;
+        assert_eq!(candles[0]["start"], "2030-01-01T00:04:00Z");
+
+        for query in ["interval=2m", "empty=zero"] {
+            let (status, _) = request(&app, "GET", &format!("/api/candles?{query}"), None).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{query}");
+        }
+    }
+
+    /// Candles as the journal would aggregate them
+    struct StubArchive(Vec<CandleRecord>);
+
+    impl CandleArchive for StubArchive {
+        fn candles<'a>(
+            &'a self,
+            _symbol: &'a str,
+            _interval_secs: i64,
+            from: DateTime<Utc>,
+            to: DateTime<Utc>,
+        ) -> BoxFuture<'a, Result<Vec<CandleRecord>, sqlx::Error>> {
+            let candles = self.0.iter().filter(|candle| candle.start >= from && candle.start < to).cloned().collect();
+            Box::pin(async move { Ok(candles) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_older_candles_come_from_the_journal() {
+        let clock = Arc::new(ManualClock::new(at(0, 0, 30, 0)));
+        let (engine, handle) = EngineBuilder::new("BTC/USD").clock(clock.clone()).build();
+        tokio::spawn(engine.run());
+        let record = |start, price, volume, trades| CandleRecord {
+            start,
+            open: price,
+            high: price,
+            low: price,
+            close: price,
+            volume,
+            trades,
+        };
+        // The minute the server started in, whole, and one from before it
+        let archive: Arc<dyn CandleArchive> = Arc::new(StubArchive(vec![
+            record(at(0, 0, 0, 0) - Duration::minutes(2), dec!(90), dec!(5), 2),
+            record(at(0, 0, 0, 0), dec!(100), dec!(3), 2),
+        ]));
+        let app = router(Arc::new(handle)).layer(Extension(archive));
+        clock.set(at(0, 0, 40, 0));
+        trade(&app, "100", "1").await;
+        clock.set(at(0, 1, 10, 0));
+        trade(&app, "102", "1").await;
+
+        let candles = candles_once(&app, "/api/candles?interval=1m&limit=3", 3).await;
+        assert_eq!(candles.len(), 2);
+        assert_eq!((ohlcv(&candles[0]), &candles[0]["trades"]), (["100", "100", "100", "100", "3"], &json!(2)));
+        assert_eq!(ohlcv(&candles[1]), ["102", "102", "102", "102", "1"]);
+        let candles = candles_once(&app, "/api/candles?interval=1m&limit=5&empty=carry", 5).await;
+        let closes: Vec<_> = candles.iter().map(|candle| candle["close"].as_str().unwrap()).collect();
+        assert_eq!(closes, ["90", "90", "100", "102"]);
+    }
+}
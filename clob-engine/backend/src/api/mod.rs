@@ -1,9 +1,148 @@
 //! API module - HTTP and WebSocket endpoints.
 
+pub mod admin;
+pub mod auth;
+pub mod candles;
+pub mod openapi;
 pub mod orders;
+pub mod quotes;
 pub mod simulation;
+pub mod stats;
+pub mod symbols;
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_clock;
+#[cfg(test)]
+pub(crate) mod test_support;
+pub mod throttle;
+pub mod ticker;
+pub mod trades;
 pub mod websocket;
 
-pub use orders::{get_order_book, health_check, submit_order};
+pub use admin::{bust_trade, engage_kill_switch, release_kill_switch, set_book_updates, set_rate_limit};
+pub use auth::{KeyStore, Principal, Scope};
+pub use candles::{get_candles, CandleAggregator, CANDLE_CAPACITY};
+pub use openapi::ApiDoc;
+pub use orders::{
+    cancel_all_orders, cancel_order, get_liquidity, get_order, get_order_book, get_price_impact, health_check,
+    list_orders, modify_order, submit_order,
+};
+pub use quotes::submit_quote;
 pub use simulation::run_simulation;
-pub use websocket::ws_handler;
+pub use stats::get_stats;
+pub use symbols::list_symbols;
+pub use throttle::{RequestClass, Throttle};
+pub use ticker::{get_ticker, Ticker};
+pub use trades::{get_trades, TradeTape, TRADE_TAPE_CAPACITY};
+pub use websocket::{orders_ws_handler, ws_handler};
+
+use crate::engine::{url_key, EngineHandle, EngineRegistry};
+use axum::{
+    middleware,
+    routing::{delete, get, post, put},
+    Extension, Router,
+};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Build the HTTP router for a single engine
+pub fn router(handle: Arc<EngineHandle>) -> Router {
+    registry_router(Arc::new(EngineRegistry::single(handle)))
+}
+
+/// Build the HTTP router for all REST and WebSocket endpoints. Each
+/// symbol's engine is served under `/api/{symbol}` and `/ws/{symbol}`,
+/// with the symbol's `/` written as `-` (`/api/BTC-USD/orders`); the
+/// default symbol is also served at the unprefixed paths. Unknown symbols
+/// are 404s. Starts a trade tape, a ticker and a candle aggregator per
+/// engine, so needs a Tokio runtime. Routes check API keys against the
+/// [`KeyStore`] layered on as an `Extension`, and trading and market data
+/// requests are held to the [`Throttle`] layered on the same way; both are
+/// open without one. The OpenAPI specification and its Swagger UI are
+/// public, like the health check.
+pub fn registry_router(engines: Arc<EngineRegistry>) -> Router {
+    let mut router = Router::new()
+        .route("/api/health", get(health_check))
+        .route("/api/symbols", get(list_symbols))
+        .route("/api/openapi.json", get(openapi::openapi_json))
+        .route("/api/docs", get(openapi::swagger_ui))
+        .with_state(engines.clone());
+
+    let mut apis = HashMap::new();
+    for handle in engines.iter() {
+        let key = url_key(&handle.config.symbol);
+        let api = engine_api()
+            .layer(Extension(TradeTape::spawn(handle, TRADE_TAPE_CAPACITY)))
+            .layer(Extension(Ticker::spawn(handle)))
+            .layer(Extension(CandleAggregator::spawn(handle, CANDLE_CAPACITY)));
+        apis.insert(handle.config.symbol.clone(), api.clone());
+        router = router.merge(
+            Router::new()
+                .nest(&format!("/api/{key}"), api)
+                .nest(&format!("/ws/{key}"), engine_ws())
+                .with_state(handle.clone()),
+        );
+    }
+
+    if let Some(handle) = engines.default_engine() {
+        // The same followers of its events serve both of the default
+        // engine's paths
+        let api = apis.remove(&handle.config.symbol).expect("every engine has its API");
+        let unprefixed = Router::new().nest("/api", api).nest("/ws", engine_ws());
+
+        // Test-only clock control, compiled out of normal builds
+        #[cfg(any(test, feature = "test-util"))]
+        let unprefixed = unprefixed
+            .route("/api/test/clock", get(test_clock::get_clock))
+            .route("/api/test/clock/advance", post(test_clock::advance_clock))
+            .route("/api/test/clock/set", post(test_clock::set_clock));
+
+        router = router.merge(unprefixed.with_state(handle.clone()));
+    }
+    router
+}
+
+/// REST endpoints of one engine, relative to its `/api` prefix, each
+/// behind the scope it needs. Requests are throttled before their key is
+/// checked, so refused ones count too.
+fn engine_api() -> Router<Arc<EngineHandle>> {
+    let market_data = Router::new()
+        .route("/orderbook", get(get_order_book))
+        .route("/orderbook/liquidity", get(get_liquidity))
+        .route("/orderbook/impact", get(get_price_impact))
+        .route("/stats", get(get_stats))
+        .route("/trades", get(get_trades))
+        .route("/ticker", get(get_ticker))
+        .route("/candles", get(get_candles))
+        .route_layer(middleware::from_fn(auth::market_data_access))
+        .route_layer(middleware::from_fn_with_state(RequestClass::MarketData, throttle::throttle));
+    let read = Router::new()
+        .route("/orders", get(list_orders))
+        .route("/orders/:id", get(get_order))
+        .route_layer(middleware::from_fn_with_state(Scope::Read, auth::require_scope));
+    let trade = Router::new()
+        .route("/orders", post(submit_order).delete(cancel_all_orders))
+        .route("/orders/:id", delete(cancel_order).patch(modify_order))
+        .route("/quotes", post(submit_quote))
+        .route_layer(middleware::from_fn_with_state(Scope::Trade, auth::require_scope))
+        .route_layer(middleware::from_fn_with_state(RequestClass::Trading, throttle::throttle));
+    let admin = Router::new()
+        .route("/simulation", post(run_simulation))
+        .route("/admin/kill/:owner_id", post(engage_kill_switch).delete(release_kill_switch))
+        .route("/admin/bust/:trade_id", post(bust_trade))
+        .route("/admin/book-updates", put(set_book_updates))
+        .route("/admin/rate-limits", put(set_rate_limit))
+        .route_layer(middleware::from_fn_with_state(Scope::Admin, auth::require_scope));
+    market_data.merge(read).merge(trade).merge(admin)
+}
+
+/// WebSocket feeds of one engine, relative to its `/ws` prefix
+fn engine_ws() -> Router<Arc<EngineHandle>> {
+    let market = Router::new()
+        .route("/market", get(ws_handler))
+        .route_layer(middleware::from_fn(auth::market_data_access))
+        .route_layer(middleware::from_fn_with_state(RequestClass::MarketData, throttle::throttle));
+    let orders = Router::new()
+        .route("/orders", get(orders_ws_handler))
+        .route_layer(middleware::from_fn_with_state(Scope::Read, auth::require_scope));
+    market.merge(orders)
+}
@@ -0,0 +1,241 @@
+//! OpenAPI description of the REST API, served at `/api/openapi.json`, with
+//! a Swagger UI for it at `/api/docs`.
+//!
+//! The paths are those of the default symbol; every symbol's engine serves
+//! the same ones under `/api/{symbol}`, as `/api/ETH-USD/orders`.
+
+use crate::api::candles::CANDLE_INTERVALS;
+use crate::api::orders::{ORDER_CLOSED, ORDER_FILLED, QUEUE_FULL};
+use crate::api::{admin, candles, orders, quotes, simulation, stats, symbols, ticker, trades};
+use crate::engine::RejectReason;
+use axum::{response::Html, Json};
+use utoipa::openapi::schema::{ObjectBuilder, Schema, Type};
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, SecurityScheme};
+use utoipa::openapi::RefOr;
+use utoipa::{Modify, OpenApi, PartialSchema, ToSchema};
+
+/// Name of the API key security scheme in the specification
+const API_KEY_SCHEME: &str = "api_key";
+
+/// Swagger UI version loaded by `/api/docs`
+const SWAGGER_UI_VERSION: &str = "5";
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "CLOB Engine API",
+        description = "Order entry, market data and operational controls for the matching engine. \
+            Paths are the default symbol's; each symbol also serves them under `/api/{symbol}`, \
+            with its `/` written as `-`."
+    ),
+    paths(
+        orders::health_check,
+        symbols::list_symbols,
+        orders::submit_order,
+        orders::list_orders,
+        orders::cancel_all_orders,
+        orders::get_order,
+        orders::cancel_order,
+        orders::modify_order,
+        quotes::submit_quote,
+        orders::get_order_book,
+        orders::get_liquidity,
+        orders::get_price_impact,
+        stats::get_stats,
+        trades::get_trades,
+        ticker::get_ticker,
+        candles::get_candles,
+        simulation::run_simulation,
+        admin::engage_kill_switch,
+        admin::release_kill_switch,
+        admin::bust_trade,
+        admin::set_book_updates,
+        admin::set_rate_limit,
+    ),
+    // Those only query parameters name are not collected on their own
+    components(schemas(CandleInterval, ErrorCode, ErrorResponse)),
+    modifiers(&ApiKeyScheme),
+    tags(
+        (name = "orders", description = "Order entry; needs the read or trade scope"),
+        (name = "market data", description = "Book, trades and statistics; public unless the key file says otherwise"),
+        (name = "simulation", description = "Load generation; needs the admin scope"),
+        (name = "admin", description = "Operational controls; needs the admin scope"),
+        (name = "service", description = "Health and symbols, always public"),
+    )
+)]
+pub struct ApiDoc;
+
+/// Adds the `X-API-Key` header the protected paths name
+struct ApiKeyScheme;
+
+impl Modify for ApiKeyScheme {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        let header = ApiKeyValue::with_description("X-API-Key", "API key from the server's key file");
+        components.add_security_scheme(API_KEY_SCHEME, SecurityScheme::ApiKey(ApiKey::Header(header)));
+    }
+}
+
+/// Machine-readable reason a request was refused: one of the engine's
+/// rejection codes, or the API's own for a full engine queue and for
+/// cancels of orders that have already closed
+pub struct ErrorCode;
+
+impl PartialSchema for ErrorCode {
+    fn schema() -> RefOr<Schema> {
+        let codes = RejectReason::CODES.into_iter().chain([QUEUE_FULL, ORDER_FILLED, ORDER_CLOSED]);
+        string_enum(codes, "Machine-readable reason a request was refused")
+    }
+}
+
+impl ToSchema for ErrorCode {}
+
+/// A `?interval=` of `GET /api/candles`
+pub struct CandleInterval;
+
+impl PartialSchema for CandleInterval {
+    fn schema() -> RefOr<Schema> {
+        string_enum(CANDLE_INTERVALS.map(|(name, _)| name), "Candle length")
+    }
+}
+
+impl ToSchema for CandleInterval {}
+
+/// A string schema allowing only `values`
+fn string_enum<'a>(values: impl IntoIterator<Item = &'a str>, description: &str) -> RefOr<Schema> {
+    ObjectBuilder::new()
+        .schema_type(Type::String)
+        .enum_values(Some(values))
+        .description(Some(description))
+        .into()
+}
+
+/// Body of a request refused before it reached the engine, or one the
+/// engine could not answer
+#[derive(Debug, ToSchema)]
+pub struct ErrorResponse {
+    /// Always false
+    pub success: bool,
+    pub message: String,
+}
+
+/// The OpenAPI specification of the REST API
+pub async fn openapi_json() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+/// A Swagger UI page for the specification at `/api/openapi.json`
+pub async fn swagger_ui() -> Html<String> {
+    let assets = format!("https://unpkg.com/swagger-ui-dist@{SWAGGER_UI_VERSION}");
+    Html(format!(
+        r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8">
+  <title>CLOB Engine API</title>
+  <link rel="stylesheet" href="{assets}/swagger-ui.css">
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="{assets}/swagger-ui-bundle.js"></script>
+  <script>
+    SwaggerUIBundle({{ url: "/api/openapi.json", dom_id: "#swagger-ui" }});
+  </script>
+</body>
+</html>
+"##
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ApiDoc;
+    use crate::api::test_support::{request, spawn_app};
+    use crate::engine::EngineBuilder;
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use serde_json::{json, Value};
+    use tower::ServiceExt;
+    use utoipa::OpenApi;
+
+    #[test]
+    fn test_spec_lists_every_path_with_enumerated_values() {
+        let spec: Value = serde_json::from_str(&ApiDoc::openapi().to_json().unwrap()).unwrap();
+        let paths = spec["paths"].as_object().unwrap();
+        for (path, methods) in [
+            ("/api/health", &["get"][..]),
+            ("/api/symbols", &["get"]),
+            ("/api/orders", &["get", "post", "delete"]),
+            ("/api/orders/{id}", &["get", "delete", "patch"]),
+            ("/api/quotes", &["post"]),
+            ("/api/orderbook", &["get"]),
+            ("/api/orderbook/liquidity", &["get"]),
+            ("/api/orderbook/impact", &["get"]),
+            ("/api/stats", &["get"]),
+            ("/api/trades", &["get"]),
+            ("/api/ticker", &["get"]),
+            ("/api/candles", &["get"]),
+            ("/api/simulation", &["post"]),
+            ("/api/admin/kill/{owner_id}", &["post", "delete"]),
+            ("/api/admin/bust/{trade_id}", &["post"]),
+            ("/api/admin/book-updates", &["put"]),
+            ("/api/admin/rate-limits", &["put"]),
+        ] {
+            for method in methods {
+                assert!(paths[path][method].is_object(), "{method} {path} is missing");
+            }
+        }
+
+        // Constrained strings come with their values, not as free text
+        let schemas = &spec["components"]["schemas"];
+        assert_eq!(schemas["Side"]["enum"], json!(["buy", "sell"]));
+        assert_eq!(schemas["OrderType"]["enum"], json!(["limit", "market"]));
+        let codes = schemas["ErrorCode"]["enum"].as_array().unwrap();
+        for code in ["POST_ONLY_WOULD_CROSS", "RATE_LIMITED", "QUEUE_FULL", "ORDER_FILLED"] {
+            assert!(codes.contains(&json!(code)), "{code} is not listed");
+        }
+        let order = &schemas["SubmitOrderRequest"]["properties"];
+        assert_eq!(order["side"]["$ref"], "#/components/schemas/Side");
+        assert!(schemas["SubmitOrderResponse"].to_string().contains("#/components/schemas/ErrorCode"));
+        assert_eq!(schemas["CandleInterval"]["enum"], json!(["1m", "5m", "1h"]));
+
+        // Every schema referred to is there
+        let mut refs = Vec::new();
+        collect_refs(&spec, &mut refs);
+        for reference in refs {
+            let name = reference.strip_prefix("#/components/schemas/").unwrap();
+            assert!(schemas[name].is_object(), "{reference} is not defined");
+        }
+
+        // Protected routes name the key header
+        assert_eq!(spec["components"]["securitySchemes"]["api_key"]["name"], "X-API-Key");
+        assert_eq!(paths["/api/orders"]["post"]["security"], json!([{ "api_key": [] }]));
+    }
+
+    fn collect_refs(value: &Value, refs: &mut Vec<String>) {
+        match value {
+            Value::Object(fields) => {
+                if let Some(Value::String(reference)) = fields.get("$ref") {
+                    refs.push(reference.clone());
+                }
+                fields.values().for_each(|field| collect_refs(field, refs));
+            }
+            Value::Array(items) => items.iter().for_each(|item| collect_refs(item, refs)),
+            _ => {}
+        }
+    }
+
+    #[tokio::test]
+    async fn test_spec_and_docs_are_served() {
+        let app = spawn_app(EngineBuilder::new("BTC/USD"));
+        let (status, spec) = request(&app, "GET", "/api/openapi.json", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(spec["paths"]["/api/orders"].is_object());
+
+        let docs = Request::get("/api/docs").body(Body::empty()).unwrap();
+        let response = app.oneshot(docs).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let page = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert!(String::from_utf8_lossy(&page).contains("/api/openapi.json"));
+    }
+}
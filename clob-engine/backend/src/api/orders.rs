@@ -1,41 +1,451 @@
 //! REST API for order submission.
 
-use crate::engine::{EngineHandle, OrderRequest, Side};
+use crate::api::auth::{acting_owner, Principal, Scope};
+use crate::api::openapi::{ErrorCode, ErrorResponse};
+use crate::api::websocket::{depth_rows, FEED_VERSION};
+use crate::engine::{
+    url_key, EngineHandle, EngineRegistry, Order, OrderRequest, OrderState, OrderStatus, OrderType, RejectReason, Side,
+    StpPolicy, SubmitAck, SymbolConfig, TimeInForce,
+};
+use crate::persistence::{ArchivedOrder, OrderArchive};
 use axum::{
-    extract::State,
-    http::StatusCode,
-    response::IntoResponse,
-    Json,
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Extension, Json,
 };
+use chrono::{DateTime, Utc};
 use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
+use tokio::sync::mpsc::error::TrySendError;
+use utoipa::{IntoParams, ToSchema};
 use uuid::Uuid;
 
 /// Request body for submitting an order
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct SubmitOrderRequest {
+    /// Submitting participant, used for self-trade prevention
+    #[serde(default)]
+    pub owner_id: Option<String>,
+    /// Caller's own id for the order; must not repeat one of the owner's open orders
+    #[serde(default)]
+    pub client_order_id: Option<String>,
     /// "buy" or "sell"
+    #[schema(value_type = Side)]
     pub side: String,
-    /// Limit price
+    /// "limit" (default) or "market"
+    #[serde(default)]
+    #[schema(value_type = Option<OrderType>)]
+    pub order_type: Option<String>,
+    /// Limit price (not required for market orders)
+    #[serde(default)]
     pub price: Decimal,
     /// Order quantity
     pub quantity: Decimal,
+    /// Trigger price; makes this a stop (market) or stop-limit (limit) order
+    #[serde(default)]
+    pub stop_price: Option<Decimal>,
+    /// Reject the order instead of letting it take liquidity
+    #[serde(default)]
+    pub post_only: bool,
+    /// Rest without showing in depth; limit orders only
+    #[serde(default)]
+    pub hidden: bool,
+    /// Market orders only: furthest from the reference price the order may
+    /// trade; the remainder beyond it is cancelled
+    #[serde(default)]
+    pub max_slippage: Option<Decimal>,
+    /// Self-trade prevention: "cancel_newest", "cancel_oldest" or
+    /// "decrement_both"; the engine default when omitted
+    #[serde(default)]
+    #[schema(value_type = Option<StpPolicy>)]
+    pub stp_policy: Option<String>,
+    /// "gtc", "ioc", "fok" or "gtd"; defaults to "gtd" when `expires_at`
+    /// is set and "gtc" otherwise
+    #[serde(default)]
+    #[schema(value_type = Option<TimeInForce>)]
+    pub time_in_force: Option<String>,
+    /// Good-till-date expiry (RFC 3339)
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Request body for amending a resting order
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ModifyOrderRequest {
+    /// New limit price; a change loses time priority
+    #[serde(default)]
+    pub price: Option<Decimal>,
+    /// New open quantity; only a reduction keeps time priority
+    #[serde(default)]
+    pub quantity: Option<Decimal>,
+}
+
+/// Query parameters for order submission
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SubmitOrderQuery {
+    /// Wait for the engine to accept or reject the order before responding
+    #[serde(default)]
+    pub confirm: bool,
+}
+
+/// Query parameters for cancelling one order
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CancelOrderQuery {
+    /// Only cancel the order if it belongs to this participant
+    #[serde(default)]
+    pub owner_id: Option<String>,
+}
+
+/// Query parameters for cancelling all of an owner's orders
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CancelAllQuery {
+    /// Participant whose orders are cancelled
+    #[serde(default)]
+    pub owner_id: Option<String>,
+    /// "buy" or "sell" to cancel one side only
+    #[serde(default)]
+    #[param(value_type = Option<Side>)]
+    pub side: Option<String>,
+}
+
+/// Query parameters for listing open orders
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ListOrdersQuery {
+    /// Only this participant's orders
+    #[serde(default)]
+    pub owner_id: Option<String>,
+    /// "buy" or "sell" for one side only
+    #[serde(default)]
+    #[param(value_type = Option<Side>)]
+    pub side: Option<String>,
+    /// The symbol served at this path, as `BTC/USD` or `BTC-USD`
+    #[serde(default)]
+    pub symbol: Option<String>,
+    /// Most orders to return, at most [`MAX_ORDER_LIST_LIMIT`]
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+/// Orders listed unless `?limit=` asks for another number
+pub const DEFAULT_ORDER_LIST_LIMIT: usize = 100;
+
+/// Most orders one listing returns
+pub const MAX_ORDER_LIST_LIMIT: usize = 1000;
+
+/// Query parameters for the order book snapshot
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct OrderBookQuery {
+    /// Group depth into price buckets this wide, e.g. "0.5" or "1"
+    #[serde(default)]
+    pub group: Option<Decimal>,
+    /// Add each level's notional value and the running notional
+    #[serde(default)]
+    pub notional: bool,
+    /// Levels per side to return; 0 for the best bid and offer only
+    #[serde(default)]
+    pub levels: Option<usize>,
+}
+
+/// Most levels per side an order book snapshot returns
+pub const MAX_BOOK_LEVELS: usize = 500;
+
+/// Query parameters for `GET /api/orderbook/liquidity`
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct LiquidityQuery {
+    /// Price distance from each touch to total liquidity over, e.g. "0.5"
+    pub distance: Decimal,
+}
+
+/// Query parameters for `GET /api/orderbook/impact`
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ImpactQuery {
+    /// "buy" or "sell": the side of the order being estimated
+    #[param(value_type = Side)]
+    pub side: String,
+    /// Quantity the order would take
+    pub quantity: Decimal,
+}
+
+/// Response for `GET /api/orderbook`: the book's touch and depth, laid out
+/// as the feed's `order_book` messages are
+#[derive(Debug, Serialize, ToSchema)]
+pub struct OrderBookResponse {
+    /// Depth layout, see [`FEED_VERSION`]
+    pub version: u32,
+    pub best_bid: Option<String>,
+    pub best_ask: Option<String>,
+    pub best_bid_qty: Option<String>,
+    pub best_ask_qty: Option<String>,
+    pub mid_price: Option<String>,
+    pub microprice: Option<String>,
+    pub top_imbalance: Option<String>,
+    pub depth_imbalance: Option<String>,
+    pub checksum: u32,
+    /// `[price, quantity, orders]` per level, best first, followed by the
+    /// level's notional and the running notional with `?notional=true`
+    pub bids: Vec<Vec<String>>,
+    pub asks: Vec<Vec<String>>,
+    /// Book update the snapshot was taken at
+    pub sequence: u64,
+    /// Unix milliseconds
+    pub timestamp: i64,
+}
+
+/// Response for `GET /api/orderbook/liquidity`
+#[derive(Debug, Serialize, ToSchema)]
+pub struct LiquidityResponse {
+    pub distance: String,
+    /// Bid quantity within `distance` of the best bid
+    pub bid_liquidity: String,
+    /// Ask quantity within `distance` of the best ask
+    pub ask_liquidity: String,
+    /// `[price, cumulative quantity]` per level, best first
+    pub bids: Vec<[String; 2]>,
+    pub asks: Vec<[String; 2]>,
+}
+
+/// Response for `GET /api/orderbook/impact`
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PriceImpactResponse {
+    pub side: Side,
+    pub quantity: String,
+    /// Average price of the quantity the book can fill; absent on an empty side
+    pub vwap: Option<String>,
+    /// Furthest price the order would reach
+    pub worst_price: Option<String>,
+    pub fillable_quantity: String,
+    /// Whether the book holds the whole quantity
+    pub complete: bool,
 }
 
 /// Response for a successful order submission
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct SubmitOrderResponse {
     pub success: bool,
     pub message: String,
     pub order_id: Option<Uuid>,
+    /// Machine-readable rejection code, when the order broke a trading rule
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<ErrorCode>)]
+    pub code: Option<String>,
+    /// Client order id from the request, echoed back
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_order_id: Option<String>,
+    /// What the engine did with the order, for confirmed submissions
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    pub ack: Option<OrderAck>,
+}
+
+impl SubmitOrderResponse {
+    /// A refusal, with its machine-readable code if it has one
+    fn rejected(message: impl Into<String>, code: Option<String>) -> Self {
+        Self {
+            success: false,
+            message: message.into(),
+            order_id: None,
+            code,
+            client_order_id: None,
+            ack: None,
+        }
+    }
+}
+
+/// Response for a cancelled order
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CancelOrderResponse {
+    pub success: bool,
+    pub message: String,
+    pub order_id: Uuid,
+    /// Quantity the order traded before it was cancelled
+    pub filled_quantity: String,
+    /// Open quantity taken off the book
+    pub remaining_quantity: String,
+}
+
+/// Fill summary and state of a confirmed order. Only ever flattened into
+/// a [`SubmitOrderResponse`], whose unconfirmed submissions leave it out,
+/// so its schema requires none of it.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct OrderAck {
+    #[schema(required = false)]
+    pub state: OrderState,
+    #[schema(required = false)]
+    pub filled_quantity: String,
+    /// Volume-weighted fill price; absent if nothing traded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub average_price: Option<String>,
+    /// Open quantity left resting, pending or queued
+    #[schema(required = false)]
+    pub remaining_quantity: String,
+}
+
+impl From<SubmitAck> for OrderAck {
+    fn from(ack: SubmitAck) -> Self {
+        Self {
+            state: ack.state,
+            filled_quantity: ack.filled_quantity.to_string(),
+            average_price: ack.average_price.map(|p| p.to_string()),
+            remaining_quantity: ack.remaining_quantity.to_string(),
+        }
+    }
+}
+
+/// An order as it stands, live from the engine or as last journaled
+#[derive(Debug, Serialize, ToSchema)]
+pub struct OrderDetails {
+    pub order_id: Uuid,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub owner_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_order_id: Option<String>,
+    pub side: Side,
+    /// Zero for market orders
+    pub price: String,
+    pub quantity: String,
+    pub filled_quantity: String,
+    pub remaining_quantity: String,
+    pub status: OrderStatus,
+    /// Whether the order is still working in the engine
+    pub open: bool,
+    /// When the order reached the engine
+    pub created_at: DateTime<Utc>,
+    /// When the journal last changed the order; absent for open orders
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+impl OrderDetails {
+    /// An order the engine still holds, known to clients as `order_id`
+    fn open(order_id: Uuid, order: Order) -> Self {
+        Self {
+            order_id,
+            owner_id: order.owner_id,
+            client_order_id: order.client_order_id,
+            side: order.side,
+            price: order.price.to_string(),
+            quantity: order.quantity.to_string(),
+            filled_quantity: (order.quantity - order.remaining_quantity).to_string(),
+            remaining_quantity: order.remaining_quantity.to_string(),
+            status: order.status,
+            open: true,
+            created_at: order.timestamp,
+            updated_at: None,
+        }
+    }
+}
+
+/// An order in a listing of open orders
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ListedOrder {
+    #[serde(flatten)]
+    pub order: OrderDetails,
+    /// Time since the order reached the engine, by the engine's clock
+    pub age_ms: i64,
+}
+
+/// Response listing open orders
+#[derive(Debug, Serialize, ToSchema)]
+pub struct OrderListResponse {
+    pub symbol: String,
+    /// Orders that matched the filters; more than `orders` holds when the
+    /// listing was cut at its limit
+    pub total: usize,
+    pub orders: Vec<ListedOrder>,
+}
+
+impl From<ArchivedOrder> for OrderDetails {
+    /// A journaled order that is no longer in its engine. Whatever did not
+    /// fill was cancelled, expired or rejected, so nothing remains.
+    fn from(order: ArchivedOrder) -> Self {
+        Self {
+            order_id: order.order_id,
+            owner_id: order.owner_id,
+            client_order_id: order.client_order_id,
+            side: order.side,
+            price: order.price.to_string(),
+            quantity: order.quantity.to_string(),
+            filled_quantity: order.filled_quantity.to_string(),
+            remaining_quantity: Decimal::ZERO.to_string(),
+            status: order.status,
+            open: false,
+            created_at: order.created_at,
+            updated_at: Some(order.updated_at),
+        }
+    }
 }
 
-/// Submit a new order to the matching engine
+/// HTTP status for an order the engine's rules refuse: risk limits that
+/// clear over time are 429, everything else 422
+pub(crate) fn rejection_status(reason: &RejectReason) -> StatusCode {
+    match reason {
+        RejectReason::TooManyOpenOrders { .. } | RejectReason::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+        _ => StatusCode::UNPROCESSABLE_ENTITY,
+    }
+}
+
+/// Seconds a client refused for a full engine queue is told to wait
+pub const QUEUE_FULL_RETRY_AFTER_SECS: u64 = 1;
+
+/// Code of the rejection sent when the engine's command queue is full
+pub(crate) const QUEUE_FULL: &str = "QUEUE_FULL";
+
+/// Code of a cancel refused because the order has traded in full
+pub(crate) const ORDER_FILLED: &str = "ORDER_FILLED";
+
+/// Code of a cancel refused because the order was already cancelled
+pub(crate) const ORDER_CLOSED: &str = "ORDER_CLOSED";
+
+/// Submit a new order to the matching engine. The order is refused with
+/// 429 and a `Retry-After` hint when the engine's queue is full, rather
+/// than left waiting for room. With API keys, the order is the key
+/// owner's.
+#[utoipa::path(
+    post,
+    path = "/api/orders",
+    tag = "orders",
+    params(SubmitOrderQuery),
+    request_body = SubmitOrderRequest,
+    responses(
+        (status = 200, description = "Confirmed and accepted by the engine", body = SubmitOrderResponse),
+        (status = 202, description = "Handed to the engine", body = SubmitOrderResponse),
+        (status = 400, description = "Malformed order", body = SubmitOrderResponse),
+        (status = 403, description = "Names an owner the API key may not act for", body = SubmitOrderResponse),
+        (status = 422, description = "Breaks a trading rule", body = SubmitOrderResponse),
+        (status = 429, description = "Engine queue full or risk limit hit", body = SubmitOrderResponse),
+        (status = 503, description = "Engine unavailable", body = SubmitOrderResponse),
+    ),
+    security(("api_key" = []))
+)]
 pub async fn submit_order(
     State(handle): State<Arc<EngineHandle>>,
-    Json(req): Json<SubmitOrderRequest>,
+    caller: Option<Extension<Principal>>,
+    Query(query): Query<SubmitOrderQuery>,
+    Json(mut req): Json<SubmitOrderRequest>,
 ) -> impl IntoResponse {
+    let (status, Json(body)) = match acting_owner(caller, req.owner_id.take()) {
+        Ok(owner_id) => submit(&handle, query, SubmitOrderRequest { owner_id, ..req }).await,
+        Err(message) => not_owner(message),
+    };
+    let retry_after = (body.code.as_deref() == Some(QUEUE_FULL))
+        .then(|| [(header::RETRY_AFTER, QUEUE_FULL_RETRY_AFTER_SECS.to_string())]);
+    (status, retry_after, Json(body))
+}
+
+/// The response to a submission
+async fn submit(
+    handle: &EngineHandle,
+    query: SubmitOrderQuery,
+    req: SubmitOrderRequest,
+) -> (StatusCode, Json<SubmitOrderResponse>) {
     // Parse side
     let side = match req.side.to_lowercase().as_str() {
         "buy" => Side::Buy,
@@ -43,86 +453,1535 @@ pub async fn submit_order(
         _ => {
             return (
                 StatusCode::BAD_REQUEST,
-                Json(SubmitOrderResponse {
-                    success: false,
-                    message: "Invalid side. Must be 'buy' or 'sell'".to_string(),
-                    order_id: None,
-                }),
+                Json(SubmitOrderResponse::rejected("Invalid side. Must be 'buy' or 'sell'", None)),
+            );
+        }
+    };
+
+    // Parse order type
+    let order_type = match req.order_type.as_deref().map(str::to_lowercase).as_deref() {
+        None | Some("limit") => OrderType::Limit,
+        Some("market") => OrderType::Market,
+        Some(_) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(SubmitOrderResponse::rejected("Invalid order_type. Must be 'limit' or 'market'", None)),
+            );
+        }
+    };
+
+    // Parse time in force
+    let time_in_force = match req.time_in_force.as_deref().map(str::to_lowercase).as_deref() {
+        None if req.expires_at.is_some() => TimeInForce::Gtd,
+        None | Some("gtc") => TimeInForce::Gtc,
+        Some("ioc") => TimeInForce::Ioc,
+        Some("fok") => TimeInForce::Fok,
+        Some("gtd") => TimeInForce::Gtd,
+        Some(_) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(SubmitOrderResponse::rejected(
+                    "Invalid time_in_force. Must be 'gtc', 'ioc', 'fok' or 'gtd'",
+                    None,
+                )),
+            );
+        }
+    };
+
+    // Parse self-trade prevention policy
+    let stp_policy = match req.stp_policy.as_deref().map(str::to_lowercase).as_deref() {
+        None => None,
+        Some("cancel_newest") => Some(StpPolicy::CancelNewest),
+        Some("cancel_oldest") => Some(StpPolicy::CancelOldest),
+        Some("decrement_both") => Some(StpPolicy::DecrementBoth),
+        Some(_) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(SubmitOrderResponse::rejected(
+                    "Invalid stp_policy. Must be 'cancel_newest', 'cancel_oldest' or 'decrement_both'",
+                    None,
+                )),
             );
         }
     };
 
     // Validate price and quantity
-    if req.price <= Decimal::ZERO {
+    if order_type == OrderType::Limit && req.price <= Decimal::ZERO {
         return (
             StatusCode::BAD_REQUEST,
-            Json(SubmitOrderResponse {
-                success: false,
-                message: "Price must be positive".to_string(),
-                order_id: None,
-            }),
+            Json(SubmitOrderResponse::rejected("Price must be positive", None)),
         );
     }
 
     if req.quantity <= Decimal::ZERO {
         return (
             StatusCode::BAD_REQUEST,
-            Json(SubmitOrderResponse {
-                success: false,
-                message: "Quantity must be positive".to_string(),
-                order_id: None,
-            }),
+            Json(SubmitOrderResponse::rejected("Quantity must be positive", None)),
         );
     }
 
-    let order_id = Uuid::new_v4();
+    if let Some(stop_price) = req.stop_price {
+        if stop_price <= Decimal::ZERO {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(SubmitOrderResponse::rejected("Stop price must be positive", None)),
+            );
+        }
+    }
+
+    if req.post_only && order_type == OrderType::Market {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(SubmitOrderResponse::rejected("Market orders cannot be post-only", None)),
+        );
+    }
+
+    if req.hidden && order_type == OrderType::Market {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(SubmitOrderResponse::rejected("Market orders cannot be hidden", None)),
+        );
+    }
+
+    if req.max_slippage.is_some() && order_type != OrderType::Market {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(SubmitOrderResponse::rejected("max_slippage is only allowed on market orders", None)),
+        );
+    }
+
+    if req.max_slippage.is_some_and(|slippage| slippage < Decimal::ZERO) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(SubmitOrderResponse::rejected("max_slippage must not be negative", None)),
+        );
+    }
+
+    if (time_in_force == TimeInForce::Gtd) != req.expires_at.is_some() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(SubmitOrderResponse::rejected(
+                "expires_at is required for, and only allowed with, time_in_force 'gtd'",
+                None,
+            )),
+        );
+    }
+
+    if req.expires_at.is_some_and(|expires_at| expires_at <= handle.clock.now()) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(SubmitOrderResponse::rejected("expires_at must be in the future", None)),
+        );
+    }
+
+    if req.client_order_id.as_deref().is_some_and(|id| id.trim().is_empty()) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(SubmitOrderResponse::rejected("client_order_id must not be empty", None)),
+        );
+    }
+
+    let client_order_id = req.client_order_id;
 
     // Create order request
     let order_request = OrderRequest {
+        id: None,
+        owner_id: req.owner_id,
+        client_order_id: client_order_id.clone(),
         side,
         price: req.price,
         quantity: req.quantity,
+        order_type,
+        stop_price: req.stop_price,
+        time_in_force,
+        post_only: req.post_only,
+        hidden: req.hidden,
+        stp_policy,
+        expires_at: req.expires_at,
+        max_slippage: req.max_slippage,
+        skip_risk_checks: false,
+    }
+    .normalized();
+
+    // Symbol trading rules (precision, tick, lot, minimum size, price band)
+    let reference = {
+        let snapshot = handle.current_state.borrow();
+        SymbolConfig::reference_price(snapshot.last_trade_price, snapshot.best_bid, snapshot.best_ask)
     };
+    if let Err(reason) = handle
+        .config
+        .validate(&order_request)
+        .and_then(|_| handle.config.check_price_band(&order_request, reference))
+    {
+        return (
+            rejection_status(&reason),
+            Json(SubmitOrderResponse {
+                client_order_id,
+                ..SubmitOrderResponse::rejected(reason.to_string(), Some(reason.code().to_string()))
+            }),
+        );
+    }
 
-    // Submit to engine
-    match handle.submit_order(order_request).await {
-        Ok(_) => (
+    // With confirmation, report the engine's verdict instead of just the hand-off
+    if query.confirm {
+        return match handle.try_submit_order_confirmed(order_request).await {
+            Err(TrySendError::Full(_)) => queue_full(client_order_id),
+            Ok(Some(Ok(ack))) => (
+                StatusCode::OK,
+                Json(SubmitOrderResponse {
+                    success: true,
+                    message: "Order accepted".to_string(),
+                    order_id: Some(handle.ids.uuid(ack.order_id)),
+                    code: None,
+                    client_order_id,
+                    ack: Some(ack.into()),
+                }),
+            ),
+            Ok(Some(Err(reason))) => (
+                rejection_status(&reason),
+                Json(SubmitOrderResponse {
+                    client_order_id,
+                    ..SubmitOrderResponse::rejected(reason.to_string(), Some(reason.code().to_string()))
+                }),
+            ),
+            Ok(None) | Err(TrySendError::Closed(_)) => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(SubmitOrderResponse::rejected("Engine unavailable", None)),
+            ),
+        };
+    }
+
+    // Submit to engine under an id reserved for the order, which
+    // `order_accepted` carries too, with the client order id if given
+    match handle.try_submit_order(order_request) {
+        Ok(id) => (
             StatusCode::ACCEPTED,
             Json(SubmitOrderResponse {
                 success: true,
                 message: "Order submitted successfully".to_string(),
-                order_id: Some(order_id),
+                order_id: Some(handle.ids.uuid(id)),
+                code: None,
+                client_order_id,
+                ack: None,
             }),
         ),
-        Err(_) => (
+        Err(TrySendError::Full(_)) => queue_full(client_order_id),
+        Err(TrySendError::Closed(_)) => (
             StatusCode::SERVICE_UNAVAILABLE,
+            Json(SubmitOrderResponse::rejected("Engine unavailable", None)),
+        ),
+    }
+}
+
+/// Refusal of a request naming an owner its API key may not act for
+fn not_owner(message: String) -> (StatusCode, Json<SubmitOrderResponse>) {
+    (
+        StatusCode::FORBIDDEN,
+        Json(SubmitOrderResponse::rejected(message, None)),
+    )
+}
+
+/// Refusal of an order the engine had no room to queue
+fn queue_full(client_order_id: Option<String>) -> (StatusCode, Json<SubmitOrderResponse>) {
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        Json(SubmitOrderResponse {
+            client_order_id,
+            ..SubmitOrderResponse::rejected("Engine queue is full, retry later", Some(QUEUE_FULL.to_string()))
+        }),
+    )
+}
+
+/// List the orders resting on the book, optionally only `?owner_id=`'s,
+/// one `?side=`'s, or after checking `?symbol=` is the one served here.
+/// Sorted by price, lowest first, then time priority; `?limit=` caps how
+/// many come back, and `total` says how many matched. With API keys, only
+/// the key owner's orders are listed, unless it is an admin key.
+#[utoipa::path(
+    get,
+    path = "/api/orders",
+    tag = "orders",
+    params(ListOrdersQuery),
+    responses(
+        (status = 200, description = "Open orders", body = OrderListResponse),
+        (status = 400, description = "Invalid filter", body = ErrorResponse),
+        (status = 403, description = "Names an owner the API key may not act for", body = SubmitOrderResponse),
+        (status = 503, description = "Engine unavailable", body = ErrorResponse),
+    ),
+    security(("api_key" = []))
+)]
+pub async fn list_orders(
+    State(handle): State<Arc<EngineHandle>>,
+    caller: Option<Extension<Principal>>,
+    Query(query): Query<ListOrdersQuery>,
+) -> Response {
+    let invalid = |message: String| {
+        (StatusCode::BAD_REQUEST, Json(serde_json::json!({ "success": false, "message": message }))).into_response()
+    };
+    let side = match query.side.as_deref().map(str::to_lowercase).as_deref() {
+        None => None,
+        Some("buy") => Some(Side::Buy),
+        Some("sell") => Some(Side::Sell),
+        Some(_) => return invalid("Invalid side. Must be 'buy' or 'sell'".to_string()),
+    };
+    let symbol = &handle.config.symbol;
+    if let Some(requested) = query.symbol.filter(|requested| url_key(requested) != url_key(symbol)) {
+        let key = url_key(&requested);
+        return invalid(format!("symbol {requested} is not served here; its orders are at /api/{key}/orders"));
+    }
+    let limit = match query.limit {
+        None => DEFAULT_ORDER_LIST_LIMIT,
+        Some(limit) if (1..=MAX_ORDER_LIST_LIMIT).contains(&limit) => limit,
+        Some(_) => return invalid(format!("limit must be between 1 and {MAX_ORDER_LIST_LIMIT}")),
+    };
+    let owner_id = match acting_owner(caller, query.owner_id) {
+        Ok(owner_id) => owner_id.filter(|id| !id.trim().is_empty()),
+        Err(message) => return not_owner(message).into_response(),
+    };
+
+    let Some(listing) = handle.list_orders(owner_id, side, limit).await else {
+        let body = serde_json::json!({ "success": false, "message": "Engine unavailable" });
+        return (StatusCode::SERVICE_UNAVAILABLE, Json(body)).into_response();
+    };
+    let orders = listing
+        .orders
+        .into_iter()
+        .map(|order| ListedOrder {
+            age_ms: (listing.as_of - order.timestamp).num_milliseconds().max(0),
+            order: OrderDetails::open(handle.ids.uuid(order.id), order),
+        })
+        .collect();
+    Json(OrderListResponse {
+        symbol: symbol.clone(),
+        total: listing.total,
+        orders,
+    })
+    .into_response()
+}
+
+/// Look up an order by id. Open orders are answered by the engine; once an
+/// order has left it, by the order journal when the server keeps one.
+/// Orders neither knows of are 404s, as are other owners' orders to API
+/// keys that are not admin keys.
+#[utoipa::path(
+    get,
+    path = "/api/orders/{id}",
+    tag = "orders",
+    params(("id" = Uuid, Path, description = "Order id")),
+    responses(
+        (status = 200, description = "The order", body = OrderDetails),
+        (status = 404, description = "Order not found", body = ErrorResponse),
+        (status = 503, description = "Engine or order journal unavailable", body = ErrorResponse),
+    ),
+    security(("api_key" = []))
+)]
+pub async fn get_order(
+    State(handle): State<Arc<EngineHandle>>,
+    caller: Option<Extension<Principal>>,
+    archive: Option<Extension<Arc<dyn OrderArchive>>>,
+    Path(order_id): Path<Uuid>,
+) -> Response {
+    let failure = |status: StatusCode, message: &str| {
+        (status, Json(serde_json::json!({ "success": false, "message": message }))).into_response()
+    };
+    let hidden = |owner_id: Option<&str>| caller.as_ref().is_some_and(|Extension(caller)| !caller.sees(owner_id));
+    if let Some(id) = handle.ids.id(order_id) {
+        match handle.query_order(id).await {
+            Some(Some(order)) if hidden(order.owner_id.as_deref()) => {
+                return failure(StatusCode::NOT_FOUND, "Order not found")
+            }
+            Some(Some(order)) => return Json(OrderDetails::open(order_id, order)).into_response(),
+            Some(None) => {}
+            None => return failure(StatusCode::SERVICE_UNAVAILABLE, "Engine unavailable"),
+        }
+    }
+    let Some(Extension(archive)) = archive else {
+        return failure(StatusCode::NOT_FOUND, "Order not found");
+    };
+    match archive.find_order(&handle.config.symbol, order_id).await {
+        Ok(Some(order)) if !hidden(order.owner_id.as_deref()) => Json(OrderDetails::from(order)).into_response(),
+        Ok(_) => failure(StatusCode::NOT_FOUND, "Order not found"),
+        Err(e) => {
+            tracing::error!("Failed to look up order {order_id}: {e}");
+            failure(StatusCode::SERVICE_UNAVAILABLE, "Order journal unavailable")
+        }
+    }
+}
+
+/// Cancel an open order and report what the engine took off the book:
+/// 200 with the quantities, 403 if `?owner_id=` names someone else, 409 if
+/// the order journal shows it already filled or cancelled, 404 otherwise.
+/// The cancel is also published on the WebSocket feed. With API keys, only
+/// the key owner's order is cancelled, unless it is an admin key.
+#[utoipa::path(
+    delete,
+    path = "/api/orders/{id}",
+    tag = "orders",
+    params(("id" = Uuid, Path, description = "Order id"), CancelOrderQuery),
+    responses(
+        (status = 200, description = "Cancelled", body = CancelOrderResponse),
+        (status = 403, description = "Another owner's order", body = SubmitOrderResponse),
+        (status = 404, description = "Order not open", body = SubmitOrderResponse),
+        (status = 409, description = "Order already filled or cancelled", body = SubmitOrderResponse),
+        (status = 503, description = "Engine unavailable", body = SubmitOrderResponse),
+    ),
+    security(("api_key" = []))
+)]
+pub async fn cancel_order(
+    State(handle): State<Arc<EngineHandle>>,
+    caller: Option<Extension<Principal>>,
+    archive: Option<Extension<Arc<dyn OrderArchive>>>,
+    Path(order_id): Path<Uuid>,
+    Query(query): Query<CancelOrderQuery>,
+) -> Response {
+    let refused = |status: StatusCode, message: String, code: Option<&str>| {
+        let body = SubmitOrderResponse {
+            order_id: Some(order_id),
+            ..SubmitOrderResponse::rejected(message, code.map(str::to_string))
+        };
+        (status, Json(body)).into_response()
+    };
+    let owner_id = match acting_owner(caller, query.owner_id) {
+        Ok(owner_id) => owner_id.filter(|id| !id.trim().is_empty()),
+        Err(message) => return refused(StatusCode::FORBIDDEN, message, None),
+    };
+    let reason = match handle.cancel_order_confirmed(handle.ids.lookup(order_id), owner_id).await {
+        Some(Ok(ack)) => {
+            let body = CancelOrderResponse {
+                success: true,
+                message: "Order cancelled".to_string(),
+                order_id,
+                filled_quantity: ack.filled_quantity.to_string(),
+                remaining_quantity: ack.remaining_quantity.to_string(),
+            };
+            return Json(body).into_response();
+        }
+        Some(Err(reason)) => reason,
+        None => return refused(StatusCode::SERVICE_UNAVAILABLE, "Engine unavailable".to_string(), None),
+    };
+    if reason != RejectReason::UnknownOrder {
+        return refused(StatusCode::FORBIDDEN, reason.to_string(), Some(reason.code()));
+    }
+
+    // Not open: the journal tells a closed order from one never seen
+    if let Some(Extension(archive)) = archive {
+        match archive.find_order(&handle.config.symbol, order_id).await {
+            Ok(Some(order)) => {
+                let (message, code) = match order.status {
+                    OrderStatus::Filled => ("Order already filled", ORDER_FILLED),
+                    _ => ("Order already closed", ORDER_CLOSED),
+                };
+                return refused(StatusCode::CONFLICT, message.to_string(), Some(code));
+            }
+            Ok(None) => {}
+            Err(e) => tracing::error!("Failed to look up order {order_id}: {e}"),
+        }
+    }
+    refused(StatusCode::NOT_FOUND, reason.to_string(), Some(reason.code()))
+}
+
+/// Request cancellation of every open order belonging to an owner, the API
+/// key's own unless it is an admin key. The engine reports each
+/// cancellation and a summary on the WebSocket feed.
+#[utoipa::path(
+    delete,
+    path = "/api/orders",
+    tag = "orders",
+    params(CancelAllQuery),
+    responses(
+        (status = 202, description = "Handed to the engine", body = SubmitOrderResponse),
+        (status = 400, description = "Missing owner or invalid side", body = SubmitOrderResponse),
+        (status = 403, description = "Names an owner the API key may not act for", body = SubmitOrderResponse),
+        (status = 503, description = "Engine unavailable", body = SubmitOrderResponse),
+    ),
+    security(("api_key" = []))
+)]
+pub async fn cancel_all_orders(
+    State(handle): State<Arc<EngineHandle>>,
+    caller: Option<Extension<Principal>>,
+    Query(query): Query<CancelAllQuery>,
+) -> impl IntoResponse {
+    let owner_id = match acting_owner(caller, query.owner_id) {
+        Ok(owner_id) => owner_id,
+        Err(message) => return not_owner(message),
+    };
+    let Some(owner_id) = owner_id.filter(|id| !id.trim().is_empty()) else {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(SubmitOrderResponse::rejected("owner_id is required", None)),
+        );
+    };
+
+    let side = match query.side.as_deref().map(str::to_lowercase).as_deref() {
+        None => None,
+        Some("buy") => Some(Side::Buy),
+        Some("sell") => Some(Side::Sell),
+        Some(_) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(SubmitOrderResponse::rejected("Invalid side. Must be 'buy' or 'sell'", None)),
+            );
+        }
+    };
+
+    match handle.cancel_all(owner_id, side).await {
+        Ok(_) => (
+            StatusCode::ACCEPTED,
             Json(SubmitOrderResponse {
-                success: false,
-                message: "Engine unavailable".to_string(),
+                success: true,
+                message: "Cancel-all request submitted".to_string(),
                 order_id: None,
+                code: None,
+                client_order_id: None,
+                ack: None,
+            }),
+        ),
+        Err(_) => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(SubmitOrderResponse::rejected("Engine unavailable", None)),
+        ),
+    }
+}
+
+/// Request an amendment to a resting order, the API key owner's unless it
+/// is an admin key.
+/// The engine reports the outcome, including any replacement order id, on the
+/// WebSocket feed.
+#[utoipa::path(
+    patch,
+    path = "/api/orders/{id}",
+    tag = "orders",
+    params(("id" = Uuid, Path, description = "Order id")),
+    request_body = ModifyOrderRequest,
+    responses(
+        (status = 202, description = "Handed to the engine", body = SubmitOrderResponse),
+        (status = 400, description = "Nothing to change, or a non-positive value", body = SubmitOrderResponse),
+        (status = 403, description = "Another owner's order", body = SubmitOrderResponse),
+        (status = 503, description = "Engine unavailable", body = SubmitOrderResponse),
+    ),
+    security(("api_key" = []))
+)]
+pub async fn modify_order(
+    State(handle): State<Arc<EngineHandle>>,
+    caller: Option<Extension<Principal>>,
+    Path(order_id): Path<Uuid>,
+    Json(req): Json<ModifyOrderRequest>,
+) -> impl IntoResponse {
+    // An order's owner never changes, so it is safe to check it first
+    if let Some(Extension(caller)) = caller.filter(|Extension(caller)| !caller.allows(Scope::Admin)) {
+        let owner = match handle.ids.id(order_id) {
+            Some(id) => handle.query_order(id).await.flatten().and_then(|order| order.owner_id),
+            None => None,
+        };
+        if !caller.sees(owner.as_deref()) {
+            return not_owner(format!("API key for {} cannot modify order {order_id}", caller.owner_id));
+        }
+    }
+
+    if req.price.is_none() && req.quantity.is_none() {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(SubmitOrderResponse::rejected("Nothing to modify. Provide price and/or quantity", None)),
+        );
+    }
+
+    if req.price.is_some_and(|p| p <= Decimal::ZERO) || req.quantity.is_some_and(|q| q <= Decimal::ZERO) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(SubmitOrderResponse::rejected("Price and quantity must be positive", None)),
+        );
+    }
+
+    match handle.modify_order(handle.ids.lookup(order_id), req.price, req.quantity).await {
+        Ok(_) => (
+            StatusCode::ACCEPTED,
+            Json(SubmitOrderResponse {
+                success: true,
+                message: "Modify request submitted".to_string(),
+                order_id: Some(order_id),
+                code: None,
+                client_order_id: None,
+                ack: None,
             }),
         ),
+        Err(_) => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(SubmitOrderResponse::rejected("Engine unavailable", None)),
+        ),
     }
 }
 
-/// Health check endpoint
-pub async fn health_check() -> impl IntoResponse {
-    Json(serde_json::json!({
-        "status": "healthy",
-        "service": "clob-engine"
-    }))
+/// Health check endpoint, with each engine's liveness and command queue
+/// depth. The service is `unhealthy` (503) while any engine has stopped or
+/// missed its heartbeats, since its orders are not being matched, and
+/// `degraded` while any queue is full, since submissions to that engine are
+/// being refused.
+#[utoipa::path(
+    get,
+    path = "/api/health",
+    tag = "service",
+    responses(
+        (status = 200, description = "Healthy or degraded", body = serde_json::Value),
+        (status = 503, description = "An engine has stopped", body = serde_json::Value),
+    )
+)]
+pub async fn health_check(State(engines): State<Arc<EngineRegistry>>) -> impl IntoResponse {
+    let depths: Vec<_> = engines.iter().map(|handle| (&handle.config.symbol, handle.queue_depth())).collect();
+    let saturated = depths.iter().any(|(_, depth)| depth.is_full());
+    let stalled = engines.iter().any(|handle| !handle.is_live());
+    let queues: Vec<_> = depths
+        .iter()
+        .map(|(symbol, depth)| {
+            serde_json::json!({
+                "symbol": symbol,
+                "len": depth.len,
+                "capacity": depth.capacity,
+                "saturated": depth.is_full(),
+            })
+        })
+        .collect();
+    let liveness: Vec<_> = engines
+        .iter()
+        .map(|handle| {
+            serde_json::json!({
+                "symbol": handle.config.symbol,
+                "live": handle.is_live(),
+                "heartbeat_age_ms": handle.heartbeat_age().as_millis() as u64,
+            })
+        })
+        .collect();
+    let (status, health) = match (stalled, saturated) {
+        (true, _) => (StatusCode::SERVICE_UNAVAILABLE, "unhealthy"),
+        (false, true) => (StatusCode::OK, "degraded"),
+        (false, false) => (StatusCode::OK, "healthy"),
+    };
+    (
+        status,
+        Json(serde_json::json!({
+            "status": health,
+            "service": "clob-engine",
+            "engines": liveness,
+            "queues": queues,
+        })),
+    )
 }
 
-/// Get current order book state
+/// Get current order book state, optionally with depth grouped into
+/// `?group=` sized price buckets, or with `?notional=true` each level's
+/// price × quantity and the running total, in the symbol's quote precision.
+/// `?levels=` sets the depth per side, up to 500; the broadcast book
+/// answers when it is deep enough, and the engine otherwise.
+#[utoipa::path(
+    get,
+    path = "/api/orderbook",
+    tag = "market data",
+    params(OrderBookQuery),
+    responses(
+        (status = 200, description = "Book snapshot", body = OrderBookResponse),
+        (status = 400, description = "Invalid grouping", body = ErrorResponse),
+        (status = 503, description = "Engine unavailable", body = ErrorResponse),
+    ),
+    security((), ("api_key" = []))
+)]
 pub async fn get_order_book(
     State(handle): State<Arc<EngineHandle>>,
-) -> impl IntoResponse {
-    let snapshot = handle.current_state.read().await;
-    
-    Json(serde_json::json!({
-        "best_bid": snapshot.best_bid.map(|p| p.to_string()),
-        "best_ask": snapshot.best_ask.map(|p| p.to_string()),
-        "bids": snapshot.bid_depth.iter().map(|(p, q)| [p.to_string(), q.to_string()]).collect::<Vec<_>>(),
-        "asks": snapshot.ask_depth.iter().map(|(p, q)| [p.to_string(), q.to_string()]).collect::<Vec<_>>(),
-    }))
+    Query(query): Query<OrderBookQuery>,
+) -> Response {
+    let failure = |status: StatusCode, message: &str| {
+        (status, Json(serde_json::json!({ "success": false, "message": message }))).into_response()
+    };
+    if query.group.is_some() && query.notional {
+        return failure(StatusCode::BAD_REQUEST, "notional depth cannot be grouped");
+    }
+    let levels = query.levels.map(|levels| levels.min(MAX_BOOK_LEVELS));
+    let unavailable = || failure(StatusCode::SERVICE_UNAVAILABLE, "Engine unavailable");
+    let mut snapshot = match query.group {
+        None => {
+            let cached = handle.current_state.borrow().clone();
+            match levels {
+                // Short of levels on a side, the broadcast book may have
+                // been cut off there
+                Some(levels) if cached.bid_depth.len() < levels || cached.ask_depth.len() < levels => {
+                    match handle.deep_book(levels).await {
+                        Some(snapshot) => snapshot,
+                        None => return unavailable(),
+                    }
+                }
+                _ => cached,
+            }
+        }
+        Some(group) if group <= Decimal::ZERO => return failure(StatusCode::BAD_REQUEST, "group must be positive"),
+        Some(group) => match handle.grouped_book(group).await {
+            Some(snapshot) => snapshot,
+            None => return unavailable(),
+        },
+    };
+    if let Some(levels) = levels {
+        snapshot.bid_depth.truncate(levels);
+        snapshot.ask_depth.truncate(levels);
+    }
+    let notional_scale = query.notional.then(|| handle.config.quote_scale());
+
+    Json(OrderBookResponse {
+        version: FEED_VERSION,
+        best_bid: snapshot.best_bid.map(|p| p.to_string()),
+        best_ask: snapshot.best_ask.map(|p| p.to_string()),
+        best_bid_qty: snapshot.best_bid_qty.map(|q| q.to_string()),
+        best_ask_qty: snapshot.best_ask_qty.map(|q| q.to_string()),
+        mid_price: snapshot.mid_price.map(|p| p.to_string()),
+        microprice: snapshot.microprice.map(|p| p.to_string()),
+        top_imbalance: snapshot.top_imbalance.map(|i| i.to_string()),
+        depth_imbalance: snapshot.depth_imbalance.map(|i| i.to_string()),
+        checksum: snapshot.checksum,
+        bids: depth_rows(&snapshot.bid_depth, notional_scale),
+        asks: depth_rows(&snapshot.ask_depth, notional_scale),
+        sequence: snapshot.sequence,
+        timestamp: snapshot.timestamp.timestamp_millis(),
+    })
+    .into_response()
+}
+
+/// Displayed liquidity within `?distance=` of each touch, for estimating
+/// the impact of an order before sending it, with cumulative depth per side
+#[utoipa::path(
+    get,
+    path = "/api/orderbook/liquidity",
+    tag = "market data",
+    params(LiquidityQuery),
+    responses(
+        (status = 200, description = "Liquidity near the touch", body = LiquidityResponse),
+        (status = 400, description = "Negative distance", body = ErrorResponse),
+        (status = 503, description = "Engine unavailable", body = ErrorResponse),
+    ),
+    security((), ("api_key" = []))
+)]
+pub async fn get_liquidity(
+    State(handle): State<Arc<EngineHandle>>,
+    Query(query): Query<LiquidityQuery>,
+) -> Response {
+    let failure = |status: StatusCode, message: &str| {
+        (status, Json(serde_json::json!({ "success": false, "message": message }))).into_response()
+    };
+    if query.distance < Decimal::ZERO {
+        return failure(StatusCode::BAD_REQUEST, "distance must not be negative");
+    }
+    let Some(liquidity) = handle.liquidity(query.distance).await else {
+        return failure(StatusCode::SERVICE_UNAVAILABLE, "Engine unavailable");
+    };
+
+    let levels = |depth: &[(Decimal, Decimal)]| {
+        depth
+            .iter()
+            .map(|(p, q)| [p.to_string(), q.to_string()])
+            .collect::<Vec<_>>()
+    };
+    Json(LiquidityResponse {
+        distance: liquidity.distance.to_string(),
+        bid_liquidity: liquidity.bid_liquidity.to_string(),
+        ask_liquidity: liquidity.ask_liquidity.to_string(),
+        bids: levels(&liquidity.bid_depth),
+        asks: levels(&liquidity.ask_depth),
+    })
+    .into_response()
+}
+
+/// Expected average price of an order for `?quantity=` on `?side=`, from
+/// the displayed book. A book too thin for the quantity is reported with
+/// `complete: false`, not an error.
+#[utoipa::path(
+    get,
+    path = "/api/orderbook/impact",
+    tag = "market data",
+    params(ImpactQuery),
+    responses(
+        (status = 200, description = "Estimated fill", body = PriceImpactResponse),
+        (status = 400, description = "Invalid side or quantity", body = ErrorResponse),
+        (status = 503, description = "Engine unavailable", body = ErrorResponse),
+    ),
+    security((), ("api_key" = []))
+)]
+pub async fn get_price_impact(
+    State(handle): State<Arc<EngineHandle>>,
+    Query(query): Query<ImpactQuery>,
+) -> Response {
+    let failure = |status: StatusCode, message: &str| {
+        (status, Json(serde_json::json!({ "success": false, "message": message }))).into_response()
+    };
+    let side = match query.side.to_lowercase().as_str() {
+        "buy" => Side::Buy,
+        "sell" => Side::Sell,
+        _ => return failure(StatusCode::BAD_REQUEST, "side must be buy or sell"),
+    };
+    if query.quantity <= Decimal::ZERO {
+        return failure(StatusCode::BAD_REQUEST, "quantity must be positive");
+    }
+    let Some(impact) = handle.price_impact(side, query.quantity).await else {
+        return failure(StatusCode::SERVICE_UNAVAILABLE, "Engine unavailable");
+    };
+
+    Json(PriceImpactResponse {
+        side,
+        quantity: query.quantity.to_string(),
+        vwap: impact.vwap.map(|p| p.to_string()),
+        worst_price: impact.worst_price.map(|p| p.to_string()),
+        fillable_quantity: impact.fillable.to_string(),
+        complete: impact.complete,
+    })
+    .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::router;
+    use crate::api::test_support::{next_of_type, request, spawn_app, start_stack};
+    use crate::engine::{EngineBuilder, ManualClock, OrderStatus, Side};
+    use crate::persistence::{ArchivedOrder, OrderArchive};
+    use axum::{http::StatusCode, Extension};
+    use chrono::Utc;
+    use futures::future::BoxFuture;
+    use rust_decimal_macros::dec;
+    use serde_json::json;
+    use std::sync::Arc;
+    use uuid::Uuid;
+
+    #[tokio::test]
+    async fn test_time_in_force_parsing() {
+        let app = spawn_app(EngineBuilder::new("BTC/USD"));
+
+        for tif in ["gtc", "IOC", "fok"] {
+            let order = json!({ "side": "buy", "price": "100", "quantity": "1", "time_in_force": tif });
+            let (status, _) = request(&app, "POST", "/api/orders", Some(order)).await;
+            assert_eq!(status, StatusCode::ACCEPTED, "{tif}");
+        }
+
+        let order = json!({ "side": "buy", "price": "100", "quantity": "1", "time_in_force": "day" });
+        let (status, body) = request(&app, "POST", "/api/orders", Some(order)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["success"], false);
+    }
+
+    #[tokio::test]
+    async fn test_expires_at_requires_gtd() {
+        let app = spawn_app(EngineBuilder::new("BTC/USD"));
+        let expires_at = (chrono::Utc::now() + chrono::Duration::hours(1)).to_rfc3339();
+
+        let order = json!({ "side": "buy", "price": "100", "quantity": "1", "time_in_force": "gtd" });
+        let (status, _) = request(&app, "POST", "/api/orders", Some(order)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let order = json!({
+            "side": "buy", "price": "100", "quantity": "1",
+            "time_in_force": "ioc", "expires_at": expires_at,
+        });
+        let (status, _) = request(&app, "POST", "/api/orders", Some(order)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        // expires_at alone implies gtd
+        let order = json!({ "side": "buy", "price": "100", "quantity": "1", "expires_at": expires_at });
+        let (status, _) = request(&app, "POST", "/api/orders", Some(order)).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+    }
+
+    #[tokio::test]
+    async fn test_max_slippage_only_on_market_orders() {
+        let (app, mut ws) = start_stack(Arc::new(ManualClock::new(chrono::Utc::now()))).await;
+
+        let limit = json!({ "side": "buy", "price": "100", "quantity": "1", "max_slippage": "1" });
+        let (status, _) = request(&app, "POST", "/api/orders", Some(limit)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let negative = json!({ "side": "buy", "order_type": "market", "quantity": "1", "max_slippage": "-1" });
+        let (status, _) = request(&app, "POST", "/api/orders", Some(negative)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        for price in ["100", "103"] {
+            let ask = json!({ "side": "sell", "price": price, "quantity": "1" });
+            request(&app, "POST", "/api/orders", Some(ask)).await;
+        }
+        let market = json!({ "side": "buy", "order_type": "market", "quantity": "2", "max_slippage": "2" });
+        let (status, _) = request(&app, "POST", "/api/orders", Some(market)).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        let cancelled = next_of_type(&mut ws, "order_cancelled").await;
+        assert_eq!(cancelled["reason"], "SLIPPAGE_LIMIT");
+        assert_eq!(cancelled["remaining_quantity"], "1");
+    }
+
+    #[tokio::test]
+    async fn test_stp_policy_over_http() {
+        let (app, mut ws) = start_stack(Arc::new(ManualClock::new(chrono::Utc::now()))).await;
+
+        let bogus = json!({ "side": "buy", "price": "100", "quantity": "1", "stp_policy": "cancel_both" });
+        let (status, _) = request(&app, "POST", "/api/orders", Some(bogus)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let ask = json!({ "owner_id": "alice", "side": "sell", "price": "100", "quantity": "1" });
+        request(&app, "POST", "/api/orders", Some(ask)).await;
+        let bid = json!({
+            "owner_id": "alice", "side": "buy", "price": "100", "quantity": "3", "stp_policy": "cancel_newest"
+        });
+        let (status, _) = request(&app, "POST", "/api/orders", Some(bid)).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+
+        // The ask is accepted first
+        next_of_type(&mut ws, "order_accepted").await;
+        let accepted = next_of_type(&mut ws, "order_accepted").await;
+        let cancelled = next_of_type(&mut ws, "order_cancelled").await;
+        assert_eq!(cancelled["order_id"], accepted["order_id"]);
+        assert_eq!(cancelled["reason"], "SELF_TRADE");
+        assert_eq!(cancelled["remaining_quantity"], "3");
+    }
+
+    #[tokio::test]
+    async fn test_cancel_order_by_returned_id() {
+        let (app, mut ws) = start_stack(Arc::new(ManualClock::new(chrono::Utc::now()))).await;
+
+        let order = json!({ "side": "sell", "price": "101", "quantity": "2" });
+        let (_, body) = request(&app, "POST", "/api/orders?confirm=true", Some(order)).await;
+        let order_id = body["order_id"].as_str().unwrap().to_string();
+
+        let (status, body) = request(&app, "DELETE", &format!("/api/orders/{order_id}"), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!((body["order_id"].as_str(), body["remaining_quantity"].as_str()), (Some(&*order_id), Some("2")));
+
+        let cancelled = next_of_type(&mut ws, "order_cancelled").await;
+        assert_eq!(cancelled["order_id"], order_id);
+        assert_eq!(cancelled["reason"], "USER_REQUESTED");
+
+        // A second cancel for the same id is refused, as is one for a UUID
+        // the engine never gave out
+        let (status, body) = request(&app, "DELETE", &format!("/api/orders/{order_id}"), None).await;
+        assert_eq!((status, &body["code"]), (StatusCode::NOT_FOUND, &json!("UNKNOWN_ORDER")));
+        let rejected = next_of_type(&mut ws, "cancel_rejected").await;
+        assert_eq!(rejected["reason"], "UNKNOWN_ORDER");
+        let (status, _) = request(&app, "DELETE", &format!("/api/orders/{}", Uuid::new_v4()), None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let rejected = next_of_type(&mut ws, "cancel_rejected").await;
+        assert_eq!(rejected["reason"], "UNKNOWN_ORDER");
+    }
+
+    #[tokio::test]
+    async fn test_cancel_partially_filled_order_clears_its_depth() {
+        let app = spawn_app(EngineBuilder::new("BTC/USD"));
+        let maker = json!({ "side": "sell", "price": "101", "quantity": "3", "owner_id": "alice" });
+        let (_, maker) = request(&app, "POST", "/api/orders?confirm=true", Some(maker)).await;
+        let other = json!({ "side": "sell", "price": "102", "quantity": "1" });
+        request(&app, "POST", "/api/orders?confirm=true", Some(other)).await;
+        let taker = json!({ "side": "buy", "price": "101", "quantity": "1" });
+        request(&app, "POST", "/api/orders?confirm=true", Some(taker)).await;
+        let (_, book) = request(&app, "GET", "/api/orderbook", None).await;
+        assert_eq!(book["asks"], json!([["101", "2", "1"], ["102", "1", "1"]]));
+
+        let uri = format!("/api/orders/{}", maker["order_id"].as_str().unwrap());
+        let (status, body) = request(&app, "DELETE", &format!("{uri}?owner_id=bob"), None).await;
+        assert_eq!((status, &body["code"]), (StatusCode::FORBIDDEN, &json!("NOT_ORDER_OWNER")));
+
+        let (status, body) = request(&app, "DELETE", &format!("{uri}?owner_id=alice"), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!((body["filled_quantity"].as_str(), body["remaining_quantity"].as_str()), (Some("1"), Some("2")));
+        // Answered once the book update is out
+        let (_, book) = request(&app, "GET", "/api/orderbook", None).await;
+        assert_eq!(book["asks"], json!([["102", "1", "1"]]));
+    }
+
+    #[tokio::test]
+    async fn test_cancel_of_a_filled_order_conflicts() {
+        let (engine, handle) = EngineBuilder::new("BTC/USD").build();
+        tokio::spawn(engine.run());
+        let filled = ArchivedOrder {
+            order_id: Uuid::new_v4(),
+            owner_id: None,
+            client_order_id: None,
+            side: Side::Buy,
+            price: dec!(100),
+            quantity: dec!(1),
+            filled_quantity: dec!(1),
+            status: OrderStatus::Filled,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        };
+        let archive: Arc<dyn OrderArchive> = Arc::new(StubArchive(vec![filled.clone()]));
+        let app = router(Arc::new(handle)).layer(Extension(archive));
+
+        let (status, body) = request(&app, "DELETE", &format!("/api/orders/{}", filled.order_id), None).await;
+        assert_eq!((status, &body["code"]), (StatusCode::CONFLICT, &json!("ORDER_FILLED")));
+        let (status, _) = request(&app, "DELETE", &format!("/api/orders/{}", Uuid::new_v4()), None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_modify_order_over_http() {
+        let (app, mut ws) = start_stack(Arc::new(ManualClock::new(chrono::Utc::now()))).await;
+
+        let order = json!({ "side": "buy", "price": "99", "quantity": "5" });
+        let (_, body) = request(&app, "POST", "/api/orders?confirm=true", Some(order)).await;
+        let order_id = body["order_id"].as_str().unwrap().to_string();
+        let uri = format!("/api/orders/{order_id}");
+
+        let (status, _) = request(&app, "PATCH", &uri, Some(json!({}))).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (status, _) = request(&app, "PATCH", &uri, Some(json!({ "quantity": "3" }))).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        let modified = next_of_type(&mut ws, "order_modified").await;
+        assert_eq!(modified["new_order_id"], order_id);
+        assert_eq!(modified["remaining_quantity"], "3");
+
+        request(&app, "PATCH", &uri, Some(json!({ "price": "98" }))).await;
+        let modified = next_of_type(&mut ws, "order_modified").await;
+        assert_ne!(modified["new_order_id"], order_id);
+        assert_eq!(modified["price"], "98");
+    }
+
+    #[tokio::test]
+    async fn test_off_tick_price_rejected() {
+        let app = spawn_app(EngineBuilder::new("BTC/USD").tick_size(rust_decimal_macros::dec!(0.01)));
+
+        let order = json!({ "side": "buy", "price": "100.000000001", "quantity": "1" });
+        let (status, body) = request(&app, "POST", "/api/orders", Some(order)).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["code"], "INVALID_TICK_SIZE");
+        assert!(body["message"].as_str().unwrap().contains("tick size 0.01"));
+
+        let order = json!({ "side": "buy", "price": "100.10", "quantity": "1" });
+        let (status, _) = request(&app, "POST", "/api/orders", Some(order)).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+    }
+
+    #[tokio::test]
+    async fn test_over_scale_values_rejected_with_precision() {
+        let app = spawn_app(EngineBuilder::new("BTC/USD").price_scale(2).quantity_scale(4));
+
+        let order = json!({ "side": "buy", "price": "100.123456789012345678", "quantity": "1" });
+        let (status, body) = request(&app, "POST", "/api/orders", Some(order)).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["code"], "PRICE_SCALE_EXCEEDED");
+        assert_eq!(
+            body["message"],
+            "price 100.123456789012345678 exceeds the allowed precision of 2 decimal places"
+        );
+
+        let order = json!({ "side": "buy", "price": "100", "quantity": "0.00005" });
+        let (_, body) = request(&app, "POST", "/api/orders", Some(order)).await;
+        assert_eq!(body["code"], "QUANTITY_SCALE_EXCEEDED");
+
+        // Trailing zeros are not extra precision
+        let order = json!({ "side": "buy", "price": "100.1000", "quantity": "1.00000" });
+        let (status, _) = request(&app, "POST", "/api/orders", Some(order)).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+    }
+
+    #[tokio::test]
+    async fn test_quantity_rules_return_structured_error() {
+        let builder = EngineBuilder::new("BTC/USD")
+            .lot_size(rust_decimal_macros::dec!(0.001))
+            .min_quantity(rust_decimal_macros::dec!(0.01));
+        let app = spawn_app(builder);
+
+        let order = json!({ "side": "sell", "price": "100", "quantity": "0.0005" });
+        let (status, body) = request(&app, "POST", "/api/orders", Some(order)).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["code"], "BELOW_MIN_QUANTITY");
+        assert_eq!(body["message"], "quantity 0.0005 is below the minimum of 0.01");
+    }
+
+    #[tokio::test]
+    async fn test_price_band_rejection_returns_422() {
+        let (engine, handle) = EngineBuilder::new("BTC/USD")
+            .price_band_pct(rust_decimal_macros::dec!(5))
+            .build();
+        tokio::spawn(engine.run());
+        let mut events = handle.subscribe();
+        let app = crate::api::router(Arc::new(handle));
+
+        let order = json!({ "side": "sell", "price": "100", "quantity": "1" });
+        request(&app, "POST", "/api/orders", Some(order)).await;
+        // Wait for the book update so the shared snapshot has a reference price
+        while !matches!(events.recv().await.unwrap().event, crate::engine::EngineEvent::OrderBookUpdate(_)) {}
+
+        let order = json!({ "side": "buy", "price": "150", "quantity": "1" });
+        let (status, body) = request(&app, "POST", "/api/orders", Some(order)).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["code"], "PRICE_OUTSIDE_BAND");
+        assert_eq!(body["message"], "price 150 is more than 5% away from reference price 100");
+    }
+
+    #[tokio::test]
+    async fn test_client_order_id_echoed_and_deduplicated() {
+        let (app, mut ws) = start_stack(Arc::new(ManualClock::new(chrono::Utc::now()))).await;
+        let order = json!({
+            "owner_id": "alice",
+            "client_order_id": "oms-42",
+            "side": "buy",
+            "price": "100",
+            "quantity": "1"
+        });
+
+        let (status, body) = request(&app, "POST", "/api/orders", Some(order.clone())).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        assert_eq!(body["client_order_id"], "oms-42");
+        // The id reserved for it before queuing is the one the acceptance carries
+        let accepted = next_of_type(&mut ws, "order_accepted").await;
+        assert_eq!(accepted["client_order_id"], "oms-42");
+        assert_eq!(body["order_id"], accepted["order_id"]);
+
+        request(&app, "POST", "/api/orders", Some(order)).await;
+        let rejected = next_of_type(&mut ws, "order_rejected").await;
+        assert_ne!(rejected["order_id"], accepted["order_id"]);
+        assert_eq!(rejected["reason"], "DUPLICATE_CLIENT_ORDER_ID");
+        assert_eq!(rejected["client_order_id"], "oms-42");
+    }
+
+    #[tokio::test]
+    async fn test_confirmed_submit_reports_engine_verdict() {
+        let app = spawn_app(EngineBuilder::new("BTC/USD"));
+        let maker = json!({ "side": "sell", "price": "100", "quantity": "1" });
+        let (status, body) = request(&app, "POST", "/api/orders?confirm=true", Some(maker)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["success"], true);
+        assert_eq!(body["state"], "resting");
+        assert_eq!(body["remaining_quantity"], "1");
+        assert!(body.get("average_price").is_none());
+
+        // Only the engine knows this would cross the resting ask
+        let post_only = json!({
+            "client_order_id": "po-1",
+            "side": "buy",
+            "price": "100",
+            "quantity": "1",
+            "post_only": true
+        });
+        let (status, body) = request(&app, "POST", "/api/orders?confirm=true", Some(post_only.clone())).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["code"], "POST_ONLY_WOULD_CROSS");
+        assert_eq!(body["client_order_id"], "po-1");
+        assert!(body["order_id"].is_null());
+
+        let (status, body) = request(&app, "POST", "/api/orders", Some(post_only)).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        assert!(body.get("state").is_none());
+
+        let taker = json!({ "side": "buy", "price": "100", "quantity": "1" });
+        let (status, body) = request(&app, "POST", "/api/orders?confirm=true", Some(taker)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["state"], "filled");
+        assert_eq!((&body["filled_quantity"], &body["average_price"]), (&json!("1"), &json!("100")));
+    }
+
+    #[tokio::test]
+    async fn test_trade_message_carries_both_owners() {
+        let (app, mut ws) = start_stack(Arc::new(ManualClock::new(chrono::Utc::now()))).await;
+
+        let maker = json!({ "owner_id": "alice", "side": "sell", "price": "100", "quantity": "1" });
+        request(&app, "POST", "/api/orders", Some(maker)).await;
+        let taker = json!({ "owner_id": "bob", "side": "buy", "price": "100", "quantity": "1" });
+        request(&app, "POST", "/api/orders", Some(taker)).await;
+
+        let trade = next_of_type(&mut ws, "trade").await;
+        assert_eq!(trade["maker_owner_id"], "alice");
+        assert_eq!(trade["taker_owner_id"], "bob");
+    }
+
+    #[tokio::test]
+    async fn test_cancel_all_over_http() {
+        let (app, mut ws) = start_stack(Arc::new(ManualClock::new(chrono::Utc::now()))).await;
+        for side in ["buy", "sell"] {
+            let price = if side == "buy" { "99" } else { "101" };
+            let order = json!({ "owner_id": "alice", "side": side, "price": price, "quantity": "1" });
+            request(&app, "POST", "/api/orders", Some(order)).await;
+        }
+
+        let (status, _) = request(&app, "DELETE", "/api/orders", None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (status, _) = request(&app, "DELETE", "/api/orders?owner_id=alice&side=sell", None).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        let cancelled = next_of_type(&mut ws, "order_cancelled").await;
+        assert_eq!(cancelled["reason"], "MASS_CANCEL");
+        let summary = next_of_type(&mut ws, "mass_cancelled").await;
+        assert_eq!(summary["owner_id"], "alice");
+        assert_eq!(summary["side"], "sell");
+        assert_eq!(summary["count"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_get_order_reports_open_orders() {
+        let app = spawn_app(EngineBuilder::new("BTC/USD"));
+        let maker = json!({ "side": "buy", "price": "100", "quantity": "2", "owner_id": "alice" });
+        let (_, maker) = request(&app, "POST", "/api/orders?confirm=true", Some(maker)).await;
+        let taker = json!({ "side": "sell", "price": "100", "quantity": "0.5" });
+        let (_, taker) = request(&app, "POST", "/api/orders?confirm=true", Some(taker)).await;
+
+        let path = |ack: &serde_json::Value| format!("/api/orders/{}", ack["order_id"].as_str().unwrap());
+        let (status, order) = request(&app, "GET", &path(&maker), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(order["order_id"], maker["order_id"]);
+        assert_eq!(order["owner_id"], "alice");
+        assert_eq!(order["side"], "buy");
+        assert_eq!((order["quantity"].as_str(), order["filled_quantity"].as_str()), (Some("2"), Some("0.5")));
+        assert_eq!(order["remaining_quantity"], "1.5");
+        assert_eq!(order["status"], "partiallyfilled");
+        assert_eq!(order["open"], true);
+
+        // Filled, and with no order journal to ask
+        let (status, _) = request(&app, "GET", &path(&taker), None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, body) = request(&app, "GET", &format!("/api/orders/{}", Uuid::new_v4()), None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["success"], false);
+    }
+
+    /// Order journal holding a fixed set of orders
+    struct StubArchive(Vec<ArchivedOrder>);
+
+    impl OrderArchive for StubArchive {
+        fn find_order<'a>(
+            &'a self,
+            _symbol: &'a str,
+            order_id: Uuid,
+        ) -> BoxFuture<'a, Result<Option<ArchivedOrder>, sqlx::Error>> {
+            Box::pin(async move { Ok(self.0.iter().find(|order| order.order_id == order_id).cloned()) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_order_falls_back_to_the_order_journal() {
+        let (engine, handle) = EngineBuilder::new("BTC/USD").build();
+        tokio::spawn(engine.run());
+        let filled = ArchivedOrder {
+            order_id: Uuid::new_v4(),
+            owner_id: None,
+            client_order_id: Some("c-1".to_string()),
+            side: Side::Sell,
+            price: dec!(101),
+            quantity: dec!(3),
+            filled_quantity: dec!(3),
+            status: OrderStatus::Filled,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        };
+        let archive: Arc<dyn OrderArchive> = Arc::new(StubArchive(vec![filled.clone()]));
+        let app = router(Arc::new(handle)).layer(Extension(archive));
+
+        let (status, order) = request(&app, "GET", &format!("/api/orders/{}", filled.order_id), None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(order["client_order_id"], "c-1");
+        assert_eq!(order["status"], "filled");
+        assert_eq!((order["filled_quantity"].as_str(), order["remaining_quantity"].as_str()), (Some("3"), Some("0")));
+        assert_eq!(order["open"], false);
+        assert!(order["updated_at"].is_string());
+
+        let (status, _) = request(&app, "GET", &format!("/api/orders/{}", Uuid::new_v4()), None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_list_open_orders_with_filters() {
+        let clock = Arc::new(ManualClock::new(Utc::now()));
+        let app = spawn_app(EngineBuilder::new("BTC/USD").clock(clock.clone()));
+        for (owner, side, price) in
+            [("alice", "buy", "99"), ("bob", "buy", "98"), ("alice", "sell", "102"), ("alice", "buy", "98")]
+        {
+            let order = json!({ "owner_id": owner, "side": side, "price": price, "quantity": "1" });
+            request(&app, "POST", "/api/orders?confirm=true", Some(order)).await;
+            clock.advance(chrono::Duration::seconds(1));
+        }
+
+        let (status, body) = request(&app, "GET", "/api/orders", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!((&body["symbol"], &body["total"]), (&json!("BTC/USD"), &json!(4)));
+        let listed = |body: &serde_json::Value| -> Vec<(String, String)> {
+            let orders = body["orders"].as_array().unwrap();
+            let field = |o: &serde_json::Value, name: &str| o[name].as_str().unwrap().to_string();
+            orders.iter().map(|o| (field(o, "owner_id"), field(o, "price"))).collect()
+        };
+        // By price, then time: bob's 98 came first
+        let expected = [("bob", "98"), ("alice", "98"), ("alice", "99"), ("alice", "102")];
+        assert_eq!(listed(&body), expected.map(|(owner, price)| (owner.to_string(), price.to_string())));
+        assert_eq!(body["orders"][0]["age_ms"], 3000);
+        assert_eq!(body["orders"][0]["remaining_quantity"], "1");
+
+        // Filters compose, and the total counts past the limit
+        let (_, body) = request(&app, "GET", "/api/orders?owner_id=alice&side=buy&symbol=BTC-USD&limit=1", None).await;
+        assert_eq!(body["total"], 2);
+        assert_eq!(listed(&body), vec![("alice".to_string(), "98".to_string())]);
+
+        for query in ["side=both", "symbol=ETH/USD", "limit=0", "limit=1001"] {
+            let (status, _) = request(&app, "GET", &format!("/api/orders?{query}"), None).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{query}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_size_limits_return_422() {
+        let app = spawn_app(
+            EngineBuilder::new("BTC/USD")
+                .max_quantity(rust_decimal_macros::dec!(10))
+                .max_notional(rust_decimal_macros::dec!(1000)),
+        );
+
+        let order = json!({ "side": "buy", "price": "100", "quantity": "10" });
+        let (status, _) = request(&app, "POST", "/api/orders", Some(order)).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+
+        for (order, code) in [
+            (json!({ "side": "buy", "price": "1", "quantity": "10.5" }), "MAX_QTY_EXCEEDED"),
+            (json!({ "side": "buy", "price": "100.01", "quantity": "10" }), "MAX_NOTIONAL_EXCEEDED"),
+        ] {
+            let (status, body) = request(&app, "POST", "/api/orders", Some(order)).await;
+            assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+            assert_eq!(body["code"], code);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_order_book_grouped_into_buckets() {
+        let app = spawn_app(EngineBuilder::new("BTC/USD"));
+        for (side, price) in [("buy", "100.00"), ("buy", "100.50"), ("buy", "99.25"), ("sell", "101.50"), ("sell", "102")] {
+            let order = json!({ "side": side, "price": price, "quantity": "1" });
+            request(&app, "POST", "/api/orders", Some(order)).await;
+        }
+
+        let (status, body) = request(&app, "GET", "/api/orderbook?group=1", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["best_bid"], "100.5");
+        assert_eq!(body["mid_price"], "101");
+        assert_eq!(body["microprice"], "101");
+        assert_eq!(body["bids"], json!([["100", "2", "2"], ["99", "1", "1"]]));
+        assert_eq!(body["asks"], json!([["102", "2", "2"]]));
+
+        for group in ["0", "-1", "wide"] {
+            let (status, _) = request(&app, "GET", &format!("/api/orderbook?group={group}"), None).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{group}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_order_book_with_notional_depth() {
+        let app = spawn_app(EngineBuilder::new("BTC/USD").price_scale(2));
+        for (side, price, quantity) in [("buy", "100.50", "2"), ("buy", "99.99", "0.123"), ("sell", "101", "0.5")] {
+            let order = json!({ "side": side, "price": price, "quantity": quantity });
+            request(&app, "POST", "/api/orders", Some(order)).await;
+        }
+        // The snapshot is read without a round trip; one through the engine
+        // first means every order above is in it
+        request(&app, "GET", "/api/orderbook?group=1", None).await;
+
+        let (status, body) = request(&app, "GET", "/api/orderbook?notional=true", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            body["bids"],
+            json!([["100.5", "2", "1", "201.0", "201.0"], ["99.99", "0.123", "1", "12.30", "213.30"]])
+        );
+        assert_eq!(body["asks"], json!([["101", "0.5", "1", "50.5", "50.5"]]));
+
+        let (_, body) = request(&app, "GET", "/api/orderbook?notional=false", None).await;
+        assert_eq!(body["asks"], json!([["101", "0.5", "1"]]));
+        let (status, _) = request(&app, "GET", "/api/orderbook?group=1&notional=true", None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_order_book_levels() {
+        let app = spawn_app(EngineBuilder::new("BTC/USD"));
+        for price in 80..100 {
+            let order = json!({ "side": "buy", "price": price.to_string(), "quantity": "1" });
+            request(&app, "POST", "/api/orders", Some(order)).await;
+        }
+        for price in ["101", "102"] {
+            let order = json!({ "side": "sell", "price": price, "quantity": "1" });
+            request(&app, "POST", "/api/orders", Some(order)).await;
+        }
+        let depth = |body: &serde_json::Value, side: &str| body[side].as_array().unwrap().len();
+        // A round trip through the engine puts every order in the snapshot
+        request(&app, "GET", "/api/orderbook?group=1", None).await;
+
+        // The broadcast book holds 10 levels
+        let (_, body) = request(&app, "GET", "/api/orderbook", None).await;
+        assert_eq!((depth(&body, "bids"), depth(&body, "asks")), (10, 2));
+
+        let (status, body) = request(&app, "GET", "/api/orderbook?levels=15", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!((depth(&body, "bids"), depth(&body, "asks")), (15, 2));
+        assert_eq!((&body["bids"][14][0], &body["best_ask"]), (&json!("85"), &json!("101")));
+        assert!(body["sequence"].is_u64() && body["timestamp"].is_i64());
+
+        let (_, body) = request(&app, "GET", "/api/orderbook?levels=1", None).await;
+        assert_eq!(body["bids"], json!([["99", "1", "1"]]));
+        assert_eq!(body["asks"], json!([["101", "1", "1"]]));
+
+        let (_, body) = request(&app, "GET", "/api/orderbook?levels=0", None).await;
+        assert_eq!((&body["bids"], &body["asks"]), (&json!([]), &json!([])));
+        assert_eq!((&body["best_bid"], &body["best_ask_qty"]), (&json!("99"), &json!("1")));
+
+        // Far more than the book has is all of it
+        let (status, body) = request(&app, "GET", "/api/orderbook?levels=100000", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!((depth(&body, "bids"), depth(&body, "asks")), (20, 2));
+
+        let (_, body) = request(&app, "GET", "/api/orderbook?group=5&levels=2", None).await;
+        assert_eq!(body["bids"], json!([["95", "5", "5"], ["90", "5", "5"]]));
+    }
+
+    #[tokio::test]
+    async fn test_liquidity_endpoint() {
+        let app = spawn_app(EngineBuilder::new("BTC/USD"));
+
+        // Nothing resting yet: both sides empty
+        let (status, body) = request(&app, "GET", "/api/orderbook/liquidity?distance=1", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["bid_liquidity"], "0");
+        assert_eq!(body["ask_liquidity"], "0");
+        assert_eq!(body["bids"], json!([]));
+
+        for (side, price, quantity) in [("buy", "100", "2"), ("buy", "99.5", "1"), ("buy", "98", "3")] {
+            let order = json!({ "side": side, "price": price, "quantity": quantity });
+            request(&app, "POST", "/api/orders", Some(order)).await;
+        }
+
+        let (status, body) = request(&app, "GET", "/api/orderbook/liquidity?distance=0.5", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["distance"], "0.5");
+        assert_eq!(body["bid_liquidity"], "3");
+        assert_eq!(body["ask_liquidity"], "0");
+        assert_eq!(body["bids"], json!([["100", "2"], ["99.5", "3"], ["98", "6"]]));
+        assert_eq!(body["asks"], json!([]));
+
+        for query in ["", "?distance=-1", "?distance=wide"] {
+            let (status, _) = request(&app, "GET", &format!("/api/orderbook/liquidity{query}"), None).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{query}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_price_impact_endpoint() {
+        let app = spawn_app(EngineBuilder::new("BTC/USD"));
+        for (price, quantity) in [("101", "2"), ("102", "3")] {
+            let order = json!({ "side": "sell", "price": price, "quantity": quantity });
+            request(&app, "POST", "/api/orders", Some(order)).await;
+        }
+
+        let (status, body) = request(&app, "GET", "/api/orderbook/impact?side=buy&quantity=4.0", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["vwap"], "101.5");
+        assert_eq!(body["worst_price"], "102");
+        assert_eq!(body["fillable_quantity"], "4.0");
+        assert_eq!(body["complete"], true);
+
+        // Thinner than asked is an answer, not an error
+        let (status, body) = request(&app, "GET", "/api/orderbook/impact?side=BUY&quantity=10", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["vwap"], "101.6");
+        assert_eq!(body["fillable_quantity"], "5");
+        assert_eq!(body["complete"], false);
+
+        let (status, body) = request(&app, "GET", "/api/orderbook/impact?side=sell&quantity=1", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["vwap"], serde_json::Value::Null);
+        assert_eq!(body["fillable_quantity"], "0");
+        assert_eq!(body["complete"], false);
+
+        for query in ["side=buy", "side=hold&quantity=1", "side=buy&quantity=0", "side=buy&quantity=-1"] {
+            let (status, _) = request(&app, "GET", &format!("/api/orderbook/impact?{query}"), None).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{query}");
+        }
+    }
+
+    #[test]
+    fn test_rejection_status_mapping() {
+        use super::rejection_status;
+        use crate::engine::RejectReason;
+
+        assert_eq!(
+            rejection_status(&RejectReason::TooManyOpenOrders { limit: 200 }),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        assert_eq!(
+            rejection_status(&RejectReason::PostOnlyWouldCross),
+            StatusCode::UNPROCESSABLE_ENTITY
+        );
+    }
+
+    #[tokio::test]
+    async fn test_full_queue_refuses_submissions_with_retry_after() {
+        use axum::body::Body;
+        use axum::http::{header, Request};
+        use tower::ServiceExt;
+
+        // The engine is not running yet, so nothing drains its queue of 8
+        let (engine, handle) = EngineBuilder::new("BTC/USD").order_buffer_size(8).build();
+        let handle = Arc::new(handle);
+        let app = crate::api::router(handle.clone());
+
+        let submit = |i: u32| {
+            let order = json!({ "side": "buy", "price": "100", "quantity": "1", "client_order_id": format!("c{i}") });
+            let request = Request::post("/api/orders")
+                .header("content-type", "application/json")
+                .body(Body::from(order.to_string()))
+                .unwrap();
+            app.clone().oneshot(request)
+        };
+        let responses = futures::future::join_all((0..50).map(submit)).await;
+
+        let mut accepted = 0;
+        for response in responses {
+            let response = response.unwrap();
+            if response.status() == StatusCode::ACCEPTED {
+                accepted += 1;
+                continue;
+            }
+            assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+            assert_eq!(response.headers()[header::RETRY_AFTER], "1");
+            let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+            assert_eq!(body["code"], "QUEUE_FULL");
+            assert!(body["client_order_id"].as_str().unwrap().starts_with('c'));
+        }
+        assert_eq!(accepted, 8);
+
+        // Confirmed submissions are refused the same way rather than waiting
+        let order = json!({ "side": "buy", "price": "100", "quantity": "1" });
+        let (status, body) = request(&app, "POST", "/api/orders?confirm=true", Some(order.clone())).await;
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(body["code"], "QUEUE_FULL");
+
+        let (status, health) = request(&app, "GET", "/api/health", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(health["status"], "degraded");
+        assert_eq!(
+            health["queues"],
+            json!([{ "symbol": "BTC/USD", "len": 8, "capacity": 8, "saturated": true }])
+        );
+
+        // Once the engine drains the queue, submissions go through again
+        tokio::spawn(engine.run());
+        while handle.queue_depth().len > 0 {
+            tokio::task::yield_now().await;
+        }
+        let (status, body) = request(&app, "POST", "/api/orders?confirm=true", Some(order)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["state"], "resting");
+        let (_, health) = request(&app, "GET", "/api/health", None).await;
+        assert_eq!(health["status"], "healthy");
+        assert_eq!(health["queues"][0]["len"], 0);
+    }
+
+    #[tokio::test]
+    async fn test_health_turns_unhealthy_when_the_engine_dies() {
+        let (engine, handle) = EngineBuilder::new("BTC/USD")
+            .heartbeat_interval(std::time::Duration::from_millis(20))
+            .build();
+        let app = crate::api::router(Arc::new(handle));
+        let task = tokio::spawn(engine.run());
+
+        let (status, health) = request(&app, "GET", "/api/health", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(health["status"], "healthy");
+        assert_eq!(health["engines"][0]["live"], true);
+
+        task.abort();
+        assert!(task.await.unwrap_err().is_cancelled());
+        let (status, health) = request(&app, "GET", "/api/health", None).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(health["status"], "unhealthy");
+        assert_eq!(health["engines"][0]["live"], false);
+    }
 }
@@ -0,0 +1,159 @@
+//! REST API for two-sided market maker quotes.
+
+use crate::api::auth::{acting_owner, Principal};
+use crate::api::openapi::ErrorCode;
+use crate::api::orders::rejection_status;
+use crate::engine::{EngineHandle, QuoteRequest, SymbolConfig};
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Extension, Json};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// Request body for submitting a quote
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SubmitQuoteRequest {
+    /// Quoting participant, whose previous quote is replaced; the API
+    /// key's owner if unset
+    pub owner_id: Option<String>,
+    pub bid_price: Decimal,
+    pub bid_qty: Decimal,
+    pub ask_price: Decimal,
+    pub ask_qty: Decimal,
+}
+
+/// Response for a quote submission, with the ids its orders will have;
+/// `quote_replaced` carries them too
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SubmitQuoteResponse {
+    pub success: bool,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bid_order_id: Option<Uuid>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ask_order_id: Option<Uuid>,
+    /// Machine-readable rejection code, when the quote broke a trading rule
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<ErrorCode>)]
+    pub code: Option<String>,
+}
+
+impl SubmitQuoteResponse {
+    fn failure(message: impl Into<String>, code: Option<String>) -> Json<Self> {
+        Json(Self {
+            success: false,
+            message: message.into(),
+            bid_order_id: None,
+            ask_order_id: None,
+            code,
+        })
+    }
+}
+
+/// Replace the owner's quote with a new bid and ask. With API keys, the
+/// owner must be the key's own unless it is an admin key, and defaults to it.
+/// The engine reports the replacement on the WebSocket feed.
+#[utoipa::path(
+    post,
+    path = "/api/quotes",
+    tag = "orders",
+    request_body = SubmitQuoteRequest,
+    responses(
+        (status = 202, description = "Handed to the engine", body = SubmitQuoteResponse),
+        (status = 400, description = "Missing owner or a non-positive value", body = SubmitQuoteResponse),
+        (status = 403, description = "Names an owner the API key may not act for", body = SubmitQuoteResponse),
+        (status = 422, description = "Breaks a trading rule", body = SubmitQuoteResponse),
+        (status = 429, description = "Risk limit hit", body = SubmitQuoteResponse),
+        (status = 503, description = "Engine unavailable", body = SubmitQuoteResponse),
+    ),
+    security(("api_key" = []))
+)]
+pub async fn submit_quote(
+    State(handle): State<Arc<EngineHandle>>,
+    caller: Option<Extension<Principal>>,
+    Json(req): Json<SubmitQuoteRequest>,
+) -> impl IntoResponse {
+    let owner_id = match acting_owner(caller, req.owner_id) {
+        Ok(owner_id) => owner_id.filter(|id| !id.trim().is_empty()),
+        Err(message) => return (StatusCode::FORBIDDEN, SubmitQuoteResponse::failure(message, None)),
+    };
+    let Some(owner_id) = owner_id else {
+        return (StatusCode::BAD_REQUEST, SubmitQuoteResponse::failure("owner_id is required", None));
+    };
+
+    if [req.bid_price, req.bid_qty, req.ask_price, req.ask_qty]
+        .iter()
+        .any(|v| *v <= Decimal::ZERO)
+    {
+        return (
+            StatusCode::BAD_REQUEST,
+            SubmitQuoteResponse::failure("Prices and quantities must be positive", None),
+        );
+    }
+
+    let quote = QuoteRequest::new(owner_id, req.bid_price, req.bid_qty, req.ask_price, req.ask_qty);
+
+    // Same rules the engine applies, so an invalid quote is refused up front
+    let reference = {
+        let snapshot = handle.current_state.borrow();
+        SymbolConfig::reference_price(snapshot.last_trade_price, snapshot.best_bid, snapshot.best_ask)
+    };
+    if let Err(reason) = handle.config.validate_quote(&quote, reference) {
+        return (
+            rejection_status(&reason),
+            SubmitQuoteResponse::failure(reason.to_string(), Some(reason.code().to_string())),
+        );
+    }
+
+    match handle.submit_quote(quote).await {
+        Ok([bid, ask]) => (
+            StatusCode::ACCEPTED,
+            Json(SubmitQuoteResponse {
+                success: true,
+                message: "Quote submitted successfully".to_string(),
+                bid_order_id: Some(handle.ids.uuid(bid)),
+                ask_order_id: Some(handle.ids.uuid(ask)),
+                code: None,
+            }),
+        ),
+        Err(_) => (StatusCode::SERVICE_UNAVAILABLE, SubmitQuoteResponse::failure("Engine unavailable", None)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::test_support::{next_of_type, request, start_stack};
+    use crate::engine::ManualClock;
+    use axum::http::StatusCode;
+    use serde_json::json;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_quote_replaces_previous_pair() {
+        let (app, mut ws) = start_stack(Arc::new(ManualClock::new(chrono::Utc::now()))).await;
+        let quote = |bid: &str, ask: &str| {
+            json!({ "owner_id": "mm", "bid_price": bid, "bid_qty": "1", "ask_price": ask, "ask_qty": "1" })
+        };
+
+        let (status, body) = request(&app, "POST", "/api/quotes", Some(quote("99", "101"))).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        let first = next_of_type(&mut ws, "quote_replaced").await;
+        assert!(first["bid_order_id"].is_string());
+        assert_ne!(first["bid_order_id"], first["ask_order_id"]);
+        assert_eq!(body["bid_order_id"], first["bid_order_id"]);
+        assert_eq!(body["ask_order_id"], first["ask_order_id"]);
+
+        request(&app, "POST", "/api/quotes", Some(quote("98", "102"))).await;
+        let replaced = next_of_type(&mut ws, "quote_replaced").await;
+        assert_ne!(replaced["bid_order_id"], first["bid_order_id"]);
+        assert_eq!(replaced["cancelled"], json!([first["bid_order_id"], first["ask_order_id"]]));
+        let book = next_of_type(&mut ws, "order_book").await;
+        assert_eq!(book["best_bid"], "98");
+        assert_eq!(book["best_ask"], "102");
+
+        let (status, body) = request(&app, "POST", "/api/quotes", Some(quote("101", "101"))).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["code"], "QUOTE_WOULD_SELF_CROSS");
+    }
+}
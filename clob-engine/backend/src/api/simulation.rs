@@ -5,12 +5,18 @@ use crate::simulation::Simulator;
 use axum::{extract::State, Json};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
+use utoipa::ToSchema;
 
 /// Request to start a simulation
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct SimulationRequest {
+    /// Orders to send, at most 10000
     #[serde(default = "default_num_orders")]
+    #[schema(default = 1000)]
     pub num_orders: u64,
+    /// Skip the engine's max quantity and max notional checks
+    #[serde(default)]
+    pub bypass_risk_limits: bool,
 }
 
 fn default_num_orders() -> u64 {
@@ -18,7 +24,7 @@ fn default_num_orders() -> u64 {
 }
 
 /// Response with simulation results
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct SimulationResponse {
     pub success: bool,
     pub message: String,
@@ -26,6 +32,14 @@ pub struct SimulationResponse {
 }
 
 /// Start a performance simulation
+#[utoipa::path(
+    post,
+    path = "/api/simulation",
+    tag = "simulation",
+    request_body = SimulationRequest,
+    responses((status = 200, description = "Simulation finished", body = SimulationResponse)),
+    security(("api_key" = []))
+)]
 #[axum::debug_handler]
 pub async fn run_simulation(
     State(handle): State<Arc<EngineHandle>>,
@@ -35,8 +49,11 @@ pub async fn run_simulation(
     let simulator = Simulator::new(handle);
     
     // Configure simulation
-    let mut config = crate::simulation::SimulationConfig::default();
-    config.num_orders = req.num_orders.min(10000); // Cap at 10k orders for safety
+    let config = crate::simulation::SimulationConfig {
+        num_orders: req.num_orders.min(10000), // Cap at 10k orders for safety
+        bypass_risk_limits: req.bypass_risk_limits,
+        ..Default::default()
+    };
 
     tracing::info!("Starting simulation with {} orders", config.num_orders);
 
@@ -0,0 +1,80 @@
+//! REST API for engine statistics.
+
+use crate::api::openapi::ErrorResponse;
+use crate::engine::EngineHandle;
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use std::sync::Arc;
+
+/// The engine's book, trading and latency statistics. Asked of the engine
+/// between the commands queued ahead, so it never holds up matching.
+#[utoipa::path(
+    get,
+    path = "/api/stats",
+    tag = "market data",
+    responses(
+        (status = 200, description = "Engine statistics", body = serde_json::Value),
+        (status = 503, description = "Engine unavailable", body = ErrorResponse),
+    ),
+    security((), ("api_key" = []))
+)]
+pub async fn get_stats(State(handle): State<Arc<EngineHandle>>) -> impl IntoResponse {
+    let Some(stats) = handle.stats().await else {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(serde_json::json!({ "success": false, "message": "Engine unavailable" })),
+        );
+    };
+    let price = |price: Option<rust_decimal::Decimal>| price.map(|p| p.to_string());
+    (
+        StatusCode::OK,
+        Json(serde_json::json!({
+            "symbol": stats.symbol,
+            "best_bid": price(stats.best_bid),
+            "best_ask": price(stats.best_ask),
+            "spread": price(stats.spread),
+            "order_count": stats.order_count,
+            "pending_stops": stats.pending_stops,
+            "trade_count": stats.trades.count,
+            "traded_volume": stats.trades.volume.to_string(),
+            "traded_notional": stats.trades.notional.to_string(),
+            "last_trade_price": price(stats.last_trade_price),
+            "last_24h": stats.last_24h,
+            "uptime_ms": stats.uptime.as_millis() as u64,
+            "suppressed_book_updates": stats.suppressed_book_updates,
+            "depth_levels": stats.depth_levels,
+            "min_update_interval_ms": stats.min_update_interval.as_millis() as u64,
+            "latency": stats.latency,
+        })),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::test_support::{request, spawn_app};
+    use crate::engine::EngineBuilder;
+    use axum::http::StatusCode;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_stats_count_trades_and_volume() {
+        let app = spawn_app(EngineBuilder::new("BTC/USD"));
+        let (status, body) = request(&app, "GET", "/api/stats", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!((&body["trade_count"], &body["traded_volume"]), (&json!(0), &json!("0")));
+
+        for (side, quantity) in [("sell", "3"), ("buy", "1"), ("buy", "1.5")] {
+            let order = json!({ "side": side, "price": "100", "quantity": quantity });
+            request(&app, "POST", "/api/orders", Some(order)).await;
+        }
+        let (_, body) = request(&app, "GET", "/api/BTC-USD/stats", None).await;
+        assert_eq!(body["symbol"], "BTC/USD");
+        assert_eq!((&body["trade_count"], &body["traded_volume"]), (&json!(2), &json!("2.5")));
+        assert_eq!(body["traded_notional"], "250.0");
+        assert_eq!((&body["last_trade_price"], &body["best_ask"]), (&json!("100"), &json!("100")));
+        assert_eq!(body["order_count"], 1);
+        assert_eq!(body["last_24h"]["count"], 2);
+        assert_eq!((&body["last_24h"]["open"], &body["last_24h"]["volume"]), (&json!("100"), &json!("2.5")));
+        assert_eq!(body["latency"]["matched"]["count"], 2);
+        assert!(body["uptime_ms"].is_u64());
+    }
+}
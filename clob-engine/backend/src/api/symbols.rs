@@ -0,0 +1,73 @@
+//! REST API for symbol trading rules.
+
+use crate::engine::{EngineRegistry, SymbolConfig};
+use axum::{extract::State, Json};
+use std::sync::Arc;
+
+/// Trading rules of every symbol served, so clients can size price and
+/// quantity inputs to the tick, lot and precision the engine enforces
+#[utoipa::path(
+    get,
+    path = "/api/symbols",
+    tag = "service",
+    responses((status = 200, description = "Symbols served", body = Vec<SymbolConfig>))
+)]
+pub async fn list_symbols(State(engines): State<Arc<EngineRegistry>>) -> Json<Vec<SymbolConfig>> {
+    Json(engines.iter().map(|handle| handle.config.clone()).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::test_support::request;
+    use crate::engine::{EngineBuilder, EngineRegistry, SymbolConfig, SymbolRegistry};
+    use axum::http::StatusCode;
+    use serde_json::json;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_list_symbols_returns_engine_config() {
+        let config = SymbolRegistry::defaults().get("BTC/USD").unwrap().clone();
+        let (engine, handle) = EngineBuilder::from_config(config).build();
+        tokio::spawn(engine.run());
+        let app = crate::api::router(Arc::new(handle));
+
+        let (status, body) = request(&app, "GET", "/api/symbols", None).await;
+
+        assert_eq!(status, StatusCode::OK);
+        let btc = &body[0];
+        assert_eq!(btc["symbol"], "BTC/USD");
+        assert_eq!(btc["tick_size"], "0.01");
+        assert_eq!(btc["lot_size"], "0.0001");
+        assert_eq!(btc["price_scale"], 2);
+        assert_eq!(btc["min_notional"], serde_json::Value::Null);
+        assert_eq!(btc["taker_fee_bps"], "0");
+    }
+
+    #[tokio::test]
+    async fn test_routes_reach_each_symbols_engine() {
+        let mut symbols = SymbolRegistry::defaults();
+        symbols.insert(SymbolConfig::new("ETH/USD"));
+        let app = crate::api::registry_router(Arc::new(EngineRegistry::start(&symbols, |builder| builder)));
+
+        let (_, body) = request(&app, "GET", "/api/symbols", None).await;
+        assert_eq!((&body[0]["symbol"], &body[1]["symbol"]), (&json!("BTC/USD"), &json!("ETH/USD")));
+
+        let bid = json!({ "side": "buy", "price": "2000", "quantity": "1" });
+        let (status, _) = request(&app, "POST", "/api/ETH-USD/orders?confirm=true", Some(bid)).await;
+        assert_eq!(status, StatusCode::OK);
+        let (_, eth) = request(&app, "GET", "/api/ETH-USD/orderbook", None).await;
+        assert_eq!(eth["best_bid"], "2000");
+
+        // The unprefixed paths serve the default symbol, untouched by the bid
+        let (_, btc) = request(&app, "GET", "/api/orderbook", None).await;
+        assert_eq!(btc["best_bid"], serde_json::Value::Null);
+        let (_, btc) = request(&app, "GET", "/api/BTC-USD/orderbook", None).await;
+        assert_eq!(btc["best_bid"], serde_json::Value::Null);
+
+        let (status, _) = request(&app, "GET", "/api/DOGE-USD/orderbook", None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let order = json!({ "side": "buy", "price": "1", "quantity": "1" });
+        let (status, _) = request(&app, "POST", "/api/DOGE-USD/orders", Some(order)).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,176 @@
+//! Test-only endpoints for driving the shared manual clock.
+//!
+//! Only compiled with the `test-util` feature (and in unit tests), so release
+//! builds never expose them.
+
+use crate::engine::EngineHandle;
+use axum::{extract::State, http::StatusCode, Json};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// Current reading of the engine clock
+#[derive(Debug, Serialize)]
+pub struct ClockResponse {
+    pub now: DateTime<Utc>,
+    pub timestamp_ms: i64,
+}
+
+impl From<DateTime<Utc>> for ClockResponse {
+    fn from(now: DateTime<Utc>) -> Self {
+        Self {
+            now,
+            timestamp_ms: now.timestamp_millis(),
+        }
+    }
+}
+
+/// Request body for moving the clock forward
+#[derive(Debug, Deserialize)]
+pub struct AdvanceClockRequest {
+    pub duration_ms: u64,
+}
+
+/// Request body for jumping the clock to an absolute time
+#[derive(Debug, Deserialize)]
+pub struct SetClockRequest {
+    /// RFC 3339 timestamp
+    pub timestamp: DateTime<Utc>,
+}
+
+type ClockResult = Result<Json<ClockResponse>, (StatusCode, String)>;
+
+fn not_manual() -> (StatusCode, String) {
+    (
+        StatusCode::CONFLICT,
+        "Engine is not running on a manual clock".to_string(),
+    )
+}
+
+/// Read the engine clock
+pub async fn get_clock(State(handle): State<Arc<EngineHandle>>) -> Json<ClockResponse> {
+    Json(handle.clock.now().into())
+}
+
+/// Advance the manual clock by `duration_ms`
+pub async fn advance_clock(
+    State(handle): State<Arc<EngineHandle>>,
+    Json(req): Json<AdvanceClockRequest>,
+) -> ClockResult {
+    let clock = handle.clock.as_manual().ok_or_else(not_manual)?;
+    let duration_ms = i64::try_from(req.duration_ms)
+        .map_err(|_| (StatusCode::BAD_REQUEST, "duration_ms is too large".to_string()))?;
+    let now = clock.advance(chrono::Duration::milliseconds(duration_ms));
+    tracing::debug!(now = %now, "Test clock advanced");
+    Ok(Json(now.into()))
+}
+
+/// Set the manual clock to an absolute timestamp
+pub async fn set_clock(
+    State(handle): State<Arc<EngineHandle>>,
+    Json(req): Json<SetClockRequest>,
+) -> ClockResult {
+    let clock = handle.clock.as_manual().ok_or_else(not_manual)?;
+    let now = clock.set(req.timestamp);
+    tracing::debug!(now = %now, "Test clock set");
+    Ok(Json(now.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::router;
+    use crate::api::test_support::{next_of_type, request, start_stack};
+    use crate::engine::{EngineBuilder, ManualClock};
+    use axum::http::StatusCode;
+    use chrono::{DateTime, TimeZone, Utc};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn test_clock_endpoints_drive_engine_time() {
+        let start = Utc.with_ymd_and_hms(2030, 1, 1, 0, 0, 0).unwrap();
+        let (app, mut ws) = start_stack(Arc::new(ManualClock::new(start))).await;
+
+        let (status, body) = request(&app, "GET", "/api/test/clock", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["timestamp_ms"], start.timestamp_millis());
+
+        let (status, body) = request(
+            &app,
+            "POST",
+            "/api/test/clock/advance",
+            Some(serde_json::json!({ "duration_ms": 1500 })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["timestamp_ms"], start.timestamp_millis() + 1500);
+
+        // A trade executed over HTTP is stamped with the advanced time
+        let sell = serde_json::json!({ "side": "sell", "price": "100", "quantity": "1" });
+        let buy = serde_json::json!({ "side": "buy", "price": "100", "quantity": "1" });
+        request(&app, "POST", "/api/orders", Some(sell)).await;
+        request(&app, "POST", "/api/orders", Some(buy)).await;
+
+        let trade = next_of_type(&mut ws, "trade").await;
+        assert_eq!(trade["timestamp"], start.timestamp_millis() + 1500);
+
+        let target: DateTime<Utc> = Utc.with_ymd_and_hms(2031, 6, 1, 12, 0, 0).unwrap();
+        let (status, body) = request(
+            &app,
+            "POST",
+            "/api/test/clock/set",
+            Some(serde_json::json!({ "timestamp": target.to_rfc3339() })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["timestamp_ms"], target.timestamp_millis());
+    }
+
+    #[tokio::test]
+    async fn test_gtd_order_expires_after_clock_advance() {
+        let start = Utc.with_ymd_and_hms(2030, 1, 1, 0, 0, 0).unwrap();
+        let (app, mut ws) = start_stack(Arc::new(ManualClock::new(start))).await;
+
+        let expires_at = start + chrono::Duration::seconds(30);
+        let order = serde_json::json!({
+            "side": "buy",
+            "price": "100",
+            "quantity": "2",
+            "expires_at": expires_at.to_rfc3339(),
+        });
+        let (status, _) = request(&app, "POST", "/api/orders", Some(order)).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+
+        let book = next_of_type(&mut ws, "order_book").await;
+        assert_eq!(book["bids"][0][0], "100");
+
+        request(
+            &app,
+            "POST",
+            "/api/test/clock/advance",
+            Some(serde_json::json!({ "duration_ms": 30_000 })),
+        )
+        .await;
+
+        let cancelled = next_of_type(&mut ws, "order_cancelled").await;
+        assert_eq!(cancelled["reason"], "EXPIRED");
+        assert_eq!(cancelled["remaining_quantity"], "2");
+
+        let book = next_of_type(&mut ws, "order_book").await;
+        assert_eq!(book["bids"], serde_json::json!([]));
+    }
+
+    #[tokio::test]
+    async fn test_clock_endpoints_refuse_system_clock() {
+        let (_engine, handle) = EngineBuilder::new("BTC/USD").build();
+        let app = router(Arc::new(handle));
+
+        let (status, _) = request(
+            &app,
+            "POST",
+            "/api/test/clock/advance",
+            Some(serde_json::json!({ "duration_ms": 1000 })),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CONFLICT);
+    }
+}
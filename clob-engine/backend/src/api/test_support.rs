@@ -0,0 +1,108 @@
+//! Shared helpers for end-to-end tests of the HTTP and WebSocket stack.
+
+use crate::api::router;
+use crate::engine::{run_expiry_sweeper, EngineBuilder, ManualClock};
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use futures::StreamExt;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::time::{timeout, Duration};
+use tower::ServiceExt;
+
+pub type WsStream =
+    tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+
+/// Router over a running engine built from `builder`, for REST-only tests
+pub fn spawn_app(builder: EngineBuilder) -> Router {
+    let (engine, handle) = builder.build();
+    tokio::spawn(engine.run());
+    router(Arc::new(handle))
+}
+
+/// Start the full stack on an ephemeral port with a manual clock,
+/// connected to the market data feed
+pub async fn start_stack(clock: Arc<ManualClock>) -> (Router, WsStream) {
+    let (app, addr) = serve_stack(clock).await;
+    let ws = connect(addr, "/ws/market").await;
+    (app, ws)
+}
+
+/// Start the full stack on an ephemeral port with a manual clock
+pub async fn serve_stack(clock: Arc<ManualClock>) -> (Router, SocketAddr) {
+    let (engine, handle) = EngineBuilder::new("BTC/USD").clock(clock).build();
+    tokio::spawn(engine.run());
+    // Long real-time interval: only clock notifications can drive the sweep
+    tokio::spawn(run_expiry_sweeper(handle.clone(), Duration::from_secs(3600)));
+    let app = router(Arc::new(handle));
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = app.clone();
+    tokio::spawn(async move { axum::serve(listener, server).await.unwrap() });
+    (app, addr)
+}
+
+/// Open a WebSocket to `path` and consume the connected greeting
+pub async fn connect(addr: SocketAddr, path: &str) -> WsStream {
+    let (mut ws, _) = tokio_tungstenite::connect_async(format!("ws://{}{}", addr, path))
+        .await
+        .unwrap();
+    next_message(&mut ws).await;
+    ws
+}
+
+pub async fn request(
+    app: &Router,
+    method: &str,
+    uri: &str,
+    body: Option<serde_json::Value>,
+) -> (StatusCode, serde_json::Value) {
+    request_with_headers(app, method, uri, &[], body).await
+}
+
+pub async fn request_with_headers(
+    app: &Router,
+    method: &str,
+    uri: &str,
+    headers: &[(&str, &str)],
+    body: Option<serde_json::Value>,
+) -> (StatusCode, serde_json::Value) {
+    let builder = headers
+        .iter()
+        .fold(Request::builder().method(method).uri(uri), |builder, (name, value)| builder.header(*name, *value));
+    let request = match body {
+        Some(json) => builder
+            .header("content-type", "application/json")
+            .body(Body::from(json.to_string())),
+        None => builder.body(Body::empty()),
+    }
+    .unwrap();
+
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let json = serde_json::from_slice(&bytes).unwrap_or(serde_json::Value::Null);
+    (status, json)
+}
+
+pub async fn next_message(ws: &mut WsStream) -> serde_json::Value {
+    let msg = timeout(Duration::from_secs(1), ws.next())
+        .await
+        .expect("timed out waiting for websocket message")
+        .unwrap()
+        .unwrap();
+    serde_json::from_str(msg.to_text().unwrap()).unwrap()
+}
+
+pub async fn next_of_type(ws: &mut WsStream, kind: &str) -> serde_json::Value {
+    loop {
+        let msg = next_message(ws).await;
+        if msg["type"] == kind {
+            return msg;
+        }
+    }
+}
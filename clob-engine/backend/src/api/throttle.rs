@@ -0,0 +1,402 @@
+//! Request rate limits at the HTTP layer.
+//!
+//! Each client has a token bucket per class of request: trading (order and
+//! quote submission, cancels, modifies) and market data are limited apart.
+//! Clients are told apart by their API key when the key store knows it,
+//! else by their IP address. A request arriving at an empty bucket is a 429
+//! with `Retry-After`; every limited response carries `X-RateLimit-Limit`,
+//! `X-RateLimit-Remaining` and `X-RateLimit-Reset`, the seconds until the
+//! bucket is full again. Buckets are timed in real time, unlike the
+//! engine's own per-owner limits.
+//!
+//! A bucket left idle until full is no different from a new one, so such
+//! buckets are swept once too many clients are tracked; if every tracked
+//! client is still busy, new ones are refused until some go idle.
+
+use crate::api::auth::{hash_key, KeyStore, API_KEY_HEADER};
+use crate::engine::{InvalidRateLimit, RateLimit};
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Environment variable holding the trading limit, as `per_second` or
+/// `per_second/burst`; unset leaves trading requests unlimited
+pub const TRADING_LIMIT_VAR: &str = "CLOB_HTTP_TRADING_LIMIT";
+
+/// Environment variable holding the market data limit, in the same form
+pub const MARKET_DATA_LIMIT_VAR: &str = "CLOB_HTTP_MARKET_DATA_LIMIT";
+
+/// Clients tracked before idle ones are swept
+pub const DEFAULT_MAX_CLIENTS: usize = 100_000;
+
+/// Least time between sweeps while every tracked client is busy
+const SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Requests limited together
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RequestClass {
+    /// Submitting, modifying and cancelling orders and quotes
+    Trading,
+    /// Book, trades, ticker, candles, stats and the market feed
+    MarketData,
+}
+
+/// Who a request is counted against
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Client {
+    /// A known API key, by its hash
+    Key(String),
+    Ip(IpAddr),
+    /// Neither, as when the server is not told peer addresses
+    Unknown,
+}
+
+impl Client {
+    /// The client sending a request with `headers` from `peer`: its API key
+    /// when `keys` knows it, its address otherwise. Unknown keys count
+    /// against the address, so made-up keys do not get fresh buckets.
+    pub fn of(headers: &HeaderMap, peer: Option<SocketAddr>, keys: Option<&KeyStore>) -> Self {
+        let key = headers.get(API_KEY_HEADER).and_then(|key| key.to_str().ok());
+        if let Some((keys, key)) = keys.zip(key) {
+            let hash = hash_key(key);
+            if keys.resolve_hash(&hash).is_some() {
+                return Client::Key(hash);
+            }
+        }
+        match peer {
+            Some(addr) => Client::Ip(addr.ip()),
+            None => Client::Unknown,
+        }
+    }
+}
+
+/// Where a client stands with its limit after a request
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Standing {
+    pub limit: RateLimit,
+    /// Requests the bucket still holds
+    pub remaining: u32,
+    /// Time until the bucket is full again
+    pub reset: Duration,
+    /// Time until the next request is let through, when this one was not
+    pub retry_after: Option<Duration>,
+}
+
+impl Standing {
+    fn write_headers(&self, headers: &mut HeaderMap) {
+        let mut set = |name: &'static str, value: u64| {
+            headers.insert(name, HeaderValue::from(value));
+        };
+        set("x-ratelimit-limit", u64::from(self.limit.burst));
+        set("x-ratelimit-remaining", u64::from(self.remaining));
+        set("x-ratelimit-reset", whole_seconds(self.reset));
+        if let Some(wait) = self.retry_after {
+            headers.insert(header::RETRY_AFTER, HeaderValue::from(whole_seconds(wait).max(1)));
+        }
+    }
+}
+
+/// `duration` in seconds, rounded up
+fn whole_seconds(duration: Duration) -> u64 {
+    duration.as_secs() + u64::from(duration.subsec_nanos() > 0)
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl Bucket {
+    /// Tokens held at `now`, never more than the burst
+    fn tokens_at(&self, limit: RateLimit, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(self.refilled_at).as_secs_f64();
+        (self.tokens + elapsed * f64::from(limit.per_second)).min(f64::from(limit.burst))
+    }
+}
+
+#[derive(Debug)]
+struct Buckets {
+    buckets: HashMap<(RequestClass, Client), Bucket>,
+    swept_at: Option<Instant>,
+}
+
+/// Token buckets for every client of the limited classes
+#[derive(Debug)]
+pub struct Throttle {
+    limits: HashMap<RequestClass, RateLimit>,
+    max_clients: usize,
+    state: Mutex<Buckets>,
+}
+
+impl Throttle {
+    /// No class limited yet
+    pub fn new() -> Self {
+        Self {
+            limits: HashMap::new(),
+            max_clients: DEFAULT_MAX_CLIENTS,
+            state: Mutex::new(Buckets {
+                buckets: HashMap::new(),
+                swept_at: None,
+            }),
+        }
+    }
+
+    /// The limits in `CLOB_HTTP_TRADING_LIMIT` and
+    /// `CLOB_HTTP_MARKET_DATA_LIMIT`, or `None` when neither is set
+    pub fn from_env() -> Result<Option<Self>, InvalidRateLimit> {
+        let mut throttle = Self::new();
+        let vars = [(TRADING_LIMIT_VAR, RequestClass::Trading), (MARKET_DATA_LIMIT_VAR, RequestClass::MarketData)];
+        for (var, class) in vars {
+            if let Ok(limit) = std::env::var(var) {
+                throttle = throttle.limit(class, limit.parse()?);
+            }
+        }
+        Ok((!throttle.limits.is_empty()).then_some(throttle))
+    }
+
+    /// Hold each client to `limit` for `class` requests
+    pub fn limit(mut self, class: RequestClass, limit: RateLimit) -> Self {
+        self.limits.insert(class, limit);
+        self
+    }
+
+    /// Track at most `max_clients` buckets
+    pub fn max_clients(mut self, max_clients: usize) -> Self {
+        self.max_clients = max_clients;
+        self
+    }
+
+    /// Buckets currently tracked
+    pub fn clients(&self) -> usize {
+        self.state.lock().unwrap().buckets.len()
+    }
+
+    /// Take a token for `client`'s `class` request at `now`, or `None` when
+    /// the class is not limited
+    pub fn check(&self, class: RequestClass, client: Client, now: Instant) -> Option<Standing> {
+        let limit = *self.limits.get(&class)?;
+        let mut state = self.state.lock().unwrap();
+        let key = (class, client);
+        if !state.buckets.contains_key(&key) && state.buckets.len() >= self.max_clients {
+            self.sweep(&mut state, now);
+            if state.buckets.len() >= self.max_clients {
+                return Some(Standing {
+                    limit,
+                    remaining: 0,
+                    reset: SWEEP_INTERVAL,
+                    retry_after: Some(SWEEP_INTERVAL),
+                });
+            }
+        }
+        let bucket = state.buckets.entry(key).or_insert(Bucket {
+            tokens: f64::from(limit.burst),
+            refilled_at: now,
+        });
+        bucket.tokens = bucket.tokens_at(limit, now);
+        bucket.refilled_at = now;
+
+        let rate = f64::from(limit.per_second);
+        let retry_after = if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            None
+        } else {
+            Some(Duration::from_secs_f64((1.0 - bucket.tokens) / rate))
+        };
+        Some(Standing {
+            limit,
+            remaining: bucket.tokens.floor() as u32,
+            reset: Duration::from_secs_f64((f64::from(limit.burst) - bucket.tokens) / rate),
+            retry_after,
+        })
+    }
+
+    /// Forget buckets that have refilled, at most once a `SWEEP_INTERVAL`
+    fn sweep(&self, state: &mut Buckets, now: Instant) {
+        if state.swept_at.is_some_and(|swept_at| now.saturating_duration_since(swept_at) < SWEEP_INTERVAL) {
+            return;
+        }
+        state.swept_at = Some(now);
+        let limits = &self.limits;
+        state.buckets.retain(|(class, _), bucket| {
+            let limit = limits[class];
+            bucket.tokens_at(limit, now) < f64::from(limit.burst)
+        });
+    }
+}
+
+impl Default for Throttle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Middleware holding `class` requests to the [`Throttle`] layered on as
+/// an `Extension`. Open when the server has none.
+pub async fn throttle(
+    State(class): State<RequestClass>,
+    throttle: Option<Extension<Arc<Throttle>>>,
+    keys: Option<Extension<Arc<KeyStore>>>,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(Extension(throttle)) = throttle else {
+        return next.run(request).await;
+    };
+    let peer = peer.map(|ConnectInfo(addr)| addr);
+    let client = Client::of(request.headers(), peer, keys.as_deref().map(Arc::as_ref));
+    let Some(standing) = throttle.check(class, client, Instant::now()) else {
+        return next.run(request).await;
+    };
+    let mut response = match standing.retry_after {
+        Some(wait) => {
+            let message = format!("Too many requests, retry in {}s", whole_seconds(wait).max(1));
+            let body = serde_json::json!({ "success": false, "message": message });
+            (StatusCode::TOO_MANY_REQUESTS, Json(body)).into_response()
+        }
+        None => next.run(request).await,
+    };
+    standing.write_headers(response.headers_mut());
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::auth::{Principal, Scope};
+    use crate::api::test_support::spawn_app;
+    use crate::engine::EngineBuilder;
+    use axum::body::Body;
+    use axum::extract::connect_info::MockConnectInfo;
+    use axum::Router;
+    use tower::ServiceExt;
+
+    fn ip(last: u8) -> Client {
+        Client::Ip(IpAddr::from([10, 0, 0, last]))
+    }
+
+    #[test]
+    fn test_bucket_refills_at_the_configured_rate() {
+        let throttle = Throttle::new().limit(RequestClass::Trading, RateLimit::per_second(10).with_burst(3));
+        let start = Instant::now();
+        let standings: Vec<_> = (0..5).map(|_| throttle.check(RequestClass::Trading, ip(1), start).unwrap()).collect();
+        let remaining: Vec<_> = standings.iter().map(|standing| standing.remaining).collect();
+        assert_eq!(remaining, [2, 1, 0, 0, 0]);
+        assert!(standings[..3].iter().all(|standing| standing.retry_after.is_none()));
+        assert_eq!(standings[3].retry_after, Some(Duration::from_millis(100)));
+        assert_eq!(standings[3].reset, Duration::from_millis(300));
+        // Market data is not limited, and other clients have their own bucket
+        assert_eq!(throttle.check(RequestClass::MarketData, ip(1), start), None);
+        assert!(throttle.check(RequestClass::Trading, ip(2), start).unwrap().retry_after.is_none());
+
+        // Hammered every 10ms for two seconds, about 10 a second get through
+        let later = start + Duration::from_secs(60);
+        let allowed = (0..200)
+            .map(|step| later + Duration::from_millis(10 * step))
+            .filter(|&now| throttle.check(RequestClass::Trading, ip(1), now).unwrap().retry_after.is_none())
+            .count();
+        assert!((21..=23).contains(&allowed), "{allowed} let through");
+    }
+
+    #[test]
+    fn test_idle_clients_make_room() {
+        let limit = RateLimit::per_second(1).with_burst(2);
+        let throttle = Throttle::new().limit(RequestClass::Trading, limit).max_clients(2);
+        let start = Instant::now();
+        for client in [ip(1), ip(2), ip(2)] {
+            assert!(throttle.check(RequestClass::Trading, client, start).unwrap().retry_after.is_none());
+        }
+        // Both tracked clients are busy, so a third has to wait
+        let refused = throttle.check(RequestClass::Trading, ip(3), start).unwrap();
+        assert_eq!(refused.retry_after, Some(SWEEP_INTERVAL));
+        assert_eq!(throttle.clients(), 2);
+
+        // Once the first has refilled, it is forgotten for the newcomer
+        let later = start + Duration::from_millis(1500);
+        assert!(throttle.check(RequestClass::Trading, ip(3), later).unwrap().retry_after.is_none());
+        assert_eq!(throttle.clients(), 2);
+        assert_eq!(throttle.check(RequestClass::Trading, ip(1), later).unwrap().retry_after, Some(SWEEP_INTERVAL));
+    }
+
+    /// Status and headers of a request from `peer`, with `key` if given
+    async fn send(app: &Router, method: &str, uri: &str, peer: u8, key: Option<&str>) -> (StatusCode, HeaderMap) {
+        let order = r#"{"side": "buy", "price": "100", "quantity": "1"}"#;
+        let mut builder = Request::builder().method(method).uri(uri).header("content-type", "application/json");
+        if let Some(key) = key {
+            builder = builder.header(API_KEY_HEADER, key);
+        }
+        let body = if method == "POST" { Body::from(order) } else { Body::empty() };
+        let app = app.clone().layer(MockConnectInfo(SocketAddr::from(([10, 0, 0, peer], 4000))));
+        let response = app.oneshot(builder.body(body).unwrap()).await.unwrap();
+        (response.status(), response.headers().clone())
+    }
+
+    fn header_of<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+        headers.get(name).and_then(|value| value.to_str().ok())
+    }
+
+    #[tokio::test]
+    async fn test_hammered_endpoint_is_throttled() {
+        let throttle = Throttle::new()
+            .limit(RequestClass::Trading, RateLimit::per_second(1).with_burst(5))
+            .limit(RequestClass::MarketData, RateLimit::per_second(1).with_burst(2));
+        let app = spawn_app(EngineBuilder::new("BTC/USD")).layer(Extension(Arc::new(throttle)));
+
+        let mut statuses = Vec::new();
+        for _ in 0..20 {
+            let (status, headers) = send(&app, "POST", "/api/orders", 1, None).await;
+            if statuses.is_empty() {
+                assert_eq!(header_of(&headers, "x-ratelimit-limit"), Some("5"));
+                assert_eq!(header_of(&headers, "x-ratelimit-remaining"), Some("4"));
+                assert_eq!(header_of(&headers, "x-ratelimit-reset"), Some("1"));
+            }
+            if status == StatusCode::TOO_MANY_REQUESTS {
+                assert_eq!(header_of(&headers, "retry-after"), Some("1"));
+                assert_eq!(header_of(&headers, "x-ratelimit-remaining"), Some("0"));
+            }
+            statuses.push(status);
+        }
+        let accepted = statuses.iter().filter(|&&status| status == StatusCode::ACCEPTED).count();
+        assert_eq!(accepted, 5);
+        assert!(statuses[5..].iter().all(|&status| status == StatusCode::TOO_MANY_REQUESTS));
+
+        // Market data has a budget of its own, and so does every address
+        for expected in [StatusCode::OK, StatusCode::OK, StatusCode::TOO_MANY_REQUESTS] {
+            assert_eq!(send(&app, "GET", "/api/orderbook", 1, None).await.0, expected);
+        }
+        assert_eq!(send(&app, "POST", "/api/orders", 2, None).await.0, StatusCode::ACCEPTED);
+        // Unthrottled routes say nothing of limits
+        let (status, headers) = send(&app, "GET", "/api/health", 1, None).await;
+        assert_eq!((status, header_of(&headers, "x-ratelimit-limit")), (StatusCode::OK, None));
+    }
+
+    #[tokio::test]
+    async fn test_known_keys_have_their_own_bucket() {
+        let mut keys = KeyStore::new();
+        for owner in ["alice", "bob"] {
+            keys.insert(&format!("{owner}-key"), Principal::new(owner, [Scope::Trade]));
+        }
+        let throttle = Throttle::new().limit(RequestClass::Trading, RateLimit::per_second(1));
+        let app = spawn_app(EngineBuilder::new("BTC/USD"))
+            .layer(Extension(Arc::new(keys)))
+            .layer(Extension(Arc::new(throttle)));
+
+        // One address, three buckets: each known key's, and the address's
+        // for a made-up key and none at all
+        let submit = |key| send(&app, "POST", "/api/orders", 1, key);
+        assert_eq!(submit(Some("alice-key")).await.0, StatusCode::ACCEPTED);
+        assert_eq!(submit(Some("alice-key")).await.0, StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(submit(Some("bob-key")).await.0, StatusCode::ACCEPTED);
+        assert_eq!(submit(Some("made-up-key")).await.0, StatusCode::UNAUTHORIZED);
+        assert_eq!(submit(None).await.0, StatusCode::TOO_MANY_REQUESTS);
+    }
+}
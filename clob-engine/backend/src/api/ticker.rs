@@ -0,0 +1,215 @@
+//! REST API for the 24-hour ticker.
+
+use crate::engine::{EngineEvent, EngineHandle, RollingWindow};
+use axum::{extract::State, response::IntoResponse, Extension, Json};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast::error::RecvError;
+use utoipa::ToSchema;
+
+/// Decimal places the 24-hour price change percentage is rounded to
+const CHANGE_PERCENT_SCALE: u32 = 4;
+
+/// An engine's last 24 hours of trading, followed from its events. Starts
+/// from a copy of the engine's own window, so trades from before it was
+/// started, including history loaded from the journal, count too.
+#[derive(Debug, Default)]
+pub struct Ticker {
+    window: Mutex<RollingWindow>,
+}
+
+impl Ticker {
+    /// Start following `handle`'s trades. Needs a Tokio runtime; the task
+    /// ends once the engine has stopped.
+    pub fn spawn(handle: &EngineHandle) -> Arc<Self> {
+        let ticker = Arc::new(Self::default());
+        let (mut events, follower, engine) = (handle.subscribe(), ticker.clone(), handle.clone());
+        tokio::spawn(async move {
+            // Subscribed first, so no trade falls between the copy and the
+            // events; those already in the copy are skipped
+            let Some((copied, window)) = engine.trading_window().await else {
+                return;
+            };
+            *follower.window.lock().unwrap() = window;
+            loop {
+                match events.recv().await {
+                    Ok(event) if event.seq > copied => follower.record(&event.event),
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(missed)) => {
+                        let symbol = &engine.config.symbol;
+                        tracing::warn!(%symbol, missed, "Ticker fell behind; its 24h figures miss some trades")
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+        ticker
+    }
+
+    fn record(&self, event: &EngineEvent) {
+        match event {
+            EngineEvent::Trade(trade) => self.window.lock().unwrap().add(trade),
+            EngineEvent::TradeBusted(trade) => self.window.lock().unwrap().remove(trade),
+            _ => {}
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TickerResponse {
+    pub symbol: String,
+    /// Latest trade price, from before the window if nothing traded in it
+    pub last_price: Option<String>,
+    pub open: Option<String>,
+    pub high: Option<String>,
+    pub low: Option<String>,
+    /// Base quantity traded
+    pub volume: String,
+    /// Quote value traded, price times quantity
+    pub quote_volume: String,
+    /// Last price less the open
+    pub price_change: Option<String>,
+    pub price_change_percent: Option<String>,
+    pub best_bid: Option<String>,
+    pub best_ask: Option<String>,
+    pub count: u64,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Trading over the last 24 hours of engine time, with the touch from the
+/// feed's latest book. Window prices are `null` when nothing traded in it.
+#[utoipa::path(
+    get,
+    path = "/api/ticker",
+    tag = "market data",
+    responses((status = 200, description = "24-hour ticker", body = TickerResponse)),
+    security((), ("api_key" = []))
+)]
+pub async fn get_ticker(
+    State(handle): State<Arc<EngineHandle>>,
+    Extension(ticker): Extension<Arc<Ticker>>,
+) -> impl IntoResponse {
+    let now = handle.clock.now();
+    let summary = ticker.window.lock().unwrap().summary(now);
+    let book = handle.current_state.borrow().clone();
+    let text = |value: Option<Decimal>| value.map(|v| v.to_string());
+    let change = summary.open.zip(summary.last).map(|(open, last)| last - open);
+    let change_percent = summary
+        .open
+        .zip(change)
+        .filter(|(open, _)| !open.is_zero())
+        .map(|(open, change)| (change / open * Decimal::ONE_HUNDRED).round_dp(CHANGE_PERCENT_SCALE));
+    Json(TickerResponse {
+        symbol: handle.config.symbol.clone(),
+        last_price: text(summary.last.or(book.last_trade_price)),
+        open: text(summary.open),
+        high: text(summary.high),
+        low: text(summary.low),
+        volume: summary.volume.to_string(),
+        quote_volume: summary.notional.to_string(),
+        price_change: text(change),
+        price_change_percent: text(change_percent),
+        best_bid: text(book.best_bid),
+        best_ask: text(book.best_ask),
+        count: summary.count,
+        timestamp: now,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::router;
+    use crate::api::test_support::{request, start_stack};
+    use crate::engine::{EngineBuilder, ManualClock, PastTrade};
+    use axum::http::StatusCode;
+    use axum::Router;
+    use chrono::{Duration, TimeZone, Utc};
+    use rust_decimal_macros::dec;
+    use serde_json::{json, Value};
+    use std::sync::Arc;
+    use tokio::time::{sleep, timeout};
+
+    /// The ticker once it has counted `count` trades
+    async fn ticker_once(app: &Router, count: u64) -> Value {
+        timeout(std::time::Duration::from_secs(1), async {
+            loop {
+                let (status, body) = request(app, "GET", "/api/ticker", None).await;
+                assert_eq!(status, StatusCode::OK);
+                if body["count"] == count {
+                    return body;
+                }
+                sleep(std::time::Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .expect("ticker never caught up")
+    }
+
+    /// Trade `quantity` at `price`, at the engine's time when it returns
+    async fn trade(app: &Router, price: &str, quantity: &str) {
+        for side in ["sell", "buy"] {
+            let order = json!({ "side": side, "price": price, "quantity": quantity });
+            request(app, "POST", "/api/orders?confirm=true", Some(order)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ticker_rolls_over_the_last_day() {
+        let clock = Arc::new(ManualClock::new(Utc.with_ymd_and_hms(2030, 1, 1, 0, 0, 0).unwrap()));
+        let (app, _ws) = start_stack(clock.clone()).await;
+        trade(&app, "100", "2").await;
+        clock.advance(Duration::hours(1));
+        trade(&app, "110", "1").await;
+        clock.advance(Duration::hours(1));
+        trade(&app, "95", "1").await;
+        for (side, price) in [("buy", "90"), ("sell", "120")] {
+            let order = json!({ "side": side, "price": price, "quantity": "1" });
+            request(&app, "POST", "/api/orders?confirm=true", Some(order)).await;
+        }
+
+        let ticker = ticker_once(&app, 3).await;
+        assert_eq!(ticker["symbol"], "BTC/USD");
+        assert_eq!((&ticker["open"], &ticker["high"], &ticker["low"]), (&json!("100"), &json!("110"), &json!("95")));
+        assert_eq!(ticker["last_price"], "95");
+        assert_eq!((&ticker["volume"], &ticker["quote_volume"]), (&json!("4"), &json!("405")));
+        assert_eq!((&ticker["price_change"], &ticker["price_change_percent"]), (&json!("-5"), &json!("-5.00")));
+        assert_eq!((&ticker["best_bid"], &ticker["best_ask"]), (&json!("90"), &json!("120")));
+
+        // A day after the first trade, it has aged out
+        clock.advance(Duration::hours(22));
+        let ticker = ticker_once(&app, 2).await;
+        assert_eq!((&ticker["open"], &ticker["high"], &ticker["low"]), (&json!("110"), &json!("110"), &json!("95")));
+        assert_eq!(ticker["price_change_percent"], "-13.6364");
+
+        // After a quiet day there is no range, only the last price
+        clock.advance(Duration::days(2));
+        let ticker = ticker_once(&app, 0).await;
+        assert_eq!((&ticker["open"], &ticker["high"], &ticker["low"]), (&Value::Null, &Value::Null, &Value::Null));
+        assert_eq!((&ticker["volume"], &ticker["price_change"]), (&json!("0"), &Value::Null));
+        assert_eq!(ticker["last_price"], "95");
+    }
+
+    #[tokio::test]
+    async fn test_ticker_starts_from_the_engines_window() {
+        let now = Utc.with_ymd_and_hms(2030, 1, 1, 12, 0, 0).unwrap();
+        let history = vec![PastTrade {
+            id: uuid::Uuid::new_v4(),
+            price: dec!(50),
+            quantity: dec!(3),
+            timestamp: now - Duration::hours(2),
+        }];
+        let builder = EngineBuilder::new("BTC/USD").clock(Arc::new(ManualClock::new(now))).trade_history(history);
+        let (engine, handle) = builder.build();
+        tokio::spawn(engine.run());
+        let app = router(Arc::new(handle));
+        trade(&app, "60", "1").await;
+
+        // History and live trades are counted once each
+        let ticker = ticker_once(&app, 2).await;
+        assert_eq!((&ticker["open"], &ticker["last_price"]), (&json!("50"), &json!("60")));
+        assert_eq!((&ticker["volume"], &ticker["quote_volume"]), (&json!("4"), &json!("210")));
+        assert_eq!(ticker["price_change_percent"], "20.00");
+    }
+}
@@ -0,0 +1,473 @@
+//! REST API for recent trades.
+
+use crate::api::openapi::ErrorResponse;
+use crate::engine::{EngineEvent, EngineHandle, IdEpoch, Side, Trade};
+use crate::persistence::{TradeArchive, TradeCursor, TradeKey, TradeRecord};
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast::error::RecvError;
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+/// Trades each engine's tape keeps in memory
+pub const TRADE_TAPE_CAPACITY: usize = 200;
+
+/// Trades returned when `?limit=` is not given
+pub const DEFAULT_TRADES_LIMIT: usize = 100;
+
+/// Most trades one request returns, whatever `?limit=` asks for
+pub const MAX_TRADES_LIMIT: usize = 1000;
+
+/// An engine's last trades with their event sequences, kept by a task
+/// following its events. Busted trades are struck off.
+#[derive(Debug)]
+pub struct TradeTape {
+    trades: Mutex<VecDeque<(u64, Arc<Trade>)>>,
+    capacity: usize,
+    ids: IdEpoch,
+}
+
+impl TradeTape {
+    /// Start keeping `handle`'s last `capacity` trades. Needs a Tokio
+    /// runtime; the task ends once the engine has stopped.
+    pub fn spawn(handle: &EngineHandle, capacity: usize) -> Arc<Self> {
+        let trades = Mutex::new(VecDeque::with_capacity(capacity));
+        let tape = Arc::new(Self { trades, capacity, ids: handle.ids });
+        let (mut events, keeper) = (handle.subscribe(), tape.clone());
+        let symbol = handle.config.symbol.clone();
+        tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => keeper.record(event.seq, &event.event),
+                    Err(RecvError::Lagged(missed)) => {
+                        tracing::warn!(%symbol, missed, "Trade tape fell behind; trades in between are missing")
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+        tape
+    }
+
+    fn record(&self, seq: u64, event: &EngineEvent) {
+        let mut trades = self.trades.lock().unwrap();
+        match event {
+            EngineEvent::Trade(trade) => {
+                if trades.len() == self.capacity {
+                    trades.pop_front();
+                }
+                trades.push_back((seq, trade.clone()));
+            }
+            EngineEvent::TradeBusted(busted) => trades.retain(|(_, trade)| trade.id != busted.id),
+            _ => {}
+        }
+    }
+
+    /// The last `limit` trades from `before` back when given, newest first
+    pub fn page(&self, before: Option<TradeCursor>, limit: usize) -> Vec<(u64, Arc<Trade>)> {
+        let trades = self.trades.lock().unwrap();
+        let earlier = |(seq, trade): &&(u64, Arc<Trade>)| match before {
+            Some(TradeCursor::Trade(before)) => TradeKey::new(trade.timestamp, *seq, self.ids.uuid(trade.id)) < before,
+            Some(TradeCursor::Time(before)) => trade.timestamp < before,
+            None => true,
+        };
+        trades.iter().rev().filter(earlier).take(limit).cloned().collect()
+    }
+
+    /// Most trades the tape holds
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TradesQuery {
+    /// A previous page's `next_cursor`, or an RFC 3339 time
+    pub before: Option<String>,
+    /// Trades to return, at most 1000
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TradeSummary {
+    pub trade_id: Uuid,
+    /// Sequence of the trade's engine event; later trades from the same
+    /// engine have higher ones
+    pub trade_seq: u64,
+    pub price: String,
+    pub quantity: String,
+    #[schema(value_type = Side)]
+    pub taker_side: String,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl TradeSummary {
+    fn new(trade_id: Uuid, trade_seq: u64, trade: &Trade) -> Self {
+        Self {
+            trade_id,
+            trade_seq,
+            price: trade.price.to_string(),
+            quantity: trade.quantity.to_string(),
+            taker_side: trade.taker_side.to_string(),
+            timestamp: trade.timestamp,
+        }
+    }
+
+    fn key(&self) -> TradeKey {
+        TradeKey::new(self.timestamp, self.trade_seq, self.trade_id)
+    }
+}
+
+impl From<TradeRecord> for TradeSummary {
+    fn from(record: TradeRecord) -> Self {
+        Self {
+            trade_id: record.id,
+            trade_seq: record.event_sequence.unwrap_or_default() as u64,
+            price: record.price.to_string(),
+            quantity: record.quantity.to_string(),
+            taker_side: record.taker_side,
+            timestamp: record.timestamp,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TradesResponse {
+    pub symbol: String,
+    pub trades: Vec<TradeSummary>,
+    /// `before` for the next page, while this one came back full: the
+    /// last trade's time, sequence and id, as `{micros}_{trade_seq}_{trade_id}`
+    pub next_cursor: Option<String>,
+}
+
+/// A `?before=` value: a trade cursor or an RFC 3339 time
+fn parse_cursor(before: &str) -> Option<TradeCursor> {
+    match before.parse() {
+        Ok(key) => Some(TradeCursor::Trade(key)),
+        Err(()) => DateTime::parse_from_rfc3339(before).ok().map(|time| TradeCursor::Time(time.with_timezone(&Utc))),
+    }
+}
+
+/// The engine's most recent trades, newest first, busted ones left out.
+/// `?limit=` defaults to 100 and is capped at 1000. Served from memory;
+/// asking for more than the tape keeps reaches back into the trade
+/// journal when the server has one. A full page comes with a
+/// `next_cursor` to pass back as `?before=` for the trades before it.
+#[utoipa::path(
+    get,
+    path = "/api/trades",
+    tag = "market data",
+    params(TradesQuery),
+    responses(
+        (status = 200, description = "Recent trades, newest first", body = TradesResponse),
+        (status = 400, description = "Invalid cursor", body = ErrorResponse),
+        (status = 503, description = "Trade journal unavailable", body = ErrorResponse),
+    ),
+    security((), ("api_key" = []))
+)]
+pub async fn get_trades(
+    State(handle): State<Arc<EngineHandle>>,
+    Extension(tape): Extension<Arc<TradeTape>>,
+    archive: Option<Extension<Arc<dyn TradeArchive>>>,
+    Query(query): Query<TradesQuery>,
+) -> Response {
+    let failure = |status: StatusCode, message: &str| {
+        (status, Json(serde_json::json!({ "success": false, "message": message }))).into_response()
+    };
+    let before = match query.before.as_deref().map(parse_cursor) {
+        None => None,
+        Some(Some(before)) => Some(before),
+        Some(None) => return failure(StatusCode::BAD_REQUEST, "before must be a trade cursor or an RFC 3339 time"),
+    };
+    let limit = query.limit.unwrap_or(DEFAULT_TRADES_LIMIT).min(MAX_TRADES_LIMIT);
+    let symbol = handle.config.symbol.clone();
+    let page = tape.page(before, limit);
+    let mut trades: Vec<TradeSummary> =
+        page.iter().map(|(seq, trade)| TradeSummary::new(handle.ids.uuid(trade.id), *seq, trade)).collect();
+
+    // The tape has the newest trades; the journal carries on from the
+    // oldest of them, so the two never overlap
+    let reach_back = trades.len() < limit && (before.is_some() || limit > tape.capacity());
+    if let Some(Extension(archive)) = archive.filter(|_| reach_back) {
+        let from = trades.last().map(|last| TradeCursor::Trade(last.key())).or(before);
+        match archive.recent_trades(&symbol, from, limit - trades.len()).await {
+            Ok(records) => trades.extend(records.into_iter().map(TradeSummary::from)),
+            Err(e) => {
+                tracing::error!("Failed to read trade history for {symbol}: {e}");
+                return failure(StatusCode::SERVICE_UNAVAILABLE, "Trade journal unavailable");
+            }
+        }
+    }
+    let next_cursor = trades.last().filter(|_| trades.len() == limit).map(|last| last.key().to_string());
+    Json(TradesResponse { symbol, trades, next_cursor }).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::router;
+    use crate::api::test_support::{request, spawn_app, start_stack};
+    use crate::engine::{EngineBuilder, ManualClock};
+    use axum::Router;
+    use futures::future::BoxFuture;
+    use rust_decimal_macros::dec;
+    use serde_json::{json, Value};
+    use tokio::time::{sleep, timeout, Duration};
+
+    /// `uri`'s trades once the tape has caught up to `count` of them
+    async fn trades_once(app: &Router, uri: &str, count: usize) -> Vec<Value> {
+        timeout(Duration::from_secs(1), async {
+            loop {
+                let (status, body) = request(app, "GET", uri, None).await;
+                assert_eq!(status, StatusCode::OK);
+                let trades = body["trades"].as_array().unwrap().clone();
+                if trades.len() == count {
+                    return trades;
+                }
+                sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .expect("trade tape never caught up")
+    }
+
+    async fn trade(app: &Router, price: &str, quantity: &str) {
+        for side in ["sell", "buy"] {
+            let order = json!({ "side": side, "price": price, "quantity": quantity });
+            request(app, "POST", "/api/orders", Some(order)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_recent_trades_come_newest_first() {
+        let app = spawn_app(EngineBuilder::new("BTC/USD"));
+        for (price, quantity) in [("100", "1"), ("101", "2"), ("102", "3")] {
+            trade(&app, price, quantity).await;
+        }
+
+        let trades = trades_once(&app, "/api/trades", 3).await;
+        let prices: Vec<_> = trades.iter().map(|trade| trade["price"].as_str().unwrap()).collect();
+        assert_eq!(prices, ["102", "101", "100"]);
+        assert_eq!((&trades[0]["quantity"], &trades[0]["taker_side"]), (&json!("3"), &json!("buy")));
+        assert!(trades[0]["trade_id"].as_str().unwrap().parse::<Uuid>().is_ok());
+
+        let (_, body) = request(&app, "GET", "/api/BTC-USD/trades?limit=2", None).await;
+        assert_eq!(body["symbol"], "BTC/USD");
+        assert_eq!(body["trades"].as_array().unwrap().len(), 2);
+        assert_eq!(body["trades"][1]["price"], "101");
+        // Far beyond the cap is only capped
+        let (status, _) = request(&app, "GET", "/api/trades?limit=1000000", None).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_busted_trades_leave_the_tape() {
+        let (app, _ws) = start_stack(Arc::new(ManualClock::new(Utc::now()))).await;
+        trade(&app, "100", "1").await;
+        trade(&app, "101", "1").await;
+        let trades = trades_once(&app, "/api/trades", 2).await;
+
+        let uri = format!("/api/admin/bust/{}", trades[1]["trade_id"].as_str().unwrap());
+        assert_eq!(request(&app, "POST", &uri, None).await.0, StatusCode::ACCEPTED);
+        let trades = trades_once(&app, "/api/trades", 1).await;
+        assert_eq!(trades[0]["price"], "101");
+    }
+
+    #[tokio::test]
+    async fn test_tape_keeps_only_its_capacity() {
+        let (engine, handle) = EngineBuilder::new("BTC/USD").build();
+        tokio::spawn(engine.run());
+        let tape = TradeTape::spawn(&handle, 2);
+        let app = router(Arc::new(handle));
+        for price in ["100", "101", "102"] {
+            trade(&app, price, "1").await;
+        }
+        trades_once(&app, "/api/trades", 3).await;
+
+        let prices: Vec<_> = tape.page(None, 10).iter().map(|(_, trade)| trade.price).collect();
+        assert_eq!(prices, [dec!(102), dec!(101)]);
+    }
+
+    /// Journaled trades, paged the way the journal's query pages them
+    #[derive(Default)]
+    struct StubArchive(Mutex<Vec<TradeRecord>>);
+
+    impl StubArchive {
+        /// A journal of `handle`'s trades from now on, written as they happen
+        fn following(handle: &EngineHandle) -> Arc<Self> {
+            let archive = Arc::new(Self::default());
+            let (mut events, journal, ids) = (handle.subscribe(), archive.clone(), handle.ids);
+            tokio::spawn(async move {
+                while let Ok(event) = events.recv().await {
+                    if let EngineEvent::Trade(trade) = event.event {
+                        let mut record = record(ids.uuid(trade.id), trade.price, trade.timestamp);
+                        record.event_sequence = Some(event.seq as i64);
+                        journal.0.lock().unwrap().push(record);
+                    }
+                }
+            });
+            archive
+        }
+
+        fn len(&self) -> usize {
+            self.0.lock().unwrap().len()
+        }
+    }
+
+    impl TradeArchive for StubArchive {
+        fn recent_trades<'a>(
+            &'a self,
+            _symbol: &'a str,
+            before: Option<TradeCursor>,
+            limit: usize,
+        ) -> BoxFuture<'a, Result<Vec<TradeRecord>, sqlx::Error>> {
+            let mut trades: Vec<_> = self
+                .0
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|record| match before {
+                    Some(TradeCursor::Trade(before)) => record.key() < before,
+                    Some(TradeCursor::Time(before)) => record.timestamp < before,
+                    None => true,
+                })
+                .cloned()
+                .collect();
+            trades.sort_by_key(|record| std::cmp::Reverse(record.key()));
+            trades.truncate(limit);
+            Box::pin(async move { Ok(trades) })
+        }
+    }
+
+    fn record(id: Uuid, price: rust_decimal::Decimal, timestamp: DateTime<Utc>) -> TradeRecord {
+        TradeRecord {
+            id,
+            symbol: Some("BTC/USD".to_string()),
+            taker_order_id: Uuid::new_v4(),
+            maker_order_id: Uuid::new_v4(),
+            taker_owner_id: None,
+            maker_owner_id: None,
+            price,
+            quantity: dec!(1),
+            taker_side: "sell".to_string(),
+            timestamp,
+            busted: false,
+            taker_sequence: None,
+            maker_sequence: None,
+            event_sequence: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_longer_histories_reach_into_the_trade_journal() {
+        let (engine, handle) = EngineBuilder::new("BTC/USD").build();
+        tokio::spawn(engine.run());
+        let app = router(Arc::new(handle));
+        trade(&app, "100", "1").await;
+        let live = trades_once(&app, "/api/trades", 1).await;
+
+        // The journal has the live trade too, and one from before the tape
+        let journaled: Uuid = live[0]["trade_id"].as_str().unwrap().parse().unwrap();
+        let older = Uuid::new_v4();
+        let mut again = record(journaled, dec!(100), Utc::now());
+        again.event_sequence = live[0]["trade_seq"].as_i64();
+        let archive: Arc<dyn TradeArchive> = Arc::new(StubArchive(Mutex::new(vec![
+            again,
+            record(older, dec!(95), Utc::now() - chrono::Duration::hours(1)),
+        ])));
+        let app = app.layer(Extension(archive));
+
+        let (_, body) = request(&app, "GET", "/api/trades", None).await;
+        assert_eq!(body["trades"].as_array().unwrap().len(), 1);
+        let (_, body) = request(&app, "GET", &format!("/api/trades?limit={}", TRADE_TAPE_CAPACITY + 1), None).await;
+        let trades = body["trades"].as_array().unwrap();
+        assert_eq!(trades.len(), 2);
+        assert_eq!((&trades[0]["trade_id"], &trades[0]["taker_side"]), (&json!(journaled), &json!("buy")));
+        assert_eq!((&trades[1]["trade_id"], &trades[1]["price"]), (&json!(older), &json!("95")));
+    }
+
+    #[tokio::test]
+    async fn test_pages_of_the_tape_follow_the_cursor() {
+        let app = spawn_app(EngineBuilder::new("BTC/USD"));
+        for price in ["100", "101", "102", "103", "104"] {
+            trade(&app, price, "1").await;
+        }
+        let all = trades_once(&app, "/api/trades", 5).await;
+
+        let (_, first) = request(&app, "GET", "/api/trades?limit=2", None).await;
+        let timestamp = all[1]["timestamp"].as_str().unwrap().parse().unwrap();
+        let id = all[1]["trade_id"].as_str().unwrap().parse().unwrap();
+        let key = TradeKey::new(timestamp, all[1]["trade_seq"].as_u64().unwrap(), id);
+        assert_eq!(first["next_cursor"], key.to_string());
+        let uri = format!("/api/trades?limit=2&before={}", first["next_cursor"].as_str().unwrap());
+        let (_, second) = request(&app, "GET", &uri, None).await;
+        assert_eq!(second["trades"], json!([all[2], all[3]]));
+        let uri = format!("/api/trades?limit=2&before={}", second["next_cursor"].as_str().unwrap());
+        let (_, last) = request(&app, "GET", &uri, None).await;
+        assert_eq!((&last["trades"], &last["next_cursor"]), (&json!([all[4]]), &Value::Null));
+
+        // A time starts from the trades made before it
+        let uri = format!("/api/trades?before={}", all[0]["timestamp"].as_str().unwrap());
+        let (_, body) = request(&app, "GET", &uri, None).await;
+        assert!(body["trades"].as_array().unwrap().iter().all(|trade| trade["trade_id"] != all[0]["trade_id"]));
+        let (status, _) = request(&app, "GET", "/api/trades?before=yesterday", None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_paging_through_the_journal_reassembles_the_history() {
+        let (engine, handle) = EngineBuilder::new("BTC/USD").build();
+        tokio::spawn(engine.run());
+        let archive = StubArchive::following(&handle);
+        let app = router(Arc::new(handle)).layer(Extension(archive.clone() as Arc<dyn TradeArchive>));
+
+        // Thousands of trades, far more than the tape keeps
+        let taken = 2500;
+        let ask = json!({ "side": "sell", "price": "100", "quantity": taken.to_string() });
+        request(&app, "POST", "/api/orders", Some(ask)).await;
+        for _ in 0..taken {
+            let bid = json!({ "side": "buy", "price": "100", "quantity": "1" });
+            request(&app, "POST", "/api/orders", Some(bid)).await;
+        }
+        timeout(Duration::from_secs(5), async {
+            while archive.len() < taken {
+                sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .expect("journal never caught up");
+        let mut expected: Vec<Value> =
+            archive.0.lock().unwrap().iter().map(|record| json!(record.event_sequence.unwrap())).collect();
+        expected.reverse();
+
+        let mut seen = Vec::new();
+        let mut uri = "/api/trades?limit=500".to_string();
+        loop {
+            let (status, body) = request(&app, "GET", &uri, None).await;
+            assert_eq!(status, StatusCode::OK);
+            seen.extend(body["trades"].as_array().unwrap().iter().map(|trade| trade["trade_seq"].clone()));
+            let Some(cursor) = body["next_cursor"].as_str() else {
+                break;
+            };
+            uri = format!("/api/trades?limit=500&before={cursor}");
+            // Trades made while paging are newer than every cursor
+            if seen.len() == 500 {
+                for side in ["sell", "buy"] {
+                    let order = json!({ "side": side, "price": "99", "quantity": "1" });
+                    request(&app, "POST", "/api/orders", Some(order)).await;
+                }
+            }
+        }
+        assert_eq!(seen.len(), taken);
+        assert_eq!(seen, expected);
+    }
+}
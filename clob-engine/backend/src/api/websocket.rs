@@ -1,117 +1,779 @@
 //! WebSocket handler for real-time market data streaming.
 
-use crate::engine::{EngineEvent, EngineHandle};
+use crate::api::auth::{acting_owner, Principal};
+use crate::engine::{
+    notional_depth, CancelReason, EngineEvent, EngineHandle, IdEpoch, OrderId, OrderStatus, SequencedEvent,
+};
 use axum::{
     extract::{
         ws::{Message, WebSocket, WebSocketUpgrade},
-        State,
+        Query, State,
     },
-    response::IntoResponse,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Extension,
 };
 use futures::{SinkExt, StreamExt};
-use serde::Serialize;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::sync::Arc;
 
+/// Layout of the depth levels in book messages. Version 1 sent
+/// `[price, quantity]`; version 2 appends the level's order count, so
+/// readers of the first two elements are unaffected.
+pub const FEED_VERSION: u32 = 2;
+
 /// WebSocket message sent to clients
 #[derive(Debug, Clone, Serialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum WsMessage {
     /// A trade was executed
     Trade {
+        trade_id: String,
+        price: String,
+        quantity: String,
+        side: String,
+        timestamp: i64,
+        taker_owner_id: Option<String>,
+        maker_owner_id: Option<String>,
+    },
+    /// An earlier trade was voided and should be backed out of volume and candles
+    TradeBusted {
+        trade_id: String,
         price: String,
         quantity: String,
         side: String,
         timestamp: i64,
     },
-    /// Order book update
+    /// A trade bust could not be applied
+    BustRejected {
+        trade_id: String,
+        reason: String,
+        message: String,
+    },
+    /// Order book update. Levels are `[price, quantity, orders]`, or with
+    /// notional depth `[price, quantity, orders, notional, cumulative notional]`
     OrderBook {
+        /// Depth layout, see [`FEED_VERSION`]
+        version: u32,
         best_bid: Option<String>,
         best_ask: Option<String>,
-        bids: Vec<[String; 2]>,
-        asks: Vec<[String; 2]>,
+        best_bid_qty: Option<String>,
+        best_ask_qty: Option<String>,
+        mid_price: Option<String>,
+        microprice: Option<String>,
+        top_imbalance: Option<String>,
+        depth_imbalance: Option<String>,
+        checksum: u32,
+        last_trade_price: Option<String>,
+        /// Trades made so far, less any busted, and their base quantity and
+        /// quote notional
+        trade_count: u64,
+        traded_volume: String,
+        traded_notional: String,
+        bids: Vec<Vec<String>>,
+        asks: Vec<Vec<String>>,
+        /// Book update number, one more than the last update
+        sequence: u64,
+        timestamp: i64,
+    },
+    /// The engine took an order
+    OrderAccepted {
+        order_id: String,
+        client_order_id: Option<String>,
+        owner_id: Option<String>,
+    },
+    /// An order's remainder was added to the book
+    OrderRested {
+        order_id: String,
+        price: String,
+        remaining_quantity: String,
+    },
+    /// An order was refused by the engine
+    OrderRejected {
+        order_id: String,
+        client_order_id: Option<String>,
+        reason: String,
+        message: String,
+    },
+    /// A live order was removed from the book
+    OrderCancelled {
+        order_id: String,
+        remaining_quantity: String,
+        reason: String,
+    },
+    /// A cancel request could not be applied
+    CancelRejected {
+        order_id: String,
+        reason: String,
+        message: String,
+    },
+    /// All of an owner's open orders were cancelled
+    MassCancelled {
+        owner_id: String,
+        side: Option<String>,
+        count: usize,
+    },
+    /// A resting order was amended, possibly under a new id
+    OrderModified {
+        order_id: String,
+        new_order_id: String,
+        price: String,
+        remaining_quantity: String,
+    },
+    /// A modify request could not be applied
+    ModifyRejected {
+        order_id: String,
+        reason: String,
+        message: String,
+    },
+    /// A market maker's quote was replaced
+    QuoteReplaced {
+        owner_id: String,
+        bid_order_id: String,
+        ask_order_id: String,
+        cancelled: Vec<String>,
+    },
+    /// A quote was refused
+    QuoteRejected {
+        owner_id: String,
+        reason: String,
+        message: String,
+    },
+    /// An owner was blocked and its orders pulled
+    KillSwitchEngaged { owner_id: String, cancelled: usize },
+    /// A blocked owner may trade again
+    KillSwitchReleased { owner_id: String },
+    /// Matching is suspended by the circuit breaker
+    TradingHalted { until: i64, last_price: String },
+    /// Matching resumed after a halt
+    TradingResumed,
+    /// A call auction opened
+    AuctionStarted,
+    /// Where the open auction would uncross right now
+    AuctionIndicative { price: Option<String>, volume: String },
+    /// The auction closed and the book was uncrossed
+    AuctionEnded { price: Option<String>, volume: String },
+    /// A stop order fired and was released for matching
+    StopTriggered { order_id: String, stop_price: String },
+    /// One of the subscriber's orders was filled (private stream only)
+    OrderFilled {
+        order_id: String,
+        client_order_id: Option<String>,
+        sequence: u64,
+        fill_qty: String,
+        cumulative_filled: String,
+        remaining: String,
+        status: OrderStatus,
     },
     /// Connection established
     Connected { message: String },
+    /// The engine has shut down; the server closes the connection next
+    EngineStopped,
+    /// The engine panicked and was rebuilt from its snapshot and log.
+    /// Events since the snapshot may have been undone and their numbers
+    /// reused: refresh state built from the feed.
+    EngineRecovered,
+    /// The feed fell behind and `missed` engine events were dropped;
+    /// state built from the feed should be refreshed from a snapshot
+    Lagged { missed: u64 },
+}
+
+/// A feed message with the symbol and sequence number of the engine event
+/// it reports
+#[derive(Debug, Serialize)]
+struct SequencedMessage<'a> {
+    seq: u64,
+    symbol: &'a str,
+    #[serde(flatten)]
+    message: &'a WsMessage,
+}
+
+/// Query parameters for the market data feed
+#[derive(Debug, Default, Deserialize)]
+pub struct MarketStreamQuery {
+    /// Group order book depth into price buckets this wide
+    #[serde(default)]
+    pub group: Option<Decimal>,
+    /// Add each level's notional and the running notional to book updates
+    #[serde(default)]
+    pub notional: bool,
+}
+
+/// Query parameters for the private order stream
+#[derive(Debug, Deserialize)]
+pub struct OrderStreamQuery {
+    /// Participant whose fills are streamed; the API key's owner if unset
+    pub owner_id: Option<String>,
 }
 
 /// Handler for WebSocket upgrade requests
 pub async fn ws_handler(
     ws: WebSocketUpgrade,
     State(handle): State<Arc<EngineHandle>>,
-) -> impl IntoResponse {
-    ws.on_upgrade(move |socket| handle_socket(socket, handle))
+    Query(query): Query<MarketStreamQuery>,
+) -> Response {
+    if query.group.is_some_and(|group| group <= Decimal::ZERO) {
+        return (StatusCode::BAD_REQUEST, "group must be positive").into_response();
+    }
+    if query.group.is_some() && query.notional {
+        return (StatusCode::BAD_REQUEST, "notional depth cannot be grouped").into_response();
+    }
+    let notional_scale = query.notional.then(|| handle.config.quote_scale());
+    ws.on_upgrade(move |socket| handle_socket(socket, handle, None, query.group, notional_scale))
+}
+
+/// Handler for the private stream of one owner's order updates. With API
+/// keys, the owner must be the key's own unless it is an admin key, and
+/// defaults to it.
+pub async fn orders_ws_handler(
+    ws: WebSocketUpgrade,
+    State(handle): State<Arc<EngineHandle>>,
+    caller: Option<Extension<Principal>>,
+    Query(query): Query<OrderStreamQuery>,
+) -> Response {
+    let owner_id = match acting_owner(caller, query.owner_id) {
+        Ok(owner_id) => owner_id.filter(|id| !id.trim().is_empty()),
+        Err(message) => return (StatusCode::FORBIDDEN, message).into_response(),
+    };
+    let Some(owner_id) = owner_id else {
+        return (StatusCode::BAD_REQUEST, "owner_id is required").into_response();
+    };
+    ws.on_upgrade(move |socket| handle_socket(socket, handle, Some(owner_id), None, None))
 }
 
-/// Handle an individual WebSocket connection
-async fn handle_socket(socket: WebSocket, handle: Arc<EngineHandle>) {
+/// Handle an individual WebSocket connection: the public market feed, or
+/// the private feed of `owner_id` when given. On the market feed, `group`
+/// replaces each book update's depth with depth in buckets of that size,
+/// and `notional_scale` adds notional to each level, rounded to that scale.
+async fn handle_socket(
+    socket: WebSocket,
+    handle: Arc<EngineHandle>,
+    owner_id: Option<String>,
+    group: Option<Decimal>,
+    notional_scale: Option<u32>,
+) {
     let (mut sender, mut receiver) = socket.split();
 
     // Subscribe to engine events
     let mut events = handle.subscribe();
 
     // Send connected message
+    let feed = if owner_id.is_some() { "order" } else { "market data" };
     let connected = WsMessage::Connected {
-        message: "Connected to CLOB market data feed".to_string(),
+        message: format!("Connected to CLOB {} feed", feed),
     };
     if let Ok(json) = serde_json::to_string(&connected) {
-        let _ = sender.send(Message::Text(json.into())).await;
+        let _ = sender.send(Message::Text(json)).await;
     }
 
     // Spawn task to forward engine events to WebSocket
-    let send_task = tokio::spawn(async move {
+    let mut send_task = tokio::spawn(async move {
+        let mut live_orders = HashSet::new();
         loop {
             match events.recv().await {
-                Ok(event) => {
-                    let ws_msg = match event {
-                        EngineEvent::Trade(trade) => WsMessage::Trade {
-                            price: trade.price.to_string(),
-                            quantity: trade.quantity.to_string(),
-                            side: trade.taker_side.to_string(),
-                            timestamp: trade.timestamp.timestamp_millis(),
-                        },
-                        EngineEvent::OrderBookUpdate(snapshot) => WsMessage::OrderBook {
-                            best_bid: snapshot.best_bid.map(|p| p.to_string()),
-                            best_ask: snapshot.best_ask.map(|p| p.to_string()),
-                            bids: snapshot.bid_depth
-                                .into_iter()
-                                .map(|(p, q)| [p.to_string(), q.to_string()])
-                                .collect(),
-                            asks: snapshot.ask_depth
-                                .into_iter()
-                                .map(|(p, q)| [p.to_string(), q.to_string()])
-                                .collect(),
+                Ok(SequencedEvent { seq, symbol, event }) => {
+                    let stopped = matches!(event, EngineEvent::Stopped);
+                    let event = match (event, group) {
+                        (EngineEvent::OrderBookUpdate(_), Some(group)) => match handle.grouped_book(group).await {
+                            Some(snapshot) => EngineEvent::OrderBookUpdate(Arc::new(snapshot)),
+                            None => break,
                         },
+                        (event, _) => event,
+                    };
+                    let ws_msg = match &owner_id {
+                        None => market_message(event, handle.ids, notional_scale),
+                        Some(owner_id) => owner_message(event, handle.ids, owner_id, &mut live_orders),
+                    };
+                    let Some(ws_msg) = ws_msg else {
+                        continue;
                     };
 
-                    if let Ok(json) = serde_json::to_string(&ws_msg) {
-                        if sender.send(Message::Text(json.into())).await.is_err() {
+                    let message = SequencedMessage {
+                        seq,
+                        symbol: &symbol,
+                        message: &ws_msg,
+                    };
+                    if let Ok(json) = serde_json::to_string(&message) {
+                        if sender.send(Message::Text(json)).await.is_err() {
                             break;
                         }
                     }
+                    if stopped {
+                        let _ = sender.send(Message::Close(None)).await;
+                        break;
+                    }
                 }
                 Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
-                Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(tokio::sync::broadcast::error::RecvError::Lagged(missed)) => {
+                    if let Ok(json) = serde_json::to_string(&WsMessage::Lagged { missed }) {
+                        if sender.send(Message::Text(json)).await.is_err() {
+                            break;
+                        }
+                    }
+                }
             }
         }
     });
 
     // Handle incoming messages (pings, close, etc.)
-    while let Some(msg) = receiver.next().await {
-        match msg {
-            Ok(Message::Close(_)) => break,
-            Ok(Message::Ping(data)) => {
-                // Pong is handled automatically by axum
-                tracing::trace!("Received ping: {:?}", data);
+    let receive = async {
+        while let Some(msg) = receiver.next().await {
+            match msg {
+                Ok(Message::Close(_)) => break,
+                Ok(Message::Ping(data)) => {
+                    // Pong is handled automatically by axum
+                    tracing::trace!("Received ping: {:?}", data);
+                }
+                Err(e) => {
+                    tracing::warn!("WebSocket error: {}", e);
+                    break;
+                }
+                _ => {}
+            }
+        }
+    };
+
+    // The connection ends when the client leaves or the feed does, as it
+    // does once the engine stops
+    tokio::select! {
+        _ = receive => send_task.abort(),
+        _ = &mut send_task => {}
+    }
+    tracing::debug!("WebSocket connection closed");
+}
+
+/// Depth levels as sent to clients: `[price, quantity, orders]`, or with
+/// a notional scale `[price, quantity, orders, notional, cumulative notional]`
+pub(crate) fn depth_rows(depth: &[(Decimal, Decimal, usize)], notional_scale: Option<u32>) -> Vec<Vec<String>> {
+    let rows = depth.iter().map(|(p, q, n)| vec![p.to_string(), q.to_string(), n.to_string()]);
+    match notional_scale {
+        None => rows.collect(),
+        Some(scale) => {
+            let mut total = Decimal::ZERO;
+            rows.zip(notional_depth(depth, scale))
+                .map(|(mut row, (_, _, notional))| {
+                    total += notional;
+                    row.extend([notional.to_string(), total.to_string()]);
+                    row
+                })
+                .collect()
+        }
+    }
+}
+
+/// Public market data message for an event, if it is one, naming orders
+/// and trades by their UUIDs under `ids`. Book updates carry notional
+/// depth rounded to `notional_scale` when it is given.
+fn market_message(event: EngineEvent, ids: IdEpoch, notional_scale: Option<u32>) -> Option<WsMessage> {
+    let message = match event {
+        EngineEvent::Trade(trade) => WsMessage::Trade {
+            trade_id: ids.uuid(trade.id).to_string(),
+            price: trade.price.to_string(),
+            quantity: trade.quantity.to_string(),
+            side: trade.taker_side.to_string(),
+            timestamp: trade.timestamp.timestamp_millis(),
+            taker_owner_id: trade.taker_owner_id.clone(),
+            maker_owner_id: trade.maker_owner_id.clone(),
+        },
+        // Fills are private to their owner
+        EngineEvent::OrderFilled(_) => return None,
+        // Liveness is reported by the health check, not the feeds
+        EngineEvent::Heartbeat { .. } => return None,
+        EngineEvent::TradeBusted(trade) => WsMessage::TradeBusted {
+            trade_id: ids.uuid(trade.id).to_string(),
+            price: trade.price.to_string(),
+            quantity: trade.quantity.to_string(),
+            side: trade.taker_side.to_string(),
+            timestamp: trade.timestamp.timestamp_millis(),
+        },
+        EngineEvent::BustRejected { trade_id, reason } => WsMessage::BustRejected {
+            trade_id: ids.uuid(trade_id).to_string(),
+            reason: reason.code().to_string(),
+            message: reason.to_string(),
+        },
+        EngineEvent::OrderBookUpdate(snapshot) => WsMessage::OrderBook {
+            version: FEED_VERSION,
+            best_bid: snapshot.best_bid.map(|p| p.to_string()),
+            best_ask: snapshot.best_ask.map(|p| p.to_string()),
+            best_bid_qty: snapshot.best_bid_qty.map(|q| q.to_string()),
+            best_ask_qty: snapshot.best_ask_qty.map(|q| q.to_string()),
+            mid_price: snapshot.mid_price.map(|p| p.to_string()),
+            microprice: snapshot.microprice.map(|p| p.to_string()),
+            top_imbalance: snapshot.top_imbalance.map(|i| i.to_string()),
+            depth_imbalance: snapshot.depth_imbalance.map(|i| i.to_string()),
+            checksum: snapshot.checksum,
+            last_trade_price: snapshot.last_trade_price.map(|p| p.to_string()),
+            trade_count: snapshot.trades.count,
+            traded_volume: snapshot.trades.volume.to_string(),
+            traded_notional: snapshot.trades.notional.to_string(),
+            bids: depth_rows(&snapshot.bid_depth, notional_scale),
+            asks: depth_rows(&snapshot.ask_depth, notional_scale),
+            sequence: snapshot.sequence,
+            timestamp: snapshot.timestamp.timestamp_millis(),
+        },
+        EngineEvent::OrderAccepted {
+            order_id,
+            client_order_id,
+            owner_id,
+            ..
+        } => WsMessage::OrderAccepted {
+            order_id: ids.uuid(order_id).to_string(),
+            client_order_id,
+            owner_id,
+        },
+        EngineEvent::OrderRested {
+            order_id,
+            price,
+            remaining_quantity,
+        } => rested_message(ids, order_id, price, remaining_quantity),
+        EngineEvent::OrderRejected {
+            order_id,
+            client_order_id,
+            reason,
+        } => WsMessage::OrderRejected {
+            order_id: ids.uuid(order_id).to_string(),
+            client_order_id,
+            reason: reason.code().to_string(),
+            message: reason.to_string(),
+        },
+        EngineEvent::StopTriggered { order_id, stop_price } => WsMessage::StopTriggered {
+            order_id: ids.uuid(order_id).to_string(),
+            stop_price: stop_price.to_string(),
+        },
+        EngineEvent::OrderCancelled {
+            order_id,
+            remaining_quantity,
+            reason,
+        } => cancelled_message(ids, order_id, remaining_quantity, reason),
+        EngineEvent::CancelRejected { order_id, reason } => WsMessage::CancelRejected {
+            order_id: ids.uuid(order_id).to_string(),
+            reason: reason.code().to_string(),
+            message: reason.to_string(),
+        },
+        EngineEvent::MassCancelled { owner_id, side, count } => WsMessage::MassCancelled {
+            owner_id,
+            side: side.map(|s| s.to_string()),
+            count,
+        },
+        EngineEvent::OrderModified {
+            order_id,
+            new_order_id,
+            price,
+            remaining_quantity,
+        } => WsMessage::OrderModified {
+            order_id: ids.uuid(order_id).to_string(),
+            new_order_id: ids.uuid(new_order_id).to_string(),
+            price: price.to_string(),
+            remaining_quantity: remaining_quantity.to_string(),
+        },
+        EngineEvent::ModifyRejected { order_id, reason } => WsMessage::ModifyRejected {
+            order_id: ids.uuid(order_id).to_string(),
+            reason: reason.code().to_string(),
+            message: reason.to_string(),
+        },
+        EngineEvent::QuoteReplaced {
+            owner_id,
+            bid_order_id,
+            ask_order_id,
+            cancelled,
+        } => WsMessage::QuoteReplaced {
+            owner_id,
+            bid_order_id: ids.uuid(bid_order_id).to_string(),
+            ask_order_id: ids.uuid(ask_order_id).to_string(),
+            cancelled: cancelled.iter().map(|&id| ids.uuid(id).to_string()).collect(),
+        },
+        EngineEvent::QuoteRejected { owner_id, reason } => WsMessage::QuoteRejected {
+            owner_id,
+            reason: reason.code().to_string(),
+            message: reason.to_string(),
+        },
+        EngineEvent::KillSwitchEngaged { owner_id, cancelled } => {
+            WsMessage::KillSwitchEngaged { owner_id, cancelled }
+        }
+        EngineEvent::KillSwitchReleased { owner_id } => WsMessage::KillSwitchReleased { owner_id },
+        EngineEvent::TradingHalted { until, last_price } => WsMessage::TradingHalted {
+            until: until.timestamp_millis(),
+            last_price: last_price.to_string(),
+        },
+        EngineEvent::TradingResumed => WsMessage::TradingResumed,
+        EngineEvent::AuctionStarted => WsMessage::AuctionStarted,
+        EngineEvent::AuctionIndicative { price, volume } => WsMessage::AuctionIndicative {
+            price: price.map(|p| p.to_string()),
+            volume: volume.to_string(),
+        },
+        EngineEvent::AuctionEnded { price, volume } => WsMessage::AuctionEnded {
+            price: price.map(|p| p.to_string()),
+            volume: volume.to_string(),
+        },
+        EngineEvent::Stopped => WsMessage::EngineStopped,
+        EngineEvent::Recovered { .. } => WsMessage::EngineRecovered,
+    };
+    Some(message)
+}
+
+fn rested_message(ids: IdEpoch, order_id: OrderId, price: Decimal, remaining_quantity: Decimal) -> WsMessage {
+    WsMessage::OrderRested {
+        order_id: ids.uuid(order_id).to_string(),
+        price: price.to_string(),
+        remaining_quantity: remaining_quantity.to_string(),
+    }
+}
+
+fn cancelled_message(ids: IdEpoch, order_id: OrderId, remaining_quantity: Decimal, reason: CancelReason) -> WsMessage {
+    WsMessage::OrderCancelled {
+        order_id: ids.uuid(order_id).to_string(),
+        remaining_quantity: remaining_quantity.to_string(),
+        reason: reason.code().to_string(),
+    }
+}
+
+/// Message for an event on `owner_id`'s private stream, if it concerns them.
+/// Only acceptances and fills name their owner, so `live` tracks the
+/// owner's orders accepted since the stream opened that are still open,
+/// to pick out their other events.
+fn owner_message(event: EngineEvent, ids: IdEpoch, owner_id: &str, live: &mut HashSet<OrderId>) -> Option<WsMessage> {
+    match event {
+        EngineEvent::Stopped => Some(WsMessage::EngineStopped),
+        EngineEvent::Recovered { .. } => Some(WsMessage::EngineRecovered),
+        EngineEvent::OrderAccepted {
+            order_id,
+            client_order_id,
+            owner_id: Some(owner),
+            ..
+        } if owner == owner_id => {
+            live.insert(order_id);
+            Some(WsMessage::OrderAccepted {
+                order_id: ids.uuid(order_id).to_string(),
+                client_order_id,
+                owner_id: Some(owner),
+            })
+        }
+        EngineEvent::OrderRested {
+            order_id,
+            price,
+            remaining_quantity,
+        } if live.contains(&order_id) => Some(rested_message(ids, order_id, price, remaining_quantity)),
+        EngineEvent::OrderFilled(fill) if fill.owner_id.as_deref() == Some(owner_id) => {
+            if fill.status == OrderStatus::Filled {
+                live.remove(&fill.order_id);
             }
-            Err(e) => {
-                tracing::warn!("WebSocket error: {}", e);
-                break;
+            Some(WsMessage::OrderFilled {
+                order_id: ids.uuid(fill.order_id).to_string(),
+                client_order_id: fill.client_order_id,
+                sequence: fill.sequence,
+                fill_qty: fill.fill_qty.to_string(),
+                cumulative_filled: fill.cumulative_filled.to_string(),
+                remaining: fill.remaining.to_string(),
+                status: fill.status,
+            })
+        }
+        EngineEvent::OrderCancelled {
+            order_id,
+            remaining_quantity,
+            reason,
+        } => live
+            .remove(&order_id)
+            .then(|| cancelled_message(ids, order_id, remaining_quantity, reason)),
+        EngineEvent::OrderRejected { order_id, .. } => {
+            live.remove(&order_id);
+            None
+        }
+        EngineEvent::OrderModified {
+            order_id, new_order_id, ..
+        } => {
+            // A replacement carries on under its new id
+            if new_order_id != order_id && live.remove(&order_id) {
+                live.insert(new_order_id);
             }
-            _ => {}
+            None
         }
+        _ => None,
     }
+}
 
-    // Abort the send task when client disconnects
-    send_task.abort();
-    tracing::debug!("WebSocket connection closed");
+#[cfg(test)]
+mod tests {
+    use crate::api::auth::{KeyStore, Principal, Scope, API_KEY_HEADER};
+    use crate::api::test_support::{
+        connect, next_message, next_of_type, request, request_with_headers, serve_stack, spawn_app, start_stack,
+    };
+    use crate::engine::{EngineBuilder, ManualClock};
+    use axum::Extension;
+    use serde_json::json;
+    use std::future::IntoFuture;
+    use std::sync::Arc;
+    use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+
+    #[tokio::test]
+    async fn test_market_feed_groups_depth_on_request() {
+        let (app, addr) = serve_stack(Arc::new(ManualClock::new(chrono::Utc::now()))).await;
+        let mut grouped = connect(addr, "/ws/market?group=0.5").await;
+
+        for price in ["99.75", "99.50"] {
+            let order = json!({ "side": "buy", "price": price, "quantity": "1" });
+            request(&app, "POST", "/api/orders?confirm=true", Some(order)).await;
+        }
+        next_of_type(&mut grouped, "order_book").await;
+        let book = next_of_type(&mut grouped, "order_book").await;
+        assert_eq!(book["best_bid"], "99.75");
+        assert_eq!(book["bids"], json!([["99.5", "2", "2"]]));
+        assert_eq!(book["version"], 2);
+        // Grouped views carry the number of the update they follow
+        assert_eq!(book["sequence"], 2);
+    }
+
+    #[tokio::test]
+    async fn test_market_feed_adds_notional_on_request() {
+        let (app, addr) = serve_stack(Arc::new(ManualClock::new(chrono::Utc::now()))).await;
+        let mut notional = connect(addr, "/ws/market?notional=true").await;
+
+        for (price, quantity) in [("100", "1.5"), ("99", "2")] {
+            let order = json!({ "side": "buy", "price": price, "quantity": quantity });
+            request(&app, "POST", "/api/orders?confirm=true", Some(order)).await;
+        }
+        next_of_type(&mut notional, "order_book").await;
+        let book = next_of_type(&mut notional, "order_book").await;
+        assert_eq!(book["bids"], json!([["100", "1.5", "1", "150.0", "150.0"], ["99", "2", "1", "198", "348.0"]]));
+    }
+
+    #[tokio::test]
+    async fn test_book_message_carries_imbalance() {
+        let (app, mut ws) = start_stack(Arc::new(ManualClock::new(chrono::Utc::now()))).await;
+
+        let book = {
+            let bid = json!({ "side": "buy", "price": "99", "quantity": "3" });
+            request(&app, "POST", "/api/orders?confirm=true", Some(bid)).await;
+            next_of_type(&mut ws, "order_book").await
+        };
+        assert_eq!((&book["top_imbalance"], &book["depth_imbalance"]), (&json!("1"), &json!("1")));
+        assert_eq!((&book["best_bid_qty"], &book["best_ask_qty"]), (&json!("3"), &json!(null)));
+
+        for (price, quantity) in [("101", "1"), ("102", "1")] {
+            let ask = json!({ "side": "sell", "price": price, "quantity": quantity });
+            request(&app, "POST", "/api/orders?confirm=true", Some(ask)).await;
+        }
+        next_of_type(&mut ws, "order_book").await;
+        let book = next_of_type(&mut ws, "order_book").await;
+        assert_eq!(book["top_imbalance"], "0.5");
+        assert_eq!(book["depth_imbalance"], "0.2");
+        assert_eq!(book["best_ask_qty"], "1");
+    }
+
+    #[tokio::test]
+    async fn test_book_message_checksum_matches_its_depth() {
+        let (app, mut ws) = start_stack(Arc::new(ManualClock::new(chrono::Utc::now()))).await;
+        for (side, price, quantity) in [("buy", "100.50", "2"), ("buy", "100", "1"), ("sell", "101", "0.25")] {
+            let order = json!({ "side": side, "price": price, "quantity": quantity });
+            request(&app, "POST", "/api/orders?confirm=true", Some(order)).await;
+        }
+        for _ in 0..2 {
+            next_of_type(&mut ws, "order_book").await;
+        }
+        let book = next_of_type(&mut ws, "order_book").await;
+        assert_eq!(book["checksum"], 3109731841u32);
+    }
+
+    #[tokio::test]
+    async fn test_feed_messages_carry_event_sequence() {
+        let (app, mut ws) = start_stack(Arc::new(ManualClock::new(chrono::Utc::now()))).await;
+        let maker = json!({ "side": "sell", "price": "100", "quantity": "1" });
+        request(&app, "POST", "/api/orders?confirm=true", Some(maker)).await;
+        let taker = json!({ "side": "buy", "price": "100", "quantity": "1" });
+        request(&app, "POST", "/api/orders?confirm=true", Some(taker)).await;
+
+        // The maker is accepted and rests before the book is published
+        let book = next_of_type(&mut ws, "order_book").await;
+        assert_eq!((&book["seq"], &book["symbol"]), (&json!(3), &json!("BTC/USD")));
+        assert_eq!(next_of_type(&mut ws, "trade").await["seq"], 5);
+        // The two private fills in between are numbered but not shown here
+        let book = next_of_type(&mut ws, "order_book").await;
+        assert_eq!((&book["seq"], &book["sequence"]), (&json!(8), &json!(2)));
+    }
+
+    #[tokio::test]
+    async fn test_order_stream_carries_only_own_fills() {
+        let (app, addr) = serve_stack(Arc::new(ManualClock::new(chrono::Utc::now()))).await;
+        let mut alice = connect(addr, "/ws/orders?owner_id=alice").await;
+
+        for (owner, side, quantity) in [("carol", "sell", "1"), ("dave", "buy", "1"), ("alice", "sell", "2")] {
+            let order = json!({ "owner_id": owner, "side": side, "price": "100", "quantity": quantity });
+            request(&app, "POST", "/api/orders", Some(order)).await;
+        }
+        let taker = json!({ "owner_id": "bob", "side": "buy", "price": "100", "quantity": "1" });
+        request(&app, "POST", "/api/orders", Some(taker.clone())).await;
+        request(&app, "POST", "/api/orders", Some(taker)).await;
+
+        let first = next_of_type(&mut alice, "order_filled").await;
+        assert_eq!(first["cumulative_filled"], "1");
+        assert_eq!(first["status"], "partiallyfilled");
+        let second = next_of_type(&mut alice, "order_filled").await;
+        assert_eq!(second["order_id"], first["order_id"]);
+        assert_eq!(second["cumulative_filled"], "2");
+        assert_eq!(second["remaining"], "0");
+        assert_eq!(second["status"], "filled");
+    }
+
+    #[tokio::test]
+    async fn test_order_stream_carries_own_acceptance_and_resting() {
+        let (app, addr) = serve_stack(Arc::new(ManualClock::new(chrono::Utc::now()))).await;
+        let mut alice = connect(addr, "/ws/orders?owner_id=alice").await;
+
+        let bob = json!({ "owner_id": "bob", "side": "sell", "price": "101", "quantity": "1" });
+        request(&app, "POST", "/api/orders", Some(bob)).await;
+        let order = json!({
+            "owner_id": "alice", "side": "buy", "price": "100", "quantity": "2", "client_order_id": "a1"
+        });
+        request(&app, "POST", "/api/orders", Some(order)).await;
+
+        let accepted = next_of_type(&mut alice, "order_accepted").await;
+        assert_eq!((&accepted["owner_id"], &accepted["client_order_id"]), (&json!("alice"), &json!("a1")));
+        // Bob's order rested first, but only Alice's shows here
+        let rested = next_of_type(&mut alice, "order_rested").await;
+        assert_eq!(rested["order_id"], accepted["order_id"]);
+        assert_eq!((&rested["price"], &rested["remaining_quantity"]), (&json!("100"), &json!("2")));
+        assert_eq!(rested["seq"].as_u64().unwrap(), accepted["seq"].as_u64().unwrap() + 1);
+    }
+
+    #[tokio::test]
+    async fn test_order_stream_carries_own_cancels() {
+        let (app, addr) = serve_stack(Arc::new(ManualClock::new(chrono::Utc::now()))).await;
+        let mut alice = connect(addr, "/ws/orders?owner_id=alice").await;
+
+        let mut order_ids = Vec::new();
+        for owner in ["bob", "alice"] {
+            let order = json!({ "owner_id": owner, "side": "buy", "price": "100", "quantity": "1" });
+            let (_, body) = request(&app, "POST", "/api/orders?confirm=true", Some(order)).await;
+            order_ids.push(body["order_id"].as_str().unwrap().to_string());
+        }
+        for order_id in &order_ids {
+            request(&app, "DELETE", &format!("/api/orders/{order_id}"), None).await;
+        }
+
+        let cancelled = next_of_type(&mut alice, "order_cancelled").await;
+        assert_eq!(cancelled["order_id"], order_ids[1]);
+        assert_eq!((&cancelled["remaining_quantity"], &cancelled["reason"]), (&json!("1"), &json!("USER_REQUESTED")));
+    }
+
+    #[tokio::test]
+    async fn test_order_stream_defaults_to_the_keys_owner() {
+        let mut keys = KeyStore::new();
+        keys.insert("alice-key", Principal::new("alice", [Scope::Read, Scope::Trade]));
+        let app = spawn_app(EngineBuilder::new("BTC/USD")).layer(Extension(Arc::new(keys)));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(axum::serve(listener, app.clone()).into_future());
+
+        let mut stream = format!("ws://{addr}/ws/orders").into_client_request().unwrap();
+        stream.headers_mut().insert(API_KEY_HEADER, "alice-key".parse().unwrap());
+        let (mut alice, _) = tokio_tungstenite::connect_async(stream).await.unwrap();
+        next_message(&mut alice).await;
+
+        let order = json!({ "side": "buy", "price": "100", "quantity": "1" });
+        let headers = [(API_KEY_HEADER, "alice-key")];
+        let (_, body) = request_with_headers(&app, "POST", "/api/orders", &headers, Some(order)).await;
+        let accepted = next_of_type(&mut alice, "order_accepted").await;
+        assert_eq!((&accepted["order_id"], &accepted["owner_id"]), (&body["order_id"], &json!("alice")));
+    }
 }
@@ -31,7 +31,7 @@ pub fn engine_event_to_snapshot(event: &EngineEvent, symbol: &str) -> Option<Mar
                 spread,
                 last_trade_price: None,
                 last_trade_quantity: None,
-                timestamp: chrono::Utc::now().timestamp_millis(),
+                timestamp: snapshot.timestamp.timestamp_millis(),
             })
         }
         EngineEvent::Trade(trade) => Some(MarketSnapshot {
@@ -43,5 +43,29 @@ pub fn engine_event_to_snapshot(event: &EngineEvent, symbol: &str) -> Option<Mar
             last_trade_quantity: Some(trade.quantity.to_string()),
             timestamp: trade.timestamp.timestamp_millis(),
         }),
+        EngineEvent::OrderFilled(_)
+        | EngineEvent::TradeBusted(_)
+        | EngineEvent::BustRejected { .. }
+        | EngineEvent::OrderAccepted { .. }
+        | EngineEvent::OrderRested { .. }
+        | EngineEvent::OrderRejected { .. }
+        | EngineEvent::StopTriggered { .. }
+        | EngineEvent::OrderCancelled { .. }
+        | EngineEvent::CancelRejected { .. }
+        | EngineEvent::MassCancelled { .. }
+        | EngineEvent::OrderModified { .. }
+        | EngineEvent::ModifyRejected { .. }
+        | EngineEvent::QuoteReplaced { .. }
+        | EngineEvent::QuoteRejected { .. }
+        | EngineEvent::KillSwitchEngaged { .. }
+        | EngineEvent::KillSwitchReleased { .. }
+        | EngineEvent::TradingHalted { .. }
+        | EngineEvent::TradingResumed
+        | EngineEvent::AuctionStarted
+        | EngineEvent::AuctionIndicative { .. }
+        | EngineEvent::AuctionEnded { .. }
+        | EngineEvent::Stopped
+        | EngineEvent::Heartbeat { .. }
+        | EngineEvent::Recovered { .. } => None,
     }
 }
@@ -0,0 +1,27 @@
+//! Pinning engine threads to cores.
+
+/// Pin the calling thread to `core`, so the scheduler neither moves it nor
+/// runs it elsewhere. Logs and carries on unpinned if that isn't possible.
+#[cfg(target_os = "linux")]
+pub fn pin_to_core(core: usize) {
+    // SAFETY: `cpu_set_t` is a plain bitmask, valid when zeroed, and
+    // `CPU_SET` only writes inside it; a core beyond its size is refused
+    // before the call. `sched_setaffinity` reads the set we pass and
+    // nothing else, and pid 0 means the calling thread.
+    let pinned = core < libc::CPU_SETSIZE as usize
+        && unsafe {
+            let mut set: libc::cpu_set_t = std::mem::zeroed();
+            libc::CPU_SET(core, &mut set);
+            libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) == 0
+        };
+    if pinned {
+        tracing::info!(core, "Engine thread pinned");
+    } else {
+        tracing::warn!(core, error = %std::io::Error::last_os_error(), "Could not pin engine thread");
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn pin_to_core(core: usize) {
+    tracing::warn!(core, "Pinning engine threads is only supported on Linux");
+}
@@ -0,0 +1,355 @@
+//! The command queue between engine handles and their engine.
+//!
+//! An engine run as a tokio task takes its commands from a tokio channel.
+//! One on a dedicated thread takes them from a flume channel, which a
+//! plain thread can block on without a runtime. Handles send into either
+//! the same way and get tokio's error types back, so callers never see
+//! which one an engine uses.
+
+use crate::engine::matcher::EngineCommand;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc::error::{SendError, TrySendError};
+use tokio::sync::{mpsc, watch};
+
+/// Environment variable choosing where engines run: `off` (the default)
+/// for tokio tasks, `on` for a thread each, or a comma-separated list of
+/// cores to pin each engine's thread to, in symbol order
+pub const ENGINE_THREADS_VAR: &str = "CLOB_ENGINE_THREADS";
+
+/// Where an engine runs
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EngineMode {
+    /// As a task on the tokio runtime, sharing its workers with everything else
+    #[default]
+    Task,
+    /// On a thread of its own, optionally pinned to a core
+    Thread { core: Option<usize> },
+}
+
+/// Where each engine runs, as read from `CLOB_ENGINE_THREADS`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum EngineThreads {
+    #[default]
+    Off,
+    On,
+    /// Cores in symbol order; engines beyond the list get unpinned threads
+    Pinned(Vec<usize>),
+}
+
+impl EngineThreads {
+    /// Mode of the `index`th engine in symbol order
+    pub fn mode(&self, index: usize) -> EngineMode {
+        match self {
+            Self::Off => EngineMode::Task,
+            Self::On => EngineMode::Thread { core: None },
+            Self::Pinned(cores) => EngineMode::Thread {
+                core: cores.get(index).copied(),
+            },
+        }
+    }
+}
+
+/// A `CLOB_ENGINE_THREADS` value that is neither on, off nor a core list
+#[derive(Debug, thiserror::Error)]
+#[error("invalid engine threads {0:?}: expected on, off or a comma-separated list of cores")]
+pub struct InvalidEngineThreads(String);
+
+impl FromStr for EngineThreads {
+    type Err = InvalidEngineThreads;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "" | "off" => Ok(Self::Off),
+            "on" => Ok(Self::On),
+            cores => cores
+                .split(',')
+                .map(|core| core.trim().parse())
+                .collect::<Result<_, _>>()
+                .map(Self::Pinned)
+                .map_err(|_| InvalidEngineThreads(s.to_string())),
+        }
+    }
+}
+
+/// The two ends of a command queue for an engine in `mode`, holding up to
+/// `capacity` commands
+pub fn command_channel(mode: EngineMode, capacity: usize) -> (CommandSender, CommandReceiver) {
+    match mode {
+        EngineMode::Task => {
+            let (tx, rx) = mpsc::channel(capacity);
+            (CommandSender::Task(tx), CommandReceiver::Task(rx))
+        }
+        EngineMode::Thread { .. } => {
+            let (tx, rx) = flume::bounded(capacity);
+            let (closed, _) = watch::channel(false);
+            let gate = Arc::new(Gate {
+                closed: AtomicBool::new(false),
+                sending: AtomicUsize::new(0),
+                closed_tx: closed,
+            });
+            (
+                CommandSender::Thread {
+                    tx,
+                    gate: gate.clone(),
+                },
+                CommandReceiver::Thread { rx, gate },
+            )
+        }
+    }
+}
+
+/// Lets an engine thread refuse new commands while it still takes the
+/// queued ones, as closing a tokio receiver does
+#[derive(Debug)]
+pub struct Gate {
+    closed: AtomicBool,
+    /// Sends let through before the gate closed that may not have arrived
+    sending: AtomicUsize,
+    /// Becomes true once closed, for `CommandSender::closed`
+    closed_tx: watch::Sender<bool>,
+}
+
+impl Gate {
+    /// Admit a send, unless closed. The send counts as in flight until the
+    /// guard drops, which is after its command is queued.
+    fn enter(&self) -> Option<Sending<'_>> {
+        self.sending.fetch_add(1, Ordering::SeqCst);
+        let sending = Sending(self);
+        (!self.closed.load(Ordering::SeqCst)).then_some(sending)
+    }
+
+    fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        self.closed_tx.send_replace(true);
+    }
+}
+
+/// A send admitted through the gate
+struct Sending<'a>(&'a Gate);
+
+impl Drop for Sending<'_> {
+    fn drop(&mut self) {
+        self.0.sending.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Sending side of an engine's command queue
+#[derive(Debug, Clone)]
+pub enum CommandSender {
+    Task(mpsc::Sender<EngineCommand>),
+    Thread {
+        tx: flume::Sender<EngineCommand>,
+        gate: Arc<Gate>,
+    },
+}
+
+impl CommandSender {
+    /// Queue a command, waiting for room if the queue is full. Fails once
+    /// the engine has closed its queue or stopped.
+    pub async fn send(&self, command: EngineCommand) -> Result<(), SendError<EngineCommand>> {
+        match self {
+            Self::Task(tx) => tx.send(command).await,
+            Self::Thread { tx, gate } => {
+                let Some(_sending) = gate.enter() else {
+                    return Err(SendError(command));
+                };
+                tx.send_async(command).await.map_err(|e| SendError(e.into_inner()))
+            }
+        }
+    }
+
+    /// Queue a command only if there is room right now
+    // The error hands the command back, as tokio's does
+    #[allow(clippy::result_large_err)]
+    pub fn try_send(&self, command: EngineCommand) -> Result<(), TrySendError<EngineCommand>> {
+        match self {
+            Self::Task(tx) => tx.try_send(command),
+            Self::Thread { tx, gate } => {
+                let Some(_sending) = gate.enter() else {
+                    return Err(TrySendError::Closed(command));
+                };
+                tx.try_send(command).map_err(|e| match e {
+                    flume::TrySendError::Full(command) => TrySendError::Full(command),
+                    flume::TrySendError::Disconnected(command) => TrySendError::Closed(command),
+                })
+            }
+        }
+    }
+
+    /// Commands that can be queued before senders have to wait
+    pub fn capacity(&self) -> usize {
+        match self {
+            Self::Task(tx) => tx.capacity(),
+            Self::Thread { tx, .. } => self.max_capacity().saturating_sub(tx.len()),
+        }
+    }
+
+    /// Commands the queue holds when full
+    pub fn max_capacity(&self) -> usize {
+        match self {
+            Self::Task(tx) => tx.max_capacity(),
+            Self::Thread { tx, .. } => tx.capacity().unwrap_or(usize::MAX),
+        }
+    }
+
+    /// Whether the engine takes no more commands
+    pub fn is_closed(&self) -> bool {
+        match self {
+            Self::Task(tx) => tx.is_closed(),
+            Self::Thread { tx, gate } => gate.closed.load(Ordering::SeqCst) || tx.is_disconnected(),
+        }
+    }
+
+    /// Resolves once the engine takes no more commands
+    pub async fn closed(&self) {
+        match self {
+            Self::Task(tx) => tx.closed().await,
+            Self::Thread { gate, .. } => {
+                let _ = gate.closed_tx.subscribe().wait_for(|closed| *closed).await;
+            }
+        }
+    }
+}
+
+/// Receiving side of an engine's command queue
+#[derive(Debug)]
+pub enum CommandReceiver {
+    Task(mpsc::Receiver<EngineCommand>),
+    Thread {
+        rx: flume::Receiver<EngineCommand>,
+        gate: Arc<Gate>,
+    },
+}
+
+impl CommandReceiver {
+    /// Refuse new commands; those already queued are still received
+    pub fn close(&mut self) {
+        match self {
+            Self::Task(rx) => rx.close(),
+            Self::Thread { gate, .. } => gate.close(),
+        }
+    }
+
+    /// Wait for a command, then add up to `limit` in all to `buffer` from
+    /// what is queued. `Some(0)` once the queue is closed and empty, `None`
+    /// if `wait` passes first.
+    ///
+    /// # Panics
+    ///
+    /// On a thread's queue, which only `recv_many_blocking` takes from.
+    pub async fn recv_many(
+        &mut self,
+        buffer: &mut Vec<EngineCommand>,
+        limit: usize,
+        wait: Option<Duration>,
+    ) -> Option<usize> {
+        let Self::Task(rx) = self else {
+            panic!("an engine thread's queue is received from with recv_many_blocking");
+        };
+        match wait {
+            None => Some(rx.recv_many(buffer, limit).await),
+            Some(wait) => tokio::time::timeout(wait, rx.recv_many(buffer, limit)).await.ok(),
+        }
+    }
+
+    /// `recv_many` for an engine thread, blocking it instead of awaiting
+    ///
+    /// # Panics
+    ///
+    /// On a tokio task's queue.
+    pub fn recv_many_blocking(
+        &mut self,
+        buffer: &mut Vec<EngineCommand>,
+        limit: usize,
+        wait: Option<Duration>,
+    ) -> Option<usize> {
+        let Self::Thread { rx, gate } = self else {
+            panic!("a tokio task's queue is received from with recv_many");
+        };
+        let first = loop {
+            if gate.closed.load(Ordering::SeqCst) {
+                // Shutting down: everything admitted before the close is
+                // queued once no send is in flight. Read the count first,
+                // so a send finishing meanwhile is seen in the queue.
+                let in_flight = gate.sending.load(Ordering::SeqCst);
+                match rx.try_recv() {
+                    Ok(command) => break command,
+                    Err(flume::TryRecvError::Empty) if in_flight > 0 => std::thread::yield_now(),
+                    Err(_) => return Some(0),
+                }
+            } else {
+                let received = match wait {
+                    None => rx.recv().map_err(|_| flume::RecvTimeoutError::Disconnected),
+                    Some(wait) => rx.recv_timeout(wait),
+                };
+                match received {
+                    Ok(command) => break command,
+                    Err(flume::RecvTimeoutError::Timeout) => return None,
+                    Err(flume::RecvTimeoutError::Disconnected) => return Some(0),
+                }
+            }
+        };
+        let before = buffer.len();
+        buffer.push(first);
+        buffer.extend(rx.try_iter().take(limit.saturating_sub(1)));
+        Some(buffer.len() - before)
+    }
+}
+
+impl Drop for CommandReceiver {
+    fn drop(&mut self) {
+        // Senders waiting on `closed` learn the engine is gone
+        if let Self::Thread { gate, .. } = self {
+            gate.close();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_engine_threads_parse() {
+        assert_eq!("off".parse::<EngineThreads>().unwrap(), EngineThreads::Off);
+        assert_eq!("ON".parse::<EngineThreads>().unwrap(), EngineThreads::On);
+        let pinned: EngineThreads = "2, 3".parse().unwrap();
+        assert_eq!(pinned, EngineThreads::Pinned(vec![2, 3]));
+        assert_eq!(pinned.mode(1), EngineMode::Thread { core: Some(3) });
+        assert_eq!(pinned.mode(2), EngineMode::Thread { core: None });
+        assert!("fast".parse::<EngineThreads>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_closed_thread_queue_drains_then_ends() {
+        let (tx, mut rx) = command_channel(EngineMode::Thread { core: None }, 4);
+        assert_eq!((tx.capacity(), tx.max_capacity()), (4, 4));
+        for _ in 0..3 {
+            tx.send(EngineCommand::ExpireOrders).await.unwrap();
+        }
+        assert_eq!(tx.capacity(), 1);
+        tx.try_send(EngineCommand::StartAuction).unwrap();
+        assert!(matches!(tx.try_send(EngineCommand::EndAuction), Err(TrySendError::Full(_))));
+
+        let mut buffer = Vec::new();
+        assert_eq!(rx.recv_many_blocking(&mut buffer, 2, None), Some(2));
+        rx.close();
+        tx.closed().await;
+        assert!(tx.send(EngineCommand::EndAuction).await.is_err());
+        assert!(matches!(tx.try_send(EngineCommand::EndAuction), Err(TrySendError::Closed(_))));
+
+        // What was queued before the close still arrives, then the queue ends
+        assert_eq!(rx.recv_many_blocking(&mut buffer, 8, None), Some(2));
+        assert!(matches!(buffer.last(), Some(EngineCommand::StartAuction)));
+        assert_eq!(rx.recv_many_blocking(&mut buffer, 8, None), Some(0));
+    }
+
+    #[test]
+    fn test_thread_queue_wait_times_out() {
+        let (_tx, mut rx) = command_channel(EngineMode::Thread { core: None }, 4);
+        assert_eq!(rx.recv_many_blocking(&mut Vec::new(), 4, Some(Duration::from_millis(10))), None);
+    }
+}
@@ -0,0 +1,246 @@
+//! Time sources for the engine and the services around it.
+//!
+//! Everything that needs the current time reads it from a shared [`Clock`]
+//! so tests can swap in a [`ManualClock`] and move time explicitly. An
+//! engine takes its clock from `EngineBuilder::clock`, and stamps orders
+//! and trades, expires orders and measures its uptime by it. Heartbeats and
+//! processing latency are about the process rather than the book, so they
+//! are always measured in real time.
+
+use chrono::{DateTime, Utc};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+/// Shared handle to the clock used by every component
+pub type SharedClock = Arc<dyn Clock>;
+
+/// A source of wall-clock time
+pub trait Clock: Send + Sync + std::fmt::Debug {
+    /// Current time
+    fn now(&self) -> DateTime<Utc>;
+
+    /// Current time on a scale that never goes backwards, for measuring
+    /// how long something took. Only differences between readings mean
+    /// anything.
+    fn monotonic(&self) -> Instant;
+
+    /// Notifications for jumps in time that don't happen in real time.
+    /// Time-based services should re-evaluate whenever this fires instead of
+    /// waiting for their next interval tick. `None` for clocks that only move
+    /// with real time.
+    fn subscribe(&self) -> Option<watch::Receiver<DateTime<Utc>>> {
+        None
+    }
+
+    /// Downcast hook for test tooling that needs to move time
+    fn as_manual(&self) -> Option<&ManualClock> {
+        None
+    }
+}
+
+/// The real system clock
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+
+    fn monotonic(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A clock that only moves when told to
+#[derive(Debug)]
+pub struct ManualClock {
+    /// The time, and how far the monotonic scale has moved from `origin`
+    now: Mutex<(DateTime<Utc>, Duration)>,
+    origin: Instant,
+    changed: watch::Sender<DateTime<Utc>>,
+}
+
+impl ManualClock {
+    /// Create a manual clock frozen at the given time
+    pub fn new(start: DateTime<Utc>) -> Self {
+        let (changed, _) = watch::channel(start);
+        Self {
+            now: Mutex::new((start, Duration::ZERO)),
+            origin: Instant::now(),
+            changed,
+        }
+    }
+
+    /// Move the clock forward and notify subscribers
+    pub fn advance(&self, by: chrono::Duration) -> DateTime<Utc> {
+        let now = self.now.lock().unwrap().0 + by;
+        self.set(now)
+    }
+
+    /// Jump the clock to an absolute time and notify subscribers. The
+    /// monotonic scale moves on as far when the jump is forwards, and
+    /// stays put when it is backwards.
+    pub fn set(&self, to: DateTime<Utc>) -> DateTime<Utc> {
+        let mut now = self.now.lock().unwrap();
+        let (time, elapsed) = &mut *now;
+        *elapsed += (to - *time).to_std().unwrap_or_default();
+        *time = to;
+        self.changed.send_replace(to);
+        to
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.now.lock().unwrap().0
+    }
+
+    fn monotonic(&self) -> Instant {
+        self.origin + self.now.lock().unwrap().1
+    }
+
+    fn subscribe(&self) -> Option<watch::Receiver<DateTime<Utc>>> {
+        Some(self.changed.subscribe())
+    }
+
+    fn as_manual(&self) -> Option<&ManualClock> {
+        Some(self)
+    }
+}
+
+/// A clock that ignores real time and moves a fixed step forward on
+/// every read, so a run that reads it in the same order sees the same
+/// times whenever it is repeated. Any reader advances it, so it suits an
+/// engine driven directly rather than one shared with other services.
+#[derive(Debug)]
+pub struct LogicalClock {
+    start: DateTime<Utc>,
+    next: Mutex<DateTime<Utc>>,
+    step: chrono::Duration,
+    origin: Instant,
+}
+
+impl LogicalClock {
+    /// A clock whose first read returns `start`
+    pub fn new(start: DateTime<Utc>, step: chrono::Duration) -> Self {
+        Self {
+            start,
+            next: Mutex::new(start),
+            step,
+            origin: Instant::now(),
+        }
+    }
+}
+
+impl Clock for LogicalClock {
+    fn now(&self) -> DateTime<Utc> {
+        let mut next = self.next.lock().unwrap();
+        let now = *next;
+        *next += self.step;
+        now
+    }
+
+    /// As far from the first reading as the steps taken so far; reading it
+    /// takes no step
+    fn monotonic(&self) -> Instant {
+        let next = *self.next.lock().unwrap();
+        self.origin + (next - self.start).to_std().unwrap_or_default()
+    }
+}
+
+/// Wait until a clock subscription reports a change.
+/// Never resolves for clocks without notifications, so it can sit in a
+/// `tokio::select!` next to a real-time interval.
+pub async fn changed(subscription: &mut Option<watch::Receiver<DateTime<Utc>>>) {
+    match subscription {
+        Some(rx) => {
+            if rx.changed().await.is_err() {
+                // Clock dropped; fall back to real-time ticks only
+                *subscription = None;
+                std::future::pending::<()>().await;
+            }
+        }
+        None => std::future::pending::<()>().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use tokio::time::timeout;
+
+    #[test]
+    fn test_manual_clock_moves_only_when_told() {
+        let start = Utc.with_ymd_and_hms(2030, 1, 1, 0, 0, 0).unwrap();
+        let clock = ManualClock::new(start);
+        assert_eq!(clock.now(), start);
+
+        clock.advance(chrono::Duration::milliseconds(1500));
+        assert_eq!(clock.now(), start + chrono::Duration::milliseconds(1500));
+
+        let later = Utc.with_ymd_and_hms(2031, 6, 1, 12, 0, 0).unwrap();
+        clock.set(later);
+        assert_eq!(clock.now(), later);
+    }
+
+    #[test]
+    fn test_manual_monotonic_time_moves_forwards_only() {
+        let start = Utc.with_ymd_and_hms(2030, 1, 1, 0, 0, 0).unwrap();
+        let clock = ManualClock::new(start);
+        let origin = clock.monotonic();
+        assert_eq!(clock.monotonic(), origin);
+
+        clock.advance(chrono::Duration::seconds(90));
+        assert_eq!(clock.monotonic() - origin, Duration::from_secs(90));
+        // Setting the time back leaves monotonic time where it was
+        clock.set(start);
+        assert_eq!(clock.monotonic() - origin, Duration::from_secs(90));
+        clock.advance(chrono::Duration::seconds(10));
+        assert_eq!(clock.monotonic() - origin, Duration::from_secs(100));
+    }
+
+    #[test]
+    fn test_logical_clock_steps_on_every_read() {
+        let start = Utc.with_ymd_and_hms(2030, 1, 1, 0, 0, 0).unwrap();
+        let clock = LogicalClock::new(start, chrono::Duration::milliseconds(1));
+        assert_eq!(clock.now(), start);
+        assert_eq!(clock.now(), start + chrono::Duration::milliseconds(1));
+        assert_eq!(clock.now(), start + chrono::Duration::milliseconds(2));
+        assert!(clock.subscribe().is_none());
+        // Monotonic time moves with the steps, and reading it takes none
+        let before = clock.monotonic();
+        assert_eq!(clock.monotonic(), before);
+        clock.now();
+        assert_eq!(clock.monotonic() - before, Duration::from_millis(1));
+    }
+
+    #[test]
+    fn test_system_clock_has_no_manual_controls() {
+        let clock = SystemClock;
+        assert!(clock.subscribe().is_none());
+        assert!(clock.as_manual().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_subscribers_notified_on_advance() {
+        let clock: SharedClock = Arc::new(ManualClock::new(Utc::now()));
+        let mut subscription = clock.subscribe();
+
+        clock.as_manual().unwrap().advance(chrono::Duration::seconds(1));
+
+        timeout(Duration::from_millis(100), changed(&mut subscription))
+            .await
+            .expect("subscriber should wake on advance");
+    }
+
+    #[tokio::test]
+    async fn test_changed_pends_without_notifications() {
+        let mut subscription = SystemClock.subscribe();
+        assert!(timeout(Duration::from_millis(10), changed(&mut subscription))
+            .await
+            .is_err());
+    }
+}
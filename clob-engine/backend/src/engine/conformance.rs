@@ -0,0 +1,260 @@
+//! Matching conformance suite, driven by scenario files.
+//!
+//! Every `.json` file under `scenarios/matching` describes one book: how it
+//! is configured, a sequence of submissions, cancels and size reductions,
+//! the exact trades each step must print, and the book that must be left.
+//! The files are loaded at test time, so a new scenario needs no Rust
+//! changes; a scenario that fails is reported by file name.
+//!
+//! ```json
+//! {
+//!   "description": "An incoming buy sweeps two ask levels",
+//!   "algorithm": "fifo",
+//!   "steps": [
+//!     { "submit": { "ref": "a", "side": "sell", "price": "100", "quantity": "1" } },
+//!     { "submit": { "ref": "b", "side": "sell", "price": "101", "quantity": "1" } },
+//!     {
+//!       "submit": { "ref": "c", "side": "buy", "price": "101", "quantity": "2" },
+//!       "trades": [
+//!         { "maker": "a", "taker": "c", "price": "100", "quantity": "1" },
+//!         { "maker": "b", "taker": "c", "price": "101", "quantity": "1" }
+//!       ],
+//!       "rested": false
+//!     }
+//!   ],
+//!   "book": { "bids": [], "asks": [] }
+//! }
+//! ```
+//!
+//! Orders are named by their `ref`. A step without `trades` must print
+//! none. `book` gives each side's displayed depth as `[price, quantity,
+//! orders]`, best first; `open` optionally gives named orders' remaining
+//! quantity, zero for one no longer on the book, which is how hidden orders
+//! are checked.
+
+use crate::engine::order::{Order, OrderId, OrderType, Side, StpPolicy, TimeInForce, Trade};
+use crate::engine::order_book::{MatchingAlgorithm, OrderBook};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+/// Where the scenario files live
+fn scenario_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("scenarios").join("matching")
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Scenario {
+    description: String,
+    #[serde(default)]
+    algorithm: Algorithm,
+    #[serde(default)]
+    lot_size: Option<Decimal>,
+    #[serde(default)]
+    stp_policy: Option<StpPolicy>,
+    steps: Vec<Step>,
+    book: Book,
+    #[serde(default)]
+    open: BTreeMap<String, Decimal>,
+}
+
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Algorithm {
+    #[default]
+    Fifo,
+    ProRata,
+}
+
+#[derive(Debug, Deserialize)]
+struct Step {
+    #[serde(flatten)]
+    action: Action,
+    #[serde(default)]
+    trades: Vec<ExpectedTrade>,
+    /// For a submission, whether its remainder joined the book
+    #[serde(default)]
+    rested: Option<bool>,
+    /// For a cancel or reduction, the quantity it took off the book; zero
+    /// if the order was not there
+    #[serde(default)]
+    removed: Option<Decimal>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Action {
+    Submit(Submit),
+    Cancel {
+        #[serde(rename = "ref")]
+        name: String,
+    },
+    /// Cut a resting order's total quantity to `quantity`
+    Reduce {
+        #[serde(rename = "ref")]
+        name: String,
+        quantity: Decimal,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Submit {
+    #[serde(rename = "ref")]
+    name: String,
+    side: Side,
+    /// Ignored for market orders
+    #[serde(default)]
+    price: Decimal,
+    quantity: Decimal,
+    #[serde(default, rename = "type")]
+    order_type: OrderType,
+    #[serde(default)]
+    time_in_force: TimeInForce,
+    #[serde(default)]
+    hidden: bool,
+    #[serde(default)]
+    owner: Option<String>,
+    #[serde(default)]
+    stp_policy: Option<StpPolicy>,
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ExpectedTrade {
+    maker: String,
+    taker: String,
+    price: Decimal,
+    quantity: Decimal,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Book {
+    bids: Vec<(Decimal, Decimal, usize)>,
+    asks: Vec<(Decimal, Decimal, usize)>,
+}
+
+/// Run one scenario, describing the first way the book departs from it
+fn run(scenario: &Scenario) -> Result<(), String> {
+    let algorithm = match scenario.algorithm {
+        Algorithm::Fifo => MatchingAlgorithm::Fifo,
+        Algorithm::ProRata => MatchingAlgorithm::ProRata,
+    };
+    let mut book = OrderBook::new("TEST").with_algorithm(algorithm).with_lot_size(scenario.lot_size);
+    if let Some(policy) = scenario.stp_policy {
+        book = book.with_stp_policy(policy);
+    }
+    let mut ids: HashMap<String, OrderId> = HashMap::new();
+    let mut names: HashMap<OrderId, String> = HashMap::new();
+    let id_of = |ids: &HashMap<String, OrderId>, name: &str| {
+        ids.get(name).copied().ok_or_else(|| format!("no order named {name:?} was submitted"))
+    };
+
+    for (index, step) in scenario.steps.iter().enumerate() {
+        let at = |problem: String| format!("step {}: {problem}", index + 1);
+        let (trades, rested, removed) = match &step.action {
+            Action::Submit(submit) => {
+                if ids.contains_key(&submit.name) {
+                    return Err(at(format!("order name {:?} is used twice", submit.name)));
+                }
+                let order = order_for(submit);
+                ids.insert(submit.name.clone(), order.id);
+                names.insert(order.id, submit.name.clone());
+                let result = book.match_order(order);
+                (result.trades, Some(result.rested), None)
+            }
+            Action::Cancel { name } => {
+                let id = id_of(&ids, name).map_err(at)?;
+                let removed = book.cancel_order(id).map_or(Decimal::ZERO, |order| order.remaining_quantity);
+                (Vec::new(), None, Some(removed))
+            }
+            Action::Reduce { name, quantity } => {
+                let id = id_of(&ids, name).map_err(at)?;
+                let before = book.get_order(id).map_or(Decimal::ZERO, |order| order.remaining_quantity);
+                let after = match book.reduce_order(id, *quantity) {
+                    Ok(order) => order.remaining_quantity,
+                    Err(_) => before,
+                };
+                (Vec::new(), None, Some(before - after))
+            }
+        };
+
+        let printed: Vec<ExpectedTrade> = trades.iter().map(|trade| named(trade, &names)).collect();
+        if printed != step.trades {
+            return Err(at(format!("expected trades {:?}, got {printed:?}", step.trades)));
+        }
+        if step.rested.is_some() && step.rested != rested {
+            return Err(at(format!("expected rested {:?}, got {rested:?}", step.rested)));
+        }
+        if step.removed.is_some() && step.removed != removed {
+            return Err(at(format!("expected {:?} removed from the book, got {removed:?}", step.removed)));
+        }
+        book.validate().map_err(|e| at(format!("book invariant broken: {e:?}")))?;
+    }
+
+    let (bids, asks) = (book.bid_depth(usize::MAX), book.ask_depth(usize::MAX));
+    if bids != scenario.book.bids || asks != scenario.book.asks {
+        return Err(format!(
+            "expected final book bids {:?} asks {:?}, got bids {bids:?} asks {asks:?}",
+            scenario.book.bids, scenario.book.asks
+        ));
+    }
+    for (name, expected) in &scenario.open {
+        let open = book.get_order(id_of(&ids, name)?).map_or(Decimal::ZERO, |order| order.remaining_quantity);
+        if open != *expected {
+            return Err(format!("expected order {name:?} to have {expected} open, got {open}"));
+        }
+    }
+    Ok(())
+}
+
+fn order_for(submit: &Submit) -> Order {
+    let price = match submit.order_type {
+        OrderType::Market => Decimal::ZERO,
+        OrderType::Limit => submit.price,
+    };
+    let mut order = Order::new(submit.side, price, submit.quantity);
+    order.order_type = submit.order_type;
+    order.time_in_force = submit.time_in_force;
+    order.hidden = submit.hidden;
+    order.owner_id = submit.owner.clone();
+    order.stp_policy = submit.stp_policy;
+    order
+}
+
+fn named(trade: &Trade, names: &HashMap<OrderId, String>) -> ExpectedTrade {
+    let name = |id: &OrderId| names.get(id).cloned().unwrap_or_else(|| format!("#{id}"));
+    ExpectedTrade {
+        maker: name(&trade.maker_order_id),
+        taker: name(&trade.taker_order_id),
+        price: trade.price,
+        quantity: trade.quantity,
+    }
+}
+
+#[test]
+fn test_matching_scenarios() {
+    let mut files: Vec<_> = std::fs::read_dir(scenario_dir())
+        .expect("scenario directory is missing")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    files.sort();
+    assert!(!files.is_empty(), "no scenarios in {}", scenario_dir().display());
+
+    let failures: Vec<String> = files
+        .iter()
+        .filter_map(|path| {
+            let file = path.file_name().unwrap().to_string_lossy();
+            let text = std::fs::read_to_string(path).unwrap();
+            let outcome = serde_json::from_str::<Scenario>(&text)
+                .map_err(|e| format!("unreadable: {e}"))
+                .and_then(|scenario| run(&scenario).map_err(|e| format!("{}: {e}", scenario.description)));
+            outcome.err().map(|e| format!("{file}: {e}"))
+        })
+        .collect();
+    assert!(failures.is_empty(), "{} of {} scenarios failed:\n{}", failures.len(), files.len(), failures.join("\n"));
+}
@@ -0,0 +1,273 @@
+//! Changes between two consecutive depth snapshots, for incremental feeds.
+//!
+//! Both snapshots are top-N windows of the book, so the diff is between
+//! windows: a level pushed out of the window counts as removed even if it
+//! still rests deeper in the book, and one pulled back into it as added.
+
+use crate::engine::matcher::OrderBookSnapshot;
+use crate::engine::order::Side;
+use crate::engine::order_book::OrderBook;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::cmp::Ordering;
+
+/// Displayed depth on both sides, best level first: price, displayed
+/// quantity and displayed order count per level
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DepthSnapshot {
+    pub bids: Vec<(Decimal, Decimal, usize)>,
+    pub asks: Vec<(Decimal, Decimal, usize)>,
+}
+
+impl DepthSnapshot {
+    pub fn new(bids: Vec<(Decimal, Decimal, usize)>, asks: Vec<(Decimal, Decimal, usize)>) -> Self {
+        Self { bids, asks }
+    }
+
+    /// The best `levels` displayed levels of each side of `book`
+    pub fn of_book(book: &OrderBook, levels: usize) -> Self {
+        Self::new(book.bid_depth(levels), book.ask_depth(levels))
+    }
+}
+
+impl From<&OrderBookSnapshot> for DepthSnapshot {
+    fn from(snapshot: &OrderBookSnapshot) -> Self {
+        Self::new(snapshot.bid_depth.clone(), snapshot.ask_depth.clone())
+    }
+}
+
+/// A level that is new, changed or gone. Applying it means setting the
+/// level to `quantity` and `order_count`, and deleting it when `quantity`
+/// is zero.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct LevelChange {
+    pub price: Decimal,
+    pub quantity: Decimal,
+    pub order_count: usize,
+}
+
+impl LevelChange {
+    /// The level at `price` left the window
+    pub fn removed(price: Decimal) -> Self {
+        Self {
+            price,
+            quantity: Decimal::ZERO,
+            order_count: 0,
+        }
+    }
+
+    /// Whether this change deletes its level
+    pub fn is_removal(&self) -> bool {
+        self.quantity.is_zero()
+    }
+}
+
+/// Level changes on each side, best price first
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct DepthDiff {
+    pub bids: Vec<LevelChange>,
+    pub asks: Vec<LevelChange>,
+}
+
+impl DepthDiff {
+    /// Whether the two snapshots showed the same depth
+    pub fn is_empty(&self) -> bool {
+        self.bids.is_empty() && self.asks.is_empty()
+    }
+}
+
+/// What changed from `prev` to `next`: levels added, levels removed
+/// (reported with quantity zero) and levels whose quantity or order count
+/// changed. Each side of both snapshots must be best first, one entry per
+/// price, as the book reports depth.
+pub fn compute_depth_diff(prev: &DepthSnapshot, next: &DepthSnapshot) -> DepthDiff {
+    DepthDiff {
+        bids: side_diff(Side::Buy, &prev.bids, &next.bids),
+        asks: side_diff(Side::Sell, &prev.asks, &next.asks),
+    }
+}
+
+/// Merge one side's two level lists, both best first
+fn side_diff(side: Side, prev: &[(Decimal, Decimal, usize)], next: &[(Decimal, Decimal, usize)]) -> Vec<LevelChange> {
+    let mut changes = Vec::new();
+    let (mut prev, mut next) = (prev.iter().peekable(), next.iter().peekable());
+    loop {
+        // Less: the previous level comes first, so it is gone from `next`
+        let order = match (prev.peek(), next.peek()) {
+            (None, None) => break,
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (Some(&&(was, ..)), Some(&&(now, ..))) => match side {
+                Side::Buy => now.cmp(&was),
+                Side::Sell => was.cmp(&now),
+            },
+        };
+        match order {
+            Ordering::Less => {
+                let &(price, ..) = prev.next().unwrap();
+                changes.push(LevelChange::removed(price));
+            }
+            Ordering::Greater => {
+                let &(price, quantity, order_count) = next.next().unwrap();
+                changes.push(LevelChange {
+                    price,
+                    quantity,
+                    order_count,
+                });
+            }
+            Ordering::Equal => {
+                let &(_, was_quantity, was_count) = prev.next().unwrap();
+                let &(price, quantity, order_count) = next.next().unwrap();
+                if (quantity, order_count) != (was_quantity, was_count) {
+                    changes.push(LevelChange {
+                        price,
+                        quantity,
+                        order_count,
+                    });
+                }
+            }
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::order::Order;
+    use rust_decimal_macros::dec;
+
+    fn change(price: Decimal, quantity: Decimal, order_count: usize) -> LevelChange {
+        LevelChange {
+            price,
+            quantity,
+            order_count,
+        }
+    }
+
+    /// Replay one side's changes onto `prev`, as a feed client would
+    fn apply(side: Side, prev: &[(Decimal, Decimal, usize)], changes: &[LevelChange]) -> Vec<(Decimal, Decimal, usize)> {
+        let mut levels: Vec<_> = prev.to_vec();
+        for c in changes {
+            levels.retain(|&(price, ..)| price != c.price);
+            if !c.is_removal() {
+                levels.push((c.price, c.quantity, c.order_count));
+            }
+        }
+        match side {
+            Side::Buy => levels.sort_by_key(|&(price, ..)| std::cmp::Reverse(price)),
+            Side::Sell => levels.sort_by_key(|&(price, ..)| price),
+        }
+        levels
+    }
+
+    #[test]
+    fn test_identical_snapshots_have_no_diff() {
+        let depth = DepthSnapshot::new(vec![(dec!(99), dec!(1), 1)], vec![(dec!(101), dec!(2), 2)]);
+        assert!(compute_depth_diff(&depth, &depth).is_empty());
+        assert!(compute_depth_diff(&DepthSnapshot::default(), &DepthSnapshot::default()).is_empty());
+    }
+
+    #[test]
+    fn test_added_removed_and_changed_levels_on_both_sides() {
+        let prev = DepthSnapshot::new(
+            vec![(dec!(99), dec!(1), 1), (dec!(98), dec!(2), 1), (dec!(97), dec!(3), 1)],
+            vec![(dec!(101), dec!(1), 1), (dec!(103), dec!(3), 2)],
+        );
+        let next = DepthSnapshot::new(
+            vec![(dec!(100), dec!(5), 1), (dec!(99), dec!(1), 1), (dec!(97), dec!(4), 2)],
+            vec![(dec!(101), dec!(1), 2), (dec!(102), dec!(2), 1)],
+        );
+
+        let diff = compute_depth_diff(&prev, &next);
+        assert_eq!(
+            diff.bids,
+            vec![change(dec!(100), dec!(5), 1), LevelChange::removed(dec!(98)), change(dec!(97), dec!(4), 2)]
+        );
+        // An order count change alone is reported too
+        assert_eq!(
+            diff.asks,
+            vec![change(dec!(101), dec!(1), 2), change(dec!(102), dec!(2), 1), LevelChange::removed(dec!(103))]
+        );
+        assert_eq!(apply(Side::Buy, &prev.bids, &diff.bids), next.bids);
+        assert_eq!(apply(Side::Sell, &prev.asks, &diff.asks), next.asks);
+    }
+
+    #[test]
+    fn test_side_emptied_and_filled_from_nothing() {
+        let full = DepthSnapshot::new(vec![(dec!(99), dec!(1), 1), (dec!(98), dec!(1), 1)], Vec::new());
+        let empty = DepthSnapshot::default();
+
+        let cleared = compute_depth_diff(&full, &empty);
+        assert_eq!(cleared.bids, vec![LevelChange::removed(dec!(99)), LevelChange::removed(dec!(98))]);
+        assert!(cleared.asks.is_empty());
+
+        let filled = compute_depth_diff(&empty, &full);
+        assert_eq!(filled.bids, vec![change(dec!(99), dec!(1), 1), change(dec!(98), dec!(1), 1)]);
+    }
+
+    #[test]
+    fn test_prices_compare_by_value_not_scale() {
+        let prev = DepthSnapshot::new(Vec::new(), vec![(dec!(100.50), dec!(1), 1)]);
+        let next = DepthSnapshot::new(Vec::new(), vec![(dec!(100.5), dec!(1.0), 1)]);
+        assert!(compute_depth_diff(&prev, &next).is_empty());
+    }
+
+    #[test]
+    fn test_level_pushed_out_of_the_window_is_removed() {
+        let mut book = OrderBook::new("BTC/USD");
+        for price in [dec!(101), dec!(102), dec!(103)] {
+            book.add_order(Order::new(Side::Sell, price, dec!(1)));
+        }
+        let prev = DepthSnapshot::of_book(&book, 3);
+
+        // A better ask pushes 103 down to fourth place: still resting, but out of view
+        book.add_order(Order::new(Side::Sell, dec!(100), dec!(1)));
+        let next = DepthSnapshot::of_book(&book, 3);
+        let diff = compute_depth_diff(&prev, &next);
+        assert_eq!(diff.asks, vec![change(dec!(100), dec!(1), 1), LevelChange::removed(dec!(103))]);
+        assert!(book.iter_asks().any(|l| l.price == dec!(103)));
+        assert_eq!(apply(Side::Sell, &prev.asks, &diff.asks), next.asks);
+
+        // Taking the new best ask brings 103 back into the window as an addition
+        book.match_order(Order::new(Side::Buy, dec!(100), dec!(1)));
+        let back = DepthSnapshot::of_book(&book, 3);
+        let diff = compute_depth_diff(&next, &back);
+        assert_eq!(diff.asks, vec![LevelChange::removed(dec!(100)), change(dec!(103), dec!(1), 1)]);
+        assert_eq!(apply(Side::Sell, &next.asks, &diff.asks), back.asks);
+    }
+
+    #[test]
+    fn test_bid_window_shifts_both_ways() {
+        let mut book = OrderBook::new("BTC/USD");
+        for price in [dec!(99), dec!(98)] {
+            book.add_order(Order::new(Side::Buy, price, dec!(1)));
+        }
+        let prev = DepthSnapshot::of_book(&book, 2);
+
+        // A new touch and a level beyond the window: only the touch is seen
+        // joining, and 98 leaves; 97 never shows
+        book.add_order(Order::new(Side::Buy, dec!(100), dec!(2)));
+        book.add_order(Order::new(Side::Buy, dec!(97), dec!(1)));
+        let next = DepthSnapshot::of_book(&book, 2);
+        let diff = compute_depth_diff(&prev, &next);
+        assert_eq!(diff.bids, vec![change(dec!(100), dec!(2), 1), LevelChange::removed(dec!(98))]);
+        assert!(diff.asks.is_empty());
+        assert_eq!(apply(Side::Buy, &prev.bids, &diff.bids), next.bids);
+    }
+
+    #[test]
+    fn test_hidden_liquidity_changes_nothing() {
+        let mut book = OrderBook::new("BTC/USD");
+        book.add_order(Order::new(Side::Buy, dec!(99), dec!(1)));
+        let prev = DepthSnapshot::of_book(&book, 10);
+
+        let mut hidden = Order::new(Side::Buy, dec!(99), dec!(5));
+        hidden.hidden = true;
+        book.add_order(hidden);
+        let mut hidden = Order::new(Side::Buy, dec!(100), dec!(5));
+        hidden.hidden = true;
+        book.add_order(hidden);
+        assert!(compute_depth_diff(&prev, &DepthSnapshot::of_book(&book, 10)).is_empty());
+    }
+}
@@ -0,0 +1,257 @@
+//! Running engines, one per symbol.
+//!
+//! Every symbol gets its own `MatchingEngine` task and event stream, so a
+//! busy book never delays or laps the consumers of another. `subscribe`
+//! merges the streams for consumers that want everything; each event says
+//! which symbol it came from. `journal` merges the engines' lossless
+//! journals for the journaler.
+
+use crate::engine::matcher::{EngineBuilder, EngineEvent, EngineHandle, SequencedEvent};
+use crate::engine::registry::SymbolRegistry;
+use crate::engine::snapshot::EngineSnapshot;
+use futures::stream::{self, BoxStream, StreamExt};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+
+/// Engine handles by symbol, with the symbol served at the unprefixed paths
+#[derive(Clone, Default)]
+pub struct EngineRegistry {
+    engines: BTreeMap<String, Arc<EngineHandle>>,
+    default_symbol: Option<String>,
+}
+
+impl EngineRegistry {
+    /// A registry of one engine, which is also the default
+    pub fn single(handle: Arc<EngineHandle>) -> Self {
+        let mut registry = Self::default();
+        registry.insert(handle);
+        registry
+    }
+
+    /// Build and spawn an engine for every configured symbol. `configure`
+    /// applies the options shared by all of them, such as risk limits or
+    /// the clock.
+    pub fn start(symbols: &SymbolRegistry, configure: impl Fn(EngineBuilder) -> EngineBuilder) -> Self {
+        let mut registry = Self::default();
+        for config in symbols.iter() {
+            let (engine, handle) = configure(EngineBuilder::from_config(config.clone())).build();
+            tokio::spawn(engine.run());
+            registry.insert(Arc::new(handle));
+        }
+        registry
+    }
+
+    /// `start`, with every engine supervised: one that panics is replaced by
+    /// an engine `configure` builds again, so `configure` should restore the
+    /// symbol's latest snapshot and log each time it is called
+    pub fn start_supervised(
+        symbols: &SymbolRegistry,
+        configure: impl Fn(EngineBuilder) -> EngineBuilder + Send + Sync + 'static,
+    ) -> Self {
+        let configure = Arc::new(configure);
+        let mut registry = Self::default();
+        for config in symbols.iter() {
+            let rebuild = {
+                let (configure, config) = (configure.clone(), config.clone());
+                move || configure(EngineBuilder::from_config(config.clone()))
+            };
+            let (engine, handle) = configure(EngineBuilder::from_config(config.clone())).recover_with(rebuild).build();
+            tokio::spawn(engine.run());
+            registry.insert(Arc::new(handle));
+        }
+        registry
+    }
+
+    /// Add or replace the engine for the handle's symbol. The first engine
+    /// added becomes the default.
+    pub fn insert(&mut self, handle: Arc<EngineHandle>) {
+        let symbol = handle.config.symbol.clone();
+        self.default_symbol.get_or_insert_with(|| symbol.clone());
+        self.engines.insert(symbol, handle);
+    }
+
+    /// Serve `symbol` at the unprefixed paths; false if it has no engine
+    pub fn set_default(&mut self, symbol: &str) -> bool {
+        let known = self.engines.contains_key(symbol);
+        if known {
+            self.default_symbol = Some(symbol.to_string());
+        }
+        known
+    }
+
+    /// The engine for `symbol`
+    pub fn get(&self, symbol: &str) -> Option<&Arc<EngineHandle>> {
+        self.engines.get(symbol)
+    }
+
+    /// The engine served at the unprefixed paths; `None` only when empty
+    pub fn default_engine(&self) -> Option<&Arc<EngineHandle>> {
+        self.default_symbol.as_deref().and_then(|symbol| self.get(symbol))
+    }
+
+    /// Every engine, in symbol order
+    pub fn iter(&self) -> impl Iterator<Item = &Arc<EngineHandle>> {
+        self.engines.values()
+    }
+
+    /// Events of every engine, interleaved as they arrive. A lagging
+    /// engine stream reports `Lagged` and carries on; the merged stream
+    /// ends once every engine has sent `Stopped`.
+    pub fn subscribe(&self) -> BoxStream<'static, Result<SequencedEvent, RecvError>> {
+        let streams = self.iter().map(|handle| {
+            stream::unfold(Some(handle.subscribe()), |events| async move {
+                let mut events = events?;
+                match events.recv().await {
+                    Err(RecvError::Closed) => None,
+                    Ok(last @ SequencedEvent {
+                        event: EngineEvent::Stopped,
+                        ..
+                    }) => Some((Ok(last), None)),
+                    result => Some((result, Some(events))),
+                }
+            })
+            .boxed()
+        });
+        stream::select_all(streams).boxed()
+    }
+
+    /// Every engine's journal, interleaved as events arrive, ending once
+    /// every engine has stopped. Takes each journal, so there is one
+    /// journaler; engines built without a journal, or whose journal is
+    /// already taken, are left out.
+    pub fn journal(&self) -> BoxStream<'static, SequencedEvent> {
+        let journals = self.iter().filter_map(|handle| {
+            let journal = handle.take_journal();
+            if journal.is_none() {
+                tracing::warn!(symbol = %handle.config.symbol, "Engine has no journal to take; its trades go unjournaled");
+            }
+            journal
+        });
+        let streams = journals.map(|journal| {
+            stream::unfold(journal, |mut journal| async move { Some((journal.recv().await?, journal)) }).boxed()
+        });
+        stream::select_all(streams).boxed()
+    }
+
+    /// Shut every engine down, each after the commands already queued for
+    /// it, and wait until all have stopped. Returns the final state of
+    /// each engine that was still running.
+    pub async fn shutdown(&self) -> Vec<EngineSnapshot> {
+        let stopped = futures::future::join_all(self.iter().map(|handle| handle.shutdown())).await;
+        stopped.into_iter().flatten().collect()
+    }
+}
+
+/// Path segment naming a symbol in URLs: "BTC/USD" is served under
+/// `/api/BTC-USD/...`
+pub fn url_key(symbol: &str) -> String {
+    symbol.replace('/', "-")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::order::{OrderRequest, Side};
+    use crate::engine::scratch::ScratchDir;
+    use crate::engine::SymbolConfig;
+    use rust_decimal_macros::dec;
+    use tokio::time::{timeout, Duration};
+
+    fn two_symbols() -> SymbolRegistry {
+        let mut symbols = SymbolRegistry::defaults();
+        symbols.insert(SymbolConfig::new("ETH/USD"));
+        symbols
+    }
+
+    #[tokio::test]
+    async fn test_one_engine_per_configured_symbol() {
+        let mut registry = EngineRegistry::start(&two_symbols(), |builder| builder.max_open_orders(5));
+        assert_eq!(registry.iter().map(|h| h.config.symbol.as_str()).collect::<Vec<_>>(), ["BTC/USD", "ETH/USD"]);
+        assert_eq!(registry.get("BTC/USD").unwrap().config.tick_size, Some(dec!(0.01)));
+        assert!(registry.get("DOGE/USD").is_none());
+
+        // Symbol order decides the first default
+        assert_eq!(registry.default_engine().unwrap().config.symbol, "BTC/USD");
+        assert!(registry.set_default("ETH/USD"));
+        assert!(!registry.set_default("DOGE/USD"));
+        assert_eq!(registry.default_engine().unwrap().config.symbol, "ETH/USD");
+    }
+
+    #[tokio::test]
+    async fn test_merged_stream_tells_symbols_apart() {
+        let registry = EngineRegistry::start(&two_symbols(), |builder| builder);
+        let mut events = registry.subscribe();
+
+        for symbol in ["ETH/USD", "BTC/USD"] {
+            let handle = registry.get(symbol).unwrap();
+            // Confirmed, so the maker's book update is sent before the taker arrives
+            handle.submit_order_confirmed(OrderRequest::new(Side::Sell, dec!(100), dec!(1))).await.unwrap().unwrap();
+            handle.submit_order(OrderRequest::new(Side::Buy, dec!(100), dec!(1))).await.unwrap();
+        }
+
+        let mut trades = Vec::new();
+        while trades.len() < 2 {
+            let event = timeout(Duration::from_millis(500), events.next()).await.unwrap().unwrap().unwrap();
+            if let EngineEvent::Trade(_) = event.event {
+                trades.push((event.symbol.to_string(), event.seq));
+            }
+        }
+        trades.sort();
+        // Each engine numbers its own events: the maker's acceptance, resting
+        // and book update, then the taker's acceptance and trade
+        assert_eq!(trades, [("BTC/USD".to_string(), 5), ("ETH/USD".to_string(), 5)]);
+    }
+
+    #[tokio::test]
+    async fn test_merged_stream_ends_after_every_engine_stops() {
+        let registry = EngineRegistry::start(&two_symbols(), |builder| builder);
+        let mut events = registry.subscribe();
+        let btc = registry.get("BTC/USD").unwrap();
+        btc.submit_order(OrderRequest::new(Side::Sell, dec!(100), dec!(1))).await.unwrap();
+        registry.shutdown().await;
+
+        let mut stopped = Vec::new();
+        while let Some(event) = timeout(Duration::from_millis(500), events.next()).await.unwrap() {
+            let event = event.unwrap();
+            if let EngineEvent::Stopped = event.event {
+                stopped.push(event.symbol.to_string());
+            }
+        }
+        stopped.sort();
+        assert_eq!(stopped, ["BTC/USD", "ETH/USD"]);
+        assert!(btc.submit_order(OrderRequest::new(Side::Sell, dec!(100), dec!(1))).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_journal_keeps_every_trade_the_broadcast_drops() {
+        let dir = ScratchDir::new("engines-journal");
+        let registry = EngineRegistry::start(&SymbolRegistry::defaults(), |builder| {
+            builder.event_buffer_size(4).journal(dir.to_path_buf())
+        });
+        let mut market = registry.subscribe();
+        let mut journal = registry.journal();
+        assert!(registry.journal().next().await.is_none(), "the journal is taken once");
+
+        let btc = registry.get("BTC/USD").unwrap();
+        for _ in 0..20 {
+            btc.submit_order(OrderRequest::new(Side::Sell, dec!(100), dec!(1))).await.unwrap();
+            btc.submit_order(OrderRequest::new(Side::Buy, dec!(100), dec!(1))).await.unwrap();
+        }
+        registry.shutdown().await;
+
+        assert!(matches!(market.next().await, Some(Err(RecvError::Lagged(_)))));
+        let mut trades = 0;
+        while let Some(event) = timeout(Duration::from_millis(500), journal.next()).await.unwrap() {
+            assert!(!matches!(event.event, EngineEvent::OrderBookUpdate(_) | EngineEvent::Stopped));
+            trades += matches!(event.event, EngineEvent::Trade(_)) as usize;
+        }
+        assert_eq!(trades, 20);
+    }
+
+    #[test]
+    fn test_url_key_replaces_slashes() {
+        assert_eq!(url_key("BTC/USD"), "BTC-USD");
+        assert_eq!(url_key("SPX"), "SPX");
+    }
+}
@@ -0,0 +1,30 @@
+//! Background sweeper that asks the engine to drop expired orders.
+//!
+//! The engine already expires orders lazily before every submission, and
+//! sweeps its book every tick. The sweeper adds sweeps of its own, and one
+//! as soon as a manual clock is moved, so tests see expiries without
+//! waiting for the next tick.
+
+use crate::engine::clock;
+use crate::engine::matcher::{EngineCommand, EngineHandle};
+use std::time::Duration;
+
+/// Send `ExpireOrders` to the engine every `every`, and immediately whenever
+/// a manual clock is moved. Exits once the engine stops accepting commands.
+pub async fn run_expiry_sweeper(handle: EngineHandle, every: Duration) {
+    let mut interval = tokio::time::interval(every);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let mut clock_changes = handle.clock.subscribe();
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = clock::changed(&mut clock_changes) => {}
+        }
+
+        if handle.command_tx.send(EngineCommand::ExpireOrders).await.is_err() {
+            tracing::debug!("Engine closed, expiry sweeper exiting");
+            break;
+        }
+    }
+}
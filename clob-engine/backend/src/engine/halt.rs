@@ -0,0 +1,151 @@
+//! Circuit breaker that halts matching after a rapid price move.
+
+use chrono::{DateTime, Duration, Utc};
+use rust_decimal::Decimal;
+use std::collections::VecDeque;
+
+/// What happens to orders that arrive while trading is halted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HaltPolicy {
+    /// Refuse them with a `TradingHalted` rejection
+    #[default]
+    Reject,
+    /// Hold them and match them in arrival order once trading resumes
+    Queue,
+}
+
+/// Circuit breaker settings
+#[derive(Debug, Clone)]
+pub struct CircuitBreakerConfig {
+    /// Largest allowed high/low range within the window, in percent of the low
+    pub max_move_pct: Decimal,
+    /// Rolling window the move is measured over
+    pub window: Duration,
+    /// How long trading stays halted once tripped
+    pub cooldown: Duration,
+    /// Treatment of orders arriving during a halt
+    pub policy: HaltPolicy,
+}
+
+/// Tracks recent trade prices and the halt state
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    config: CircuitBreakerConfig,
+    /// Trade prints inside the window, oldest first
+    recent: VecDeque<(DateTime<Utc>, Decimal)>,
+    halted_until: Option<DateTime<Utc>>,
+}
+
+impl CircuitBreaker {
+    pub fn new(config: CircuitBreakerConfig) -> Self {
+        Self {
+            config,
+            recent: VecDeque::new(),
+            halted_until: None,
+        }
+    }
+
+    pub fn policy(&self) -> HaltPolicy {
+        self.config.policy
+    }
+
+    /// Whether trading is halted at `now`
+    pub fn is_halted(&self, now: DateTime<Utc>) -> bool {
+        self.halted_until.is_some_and(|until| now < until)
+    }
+
+    /// End of the current halt, if tripped and not yet resumed
+    pub fn halted_until(&self) -> Option<DateTime<Utc>> {
+        self.halted_until
+    }
+
+    /// Halt until `until`, as when restoring a halted engine
+    pub fn halt_until(&mut self, until: DateTime<Utc>) {
+        self.halted_until = Some(until);
+    }
+
+    /// End a halt whose cooldown has elapsed. Returns true if trading just resumed.
+    pub fn try_resume(&mut self, now: DateTime<Utc>) -> bool {
+        match self.halted_until {
+            Some(until) if now >= until => {
+                self.halted_until = None;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Record a trade print. Returns the end of the halt if this trade tripped the breaker.
+    pub fn record_trade(&mut self, at: DateTime<Utc>, price: Decimal) -> Option<DateTime<Utc>> {
+        self.recent.push_back((at, price));
+        while self.recent.front().is_some_and(|(t, _)| *t < at - self.config.window) {
+            self.recent.pop_front();
+        }
+
+        let (low, high) = self
+            .recent
+            .iter()
+            .fold((price, price), |(low, high), (_, p)| (low.min(*p), high.max(*p)));
+        if (high - low) * Decimal::ONE_HUNDRED <= self.config.max_move_pct * low {
+            return None;
+        }
+
+        // Start the next window fresh so old prints can't re-trip after resuming
+        self.recent.clear();
+        let until = at + self.config.cooldown;
+        self.halted_until = Some(until);
+        Some(until)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use rust_decimal_macros::dec;
+
+    fn breaker() -> CircuitBreaker {
+        CircuitBreaker::new(CircuitBreakerConfig {
+            max_move_pct: dec!(5),
+            window: Duration::seconds(10),
+            cooldown: Duration::seconds(30),
+            policy: HaltPolicy::Reject,
+        })
+    }
+
+    #[test]
+    fn test_trips_on_move_within_window() {
+        let start = Utc.with_ymd_and_hms(2030, 1, 1, 0, 0, 0).unwrap();
+        let mut breaker = breaker();
+
+        assert!(breaker.record_trade(start, dec!(100)).is_none());
+        assert!(breaker.record_trade(start + Duration::seconds(5), dec!(105)).is_none());
+        let until = breaker.record_trade(start + Duration::seconds(6), dec!(105.01));
+
+        assert_eq!(until, Some(start + Duration::seconds(36)));
+        assert!(breaker.is_halted(start + Duration::seconds(35)));
+        assert!(!breaker.try_resume(start + Duration::seconds(35)));
+        assert!(breaker.try_resume(start + Duration::seconds(36)));
+        assert!(!breaker.is_halted(start + Duration::seconds(36)));
+    }
+
+    #[test]
+    fn test_old_prints_leave_the_window() {
+        let start = Utc.with_ymd_and_hms(2030, 1, 1, 0, 0, 0).unwrap();
+        let mut breaker = breaker();
+
+        breaker.record_trade(start, dec!(100));
+        // Same move, but spread over more than the window
+        assert!(breaker.record_trade(start + Duration::seconds(11), dec!(110)).is_none());
+        assert!(!breaker.is_halted(start + Duration::seconds(11)));
+    }
+
+    #[test]
+    fn test_falling_price_trips() {
+        let start = Utc.with_ymd_and_hms(2030, 1, 1, 0, 0, 0).unwrap();
+        let mut breaker = breaker();
+
+        breaker.record_trade(start, dec!(100));
+        assert!(breaker.record_trade(start, dec!(94)).is_some());
+    }
+}
@@ -0,0 +1,192 @@
+//! The ids the engine assigns, and the UUIDs they are known by outside it.
+//!
+//! The engine numbers orders and trades itself: orders count up from 1 in
+//! [`OrderIds`], which engine handles draw from before queuing an order so
+//! its submitter learns the id at once, and trades count up from 1 in the
+//! engine, so ids cost nothing to draw and index as plain `u64`s. An
+//! order's id is not its time priority; the engine keeps a separate
+//! arrival sequence for that. Clients see UUIDs instead, made from an
+//! id and the engine's epoch, a random number drawn when the engine first
+//! starts and carried across restarts by its snapshots and log. Engines
+//! never hand out the same UUID, nor does one started afresh, and a UUID
+//! converts back to its id without a lookup. [`SeededIds`] draws the same
+//! epoch on every run, for replaying the same input to the same output.
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Version nibble of a UUID's first half
+const VERSION_BITS: u64 = 0xF000;
+/// Version 8: a UUID laid out by its issuer
+const VERSION_8: u64 = 0x8000;
+/// Variant bits of a UUID's second half, `10` for RFC 9562
+const VARIANT: u64 = 1 << 63;
+/// Bits of an id a UUID carries
+const ID_BITS: u64 = (1 << 62) - 1;
+
+/// An engine's epoch, which turns its ids into UUIDs: version 8 UUIDs with
+/// the epoch in the first half and the id in the second
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "u64", into = "u64")]
+pub struct IdEpoch(u64);
+
+impl IdEpoch {
+    /// The epoch made from `bits`, less the four a UUID's version takes
+    pub fn new(bits: u64) -> Self {
+        Self((bits & !VERSION_BITS) | VERSION_8)
+    }
+
+    /// The UUID id `id` is known by outside the engine
+    pub fn uuid(&self, id: u64) -> Uuid {
+        Uuid::from_u64_pair(self.0, (id & ID_BITS) | VARIANT)
+    }
+
+    /// The id `uuid` stands for; `None` if it was not made by this epoch
+    pub fn id(&self, uuid: Uuid) -> Option<u64> {
+        let (high, low) = uuid.as_u64_pair();
+        (high == self.0 && low & !ID_BITS == VARIANT).then_some(low & ID_BITS)
+    }
+
+    /// The id `uuid` stands for, or 0, which no order or trade has, so a
+    /// command naming a UUID from elsewhere is refused as unknown
+    pub fn lookup(&self, uuid: Uuid) -> u64 {
+        self.id(uuid).unwrap_or(0)
+    }
+}
+
+impl From<u64> for IdEpoch {
+    fn from(bits: u64) -> Self {
+        Self::new(bits)
+    }
+}
+
+impl From<IdEpoch> for u64 {
+    fn from(epoch: IdEpoch) -> Self {
+        epoch.0
+    }
+}
+
+/// Order ids yet to be given out, shared by an engine and its handles. Ids
+/// are unique but only roughly in arrival order: a handle may reserve one
+/// and queue its order after another handle's.
+#[derive(Debug, Clone)]
+pub struct OrderIds(Arc<AtomicU64>);
+
+impl OrderIds {
+    /// Ids from `next` on
+    pub fn new(next: u64) -> Self {
+        Self(Arc::new(AtomicU64::new(next.max(1))))
+    }
+
+    /// Take the next id
+    pub fn reserve(&self) -> u64 {
+        self.0.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// The id `reserve` would take next
+    pub fn peek(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Never give out `id`, or any id before it, again
+    pub fn reserve_through(&self, id: u64) {
+        self.0.fetch_max(id + 1, Ordering::Relaxed);
+    }
+}
+
+impl Default for OrderIds {
+    fn default() -> Self {
+        Self::new(1)
+    }
+}
+
+/// A source of epochs for engines starting afresh
+pub trait IdSource: Send + std::fmt::Debug {
+    /// Epoch for the next engine
+    fn epoch(&mut self) -> IdEpoch;
+}
+
+/// Random epochs, so UUIDs never repeat across runs
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RandomIds;
+
+impl IdSource for RandomIds {
+    fn epoch(&mut self) -> IdEpoch {
+        IdEpoch::new(rand::random())
+    }
+}
+
+/// Epochs drawn from a generator seeded with a fixed number: the same
+/// UUIDs on every run with the same seed
+#[derive(Debug, Clone)]
+pub struct SeededIds {
+    rng: StdRng,
+}
+
+impl SeededIds {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl IdSource for SeededIds {
+    fn epoch(&mut self) -> IdEpoch {
+        IdEpoch::new(self.rng.gen())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seeded_epochs_repeat_for_the_same_seed() {
+        let draw = |seed| {
+            let mut ids = SeededIds::new(seed);
+            (0..3).map(|_| ids.epoch()).collect::<Vec<_>>()
+        };
+        assert_eq!(draw(7), draw(7));
+        assert_ne!(draw(7), draw(8));
+    }
+
+    #[test]
+    fn test_order_ids_are_shared_and_never_reissued() {
+        let ids = OrderIds::default();
+        let handle = ids.clone();
+        assert_eq!((ids.reserve(), handle.reserve()), (1, 2));
+        ids.reserve_through(9);
+        assert_eq!(handle.peek(), 10);
+        handle.reserve_through(3);
+        assert_eq!(ids.reserve(), 10);
+    }
+
+    #[test]
+    fn test_uuids_convert_back_to_their_ids() {
+        let epoch = RandomIds.epoch();
+        for id in [1, 42, ID_BITS] {
+            let uuid = epoch.uuid(id);
+            assert_eq!(uuid.get_version_num(), 8);
+            assert_eq!(uuid.get_variant(), uuid::Variant::RFC4122);
+            assert_eq!(epoch.id(uuid), Some(id));
+        }
+        assert_ne!(epoch.uuid(1), epoch.uuid(2));
+
+        // Another engine's UUIDs, and anyone else's, are not this one's ids
+        let other = IdEpoch::new(u64::from(epoch) ^ 1);
+        assert_ne!(other.uuid(1), epoch.uuid(1));
+        assert_eq!(epoch.id(other.uuid(1)), None);
+        assert_eq!(epoch.id(Uuid::new_v4()), None);
+        assert_eq!(epoch.lookup(Uuid::new_v4()), 0);
+
+        // Saved as a plain number, and normalized on the way back in
+        let saved = serde_json::to_string(&epoch).unwrap();
+        assert_eq!(serde_json::from_str::<IdEpoch>(&saved).unwrap(), epoch);
+        assert_eq!(serde_json::from_str::<IdEpoch>("0").unwrap(), IdEpoch::new(0));
+    }
+}
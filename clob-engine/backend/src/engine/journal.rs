@@ -0,0 +1,478 @@
+//! The lossless feed of the events the trade journaler records.
+//!
+//! Market data goes out on the engine's broadcast channel, where a slow
+//! subscriber falls behind and loses events rather than hold the engine
+//! up. The journaler cannot lose a trade, so an engine built with a journal
+//! also sends it every trade, bust and order lifecycle event on a bounded
+//! channel of its own, where a slow journaler holds the engine back.
+//!
+//! When the channel is full the engine waits up to `JOURNAL_BLOCK` for
+//! room, then spills the event to a file beside its snapshots, and keeps
+//! spilling until the journaler has caught up with the channel and read the
+//! file back. Events come out in the order they were sent either way. A
+//! spill left by a process that stopped before the journaler read it is
+//! delivered first on the next start.
+
+use crate::engine::engines::url_key;
+use crate::engine::matcher::{CancelReason, EngineEvent, RejectReason, SequencedEvent};
+use crate::engine::order::{OrderId, Side, Trade};
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Events the journal channel holds before the engine has to wait
+pub const JOURNAL_CAPACITY: usize = 10_000;
+
+/// Longest the engine waits for room in a full journal channel before
+/// spilling to disk
+pub const JOURNAL_BLOCK: Duration = Duration::from_millis(10);
+
+/// Whether the journaler records `event`
+pub fn is_journaled(event: &EngineEvent) -> bool {
+    matches!(
+        event,
+        EngineEvent::Trade(_)
+            | EngineEvent::TradeBusted(_)
+            | EngineEvent::OrderAccepted { .. }
+            | EngineEvent::OrderRested { .. }
+            | EngineEvent::OrderRejected { .. }
+            | EngineEvent::OrderCancelled { .. }
+            | EngineEvent::OrderModified { .. }
+    )
+}
+
+/// Path of the spill file for `symbol`'s journal in `dir`
+pub fn spill_path(dir: &Path, symbol: &str) -> PathBuf {
+    dir.join(format!("{}.journal-spill", url_key(symbol)))
+}
+
+/// The two ends of `symbol`'s journal, spilling to a file in `dir` once
+/// `capacity` events are waiting
+pub fn journal_channel(dir: &Path, symbol: &str, capacity: usize) -> (JournalSender, JournalReceiver) {
+    let (tx, rx) = flume::bounded(capacity);
+    let path = spill_path(dir, symbol);
+    // Anything a previous run spilled is delivered ahead of this run's events
+    let left_over = std::fs::metadata(&path).is_ok_and(|meta| meta.len() > 0);
+    let spill = Arc::new(Spill {
+        spilling: AtomicBool::new(left_over),
+        file: Mutex::new(None),
+        path,
+    });
+    let symbol: Arc<str> = symbol.into();
+    (
+        JournalSender {
+            tx,
+            spill: spill.clone(),
+        },
+        JournalReceiver {
+            rx,
+            spill,
+            symbol,
+            unspilled: VecDeque::new(),
+        },
+    )
+}
+
+/// Events sent while the channel was full, waiting on disk
+#[derive(Debug)]
+struct Spill {
+    /// Set while the file holds events the journaler has not read. The
+    /// engine sends nothing on the channel meanwhile, so nothing overtakes
+    /// them.
+    spilling: AtomicBool,
+    /// Open for appending while spilling
+    file: Mutex<Option<File>>,
+    path: PathBuf,
+}
+
+/// The engine's end of its journal. An engine is the only sender on its
+/// journal, which is what keeps spilled events in order.
+#[derive(Debug)]
+pub struct JournalSender {
+    tx: flume::Sender<SequencedEvent>,
+    spill: Arc<Spill>,
+}
+
+impl JournalSender {
+    /// Send `event` to the journaler, waiting briefly for room in the
+    /// channel, else spilling it to disk
+    pub fn send(&self, event: SequencedEvent) {
+        let event = if self.spill.spilling.load(Ordering::Acquire) {
+            event
+        } else {
+            match self.tx.send_timeout(event, JOURNAL_BLOCK) {
+                Ok(()) => return,
+                Err(flume::SendTimeoutError::Timeout(event)) => event,
+                // No journaler left to lose it to
+                Err(flume::SendTimeoutError::Disconnected(_)) => return,
+            }
+        };
+        self.spill_event(&event);
+    }
+
+    fn spill_event(&self, event: &SequencedEvent) {
+        let Some(spilled) = Spilled::of(event) else {
+            return;
+        };
+        let mut file = self.spill.file.lock().unwrap();
+        if file.is_none() {
+            tracing::warn!(path = %self.spill.path.display(), "Journal channel full, spilling to disk");
+        }
+        let written = match &mut *file {
+            Some(file) => Ok(file),
+            None => OpenOptions::new().create(true).append(true).open(&self.spill.path).map(|f| file.insert(f)),
+        }
+        .and_then(|file| {
+            let mut line = serde_json::to_vec(&spilled)?;
+            line.push(b'\n');
+            file.write_all(&line)
+        });
+        match written {
+            Ok(()) => self.spill.spilling.store(true, Ordering::Release),
+            Err(e) => tracing::error!(seq = event.seq, "Journal event lost, the spill file cannot be written: {e}"),
+        }
+    }
+}
+
+/// The journaler's end of an engine's journal
+#[derive(Debug)]
+pub struct JournalReceiver {
+    rx: flume::Receiver<SequencedEvent>,
+    spill: Arc<Spill>,
+    symbol: Arc<str>,
+    /// Events read back from the spill file, not yet handed out
+    unspilled: VecDeque<SequencedEvent>,
+}
+
+impl JournalReceiver {
+    /// The next event, in the order the engine sent them; `None` once the
+    /// engine has stopped and everything it sent has been received
+    pub async fn recv(&mut self) -> Option<SequencedEvent> {
+        loop {
+            if let Some(event) = self.unspilled.pop_front() {
+                return Some(event);
+            }
+            match self.rx.try_recv() {
+                Ok(event) => return Some(event),
+                Err(flume::TryRecvError::Empty | flume::TryRecvError::Disconnected)
+                    if self.spill.spilling.load(Ordering::Acquire) =>
+                {
+                    // The channel has caught up; what spilled comes next
+                    self.read_spill();
+                }
+                Err(flume::TryRecvError::Disconnected) => return None,
+                Err(flume::TryRecvError::Empty) => {
+                    if let Ok(event) = self.rx.recv_async().await {
+                        return Some(event);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Take every spilled event into `unspilled` and empty the file
+    fn read_spill(&mut self) {
+        let mut file = self.spill.file.lock().unwrap();
+        let contents = std::fs::read_to_string(&self.spill.path).unwrap_or_else(|e| {
+            tracing::error!(path = %self.spill.path.display(), "Spilled journal events lost, unreadable: {e}");
+            String::new()
+        });
+        for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+            match serde_json::from_str::<Spilled>(line) {
+                Ok(spilled) => self.unspilled.push_back(spilled.into_event(self.symbol.clone())),
+                Err(e) => tracing::error!("Skipping unreadable spilled journal event: {e}"),
+            }
+        }
+        *file = None;
+        if let Err(e) = File::create(&self.spill.path) {
+            tracing::error!(path = %self.spill.path.display(), "Spill file not emptied: {e}");
+        }
+        self.spill.spilling.store(false, Ordering::Release);
+        tracing::info!(events = self.unspilled.len(), "Journal caught up with its spill file");
+    }
+}
+
+/// A journaled event as written to the spill file
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Spilled {
+    Trade {
+        seq: u64,
+        trade: Trade,
+    },
+    TradeBusted {
+        seq: u64,
+        trade: Trade,
+    },
+    OrderAccepted {
+        seq: u64,
+        order_id: OrderId,
+        client_order_id: Option<String>,
+        owner_id: Option<String>,
+        side: Side,
+        price: Decimal,
+        quantity: Decimal,
+        timestamp: DateTime<Utc>,
+    },
+    OrderRested {
+        seq: u64,
+        order_id: OrderId,
+        price: Decimal,
+        remaining_quantity: Decimal,
+    },
+    OrderRejected {
+        seq: u64,
+        order_id: OrderId,
+        client_order_id: Option<String>,
+        reason: RejectReason,
+    },
+    OrderCancelled {
+        seq: u64,
+        order_id: OrderId,
+        remaining_quantity: Decimal,
+        reason: CancelReason,
+    },
+    OrderModified {
+        seq: u64,
+        order_id: OrderId,
+        new_order_id: OrderId,
+        price: Decimal,
+        remaining_quantity: Decimal,
+    },
+}
+
+impl Spilled {
+    fn of(event: &SequencedEvent) -> Option<Self> {
+        let seq = event.seq;
+        Some(match &event.event {
+            EngineEvent::Trade(trade) => Self::Trade {
+                seq,
+                trade: Trade::clone(trade),
+            },
+            EngineEvent::TradeBusted(trade) => Self::TradeBusted {
+                seq,
+                trade: Trade::clone(trade),
+            },
+            EngineEvent::OrderAccepted {
+                order_id,
+                client_order_id,
+                owner_id,
+                side,
+                price,
+                quantity,
+                timestamp,
+            } => Self::OrderAccepted {
+                seq,
+                order_id: *order_id,
+                client_order_id: client_order_id.clone(),
+                owner_id: owner_id.clone(),
+                side: *side,
+                price: *price,
+                quantity: *quantity,
+                timestamp: *timestamp,
+            },
+            EngineEvent::OrderRested {
+                order_id,
+                price,
+                remaining_quantity,
+            } => Self::OrderRested {
+                seq,
+                order_id: *order_id,
+                price: *price,
+                remaining_quantity: *remaining_quantity,
+            },
+            EngineEvent::OrderRejected {
+                order_id,
+                client_order_id,
+                reason,
+            } => Self::OrderRejected {
+                seq,
+                order_id: *order_id,
+                client_order_id: client_order_id.clone(),
+                reason: reason.clone(),
+            },
+            EngineEvent::OrderCancelled {
+                order_id,
+                remaining_quantity,
+                reason,
+            } => Self::OrderCancelled {
+                seq,
+                order_id: *order_id,
+                remaining_quantity: *remaining_quantity,
+                reason: *reason,
+            },
+            EngineEvent::OrderModified {
+                order_id,
+                new_order_id,
+                price,
+                remaining_quantity,
+            } => Self::OrderModified {
+                seq,
+                order_id: *order_id,
+                new_order_id: *new_order_id,
+                price: *price,
+                remaining_quantity: *remaining_quantity,
+            },
+            _ => return None,
+        })
+    }
+
+    fn into_event(self, symbol: Arc<str>) -> SequencedEvent {
+        let (seq, event) = match self {
+            Self::Trade { seq, trade } => (seq, EngineEvent::Trade(Arc::new(trade))),
+            Self::TradeBusted { seq, trade } => (seq, EngineEvent::TradeBusted(Arc::new(trade))),
+            Self::OrderAccepted {
+                seq,
+                order_id,
+                client_order_id,
+                owner_id,
+                side,
+                price,
+                quantity,
+                timestamp,
+            } => (
+                seq,
+                EngineEvent::OrderAccepted {
+                    order_id,
+                    client_order_id,
+                    owner_id,
+                    side,
+                    price,
+                    quantity,
+                    timestamp,
+                },
+            ),
+            Self::OrderRested {
+                seq,
+                order_id,
+                price,
+                remaining_quantity,
+            } => (
+                seq,
+                EngineEvent::OrderRested {
+                    order_id,
+                    price,
+                    remaining_quantity,
+                },
+            ),
+            Self::OrderRejected {
+                seq,
+                order_id,
+                client_order_id,
+                reason,
+            } => (
+                seq,
+                EngineEvent::OrderRejected {
+                    order_id,
+                    client_order_id,
+                    reason,
+                },
+            ),
+            Self::OrderCancelled {
+                seq,
+                order_id,
+                remaining_quantity,
+                reason,
+            } => (
+                seq,
+                EngineEvent::OrderCancelled {
+                    order_id,
+                    remaining_quantity,
+                    reason,
+                },
+            ),
+            Self::OrderModified {
+                seq,
+                order_id,
+                new_order_id,
+                price,
+                remaining_quantity,
+            } => (
+                seq,
+                EngineEvent::OrderModified {
+                    order_id,
+                    new_order_id,
+                    price,
+                    remaining_quantity,
+                },
+            ),
+        };
+        SequencedEvent { seq, symbol, event }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::scratch::ScratchDir;
+    use rust_decimal_macros::dec;
+
+    fn rested(seq: u64) -> SequencedEvent {
+        SequencedEvent {
+            seq,
+            symbol: "BTC/USD".into(),
+            event: EngineEvent::OrderRested {
+                order_id: seq,
+                price: dec!(100),
+                remaining_quantity: dec!(1),
+            },
+        }
+    }
+
+    async fn received(journal: &mut JournalReceiver, count: usize) -> Vec<u64> {
+        let mut seqs = Vec::new();
+        for _ in 0..count {
+            seqs.push(journal.recv().await.unwrap().seq);
+        }
+        seqs
+    }
+
+    #[tokio::test]
+    async fn test_full_channel_spills_and_keeps_order() {
+        let dir = ScratchDir::new("journal-spill");
+        let (tx, mut rx) = journal_channel(&dir, "BTC/USD", 2);
+
+        for seq in 1..=5 {
+            tx.send(rested(seq));
+        }
+        assert!(std::fs::metadata(spill_path(&dir, "BTC/USD")).unwrap().len() > 0);
+        // Room in the channel again, but spilled events are not overtaken
+        assert_eq!(received(&mut rx, 1).await, [1]);
+        tx.send(rested(6));
+        assert_eq!(received(&mut rx, 5).await, [2, 3, 4, 5, 6]);
+
+        // Once caught up, events go on the channel again
+        tx.send(rested(7));
+        assert_eq!(std::fs::metadata(spill_path(&dir, "BTC/USD")).unwrap().len(), 0);
+        drop(tx);
+        assert_eq!(received(&mut rx, 1).await, [7]);
+        assert!(rx.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_spill_left_by_a_previous_run_comes_first() {
+        let dir = ScratchDir::new("journal-left-over");
+        let (tx, rx) = journal_channel(&dir, "BTC/USD", 1);
+        for seq in 1..=3 {
+            tx.send(rested(seq));
+        }
+        drop((tx, rx));
+
+        let (tx, mut rx) = journal_channel(&dir, "BTC/USD", 1);
+        tx.send(rested(4));
+        drop(tx);
+        let mut seqs = Vec::new();
+        while let Some(event) = rx.recv().await {
+            seqs.push(event.seq);
+        }
+        // The first run's channel held 1 when it stopped
+        assert_eq!(seqs, [2, 3, 4]);
+    }
+}
@@ -0,0 +1,486 @@
+//! One side of the book: a dense window of levels around the touch, with
+//! `BTreeMap`s for prices outside it.
+//!
+//! Most activity happens within a few hundred ticks of the best price, so
+//! levels there live in a flat array indexed by their offset from the
+//! window's base, found through an occupancy bitmap. Everything else stays
+//! in one tree below the window and one above it. The window re-centres on
+//! the touch whenever the touch would otherwise fall outside it: when a
+//! level arrives beyond the best edge, or when the window empties while a
+//! tree still holds levels. So the tree on the touch side of the window is
+//! always empty, and walking from the touch reaches the window without a
+//! tree search.
+
+use crate::engine::order::Side;
+use crate::engine::ticks::Ticks;
+use std::collections::{btree_map, BTreeMap};
+use std::ops::{Bound, Index, RangeBounds};
+
+/// Ticks covered by the dense window
+pub const WINDOW_TICKS: usize = 1024;
+
+const WORDS: usize = WINDOW_TICKS / 64;
+
+/// Levels on one side of the book, keyed by price in ticks, iterated in
+/// ascending price order like a `BTreeMap`
+#[derive(Debug)]
+pub struct Ladder<V> {
+    /// Which end of the ladder is the touch: the highest price for bids,
+    /// the lowest for asks
+    side: Side,
+    /// Price of `slots[0]`
+    base: Ticks,
+    /// Levels at `base..base + WINDOW_TICKS`; a slot is only meaningful
+    /// while its bit in `occupied` is set
+    slots: Box<[V]>,
+    /// One bit per slot
+    occupied: [u64; WORDS],
+    /// Levels below the window
+    below: BTreeMap<Ticks, V>,
+    /// Levels above the window
+    above: BTreeMap<Ticks, V>,
+}
+
+impl<V: Default> Ladder<V> {
+    /// An empty ladder for `side`
+    pub fn new(side: Side) -> Self {
+        Self {
+            side,
+            base: 0,
+            slots: (0..WINDOW_TICKS).map(|_| V::default()).collect(),
+            occupied: [0; WORDS],
+            below: BTreeMap::new(),
+            above: BTreeMap::new(),
+        }
+    }
+
+    /// The level at `ticks`
+    pub fn get(&self, ticks: &Ticks) -> Option<&V> {
+        match self.slot(*ticks) {
+            Some(i) => self.is_occupied(i).then(|| &self.slots[i]),
+            None => self.tree(*ticks).get(ticks),
+        }
+    }
+
+    /// The level at `ticks`, mutably
+    pub fn get_mut(&mut self, ticks: &Ticks) -> Option<&mut V> {
+        match self.slot(*ticks) {
+            Some(i) => self.is_occupied(i).then(|| &mut self.slots[i]),
+            None => self.tree_mut(*ticks).get_mut(ticks),
+        }
+    }
+
+    /// The level at `ticks`, created with `make` if there is none
+    pub fn get_or_insert_with(&mut self, ticks: Ticks, make: impl FnOnce() -> V) -> &mut V {
+        if self.slot(ticks).is_none() && self.beyond_touch(ticks) {
+            self.recentre(ticks);
+        }
+        match self.slot(ticks) {
+            Some(i) => {
+                if !self.is_occupied(i) {
+                    self.slots[i] = make();
+                    self.occupied[i / 64] |= 1 << (i % 64);
+                }
+                &mut self.slots[i]
+            }
+            None => self.tree_mut(ticks).entry(ticks).or_insert_with(make),
+        }
+    }
+
+    /// Put `level` at `ticks`, replacing any level already there
+    pub fn insert(&mut self, ticks: Ticks, level: V) {
+        let mut level = Some(level);
+        let slot = self.get_or_insert_with(ticks, || level.take().unwrap_or_default());
+        if let Some(level) = level {
+            *slot = level;
+        }
+    }
+
+    /// Take the level at `ticks` off the ladder
+    pub fn remove(&mut self, ticks: &Ticks) -> Option<V> {
+        let Some(i) = self.slot(*ticks) else {
+            return self.tree_mut(*ticks).remove(ticks);
+        };
+        if !self.is_occupied(i) {
+            return None;
+        }
+        self.occupied[i / 64] &= !(1 << (i % 64));
+        let level = std::mem::take(&mut self.slots[i]);
+        self.follow_touch();
+        Some(level)
+    }
+
+    /// Keep only the levels `keep` returns true for
+    pub fn retain(&mut self, mut keep: impl FnMut(&Ticks, &mut V) -> bool) {
+        let occupied = self.occupied;
+        for i in SetBits::new(&occupied, 0, WINDOW_TICKS) {
+            if !keep(&(self.base + i as Ticks), &mut self.slots[i]) {
+                self.occupied[i / 64] &= !(1 << (i % 64));
+                self.slots[i] = V::default();
+            }
+        }
+        self.below.retain(|ticks, level| keep(ticks, level));
+        self.above.retain(|ticks, level| keep(ticks, level));
+        self.follow_touch();
+    }
+
+    /// Whether the ladder holds no levels
+    pub fn is_empty(&self) -> bool {
+        self.window_is_empty() && self.below.is_empty() && self.above.is_empty()
+    }
+
+    /// Number of levels
+    pub fn len(&self) -> usize {
+        self.occupied.iter().map(|w| w.count_ones() as usize).sum::<usize>() + self.below.len() + self.above.len()
+    }
+}
+
+impl<V> Ladder<V> {
+    /// Levels with prices in `range`, lowest first
+    pub fn range(&self, range: impl RangeBounds<Ticks>) -> impl DoubleEndedIterator<Item = (Ticks, &V)> + '_ {
+        // Inclusive bounds, widened so the arithmetic can't overflow
+        let lo = match range.start_bound() {
+            Bound::Included(&t) => t as i128,
+            Bound::Excluded(&t) => t as i128 + 1,
+            Bound::Unbounded => Ticks::MIN as i128,
+        };
+        let hi = match range.end_bound() {
+            Bound::Included(&t) => t as i128,
+            Bound::Excluded(&t) => t as i128 - 1,
+            Bound::Unbounded => Ticks::MAX as i128,
+        };
+        let (base, end) = (self.base as i128, self.base as i128 + WINDOW_TICKS as i128);
+
+        let below = span(&self.below, lo, hi.min(base - 1));
+        let above = span(&self.above, lo.max(end), hi);
+        let (first, last) = (lo.max(base), hi.min(end - 1));
+        let window = (first <= last)
+            .then(|| SetBits::new(&self.occupied, (first - base) as usize, (last - base + 1) as usize))
+            .into_iter()
+            .flatten()
+            .map(|i| (self.base + i as Ticks, &self.slots[i]));
+
+        below
+            .into_iter()
+            .flatten()
+            .map(|(&t, level)| (t, level))
+            .chain(window)
+            .chain(above.into_iter().flatten().map(|(&t, level)| (t, level)))
+    }
+
+    /// Every level, lowest price first. Unlike `range` nothing is searched
+    /// up front; each tree is only walked once iteration reaches it.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (Ticks, &V)> + '_ {
+        let window = SetBits::new(&self.occupied, 0, WINDOW_TICKS).map(|i| (self.base + i as Ticks, &self.slots[i]));
+        self.below
+            .iter()
+            .map(|(&t, level)| (t, level))
+            .chain(window)
+            .chain(self.above.iter().map(|(&t, level)| (t, level)))
+    }
+
+    /// Every price, lowest first
+    pub fn keys(&self) -> impl DoubleEndedIterator<Item = Ticks> + '_ {
+        self.iter().map(|(ticks, _)| ticks)
+    }
+
+    /// Every level, lowest price first
+    pub fn values(&self) -> impl DoubleEndedIterator<Item = &V> + '_ {
+        self.iter().map(|(_, level)| level)
+    }
+
+    /// The lowest price
+    pub fn first_key(&self) -> Option<Ticks> {
+        self.below
+            .first_key_value()
+            .map(|(&t, _)| t)
+            .or_else(|| SetBits::new(&self.occupied, 0, WINDOW_TICKS).next().map(|i| self.base + i as Ticks))
+            .or_else(|| self.above.first_key_value().map(|(&t, _)| t))
+    }
+
+    /// The highest price
+    pub fn last_key(&self) -> Option<Ticks> {
+        self.above
+            .last_key_value()
+            .map(|(&t, _)| t)
+            .or_else(|| SetBits::new(&self.occupied, 0, WINDOW_TICKS).next_back().map(|i| self.base + i as Ticks))
+            .or_else(|| self.below.last_key_value().map(|(&t, _)| t))
+    }
+
+    /// Whether every tree level lies on its own side of the window
+    pub fn is_consistent(&self) -> bool {
+        let end = self.base as i128 + WINDOW_TICKS as i128;
+        self.below.last_key_value().is_none_or(|(&ticks, _)| ticks < self.base)
+            && self.above.first_key_value().is_none_or(|(&ticks, _)| ticks as i128 >= end)
+    }
+
+    /// Offset of `ticks` into the window, if it falls inside
+    fn slot(&self, ticks: Ticks) -> Option<usize> {
+        let offset = (ticks as i128) - (self.base as i128);
+        (0..WINDOW_TICKS as i128).contains(&offset).then_some(offset as usize)
+    }
+
+    /// The tree a price outside the window belongs in
+    fn tree(&self, ticks: Ticks) -> &BTreeMap<Ticks, V> {
+        if ticks < self.base {
+            &self.below
+        } else {
+            &self.above
+        }
+    }
+
+    fn tree_mut(&mut self, ticks: Ticks) -> &mut BTreeMap<Ticks, V> {
+        if ticks < self.base {
+            &mut self.below
+        } else {
+            &mut self.above
+        }
+    }
+
+    fn is_occupied(&self, i: usize) -> bool {
+        self.occupied[i / 64] & (1 << (i % 64)) != 0
+    }
+
+    fn window_is_empty(&self) -> bool {
+        self.occupied.iter().all(|&w| w == 0)
+    }
+
+    /// Whether a new level at `ticks`, outside the window, would become the
+    /// touch: the window is empty, or `ticks` is past its best edge
+    fn beyond_touch(&self, ticks: Ticks) -> bool {
+        self.window_is_empty()
+            || match self.side {
+                Side::Buy => ticks > self.base,
+                Side::Sell => ticks < self.base,
+            }
+    }
+
+    /// The best price
+    fn touch(&self) -> Option<Ticks> {
+        match self.side {
+            Side::Buy => self.last_key(),
+            Side::Sell => self.first_key(),
+        }
+    }
+}
+
+impl<V: Default> Ladder<V> {
+    /// Re-centre on the touch once the window has emptied. While the window
+    /// holds a level the touch is always in it: anything beyond its best
+    /// edge re-centres on arrival.
+    fn follow_touch(&mut self) {
+        if self.window_is_empty() {
+            if let Some(touch) = self.touch() {
+                self.recentre(touch);
+            }
+        }
+    }
+
+    /// Move the window so `centre` sits in its middle, moving levels between
+    /// the window and the trees as they fall in or out of it
+    fn recentre(&mut self, centre: Ticks) {
+        let base = centre
+            .saturating_sub(WINDOW_TICKS as Ticks / 2)
+            .min(Ticks::MAX - WINDOW_TICKS as Ticks);
+        let end = base + WINDOW_TICKS as Ticks;
+
+        // Everything that may change home: the window, the top of the tree
+        // below and the bottom of the tree above. Keys are moved one by one
+        // rather than with `split_off`, which walks the half it splits off
+        // to count it.
+        let occupied = self.occupied;
+        let mut moving: Vec<(Ticks, V)> = SetBits::new(&occupied, 0, WINDOW_TICKS)
+            .map(|i| (self.base + i as Ticks, std::mem::take(&mut self.slots[i])))
+            .collect();
+        while let Some(entry) = self.below.last_entry().filter(|e| *e.key() >= base) {
+            moving.push(entry.remove_entry());
+        }
+        while let Some(entry) = self.above.first_entry().filter(|e| *e.key() < end) {
+            moving.push(entry.remove_entry());
+        }
+        self.occupied = [0; WORDS];
+        self.base = base;
+
+        for (ticks, level) in moving {
+            match self.slot(ticks) {
+                Some(i) => {
+                    self.slots[i] = level;
+                    self.occupied[i / 64] |= 1 << (i % 64);
+                }
+                None => {
+                    self.tree_mut(ticks).insert(ticks, level);
+                }
+            }
+        }
+    }
+}
+
+impl<V: Default> Index<&Ticks> for Ladder<V> {
+    type Output = V;
+
+    fn index(&self, ticks: &Ticks) -> &V {
+        self.get(ticks).expect("no level at this price")
+    }
+}
+
+/// Levels of `tree` at `lo..=hi`, skipping the search when there can be none
+fn span<V>(tree: &BTreeMap<Ticks, V>, lo: i128, hi: i128) -> Option<btree_map::Range<'_, Ticks, V>> {
+    (lo <= hi && !tree.is_empty()).then(|| tree.range(lo as Ticks..=hi as Ticks))
+}
+
+/// Positions of the set bits of a bitmap within `front..back`, from either end
+struct SetBits<'a> {
+    words: &'a [u64],
+    front: usize,
+    back: usize,
+}
+
+impl<'a> SetBits<'a> {
+    fn new(words: &'a [u64], front: usize, back: usize) -> Self {
+        Self { words, front, back }
+    }
+}
+
+impl Iterator for SetBits<'_> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        while self.front < self.back {
+            let w = self.front / 64;
+            let word = self.words[w] & (!0u64 << (self.front % 64));
+            if word != 0 {
+                let bit = w * 64 + word.trailing_zeros() as usize;
+                if bit >= self.back {
+                    break;
+                }
+                self.front = bit + 1;
+                return Some(bit);
+            }
+            self.front = (w + 1) * 64;
+        }
+        self.front = self.back;
+        None
+    }
+}
+
+impl DoubleEndedIterator for SetBits<'_> {
+    fn next_back(&mut self) -> Option<usize> {
+        while self.front < self.back {
+            let last = self.back - 1;
+            let w = last / 64;
+            let word = self.words[w] & (!0u64 >> (63 - last % 64));
+            if word != 0 {
+                let bit = w * 64 + 63 - word.leading_zeros() as usize;
+                if bit < self.front {
+                    break;
+                }
+                self.back = bit;
+                return Some(bit);
+            }
+            self.back = w * 64;
+        }
+        self.back = self.front;
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    #[test]
+    fn test_set_bits_from_both_ends() {
+        let words = [0b1001u64, 0, 1 << 63];
+        let all: Vec<usize> = SetBits::new(&words, 0, 192).collect();
+        assert_eq!(all, vec![0, 3, 191]);
+        assert_eq!(SetBits::new(&words, 0, 192).rev().collect::<Vec<_>>(), vec![191, 3, 0]);
+        assert_eq!(SetBits::new(&words, 1, 191).collect::<Vec<_>>(), vec![3]);
+
+        let mut bits = SetBits::new(&words, 0, 192);
+        assert_eq!((bits.next(), bits.next_back(), bits.next(), bits.next_back()), (Some(0), Some(191), Some(3), None));
+    }
+
+    #[test]
+    fn test_touch_re_centres_the_window() {
+        let mut bids: Ladder<u32> = Ladder::new(Side::Buy);
+        bids.insert(10_000, 1);
+        assert!(bids.slot(10_000).is_some());
+
+        // Deep below the touch stays in the tree
+        bids.insert(1_000, 2);
+        assert!(bids.slot(1_000).is_none());
+
+        // A better bid far above moves the window; the old touch drops out
+        bids.insert(50_000, 3);
+        assert!(bids.slot(50_000).is_some() && bids.slot(10_000).is_none());
+
+        // Emptying the window brings it back to whatever is now best
+        bids.remove(&50_000);
+        assert!(bids.slot(10_000).is_some());
+        assert_eq!(bids.iter().collect::<Vec<_>>(), vec![(1_000, &2), (10_000, &1)]);
+        assert!(bids.is_consistent());
+    }
+
+    #[test]
+    fn test_range_matches_btree_map() {
+        let mut ladder: Ladder<u32> = Ladder::new(Side::Sell);
+        let mut model = BTreeMap::new();
+        for (i, ticks) in [5_000, 4_990, 5_300, 6_000, 100, 5_255, 5_256].into_iter().enumerate() {
+            ladder.insert(ticks, i as u32);
+            model.insert(ticks, i as u32);
+        }
+        let bounds = [Ticks::MIN, 0, 100, 4_999, 5_000, 5_255, 5_300, 7_000, Ticks::MAX];
+        for &lo in &bounds {
+            for &hi in &bounds {
+                if lo > hi {
+                    continue;
+                }
+                let got: Vec<_> = ladder.range(lo..=hi).collect();
+                let want: Vec<_> = model.range(lo..=hi).map(|(&t, v)| (t, v)).collect();
+                assert_eq!(got, want, "{lo}..={hi}");
+                let got: Vec<_> = ladder.range(lo..=hi).rev().collect();
+                assert_eq!(got, want.into_iter().rev().collect::<Vec<_>>(), "{lo}..={hi} reversed");
+            }
+        }
+    }
+
+    #[test]
+    fn test_re_centring_never_loses_or_duplicates_levels() {
+        let mut rng = StdRng::seed_from_u64(11);
+        for side in [Side::Buy, Side::Sell] {
+            let mut ladder: Ladder<u64> = Ladder::new(side);
+            let mut model: BTreeMap<Ticks, u64> = BTreeMap::new();
+            let mut mid: Ticks = 1_000_000;
+            for step in 0..5_000u64 {
+                // Big jumps now and then drag the touch well out of the window
+                mid += if rng.gen_bool(0.02) { rng.gen_range(-5_000..=5_000) } else { rng.gen_range(-3..=3) };
+                let ticks = mid + rng.gen_range(-2_000..=2_000);
+                if rng.gen_bool(0.55) {
+                    *ladder.get_or_insert_with(ticks, || 0) += step;
+                    *model.entry(ticks).or_default() += step;
+                } else {
+                    let target = match side {
+                        Side::Buy => model.keys().next_back().copied(),
+                        Side::Sell => model.keys().next().copied(),
+                    };
+                    let ticks = if rng.gen_bool(0.5) { target.unwrap_or(ticks) } else { ticks };
+                    assert_eq!(ladder.remove(&ticks), model.remove(&ticks), "step {step}");
+                }
+                if step % 97 == 0 {
+                    ladder.retain(|&t, _| t % 7 != 0);
+                    model.retain(|&t, _| t % 7 != 0);
+                }
+
+                assert_eq!(ladder.len(), model.len(), "step {step}");
+                assert_eq!(ladder.first_key(), model.keys().next().copied());
+                assert_eq!(ladder.last_key(), model.keys().next_back().copied());
+                assert!(ladder.is_consistent());
+                if let Some(touch) = ladder.touch() {
+                    assert!(ladder.slot(touch).is_some(), "touch {touch} outside the window at step {step}");
+                }
+            }
+            assert!(ladder.iter().map(|(t, &v)| (t, v)).eq(model.into_iter()));
+        }
+    }
+}
@@ -0,0 +1,187 @@
+//! How long the engine takes over each order.
+//!
+//! Every order that reaches matching is timed from the engine taking it up
+//! to its events being sent, and counted in a histogram for its outcome:
+//! rested without trading, matched at one price, or swept several. The
+//! histograms are fixed arrays of log-linear buckets, each power of two
+//! split into 16, so a percentile is within about 6% of the true value.
+//! Recording is an index computation and a single increment, touching one
+//! cache line; nothing allocates. Totals are worked out when asked for.
+
+use serde::Serialize;
+use utoipa::ToSchema;
+use std::time::Duration;
+
+/// Buckets per power of two, as a number of bits
+const SUB_BITS: u32 = 4;
+const SUB_BUCKETS: usize = 1 << SUB_BITS;
+
+/// Latencies from 2^40ns (about 18 minutes) up share the last bucket
+const MAX_EXPONENT: u32 = 40;
+
+const BUCKETS: usize = SUB_BUCKETS * (1 + (MAX_EXPONENT - SUB_BITS) as usize);
+
+/// Counts of latencies in log-linear buckets of nanoseconds
+#[derive(Debug, Clone)]
+pub struct LatencyHistogram {
+    counts: [u64; BUCKETS],
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self { counts: [0; BUCKETS] }
+    }
+}
+
+impl LatencyHistogram {
+    pub fn record(&mut self, latency: Duration) {
+        let nanos = u64::try_from(latency.as_nanos()).unwrap_or(u64::MAX);
+        self.counts[bucket_of(nanos)] += 1;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Latency at or under which `quantile` of those recorded fall, as the
+    /// top of its bucket; zero if nothing was recorded
+    pub fn quantile(&self, quantile: f64) -> u64 {
+        let rank = ((quantile.clamp(0.0, 1.0) * self.count() as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (bucket, &count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return bucket_top(bucket);
+            }
+        }
+        0
+    }
+
+    pub fn summary(&self) -> LatencySummary {
+        LatencySummary {
+            count: self.count(),
+            p50_ns: self.quantile(0.5),
+            p90_ns: self.quantile(0.9),
+            p99_ns: self.quantile(0.99),
+            p999_ns: self.quantile(0.999),
+            max_ns: self.quantile(1.0),
+        }
+    }
+}
+
+/// Bucket holding `nanos`: one per value below 16, then 16 per power of two
+fn bucket_of(nanos: u64) -> usize {
+    if nanos < SUB_BUCKETS as u64 {
+        return nanos as usize;
+    }
+    let exponent = u64::BITS - 1 - nanos.leading_zeros();
+    if exponent >= MAX_EXPONENT {
+        return BUCKETS - 1;
+    }
+    let shift = exponent - SUB_BITS;
+    SUB_BUCKETS * (1 + shift as usize) + ((nanos >> shift) as usize - SUB_BUCKETS)
+}
+
+/// Largest value `bucket` holds
+fn bucket_top(bucket: usize) -> u64 {
+    if bucket < SUB_BUCKETS {
+        return bucket as u64;
+    }
+    let shift = (bucket / SUB_BUCKETS - 1) as u32;
+    let low = ((SUB_BUCKETS + bucket % SUB_BUCKETS) as u64) << shift;
+    low + (1 << shift) - 1
+}
+
+/// Percentiles of a latency histogram, in nanoseconds, each the top of
+/// the bucket it falls in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, ToSchema)]
+pub struct LatencySummary {
+    pub count: u64,
+    pub p50_ns: u64,
+    pub p90_ns: u64,
+    pub p99_ns: u64,
+    pub p999_ns: u64,
+    pub max_ns: u64,
+}
+
+/// What matching did with an order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchKind {
+    /// Took no liquidity
+    Rested,
+    /// Traded at a single price
+    Matched,
+    /// Traded through more than one price level
+    Swept,
+}
+
+/// A latency histogram per match outcome
+#[derive(Debug, Clone, Default)]
+pub struct OrderLatencies {
+    rested: LatencyHistogram,
+    matched: LatencyHistogram,
+    swept: LatencyHistogram,
+}
+
+impl OrderLatencies {
+    pub fn record(&mut self, kind: MatchKind, latency: Duration) {
+        match kind {
+            MatchKind::Rested => self.rested.record(latency),
+            MatchKind::Matched => self.matched.record(latency),
+            MatchKind::Swept => self.swept.record(latency),
+        }
+    }
+
+    pub fn summary(&self) -> OrderLatency {
+        OrderLatency {
+            rested: self.rested.summary(),
+            matched: self.matched.summary(),
+            swept: self.swept.summary(),
+        }
+    }
+}
+
+/// Engine processing latency percentiles by match outcome, since the
+/// engine started
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, ToSchema)]
+pub struct OrderLatency {
+    pub rested: LatencySummary,
+    pub matched: LatencySummary,
+    pub swept: LatencySummary,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buckets_cover_every_value_in_order() {
+        let mut previous_top = None;
+        for bucket in 0..BUCKETS {
+            let top = bucket_top(bucket);
+            assert_eq!(bucket_of(top), bucket);
+            assert_eq!(bucket_of(previous_top.map_or(0, |t| t + 1)), bucket);
+            previous_top = Some(top);
+        }
+        assert_eq!(bucket_of(u64::MAX), BUCKETS - 1);
+    }
+
+    #[test]
+    fn test_quantiles_are_within_a_bucket() {
+        let mut histogram = LatencyHistogram::default();
+        assert_eq!(histogram.summary(), LatencySummary::default());
+        for micros in 1..=1000 {
+            histogram.record(Duration::from_micros(micros));
+        }
+        let summary = histogram.summary();
+        assert_eq!(summary.count, 1000);
+        for (quantile, exact) in [
+            (summary.p50_ns, 500_000.0),
+            (summary.p99_ns, 990_000.0),
+            (summary.max_ns, 1_000_000.0),
+        ] {
+            let error = (quantile as f64 - exact) / exact;
+            assert!((0.0..0.07).contains(&error), "{quantile} for {exact}");
+        }
+    }
+}
@@ -1,17 +1,609 @@
 //! Matching engine - single-threaded event loop for order processing.
 
-use crate::engine::order::{Order, OrderRequest, Trade};
-use crate::engine::order_book::OrderBook;
-use std::sync::Arc;
-use tokio::sync::{broadcast, mpsc};
+use crate::engine::affinity;
+use crate::engine::channel::{command_channel, CommandReceiver, CommandSender, EngineMode};
+use crate::engine::clock::{LogicalClock, SharedClock, SystemClock};
+use crate::engine::engines::url_key;
+use crate::engine::ids::{IdEpoch, IdSource, OrderIds, RandomIds, SeededIds};
+use crate::engine::journal::{is_journaled, journal_channel, JournalReceiver, JournalSender, JOURNAL_CAPACITY};
+use crate::engine::latency::{MatchKind, OrderLatencies, OrderLatency};
+use crate::engine::halt::{CircuitBreaker, CircuitBreakerConfig, HaltPolicy};
+use crate::engine::order::{
+    Fill, Order, OrderId, OrderRequest, OrderStatus, OrderType, QuoteRequest, Side, StpPolicy, TimeInForce, Trade,
+    TradeId,
+};
+use crate::engine::rate_limit::{RateLimit, RateLimiter, RateLimits};
+use crate::engine::rolling::{PastTrade, RollingWindow, WindowSummary};
+use crate::engine::order_book::{
+    depth_checksum, BookInvariantError, MatchOutcome, MatchingAlgorithm, OrderBook, PriceImpact,
+};
+use crate::engine::snapshot::EngineSnapshot;
+use crate::engine::stops::StopBook;
+use crate::engine::wal::{LoggedCommand, WalRecord, WalWriter};
+use chrono::{DateTime, Utc};
+use crate::engine::symbol::SymbolConfig;
+use rust_decimal::Decimal;
+use std::any::Any;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::panic::AssertUnwindSafe;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, mpsc, oneshot, watch};
+
+/// How many of the most recent trades can still be busted
+const BUSTABLE_TRADES: usize = 10_000;
+
+/// Most commands processed together before the book is published
+pub const DEFAULT_BATCH_SIZE: usize = 256;
+
+/// Time between heartbeats unless the builder sets another
+pub const DEFAULT_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Time between engine ticks unless the builder sets another
+pub const DEFAULT_TICK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Heartbeat intervals that may pass without one before an engine counts
+/// as stalled
+const STALE_HEARTBEATS: u32 = 3;
+
+/// Owner whose orders panic the engine in unit tests, to exercise recovery
+#[cfg(test)]
+const PANIC_OWNER: &str = "panic-the-engine";
+
+/// Builds a symbol's engine again, from its latest snapshot and log, to
+/// take over from one that panicked
+pub type Rebuild = Arc<dyn Fn() -> EngineBuilder + Send + Sync>;
+
+/// Commands processed by the matching engine, in arrival order
+#[derive(Debug)]
+pub enum EngineCommand {
+    /// Submit a new order
+    Submit(OrderRequest),
+    /// Submit a new order and report what the engine did with it
+    SubmitConfirmed {
+        request: OrderRequest,
+        reply: oneshot::Sender<Result<SubmitAck, RejectReason>>,
+    },
+    /// Cancel a resting or pending stop order
+    Cancel { order_id: OrderId },
+    /// Cancel a resting or pending stop order and report what was removed.
+    /// With `owner_id`, an order belonging to anyone else is left alone.
+    CancelConfirmed {
+        order_id: OrderId,
+        owner_id: Option<String>,
+        reply: oneshot::Sender<Result<CancelAck, RejectReason>>,
+    },
+    /// Cancel every open order an owner has, optionally on one side only
+    CancelAll { owner_id: String, side: Option<Side> },
+    /// Replace the owner's previous two-sided quote with a new one
+    Quote(QuoteRequest),
+    /// Cancel all of an owner's orders and refuse new ones until released
+    KillSwitch { owner_id: String },
+    /// Allow a killed owner to trade again
+    ReleaseKillSwitch { owner_id: String },
+    /// Void an executed trade
+    BustTrade { trade_id: TradeId },
+    /// Amend a resting order's price and/or open quantity
+    Modify {
+        order_id: OrderId,
+        new_price: Option<Decimal>,
+        new_quantity: Option<Decimal>,
+        /// Id of a replacement, should the amendment need one; the engine
+        /// takes the next one itself when unset
+        new_order_id: Option<OrderId>,
+    },
+    /// Remove resting orders whose expiry has passed
+    ExpireOrders,
+    /// Open a call auction: orders rest without matching until `EndAuction`
+    StartAuction,
+    /// Close the call auction and uncross the book at a single price
+    EndAuction,
+    /// Reply with the current book, depth grouped into `bucket_size` buckets
+    GroupedBook {
+        bucket_size: Decimal,
+        reply: oneshot::Sender<OrderBookSnapshot>,
+    },
+    /// Reply with the current book, `levels` deep on each side
+    DeepBook {
+        levels: usize,
+        reply: oneshot::Sender<OrderBookSnapshot>,
+    },
+    /// Reply with displayed liquidity within `distance` of each touch
+    Liquidity {
+        distance: Decimal,
+        reply: oneshot::Sender<LiquiditySnapshot>,
+    },
+    /// Reply with what taking `quantity` on `side` from the book would cost
+    PriceImpact {
+        side: Side,
+        quantity: Decimal,
+        reply: oneshot::Sender<PriceImpact>,
+    },
+    /// Reply with the engine's per-order processing latencies
+    QueryLatency { reply: oneshot::Sender<OrderLatency> },
+    /// Reply with the engine's statistics
+    QueryStats { reply: oneshot::Sender<EngineStats> },
+    /// Reply with the last day's trading, and the sequence of the last
+    /// event it takes in
+    QueryWindow { reply: oneshot::Sender<(u64, RollingWindow)> },
+    /// Reply with an open order as it stands, on the book or held as a
+    /// stop; `None` once it has left the engine
+    QueryOrder {
+        order_id: OrderId,
+        reply: oneshot::Sender<Option<Order>>,
+    },
+    /// Reply with up to `limit` of the orders resting on the book, those of
+    /// `owner_id` and on `side` where given
+    ListOrders {
+        owner_id: Option<String>,
+        side: Option<Side>,
+        limit: usize,
+        reply: oneshot::Sender<OrderListing>,
+    },
+    /// Change how many levels per side book updates carry, or the least
+    /// time between them; `None` leaves a setting as it is
+    SetBookUpdates {
+        depth_levels: Option<usize>,
+        min_update_interval: Option<Duration>,
+    },
+    /// Set how fast `owner_id` may send orders, or the default for owners
+    /// without a limit of their own when `None`; a `None` limit lifts it
+    SetRateLimit {
+        owner_id: Option<String>,
+        limit: Option<RateLimit>,
+    },
+    /// Reply with the engine's full state, for saving across restarts
+    Snapshot { reply: oneshot::Sender<EngineSnapshot> },
+    /// Stop taking commands, process those already queued, publish the
+    /// final book and stop; `reply` gets the final state once stopped
+    Shutdown { reply: oneshot::Sender<EngineSnapshot> },
+}
+
+/// An engine event and its place in the engine's output. Every event
+/// gets the next number, starting from 1, so a consumer that sees a jump
+/// knows it missed events and should resync from a snapshot. Each symbol's
+/// engine numbers its own events.
+#[derive(Debug, Clone)]
+pub struct SequencedEvent {
+    pub seq: u64,
+    /// Symbol of the engine that emitted the event
+    pub symbol: Arc<str>,
+    pub event: EngineEvent,
+}
 
 /// Events emitted by the matching engine
 #[derive(Debug, Clone)]
 pub enum EngineEvent {
-    /// A trade was executed
-    Trade(Trade),
+    /// A trade was executed. Shared, so each subscriber's copy of the event is
+    /// a pointer copy.
+    Trade(Arc<Trade>),
+    /// One side of a trade; each trade is followed by its taker then maker fill
+    OrderFilled(Fill),
+    /// A previously executed trade was voided. The orders it filled are not
+    /// restored; consumers should back it out of volume and candles.
+    TradeBusted(Arc<Trade>),
+    /// A bust request could not be applied
+    BustRejected { trade_id: TradeId, reason: RejectReason },
     /// Order book state changed
-    OrderBookUpdate(OrderBookSnapshot),
+    OrderBookUpdate(Arc<OrderBookSnapshot>),
+    /// The engine took an order that passed validation; it comes before the
+    /// order's other events. Refusals only found at matching, such as a
+    /// post-only order that would cross, still follow as `OrderRejected`.
+    /// `price` is zero for a market order; `timestamp` is when the order
+    /// reached the engine.
+    OrderAccepted {
+        order_id: OrderId,
+        client_order_id: Option<String>,
+        owner_id: Option<String>,
+        side: Side,
+        price: Decimal,
+        quantity: Decimal,
+        timestamp: DateTime<Utc>,
+    },
+    /// An order's unfilled remainder was added to the book, after its trades
+    OrderRested {
+        order_id: OrderId,
+        price: Decimal,
+        remaining_quantity: Decimal,
+    },
+    /// An order was refused by the engine and never reached the book
+    OrderRejected {
+        order_id: OrderId,
+        client_order_id: Option<String>,
+        reason: RejectReason,
+    },
+    /// A pending stop order fired and was released for matching
+    StopTriggered { order_id: OrderId, stop_price: Decimal },
+    /// A live order was removed before it fully filled
+    OrderCancelled {
+        order_id: OrderId,
+        remaining_quantity: Decimal,
+        reason: CancelReason,
+    },
+    /// A cancel request could not be applied
+    CancelRejected { order_id: OrderId, reason: RejectReason },
+    /// A mass cancel finished; each removed order had its own `OrderCancelled`
+    MassCancelled {
+        owner_id: String,
+        side: Option<Side>,
+        count: usize,
+    },
+    /// A resting order was amended. `new_order_id` equals `order_id` for an
+    /// in-place size reduction; otherwise the order was replaced.
+    OrderModified {
+        order_id: OrderId,
+        new_order_id: OrderId,
+        price: Decimal,
+        remaining_quantity: Decimal,
+    },
+    /// A modify request could not be applied
+    ModifyRejected { order_id: OrderId, reason: RejectReason },
+    /// An owner's quote was replaced. `cancelled` lists the previous quote
+    /// orders that were still open, each also reported as `OrderCancelled`.
+    QuoteReplaced {
+        owner_id: String,
+        bid_order_id: OrderId,
+        ask_order_id: OrderId,
+        cancelled: Vec<OrderId>,
+    },
+    /// A quote was refused; the owner's previous quote is left in place
+    QuoteRejected { owner_id: String, reason: RejectReason },
+    /// An owner was blocked and `cancelled` of its open orders removed
+    KillSwitchEngaged { owner_id: String, cancelled: usize },
+    /// A blocked owner may trade again
+    KillSwitchReleased { owner_id: String },
+    /// The circuit breaker tripped; matching is suspended until `until`
+    TradingHalted {
+        until: chrono::DateTime<chrono::Utc>,
+        last_price: Decimal,
+    },
+    /// Matching resumed after a halt
+    TradingResumed,
+    /// A call auction opened
+    AuctionStarted,
+    /// Price and volume the open auction would uncross at if it ended now
+    AuctionIndicative {
+        price: Option<Decimal>,
+        volume: Decimal,
+    },
+    /// The auction closed and the book was uncrossed
+    AuctionEnded {
+        price: Option<Decimal>,
+        volume: Decimal,
+    },
+    /// The engine has stopped; this is its last event, right after the
+    /// final book update
+    Stopped,
+    /// The engine is alive and taking commands. Sent every heartbeat
+    /// interval of real time, busy or idle; `seq` counts heartbeats from 1
+    /// and `orders_processed` the orders taken since the engine started.
+    /// Heartbeats do not take an event number: they repeat the last one.
+    Heartbeat {
+        seq: u64,
+        timestamp: DateTime<Utc>,
+        orders_processed: u64,
+    },
+    /// A command panicked the engine, which was rebuilt from its snapshot
+    /// and log and takes commands again. Events of the command that
+    /// panicked, and of any run after the snapshot without a log, were
+    /// undone with it, and their numbers are given out again: state built
+    /// from the feed should be refreshed. `voided_record` is the log
+    /// record of the command that panicked, never to be replayed.
+    Recovered {
+        reason: String,
+        voided_record: Option<u64>,
+    },
+}
+
+/// What the engine does when a periodic book check finds a broken invariant
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvariantPolicy {
+    /// Log the violation and keep matching
+    Log,
+    /// Stop the engine
+    Panic,
+}
+
+/// What the engine does when a side of the book is at its resting order cap
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BookLimitPolicy {
+    /// Refuse orders that would rest on the full side
+    Reject,
+    /// Make room by cancelling the newest order at the level farthest from
+    /// the touch; an order that would itself be the farthest is refused
+    EvictFarthest,
+}
+
+/// Why a live order was removed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum CancelReason {
+    /// Cancelled on request
+    UserRequested,
+    /// Removed by a cancel-all request for its owner
+    MassCancel,
+    /// Removed when its owner's kill switch was engaged
+    KillSwitch,
+    /// Replaced by a modification that lost time priority
+    Replaced,
+    /// Removed by self-trade prevention: a resting order met by its owner's
+    /// incoming order, or the incoming order itself
+    SelfTrade,
+    /// Its good-till-date expiry was reached
+    Expired,
+    /// Market or IOC remainder that could not fill immediately
+    Unfilled,
+    /// Market order remainder left when the next level broke its slippage bound
+    SlippageLimit,
+    /// Fill-or-kill order that could not fill in full
+    FillOrKill,
+    /// Less than one lot left open after a partial fill, too small to ever match
+    Residual,
+    /// Pushed off the far end of a full book by better-priced liquidity
+    Evicted,
+    /// Remainder of a crossing order with no room left to rest
+    BookFull,
+    /// Remainder left when a fill was refused for driving an order negative
+    FillRefused,
+}
+
+impl CancelReason {
+    /// Machine-readable reason code
+    pub fn code(&self) -> &'static str {
+        match self {
+            CancelReason::UserRequested => "USER_REQUESTED",
+            CancelReason::MassCancel => "MASS_CANCEL",
+            CancelReason::KillSwitch => "KILL_SWITCH",
+            CancelReason::Replaced => "REPLACED",
+            CancelReason::SelfTrade => "SELF_TRADE",
+            CancelReason::Expired => "EXPIRED",
+            CancelReason::Unfilled => "UNFILLED",
+            CancelReason::SlippageLimit => "SLIPPAGE_LIMIT",
+            CancelReason::FillOrKill => "FILL_OR_KILL",
+            CancelReason::Residual => "RESIDUAL",
+            CancelReason::Evicted => "EVICTED",
+            CancelReason::BookFull => "BOOK_FULL",
+            CancelReason::FillRefused => "FILL_REFUSED",
+        }
+    }
+}
+
+/// Reason an order was refused by the engine
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error, serde::Serialize, serde::Deserialize)]
+pub enum RejectReason {
+    /// A post-only order would have taken liquidity
+    #[error("post-only order would cross the spread")]
+    PostOnlyWouldCross,
+    /// The order's expiry had already passed when it reached the engine
+    #[error("order expiry is not in the future")]
+    AlreadyExpired,
+    /// A good-till-date order without an expiry
+    #[error("time in force gtd requires expires_at")]
+    MissingExpiry,
+    /// A price with more decimal places than the symbol allows
+    #[error("price {price} exceeds the allowed precision of {max_scale} decimal places")]
+    PriceScaleExceeded { price: Decimal, max_scale: u32 },
+    /// A quantity with more decimal places than the symbol allows
+    #[error("quantity {quantity} exceeds the allowed precision of {max_scale} decimal places")]
+    QuantityScaleExceeded { quantity: Decimal, max_scale: u32 },
+    /// A price that is not a multiple of the symbol's tick size
+    #[error("price {price} is not a multiple of tick size {tick_size}")]
+    InvalidTickSize { price: Decimal, tick_size: Decimal },
+    #[error("price {price} is too large to trade")]
+    PriceOutOfRange { price: Decimal },
+    /// A quantity that is not a multiple of the symbol's lot size
+    #[error("quantity {quantity} is not a multiple of lot size {lot_size}")]
+    InvalidLotSize { quantity: Decimal, lot_size: Decimal },
+    /// A quantity below the symbol's minimum
+    #[error("quantity {quantity} is below the minimum of {min_quantity}")]
+    BelowMinQuantity { quantity: Decimal, min_quantity: Decimal },
+    /// A priced order worth less than the symbol's minimum notional
+    #[error("order notional {notional} is below the minimum of {min_notional}")]
+    BelowMinNotional { notional: Decimal, min_notional: Decimal },
+    /// A quantity above the pre-trade size limit
+    #[error("quantity {quantity} exceeds the maximum of {max_quantity}")]
+    MaxQuantityExceeded { quantity: Decimal, max_quantity: Decimal },
+    /// A priced order worth more than the pre-trade notional limit
+    #[error("order notional {notional} exceeds the maximum of {max_notional}")]
+    MaxNotionalExceeded { notional: Decimal, max_notional: Decimal },
+    /// A limit price too far from the reference price
+    #[error("price {price} is more than {band_pct}% away from reference price {reference}")]
+    PriceOutsideBand {
+        price: Decimal,
+        reference: Decimal,
+        band_pct: Decimal,
+    },
+    /// The circuit breaker has suspended matching
+    #[error("trading is halted")]
+    TradingHalted,
+    /// Orders that cannot rest are refused while an auction is collecting orders
+    #[error("only resting limit orders are accepted during an auction")]
+    AuctionOrderNotAllowed,
+    /// The owner's kill switch is engaged
+    #[error("owner is blocked from trading")]
+    OwnerBlocked,
+    /// The owner has sent orders faster than its rate limit allows
+    #[error("owner exceeded its rate limit of {per_second} orders per second")]
+    RateLimited { per_second: u32 },
+    /// The owner is at its open order cap
+    #[error("owner already has the maximum of {limit} open orders")]
+    TooManyOpenOrders { limit: usize },
+    /// The order's side of the book already holds the most resting orders allowed
+    #[error("book side already holds the maximum of {limit} resting orders")]
+    BookFull { limit: usize },
+    /// The owner already has an open order under this client order id
+    #[error("client order id {client_order_id} is already in use by an open order")]
+    DuplicateClientOrderId { client_order_id: String },
+    /// No open order with this id (unknown, already filled or cancelled)
+    #[error("order is not open")]
+    UnknownOrder,
+    /// A cancel naming an owner other than the order's
+    #[error("order belongs to another owner")]
+    NotOrderOwner,
+    /// A quote whose bid is at or above its ask
+    #[error("quote bid {bid_price} must be below ask {ask_price}")]
+    QuoteWouldSelfCross { bid_price: Decimal, ask_price: Decimal },
+    /// A modification with a non-positive price or quantity
+    #[error("modified price and quantity must be positive")]
+    InvalidModification,
+    /// No recent trade with this id, or it was already busted
+    #[error("trade is unknown or already busted")]
+    UnknownTrade,
+}
+
+impl RejectReason {
+    /// Every code [`code`](Self::code) gives
+    pub const CODES: [&'static str; 25] = [
+        "POST_ONLY_WOULD_CROSS",
+        "ALREADY_EXPIRED",
+        "MISSING_EXPIRY",
+        "PRICE_SCALE_EXCEEDED",
+        "QUANTITY_SCALE_EXCEEDED",
+        "INVALID_TICK_SIZE",
+        "PRICE_OUT_OF_RANGE",
+        "INVALID_LOT_SIZE",
+        "BELOW_MIN_QUANTITY",
+        "BELOW_MIN_NOTIONAL",
+        "MAX_QTY_EXCEEDED",
+        "MAX_NOTIONAL_EXCEEDED",
+        "PRICE_OUTSIDE_BAND",
+        "TRADING_HALTED",
+        "AUCTION_ORDER_NOT_ALLOWED",
+        "OWNER_BLOCKED",
+        "RATE_LIMITED",
+        "TOO_MANY_OPEN_ORDERS",
+        "BOOK_FULL",
+        "DUPLICATE_CLIENT_ORDER_ID",
+        "UNKNOWN_ORDER",
+        "NOT_ORDER_OWNER",
+        "QUOTE_WOULD_SELF_CROSS",
+        "INVALID_MODIFICATION",
+        "UNKNOWN_TRADE",
+    ];
+
+    /// Machine-readable reason code
+    pub fn code(&self) -> &'static str {
+        match self {
+            RejectReason::PostOnlyWouldCross => "POST_ONLY_WOULD_CROSS",
+            RejectReason::AlreadyExpired => "ALREADY_EXPIRED",
+            RejectReason::MissingExpiry => "MISSING_EXPIRY",
+            RejectReason::PriceScaleExceeded { .. } => "PRICE_SCALE_EXCEEDED",
+            RejectReason::QuantityScaleExceeded { .. } => "QUANTITY_SCALE_EXCEEDED",
+            RejectReason::InvalidTickSize { .. } => "INVALID_TICK_SIZE",
+            RejectReason::PriceOutOfRange { .. } => "PRICE_OUT_OF_RANGE",
+            RejectReason::InvalidLotSize { .. } => "INVALID_LOT_SIZE",
+            RejectReason::BelowMinQuantity { .. } => "BELOW_MIN_QUANTITY",
+            RejectReason::BelowMinNotional { .. } => "BELOW_MIN_NOTIONAL",
+            RejectReason::MaxQuantityExceeded { .. } => "MAX_QTY_EXCEEDED",
+            RejectReason::MaxNotionalExceeded { .. } => "MAX_NOTIONAL_EXCEEDED",
+            RejectReason::PriceOutsideBand { .. } => "PRICE_OUTSIDE_BAND",
+            RejectReason::TradingHalted => "TRADING_HALTED",
+            RejectReason::AuctionOrderNotAllowed => "AUCTION_ORDER_NOT_ALLOWED",
+            RejectReason::OwnerBlocked => "OWNER_BLOCKED",
+            RejectReason::RateLimited { .. } => "RATE_LIMITED",
+            RejectReason::TooManyOpenOrders { .. } => "TOO_MANY_OPEN_ORDERS",
+            RejectReason::BookFull { .. } => "BOOK_FULL",
+            RejectReason::DuplicateClientOrderId { .. } => "DUPLICATE_CLIENT_ORDER_ID",
+            RejectReason::UnknownOrder => "UNKNOWN_ORDER",
+            RejectReason::NotOrderOwner => "NOT_ORDER_OWNER",
+            RejectReason::QuoteWouldSelfCross { .. } => "QUOTE_WOULD_SELF_CROSS",
+            RejectReason::InvalidModification => "INVALID_MODIFICATION",
+            RejectReason::UnknownTrade => "UNKNOWN_TRADE",
+        }
+    }
+}
+
+/// Where an accepted order stands once the engine has processed it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderState {
+    /// Open quantity is resting on the book
+    Resting,
+    /// Traded in full
+    Filled,
+    /// Whatever did not trade was cancelled, as for IOC and market orders
+    Cancelled,
+    /// A stop waiting for its trigger price
+    PendingStop,
+    /// Held until a trading halt ends
+    Queued,
+}
+
+/// The engine's reply to a confirmed submission it accepted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubmitAck {
+    pub order_id: OrderId,
+    pub state: OrderState,
+    /// Quantity the order traded while it was processed
+    pub filled_quantity: Decimal,
+    /// Volume-weighted price of those trades; `None` if nothing traded
+    pub average_price: Option<Decimal>,
+    /// Open quantity left resting, pending or queued
+    pub remaining_quantity: Decimal,
+}
+
+/// What a confirmed submission gets back
+type SubmitOutcome = Result<SubmitAck, RejectReason>;
+
+/// The engine's reply to a confirmed cancel it carried out
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CancelAck {
+    pub order_id: OrderId,
+    /// Quantity the order had traded before it was cancelled
+    pub filled_quantity: Decimal,
+    /// Open quantity taken off the book
+    pub remaining_quantity: Decimal,
+}
+
+/// What a confirmed cancel gets back
+type CancelOutcome = Result<CancelAck, RejectReason>;
+
+/// Resting orders picked out by a `ListOrders` query
+#[derive(Debug, Clone)]
+pub struct OrderListing {
+    /// By price, lowest first, then by time priority
+    pub orders: Vec<Order>,
+    /// How many orders matched, those past the limit included
+    pub total: usize,
+    /// The engine's time when it answered, for the orders' ages
+    pub as_of: DateTime<Utc>,
+}
+
+/// A confirmed submission being processed: its rejection, if any, and
+/// what it has traded so far
+struct Confirming {
+    order_id: OrderId,
+    quantity: Decimal,
+    rejection: Option<RejectReason>,
+    filled: Decimal,
+    notional: Decimal,
+}
+
+/// Everything the engine has traded, less any busted trades. Sums are
+/// `Decimal`s, which add exactly while a total fits in 28 digits: at a
+/// quantity scale of 8, that is beyond 10^20 in volume, so millions of
+/// trades accumulate without rounding.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize)]
+pub struct TradeTotals {
+    pub count: u64,
+    /// Base quantity traded
+    pub volume: Decimal,
+    /// Quote value traded, price times quantity
+    pub notional: Decimal,
+}
+
+impl TradeTotals {
+    fn add(&mut self, trade: &Trade) {
+        self.count += 1;
+        self.volume += trade.quantity;
+        self.notional += trade.price * trade.quantity;
+    }
+
+    fn remove(&mut self, trade: &Trade) {
+        self.count -= 1;
+        self.volume -= trade.quantity;
+        self.notional -= trade.price * trade.quantity;
+    }
 }
 
 /// Snapshot of the order book state
@@ -19,282 +611,5580 @@ pub enum EngineEvent {
 pub struct OrderBookSnapshot {
     pub best_bid: Option<rust_decimal::Decimal>,
     pub best_ask: Option<rust_decimal::Decimal>,
-    pub bid_depth: Vec<(rust_decimal::Decimal, rust_decimal::Decimal)>,
-    pub ask_depth: Vec<(rust_decimal::Decimal, rust_decimal::Decimal)>,
+    /// Quantity displayed at the best bid
+    pub best_bid_qty: Option<rust_decimal::Decimal>,
+    /// Quantity displayed at the best ask
+    pub best_ask_qty: Option<rust_decimal::Decimal>,
+    pub mid_price: Option<rust_decimal::Decimal>,
+    pub microprice: Option<rust_decimal::Decimal>,
+    /// Displayed volume imbalance at the best level of each side
+    pub top_imbalance: Option<rust_decimal::Decimal>,
+    /// Displayed volume imbalance over the engine's imbalance levels
+    pub depth_imbalance: Option<rust_decimal::Decimal>,
+    /// CRC-32 of the depth below, see `order_book::depth_checksum`
+    pub checksum: u32,
+    pub last_trade_price: Option<rust_decimal::Decimal>,
+    /// Everything traded so far
+    pub trades: TradeTotals,
+    /// Price, displayed quantity and displayed order count per level
+    pub bid_depth: Vec<(rust_decimal::Decimal, rust_decimal::Decimal, usize)>,
+    pub ask_depth: Vec<(rust_decimal::Decimal, rust_decimal::Decimal, usize)>,
+    /// Engine clock time the snapshot was taken
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+    /// Number of the book update this state was broadcast in, counting from
+    /// 1; consecutive updates differ by one, so a gap means one was missed
+    pub sequence: u64,
+}
+
+/// Displayed liquidity on each side of the book
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct LiquiditySnapshot {
+    /// Price distance from the touch the liquidity figures cover
+    pub distance: Decimal,
+    /// Displayed bid quantity within `distance` of the best bid
+    pub bid_liquidity: Decimal,
+    /// Displayed ask quantity within `distance` of the best ask
+    pub ask_liquidity: Decimal,
+    /// Bid levels best first, each with the running total up to it
+    pub bid_depth: Vec<(Decimal, Decimal)>,
+    /// Ask levels best first, each with the running total up to it
+    pub ask_depth: Vec<(Decimal, Decimal)>,
+}
+
+/// What an engine on its own thread hands the task broadcasting for it,
+/// in the order it happened
+// Events go unboxed: they are most of what passes, and boxing each would
+// add an allocation per event
+#[allow(clippy::large_enum_variant)]
+enum Outbound {
+    Event(SequencedEvent),
+    Reply(Box<dyn FnOnce() + Send>),
 }
 
 /// The matching engine processes orders and generates trades
 pub struct MatchingEngine {
     /// The order book
     order_book: OrderBook,
-    /// Channel to receive incoming orders
-    order_rx: mpsc::Receiver<OrderRequest>,
+    /// Trading rules for the symbol
+    config: SymbolConfig,
+    /// Stop orders waiting for their trigger price
+    stops: StopBook,
+    /// Price of the most recent trade, used to trigger stops
+    last_trade_price: Option<Decimal>,
+    /// Everything traded, less any busted
+    trades: TradeTotals,
+    /// Trading over the last 24 hours, less any busted
+    last_24h: RollingWindow,
+    /// When the engine was built by its clock's monotonic time, for its uptime
+    started_at: Instant,
+    /// Orders taken since the engine started, rejected ones included
+    orders_processed: u64,
+    /// Real time between ticks, at most
+    tick_interval: Duration,
+    /// When the next tick is due
+    next_tick: Instant,
+    /// Real time between heartbeats
+    heartbeat_interval: Duration,
+    /// When the next heartbeat is due
+    next_heartbeat: Instant,
+    /// Heartbeats sent so far
+    heartbeats: u64,
+    /// When the last heartbeat was sent, watched by handles for liveness
+    heartbeat_tx: watch::Sender<Instant>,
+    /// Halts matching after a rapid price move, if configured
+    breaker: Option<CircuitBreaker>,
+    /// Orders held during a halt under `HaltPolicy::Queue`
+    halted_orders: VecDeque<Order>,
+    /// Whether a call auction is collecting orders
+    in_auction: bool,
+    /// Order ids of each owner's current quote, bid then ask
+    quotes: HashMap<String, (OrderId, OrderId)>,
+    /// Most open orders (resting, pending stop or halt-queued) an owner may have
+    max_open_orders: Option<usize>,
+    /// How fast each owner may send orders
+    rate_limiter: RateLimiter,
+    /// Owners whose kill switch is engaged
+    blocked_owners: HashSet<String>,
+    /// Most orders each side of the book may hold, and how to keep to it
+    book_limit: Option<(usize, BookLimitPolicy)>,
+    /// Most recent trades, oldest first, kept so they can be busted
+    recent_trades: VecDeque<Arc<Trade>>,
+    /// Lists matching fills for each order, kept between orders so their
+    /// capacity is reused rather than allocated afresh
+    matched: MatchOutcome,
+    /// Order a submitter is waiting on, and its outcome so far
+    confirming: Option<Confirming>,
+    /// Callers waiting for a requested shutdown to finish
+    shutdown_replies: Vec<oneshot::Sender<EngineSnapshot>>,
+    /// Validate the book after every `n` orders, and how to react to corruption
+    book_check: Option<(usize, InvariantPolicy)>,
+    /// Orders processed since the book was last validated
+    orders_since_check: usize,
+    /// Arrival sequence the next order to reach the engine, or rejoin the
+    /// book, will get: its time priority
+    next_sequence: u64,
+    /// Ids for new orders, shared with the handles, which reserve them
+    /// before queuing an order
+    order_ids: OrderIds,
+    /// Id the next trade will get
+    next_trade_id: TradeId,
+    /// Turns the engine's ids into the UUIDs clients know them by
+    epoch: IdEpoch,
+    /// Channel to receive incoming commands
+    command_rx: CommandReceiver,
+    /// Whether `run` keeps the engine on the runtime or moves it to a thread
+    mode: EngineMode,
     /// Channel to broadcast engine events (trades, updates)
-    event_tx: broadcast::Sender<EngineEvent>,
-    /// Shared current state for REST API queries
-    current_state: Arc<tokio::sync::RwLock<OrderBookSnapshot>>,
+    event_tx: broadcast::Sender<SequencedEvent>,
+    /// On its own thread, events and replies go through here to the task
+    /// that broadcasts them
+    outbound: Option<flume::Sender<Outbound>>,
+    /// Sequence number of the last event broadcast
+    event_sequence: u64,
+    /// Symbol stamped on every event
+    event_symbol: Arc<str>,
+    /// Latest book update, for readers that don't follow the event stream
+    current_state: watch::Sender<OrderBookSnapshot>,
     /// Number of depth levels to include in updates
     depth_levels: usize,
+    /// Levels per side the depth imbalance covers, at most `depth_levels`
+    imbalance_levels: usize,
+    /// Last book update sent, whose imbalances are reused while depth is unchanged
+    last_book_update: Option<Arc<OrderBookSnapshot>>,
+    /// Book updates not sent because they matched the last one
+    suppressed_book_updates: u64,
+    /// Sequence of the last book update sent
+    book_sequence: u64,
+    /// Least engine time between book updates; zero sends every one
+    min_update_interval: Duration,
+    /// Engine time the last book update was sent
+    last_book_published: Option<DateTime<Utc>>,
+    /// Source of order and trade timestamps
+    clock: SharedClock,
+    /// Time the command being processed runs at, read once per command
+    command_time: Option<DateTime<Utc>>,
+    /// Most commands `run` takes from the queue to process as one batch
+    batch_size: usize,
+    /// Whether a batch is being processed, so book updates wait for its end
+    in_batch: bool,
+    /// The book changed since the last update sent
+    book_update_due: bool,
+    /// Confirmations held back until the batch's book update is sent
+    acks: Vec<(oneshot::Sender<SubmitOutcome>, SubmitOutcome)>,
+    cancel_acks: Vec<(oneshot::Sender<CancelOutcome>, CancelOutcome)>,
+    /// Time each order took to process, by what matching did with it
+    latency: Box<OrderLatencies>,
+    /// When the command being processed was taken up, while processing a
+    /// batch from the queue; replayed commands aren't timed
+    command_started: Option<Instant>,
+    /// Order being processed, zero between orders, and what matching did
+    /// with it once it got there
+    timed_order: OrderId,
+    timed_match: Option<MatchKind>,
+    /// Log every state-changing command is appended to before it runs
+    wal: Option<WalWriter>,
+    /// Lossless feed of the events the journaler records
+    journal: Option<JournalSender>,
+    /// Sequence of the last logged command processed
+    wal_sequence: u64,
+    /// Log record of the command being run, voided if it panics the engine
+    running_record: Option<u64>,
+    /// Rebuilds the engine should a command panic it; without, the panic
+    /// ends the engine
+    rebuild: Option<Rebuild>,
+}
+
+/// What the event loop woke for
+enum Wake {
+    /// This many commands were taken from the queue; none once it has ended
+    Received(usize),
+    /// The update held back by the minimum interval fell due
+    HeldUpdate,
+    /// The tick fell due
+    Tick,
+}
+
+/// What an engine rebuilt after a panic takes over from the one it
+/// replaces: the channels its handles and journaler hold, so they carry on
+/// unchanged, and the statistics snapshots don't keep
+struct Wiring {
+    command_rx: CommandReceiver,
+    event_tx: broadcast::Sender<SequencedEvent>,
+    outbound: Option<flume::Sender<Outbound>>,
+    current_state: watch::Sender<OrderBookSnapshot>,
+    heartbeat_tx: watch::Sender<Instant>,
+    order_ids: OrderIds,
+    journal: Option<JournalSender>,
+    last_24h: RollingWindow,
+    started_at: Instant,
+    orders_processed: u64,
+    /// Log record of the command that panicked
+    voided_record: Option<u64>,
 }
 
 impl MatchingEngine {
     /// Create a new matching engine
     pub fn new(
-        symbol: impl Into<String>,
-        order_rx: mpsc::Receiver<OrderRequest>,
-        event_tx: broadcast::Sender<EngineEvent>,
-        current_state: Arc<tokio::sync::RwLock<OrderBookSnapshot>>,
+        config: SymbolConfig,
+        command_rx: CommandReceiver,
+        event_tx: broadcast::Sender<SequencedEvent>,
+        current_state: watch::Sender<OrderBookSnapshot>,
+        clock: SharedClock,
     ) -> Self {
         Self {
-            order_book: OrderBook::new(symbol),
-            order_rx,
+            order_book: OrderBook::new(config.symbol.clone()).with_price_grid(config.price_grid()),
+            event_symbol: config.symbol.as_str().into(),
+            config,
+            stops: StopBook::new(),
+            last_trade_price: None,
+            trades: TradeTotals::default(),
+            last_24h: RollingWindow::default(),
+            started_at: clock.monotonic(),
+            orders_processed: 0,
+            tick_interval: DEFAULT_TICK_INTERVAL,
+            next_tick: Instant::now(),
+            heartbeat_interval: DEFAULT_HEARTBEAT_INTERVAL,
+            next_heartbeat: Instant::now(),
+            heartbeats: 0,
+            heartbeat_tx: watch::channel(Instant::now()).0,
+            breaker: None,
+            halted_orders: VecDeque::new(),
+            in_auction: false,
+            quotes: HashMap::new(),
+            max_open_orders: None,
+            rate_limiter: RateLimiter::new(RateLimits::default()),
+            blocked_owners: HashSet::new(),
+            book_limit: None,
+            recent_trades: VecDeque::new(),
+            matched: MatchOutcome::default(),
+            confirming: None,
+            shutdown_replies: Vec::new(),
+            book_check: None,
+            orders_since_check: 0,
+            next_sequence: 1,
+            order_ids: OrderIds::default(),
+            next_trade_id: 1,
+            epoch: RandomIds.epoch(),
+            command_rx,
+            mode: EngineMode::Task,
             event_tx,
+            outbound: None,
+            event_sequence: 0,
             current_state,
             depth_levels: 10,
+            imbalance_levels: 5,
+            last_book_update: None,
+            suppressed_book_updates: 0,
+            book_sequence: 0,
+            min_update_interval: Duration::ZERO,
+            last_book_published: None,
+            clock,
+            command_time: None,
+            batch_size: DEFAULT_BATCH_SIZE,
+            in_batch: false,
+            book_update_due: false,
+            acks: Vec::new(),
+            cancel_acks: Vec::new(),
+            latency: Box::default(),
+            command_started: None,
+            timed_order: 0,
+            timed_match: None,
+            wal: None,
+            journal: None,
+            wal_sequence: 0,
+            running_record: None,
+            rebuild: None,
         }
     }
 
     /// Run the matching engine event loop
-    /// This should be spawned as a dedicated task
+    /// This should be spawned as a dedicated task. An engine built for a
+    /// dedicated thread moves onto it, and the task only broadcasts what the
+    /// thread emits until the engine stops.
     pub async fn run(mut self) {
-        tracing::info!("Matching engine started for {}", self.order_book.symbol);
+        let EngineMode::Thread { core } = self.mode else {
+            tracing::info!("Matching engine started for {}", self.order_book.symbol);
+            // Waits for one command, then takes whatever else is already
+            // queued, or for the next tick. Commands win when both are
+            // ready. Ends once every sender is gone, or after a shutdown
+            // once the commands queued ahead of the close are drained.
+            let mut batch = Vec::with_capacity(self.batch_size);
+            self.start_heartbeats();
+            loop {
+                let wait = self.held_update_wait();
+                let next_tick = tokio::time::Instant::from_std(self.next_tick);
+                let wake = tokio::select! {
+                    biased;
+                    received = self.command_rx.recv_many(&mut batch, self.batch_size, wait) => {
+                        received.map_or(Wake::HeldUpdate, Wake::Received)
+                    }
+                    _ = tokio::time::sleep_until(next_tick) => Wake::Tick,
+                };
+                match std::panic::catch_unwind(AssertUnwindSafe(|| self.take_received(wake, &mut batch))) {
+                    Ok(true) => {}
+                    Ok(false) => break,
+                    Err(panic) => {
+                        batch.clear();
+                        self = self.recover(panic);
+                    }
+                }
+            }
+            tracing::info!("Matching engine shutting down");
+            self.stop();
+            return;
+        };
 
-        while let Some(request) = self.order_rx.recv().await {
-            self.process_order(request);
+        let (outbound, forward) = flume::unbounded();
+        self.outbound = Some(outbound);
+        let event_tx = self.event_tx.clone();
+        let engine = std::thread::Builder::new()
+            .name(format!("engine-{}", url_key(&self.config.symbol)))
+            .spawn(move || {
+                if let Some(core) = core {
+                    affinity::pin_to_core(core);
+                }
+                self.run_blocking();
+            })
+            .expect("failed to spawn the engine thread");
+        // Ends when the engine drops its end on stopping
+        while let Ok(outbound) = forward.recv_async().await {
+            match outbound {
+                Outbound::Event(event) => {
+                    let _ = event_tx.send(event);
+                }
+                Outbound::Reply(send) => send(),
+            }
+        }
+        if let Ok(Err(panic)) = tokio::task::spawn_blocking(move || engine.join()).await {
+            std::panic::resume_unwind(panic);
         }
+    }
 
+    /// The event loop of `run`, blocking the engine's own thread while it
+    /// waits for commands
+    fn run_blocking(mut self) {
+        tracing::info!("Matching engine started for {} on its own thread", self.order_book.symbol);
+        let mut batch = Vec::with_capacity(self.batch_size);
+        self.start_heartbeats();
+        loop {
+            // No runtime to select on here: the wait ends at whichever of
+            // the held update or the tick is due first
+            let held = self.held_update_wait();
+            let tick = self.next_tick.saturating_duration_since(Instant::now());
+            let wait = held.map_or(tick, |held| held.min(tick));
+            let wake = match self.command_rx.recv_many_blocking(&mut batch, self.batch_size, Some(wait)) {
+                Some(received) => Wake::Received(received),
+                None if held.is_some_and(|held| held <= tick) => Wake::HeldUpdate,
+                None => Wake::Tick,
+            };
+            match std::panic::catch_unwind(AssertUnwindSafe(|| self.take_received(wake, &mut batch))) {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(panic) => {
+                    batch.clear();
+                    self = self.recover(panic);
+                }
+            }
+        }
         tracing::info!("Matching engine shutting down");
+        self.stop();
     }
 
-    /// Process a single order request
-    fn process_order(&mut self, request: OrderRequest) {
-        let order = Order::new(request.side, request.price, request.quantity);
-        let order_id = order.id;
-
-        tracing::debug!(
-            order_id = %order_id,
-            side = %order.side,
-            price = %order.price,
-            quantity = %order.quantity,
-            "Processing order"
-        );
-
-        // Match the order against the book
-        let trades = self.order_book.match_order(order);
+    /// How long until a held book update is due, if one is held
+    fn held_update_wait(&self) -> Option<Duration> {
+        self.held_book_update()
+            .map(|due_at| (due_at - self.clock.now()).to_std().unwrap_or_default())
+    }
 
-        // Broadcast trades
-        for trade in &trades {
-            tracing::debug!(
-                trade_id = %trade.id,
-                price = %trade.price,
-                quantity = %trade.quantity,
-                "Trade executed"
-            );
+    /// Act on what the event loop woke for. False once the queue has ended.
+    fn take_received(&mut self, wake: Wake, batch: &mut Vec<EngineCommand>) -> bool {
+        match wake {
+            Wake::Received(0) => return false,
+            Wake::Received(_) => self.process_batch(batch.drain(..)),
+            Wake::HeldUpdate => self.publish_held_book_update(),
+            Wake::Tick => {}
+        }
+        // Checked after every batch too, so a busy engine still ticks; a
+        // batch is at most `batch_size` commands, so ticks run late by one
+        // batch at worst and never hold a command up by more than one tick
+        self.tick_if_due();
+        true
+    }
 
-            // Ignore send errors (no subscribers)
-            let _ = self.event_tx.send(EngineEvent::Trade(trade.clone()));
+    /// Run `on_tick` if the tick is due, and time the next one. Ticks come
+    /// every tick interval of real time, and at least once a heartbeat
+    /// interval.
+    fn tick_if_due(&mut self) {
+        let now = Instant::now();
+        if now < self.next_tick {
+            return;
         }
+        self.next_tick = now + self.tick_interval.min(self.heartbeat_interval);
+        self.on_tick();
+    }
 
-        // Broadcast order book update
-        self.broadcast_book_update();
+    /// The engine's time-driven work, which `run` does every tick whether
+    /// or not commands arrive: orders whose expiry has passed are removed,
+    /// and a halt whose cooldown is over ends, logged like the same sweep
+    /// sent as `ExpireOrders`; then the heartbeat is sent if due. Lets an
+    /// engine driven without its task keep time too.
+    pub fn on_tick(&mut self) {
+        if self.sweep_due(self.clock.now()) {
+            self.process_command(EngineCommand::ExpireOrders);
+        }
+        self.heartbeat_if_due();
     }
 
-    /// Broadcast current order book state
-    fn broadcast_book_update(&mut self) {
-        let snapshot = OrderBookSnapshot {
-            best_bid: self.order_book.best_bid(),
-            best_ask: self.order_book.best_ask(),
-            bid_depth: self.order_book.bid_depth(self.depth_levels),
-            ask_depth: self.order_book.ask_depth(self.depth_levels),
+    /// Replace an engine a command has panicked, whose state can't be
+    /// trusted, with one its `Rebuild` makes from the latest snapshot and
+    /// log. The new engine takes over the old one's channels, so commands
+    /// queued meanwhile are kept and handles never notice; the rest of the
+    /// panicked batch is dropped, its callers told the engine is gone.
+    /// Without a `Rebuild` the panic carries on.
+    fn recover(self, panic: Box<dyn Any + Send>) -> Self {
+        let reason = match (panic.downcast_ref::<&str>(), panic.downcast_ref::<String>()) {
+            (Some(reason), _) => reason.to_string(),
+            (_, Some(reason)) => reason.clone(),
+            _ => "unknown panic".to_string(),
+        };
+        let Some(rebuild) = self.rebuild.clone() else {
+            tracing::error!(symbol = %self.config.symbol, "Matching engine panicked: {reason}");
+            std::panic::resume_unwind(panic);
         };
+        tracing::error!(
+            symbol = %self.config.symbol,
+            voided_record = ?self.running_record,
+            "Matching engine panicked, rebuilding it from its snapshot and log: {reason}"
+        );
+        let voided_record = self.running_record;
+        // The old engine, and its log, are gone before the log is read again
+        let wiring = self.into_wiring();
+        let mut engine = rebuild().recover_with_shared(rebuild).build_wired(Some(wiring)).0;
+        tracing::warn!(symbol = %engine.config.symbol, "Matching engine recovered");
+        engine.start_heartbeats();
+        engine.emit(EngineEvent::Recovered { reason, voided_record });
+        engine
+    }
 
-        // Update shared state for REST API
-        if let Ok(mut state) = self.current_state.try_write() {
-            *state = snapshot.clone();
+    /// What a rebuilt engine takes over from this one; the rest, its log
+    /// writer included, is dropped on return
+    fn into_wiring(self) -> Wiring {
+        Wiring {
+            voided_record: self.running_record,
+            command_rx: self.command_rx,
+            event_tx: self.event_tx,
+            outbound: self.outbound,
+            current_state: self.current_state,
+            heartbeat_tx: self.heartbeat_tx,
+            order_ids: self.order_ids,
+            journal: self.journal,
+            last_24h: self.last_24h,
+            started_at: self.started_at,
+            orders_processed: self.orders_processed,
         }
+    }
 
-        let _ = self.event_tx.send(EngineEvent::OrderBookUpdate(snapshot));
+    /// Count the engine as live from now, with its first heartbeat an
+    /// interval away, and its first tick a tick away
+    fn start_heartbeats(&mut self) {
+        let now = Instant::now();
+        self.next_tick = now + self.tick_interval.min(self.heartbeat_interval);
+        self.next_heartbeat = now + self.heartbeat_interval;
+        self.heartbeat_tx.send_replace(now);
     }
-    #[allow(dead_code)]
-    pub fn stats(&self) -> EngineStats {
-        EngineStats {
-            symbol: self.order_book.symbol.clone(),
-            best_bid: self.order_book.best_bid(),
-            best_ask: self.order_book.best_ask(),
-            spread: self.order_book.spread(),
-            order_count: self.order_book.order_count(),
+
+    /// Send a heartbeat if one is due. It is timed and stamped by the real
+    /// clock, never the engine's, so heartbeats change nothing a replay or
+    /// a deterministic run produces.
+    fn heartbeat_if_due(&mut self) {
+        let now = Instant::now();
+        if now < self.next_heartbeat {
+            return;
         }
+        self.next_heartbeat = now + self.heartbeat_interval;
+        self.heartbeats += 1;
+        self.heartbeat_tx.send_replace(now);
+        self.broadcast(SequencedEvent {
+            seq: self.event_sequence,
+            symbol: self.event_symbol.clone(),
+            event: EngineEvent::Heartbeat {
+                seq: self.heartbeats,
+                timestamp: Utc::now(),
+                orders_processed: self.orders_processed,
+            },
+        });
     }
-}
-
-/// Engine statistics
-#[derive(Debug, Clone)]
-pub struct EngineStats {
-    pub symbol: String,
-    pub best_bid: Option<rust_decimal::Decimal>,
-    pub best_ask: Option<rust_decimal::Decimal>,
-    pub spread: Option<rust_decimal::Decimal>,
-    pub order_count: usize,
-}
-
-/// Builder for creating the matching engine and its channels
-pub struct EngineBuilder {
-    symbol: String,
-    order_buffer_size: usize,
-    event_buffer_size: usize,
-}
 
-impl EngineBuilder {
-    pub fn new(symbol: impl Into<String>) -> Self {
-        Self {
-            symbol: symbol.into(),
-            order_buffer_size: 10_000,
-            event_buffer_size: 1_000,
+    /// Publish the final book and the `Stopped` event, then hand the final
+    /// state to anyone waiting on the shutdown
+    fn stop(&mut self) {
+        let bid_depth = self.order_book.bid_depth(self.depth_levels);
+        let ask_depth = self.order_book.ask_depth(self.depth_levels);
+        self.publish_book(bid_depth, ask_depth);
+        self.emit(EngineEvent::Stopped);
+        if self.shutdown_replies.is_empty() {
+            return;
         }
+        let snapshot = self.snapshot();
+        let replies = std::mem::take(&mut self.shutdown_replies);
+        self.reply(move || {
+            for reply in replies {
+                let _ = reply.send(snapshot.clone());
+            }
+        });
     }
 
-    #[allow(dead_code)]
-    pub fn order_buffer_size(mut self, size: usize) -> Self {
-        self.order_buffer_size = size;
-        self
+    /// The engine's full state: what `EngineBuilder::restore_from` needs to
+    /// carry on from here
+    pub fn snapshot(&self) -> EngineSnapshot {
+        EngineSnapshot {
+            symbol: self.config.symbol.clone(),
+            event_sequence: self.event_sequence,
+            book_sequence: self.book_sequence,
+            next_sequence: self.next_sequence,
+            next_order_id: Some(self.order_ids.peek()),
+            wal_sequence: self.wal_sequence,
+            resting: self.order_book.resting_orders().cloned().collect(),
+            stops: self.stops.iter().cloned().collect(),
+            halted_orders: self.halted_orders.iter().cloned().collect(),
+            halted_until: self.breaker.as_ref().and_then(CircuitBreaker::halted_until),
+            last_trade_price: self.last_trade_price,
+            trade_count: self.trades.count,
+            traded_volume: self.trades.volume,
+            traded_notional: self.trades.notional,
+            in_auction: self.in_auction,
+            next_trade_id: self.next_trade_id,
+            id_epoch: Some(self.epoch),
+            quotes: self.quotes.clone(),
+            blocked_owners: self.blocked_owners.iter().cloned().collect(),
+            rate_limits: Some(self.rate_limiter.limits().clone()),
+            recent_trades: self.recent_trades.iter().map(|trade| Trade::clone(trade)).collect(),
+            depth_levels: Some(self.depth_levels),
+            min_update_interval: Some(self.min_update_interval),
+            last_book_published: self.last_book_published,
+            taken_at: self.clock.now(),
+        }
     }
 
-    #[allow(dead_code)]
-    pub fn event_buffer_size(mut self, size: usize) -> Self {
-        self.event_buffer_size = size;
-        self
+    /// Take up the state of a snapshot on a freshly built engine. Nothing
+    /// is broadcast.
+    fn restore(&mut self, snapshot: EngineSnapshot) {
+        self.event_sequence = snapshot.event_sequence;
+        self.book_sequence = snapshot.book_sequence;
+        self.next_sequence = snapshot.next_sequence;
+        // Older snapshots gave orders their arrival sequence as their id
+        let next_order_id = snapshot.next_order_id.unwrap_or(snapshot.next_sequence);
+        self.order_ids.reserve_through(next_order_id.saturating_sub(1));
+        self.wal_sequence = snapshot.wal_sequence;
+        for order in snapshot.resting {
+            // Crossing is allowed for a book saved during an auction
+            self.order_book.add_auction_order(order);
+        }
+        for order in snapshot.stops {
+            self.stops.insert(order);
+        }
+        match (&mut self.breaker, snapshot.halted_until) {
+            (Some(breaker), Some(until)) => {
+                breaker.halt_until(until);
+                self.halted_orders = snapshot.halted_orders.into();
+            }
+            _ if !snapshot.halted_orders.is_empty() => {
+                tracing::warn!(
+                    dropped = snapshot.halted_orders.len(),
+                    "Orders queued during a halt dropped: the restored engine is not halted"
+                );
+            }
+            _ => {}
+        }
+        self.last_trade_price = snapshot.last_trade_price;
+        self.trades = TradeTotals {
+            count: snapshot.trade_count,
+            volume: snapshot.traded_volume,
+            notional: snapshot.traded_notional,
+        };
+        self.in_auction = snapshot.in_auction;
+        self.next_trade_id = snapshot.next_trade_id.max(1);
+        self.epoch = snapshot.id_epoch.unwrap_or(self.epoch);
+        self.quotes = snapshot.quotes;
+        self.blocked_owners = snapshot.blocked_owners.into_iter().collect();
+        if let Some(limits) = snapshot.rate_limits {
+            self.rate_limiter = RateLimiter::new(limits);
+        }
+        self.recent_trades = snapshot.recent_trades.into_iter().map(Arc::new).collect();
+        self.depth_levels = snapshot.depth_levels.unwrap_or(self.depth_levels);
+        self.min_update_interval = snapshot.min_update_interval.unwrap_or(self.min_update_interval);
+        self.last_book_published = snapshot.last_book_published;
+        // As broadcast last, so replayed commands skip the same unchanged updates
+        self.publish_restored_book();
     }
 
-    /// Build the engine and return handles for interaction
-    pub fn build(self) -> (MatchingEngine, EngineHandle) {
-        let (order_tx, order_rx) = mpsc::channel(self.order_buffer_size);
-        let (event_tx, _) = broadcast::channel(self.event_buffer_size);
+    /// Publish the book as rebuilt from a snapshot, without broadcasting it
+    fn publish_restored_book(&mut self) {
+        let bid_depth = self.order_book.bid_depth(self.depth_levels);
+        let ask_depth = self.order_book.ask_depth(self.depth_levels);
+        let book = self.snapshot_of(bid_depth, ask_depth, false);
+        self.current_state.send_replace(book.clone());
+        self.last_book_update = Some(Arc::new(book));
+    }
 
-        let initial_snapshot = OrderBookSnapshot {
-            best_bid: None,
-            best_ask: None,
-            bid_depth: Vec::new(),
-            ask_depth: Vec::new(),
-        };
+    /// Process a single engine command, as a batch of one. Lets the engine
+    /// be driven without its task, as the engine benchmark does.
+    pub fn process_command(&mut self, command: EngineCommand) {
+        self.process_batch([command]);
+    }
 
-        let current_state = Arc::new(tokio::sync::RwLock::new(initial_snapshot));
+    /// Process commands taken from the queue together, as `run` does with
+    /// each batch it receives. Trades and fills are broadcast as they
+    /// happen, but the book is published once, after the last command, and
+    /// confirmations wait for that update so the current state already
+    /// shows their orders. With a write-ahead log, a state-changing command
+    /// is logged before it runs, with the ids of the orders it makes.
+    pub fn process_batch(&mut self, commands: impl IntoIterator<Item = EngineCommand>) {
+        let mut commands = commands.into_iter().peekable();
+        let mut batched = false;
+        self.command_started = Some(Instant::now());
+        while let Some(mut command) = commands.next() {
+            self.stamp_ids(&mut command);
+            let now = self.clock.now();
+            if let Some(logged) = self.wal.as_ref().and_then(|_| self.loggable(&command, now)) {
+                let wal = self.wal.as_mut().unwrap();
+                match wal.append(now, self.epoch, batched, logged) {
+                    Ok(seq) => {
+                        self.wal_sequence = seq;
+                        self.running_record = Some(seq);
+                        batched = true;
+                    }
+                    Err(e) => tracing::error!("Write-ahead log append failed, command not durable: {e}"),
+                }
+            }
+            self.run_command(command, now, commands.peek().is_none());
+            self.running_record = None;
+        }
+        self.command_started = None;
+    }
 
-        let engine = MatchingEngine::new(self.symbol, order_rx, event_tx.clone(), current_state.clone());
+    /// Process a logged command again, at its logged time and under its
+    /// logged epoch, so it has exactly the effect it had the first time:
+    /// the counters it draws ids from are where they were then.
+    /// `ends_batch` when the next record was not logged in the same batch.
+    fn replay(&mut self, record: WalRecord, ends_batch: bool) {
+        self.wal_sequence = record.seq;
+        self.epoch = record.epoch;
+        if let Some(mut command) = record.command.into_command() {
+            self.stamp_ids(&mut command);
+            self.run_command(command, record.at, ends_batch);
+        }
+    }
 
-        let handle = EngineHandle {
-            order_tx,
-            event_tx,
-            current_state,
+    /// Give the orders `command` makes the next ids, unless a handle
+    /// reserved theirs, and never give out the ids they have again. Logged
+    /// with their ids, orders replay as they ran whatever was reserved since.
+    fn stamp_ids(&self, command: &mut EngineCommand) {
+        let stamp = |id: &mut Option<OrderId>| match *id {
+            Some(reserved) => self.order_ids.reserve_through(reserved),
+            None => *id = Some(self.order_ids.reserve()),
         };
-
-        (engine, handle)
+        match command {
+            EngineCommand::Submit(request) | EngineCommand::SubmitConfirmed { request, .. } => stamp(&mut request.id),
+            // Taken whether or not the amendment ends up needing a replacement
+            EngineCommand::Modify { new_order_id, .. } => stamp(new_order_id),
+            EngineCommand::Quote(quote) => {
+                let [mut bid, mut ask] = quote.order_ids.map_or([None; 2], |ids| ids.map(Some));
+                stamp(&mut bid);
+                stamp(&mut ask);
+                quote.order_ids = bid.zip(ask).map(|(bid, ask)| [bid, ask]);
+            }
+            _ => {}
+        }
     }
-}
-
-/// Handle for interacting with the matching engine
-#[derive(Clone)]
-pub struct EngineHandle {
-    /// Send orders to the engine
-    pub order_tx: mpsc::Sender<OrderRequest>,
-    /// Subscribe to engine events
-    pub event_tx: broadcast::Sender<EngineEvent>,
-    /// Current order book snapshot
-    pub current_state: Arc<tokio::sync::RwLock<OrderBookSnapshot>>,
-}
 
-impl EngineHandle {
-    /// Submit an order to the engine
-    pub async fn submit_order(&self, request: OrderRequest) -> Result<(), mpsc::error::SendError<OrderRequest>> {
-        self.order_tx.send(request).await
+    /// The form `command` is logged in, if it can change anything. An
+    /// expiry sweep with nothing due is left out of the log; sweepers may
+    /// send one every few milliseconds.
+    fn loggable(&self, command: &EngineCommand, now: DateTime<Utc>) -> Option<LoggedCommand> {
+        if let EngineCommand::ExpireOrders = command {
+            if !self.sweep_due(now) {
+                return None;
+            }
+        }
+        LoggedCommand::of(command)
     }
 
-    /// Subscribe to engine events
-    pub fn subscribe(&self) -> broadcast::Receiver<EngineEvent> {
-        self.event_tx.subscribe()
+    /// Whether an expiry sweep at `now` would remove an order or end a halt
+    fn sweep_due(&self, now: DateTime<Utc>) -> bool {
+        let expiry_due = self.order_book.next_expiry().is_some_and(|at| at <= now);
+        let resume_due = self.breaker.as_ref().and_then(CircuitBreaker::halted_until).is_some_and(|at| at <= now);
+        expiry_due || resume_due
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::engine::order::Side;
-    use rust_decimal_macros::dec;
-    use tokio::time::{timeout, Duration};
+    /// The time the current command runs at, or the clock's between commands
+    fn now(&self) -> DateTime<Utc> {
+        self.command_time.unwrap_or_else(|| self.clock.now())
+    }
 
-    #[tokio::test]
-    async fn test_engine_processes_orders() {
-        let (engine, handle) = EngineBuilder::new("BTC/USD").build();
-        let mut events = handle.subscribe();
+    /// Process `command` as of `now`, and publish the book afterwards if
+    /// it `ends_batch`
+    fn run_command(&mut self, command: EngineCommand, now: DateTime<Utc>, ends_batch: bool) {
+        self.command_time = Some(now);
+        self.in_batch = true;
+        // Sent now if the timer would have sent it before this command
+        if self.held_book_update().is_some_and(|due_at| due_at <= now) {
+            self.publish_held_book_update();
+        }
+        self.dispatch(command);
+        self.time_order();
+        if ends_batch {
+            self.end_batch();
+        }
+        self.command_time = None;
+    }
 
-        // Spawn the engine
-        tokio::spawn(engine.run());
+    /// Record how long the order just processed took, from the engine
+    /// taking up its command, and its log append, to its events being sent.
+    /// One clock read times each command: where it ends, the next starts.
+    /// `Instant` reads the monotonic clock without a syscall on Linux.
+    fn time_order(&mut self) {
+        let matched = self.timed_match.take();
+        let Some(started) = self.command_started else {
+            return;
+        };
+        let finished = Instant::now();
+        self.command_started = Some(finished);
+        if let Some(kind) = matched {
+            self.latency.record(kind, finished - started);
+        }
+    }
 
-        // Submit a sell order
-        handle
-            .submit_order(OrderRequest {
-                side: Side::Sell,
-                price: dec!(100),
-                quantity: dec!(10),
-            })
-            .await
-            .unwrap();
+    /// Send the book update the batch owes, then the confirmations
+    /// waiting on it
+    fn end_batch(&mut self) {
+        self.in_batch = false;
+        self.flush_book_update();
+        if self.acks.is_empty() && self.cancel_acks.is_empty() {
+            return;
+        }
+        let acks = std::mem::take(&mut self.acks);
+        let cancel_acks = std::mem::take(&mut self.cancel_acks);
+        self.reply(move || {
+            for (reply, ack) in acks {
+                let _ = reply.send(ack);
+            }
+            for (reply, ack) in cancel_acks {
+                let _ = reply.send(ack);
+            }
+        });
+    }
 
-        // Wait for update
-        let event = timeout(Duration::from_millis(100), events.recv())
-            .await
-            .unwrap()
-            .unwrap();
+    fn dispatch(&mut self, command: EngineCommand) {
+        self.resume_if_due();
 
-        match event {
-            EngineEvent::OrderBookUpdate { best_ask, .. } => {
-                assert_eq!(best_ask, Some(dec!(100)));
+        match command {
+            EngineCommand::Submit(request) => self.process_order(request),
+            EngineCommand::SubmitConfirmed { request, reply } => {
+                self.confirming = Some(Confirming {
+                    // Known once the engine numbers the order
+                    order_id: 0,
+                    quantity: request.quantity,
+                    rejection: None,
+                    filled: Decimal::ZERO,
+                    notional: Decimal::ZERO,
+                });
+                self.process_order(request);
+                if let Some(confirming) = self.confirming.take() {
+                    let ack = self.acknowledge(confirming);
+                    self.acks.push((reply, ack));
+                }
+            }
+            EngineCommand::Cancel { order_id } => {
+                let _ = self.cancel_order(order_id, None);
+            }
+            EngineCommand::CancelConfirmed {
+                order_id,
+                owner_id,
+                reply,
+            } => {
+                let outcome = self.cancel_order(order_id, owner_id.as_deref()).map(|order| CancelAck {
+                    order_id,
+                    filled_quantity: order.quantity - order.remaining_quantity,
+                    remaining_quantity: order.remaining_quantity,
+                });
+                self.cancel_acks.push((reply, outcome));
+            }
+            EngineCommand::CancelAll { owner_id, side } => self.cancel_all(owner_id, side),
+            EngineCommand::Quote(quote) => self.process_quote(quote),
+            EngineCommand::KillSwitch { owner_id } => self.engage_kill_switch(owner_id),
+            EngineCommand::ReleaseKillSwitch { owner_id } => {
+                if self.blocked_owners.remove(&owner_id) {
+                    tracing::warn!(owner_id = %owner_id, "Kill switch released");
+                    self.emit(EngineEvent::KillSwitchReleased { owner_id });
+                }
+            }
+            EngineCommand::BustTrade { trade_id } => self.bust_trade(trade_id),
+            EngineCommand::Modify {
+                order_id,
+                new_price,
+                new_quantity,
+                new_order_id,
+            } => self.modify_order(order_id, new_price, new_quantity, new_order_id),
+            EngineCommand::ExpireOrders => {
+                if self.expire_orders(self.now()) {
+                    self.book_changed();
+                }
+            }
+            EngineCommand::StartAuction => self.start_auction(),
+            EngineCommand::EndAuction => self.end_auction(),
+            EngineCommand::GroupedBook { bucket_size, reply } => {
+                let _ = reply.send(self.book_snapshot(Some(bucket_size), self.depth_levels));
+            }
+            EngineCommand::DeepBook { levels, reply } => {
+                let _ = reply.send(self.book_snapshot(None, levels));
+            }
+            EngineCommand::Liquidity { distance, reply } => {
+                let _ = reply.send(LiquiditySnapshot {
+                    distance,
+                    bid_liquidity: self.order_book.liquidity_within(Side::Buy, distance),
+                    ask_liquidity: self.order_book.liquidity_within(Side::Sell, distance),
+                    bid_depth: self.order_book.cumulative_depth(Side::Buy, self.depth_levels),
+                    ask_depth: self.order_book.cumulative_depth(Side::Sell, self.depth_levels),
+                });
+            }
+            EngineCommand::PriceImpact { side, quantity, reply } => {
+                let _ = reply.send(self.order_book.vwap_for_quantity(side, quantity));
+            }
+            EngineCommand::QueryLatency { reply } => {
+                let _ = reply.send(self.latency.summary());
+            }
+            EngineCommand::QueryStats { reply } => {
+                let _ = reply.send(self.stats());
+            }
+            EngineCommand::QueryWindow { reply } => {
+                let _ = reply.send((self.event_sequence, self.last_24h.clone()));
+            }
+            EngineCommand::QueryOrder { order_id, reply } => {
+                let _ = reply.send(self.open_order(order_id).cloned());
+            }
+            EngineCommand::ListOrders {
+                owner_id,
+                side,
+                limit,
+                reply,
+            } => {
+                let _ = reply.send(self.list_orders(owner_id.as_deref(), side, limit));
+            }
+            EngineCommand::SetBookUpdates {
+                depth_levels,
+                min_update_interval,
+            } => {
+                if let Some(levels) = depth_levels.filter(|&levels| levels > 0) {
+                    self.depth_levels = levels;
+                    self.book_changed();
+                }
+                if let Some(interval) = min_update_interval {
+                    self.min_update_interval = interval;
+                }
+                tracing::info!(
+                    depth_levels = self.depth_levels,
+                    min_update_interval = ?self.min_update_interval,
+                    "Book update settings changed"
+                );
+            }
+            EngineCommand::SetRateLimit { owner_id, limit } => {
+                tracing::info!(owner_id = ?owner_id, limit = ?limit, "Rate limit changed");
+                self.rate_limiter.set(owner_id, limit);
+            }
+            EngineCommand::Snapshot { reply } => {
+                // A snapshot never leaves an update owed, so restoring from
+                // it publishes the same updates as carrying on would
+                self.send_owed_book_update();
+                let _ = reply.send(self.snapshot());
+            }
+            EngineCommand::Shutdown { reply } => {
+                // Senders now fail; what is already queued is still received
+                self.command_rx.close();
+                self.shutdown_replies.push(reply);
             }
-            _ => panic!("Expected OrderBookUpdate"),
         }
     }
 
-    #[tokio::test]
-    async fn test_engine_generates_trades() {
-        let (engine, handle) = EngineBuilder::new("BTC/USD").build();
-        let mut events = handle.subscribe();
-
-        tokio::spawn(engine.run());
+    /// Validate the book once every configured number of orders
+    fn check_book(&mut self) {
+        let Some((every, policy)) = self.book_check else {
+            return;
+        };
+        self.orders_since_check += 1;
+        if self.orders_since_check < every {
+            return;
+        }
+        self.orders_since_check = 0;
 
-        // Submit a sell order
-        handle
-            .submit_order(OrderRequest {
-                side: Side::Sell,
-                price: dec!(100),
-                quantity: dec!(10),
-            })
-            .await
-            .unwrap();
+        let violation = match self.order_book.validate() {
+            // Auctions collect crossing orders by design
+            Err(BookInvariantError::CrossedBook { .. }) if self.in_auction => return,
+            Ok(()) => return,
+            Err(violation) => violation,
+        };
+        match policy {
+            InvariantPolicy::Log => tracing::error!(error = %violation, "Order book invariant violated"),
+            InvariantPolicy::Panic => panic!("order book invariant violated: {violation}"),
+        }
+    }
 
-        // Drain the book update
-        let _ = timeout(Duration::from_millis(100), events.recv()).await;
+    /// Process a single order request, then run any due book check
+    fn process_order(&mut self, request: OrderRequest) {
+        self.place_order(request);
+        self.timed_order = 0;
+        self.check_book();
+    }
 
-        // Submit a matching buy order
-        handle
-            .submit_order(OrderRequest {
-                side: Side::Buy,
-                price: dec!(100),
-                quantity: dec!(10),
-            })
-            .await
-            .unwrap();
+    /// Validate, match and rest (or hold) a single order
+    fn place_order(&mut self, request: OrderRequest) {
+        let now = self.now();
+        // Sweep first so an expired order can never match, however recently it expired
+        let expired = self.expire_orders(now);
 
-        // Should receive a trade event
-        let event = timeout(Duration::from_millis(100), events.recv())
+        // Numbered on arrival, so even a rejected order has an id to report
+        let order = self.new_order(&request, now);
+        #[cfg(test)]
+        if request.owner_id.as_deref() == Some(PANIC_OWNER) {
+            panic!("order {} from {PANIC_OWNER}", order.id);
+        }
+        let order_id = order.id;
+        // What matching does with it decides which latency histogram it's timed in
+        self.timed_order = order_id;
+        if let Some(confirming) = self.confirming.as_mut() {
+            confirming.order_id = order_id;
+        }
+
+        tracing::debug!(
+            order_id = %order_id,
+            side = %order.side,
+            price = %order.price,
+            quantity = %order.quantity,
+            "Processing order"
+        );
+
+        let reference = self.reference_price();
+        // Checked first, so an owner over its limit costs the engine nothing more
+        let limited = match order.owner_id.as_deref() {
+            Some(owner) => self.rate_limiter.try_take(owner, 1, now).err(),
+            None => None,
+        };
+        let blocked = order.owner_id.as_ref().is_some_and(|owner| self.blocked_owners.contains(owner));
+        let invalid = if let Some(limit) = limited {
+            Some(RejectReason::RateLimited {
+                per_second: limit.per_second,
+            })
+        } else if blocked {
+            Some(RejectReason::OwnerBlocked)
+        } else if let Err(reason) = self
+            .config
+            .validate(&request)
+            .and_then(|_| self.config.check_price_band(&request, reference))
+        {
+            Some(reason)
+        } else if order.time_in_force == TimeInForce::Gtd && order.expires_at.is_none() {
+            Some(RejectReason::MissingExpiry)
+        } else if order.is_expired(now) {
+            Some(RejectReason::AlreadyExpired)
+        } else if let Err(reason) = self.check_open_order_limit(&order) {
+            Some(reason)
+        } else if self.client_order_in_use(&order) {
+            Some(RejectReason::DuplicateClientOrderId {
+                client_order_id: order.client_order_id.clone().unwrap_or_default(),
+            })
+        } else {
+            None
+        };
+        if let Some(reason) = invalid {
+            self.reject(&order, reason);
+            if expired {
+                self.book_changed();
+            }
+            return;
+        }
+        self.accepted(&order);
+
+        // Stops are held off the book unless the last trade already satisfies the trigger
+        if let Some(stop_price) = order.stop_price {
+            let already_triggered = self
+                .last_trade_price
+                .is_some_and(|p| StopBook::is_triggered(order.side, stop_price, p, p));
+            if !already_triggered {
+                tracing::debug!(order_id = %order_id, stop_price = %stop_price, "Stop order pending");
+                self.stops.insert(order);
+                if expired {
+                    self.book_changed();
+                }
+                return;
+            }
+            self.stop_triggered(&order);
+        }
+
+        // Broadcast order book update
+        let executed = self.execute(order);
+        if expired || executed {
+            self.book_changed();
+        }
+    }
+
+    /// Refuse an order that would take its owner past the open order cap.
+    /// Orders that cannot rest never add to the count, so they always pass.
+    fn check_open_order_limit(&self, order: &Order) -> Result<(), RejectReason> {
+        let (Some(limit), Some(owner_id)) = (self.max_open_orders, order.owner_id.as_deref()) else {
+            return Ok(());
+        };
+        if (order.can_rest() || order.stop_price.is_some()) && self.open_order_count(owner_id) >= limit {
+            return Err(RejectReason::TooManyOpenOrders { limit });
+        }
+        Ok(())
+    }
+
+    /// Apply the open order cap to a quote, counting the owner's previous
+    /// quote orders as already gone
+    fn check_quote_limit(&self, quote: &QuoteRequest) -> Result<(), RejectReason> {
+        let Some(limit) = self.max_open_orders else {
+            return Ok(());
+        };
+        let replaced = self.quotes.get(&quote.owner_id).map_or(0, |(bid_id, ask_id)| {
+            [*bid_id, *ask_id]
+                .into_iter()
+                .filter(|id| self.order_book.get_order(*id).is_some() || self.halted_orders.iter().any(|o| o.id == *id))
+                .count()
+        });
+        if self.open_order_count(&quote.owner_id) - replaced + 2 > limit {
+            return Err(RejectReason::TooManyOpenOrders { limit });
+        }
+        Ok(())
+    }
+
+    /// Open order `order_id`, wherever the engine holds it: resting,
+    /// collected for an auction, pending as a stop or queued during a halt
+    fn open_order(&self, order_id: OrderId) -> Option<&Order> {
+        self.order_book
+            .get_order(order_id)
+            .or_else(|| self.stops.iter().find(|o| o.id == order_id))
+            .or_else(|| self.halted_orders.iter().find(|o| o.id == order_id))
+    }
+
+    /// Resting orders of `owner_id` on `side`, either filter left out where
+    /// `None`; the first `limit` by price then time are copied
+    fn list_orders(&self, owner_id: Option<&str>, side: Option<Side>, limit: usize) -> OrderListing {
+        let mut matching: Vec<&Order> = self
+            .order_book
+            .resting_orders()
+            .filter(|o| owner_id.is_none_or(|owner| o.owner_id.as_deref() == Some(owner)))
+            .filter(|o| side.is_none_or(|side| o.side == side))
+            .collect();
+        matching.sort_by_key(|o| (o.price, o.sequence));
+        OrderListing {
+            total: matching.len(),
+            orders: matching.into_iter().take(limit).cloned().collect(),
+            as_of: self.now(),
+        }
+    }
+
+    /// Resting, pending stop and halt-queued orders belonging to `owner_id`
+    fn open_order_count(&self, owner_id: &str) -> usize {
+        let owned = |o: &&Order| o.owner_id.as_deref() == Some(owner_id);
+        self.order_book.open_order_count(owner_id)
+            + self.stops.iter().filter(owned).count()
+            + self.halted_orders.iter().filter(owned).count()
+    }
+
+    /// Whether the order's owner already has an open order (resting, pending
+    /// stop or queued during a halt) under the same client order id
+    fn client_order_in_use(&self, order: &Order) -> bool {
+        let Some(client_order_id) = order.client_order_id.as_deref() else {
+            return false;
+        };
+        let matches =
+            |o: &Order| o.owner_id == order.owner_id && o.client_order_id.as_deref() == Some(client_order_id);
+        self.order_book
+            .find_client_order(order.owner_id.as_deref(), client_order_id)
+            .is_some()
+            || self.stops.iter().any(matches)
+            || self.halted_orders.iter().any(matches)
+    }
+
+    /// Stop matching and collect orders for a call auction
+    fn start_auction(&mut self) {
+        if self.in_auction {
+            return;
+        }
+        tracing::info!("Auction started");
+        self.in_auction = true;
+        self.emit(EngineEvent::AuctionStarted);
+        self.book_changed();
+    }
+
+    /// Uncross the auction book and return to continuous matching
+    fn end_auction(&mut self) {
+        if !self.in_auction {
+            return;
+        }
+        self.in_auction = false;
+        let now = self.now();
+        self.expire_orders(now);
+
+        let mut outcome = self.order_book.uncross(self.last_trade_price, now);
+        for order in &outcome.self_trade_cancelled {
+            self.cancelled(order.id, order.remaining_quantity, CancelReason::SelfTrade);
+        }
+        for order in &outcome.residual_cancelled {
+            self.cancelled(order.id, order.remaining_quantity, CancelReason::Residual);
+        }
+        let price = outcome.trades.first().map(|t| t.price);
+        let volume: Decimal = outcome.trades.iter().map(|t| t.quantity).sum();
+        tracing::info!(price = ?price, volume = %volume, "Auction uncrossed");
+
+        let released = self.publish_trades(&mut outcome.trades, &mut outcome.fills);
+        self.emit(EngineEvent::AuctionEnded { price, volume });
+        for stop in released {
+            self.execute(stop);
+        }
+        self.book_changed();
+    }
+
+    /// End a halt whose cooldown has passed and work off any queued orders.
+    /// Runs at the start of every command, so the engine's tick, or a
+    /// sweeper's `ExpireOrders`, also drives resumption.
+    fn resume_if_due(&mut self) {
+        let now = self.now();
+        if !self.breaker.as_mut().is_some_and(|b| b.try_resume(now)) {
+            return;
+        }
+
+        tracing::info!("Trading resumed");
+        self.emit(EngineEvent::TradingResumed);
+
+        let mut executed = false;
+        while let Some(mut order) = self.halted_orders.pop_front() {
+            // Queued orders join the book as of the resume, in arrival order
+            order.timestamp = now;
+            self.assign_sequence(&mut order);
+            executed |= self.execute(order);
+            if self.breaker.as_ref().is_some_and(|b| b.is_halted(self.now())) {
+                // Tripped again; the rest stay queued
+                break;
+            }
+        }
+        if executed {
+            self.book_changed();
+        }
+    }
+
+    /// Hold back an order while trading is halted, rejecting or queueing it
+    /// according to the halt policy. Returns the order if matching may proceed.
+    fn admit(&mut self, order: Order) -> Option<Order> {
+        let Some(breaker) = &self.breaker else {
+            return Some(order);
+        };
+        if !breaker.is_halted(self.now()) {
+            return Some(order);
+        }
+        match breaker.policy() {
+            HaltPolicy::Reject => self.reject(&order, RejectReason::TradingHalted),
+            HaltPolicy::Queue => {
+                tracing::debug!(order_id = %order.id, "Order queued during halt");
+                self.halted_orders.push_back(order);
+            }
+        }
+        None
+    }
+
+    /// Feed trade prints to the circuit breaker, returning the halt to
+    /// broadcast if it trips
+    fn record_trades(&mut self, trades: &[Trade]) -> Option<EngineEvent> {
+        let breaker = self.breaker.as_mut()?;
+        trades.iter().find_map(|trade| {
+            let until = breaker.record_trade(trade.timestamp, trade.price)?;
+            tracing::warn!(price = %trade.price, until = %until, "Circuit breaker tripped");
+            Some(EngineEvent::TradingHalted {
+                until,
+                last_price: trade.price,
+            })
+        })
+    }
+
+    /// Cancel a resting or pending stop order, provided it belongs to
+    /// `owner_id` when one is given, returning the order removed
+    fn cancel_order(&mut self, order_id: OrderId, owner_id: Option<&str>) -> Result<Order, RejectReason> {
+        let expired = self.expire_orders(self.now());
+
+        let open = self.order_book.get_order(order_id).or_else(|| self.stops.iter().find(|o| o.id == order_id));
+        let refusal = match open {
+            None => Some(RejectReason::UnknownOrder),
+            Some(order) if owner_id.is_some_and(|owner| order.owner_id.as_deref() != Some(owner)) => {
+                Some(RejectReason::NotOrderOwner)
+            }
+            Some(_) => None,
+        };
+        if let Some(reason) = refusal {
+            tracing::debug!(order_id = %order_id, reason = %reason, "Cancel rejected");
+            self.emit(EngineEvent::CancelRejected {
+                order_id,
+                reason: reason.clone(),
+            });
+            if expired {
+                self.book_changed();
+            }
+            return Err(reason);
+        }
+
+        if let Some(order) = self.order_book.cancel_order(order_id) {
+            self.cancelled(order.id, order.remaining_quantity, CancelReason::UserRequested);
+            self.book_changed();
+            return Ok(order);
+        }
+        let stop = self.stops.remove(order_id).expect("pending stop was just found");
+        self.cancelled(stop.id, stop.remaining_quantity, CancelReason::UserRequested);
+        if expired {
+            self.book_changed();
+        }
+        Ok(stop)
+    }
+
+    /// Void a recent trade so downstream consumers can back it out.
+    /// Each trade can be busted once.
+    fn bust_trade(&mut self, trade_id: TradeId) {
+        let Some(position) = self.recent_trades.iter().position(|t| t.id == trade_id) else {
+            tracing::debug!(trade_id = %trade_id, "Bust rejected");
+            self.emit(EngineEvent::BustRejected {
+                trade_id,
+                reason: RejectReason::UnknownTrade,
+            });
+            return;
+        };
+        let Some(trade) = self.recent_trades.remove(position) else {
+            return;
+        };
+        tracing::warn!(trade_id = %trade_id, price = %trade.price, quantity = %trade.quantity, "Trade busted");
+        self.trades.remove(&trade);
+        self.last_24h.remove(&trade);
+        self.emit(EngineEvent::TradeBusted(trade));
+    }
+
+    /// Cancel every resting, pending stop and halt-queued order of an owner
+    fn cancel_all(&mut self, owner_id: String, side: Option<Side>) {
+        let count = self.remove_owned(&owner_id, side, CancelReason::MassCancel);
+        tracing::info!(owner_id = %owner_id, count, "Mass cancel");
+        self.emit(EngineEvent::MassCancelled { owner_id, side, count });
+    }
+
+    /// Block an owner and pull all of its orders
+    fn engage_kill_switch(&mut self, owner_id: String) {
+        self.blocked_owners.insert(owner_id.clone());
+        let cancelled = self.remove_owned(&owner_id, None, CancelReason::KillSwitch);
+        tracing::warn!(owner_id = %owner_id, cancelled, "Kill switch engaged");
+        self.emit(EngineEvent::KillSwitchEngaged { owner_id, cancelled });
+    }
+
+    /// Remove an owner's resting, pending stop and halt-queued orders,
+    /// broadcasting a cancellation for each. Returns how many were removed.
+    fn remove_owned(&mut self, owner_id: &str, side: Option<Side>, reason: CancelReason) -> usize {
+        let expired = self.expire_orders(self.now());
+
+        let mut orders = self.order_book.cancel_owned(owner_id, side);
+        let book_changed = !orders.is_empty();
+        orders.extend(self.stops.remove_owned(owner_id, side));
+        let (queued, kept): (VecDeque<_>, VecDeque<_>) = self.halted_orders.drain(..).partition(|o| {
+            o.owner_id.as_deref() == Some(owner_id) && side.is_none_or(|s| s == o.side)
+        });
+        self.halted_orders = kept;
+        orders.extend(queued);
+
+        for order in &orders {
+            self.cancelled(order.id, order.remaining_quantity, reason);
+        }
+        if book_changed || expired {
+            self.book_changed();
+        }
+        orders.len()
+    }
+
+    /// Replace an owner's quote in a single step: the old bid and ask come off
+    /// and the new pair goes on before any other command is processed.
+    /// A quote that fails validation leaves the previous one in place.
+    fn process_quote(&mut self, quote: QuoteRequest) {
+        let now = self.now();
+        let expired = self.expire_orders(now);
+
+        let reference = self.reference_price();
+        // Both sides of a quote count against the owner's rate limit
+        let checked = if let Err(limit) = self.rate_limiter.try_take(&quote.owner_id, 2, now) {
+            Err(RejectReason::RateLimited {
+                per_second: limit.per_second,
+            })
+        } else if self.blocked_owners.contains(&quote.owner_id) {
+            Err(RejectReason::OwnerBlocked)
+        } else {
+            self.config
+                .validate_quote(&quote, reference)
+                .and_then(|_| self.check_quote_limit(&quote))
+        };
+        if let Err(reason) = checked {
+            tracing::debug!(owner_id = %quote.owner_id, reason = %reason, "Quote rejected");
+            self.emit(EngineEvent::QuoteRejected {
+                owner_id: quote.owner_id,
+                reason,
+            });
+            if expired {
+                self.book_changed();
+            }
+            return;
+        }
+
+        let [bid, ask] = quote.orders().map(|request| self.new_order(&request, now));
+        let mut cancelled = Vec::new();
+        if let Some((bid_id, ask_id)) = self.quotes.remove(&quote.owner_id) {
+            for order_id in [bid_id, ask_id] {
+                let order = self.order_book.cancel_order(order_id).or_else(|| {
+                    let position = self.halted_orders.iter().position(|o| o.id == order_id)?;
+                    self.halted_orders.remove(position)
+                });
+                if let Some(order) = order {
+                    self.cancelled(order.id, order.remaining_quantity, CancelReason::Replaced);
+                    cancelled.push(order.id);
+                }
+            }
+        }
+        self.quotes.insert(quote.owner_id.clone(), (bid.id, ask.id));
+        self.emit(EngineEvent::QuoteReplaced {
+            owner_id: quote.owner_id,
+            bid_order_id: bid.id,
+            ask_order_id: ask.id,
+            cancelled,
+        });
+
+        self.execute(bid);
+        self.execute(ask);
+        self.book_changed();
+    }
+
+    /// Amend a resting order.
+    ///
+    /// A pure quantity reduction is applied in place and keeps queue position.
+    /// A price change or size increase cancels the order and re-enters a
+    /// replacement with a new id, `new_order_id` if given, at the back of the
+    /// queue, which may match.
+    fn modify_order(
+        &mut self,
+        order_id: OrderId,
+        new_price: Option<Decimal>,
+        new_quantity: Option<Decimal>,
+        new_order_id: Option<OrderId>,
+    ) {
+        let now = self.now();
+        let expired = self.expire_orders(now);
+
+        let Some(existing) = self.order_book.get_order(order_id) else {
+            self.modify_rejected(order_id, RejectReason::UnknownOrder, expired);
+            return;
+        };
+        let price = new_price.unwrap_or(existing.price).normalize();
+        let quantity = new_quantity.unwrap_or(existing.remaining_quantity).normalize();
+        if price <= Decimal::ZERO || quantity <= Decimal::ZERO {
+            self.modify_rejected(order_id, RejectReason::InvalidModification, expired);
+            return;
+        }
+        // A larger or repriced order is held to the same precision and size limits as a new one
+        let amended = OrderRequest::new(existing.side, price, quantity);
+        if let Err(reason) = self
+            .config
+            .check_scale(&amended)
+            .and_then(|_| self.config.check_price_grid(&amended))
+            .and_then(|_| self.config.check_risk_limits(&amended))
+        {
+            self.modify_rejected(order_id, reason, expired);
+            return;
+        }
+
+        if price == existing.price && quantity <= existing.remaining_quantity {
+            let filled = existing.quantity - existing.remaining_quantity;
+            let reduced = match self.order_book.reduce_order(order_id, filled + quantity) {
+                Ok(reduced) => reduced,
+                Err(_) => {
+                    self.modify_rejected(order_id, RejectReason::InvalidModification, expired);
+                    return;
+                }
+            };
+            self.emit(EngineEvent::OrderModified {
+                order_id,
+                new_order_id: order_id,
+                price,
+                remaining_quantity: reduced.remaining_quantity,
+            });
+            self.book_changed();
+            return;
+        }
+
+        let Some(original) = self.order_book.cancel_order(order_id) else {
+            return;
+        };
+        self.cancelled(order_id, original.remaining_quantity, CancelReason::Replaced);
+
+        let mut replacement = original;
+        self.assign_sequence(&mut replacement);
+        replacement.id = new_order_id.unwrap_or_else(|| self.order_ids.reserve());
+        replacement.price = price;
+        replacement.quantity = quantity;
+        replacement.remaining_quantity = quantity;
+        replacement.timestamp = now;
+        replacement.status = OrderStatus::Open;
+
+        tracing::debug!(order_id = %order_id, new_order_id = %replacement.id, "Order replaced");
+        self.emit(EngineEvent::OrderModified {
+            order_id,
+            new_order_id: replacement.id,
+            price,
+            remaining_quantity: quantity,
+        });
+        self.execute(replacement);
+        self.book_changed();
+    }
+
+    /// Broadcast that a modify request was refused
+    fn modify_rejected(&mut self, order_id: OrderId, reason: RejectReason, book_changed: bool) {
+        tracing::debug!(order_id = %order_id, reason = %reason, "Modify rejected");
+        self.emit(EngineEvent::ModifyRejected { order_id, reason });
+        if book_changed {
+            self.book_changed();
+        }
+    }
+
+    /// Remove expired resting orders, broadcasting a cancellation for each.
+    /// Returns whether anything was removed.
+    fn expire_orders(&mut self, now: chrono::DateTime<chrono::Utc>) -> bool {
+        let expired = self.order_book.expire_orders(now);
+        for order in &expired {
+            self.cancelled(order.id, order.remaining_quantity, CancelReason::Expired);
+        }
+        !expired.is_empty()
+    }
+
+    /// Match an order against the book, then any stops its trades trigger.
+    ///
+    /// Triggered stops are queued and matched in turn rather than recursively,
+    /// so a cascade is bounded by the number of pending stops.
+    /// Returns whether anything reached the book.
+    fn execute(&mut self, order: Order) -> bool {
+        let mut queue = VecDeque::from([order]);
+        let mut book_changed = false;
+
+        while let Some(order) = queue.pop_front() {
+            let Some(mut order) = self.admit(order) else {
+                continue;
+            };
+
+            // A stop released after its expiry never reaches the book
+            if order.is_expired(self.now()) {
+                self.cancelled(order.id, order.remaining_quantity, CancelReason::Expired);
+                continue;
+            }
+
+            if let Err(reason) = self.check_book_limit(&order) {
+                self.reject(&order, reason);
+                continue;
+            }
+
+            // Auctions only collect orders; uncrossing happens at the end
+            if self.in_auction {
+                if order.can_rest() {
+                    let (order_id, price, quantity) = (order.id, order.price, order.remaining_quantity);
+                    self.order_book.add_auction_order(order);
+                    self.rested(order_id, price, quantity);
+                    book_changed = true;
+                } else {
+                    self.reject(&order, RejectReason::AuctionOrderNotAllowed);
+                }
+                continue;
+            }
+
+            // Slippage is measured from the market as it stands when the order arrives
+            if order.order_type == OrderType::Market {
+                order.protection_price = self.protection_price(&order);
+            }
+
+            // Post-only orders must rest; reject if they would take liquidity
+            if order.post_only && self.order_book.would_cross(order.side, order.price) {
+                self.reject(&order, RejectReason::PostOnlyWouldCross);
+                continue;
+            }
+
+            // Fill-or-kill needs the whole quantity available before touching the book
+            if order.time_in_force == TimeInForce::Fok
+                && self.order_book.fillable_quantity(&order) < order.remaining_quantity
+            {
+                self.cancelled(order.id, order.remaining_quantity, CancelReason::FillOrKill);
+                continue;
+            }
+
+            let (order_id, side, price, quantity, can_rest) =
+                (order.id, order.side, order.price, order.remaining_quantity, order.can_rest());
+            let protected = order.protection_price.is_some();
+
+            // Match the order against the book
+            let mut outcome = std::mem::take(&mut self.matched);
+            self.order_book.match_order_into(order, &mut outcome);
+            book_changed = true;
+            if order_id == self.timed_order {
+                self.timed_match = Some(match outcome.trades.as_slice() {
+                    [] => MatchKind::Rested,
+                    [first, rest @ ..] if rest.iter().all(|t| t.price == first.price) => MatchKind::Matched,
+                    _ => MatchKind::Swept,
+                });
+            }
+
+            for maker in &outcome.self_trade_cancelled {
+                self.cancelled(maker.id, maker.remaining_quantity, CancelReason::SelfTrade);
+            }
+            for maker in &outcome.self_trade_decremented {
+                self.decremented(maker.id, maker.price, maker.remaining_quantity);
+            }
+            for maker in &outcome.residual_cancelled {
+                self.cancelled(maker.id, maker.remaining_quantity, CancelReason::Residual);
+            }
+            if !outcome.taker_residual.is_zero() {
+                self.cancelled(order_id, outcome.taker_residual, CancelReason::Residual);
+            }
+
+            let filled: Decimal = outcome.trades.iter().map(|t| t.quantity).sum();
+            let unfilled = quantity - filled - outcome.taker_self_trade;
+
+            // Self-trade prevention either ends the incoming order or shrinks it
+            if !outcome.taker_self_trade.is_zero() {
+                if unfilled.is_zero() {
+                    self.cancelled(order_id, outcome.taker_self_trade, CancelReason::SelfTrade);
+                } else {
+                    self.decremented(order_id, price, unfilled);
+                }
+            }
+
+            // Remainders that may not rest are cancelled, as is any a refused fill left
+            let refused = outcome.fill_refused.is_some();
+            if (!can_rest || refused) && !unfilled.is_zero() {
+                // A market order only stops short of remaining liquidity at its bound
+                let opposing = match side {
+                    Side::Buy => self.order_book.best_ask(),
+                    Side::Sell => self.order_book.best_bid(),
+                };
+                let reason = if refused {
+                    CancelReason::FillRefused
+                } else if protected && opposing.is_some() {
+                    CancelReason::SlippageLimit
+                } else {
+                    CancelReason::Unfilled
+                };
+                self.cancelled(order_id, unfilled, reason);
+            }
+
+            if can_rest && self.order_book.get_order(order_id).is_some() {
+                self.enforce_book_limit(side, order_id);
+            }
+
+            queue.extend(self.publish_trades(&mut outcome.trades, &mut outcome.fills));
+            self.matched = outcome;
+            if let Some(remaining) = self.order_book.get_order(order_id).map(|o| o.remaining_quantity) {
+                self.rested(order_id, price, remaining);
+            }
+        }
+
+        book_changed
+    }
+
+    /// Refuse a passive order for a side already at the resting order cap.
+    /// Under `EvictFarthest` only an order at or behind the farthest level
+    /// is refused, since it would be the one evicted; a crossing order is
+    /// let through to trade and any remainder is settled once it rests.
+    /// Auction orders are refused outright: a crossed book has no touch to
+    /// measure from.
+    fn check_book_limit(&self, order: &Order) -> Result<(), RejectReason> {
+        let Some((limit, policy)) = self.book_limit else {
+            return Ok(());
+        };
+        if !order.can_rest() || self.order_book.side_order_count(order.side) < limit {
+            return Ok(());
+        }
+        if !self.in_auction && self.order_book.would_cross(order.side, order.price) {
+            return Ok(());
+        }
+        let behind_farthest = self.order_book.farthest_price(order.side).is_none_or(|farthest| match order.side {
+            Side::Buy => order.price <= farthest,
+            Side::Sell => order.price >= farthest,
+        });
+        if policy == BookLimitPolicy::Reject || self.in_auction || behind_farthest {
+            return Err(RejectReason::BookFull { limit });
+        }
+        Ok(())
+    }
+
+    /// Bring `side` back within the resting order cap after `order_id`
+    /// rested there, evicting from the far end under `EvictFarthest`. When
+    /// nothing can be evicted, or under `Reject`, the newcomer's remainder
+    /// is cancelled instead; the best level is never evicted.
+    fn enforce_book_limit(&mut self, side: Side, order_id: OrderId) {
+        let Some((limit, policy)) = self.book_limit else {
+            return;
+        };
+        while self.order_book.side_order_count(side) > limit {
+            let evicted = match policy {
+                BookLimitPolicy::EvictFarthest => self.order_book.evict_farthest(side),
+                BookLimitPolicy::Reject => None,
+            };
+            if let Some(order) = evicted {
+                tracing::debug!(order_id = %order.id, price = %order.price, "Order evicted from a full book");
+                self.cancelled(order.id, order.remaining_quantity, CancelReason::Evicted);
+                continue;
+            }
+            if let Some(order) = self.order_book.cancel_order(order_id) {
+                self.cancelled(order.id, order.remaining_quantity, CancelReason::BookFull);
+            }
+            break;
+        }
+    }
+
+    /// Stamp an order with the next arrival sequence, as it joins the book afresh
+    fn assign_sequence(&mut self, order: &mut Order) {
+        order.sequence = self.next_sequence;
+        self.next_sequence += 1;
+    }
+
+    /// The order `request` makes, arriving at `now`, under the id reserved
+    /// for it and with the next arrival sequence as its time priority
+    fn new_order(&mut self, request: &OrderRequest, now: DateTime<Utc>) -> Order {
+        self.orders_processed += 1;
+        let id = request.id.unwrap_or_else(|| self.order_ids.reserve());
+        let mut order = Order::from_request(id, request, now);
+        self.assign_sequence(&mut order);
+        order
+    }
+
+    /// Published reference price: last trade, else the displayed mid.
+    /// Hidden liquidity never moves it, matching what REST clients see.
+    fn reference_price(&self) -> Option<Decimal> {
+        SymbolConfig::reference_price(
+            self.last_trade_price,
+            self.order_book.best_visible_bid(),
+            self.order_book.best_visible_ask(),
+        )
+    }
+
+    /// Worst price a market order with `max_slippage` may trade at: that far
+    /// from the reference price (last trade, else mid), or from the best
+    /// opposing price, hidden or not, on a book with neither
+    fn protection_price(&self, order: &Order) -> Option<Decimal> {
+        let max_slippage = order.max_slippage?;
+        let opposing = match order.side {
+            Side::Buy => self.order_book.best_ask(),
+            Side::Sell => self.order_book.best_bid(),
+        };
+        let reference = self.reference_price().or(opposing)?;
+        Some(match order.side {
+            Side::Buy => reference + max_slippage,
+            Side::Sell => reference - max_slippage,
+        })
+    }
+
+    /// Broadcast trades with their fills, feed the circuit breaker, and release
+    /// the stops they trigger, restamped as fresh orders. Both lists are
+    /// drained: trades and fills move into their events rather than being copied.
+    fn publish_trades(&mut self, trades: &mut Vec<Trade>, fills: &mut Vec<[Fill; 2]>) -> Vec<Order> {
+        let (Some(first), Some(last)) = (trades.first(), trades.last()) else {
+            return Vec::new();
+        };
+        self.last_trade_price = Some(last.price);
+        let mut low = first.price;
+        let mut high = first.price;
+        let halted = self.record_trades(trades);
+
+        for (mut trade, fills) in trades.drain(..).zip(fills.drain(..)) {
+            // Numbered as published, so a replay of the log numbers trades alike
+            trade.id = self.next_trade_id;
+            self.next_trade_id += 1;
+            tracing::debug!(
+                trade_id = %trade.id,
+                price = %trade.price,
+                quantity = %trade.quantity,
+                "Trade executed"
+            );
+            low = low.min(trade.price);
+            high = high.max(trade.price);
+
+            if self.recent_trades.len() == BUSTABLE_TRADES {
+                self.recent_trades.pop_front();
+            }
+            let trade = Arc::new(trade);
+            self.recent_trades.push_back(trade.clone());
+            self.trades.add(&trade);
+            self.last_24h.add(&trade);
+            if let Some(confirming) = self.confirming.as_mut() {
+                if confirming.order_id == trade.taker_order_id || confirming.order_id == trade.maker_order_id {
+                    confirming.filled += trade.quantity;
+                    confirming.notional += trade.quantity * trade.price;
+                }
+            }
+            self.emit(EngineEvent::Trade(trade));
+            for fill in fills {
+                self.emit(EngineEvent::OrderFilled(fill));
+            }
+        }
+        if let Some(halted) = halted {
+            self.emit(halted);
+        }
+
+        let mut released = self.stops.take_triggered(low, high);
+        for stop in &mut released {
+            self.stop_triggered(stop);
+            // A released stop joins the book as a fresh order
+            stop.timestamp = self.now();
+            self.assign_sequence(stop);
+        }
+        released
+    }
+
+    /// Broadcast that a stop order fired
+    fn stop_triggered(&mut self, order: &Order) {
+        let stop_price = order.stop_price.unwrap_or_default();
+        tracing::debug!(order_id = %order.id, stop_price = %stop_price, "Stop triggered");
+        self.emit(EngineEvent::StopTriggered {
+            order_id: order.id,
+            stop_price,
+        });
+    }
+
+    /// Broadcast that an order passed validation
+    fn accepted(&mut self, order: &Order) {
+        self.emit(EngineEvent::OrderAccepted {
+            order_id: order.id,
+            client_order_id: order.client_order_id.clone(),
+            owner_id: order.owner_id.clone(),
+            side: order.side,
+            price: order.price,
+            quantity: order.quantity,
+            timestamp: order.timestamp,
+        });
+    }
+
+    /// Broadcast that an order's remainder joined the book
+    fn rested(&mut self, order_id: OrderId, price: Decimal, remaining_quantity: Decimal) {
+        tracing::debug!(order_id = %order_id, price = %price, remaining = %remaining_quantity, "Order rested");
+        self.emit(EngineEvent::OrderRested {
+            order_id,
+            price,
+            remaining_quantity,
+        });
+    }
+
+    /// Broadcast that a live order was removed
+    fn cancelled(&mut self, order_id: OrderId, remaining_quantity: Decimal, reason: CancelReason) {
+        tracing::debug!(order_id = %order_id, reason = reason.code(), "Order cancelled");
+        self.emit(EngineEvent::OrderCancelled {
+            order_id,
+            remaining_quantity,
+            reason,
+        });
+    }
+
+    /// Broadcast that self-trade prevention shrank an order in place
+    fn decremented(&mut self, order_id: OrderId, price: Decimal, remaining_quantity: Decimal) {
+        tracing::debug!(order_id = %order_id, remaining = %remaining_quantity, "Order decremented by self-trade prevention");
+        self.emit(EngineEvent::OrderModified {
+            order_id,
+            new_order_id: order_id,
+            price,
+            remaining_quantity,
+        });
+    }
+
+    /// The reply to a confirmed submission, from where its order ended up
+    fn acknowledge(&self, confirming: Confirming) -> Result<SubmitAck, RejectReason> {
+        let Confirming {
+            order_id,
+            quantity,
+            rejection,
+            filled,
+            notional,
+        } = confirming;
+        if let Some(reason) = rejection {
+            return Err(reason);
+        }
+        let (state, remaining_quantity) = if let Some(order) = self.order_book.get_order(order_id) {
+            (OrderState::Resting, order.remaining_quantity)
+        } else if let Some(stop) = self.stops.iter().find(|o| o.id == order_id) {
+            (OrderState::PendingStop, stop.remaining_quantity)
+        } else if let Some(queued) = self.halted_orders.iter().find(|o| o.id == order_id) {
+            (OrderState::Queued, queued.remaining_quantity)
+        } else if filled >= quantity {
+            (OrderState::Filled, Decimal::ZERO)
+        } else {
+            (OrderState::Cancelled, Decimal::ZERO)
+        };
+        Ok(SubmitAck {
+            order_id,
+            state,
+            filled_quantity: filled,
+            average_price: (!filled.is_zero()).then(|| (notional / filled).normalize()),
+            remaining_quantity,
+        })
+    }
+
+    /// Number and broadcast an event. Numbers are spent even with no
+    /// subscribers, so they count everything the engine has emitted.
+    fn emit(&mut self, event: EngineEvent) {
+        self.event_sequence += 1;
+        let event = SequencedEvent {
+            seq: self.event_sequence,
+            symbol: self.event_symbol.clone(),
+            event,
+        };
+        if let Some(journal) = self.journal.as_ref().filter(|_| is_journaled(&event.event)) {
+            journal.send(event.clone());
+        }
+        self.broadcast(event);
+    }
+
+    /// Hand a numbered event to subscribers
+    fn broadcast(&self, event: SequencedEvent) {
+        // Ignore send errors (no subscribers)
+        match &self.outbound {
+            Some(outbound) => {
+                let _ = outbound.send(Outbound::Event(event));
+            }
+            None => {
+                let _ = self.event_tx.send(event);
+            }
+        }
+    }
+
+    /// Send replies once the events emitted before them are broadcast, so
+    /// a caller who hears back has had the chance to see what it caused
+    fn reply(&self, send: impl FnOnce() + Send + 'static) {
+        match &self.outbound {
+            Some(outbound) => {
+                let _ = outbound.send(Outbound::Reply(Box::new(send)));
+            }
+            None => send(),
+        }
+    }
+
+    /// Broadcast that an order was refused
+    fn reject(&mut self, order: &Order, reason: RejectReason) {
+        tracing::debug!(order_id = %order.id, reason = %reason, "Order rejected");
+        if let Some(confirming) = self.confirming.as_mut().filter(|c| c.order_id == order.id) {
+            confirming.rejection = Some(reason.clone());
+        }
+        self.emit(EngineEvent::OrderRejected {
+            order_id: order.id,
+            client_order_id: order.client_order_id.clone(),
+            reason,
+        });
+    }
+
+    /// Note that the book changed: within a batch the update waits for the
+    /// batch to end, so a burst of orders publishes the book once
+    fn book_changed(&mut self) {
+        if self.in_batch {
+            self.book_update_due = true;
+        } else {
+            self.broadcast_book_update();
+        }
+    }
+
+    /// Send the update a batch owes, unless the last one went out less
+    /// than the minimum interval ago; then it is held until the interval
+    /// has passed
+    fn flush_book_update(&mut self) {
+        if self.book_update_deadline().is_none_or(|due_at| self.now() >= due_at) {
+            self.send_owed_book_update();
+        }
+    }
+
+    /// Send the update owed, if any, whatever the interval
+    fn send_owed_book_update(&mut self) {
+        if std::mem::take(&mut self.book_update_due) {
+            self.broadcast_book_update();
+        }
+    }
+
+    /// Earliest engine time the next book update may be sent; `None` if it
+    /// may go at any time
+    fn book_update_deadline(&self) -> Option<DateTime<Utc>> {
+        let last = self.last_book_published.filter(|_| !self.min_update_interval.is_zero())?;
+        let interval = chrono::Duration::from_std(self.min_update_interval).unwrap_or(chrono::Duration::MAX);
+        Some(last.checked_add_signed(interval).unwrap_or(DateTime::<Utc>::MAX_UTC))
+    }
+
+    /// When the update held back by the minimum interval falls due, if one is held
+    fn held_book_update(&self) -> Option<DateTime<Utc>> {
+        self.book_update_deadline().filter(|_| self.book_update_due)
+    }
+
+    /// Send the held update as of the moment it fell due, so a replay,
+    /// which sends it before the next command, times it the same way
+    fn publish_held_book_update(&mut self) {
+        let Some(due_at) = self.held_book_update() else {
+            return;
+        };
+        let command_time = self.command_time.replace(due_at);
+        self.send_owed_book_update();
+        self.command_time = command_time;
+    }
+
+    /// Broadcast current order book state, unless it is what the last
+    /// update already said
+    fn broadcast_book_update(&mut self) {
+        let bid_depth = self.order_book.bid_depth(self.depth_levels);
+        let ask_depth = self.order_book.ask_depth(self.depth_levels);
+        // Every other field of an update follows from its depth, which
+        // covers the checksum's levels, or is the last trade price
+        let unchanged = self.last_book_update.as_ref().is_some_and(|last| {
+            last.bid_depth == bid_depth && last.ask_depth == ask_depth && last.last_trade_price == self.last_trade_price
+        });
+        if unchanged {
+            self.suppressed_book_updates += 1;
+        } else {
+            self.publish_book(bid_depth, ask_depth);
+        }
+
+        if self.in_auction {
+            let indicative = self.order_book.indicative_uncross(self.last_trade_price);
+            self.emit(EngineEvent::AuctionIndicative {
+                price: indicative.map(|(price, _)| price),
+                volume: indicative.map(|(_, volume)| volume).unwrap_or_default(),
+            });
+        }
+    }
+
+    /// Number a book update with this depth and send it to both the current
+    /// state and the event stream
+    fn publish_book(&mut self, bid_depth: Vec<(Decimal, Decimal, usize)>, ask_depth: Vec<(Decimal, Decimal, usize)>) {
+        self.book_sequence += 1;
+        self.last_book_published = Some(self.now());
+        let snapshot = self.snapshot_of(bid_depth, ask_depth, false);
+
+        // Never waits on readers, so no update is skipped
+        self.current_state.send_replace(snapshot.clone());
+
+        let snapshot = Arc::new(snapshot);
+        self.last_book_update = Some(snapshot.clone());
+        self.emit(EngineEvent::OrderBookUpdate(snapshot));
+    }
+
+    /// The displayed book `levels` deep, with depth grouped into buckets of
+    /// the given size or else level by level
+    fn book_snapshot(&self, bucket_size: Option<Decimal>, levels: usize) -> OrderBookSnapshot {
+        match bucket_size {
+            Some(bucket_size) => self.snapshot_of(
+                self.order_book.aggregated_depth(Side::Buy, bucket_size, levels),
+                self.order_book.aggregated_depth(Side::Sell, bucket_size, levels),
+                true,
+            ),
+            None => self.snapshot_of(self.order_book.bid_depth(levels), self.order_book.ask_depth(levels), false),
+        }
+    }
+
+    /// The displayed book around depth just read from it, `grouped` into
+    /// buckets or level by level
+    fn snapshot_of(
+        &self,
+        bid_depth: Vec<(Decimal, Decimal, usize)>,
+        ask_depth: Vec<(Decimal, Decimal, usize)>,
+        grouped: bool,
+    ) -> OrderBookSnapshot {
+        // Imbalances cover no more than the broadcast depth, so identical
+        // depth means identical imbalances
+        let (top_imbalance, depth_imbalance) = match &self.last_book_update {
+            Some(last) if !grouped && last.bid_depth == bid_depth && last.ask_depth == ask_depth => {
+                (last.top_imbalance, last.depth_imbalance)
+            }
+            _ => (
+                self.order_book.imbalance(1),
+                self.order_book.imbalance(self.imbalance_levels.min(self.depth_levels)),
+            ),
+        };
+        let checksum = if grouped {
+            depth_checksum(&bid_depth, &ask_depth)
+        } else {
+            self.order_book.checksum()
+        };
+        let bbo = self.order_book.bbo();
+        OrderBookSnapshot {
+            best_bid: bbo.bid.map(|(price, _)| price),
+            best_ask: bbo.ask.map(|(price, _)| price),
+            best_bid_qty: bbo.bid.map(|(_, quantity)| quantity),
+            best_ask_qty: bbo.ask.map(|(_, quantity)| quantity),
+            mid_price: self.order_book.mid_price(),
+            microprice: self.order_book.microprice(),
+            top_imbalance,
+            depth_imbalance,
+            checksum,
+            last_trade_price: self.last_trade_price,
+            trades: self.trades,
+            bid_depth,
+            ask_depth,
+            timestamp: self.now(),
+            sequence: self.book_sequence,
+        }
+    }
+
+    /// The engine's statistics as of now. Every field is kept up to date
+    /// as orders are processed, so this is a handful of reads; the latency
+    /// percentiles walk a few fixed-size histograms.
+    pub fn stats(&self) -> EngineStats {
+        EngineStats {
+            symbol: self.order_book.symbol.clone(),
+            best_bid: self.order_book.best_bid(),
+            best_ask: self.order_book.best_ask(),
+            spread: self.order_book.spread(),
+            order_count: self.order_book.order_count(),
+            pending_stops: self.stops.len(),
+            trades: self.trades,
+            last_trade_price: self.last_trade_price,
+            last_24h: self.last_24h.summary(self.now()),
+            uptime: self.clock.monotonic().saturating_duration_since(self.started_at),
+            suppressed_book_updates: self.suppressed_book_updates,
+            depth_levels: self.depth_levels,
+            min_update_interval: self.min_update_interval,
+            latency: self.latency.summary(),
+        }
+    }
+}
+
+/// Engine statistics
+#[derive(Debug, Clone)]
+pub struct EngineStats {
+    pub symbol: String,
+    pub best_bid: Option<rust_decimal::Decimal>,
+    pub best_ask: Option<rust_decimal::Decimal>,
+    pub spread: Option<rust_decimal::Decimal>,
+    pub order_count: usize,
+    pub pending_stops: usize,
+    /// Everything traded, less any busted, carried over restarts
+    pub trades: TradeTotals,
+    pub last_trade_price: Option<Decimal>,
+    /// Trading over the last 24 hours, journaled trades included after a restart
+    pub last_24h: WindowSummary,
+    /// Time since this engine was started
+    pub uptime: Duration,
+    /// Book updates skipped because the top of the book was unchanged
+    pub suppressed_book_updates: u64,
+    /// Levels per side book updates carry
+    pub depth_levels: usize,
+    /// Least time between book updates
+    pub min_update_interval: Duration,
+    /// Per-order processing latency since the engine started
+    pub latency: OrderLatency,
+}
+
+/// Builder for creating the matching engine and its channels
+pub struct EngineBuilder {
+    config: SymbolConfig,
+    matching_algorithm: MatchingAlgorithm,
+    circuit_breaker: Option<CircuitBreakerConfig>,
+    max_open_orders: Option<usize>,
+    rate_limits: RateLimits,
+    book_limit: Option<(usize, BookLimitPolicy)>,
+    book_check: Option<(usize, InvariantPolicy)>,
+    order_buffer_size: usize,
+    event_buffer_size: usize,
+    mode: EngineMode,
+    clock: SharedClock,
+    ids: Box<dyn IdSource>,
+    batch_size: usize,
+    stp_policy: StpPolicy,
+    depth_levels: usize,
+    min_update_interval: Duration,
+    tick_interval: Duration,
+    heartbeat_interval: Duration,
+    imbalance_levels: usize,
+    restore: Option<EngineSnapshot>,
+    wal: Option<(WalWriter, Vec<WalRecord>)>,
+    journal: Option<PathBuf>,
+    trade_history: Vec<PastTrade>,
+    rebuild: Option<Rebuild>,
+}
+
+impl EngineBuilder {
+    pub fn new(symbol: impl Into<String>) -> Self {
+        Self::from_config(SymbolConfig::new(symbol))
+    }
+
+    /// Start from a symbol's configured trading rules, e.g. from the registry
+    pub fn from_config(config: SymbolConfig) -> Self {
+        Self {
+            config,
+            matching_algorithm: MatchingAlgorithm::default(),
+            circuit_breaker: None,
+            max_open_orders: None,
+            rate_limits: RateLimits::default(),
+            book_limit: None,
+            // Unit tests check the book after every order
+            book_check: cfg!(test).then_some((1, InvariantPolicy::Panic)),
+            order_buffer_size: 10_000,
+            event_buffer_size: 1_000,
+            mode: EngineMode::Task,
+            clock: Arc::new(SystemClock),
+            ids: Box::new(RandomIds),
+            batch_size: DEFAULT_BATCH_SIZE,
+            stp_policy: StpPolicy::default(),
+            depth_levels: 10,
+            min_update_interval: Duration::ZERO,
+            tick_interval: DEFAULT_TICK_INTERVAL,
+            heartbeat_interval: DEFAULT_HEARTBEAT_INTERVAL,
+            imbalance_levels: 5,
+            restore: None,
+            wal: None,
+            journal: None,
+            trade_history: Vec::new(),
+            rebuild: None,
+        }
+    }
+
+    /// Symbol the engine will trade
+    pub fn symbol(&self) -> &str {
+        &self.config.symbol
+    }
+
+    #[allow(dead_code)]
+    pub fn order_buffer_size(mut self, size: usize) -> Self {
+        self.order_buffer_size = size;
+        self
+    }
+
+    /// Where the engine runs. `EngineMode::Thread` gives it a thread of its
+    /// own instead of a runtime worker, so it never waits behind other
+    /// tasks; `MatchingEngine::run` is still spawned as a task, and
+    /// broadcasts the engine's events while the thread matches.
+    pub fn mode(mut self, mode: EngineMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn event_buffer_size(mut self, size: usize) -> Self {
+        self.event_buffer_size = size;
+        self
+    }
+
+    /// Reject prices with more than `scale` decimal places
+    pub fn price_scale(mut self, scale: u32) -> Self {
+        self.config.price_scale = Some(scale);
+        self
+    }
+
+    /// Reject quantities with more than `scale` decimal places
+    pub fn quantity_scale(mut self, scale: u32) -> Self {
+        self.config.quantity_scale = Some(scale);
+        self
+    }
+
+    /// Only accept prices that are a multiple of `tick_size`
+    pub fn tick_size(mut self, tick_size: Decimal) -> Self {
+        self.config.tick_size = Some(tick_size);
+        self
+    }
+
+    /// Only accept quantities that are a multiple of `lot_size`
+    pub fn lot_size(mut self, lot_size: Decimal) -> Self {
+        self.config.lot_size = Some(lot_size);
+        self
+    }
+
+    /// Reject orders smaller than `min_quantity`
+    pub fn min_quantity(mut self, min_quantity: Decimal) -> Self {
+        self.config.min_quantity = Some(min_quantity);
+        self
+    }
+
+    /// Reject priced orders whose price × quantity is below `min_notional`
+    pub fn min_notional(mut self, min_notional: Decimal) -> Self {
+        self.config.min_notional = Some(min_notional);
+        self
+    }
+
+    /// Reject limit orders priced more than `pct` percent from the reference
+    /// price (last trade, else mid-price)
+    pub fn price_band_pct(mut self, pct: Decimal) -> Self {
+        self.config.price_band_pct = Some(pct);
+        self
+    }
+
+    /// Choose how fills are shared within a price level (FIFO by default)
+    pub fn matching_algorithm(mut self, algorithm: MatchingAlgorithm) -> Self {
+        self.matching_algorithm = algorithm;
+        self
+    }
+
+    /// Self-trade prevention for orders that don't set their own
+    /// (cancel-oldest by default)
+    pub fn stp_policy(mut self, stp_policy: StpPolicy) -> Self {
+        self.stp_policy = stp_policy;
+        self
+    }
+
+    /// Reject orders larger than `max_quantity`
+    pub fn max_quantity(mut self, max_quantity: Decimal) -> Self {
+        self.config.max_quantity = Some(max_quantity);
+        self
+    }
+
+    /// Reject priced orders whose price × quantity exceeds `max_notional`
+    pub fn max_notional(mut self, max_notional: Decimal) -> Self {
+        self.config.max_notional = Some(max_notional);
+        self
+    }
+
+    /// Levels per side the depth imbalance in book updates covers (5 by
+    /// default, at most `depth_levels`)
+    pub fn imbalance_levels(mut self, levels: usize) -> Self {
+        self.imbalance_levels = levels;
+        self
+    }
+
+    /// Levels per side book updates and depth queries carry (10 by default)
+    ///
+    /// # Panics
+    ///
+    /// If `levels` is zero.
+    pub fn depth_levels(mut self, levels: usize) -> Self {
+        assert!(levels > 0, "book updates need at least one level per side");
+        self.depth_levels = levels;
+        self
+    }
+
+    /// Send book updates no closer together than `interval` of engine time.
+    /// An update that would come sooner is held, and sent with the book as
+    /// it then is once the interval has passed; trades and every other
+    /// event still go out at once. Zero, the default, sends each update.
+    pub fn min_update_interval(mut self, interval: Duration) -> Self {
+        self.min_update_interval = interval;
+        self
+    }
+
+    /// Run the engine's time-driven work, `MatchingEngine::on_tick`, every
+    /// `interval` of real time, busy or idle. An expired order leaves a
+    /// quiet book within a tick of its expiry.
+    ///
+    /// # Panics
+    ///
+    /// If `interval` is zero.
+    pub fn tick_interval(mut self, interval: Duration) -> Self {
+        assert!(!interval.is_zero(), "tick interval must be positive");
+        self.tick_interval = interval;
+        self
+    }
+
+    /// Send a `Heartbeat` every `interval` of real time. Handles count the
+    /// engine as stalled once three intervals pass without one.
+    pub fn heartbeat_interval(mut self, interval: Duration) -> Self {
+        assert!(!interval.is_zero(), "heartbeat interval must be positive");
+        self.heartbeat_interval = interval;
+        self
+    }
+
+    /// Cap how many open orders each owner may have
+    pub fn max_open_orders(mut self, limit: usize) -> Self {
+        self.max_open_orders = Some(limit);
+        self
+    }
+
+    /// Limit how fast each owner may send orders, unless it has a limit of
+    /// its own. Quotes count as two orders; cancels are never limited.
+    pub fn rate_limit(mut self, limit: RateLimit) -> Self {
+        self.rate_limits.default = Some(limit);
+        self
+    }
+
+    /// Limit how fast `owner_id` may send orders, in place of the default
+    pub fn owner_rate_limit(mut self, owner_id: impl Into<String>, limit: RateLimit) -> Self {
+        self.rate_limits.owners.insert(owner_id.into(), limit);
+        self
+    }
+
+    /// Cap how many orders may rest on each side of the book, refusing new
+    /// passive orders or evicting the farthest from the touch once full
+    pub fn max_resting_orders(mut self, limit: usize, policy: BookLimitPolicy) -> Self {
+        self.book_limit = Some((limit, policy));
+        self
+    }
+
+    /// Check the book's invariants after every `every` orders, logging or
+    /// panicking on a violation. Off by default; costs a full book scan.
+    pub fn validate_book_every(mut self, every: usize, policy: InvariantPolicy) -> Self {
+        self.book_check = Some((every.max(1), policy));
+        self
+    }
+
+    /// Halt matching when prices move too far too fast
+    pub fn circuit_breaker(mut self, config: CircuitBreakerConfig) -> Self {
+        self.circuit_breaker = Some(config);
+        self
+    }
+
+    /// Use a specific clock (e.g. a `ManualClock` in tests)
+    pub fn clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Most queued commands to process before publishing the book. The
+    /// engine never waits to fill a batch: a lone order is a batch of one.
+    ///
+    /// # Panics
+    ///
+    /// If `size` is zero.
+    pub fn batch_size(mut self, size: usize) -> Self {
+        assert!(size > 0, "batch size must be at least 1");
+        self.batch_size = size;
+        self
+    }
+
+    /// Draw the epoch a fresh engine's UUIDs are made from from `ids`
+    /// instead of at random. A restored engine keeps its saved epoch.
+    pub fn id_source(mut self, ids: impl IdSource + 'static) -> Self {
+        self.ids = Box::new(ids);
+        self
+    }
+
+    /// Make runs repeatable: a [`LogicalClock`] starting at the Unix epoch
+    /// and stepping a millisecond per read, and UUIDs seeded from `seed`.
+    /// Fed the same commands in the same order, two engines built this way
+    /// emit identical trades and events. Anything else reading the clock,
+    /// such as the engine's tick or the expiry sweeper, moves it, so drive
+    /// the engine with `process_command` rather than `run`.
+    pub fn deterministic(self, seed: u64) -> Self {
+        let clock = LogicalClock::new(DateTime::UNIX_EPOCH, chrono::Duration::milliseconds(1));
+        self.clock(Arc::new(clock)).id_source(SeededIds::new(seed))
+    }
+
+    /// Start from a saved state rather than an empty book. Orders keep
+    /// their ids, remainders and time priority, and event and arrival
+    /// sequences carry on from the snapshot's.
+    ///
+    /// # Panics
+    ///
+    /// On `build`, if the snapshot is of another symbol.
+    pub fn restore_from(mut self, snapshot: EngineSnapshot) -> Self {
+        self.restore = Some(snapshot);
+        self
+    }
+
+    /// Log every state-changing command to `wal` before it runs. On build,
+    /// `records` after the snapshot's last one are replayed first, as the
+    /// engine processed them, so the book is rebuilt to where the log ends.
+    /// Nothing is broadcast while replaying.
+    pub fn write_ahead_log(mut self, wal: WalWriter, records: Vec<WalRecord>) -> Self {
+        self.wal = Some((wal, records));
+        self
+    }
+
+    /// Send the events the journaler records on a lossless channel as well
+    /// as the broadcast one, spilling to a file in `dir` whenever the
+    /// journaler falls too far behind. The journaler takes the other end
+    /// with `EngineHandle::take_journal`. Events of commands replayed from
+    /// the write-ahead log are not sent.
+    pub fn journal(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.journal = Some(dir.into());
+        self
+    }
+
+    /// Start the engine's 24-hour statistics from `trades` journaled
+    /// before a restart, oldest first and none busted. Trades of commands
+    /// the write-ahead log replays are counted by the replay instead, so
+    /// those from its first command on are skipped.
+    pub fn trade_history(mut self, trades: Vec<PastTrade>) -> Self {
+        self.trade_history = trades;
+        self
+    }
+
+    /// Supervise the engine: should a command panic it, log the panic and
+    /// carry on with an engine from `rebuild`, which should restore the
+    /// symbol's latest snapshot and log as on startup. The log record of
+    /// the command that panicked is voided first, so the rebuild doesn't
+    /// replay it into the same panic. Handles keep working throughout, and
+    /// subscribers get a `Recovered` event.
+    pub fn recover_with(self, rebuild: impl Fn() -> EngineBuilder + Send + Sync + 'static) -> Self {
+        self.recover_with_shared(Arc::new(rebuild))
+    }
+
+    fn recover_with_shared(mut self, rebuild: Rebuild) -> Self {
+        self.rebuild = Some(rebuild);
+        self
+    }
+
+    /// Build the engine and return handles for interaction
+    pub fn build(self) -> (MatchingEngine, EngineHandle) {
+        self.build_wired(None)
+    }
+
+    /// Build the engine, on the channels of the panicked one it replaces
+    /// if `wiring` is given
+    fn build_wired(self, wiring: Option<Wiring>) -> (MatchingEngine, EngineHandle) {
+        let (command_tx, command_rx) = command_channel(self.mode, self.order_buffer_size);
+        let (event_tx, _) = broadcast::channel(self.event_buffer_size);
+
+        let initial_snapshot = OrderBookSnapshot {
+            best_bid: None,
+            best_ask: None,
+            best_bid_qty: None,
+            best_ask_qty: None,
+            mid_price: None,
+            microprice: None,
+            top_imbalance: None,
+            depth_imbalance: None,
+            checksum: 0,
+            last_trade_price: None,
+            trades: TradeTotals::default(),
+            bid_depth: Vec::new(),
+            ask_depth: Vec::new(),
+            timestamp: self.clock.now(),
+            sequence: 0,
+        };
+
+        let (state_tx, current_state) = watch::channel(initial_snapshot);
+
+        let mut engine = MatchingEngine::new(
+            self.config.clone(),
+            command_rx,
+            event_tx.clone(),
+            state_tx,
+            self.clock.clone(),
+        );
+        engine.rebuild = self.rebuild;
+        // Replayed book updates reach the handles' current state; events
+        // don't reach their subscribers until replay is done
+        let wiring = wiring.map(|mut wiring| {
+            std::mem::swap(&mut engine.command_rx, &mut wiring.command_rx);
+            std::mem::swap(&mut engine.current_state, &mut wiring.current_state);
+            std::mem::swap(&mut engine.heartbeat_tx, &mut wiring.heartbeat_tx);
+            engine.order_ids = wiring.order_ids.clone();
+            wiring
+        });
+        engine.breaker = self.circuit_breaker.map(CircuitBreaker::new);
+        engine.max_open_orders = self.max_open_orders;
+        engine.rate_limiter = RateLimiter::new(self.rate_limits);
+        engine.book_limit = self.book_limit;
+        engine.book_check = self.book_check;
+        engine.imbalance_levels = self.imbalance_levels;
+        engine.depth_levels = self.depth_levels;
+        engine.min_update_interval = self.min_update_interval;
+        engine.tick_interval = self.tick_interval;
+        engine.heartbeat_interval = self.heartbeat_interval;
+        let mut ids = self.ids;
+        engine.epoch = ids.epoch();
+        engine.batch_size = self.batch_size;
+        engine.mode = self.mode;
+        engine.order_book = OrderBook::new(self.config.symbol.clone())
+            .with_price_grid(self.config.price_grid())
+            .with_quote_scale(self.config.quote_scale())
+            .with_algorithm(self.matching_algorithm)
+            .with_lot_size(self.config.lot_size)
+            .with_stp_policy(self.stp_policy);
+        if let Some(snapshot) = self.restore {
+            assert_eq!(
+                snapshot.symbol, self.config.symbol,
+                "cannot restore a {} engine from a {} snapshot",
+                self.config.symbol, snapshot.symbol
+            );
+            engine.restore(snapshot);
+        }
+        let voided_record = wiring.as_ref().and_then(|wiring| wiring.voided_record);
+        let replay = self.wal.map(|(wal, records)| {
+            let voided: HashSet<u64> = records
+                .iter()
+                .filter_map(|r| match r.command {
+                    LoggedCommand::Void { seq } => Some(seq),
+                    _ => None,
+                })
+                .chain(voided_record)
+                .collect();
+            let replay: Vec<_> = records
+                .into_iter()
+                .filter(|r| r.seq > engine.wal_sequence && !voided.contains(&r.seq))
+                .collect();
+            (wal, replay)
+        });
+        let replayed_from = replay.as_ref().and_then(|(_, replay)| replay.first()).map(|record| record.at);
+        let seeded = self.trade_history.len();
+        for trade in self.trade_history {
+            if replayed_from.is_none_or(|from| trade.timestamp < from) {
+                engine.last_24h.record(trade.price, trade.quantity, trade.timestamp);
+            }
+        }
+        if seeded > 0 {
+            tracing::info!(trades = seeded, "Loaded journaled trades into 24h statistics");
+        }
+        if let Some((mut wal, replay)) = replay {
+            if replay.first().is_some_and(|r| r.seq != engine.wal_sequence + 1) {
+                tracing::warn!(
+                    after = engine.wal_sequence,
+                    resumes = replay[0].seq,
+                    "Write-ahead log has a gap after the snapshot"
+                );
+            }
+            if !replay.is_empty() {
+                tracing::info!(records = replay.len(), "Replaying write-ahead log");
+                // Book updates made while replaying reach the current state.
+                // Batches end where they did, so as many updates are made.
+                let mut replay = replay.into_iter().peekable();
+                while let Some(record) = replay.next() {
+                    let ends_batch = !replay.peek().is_some_and(|next| next.batched);
+                    engine.replay(record, ends_batch);
+                }
+            }
+            wal.skip_to(engine.wal_sequence + 1);
+            if let Some(seq) = voided_record {
+                let now = engine.clock.now();
+                match wal.append(now, engine.epoch, false, LoggedCommand::Void { seq }) {
+                    Ok(void) => engine.wal_sequence = void,
+                    Err(e) => tracing::error!(seq, "Could not void the record that panicked the engine: {e}"),
+                }
+            }
+            engine.wal = Some(wal);
+        }
+        let journal = match wiring {
+            Some(wiring) => {
+                engine.event_tx = wiring.event_tx;
+                engine.outbound = wiring.outbound;
+                engine.journal = wiring.journal;
+                engine.last_24h = wiring.last_24h;
+                engine.started_at = wiring.started_at;
+                engine.orders_processed = wiring.orders_processed;
+                None
+            }
+            None => self.journal.map(|dir| {
+                let (tx, rx) = journal_channel(&dir, &self.config.symbol, JOURNAL_CAPACITY);
+                engine.journal = Some(tx);
+                rx
+            }),
+        };
+
+        let handle = EngineHandle {
+            command_tx,
+            event_tx,
+            current_state,
+            clock: self.clock,
+            config: self.config,
+            ids: engine.epoch,
+            order_ids: engine.order_ids.clone(),
+            heartbeat: engine.heartbeat_tx.subscribe(),
+            heartbeat_interval: self.heartbeat_interval,
+            journal: Arc::new(Mutex::new(journal)),
+        };
+
+        (engine, handle)
+    }
+}
+
+/// How full an engine's command queue is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub struct QueueDepth {
+    /// Commands sent but not yet taken by the engine
+    pub len: usize,
+    pub capacity: usize,
+}
+
+impl QueueDepth {
+    /// Whether a new command would have to wait
+    pub fn is_full(&self) -> bool {
+        self.len >= self.capacity
+    }
+}
+
+/// Handle for interacting with the matching engine
+#[derive(Clone)]
+pub struct EngineHandle {
+    /// Send commands to the engine
+    pub command_tx: CommandSender,
+    /// Subscribe to engine events
+    pub event_tx: broadcast::Sender<SequencedEvent>,
+    /// Latest book update, replaced by the engine on every update it
+    /// broadcasts. Borrows are brief and never hold up the engine; use
+    /// `changed()` on a clone to wait for the next update.
+    pub current_state: watch::Receiver<OrderBookSnapshot>,
+    /// Clock shared with the engine
+    pub clock: SharedClock,
+    /// Trading rules the engine enforces, for validating before submission
+    pub config: SymbolConfig,
+    /// Converts between the engine's order and trade ids and their UUIDs
+    pub ids: IdEpoch,
+    /// Ids new orders are submitted under, shared with the engine
+    order_ids: OrderIds,
+    /// When the engine last sent a heartbeat
+    heartbeat: watch::Receiver<Instant>,
+    heartbeat_interval: Duration,
+    /// The journal's receiving end, until the journaler takes it
+    journal: Arc<Mutex<Option<JournalReceiver>>>,
+}
+
+/// Why a command was refused, without the command itself
+fn unsent(error: mpsc::error::TrySendError<EngineCommand>) -> mpsc::error::TrySendError<()> {
+    match error {
+        mpsc::error::TrySendError::Full(_) => mpsc::error::TrySendError::Full(()),
+        mpsc::error::TrySendError::Closed(_) => mpsc::error::TrySendError::Closed(()),
+    }
+}
+
+impl EngineHandle {
+    /// Submit an order to the engine, returning the id it will have
+    pub async fn submit_order(&self, request: OrderRequest) -> Result<OrderId, mpsc::error::SendError<EngineCommand>> {
+        let (id, request) = self.with_id(request);
+        self.command_tx.send(EngineCommand::Submit(request)).await?;
+        Ok(id)
+    }
+
+    /// Submit an order if the command queue has room, instead of waiting
+    /// for the engine to catch up, returning the id it will have. A refused
+    /// order is dropped, and its id never used.
+    pub fn try_submit_order(&self, request: OrderRequest) -> Result<OrderId, mpsc::error::TrySendError<()>> {
+        let (id, request) = self.with_id(request);
+        self.command_tx.try_send(EngineCommand::Submit(request)).map_err(unsent)?;
+        Ok(id)
+    }
+
+    /// `request` under its own id, or the next one reserved for it
+    fn with_id(&self, request: OrderRequest) -> (OrderId, OrderRequest) {
+        let id = request.id.unwrap_or_else(|| self.order_ids.reserve());
+        (id, OrderRequest { id: Some(id), ..request })
+    }
+
+    /// As `submit_order_confirmed`, but refused at once if the command
+    /// queue is full. The inner `None` means the engine stopped before
+    /// giving its verdict.
+    pub async fn try_submit_order_confirmed(
+        &self,
+        request: OrderRequest,
+    ) -> Result<Option<Result<SubmitAck, RejectReason>>, mpsc::error::TrySendError<()>> {
+        let (reply, outcome) = oneshot::channel();
+        self.command_tx
+            .try_send(EngineCommand::SubmitConfirmed { request, reply })
+            .map_err(unsent)?;
+        Ok(outcome.await.ok())
+    }
+
+    /// Commands waiting in the engine's queue, against its capacity
+    pub fn queue_depth(&self) -> QueueDepth {
+        let capacity = self.command_tx.max_capacity();
+        QueueDepth {
+            len: capacity - self.command_tx.capacity(),
+            capacity,
+        }
+    }
+
+    /// Time since the engine's last heartbeat, or since it was built if it
+    /// has not started
+    pub fn heartbeat_age(&self) -> Duration {
+        self.heartbeat.borrow().elapsed()
+    }
+
+    /// Whether the engine is running: it still takes commands, and has sent
+    /// a heartbeat lately. False once it has stopped or panicked, or while
+    /// it is stuck on a command.
+    pub fn is_live(&self) -> bool {
+        let stopped = self.command_tx.is_closed() || self.heartbeat.has_changed().is_err();
+        !stopped && self.heartbeat_age() < self.heartbeat_interval * STALE_HEARTBEATS
+    }
+
+    /// Submit an order and wait until the engine has accepted or rejected it.
+    /// Accepted means it passed every check and was matched, rested, held as
+    /// a stop or queued during a halt; the ack says which, and what traded.
+    /// Returns `None` if the engine has stopped.
+    pub async fn submit_order_confirmed(&self, request: OrderRequest) -> Option<Result<SubmitAck, RejectReason>> {
+        let (reply, outcome) = oneshot::channel();
+        self.command_tx
+            .send(EngineCommand::SubmitConfirmed { request, reply })
+            .await
+            .ok()?;
+        outcome.await.ok()
+    }
+
+    /// Ask the engine to cancel an order.
+    /// The outcome arrives as an `OrderCancelled` or `CancelRejected` event.
+    pub async fn cancel_order(&self, order_id: OrderId) -> Result<(), mpsc::error::SendError<EngineCommand>> {
+        self.command_tx.send(EngineCommand::Cancel { order_id }).await
+    }
+
+    /// Cancel an order and wait for the engine's verdict: what it took off
+    /// the book, or why it would not. With `owner_id`, only that owner's
+    /// order is cancelled. Returns `None` if the engine has stopped.
+    pub async fn cancel_order_confirmed(
+        &self,
+        order_id: OrderId,
+        owner_id: Option<String>,
+    ) -> Option<Result<CancelAck, RejectReason>> {
+        let (reply, outcome) = oneshot::channel();
+        self.command_tx
+            .send(EngineCommand::CancelConfirmed {
+                order_id,
+                owner_id,
+                reply,
+            })
+            .await
+            .ok()?;
+        outcome.await.ok()
+    }
+
+    /// Ask the engine to cancel all of an owner's open orders.
+    /// Each removal arrives as an `OrderCancelled` event, followed by `MassCancelled`.
+    pub async fn cancel_all(
+        &self,
+        owner_id: impl Into<String>,
+        side: Option<Side>,
+    ) -> Result<(), mpsc::error::SendError<EngineCommand>> {
+        self.command_tx
+            .send(EngineCommand::CancelAll {
+                owner_id: owner_id.into(),
+                side,
+            })
+            .await
+    }
+
+    /// Ask the engine to bust a recent trade.
+    /// The outcome arrives as a `TradeBusted` or `BustRejected` event.
+    pub async fn bust_trade(&self, trade_id: TradeId) -> Result<(), mpsc::error::SendError<EngineCommand>> {
+        self.command_tx.send(EngineCommand::BustTrade { trade_id }).await
+    }
+
+    /// Change the depth book updates carry or the least time between them;
+    /// `None` leaves a setting as it is
+    pub async fn set_book_updates(
+        &self,
+        depth_levels: Option<usize>,
+        min_update_interval: Option<Duration>,
+    ) -> Result<(), mpsc::error::SendError<EngineCommand>> {
+        self.command_tx
+            .send(EngineCommand::SetBookUpdates {
+                depth_levels,
+                min_update_interval,
+            })
+            .await
+    }
+
+    /// Set how fast `owner_id` may send orders, or the default when `None`;
+    /// a `None` limit lifts it
+    pub async fn set_rate_limit(
+        &self,
+        owner_id: Option<String>,
+        limit: Option<RateLimit>,
+    ) -> Result<(), mpsc::error::SendError<EngineCommand>> {
+        self.command_tx.send(EngineCommand::SetRateLimit { owner_id, limit }).await
+    }
+
+    /// Block an owner: its open orders are cancelled and new ones refused
+    pub async fn kill_switch(&self, owner_id: impl Into<String>) -> Result<(), mpsc::error::SendError<EngineCommand>> {
+        self.command_tx
+            .send(EngineCommand::KillSwitch {
+                owner_id: owner_id.into(),
+            })
+            .await
+    }
+
+    /// Lift an owner's block
+    pub async fn release_kill_switch(
+        &self,
+        owner_id: impl Into<String>,
+    ) -> Result<(), mpsc::error::SendError<EngineCommand>> {
+        self.command_tx
+            .send(EngineCommand::ReleaseKillSwitch {
+                owner_id: owner_id.into(),
+            })
+            .await
+    }
+
+    /// Replace the owner's quote, returning the ids its bid and ask will have.
+    /// The outcome arrives as a `QuoteReplaced` or `QuoteRejected` event.
+    pub async fn submit_quote(
+        &self,
+        quote: QuoteRequest,
+    ) -> Result<[OrderId; 2], mpsc::error::SendError<EngineCommand>> {
+        let ids = quote.order_ids.unwrap_or_else(|| [self.order_ids.reserve(), self.order_ids.reserve()]);
+        let quote = QuoteRequest { order_ids: Some(ids), ..quote };
+        self.command_tx.send(EngineCommand::Quote(quote)).await?;
+        Ok(ids)
+    }
+
+    /// Ask the engine to amend a resting order.
+    /// The outcome arrives as an `OrderModified` or `ModifyRejected` event.
+    pub async fn modify_order(
+        &self,
+        order_id: OrderId,
+        new_price: Option<Decimal>,
+        new_quantity: Option<Decimal>,
+    ) -> Result<(), mpsc::error::SendError<EngineCommand>> {
+        self.command_tx
+            .send(EngineCommand::Modify {
+                order_id,
+                new_price,
+                new_quantity,
+                new_order_id: None,
+            })
+            .await
+    }
+
+    /// Open a call auction
+    pub async fn start_auction(&self) -> Result<(), mpsc::error::SendError<EngineCommand>> {
+        self.command_tx.send(EngineCommand::StartAuction).await
+    }
+
+    /// Close the call auction and uncross
+    pub async fn end_auction(&self) -> Result<(), mpsc::error::SendError<EngineCommand>> {
+        self.command_tx.send(EngineCommand::EndAuction).await
+    }
+
+    /// The current book with depth grouped into `bucket_size` buckets.
+    /// Returns `None` if the engine has stopped.
+    pub async fn grouped_book(&self, bucket_size: Decimal) -> Option<OrderBookSnapshot> {
+        let (reply, snapshot) = oneshot::channel();
+        self.command_tx
+            .send(EngineCommand::GroupedBook { bucket_size, reply })
+            .await
+            .ok()?;
+        snapshot.await.ok()
+    }
+
+    /// The current book `levels` deep on each side, however deep the
+    /// broadcast book is. Returns `None` if the engine has stopped.
+    pub async fn deep_book(&self, levels: usize) -> Option<OrderBookSnapshot> {
+        let (reply, snapshot) = oneshot::channel();
+        self.command_tx.send(EngineCommand::DeepBook { levels, reply }).await.ok()?;
+        snapshot.await.ok()
+    }
+
+    /// Displayed liquidity within `distance` of each touch, with cumulative
+    /// depth. Returns `None` if the engine has stopped.
+    pub async fn liquidity(&self, distance: Decimal) -> Option<LiquiditySnapshot> {
+        let (reply, liquidity) = oneshot::channel();
+        self.command_tx
+            .send(EngineCommand::Liquidity { distance, reply })
+            .await
+            .ok()?;
+        liquidity.await.ok()
+    }
+
+    /// What taking `quantity` with an order on `side` would cost at the
+    /// current book. Returns `None` if the engine has stopped.
+    pub async fn price_impact(&self, side: Side, quantity: Decimal) -> Option<PriceImpact> {
+        let (reply, impact) = oneshot::channel();
+        self.command_tx
+            .send(EngineCommand::PriceImpact { side, quantity, reply })
+            .await
+            .ok()?;
+        impact.await.ok()
+    }
+
+    /// The engine's statistics, answered between commands like any other
+    /// query. Returns `None` if the engine has stopped.
+    pub async fn stats(&self) -> Option<EngineStats> {
+        let (reply, stats) = oneshot::channel();
+        self.command_tx.send(EngineCommand::QueryStats { reply }).await.ok()?;
+        stats.await.ok()
+    }
+
+    /// A copy of the engine's last day of trading, with the sequence of the
+    /// last event in it, for following the window from the event stream.
+    /// Returns `None` if the engine has stopped.
+    pub async fn trading_window(&self) -> Option<(u64, RollingWindow)> {
+        let (reply, window) = oneshot::channel();
+        self.command_tx.send(EngineCommand::QueryWindow { reply }).await.ok()?;
+        window.await.ok()
+    }
+
+    /// Open order `order_id` as it stands, on the book or held as a stop,
+    /// or `Some(None)` if it is not open. Returns `None` if the engine has
+    /// stopped.
+    pub async fn query_order(&self, order_id: OrderId) -> Option<Option<Order>> {
+        let (reply, order) = oneshot::channel();
+        self.command_tx.send(EngineCommand::QueryOrder { order_id, reply }).await.ok()?;
+        order.await.ok()
+    }
+
+    /// Up to `limit` resting orders, those of `owner_id` and on `side`
+    /// where given, with how many matched in all. Returns `None` if the
+    /// engine has stopped.
+    pub async fn list_orders(
+        &self,
+        owner_id: Option<String>,
+        side: Option<Side>,
+        limit: usize,
+    ) -> Option<OrderListing> {
+        let (reply, listing) = oneshot::channel();
+        self.command_tx
+            .send(EngineCommand::ListOrders {
+                owner_id,
+                side,
+                limit,
+                reply,
+            })
+            .await
+            .ok()?;
+        listing.await.ok()
+    }
+
+    /// How long the engine has taken over each order, by match outcome.
+    /// Returns `None` if the engine has stopped.
+    pub async fn order_latency(&self) -> Option<OrderLatency> {
+        let (reply, latency) = oneshot::channel();
+        self.command_tx.send(EngineCommand::QueryLatency { reply }).await.ok()?;
+        latency.await.ok()
+    }
+
+    /// Ask the engine to stop once it has processed every command already
+    /// queued, and wait until it has. Later commands are refused. Returns
+    /// the final state, or `None` if the engine had already stopped.
+    pub async fn shutdown(&self) -> Option<EngineSnapshot> {
+        let (reply, stopped) = oneshot::channel();
+        self.command_tx.send(EngineCommand::Shutdown { reply }).await.ok()?;
+        stopped.await.ok()
+    }
+
+    /// The engine's full state as of the commands queued so far; `None` if
+    /// the engine has stopped
+    pub async fn snapshot(&self) -> Option<EngineSnapshot> {
+        let (reply, snapshot) = oneshot::channel();
+        self.command_tx.send(EngineCommand::Snapshot { reply }).await.ok()?;
+        snapshot.await.ok()
+    }
+
+    /// Subscribe to engine events
+    pub fn subscribe(&self) -> broadcast::Receiver<SequencedEvent> {
+        self.event_tx.subscribe()
+    }
+
+    /// The lossless feed of the events the journaler records, for the one
+    /// journaler to take. `None` if the engine was built without a journal
+    /// or it was already taken.
+    pub fn take_journal(&self) -> Option<JournalReceiver> {
+        self.journal.lock().unwrap().take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::clock::{Clock, ManualClock};
+    use crate::engine::order::Side;
+    use crate::engine::scratch::ScratchDir;
+    use rust_decimal_macros::dec;
+    use tokio::time::{timeout, Duration};
+
+    fn drain(events: &mut broadcast::Receiver<SequencedEvent>) -> Vec<EngineEvent> {
+        let mut out = Vec::new();
+        while let Ok(sequenced) = events.try_recv() {
+            out.push(sequenced.event);
+        }
+        out
+    }
+
+    #[test]
+    fn test_reject_codes_are_all_listed() {
+        let (d, n) = (Decimal::ZERO, 0);
+        let reasons = [
+            RejectReason::PostOnlyWouldCross,
+            RejectReason::AlreadyExpired,
+            RejectReason::MissingExpiry,
+            RejectReason::PriceScaleExceeded { price: d, max_scale: 0 },
+            RejectReason::QuantityScaleExceeded { quantity: d, max_scale: 0 },
+            RejectReason::InvalidTickSize { price: d, tick_size: d },
+            RejectReason::PriceOutOfRange { price: d },
+            RejectReason::InvalidLotSize { quantity: d, lot_size: d },
+            RejectReason::BelowMinQuantity { quantity: d, min_quantity: d },
+            RejectReason::BelowMinNotional { notional: d, min_notional: d },
+            RejectReason::MaxQuantityExceeded { quantity: d, max_quantity: d },
+            RejectReason::MaxNotionalExceeded { notional: d, max_notional: d },
+            RejectReason::PriceOutsideBand { price: d, reference: d, band_pct: d },
+            RejectReason::TradingHalted,
+            RejectReason::AuctionOrderNotAllowed,
+            RejectReason::OwnerBlocked,
+            RejectReason::RateLimited { per_second: 0 },
+            RejectReason::TooManyOpenOrders { limit: n },
+            RejectReason::BookFull { limit: n },
+            RejectReason::DuplicateClientOrderId { client_order_id: String::new() },
+            RejectReason::UnknownOrder,
+            RejectReason::NotOrderOwner,
+            RejectReason::QuoteWouldSelfCross { bid_price: d, ask_price: d },
+            RejectReason::InvalidModification,
+            RejectReason::UnknownTrade,
+        ];
+        let codes: Vec<_> = reasons.iter().map(RejectReason::code).collect();
+        assert_eq!(codes, RejectReason::CODES);
+    }
+
+    #[tokio::test]
+    async fn test_engine_processes_orders() {
+        let (engine, handle) = EngineBuilder::new("BTC/USD").build();
+        let mut events = handle.subscribe();
+
+        // Spawn the engine
+        tokio::spawn(engine.run());
+
+        // Submit a sell order
+        handle
+            .submit_order(OrderRequest::new(Side::Sell, dec!(100), dec!(10)))
+            .await
+            .unwrap();
+
+        // The order is accepted and rests, then the book is updated
+        let mut received = Vec::new();
+        for _ in 0..3 {
+            received.push(timeout(Duration::from_millis(100), events.recv()).await.unwrap().unwrap().event);
+        }
+        assert!(matches!(received[0], EngineEvent::OrderAccepted { .. }));
+        assert!(matches!(
+            received[1],
+            EngineEvent::OrderRested { price, remaining_quantity, .. }
+                if price == dec!(100) && remaining_quantity == dec!(10)
+        ));
+
+        match &received[2] {
+            EngineEvent::OrderBookUpdate(snapshot) => {
+                assert_eq!(snapshot.best_ask, Some(dec!(100)));
+            }
+            _ => panic!("Expected OrderBookUpdate"),
+        }
+    }
+
+    #[test]
+    fn test_handles_reserve_ids_but_arrival_decides_priority() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD").build();
+        let mut events = handle.subscribe();
+        let sell = |id| EngineCommand::Submit(OrderRequest {
+            id: Some(id),
+            ..OrderRequest::new(Side::Sell, dec!(100), dec!(1))
+        });
+
+        // The second id is reserved after the first, but its order is queued ahead
+        let first = handle.order_ids.reserve();
+        let second = handle.order_ids.reserve();
+        engine.process_batch([sell(second), sell(first)]);
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(100), dec!(1)));
+
+        let events = drain(&mut events);
+        let accepted: Vec<_> = events
+            .iter()
+            .filter_map(|event| match event {
+                EngineEvent::OrderAccepted { order_id, .. } => Some(*order_id),
+                _ => None,
+            })
+            .collect();
+        // The engine's own numbering carries on past every reserved id
+        assert_eq!(accepted, vec![second, first, second + 1]);
+        assert_eq!(trades(&events)[0].maker_order_id, second);
+    }
+
+    #[test]
+    fn test_every_event_gets_the_next_sequence_number() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD").build();
+        let mut events = handle.subscribe();
+
+        // Numbers are spent while nobody listens, so a late subscriber sees a gap
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(100), dec!(1)));
+        let mut late = handle.subscribe();
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(101), dec!(1)));
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(101), dec!(2)));
+        engine.process_command(EngineCommand::Cancel { order_id: u64::MAX });
+
+        let mut seen = Vec::new();
+        while let Ok(SequencedEvent { seq, event, .. }) = events.try_recv() {
+            seen.push((seq, event));
+        }
+        assert_eq!(seen.iter().map(|(seq, _)| *seq).collect::<Vec<_>>(), (1..=seen.len() as u64).collect::<Vec<_>>());
+        assert!(seen.iter().any(|(_, e)| matches!(e, EngineEvent::Trade(_))));
+        assert!(matches!(seen.last(), Some((_, EngineEvent::CancelRejected { .. }))));
+        // The first order was accepted, rested and published
+        assert_eq!(late.try_recv().unwrap().seq, 4);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_drains_queued_commands() {
+        let (engine, handle) = EngineBuilder::new("BTC/USD").build();
+        let mut events = handle.subscribe();
+
+        // Everything is queued before the engine starts, with shutdown last
+        for _ in 0..50 {
+            handle.submit_order(OrderRequest::new(Side::Buy, dec!(100), dec!(1))).await.unwrap();
+        }
+        let (reply, stopped) = oneshot::channel();
+        handle.command_tx.send(EngineCommand::Shutdown { reply }).await.unwrap();
+        let engine = tokio::spawn(engine.run());
+        timeout(Duration::from_secs(1), stopped).await.unwrap().unwrap();
+        engine.await.unwrap();
+
+        // No queued order was dropped, and nothing more is taken
+        assert_eq!(handle.current_state.borrow().bid_depth, vec![(dec!(100), dec!(50), 50)]);
+        assert!(handle.submit_order(OrderRequest::new(Side::Buy, dec!(100), dec!(1))).await.is_err());
+        handle.shutdown().await;
+
+        let events = drain(&mut events);
+        assert!(matches!(events.last(), Some(EngineEvent::Stopped)));
+        let Some(EngineEvent::OrderBookUpdate(last_book)) = events.iter().rev().nth(1) else {
+            panic!("no final book update before Stopped");
+        };
+        assert_eq!(last_book.bid_depth, vec![(dec!(100), dec!(50), 50)]);
+        // All 50 were taken as one batch, so the book was published once
+        // for them and once more on stopping
+        assert_eq!(events.iter().filter(|e| matches!(e, EngineEvent::OrderBookUpdate(_))).count(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_engine_on_its_own_thread() {
+        let (engine, handle) = EngineBuilder::new("BTC/USD").mode(EngineMode::Thread { core: None }).build();
+        let mut events = handle.subscribe();
+        let engine = tokio::spawn(engine.run());
+
+        let maker = handle.submit_order_confirmed(OrderRequest::new(Side::Sell, dec!(100), dec!(1))).await;
+        assert!(matches!(maker, Some(Ok(_))));
+        let taker = handle.submit_order_confirmed(OrderRequest::new(Side::Buy, dec!(100), dec!(1))).await;
+        assert!(matches!(taker, Some(Ok(_))));
+        // The trade was broadcast before the taker heard back
+        let trades: Vec<_> = drain(&mut events)
+            .into_iter()
+            .filter_map(|e| match e {
+                EngineEvent::Trade(trade) => Some(trade),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(trades.len(), 1);
+        assert_eq!(handle.current_state.borrow().last_trade_price, Some(dec!(100)));
+
+        let snapshot = handle.shutdown().await.unwrap();
+        assert!(snapshot.resting.is_empty());
+        timeout(Duration::from_secs(1), engine).await.unwrap().unwrap();
+        assert!(matches!(drain(&mut events).last(), Some(EngineEvent::Stopped)));
+        assert!(handle.submit_order(OrderRequest::new(Side::Buy, dec!(100), dec!(1))).await.is_err());
+        assert!(handle.try_submit_order(OrderRequest::new(Side::Buy, dec!(100), dec!(1))).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_current_state_follows_every_book_update() {
+        let (engine, handle) = EngineBuilder::new("BTC/USD").build();
+        let mut state = handle.current_state.clone();
+        tokio::spawn(engine.run());
+
+        for (price, sequence) in [(dec!(99), 1), (dec!(99.5), 2)] {
+            handle
+                .submit_order(OrderRequest::new(Side::Buy, price, dec!(1)))
+                .await
+                .unwrap();
+            timeout(Duration::from_millis(100), state.changed()).await.unwrap().unwrap();
+            let snapshot = state.borrow_and_update();
+            assert_eq!((snapshot.best_bid, snapshot.sequence), (Some(price), sequence));
+        }
+        assert_eq!(handle.current_state.borrow().sequence, 2);
+    }
+
+    #[tokio::test]
+    async fn test_confirmed_submit_waits_for_verdict() {
+        let (engine, handle) = EngineBuilder::new("BTC/USD").build();
+        let mut events = handle.subscribe();
+        tokio::spawn(engine.run());
+
+        let maker = OrderRequest::new(Side::Sell, dec!(100), dec!(1));
+        let ack = handle.submit_order_confirmed(maker).await.unwrap().unwrap();
+        // Numbered by the engine as it arrived, the first
+        assert_eq!(ack.order_id, 1);
+        assert_eq!(ack.state, OrderState::Resting);
+        assert_eq!((ack.filled_quantity, ack.average_price, ack.remaining_quantity), (dec!(0), None, dec!(1)));
+
+        // Rejected while matching, not during validation
+        let post_only = OrderRequest::new(Side::Buy, dec!(100), dec!(1))
+            .with_client_order_id("po-1")
+            .with_post_only(true);
+        let post_only_id = 2;
+        assert_eq!(
+            handle.submit_order_confirmed(post_only).await,
+            Some(Err(RejectReason::PostOnlyWouldCross))
+        );
+
+        let rejected = loop {
+            match timeout(Duration::from_millis(100), events.recv()).await.unwrap().unwrap().event {
+                EngineEvent::OrderRejected {
+                    order_id,
+                    client_order_id,
+                    reason,
+                } => break (order_id, client_order_id, reason),
+                _ => continue,
+            }
+        };
+        assert_eq!(rejected, (post_only_id, Some("po-1".to_string()), RejectReason::PostOnlyWouldCross));
+    }
+
+    #[tokio::test]
+    async fn test_confirmed_submit_reports_fills_and_state() {
+        let (engine, handle) = EngineBuilder::new("BTC/USD").build();
+        tokio::spawn(engine.run());
+        for (price, quantity) in [(dec!(100), dec!(1)), (dec!(101), dec!(3))] {
+            handle
+                .submit_order_confirmed(OrderRequest::new(Side::Sell, price, quantity))
+                .await
+                .unwrap()
+                .unwrap();
+        }
+
+        // Sweeps both levels and rests the rest
+        let ack = handle
+            .submit_order_confirmed(OrderRequest::new(Side::Buy, dec!(101), dec!(5)))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(ack.state, OrderState::Resting);
+        assert_eq!((ack.filled_quantity, ack.remaining_quantity), (dec!(4), dec!(1)));
+        assert_eq!(ack.average_price, Some(dec!(100.75)));
+
+        // Fully traded against the resting bid
+        let sell = OrderRequest::new(Side::Sell, dec!(101), dec!(1));
+        let ack = handle.submit_order_confirmed(sell).await.unwrap().unwrap();
+        assert_eq!(ack.state, OrderState::Filled);
+        assert_eq!((ack.filled_quantity, ack.average_price), (dec!(1), Some(dec!(101))));
+
+        // Nothing left to take, so the IOC remainder is cancelled
+        let ioc = OrderRequest::new(Side::Sell, dec!(99), dec!(1)).with_time_in_force(TimeInForce::Ioc);
+        let ack = handle.submit_order_confirmed(ioc).await.unwrap().unwrap();
+        assert_eq!(ack.state, OrderState::Cancelled);
+        assert_eq!((ack.filled_quantity, ack.average_price, ack.remaining_quantity), (dec!(0), None, dec!(0)));
+
+        let stop = OrderRequest::new(Side::Buy, dec!(110), dec!(2)).with_stop_price(dec!(105));
+        let ack = handle.submit_order_confirmed(stop).await.unwrap().unwrap();
+        assert_eq!((ack.state, ack.remaining_quantity), (OrderState::PendingStop, dec!(2)));
+    }
+
+    #[tokio::test]
+    async fn test_engine_generates_trades() {
+        let (engine, handle) = EngineBuilder::new("BTC/USD").build();
+        let mut events = handle.subscribe();
+
+        tokio::spawn(engine.run());
+
+        // Submit a sell order
+        handle
+            .submit_order(OrderRequest::new(Side::Sell, dec!(100), dec!(10)))
+            .await
+            .unwrap();
+
+        // Drain the sell's acceptance, resting and book update
+        for _ in 0..3 {
+            let _ = timeout(Duration::from_millis(100), events.recv()).await;
+        }
+
+        // Submit a matching buy order
+        handle
+            .submit_order(OrderRequest::new(Side::Buy, dec!(100), dec!(10)))
+            .await
+            .unwrap();
+
+        // Should receive a trade event after the buy's acceptance
+        let _ = timeout(Duration::from_millis(100), events.recv()).await;
+        let event = timeout(Duration::from_millis(100), events.recv())
             .await
             .unwrap()
             .unwrap();
 
-        match event {
-            EngineEvent::Trade(trade) => {
-                assert_eq!(trade.price, dec!(100));
-                assert_eq!(trade.quantity, dec!(10));
+        match event.event {
+            EngineEvent::Trade(trade) => {
+                assert_eq!(trade.price, dec!(100));
+                assert_eq!(trade.quantity, dec!(10));
+            }
+            _ => panic!("Expected Trade event"),
+        }
+    }
+
+    #[test]
+    fn test_lifecycle_events_accept_then_rest_the_remainder() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD").build();
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(100), dec!(1)));
+        let mut events = handle.subscribe();
+
+        let buy = OrderRequest::new(Side::Buy, dec!(100), dec!(3)).with_owner("alice").with_client_order_id("a1");
+        let buy_id = engine.order_ids.peek();
+        engine.process_order(buy);
+        let events = drain(&mut events);
+        assert!(matches!(
+            &events[0],
+            EngineEvent::OrderAccepted { order_id, owner_id: Some(owner), .. }
+                if *order_id == buy_id && owner == "alice"
+        ));
+        // Rests once its trade is out, with what the trade left
+        let rested = events.iter().position(|e| matches!(e, EngineEvent::OrderRested { .. })).unwrap();
+        assert!(events[..rested].iter().any(|e| matches!(e, EngineEvent::Trade(_))));
+        assert!(matches!(
+            events[rested],
+            EngineEvent::OrderRested { order_id, price, remaining_quantity }
+                if order_id == buy_id && price == dec!(100) && remaining_quantity == dec!(2)
+        ));
+
+        // An IOC remainder never rests, and a refused order is never accepted
+        let mut events = handle.subscribe();
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(99), dec!(3)).with_time_in_force(TimeInForce::Ioc));
+        let duplicate = OrderRequest::new(Side::Buy, dec!(98), dec!(1)).with_client_order_id("a1");
+        engine.process_order(duplicate.with_owner("alice"));
+        let events = drain(&mut events);
+        assert_eq!(events.iter().filter(|e| matches!(e, EngineEvent::OrderAccepted { .. })).count(), 1);
+        assert!(!events.iter().any(|e| matches!(e, EngineEvent::OrderRested { .. })));
+    }
+
+    #[test]
+    fn test_post_only_rests_on_empty_book() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD").build();
+        let mut events = handle.subscribe();
+
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(100), dec!(1)).with_post_only(true));
+
+        let events = drain(&mut events);
+        assert!(!events.iter().any(|e| matches!(e, EngineEvent::OrderRejected { .. })));
+        assert_eq!(engine.order_book.best_bid(), Some(dec!(100)));
+    }
+
+    #[test]
+    fn test_post_only_rejected_at_opposing_best_price() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD").build();
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(100), dec!(1)));
+        let mut events = handle.subscribe();
+
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(100), dec!(1)).with_post_only(true));
+
+        // Accepted, then refused at matching
+        let events = drain(&mut events);
+        assert_eq!(events.len(), 2);
+        assert!(matches!(events[0], EngineEvent::OrderAccepted { .. }));
+        match &events[1] {
+            EngineEvent::OrderRejected { reason, .. } => {
+                assert_eq!(*reason, RejectReason::PostOnlyWouldCross);
+            }
+            other => panic!("Expected OrderRejected, got {:?}", other),
+        }
+        // Resting sell untouched, nothing added to the bid side
+        assert_eq!(engine.order_book.best_ask(), Some(dec!(100)));
+        assert_eq!(engine.order_book.best_bid(), None);
+    }
+
+    #[test]
+    fn test_post_only_sell_crossing_rejected() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD").build();
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(100), dec!(1)));
+        let mut events = handle.subscribe();
+
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(99), dec!(1)).with_post_only(true));
+
+        let events = drain(&mut events);
+        assert!(matches!(
+            events.as_slice(),
+            [
+                EngineEvent::OrderAccepted { .. },
+                EngineEvent::OrderRejected { reason: RejectReason::PostOnlyWouldCross, .. }
+            ]
+        ));
+        assert_eq!(engine.order_book.order_count(), 1);
+    }
+
+    #[test]
+    fn test_post_only_rests_inside_spread() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD").build();
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(101), dec!(1)));
+        let mut events = handle.subscribe();
+
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(100), dec!(1)).with_post_only(true));
+
+        let events = drain(&mut events);
+        assert!(!events.iter().any(|e| matches!(e, EngineEvent::Trade(_))));
+        assert_eq!(engine.order_book.best_bid(), Some(dec!(100)));
+        assert_eq!(engine.order_book.best_ask(), Some(dec!(101)));
+    }
+
+    fn trades(events: &[EngineEvent]) -> Vec<&Trade> {
+        events
+            .iter()
+            .filter_map(|e| match e {
+                EngineEvent::Trade(t) => Some(&**t),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_stop_is_held_until_triggered() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD").build();
+        let mut events = handle.subscribe();
+
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(110), dec!(5)));
+        engine.process_order(OrderRequest::market(Side::Buy, dec!(2)).with_stop_price(dec!(105)));
+
+        let events = drain(&mut events);
+        assert!(trades(&events).is_empty());
+        assert_eq!(engine.stops.len(), 1);
+        assert_eq!(engine.order_book.ask_depth(10), vec![(dec!(110), dec!(5), 1)]);
+    }
+
+    #[test]
+    fn test_query_order_finds_open_orders_only() {
+        let (mut engine, _handle) = EngineBuilder::new("BTC/USD").build();
+        let query = |engine: &mut MatchingEngine, order_id| {
+            let (reply, order) = oneshot::channel();
+            engine.process_command(EngineCommand::QueryOrder { order_id, reply });
+            order.blocking_recv().unwrap()
+        };
+
+        let submit = |engine: &mut MatchingEngine, request| {
+            let id = engine.order_ids.peek();
+            engine.process_order(request);
+            id
+        };
+        let maker = submit(&mut engine, OrderRequest::new(Side::Sell, dec!(110), dec!(5)));
+        let stop = submit(&mut engine, OrderRequest::market(Side::Buy, dec!(2)).with_stop_price(dec!(120)));
+        let taker = submit(&mut engine, OrderRequest::new(Side::Buy, dec!(110), dec!(1)));
+
+        let resting = query(&mut engine, maker).expect("maker is on the book");
+        assert_eq!((resting.remaining_quantity, resting.status), (dec!(4), OrderStatus::PartiallyFilled));
+        assert_eq!(query(&mut engine, stop).expect("stop is held").stop_price, Some(dec!(120)));
+        assert!(query(&mut engine, taker).is_none(), "a filled order has left the engine");
+        assert!(query(&mut engine, 0).is_none());
+    }
+
+    #[test]
+    fn test_buy_stop_fires_as_market_on_trade_print() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD").build();
+
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(105), dec!(1)));
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(110), dec!(5)));
+        let stop = OrderRequest::market(Side::Buy, dec!(2)).with_stop_price(dec!(105));
+        engine.process_order(stop);
+        let mut events = handle.subscribe();
+
+        // Trade prints at 105, firing the stop which lifts the 110 offer
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(105), dec!(1)));
+
+        let events = drain(&mut events);
+        let fills = trades(&events);
+        assert_eq!(fills.len(), 2);
+        assert_eq!(fills[0].price, dec!(105));
+        assert_eq!(fills[1].price, dec!(110));
+        assert_eq!(fills[1].quantity, dec!(2));
+        assert!(events.iter().any(|e| matches!(e, EngineEvent::StopTriggered { stop_price, .. } if *stop_price == dec!(105))));
+        assert!(engine.stops.is_empty());
+        assert_eq!(engine.order_book.ask_depth(10), vec![(dec!(110), dec!(3), 1)]);
+    }
+
+    #[test]
+    fn test_sell_stop_fires_on_trade_at_or_below() {
+        let (mut engine, _handle) = EngineBuilder::new("BTC/USD").build();
+
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(95), dec!(1)));
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(90), dec!(5)));
+        engine.process_order(OrderRequest::market(Side::Sell, dec!(1)).with_stop_price(dec!(96)));
+
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(95), dec!(1)));
+
+        assert!(engine.stops.is_empty());
+        assert_eq!(engine.last_trade_price, Some(dec!(90)));
+        assert_eq!(engine.order_book.bid_depth(10), vec![(dec!(90), dec!(4), 1)]);
+    }
+
+    #[test]
+    fn test_stop_cascade() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD").build();
+
+        for price in [dec!(101), dec!(102), dec!(103), dec!(104)] {
+            engine.process_order(OrderRequest::new(Side::Sell, price, dec!(1)));
+        }
+        // Each stop's fill prints at the next stop's trigger
+        engine.process_order(OrderRequest::market(Side::Buy, dec!(1)).with_stop_price(dec!(101)));
+        engine.process_order(OrderRequest::market(Side::Buy, dec!(1)).with_stop_price(dec!(102)));
+        engine.process_order(OrderRequest::market(Side::Buy, dec!(1)).with_stop_price(dec!(103)));
+        let mut events = handle.subscribe();
+
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(101), dec!(1)));
+
+        let events = drain(&mut events);
+        let prices: Vec<_> = trades(&events).iter().map(|t| t.price).collect();
+        assert_eq!(prices, vec![dec!(101), dec!(102), dec!(103), dec!(104)]);
+        let triggered = events
+            .iter()
+            .filter(|e| matches!(e, EngineEvent::StopTriggered { .. }))
+            .count();
+        assert_eq!(triggered, 3);
+        assert!(engine.stops.is_empty());
+        assert!(engine.order_book.best_ask().is_none());
+    }
+
+    #[test]
+    fn test_stop_already_through_market_fires_immediately() {
+        let (mut engine, _handle) = EngineBuilder::new("BTC/USD").build();
+
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(100), dec!(1)));
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(101), dec!(1)));
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(100), dec!(1)));
+
+        engine.process_order(OrderRequest::market(Side::Buy, dec!(1)).with_stop_price(dec!(99)));
+
+        assert!(engine.stops.is_empty());
+        assert!(engine.order_book.best_ask().is_none());
+    }
+
+    #[test]
+    fn test_untriggered_stop_limit_not_in_depth() {
+        let (mut engine, _handle) = EngineBuilder::new("BTC/USD").build();
+
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(95), dec!(1)));
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(105), dec!(1)));
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(101), dec!(3)).with_stop_price(dec!(100)));
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(99), dec!(3)).with_stop_price(dec!(96)));
+
+        assert_eq!(engine.stops.len(), 2);
+        assert_eq!(engine.order_book.bid_depth(10), vec![(dec!(95), dec!(1), 1)]);
+        assert_eq!(engine.order_book.ask_depth(10), vec![(dec!(105), dec!(1), 1)]);
+        assert_eq!(engine.current_state.borrow().bid_depth, vec![(dec!(95), dec!(1), 1)]);
+    }
+
+    #[test]
+    fn test_stop_limit_through_market_matches_on_trigger() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD").build();
+
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(100), dec!(1)));
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(101), dec!(2)));
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(103), dec!(2)));
+        // Limit 102 is already marketable against the 101 offer once triggered
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(102), dec!(3)).with_stop_price(dec!(100)));
+        let mut events = handle.subscribe();
+
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(100), dec!(1)));
+
+        let events = drain(&mut events);
+        let prices: Vec<_> = trades(&events).iter().map(|t| (t.price, t.quantity)).collect();
+        assert_eq!(prices, vec![(dec!(100), dec!(1)), (dec!(101), dec!(2))]);
+        // Unfilled remainder rests at the limit, not beyond it
+        assert_eq!(engine.order_book.bid_depth(10), vec![(dec!(102), dec!(1), 1)]);
+        assert_eq!(engine.order_book.ask_depth(10), vec![(dec!(103), dec!(2), 1)]);
+    }
+
+    #[test]
+    fn test_stop_limit_rests_when_limit_not_marketable() {
+        let (mut engine, _handle) = EngineBuilder::new("BTC/USD").build();
+
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(100), dec!(1)));
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(97), dec!(5)));
+        // Sell stop-limit triggers at 100 but will only sell at 99 or better
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(99), dec!(2)).with_stop_price(dec!(100)));
+
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(100), dec!(1)));
+
+        assert!(engine.stops.is_empty());
+        assert_eq!(engine.order_book.ask_depth(10), vec![(dec!(99), dec!(2), 1)]);
+        assert_eq!(engine.order_book.bid_depth(10), vec![(dec!(97), dec!(5), 1)]);
+    }
+
+    fn manual_engine() -> (MatchingEngine, EngineHandle, Arc<ManualClock>) {
+        use chrono::TimeZone;
+        let start = chrono::Utc.with_ymd_and_hms(2030, 1, 1, 0, 0, 0).unwrap();
+        let clock = Arc::new(ManualClock::new(start));
+        let (engine, handle) = EngineBuilder::new("BTC/USD").clock(clock.clone()).build();
+        (engine, handle, clock)
+    }
+
+    #[test]
+    fn test_expired_order_never_matches() {
+        let (mut engine, handle, clock) = manual_engine();
+        let expires_at = clock.now() + chrono::Duration::seconds(10);
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(100), dec!(1)).with_expires_at(expires_at));
+        let mut events = handle.subscribe();
+
+        // Aggressor arrives a microsecond after expiry, before any sweep ran
+        clock.set(expires_at + chrono::Duration::microseconds(1));
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(100), dec!(1)));
+
+        let events = drain(&mut events);
+        assert!(trades(&events).is_empty());
+        assert!(matches!(
+            events[0],
+            EngineEvent::OrderCancelled { reason: CancelReason::Expired, remaining_quantity, .. }
+                if remaining_quantity == dec!(1)
+        ));
+        // The buy rested instead of trading with the expired sell
+        assert!(engine.order_book.best_ask().is_none());
+        assert_eq!(engine.order_book.best_bid(), Some(dec!(100)));
+    }
+
+    #[test]
+    fn test_expire_command_sweeps_and_updates_book() {
+        let (mut engine, handle, clock) = manual_engine();
+        let expires_at = clock.now() + chrono::Duration::seconds(10);
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(99), dec!(2)).with_expires_at(expires_at));
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(98), dec!(1)));
+        let mut events = handle.subscribe();
+
+        engine.process_command(EngineCommand::ExpireOrders);
+        assert!(drain(&mut events).is_empty());
+
+        clock.set(expires_at);
+        engine.process_command(EngineCommand::ExpireOrders);
+
+        let events = drain(&mut events);
+        assert_eq!(events.len(), 2);
+        assert!(matches!(events[0], EngineEvent::OrderCancelled { reason: CancelReason::Expired, .. }));
+        match &events[1] {
+            EngineEvent::OrderBookUpdate(snapshot) => {
+                assert_eq!(snapshot.bid_depth, vec![(dec!(98), dec!(1), 1)]);
+            }
+            other => panic!("Expected OrderBookUpdate, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tick_expires_orders_on_an_idle_engine() {
+        use chrono::TimeZone;
+        for mode in [EngineMode::Task, EngineMode::Thread { core: None }] {
+            let start = chrono::Utc.with_ymd_and_hms(2030, 1, 1, 0, 0, 0).unwrap();
+            let clock = Arc::new(ManualClock::new(start));
+            let (engine, handle) = EngineBuilder::new("BTC/USD")
+                .mode(mode)
+                .clock(clock.clone())
+                .tick_interval(Duration::from_millis(5))
+                .build();
+            let mut events = handle.subscribe();
+            tokio::spawn(engine.run());
+            let expires_at = start + chrono::Duration::seconds(10);
+            let expiring = OrderRequest::new(Side::Buy, dec!(99), dec!(2)).with_expires_at(expires_at);
+            handle.submit_order_confirmed(expiring).await.unwrap().unwrap();
+
+            // No command and no sweeper: only the tick can notice the expiry
+            clock.set(expires_at);
+            let cancelled = loop {
+                let event = timeout(Duration::from_millis(500), events.recv()).await.unwrap().unwrap();
+                if let EngineEvent::OrderCancelled { reason, remaining_quantity, .. } = event.event {
+                    break (reason, remaining_quantity);
+                }
+            };
+            assert_eq!(cancelled, (CancelReason::Expired, dec!(2)), "{mode:?}");
+            let book = loop {
+                let event = timeout(Duration::from_millis(500), events.recv()).await.unwrap().unwrap();
+                if let EngineEvent::OrderBookUpdate(book) = event.event {
+                    break book;
+                }
+            };
+            assert!(book.bid_depth.is_empty(), "{mode:?}");
+            handle.shutdown().await;
+        }
+    }
+
+    #[test]
+    fn test_on_tick_sweeps_only_once_due() {
+        let (mut engine, handle, clock) = manual_engine();
+        let expires_at = clock.now() + chrono::Duration::seconds(10);
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(101), dec!(1)).with_expires_at(expires_at));
+        let mut events = handle.subscribe();
+
+        engine.on_tick();
+        assert!(drain(&mut events).iter().all(|event| matches!(event, EngineEvent::Heartbeat { .. })));
+        clock.set(expires_at);
+        engine.on_tick();
+        let events = drain(&mut events);
+        assert!(events
+            .iter()
+            .any(|event| matches!(event, EngineEvent::OrderCancelled { reason: CancelReason::Expired, .. })));
+        assert!(engine.order_book.best_ask().is_none());
+    }
+
+    #[test]
+    fn test_partially_filled_gtd_expires_with_remainder() {
+        let (mut engine, handle, clock) = manual_engine();
+        let expires_at = clock.now() + chrono::Duration::seconds(10);
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(100), dec!(5)).with_expires_at(expires_at));
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(100), dec!(2)));
+        let mut events = handle.subscribe();
+
+        clock.set(expires_at);
+        engine.process_command(EngineCommand::ExpireOrders);
+
+        let events = drain(&mut events);
+        assert!(matches!(
+            events[0],
+            EngineEvent::OrderCancelled { remaining_quantity, .. } if remaining_quantity == dec!(3)
+        ));
+        assert!(engine.order_book.best_ask().is_none());
+    }
+
+    #[test]
+    fn test_already_expired_order_rejected() {
+        let (mut engine, handle, clock) = manual_engine();
+        let mut events = handle.subscribe();
+
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(100), dec!(1)).with_expires_at(clock.now()));
+
+        let events = drain(&mut events);
+        assert!(matches!(
+            events.as_slice(),
+            [EngineEvent::OrderRejected { reason: RejectReason::AlreadyExpired, .. }]
+        ));
+        assert_eq!(engine.order_book.order_count(), 0);
+    }
+
+    fn cancels(events: &[EngineEvent]) -> Vec<(Decimal, CancelReason)> {
+        events
+            .iter()
+            .filter_map(|e| match e {
+                EngineEvent::OrderCancelled {
+                    remaining_quantity,
+                    reason,
+                    ..
+                } => Some((*remaining_quantity, *reason)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_each_removal_cancels_the_order_once() {
+        let clock = Arc::new(ManualClock::new(chrono::Utc::now()));
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD")
+            .clock(clock.clone())
+            .max_resting_orders(2, BookLimitPolicy::EvictFarthest)
+            .build();
+        let mut events = handle.subscribe();
+        let buy = |price| OrderRequest::new(Side::Buy, price, dec!(1));
+
+        let explicit = buy(dec!(90));
+        let explicit_id = engine.order_ids.peek();
+        engine.process_order(explicit);
+        engine.process_command(EngineCommand::Cancel { order_id: explicit_id });
+        engine.process_order(buy(dec!(90)).with_time_in_force(TimeInForce::Ioc));
+        engine.process_order(buy(dec!(90)).with_time_in_force(TimeInForce::Fok));
+        let expires_at = clock.now() + chrono::Duration::seconds(10);
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(110), dec!(1)).with_expires_at(expires_at));
+        clock.set(expires_at + chrono::Duration::seconds(1));
+        engine.process_command(EngineCommand::ExpireOrders);
+        // The newer bob order takes out the resting one, then rests itself
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(105), dec!(1)).with_owner("bob"));
+        engine.process_order(buy(dec!(105)).with_owner("bob").with_stp_policy(StpPolicy::CancelOldest));
+        engine.process_order(buy(dec!(95)));
+        engine.process_order(buy(dec!(100)));
+        engine.process_command(EngineCommand::CancelAll {
+            owner_id: "bob".to_string(),
+            side: None,
+        });
+        engine.process_order(buy(dec!(101)).with_owner("carol"));
+        engine.process_command(EngineCommand::KillSwitch {
+            owner_id: "carol".to_string(),
+        });
+
+        let events = drain(&mut events);
+        use CancelReason::*;
+        let reasons: Vec<_> = cancels(&events).into_iter().map(|(_, reason)| reason).collect();
+        assert_eq!(
+            reasons,
+            vec![UserRequested, Unfilled, FillOrKill, Expired, SelfTrade, Evicted, MassCancel, KillSwitch]
+        );
+        let mut cancelled: Vec<_> = events
+            .iter()
+            .filter_map(|e| match e {
+                EngineEvent::OrderCancelled { order_id, .. } => Some(*order_id),
+                _ => None,
+            })
+            .collect();
+        cancelled.sort();
+        cancelled.dedup();
+        assert_eq!(cancelled.len(), reasons.len());
+        // Only the bid at 100 is left
+        assert_eq!(engine.order_book.order_count(), 1);
+        assert_eq!(engine.order_book.best_bid(), Some(dec!(100)));
+    }
+
+    #[test]
+    fn test_ioc_fills_available_and_cancels_rest() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD").build();
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(100), dec!(2)));
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(102), dec!(2)));
+        let mut events = handle.subscribe();
+
+        engine.process_order(
+            OrderRequest::new(Side::Buy, dec!(101), dec!(5)).with_time_in_force(TimeInForce::Ioc),
+        );
+
+        let events = drain(&mut events);
+        assert_eq!(trades(&events).len(), 1);
+        assert_eq!(cancels(&events), vec![(dec!(3), CancelReason::Unfilled)]);
+        assert!(engine.order_book.best_bid().is_none());
+        assert_eq!(engine.order_book.best_ask(), Some(dec!(102)));
+    }
+
+    #[test]
+    fn test_ioc_fully_filled_emits_no_cancel() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD").build();
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(100), dec!(2)));
+        let mut events = handle.subscribe();
+
+        engine.process_order(
+            OrderRequest::new(Side::Buy, dec!(100), dec!(2)).with_time_in_force(TimeInForce::Ioc),
+        );
+
+        assert!(cancels(&drain(&mut events)).is_empty());
+    }
+
+    #[test]
+    fn test_fok_killed_when_not_fully_fillable() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD").build();
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(100), dec!(2)));
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(102), dec!(2)));
+        let mut events = handle.subscribe();
+
+        engine.process_order(
+            OrderRequest::new(Side::Buy, dec!(101), dec!(3)).with_time_in_force(TimeInForce::Fok),
+        );
+
+        let events = drain(&mut events);
+        assert!(trades(&events).is_empty());
+        assert_eq!(cancels(&events), vec![(dec!(3), CancelReason::FillOrKill)]);
+        assert_eq!(engine.order_book.ask_depth(10), vec![(dec!(100), dec!(2), 1), (dec!(102), dec!(2), 1)]);
+    }
+
+    #[test]
+    fn test_fok_fills_across_levels() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD").build();
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(100), dec!(2)));
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(102), dec!(2)));
+        let mut events = handle.subscribe();
+
+        engine.process_order(
+            OrderRequest::new(Side::Buy, dec!(102), dec!(3)).with_time_in_force(TimeInForce::Fok),
+        );
+
+        let events = drain(&mut events);
+        assert_eq!(trades(&events).len(), 2);
+        assert!(cancels(&events).is_empty());
+        assert_eq!(engine.order_book.ask_depth(10), vec![(dec!(102), dec!(1), 1)]);
+    }
+
+    #[test]
+    fn test_market_remainder_reported_unfilled() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD").build();
+        let mut events = handle.subscribe();
+
+        engine.process_order(OrderRequest::market(Side::Sell, dec!(4)));
+
+        assert_eq!(cancels(&drain(&mut events)), vec![(dec!(4), CancelReason::Unfilled)]);
+    }
+
+    #[test]
+    fn test_gtd_requires_expiry() {
+        let (mut engine, handle, clock) = manual_engine();
+        let mut events = handle.subscribe();
+
+        engine.process_order(
+            OrderRequest::new(Side::Buy, dec!(100), dec!(1)).with_time_in_force(TimeInForce::Gtd),
+        );
+        engine.process_order(
+            OrderRequest::new(Side::Buy, dec!(100), dec!(1))
+                .with_time_in_force(TimeInForce::Gtd)
+                .with_expires_at(clock.now() + chrono::Duration::seconds(1)),
+        );
+
+        let events = drain(&mut events);
+        assert!(matches!(
+            events[0],
+            EngineEvent::OrderRejected { reason: RejectReason::MissingExpiry, .. }
+        ));
+        assert_eq!(engine.order_book.order_count(), 1);
+    }
+
+    #[test]
+    fn test_cancel_resting_order() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD").build();
+        let resting = OrderRequest::new(Side::Buy, dec!(100), dec!(3));
+        let id = engine.order_ids.peek();
+        engine.process_order(resting);
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(100), dec!(1)));
+        let mut events = handle.subscribe();
+
+        engine.process_command(EngineCommand::Cancel { order_id: id });
+
+        let events = drain(&mut events);
+        assert_eq!(cancels(&events), vec![(dec!(3), CancelReason::UserRequested)]);
+        assert!(matches!(
+            events.last(),
+            Some(EngineEvent::OrderBookUpdate(snapshot)) if snapshot.bid_depth == vec![(dec!(100), dec!(1), 1)]
+        ));
+    }
+
+    #[test]
+    fn test_cancel_unknown_or_filled_order_rejected() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD").build();
+        let maker = OrderRequest::new(Side::Sell, dec!(100), dec!(1));
+        let maker_id = engine.order_ids.peek();
+        engine.process_order(maker);
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(100), dec!(1)));
+        let mut events = handle.subscribe();
+
+        engine.process_command(EngineCommand::Cancel { order_id: maker_id });
+        engine.process_command(EngineCommand::Cancel { order_id: u64::MAX });
+
+        let events = drain(&mut events);
+        assert_eq!(events.len(), 2);
+        assert!(events.iter().all(|e| matches!(
+            e,
+            EngineEvent::CancelRejected { reason: RejectReason::UnknownOrder, .. }
+        )));
+        assert_eq!(engine.order_book.order_count(), 0);
+    }
+
+    #[test]
+    fn test_cancel_pending_stop() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD").build();
+        let stop = OrderRequest::market(Side::Sell, dec!(2)).with_stop_price(dec!(95));
+        let id = engine.order_ids.peek();
+        engine.process_order(stop);
+        let mut events = handle.subscribe();
+
+        engine.process_command(EngineCommand::Cancel { order_id: id });
+
+        assert_eq!(cancels(&drain(&mut events)), vec![(dec!(2), CancelReason::UserRequested)]);
+        assert_eq!(engine.stats().pending_stops, 0);
+    }
+
+    fn modified(events: &[EngineEvent]) -> Vec<(OrderId, OrderId, Decimal, Decimal)> {
+        events
+            .iter()
+            .filter_map(|e| match e {
+                EngineEvent::OrderModified {
+                    order_id,
+                    new_order_id,
+                    price,
+                    remaining_quantity,
+                } => Some((*order_id, *new_order_id, *price, *remaining_quantity)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn modify(order_id: OrderId, new_price: Option<Decimal>, new_quantity: Option<Decimal>) -> EngineCommand {
+        EngineCommand::Modify {
+            order_id,
+            new_price,
+            new_quantity,
+            new_order_id: None,
+        }
+    }
+
+    #[test]
+    fn test_modify_size_down_keeps_priority() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD").build();
+        let first = OrderRequest::new(Side::Sell, dec!(100), dec!(5));
+        let first_id = engine.order_ids.peek();
+        engine.process_order(first);
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(100), dec!(4)));
+        let mut events = handle.subscribe();
+
+        engine.process_command(modify(first_id, None, Some(dec!(2))));
+        engine.process_command(modify(first_id, Some(dec!(100)), Some(dec!(1))));
+
+        let events = drain(&mut events);
+        assert_eq!(
+            modified(&events),
+            vec![
+                (first_id, first_id, dec!(100), dec!(2)),
+                (first_id, first_id, dec!(100), dec!(1)),
+            ]
+        );
+        assert!(cancels(&events).is_empty());
+        assert_eq!(engine.order_book.ask_depth(10), vec![(dec!(100), dec!(5), 2)]);
+
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(100), dec!(1)));
+        assert!(engine.order_book.get_order(first_id).is_none());
+        assert_eq!(engine.order_book.ask_depth(10), vec![(dec!(100), dec!(4), 1)]);
+    }
+
+    #[test]
+    fn test_modify_size_up_loses_priority() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD").build();
+        let first = OrderRequest::new(Side::Sell, dec!(100), dec!(2));
+        let second = OrderRequest::new(Side::Sell, dec!(100), dec!(2));
+        let (first_id, second_id) = (engine.order_ids.peek(), engine.order_ids.peek() + 1);
+        engine.process_order(first);
+        engine.process_order(second);
+        let mut events = handle.subscribe();
+
+        engine.process_command(modify(first_id, None, Some(dec!(3))));
+
+        let events = drain(&mut events);
+        let (_, new_id, _, remaining) = modified(&events)[0];
+        assert_ne!(new_id, first_id);
+        assert_eq!(remaining, dec!(3));
+        assert_eq!(cancels(&events), vec![(dec!(2), CancelReason::Replaced)]);
+        assert_eq!(engine.order_book.ask_depth(10), vec![(dec!(100), dec!(5), 2)]);
+
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(100), dec!(1)));
+        assert_eq!(engine.order_book.get_order(second_id).unwrap().remaining_quantity, dec!(1));
+        assert_eq!(engine.order_book.get_order(new_id).unwrap().remaining_quantity, dec!(3));
+    }
+
+    #[test]
+    fn test_modify_price_can_match() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD").build();
+        let bid = OrderRequest::new(Side::Buy, dec!(99), dec!(2));
+        let bid_id = engine.order_ids.peek();
+        engine.process_order(bid);
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(101), dec!(1)));
+        let mut events = handle.subscribe();
+
+        engine.process_command(modify(bid_id, Some(dec!(101)), None));
+
+        let events = drain(&mut events);
+        let (_, new_id, price, _) = modified(&events)[0];
+        assert_eq!(price, dec!(101));
+        assert_eq!(trades(&events).len(), 1);
+        assert_eq!(engine.order_book.get_order(new_id).unwrap().remaining_quantity, dec!(1));
+        assert!(engine.order_book.best_ask().is_none());
+    }
+
+    #[test]
+    fn test_modify_rejections() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD").build();
+        let bid = OrderRequest::new(Side::Buy, dec!(99), dec!(2));
+        let bid_id = engine.order_ids.peek();
+        engine.process_order(bid);
+        let mut events = handle.subscribe();
+
+        engine.process_command(modify(u64::MAX, None, Some(dec!(1))));
+        engine.process_command(modify(bid_id, None, Some(dec!(0))));
+
+        let reasons: Vec<_> = drain(&mut events)
+            .into_iter()
+            .filter_map(|e| match e {
+                EngineEvent::ModifyRejected { reason, .. } => Some(reason),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(reasons, vec![RejectReason::UnknownOrder, RejectReason::InvalidModification]);
+        assert_eq!(engine.order_book.bid_depth(10), vec![(dec!(99), dec!(2), 1)]);
+    }
+
+    #[test]
+    fn test_self_trade_cancels_resting_orders() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD").build();
+        let own_first = OrderRequest::new(Side::Sell, dec!(100), dec!(1)).with_owner("alice");
+        let other = OrderRequest::new(Side::Sell, dec!(100), dec!(1)).with_owner("bob");
+        let own_second = OrderRequest::new(Side::Sell, dec!(101), dec!(2)).with_owner("alice");
+        let anonymous = OrderRequest::new(Side::Sell, dec!(102), dec!(1));
+        let other_id = engine.order_ids.peek() + 1;
+        let anonymous_id = engine.order_ids.peek() + 3;
+        for request in [own_first, other, own_second, anonymous] {
+            engine.process_order(request);
+        }
+        let mut events = handle.subscribe();
+
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(102), dec!(3)).with_owner("alice"));
+
+        let events = drain(&mut events);
+        let makers: Vec<_> = trades(&events).iter().map(|t| t.maker_order_id).collect();
+        assert_eq!(makers, vec![other_id, anonymous_id]);
+        assert_eq!(
+            cancels(&events),
+            vec![(dec!(1), CancelReason::SelfTrade), (dec!(2), CancelReason::SelfTrade)]
+        );
+        assert!(engine.order_book.best_ask().is_none());
+        assert_eq!(engine.order_book.bid_depth(10), vec![(dec!(102), dec!(1), 1)]);
+    }
+
+    fn modifications(events: &[EngineEvent]) -> Vec<(OrderId, Decimal)> {
+        events
+            .iter()
+            .filter_map(|e| match e {
+                EngineEvent::OrderModified {
+                    order_id,
+                    remaining_quantity,
+                    ..
+                } => Some((*order_id, *remaining_quantity)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_cancel_newest_cancels_incoming_remainder() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD").stp_policy(StpPolicy::CancelNewest).build();
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(100), dec!(1)).with_owner("bob"));
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(100), dec!(2)).with_owner("alice"));
+        let mut events = handle.subscribe();
+
+        let taker = OrderRequest::new(Side::Buy, dec!(100), dec!(3)).with_owner("alice");
+        let taker_id = engine.order_ids.peek();
+        engine.process_order(taker);
+
+        let events = drain(&mut events);
+        assert_eq!(trades(&events).len(), 1);
+        assert!(events.iter().any(|e| matches!(
+            e,
+            EngineEvent::OrderCancelled { order_id, remaining_quantity, reason: CancelReason::SelfTrade }
+                if *order_id == taker_id && *remaining_quantity == dec!(2)
+        )));
+        assert_eq!(cancels(&events).len(), 1);
+        assert_eq!(engine.order_book.ask_depth(10), vec![(dec!(100), dec!(2), 1)]);
+        assert!(engine.order_book.best_bid().is_none());
+
+        // The order's own policy overrides the engine default
+        engine.process_order(
+            OrderRequest::new(Side::Buy, dec!(100), dec!(1))
+                .with_owner("alice")
+                .with_stp_policy(StpPolicy::CancelOldest),
+        );
+        assert_eq!(engine.order_book.best_bid(), Some(dec!(100)));
+        assert!(engine.order_book.best_ask().is_none());
+    }
+
+    #[test]
+    fn test_decrement_both_reports_both_orders() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD").build();
+        let resting = OrderRequest::new(Side::Sell, dec!(100), dec!(5)).with_owner("alice");
+        let resting_id = engine.order_ids.peek();
+        engine.process_order(resting);
+        let mut events = handle.subscribe();
+
+        // Smaller incoming order: it is cancelled, the resting one shrinks in place
+        let taker = OrderRequest::new(Side::Buy, dec!(100), dec!(2))
+            .with_owner("alice")
+            .with_stp_policy(StpPolicy::DecrementBoth);
+        let taker_id = engine.order_ids.peek();
+        engine.process_order(taker);
+
+        let first = drain(&mut events);
+        assert!(trades(&first).is_empty());
+        assert_eq!(modifications(&first), vec![(resting_id, dec!(3))]);
+        assert!(first.iter().any(|e| matches!(
+            e,
+            EngineEvent::OrderCancelled { order_id, remaining_quantity, reason: CancelReason::SelfTrade }
+                if *order_id == taker_id && *remaining_quantity == dec!(2)
+        )));
+        assert_eq!(engine.order_book.ask_depth(10), vec![(dec!(100), dec!(3), 1)]);
+
+        // Larger incoming order: the resting one goes, the remainder rests reduced
+        let taker = OrderRequest::new(Side::Buy, dec!(100), dec!(4))
+            .with_owner("alice")
+            .with_stp_policy(StpPolicy::DecrementBoth);
+        let taker_id = engine.order_ids.peek();
+        engine.process_order(taker);
+
+        let second = drain(&mut events);
+        assert_eq!(cancels(&second), vec![(dec!(3), CancelReason::SelfTrade)]);
+        assert_eq!(modifications(&second), vec![(taker_id, dec!(1))]);
+        assert!(engine.order_book.best_ask().is_none());
+        assert_eq!(engine.order_book.bid_depth(10), vec![(dec!(100), dec!(1), 1)]);
+    }
+
+    #[test]
+    fn test_fok_blocked_by_own_order_under_cancel_newest() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD").stp_policy(StpPolicy::CancelNewest).build();
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(100), dec!(1)).with_owner("alice"));
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(101), dec!(5)).with_owner("bob"));
+        let mut events = handle.subscribe();
+
+        engine.process_order(
+            OrderRequest::new(Side::Buy, dec!(101), dec!(2))
+                .with_owner("alice")
+                .with_time_in_force(TimeInForce::Fok),
+        );
+
+        let events = drain(&mut events);
+        assert!(trades(&events).is_empty());
+        assert_eq!(cancels(&events), vec![(dec!(2), CancelReason::FillOrKill)]);
+        assert_eq!(engine.order_book.order_count(), 2);
+    }
+
+    #[test]
+    fn test_equal_prices_share_one_normalized_level() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD").price_scale(2).quantity_scale(4).build();
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(100.10), dec!(1.50)));
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(100.1), dec!(0.5000)));
+        let mut events = handle.subscribe();
+
+        let depth = engine.order_book.ask_depth(10);
+        assert_eq!(depth.len(), 1);
+        assert_eq!(depth[0].0.to_string(), "100.1");
+        assert_eq!(depth[0].1, dec!(2));
+
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(100.101), dec!(1)));
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(100.1), dec!(1.00001)));
+        let events = drain(&mut events);
+        assert_eq!(reject_codes(&events), vec!["PRICE_SCALE_EXCEEDED", "QUANTITY_SCALE_EXCEEDED"]);
+        assert!(engine.order_book.best_bid().is_none());
+    }
+
+    #[test]
+    fn test_sequence_orders_arrivals_with_equal_timestamps() {
+        let clock = Arc::new(ManualClock::new(chrono::Utc::now()));
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD").clock(clock).build();
+        let first = OrderRequest::new(Side::Sell, dec!(100), dec!(1));
+        let second = OrderRequest::new(Side::Sell, dec!(100), dec!(1));
+        let stop = OrderRequest::market(Side::Buy, dec!(1)).with_stop_price(dec!(100));
+        let (first_id, second_id, stop_id) = (1, 2, 3);
+        for request in [first, second, stop] {
+            engine.process_order(request);
+        }
+        let sequence = |engine: &MatchingEngine, id| engine.order_book.get_order(id).map(|o| o.sequence);
+        assert_eq!(sequence(&engine, first_id), Some(1));
+        assert_eq!(sequence(&engine, second_id), Some(2));
+        assert_eq!(engine.stops.iter().map(|o| o.sequence).collect::<Vec<_>>(), vec![3]);
+        let mut events = handle.subscribe();
+
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(100), dec!(1)));
+
+        // The taker gets 4, and the stop it triggers is resequenced on release
+        let events = drain(&mut events);
+        let trades = trades(&events);
+        assert_eq!(
+            trades.iter().map(|t| (t.maker_order_id, t.taker_sequence, t.maker_sequence)).collect::<Vec<_>>(),
+            vec![(first_id, 4, 1), (second_id, 5, 2)]
+        );
+        assert_eq!(trades[1].taker_order_id, stop_id);
+        let fill_sequences: Vec<_> = events
+            .iter()
+            .filter_map(|e| match e {
+                EngineEvent::OrderFilled(fill) => Some(fill.sequence),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(fill_sequences, vec![4, 1, 5, 2]);
+    }
+
+    #[test]
+    fn test_sub_lot_residual_is_cancelled() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD").lot_size(dec!(0.01)).build();
+        let resting = OrderRequest::new(Side::Sell, dec!(100), dec!(2));
+        let resting_id = engine.order_ids.peek();
+        engine.process_order(resting);
+        // Amendments aren't held to the lot grid, so this leaves an off-lot size
+        engine.process_command(EngineCommand::Modify {
+            order_id: resting_id,
+            new_price: None,
+            new_quantity: Some(dec!(1.005)),
+            new_order_id: None,
+        });
+        let mut events = handle.subscribe();
+
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(100), dec!(1)));
+
+        let events = drain(&mut events);
+        assert_eq!(trades(&events).len(), 1);
+        assert!(events.iter().any(|e| matches!(
+            e,
+            EngineEvent::OrderCancelled { order_id, remaining_quantity, reason: CancelReason::Residual }
+                if *order_id == resting_id && *remaining_quantity == dec!(0.005)
+        )));
+        assert!(engine.order_book.best_ask().is_none());
+        assert_eq!(engine.order_book.order_count(), 0);
+    }
+
+    #[test]
+    fn test_cancel_all_for_owner() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD").build();
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(99), dec!(1)).with_owner("alice"));
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(99), dec!(2)).with_owner("bob"));
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(101), dec!(3)).with_owner("alice"));
+        engine.process_order(
+            OrderRequest::market(Side::Sell, dec!(4))
+                .with_owner("alice")
+                .with_stop_price(dec!(90)),
+        );
+        let mut events = handle.subscribe();
+
+        engine.process_command(EngineCommand::CancelAll {
+            owner_id: "alice".to_string(),
+            side: None,
+        });
+
+        let events = drain(&mut events);
+        // Resting orders first, bids before asks, then pending stops
+        assert_eq!(
+            cancels(&events),
+            vec![
+                (dec!(1), CancelReason::MassCancel),
+                (dec!(3), CancelReason::MassCancel),
+                (dec!(4), CancelReason::MassCancel)
+            ]
+        );
+        assert!(events.iter().any(|e| matches!(
+            e,
+            EngineEvent::MassCancelled { owner_id, side: None, count: 3 } if owner_id == "alice"
+        )));
+        assert_eq!(engine.order_book.bid_depth(10), vec![(dec!(99), dec!(2), 1)]);
+        assert!(engine.order_book.best_ask().is_none());
+        assert!(engine.stops.is_empty());
+    }
+
+    #[test]
+    fn test_quote_replaces_previous_pair_atomically() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD").build();
+        let first = QuoteRequest::new("mm", dec!(99), dec!(2), dec!(101), dec!(2));
+        // A quote's orders are numbered bid then ask
+        let (old_bid, old_ask) = (engine.order_ids.peek(), engine.order_ids.peek() + 1);
+        engine.process_command(EngineCommand::Quote(first));
+        // Part of the old ask trades before the refresh
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(101), dec!(0.5)).with_owner("taker"));
+        let mut events = handle.subscribe();
+
+        let second = QuoteRequest::new("mm", dec!(98), dec!(1), dec!(102), dec!(1));
+        let (new_bid, new_ask) = (engine.order_ids.peek(), engine.order_ids.peek() + 1);
+        engine.process_command(EngineCommand::Quote(second));
+
+        let events = drain(&mut events);
+        assert_eq!(
+            cancels(&events),
+            vec![(dec!(2), CancelReason::Replaced), (dec!(1.5), CancelReason::Replaced)]
+        );
+        assert!(events.iter().any(|e| matches!(
+            e,
+            EngineEvent::QuoteReplaced { bid_order_id, ask_order_id, cancelled, .. }
+                if (*bid_order_id, *ask_order_id) == (new_bid, new_ask) && *cancelled == vec![old_bid, old_ask]
+        )));
+        // A single book update, already showing only the new pair
+        let updates: Vec<_> = events
+            .iter()
+            .filter_map(|e| match e {
+                EngineEvent::OrderBookUpdate(snapshot) => Some((snapshot.bid_depth.clone(), snapshot.ask_depth.clone())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(updates, vec![(vec![(dec!(98), dec!(1), 1)], vec![(dec!(102), dec!(1), 1)])]);
+    }
+
+    #[test]
+    fn test_self_crossing_quote_rejected_and_old_quote_kept() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD").build();
+        engine.process_command(EngineCommand::Quote(QuoteRequest::new("mm", dec!(99), dec!(1), dec!(101), dec!(1))));
+        let mut events = handle.subscribe();
+
+        engine.process_command(EngineCommand::Quote(QuoteRequest::new("mm", dec!(100), dec!(1), dec!(100), dec!(1))));
+
+        let events = drain(&mut events);
+        assert!(matches!(
+            &events[..],
+            [EngineEvent::QuoteRejected { reason: RejectReason::QuoteWouldSelfCross { .. }, .. }]
+        ));
+        assert_eq!(engine.order_book.best_bid(), Some(dec!(99)));
+        assert_eq!(engine.order_book.best_ask(), Some(dec!(101)));
+    }
+
+    fn reject_codes(events: &[EngineEvent]) -> Vec<&'static str> {
+        events
+            .iter()
+            .filter_map(|e| match e {
+                EngineEvent::OrderRejected { reason, .. } => Some(reason.code()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_rate_limit_refuses_orders_over_it_by_engine_time() {
+        let clock = Arc::new(ManualClock::new(chrono::Utc::now()));
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD")
+            .clock(clock.clone())
+            .rate_limit(RateLimit::per_second(10).with_burst(2))
+            .owner_rate_limit("mm", RateLimit::per_second(100))
+            .build();
+        let order = |owner: &str| OrderRequest::new(Side::Buy, dec!(99), dec!(1)).with_owner(owner);
+        let mut events = handle.subscribe();
+
+        for _ in 0..3 {
+            engine.process_order(order("alice"));
+        }
+        // Anonymous orders are never limited
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(98), dec!(1)));
+        assert_eq!(reject_codes(&drain(&mut events)), vec!["RATE_LIMITED"]);
+        assert_eq!(engine.order_book.open_order_count("alice"), 2);
+
+        // A quote takes two orders' worth, and is refused whole
+        engine.process_command(EngineCommand::Quote(QuoteRequest::new("alice", dec!(90), dec!(1), dec!(110), dec!(1))));
+        assert!(matches!(
+            &drain(&mut events)[..],
+            [EngineEvent::QuoteRejected { reason: RejectReason::RateLimited { per_second: 10 }, .. }]
+        ));
+        clock.advance(chrono::Duration::milliseconds(100));
+        engine.process_order(order("alice"));
+        assert!(reject_codes(&drain(&mut events)).is_empty());
+
+        // Changed at runtime, and kept by snapshots
+        engine.process_command(EngineCommand::SetRateLimit {
+            owner_id: Some("alice".to_string()),
+            limit: Some(RateLimit::per_second(1)),
+        });
+        engine.process_order(order("alice"));
+        engine.process_order(order("alice"));
+        assert_eq!(reject_codes(&drain(&mut events)), vec!["RATE_LIMITED"]);
+        let limits = engine.snapshot().rate_limits.unwrap();
+        assert_eq!(limits.limit_for("alice"), Some(RateLimit::per_second(1)));
+        assert_eq!(limits.limit_for("mm"), Some(RateLimit::per_second(100)));
+        assert_eq!(limits.limit_for("bob"), Some(RateLimit::per_second(10).with_burst(2)));
+    }
+
+    #[test]
+    fn test_open_order_cap_counts_only_orders_that_rest() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD").max_open_orders(2).build();
+        let alice = |side, price, qty| OrderRequest::new(side, price, qty).with_owner("alice");
+        let mut events = handle.subscribe();
+
+        engine.process_order(alice(Side::Buy, dec!(99), dec!(1)));
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(101), dec!(1)).with_owner("bob"));
+        // Fills in full on arrival, so it never counts against the cap
+        engine.process_order(alice(Side::Buy, dec!(101), dec!(1)));
+        engine.process_order(alice(Side::Buy, dec!(98), dec!(1)));
+        // Third resting order is over the cap; one that cannot rest is not
+        engine.process_order(alice(Side::Buy, dec!(97), dec!(1)));
+        engine.process_order(alice(Side::Buy, dec!(97), dec!(1)).with_time_in_force(TimeInForce::Ioc));
+
+        assert_eq!(reject_codes(&drain(&mut events)), vec!["TOO_MANY_OPEN_ORDERS"]);
+        assert_eq!(engine.order_book.open_order_count("alice"), 2);
+
+        // A resting order filled later frees its slot
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(99), dec!(1)).with_owner("bob"));
+        assert_eq!(engine.order_book.open_order_count("alice"), 1);
+        engine.process_order(alice(Side::Buy, dec!(97), dec!(1)));
+        assert!(reject_codes(&drain(&mut events)).is_empty());
+
+        // Partially filled orders still count until they are gone
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(98), dec!(0.5)).with_owner("bob"));
+        assert_eq!(engine.order_book.open_order_count("alice"), 2);
+        engine.process_command(EngineCommand::CancelAll {
+            owner_id: "alice".to_string(),
+            side: None,
+        });
+        assert_eq!(engine.order_book.open_order_count("alice"), 0);
+    }
+
+    #[test]
+    fn test_open_order_cap_includes_stops_and_quotes() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD").max_open_orders(2).build();
+        let mut events = handle.subscribe();
+
+        engine.process_order(
+            OrderRequest::market(Side::Sell, dec!(1))
+                .with_owner("mm")
+                .with_stop_price(dec!(90)),
+        );
+        // Stop plus a new pair would be three
+        engine.process_command(EngineCommand::Quote(QuoteRequest::new("mm", dec!(99), dec!(1), dec!(101), dec!(1))));
+        assert!(matches!(
+            drain(&mut events)[..],
+            [
+                EngineEvent::OrderAccepted { .. },
+                EngineEvent::QuoteRejected { reason: RejectReason::TooManyOpenOrders { limit: 2 }, .. }
+            ]
+        ));
+
+        // Without the stop the pair fits, and refreshing it replaces rather than adds
+        engine.process_command(EngineCommand::CancelAll {
+            owner_id: "mm".to_string(),
+            side: None,
+        });
+        for bid in [dec!(99), dec!(98)] {
+            engine.process_command(EngineCommand::Quote(QuoteRequest::new("mm", bid, dec!(1), dec!(101), dec!(1))));
+        }
+        assert!(!drain(&mut events).iter().any(|e| matches!(e, EngineEvent::QuoteRejected { .. })));
+        assert_eq!(engine.order_book.open_order_count("mm"), 2);
+    }
+
+    fn cancellations(events: &[EngineEvent]) -> Vec<(OrderId, &'static str)> {
+        events
+            .iter()
+            .filter_map(|e| match e {
+                EngineEvent::OrderCancelled { order_id, reason, .. } => Some((*order_id, reason.code())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_full_book_side_rejects_new_passive_orders() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD")
+            .max_resting_orders(2, BookLimitPolicy::Reject)
+            .build();
+        let mut events = handle.subscribe();
+
+        for price in [dec!(99), dec!(98)] {
+            engine.process_order(OrderRequest::new(Side::Buy, price, dec!(1)));
+        }
+        // Behind the full side and ahead of it alike; the other side is unaffected
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(97), dec!(1)));
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(100), dec!(1)));
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(101), dec!(1)));
+        assert_eq!(reject_codes(&drain(&mut events)), vec!["BOOK_FULL", "BOOK_FULL"]);
+
+        // A crossing order still trades, but its remainder has nowhere to rest
+        let taker = OrderRequest::new(Side::Buy, dec!(101), dec!(3));
+        let taker_id = engine.order_ids.peek();
+        engine.process_order(taker);
+        let events = drain(&mut events);
+        assert!(events.iter().any(|e| matches!(e, EngineEvent::Trade(_))));
+        assert_eq!(cancellations(&events), vec![(taker_id, "BOOK_FULL")]);
+        assert_eq!(engine.order_book.side_order_count(Side::Buy), 2);
+        assert_eq!(engine.order_book.best_bid(), Some(dec!(99)));
+        assert_eq!(engine.order_book.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_full_book_side_evicts_farthest_but_never_the_touch() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD")
+            .max_resting_orders(3, BookLimitPolicy::EvictFarthest)
+            .build();
+        let mut events = handle.subscribe();
+
+        let ids: Vec<OrderId> = [dec!(99), dec!(98), dec!(98)]
+            .into_iter()
+            .map(|price| {
+                let request = OrderRequest::new(Side::Buy, price, dec!(1));
+                let id = engine.order_ids.peek();
+                engine.process_order(request);
+                id
+            })
+            .collect();
+        drain(&mut events);
+
+        // Better liquidity pushes out the newest order at the far level
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(100), dec!(2)));
+        assert_eq!(cancellations(&drain(&mut events)), vec![(ids[2], "EVICTED")]);
+        assert!(engine.order_book.get_order(ids[2]).is_none());
+        assert_eq!(
+            engine.order_book.bid_depth(10),
+            vec![(dec!(100), dec!(2), 1), (dec!(99), dec!(1), 1), (dec!(98), dec!(1), 1)]
+        );
+
+        // An order that would itself be the farthest is refused instead
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(98), dec!(1)));
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(97), dec!(1)));
+        assert_eq!(reject_codes(&drain(&mut events)), vec!["BOOK_FULL", "BOOK_FULL"]);
+
+        // A crossing remainder resting as the new touch evicts from the far end too
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(100.5), dec!(1)));
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(100.5), dec!(2)));
+        assert_eq!(cancellations(&drain(&mut events)), vec![(ids[1], "EVICTED")]);
+        assert_eq!(engine.order_book.best_bid(), Some(dec!(100.5)));
+
+        // Evicting down to a single level leaves the touch in place
+        for price in [dec!(100), dec!(100.5), dec!(100.5)] {
+            engine.process_order(OrderRequest::new(Side::Buy, price, dec!(1)));
+        }
+        assert!(engine.order_book.get_order(ids[0]).is_none());
+        assert_eq!(engine.order_book.bid_depth(10), vec![(dec!(100.5), dec!(3), 3)]);
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(100.5), dec!(1)));
+        assert_eq!(reject_codes(&drain(&mut events)).last(), Some(&"BOOK_FULL"));
+        assert_eq!(engine.order_book.side_order_count(Side::Buy), 3);
+        assert_eq!(engine.order_book.order_count(), 3);
+        assert_eq!(engine.order_book.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_engine_enforces_size_limits() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD")
+            .max_quantity(dec!(10))
+            .max_notional(dec!(1000))
+            .build();
+        let mut events = handle.subscribe();
+
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(100), dec!(10)));
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(1), dec!(11)));
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(101), dec!(10)));
+
+        // Exactly at both limits rests; one over either is refused
+        assert_eq!(reject_codes(&drain(&mut events)), vec!["MAX_QTY_EXCEEDED", "MAX_NOTIONAL_EXCEEDED"]);
+        assert_eq!(engine.order_book.bid_depth(10), vec![(dec!(100), dec!(10), 1)]);
+    }
+
+    #[test]
+    fn test_modify_held_to_size_limits() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD").max_quantity(dec!(10)).build();
+        let request = OrderRequest::new(Side::Buy, dec!(100), dec!(5));
+        let order_id = engine.order_ids.peek();
+        engine.process_order(request);
+        let mut events = handle.subscribe();
+
+        engine.process_command(modify(order_id, None, Some(dec!(10.5))));
+
+        assert!(matches!(
+            drain(&mut events)[..],
+            [EngineEvent::ModifyRejected { reason: RejectReason::MaxQuantityExceeded { .. }, .. }]
+        ));
+        assert_eq!(engine.order_book.bid_depth(10), vec![(dec!(100), dec!(5), 1)]);
+    }
+
+    fn fills(events: &[EngineEvent]) -> Vec<(OrderId, Decimal, Decimal, Decimal, OrderStatus)> {
+        events
+            .iter()
+            .filter_map(|e| match e {
+                EngineEvent::OrderFilled(f) => {
+                    Some((f.order_id, f.fill_qty, f.cumulative_filled, f.remaining, f.status))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_fills_follow_each_trade_for_taker_and_maker() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD").build();
+        let first = OrderRequest::new(Side::Sell, dec!(100), dec!(1));
+        let second = OrderRequest::new(Side::Sell, dec!(101), dec!(2));
+        let taker = OrderRequest::new(Side::Buy, dec!(101), dec!(2));
+        let (first_id, second_id, taker_id) = (1, 2, 3);
+        engine.process_order(first);
+        engine.process_order(second);
+        let mut events = handle.subscribe();
+
+        engine.process_order(taker);
+
+        let events = drain(&mut events);
+        assert!(matches!(
+            events[..4],
+            [
+                EngineEvent::OrderAccepted { .. },
+                EngineEvent::Trade(_),
+                EngineEvent::OrderFilled(_),
+                EngineEvent::OrderFilled(_)
+            ]
+        ));
+        use OrderStatus::{Filled, PartiallyFilled};
+        assert_eq!(
+            fills(&events),
+            vec![
+                (taker_id, dec!(1), dec!(1), dec!(1), PartiallyFilled),
+                (first_id, dec!(1), dec!(1), dec!(0), Filled),
+                (taker_id, dec!(1), dec!(2), dec!(0), Filled),
+                (second_id, dec!(1), dec!(1), dec!(1), PartiallyFilled),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_maker_fills_accumulate_across_triggered_stops() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD").build();
+        let maker = OrderRequest::new(Side::Sell, dec!(100), dec!(5));
+        let maker_id = engine.order_ids.peek();
+        engine.process_order(maker);
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(100), dec!(2)).with_stop_price(dec!(100)));
+        let mut events = handle.subscribe();
+
+        // The first fill triggers the stop, which hits the same maker again
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(100), dec!(1)));
+
+        let maker_fills: Vec<_> = fills(&drain(&mut events))
+            .into_iter()
+            .filter(|f| f.0 == maker_id)
+            .map(|(_, qty, cumulative, remaining, _)| (qty, cumulative, remaining))
+            .collect();
+        assert_eq!(maker_fills, vec![(dec!(1), dec!(1), dec!(4)), (dec!(2), dec!(3), dec!(2))]);
+    }
+
+    #[test]
+    fn test_market_order_stops_at_slippage_bound() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD").build();
+        for price in [dec!(100), dec!(101), dec!(102)] {
+            engine.process_order(OrderRequest::new(Side::Sell, price, dec!(2)));
+        }
+        let mut events = handle.subscribe();
+
+        // No trades or bids yet, so the bound is measured from the best ask
+        engine.process_order(OrderRequest::market(Side::Buy, dec!(5)).with_max_slippage(dec!(1)));
+
+        let events = drain(&mut events);
+        let prices: Vec<_> = events
+            .iter()
+            .filter_map(|e| match e {
+                EngineEvent::Trade(t) => Some(t.price),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(prices, vec![dec!(100), dec!(101)]);
+        assert_eq!(cancels(&events), vec![(dec!(1), CancelReason::SlippageLimit)]);
+        assert_eq!(engine.order_book.best_ask(), Some(dec!(102)));
+    }
+
+    #[test]
+    fn test_market_order_beyond_bound_at_first_level_executes_nothing() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD").build();
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(100), dec!(1)));
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(100), dec!(1)));
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(105), dec!(1)));
+        let mut events = handle.subscribe();
+
+        // Last trade at 100 puts the bound at 102, below the only ask
+        engine.process_order(OrderRequest::market(Side::Buy, dec!(1)).with_max_slippage(dec!(2)));
+
+        let events = drain(&mut events);
+        assert!(!events.iter().any(|e| matches!(e, EngineEvent::Trade(_))));
+        assert_eq!(cancels(&events), vec![(dec!(1), CancelReason::SlippageLimit)]);
+
+        // Without a bound the same order sweeps the level
+        engine.process_order(OrderRequest::market(Side::Buy, dec!(1)));
+        assert_eq!(engine.order_book.best_ask(), None);
+    }
+
+    #[test]
+    fn test_imbalance_recomputed_only_when_depth_changes() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD").imbalance_levels(2).build();
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(99), dec!(3)));
+        for price in [dec!(101), dec!(102), dec!(103)] {
+            engine.process_order(OrderRequest::new(Side::Sell, price, dec!(1)));
+        }
+        let imbalances = |handle: &EngineHandle| {
+            let snapshot = handle.current_state.borrow();
+            (snapshot.top_imbalance, snapshot.depth_imbalance)
+        };
+        assert_eq!(imbalances(&handle), (Some(dec!(0.5)), Some(dec!(0.2))));
+
+        // A trade against a hidden order prints without touching depth, so
+        // the last values are carried over
+        Arc::make_mut(engine.last_book_update.as_mut().unwrap()).top_imbalance = Some(dec!(0.9));
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(100), dec!(4)).with_hidden(true));
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(100), dec!(1)));
+        assert_eq!(imbalances(&handle), (Some(dec!(0.9)), Some(dec!(0.2))));
+
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(99), dec!(1)));
+        assert_eq!(imbalances(&handle), (Some(dec!(0.6)), Some(dec!(0.3333))));
+    }
+
+    #[test]
+    fn test_unchanged_top_of_book_is_not_rebroadcast() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD").build();
+        for i in 0..10 {
+            engine.process_order(OrderRequest::new(Side::Buy, dec!(99) - Decimal::from(i), dec!(1)));
+        }
+        let mut events = handle.subscribe();
+        let book_updates = |events: &mut broadcast::Receiver<SequencedEvent>| {
+            drain(events)
+                .into_iter()
+                .filter(|e| matches!(e, EngineEvent::OrderBookUpdate(_)))
+                .count()
+        };
+
+        // Below the tenth level, and hidden at the touch: depth is unchanged
+        let deep = OrderRequest::new(Side::Buy, dec!(80), dec!(1));
+        let deep_id = engine.order_ids.peek();
+        engine.process_order(deep);
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(99), dec!(1)).with_hidden(true));
+        engine.process_command(EngineCommand::Cancel { order_id: deep_id });
+        assert_eq!(book_updates(&mut events), 0);
+        assert_eq!(engine.stats().suppressed_book_updates, 3);
+
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(99.5), dec!(1)));
+        assert_eq!(book_updates(&mut events), 1);
+        assert_eq!(handle.current_state.borrow().best_bid, Some(dec!(99.5)));
+        assert_eq!(engine.stats().suppressed_book_updates, 3);
+    }
+
+    #[test]
+    fn test_book_updates_are_numbered_and_stamped() {
+        let (mut engine, handle, clock) = manual_engine();
+        let start = clock.now();
+        assert_eq!(handle.current_state.borrow().sequence, 0);
+        let mut events = handle.subscribe();
+        let updates = |events: &mut broadcast::Receiver<SequencedEvent>| -> Vec<(u64, chrono::DateTime<chrono::Utc>)> {
+            drain(events)
+                .into_iter()
+                .filter_map(|e| match e {
+                    EngineEvent::OrderBookUpdate(snapshot) => Some((snapshot.sequence, snapshot.timestamp)),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(99), dec!(1)));
+        clock.advance(chrono::Duration::seconds(1));
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(98), dec!(1)));
+        // A suppressed update takes no number, so the next one follows on
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(99), dec!(1)).with_hidden(true));
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(101), dec!(1)));
+        let later = start + chrono::Duration::seconds(1);
+        assert_eq!(updates(&mut events), vec![(1, start), (2, later), (3, later)]);
+        assert_eq!(handle.current_state.borrow().sequence, 3);
+
+        // A grouped view is numbered after the update it reflects
+        assert_eq!(engine.book_snapshot(Some(dec!(10)), 10).sequence, 3);
+    }
+
+    #[test]
+    fn test_hidden_liquidity_never_reaches_the_feed() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD").build();
+        let mut events = handle.subscribe();
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(99), dec!(1)));
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(100), dec!(4)).with_hidden(true));
+
+        {
+            let snapshot = handle.current_state.borrow();
+            assert_eq!(snapshot.best_bid, Some(dec!(99)));
+            assert_eq!(snapshot.bid_depth, vec![(dec!(99), dec!(1), 1)]);
+        }
+
+        // A seller still hits the hidden bid first, as an ordinary trade
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(99), dec!(2)));
+        let events = drain(&mut events);
+        let trade = events
+            .iter()
+            .find_map(|e| match e {
+                EngineEvent::Trade(t) => Some(&**t),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!((trade.price, trade.quantity), (dec!(100), dec!(2)));
+        assert!(events.iter().all(|e| match e {
+            EngineEvent::OrderBookUpdate(s) => s.bid_depth == vec![(dec!(99), dec!(1), 1)],
+            _ => true,
+        }));
+    }
+
+    #[test]
+    fn test_kill_switch_cancels_and_blocks_owner() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD").build();
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(99), dec!(1)).with_owner("rogue"));
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(101), dec!(2)).with_owner("rogue"));
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(102), dec!(1)).with_owner("bob"));
+        let mut events = handle.subscribe();
+
+        engine.process_command(EngineCommand::KillSwitch {
+            owner_id: "rogue".to_string(),
+        });
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(98), dec!(1)).with_owner("rogue"));
+        engine.process_command(EngineCommand::Quote(QuoteRequest::new("rogue", dec!(98), dec!(1), dec!(103), dec!(1))));
+
+        let events = drain(&mut events);
+        assert_eq!(
+            cancels(&events),
+            vec![(dec!(1), CancelReason::KillSwitch), (dec!(2), CancelReason::KillSwitch)]
+        );
+        assert!(events.iter().any(|e| matches!(
+            e,
+            EngineEvent::KillSwitchEngaged { owner_id, cancelled: 2 } if owner_id == "rogue"
+        )));
+        assert_eq!(reject_codes(&events), vec!["OWNER_BLOCKED"]);
+        assert!(events.iter().any(|e| matches!(
+            e,
+            EngineEvent::QuoteRejected { reason: RejectReason::OwnerBlocked, .. }
+        )));
+        assert_eq!(engine.order_book.order_count(), 1);
+
+        // Other owners are unaffected, and release lets the owner back in
+        engine.process_command(EngineCommand::ReleaseKillSwitch {
+            owner_id: "rogue".to_string(),
+        });
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(98), dec!(1)).with_owner("rogue"));
+        assert_eq!(engine.order_book.best_bid(), Some(dec!(98)));
+    }
+
+    #[test]
+    fn test_bust_trade_only_once() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD").build();
+        let mut events = handle.subscribe();
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(100), dec!(1)));
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(100), dec!(1)));
+        let trade_id = drain(&mut events)
+            .into_iter()
+            .find_map(|e| match e {
+                EngineEvent::Trade(trade) => Some(trade.id),
+                _ => None,
+            })
+            .unwrap();
+
+        engine.process_command(EngineCommand::BustTrade { trade_id });
+        engine.process_command(EngineCommand::BustTrade { trade_id });
+        engine.process_command(EngineCommand::BustTrade { trade_id: u64::MAX });
+
+        let events = drain(&mut events);
+        assert!(matches!(&events[0], EngineEvent::TradeBusted(trade) if trade.id == trade_id && trade.quantity == dec!(1)));
+        assert!(events[1..].iter().all(|e| matches!(
+            e,
+            EngineEvent::BustRejected { reason: RejectReason::UnknownTrade, .. }
+        )));
+        assert_eq!(events.len(), 3);
+        // Taken back out of the totals, once
+        let stats = engine.stats();
+        assert_eq!(stats.trades, TradeTotals::default());
+        assert_eq!((stats.last_24h.count, stats.last_24h.volume), (0, Decimal::ZERO));
+    }
+
+    #[test]
+    fn test_trade_totals_reach_book_updates() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD").build();
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(100), dec!(1)));
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(101), dec!(1)));
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(101), dec!(1.5)));
+
+        let expected = TradeTotals {
+            count: 2,
+            volume: dec!(1.5),
+            notional: dec!(150.5),
+        };
+        assert_eq!(engine.stats().trades, expected);
+        assert_eq!(handle.current_state.borrow().trades, expected);
+    }
+
+    #[test]
+    fn test_trade_totals_add_exactly() {
+        let trade = Trade::new(0, 0, dec!(12345.6789), dec!(0.00000001), Side::Buy, Utc::now());
+        let mut totals = TradeTotals::default();
+        for _ in 0..1_000_000 {
+            totals.add(&trade);
+        }
+        assert_eq!((totals.count, totals.volume, totals.notional), (1_000_000, dec!(0.01), dec!(123.456789)));
+        totals.remove(&trade);
+        assert_eq!(totals.volume, dec!(0.00999999));
+    }
+
+    #[test]
+    fn test_fok_ignores_own_liquidity() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD").build();
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(100), dec!(2)).with_owner("alice"));
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(100), dec!(1)).with_owner("bob"));
+        let mut events = handle.subscribe();
+
+        engine.process_order(
+            OrderRequest::new(Side::Buy, dec!(100), dec!(2))
+                .with_owner("alice")
+                .with_time_in_force(TimeInForce::Fok),
+        );
+
+        let events = drain(&mut events);
+        assert!(trades(&events).is_empty());
+        assert_eq!(cancels(&events), vec![(dec!(2), CancelReason::FillOrKill)]);
+        assert_eq!(engine.order_book.ask_depth(10), vec![(dec!(100), dec!(3), 2)]);
+    }
+
+    #[test]
+    fn test_duplicate_client_order_id_rejected_while_open() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD").build();
+        let mut events = handle.subscribe();
+        let tagged = |side, price| OrderRequest::new(side, price, dec!(1)).with_owner("alice").with_client_order_id("c-1");
+
+        engine.process_order(tagged(Side::Buy, dec!(99)));
+        // Same client id: refused for the same owner, fine for another owner
+        let duplicate = tagged(Side::Buy, dec!(98));
+        let duplicate_id = engine.order_ids.peek();
+        engine.process_order(duplicate);
+        engine.process_order(
+            OrderRequest::new(Side::Buy, dec!(98), dec!(1)).with_owner("bob").with_client_order_id("c-1"),
+        );
+
+        let events = drain(&mut events);
+        let rejected: Vec<_> = events
+            .iter()
+            .filter_map(|e| match e {
+                EngineEvent::OrderRejected { order_id, reason, .. } => Some((*order_id, reason.code())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(rejected, vec![(duplicate_id, "DUPLICATE_CLIENT_ORDER_ID")]);
+        assert_eq!(engine.order_book.order_count(), 2);
+    }
+
+    #[test]
+    fn test_client_order_id_reusable_after_fill_or_cancel() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD").build();
+        let tagged = |side, price| OrderRequest::new(side, price, dec!(1)).with_owner("alice").with_client_order_id("c-1");
+
+        // Filled as a maker
+        engine.process_order(tagged(Side::Sell, dec!(100)));
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(100), dec!(1)).with_owner("bob"));
+        // Cancelled while resting
+        let resting = tagged(Side::Sell, dec!(101));
+        let resting_id = engine.order_ids.peek();
+        engine.process_order(resting);
+        engine.process_command(EngineCommand::Cancel { order_id: resting_id });
+        // Held as a pending stop, then cancelled
+        let stop = tagged(Side::Sell, dec!(90)).with_stop_price(dec!(91));
+        let stop_id = engine.order_ids.peek();
+        engine.process_order(stop);
+        let mut events = handle.subscribe();
+        engine.process_order(tagged(Side::Sell, dec!(102)));
+        engine.process_command(EngineCommand::Cancel { order_id: stop_id });
+        engine.process_order(tagged(Side::Sell, dec!(103)));
+
+        let events = drain(&mut events);
+        let rejected: Vec<_> = events
+            .iter()
+            .filter_map(|e| match e {
+                EngineEvent::OrderRejected { reason, .. } => Some(reason.code()),
+                _ => None,
+            })
+            .collect();
+        // Only the submission made while the stop was pending collides
+        assert_eq!(rejected, vec!["DUPLICATE_CLIENT_ORDER_ID"]);
+        assert_eq!(engine.order_book.ask_depth(10), vec![(dec!(103), dec!(1), 1)]);
+    }
+
+    #[test]
+    fn test_trades_carry_client_order_ids() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD").build();
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(100), dec!(1)).with_client_order_id("maker-1"));
+        let mut events = handle.subscribe();
+
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(100), dec!(1)).with_client_order_id("taker-1"));
+
+        let events = drain(&mut events);
+        let trade = trades(&events)[0];
+        assert_eq!(trade.maker_client_order_id.as_deref(), Some("maker-1"));
+        assert_eq!(trade.taker_client_order_id.as_deref(), Some("taker-1"));
+    }
+
+    #[test]
+    fn test_engine_rejects_off_tick_price() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD").tick_size(dec!(0.01)).build();
+        let mut events = handle.subscribe();
+
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(100.005), dec!(1)));
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(100.10), dec!(1)));
+
+        let events = drain(&mut events);
+        assert!(matches!(
+            events[0],
+            EngineEvent::OrderRejected { reason: RejectReason::InvalidTickSize { .. }, .. }
+        ));
+        assert_eq!(engine.order_book.bid_depth(10), vec![(dec!(100.1), dec!(1), 1)]);
+    }
+
+    #[test]
+    fn test_modify_to_off_tick_price_rejected() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD").tick_size(dec!(0.5)).build();
+        let order = OrderRequest::new(Side::Buy, dec!(100), dec!(1));
+        let order_id = engine.order_ids.peek();
+        engine.process_order(order);
+        let mut events = handle.subscribe();
+
+        engine.modify_order(order_id, Some(dec!(100.25)), None, None);
+        assert!(matches!(
+            drain(&mut events)[..],
+            [EngineEvent::ModifyRejected { reason: RejectReason::InvalidTickSize { .. }, .. }]
+        ));
+        engine.modify_order(order_id, Some(dec!(100.5)), None, None);
+        assert_eq!(engine.order_book.bid_depth(10), vec![(dec!(100.5), dec!(1), 1)]);
+    }
+
+    #[test]
+    fn test_engine_enforces_quantity_rules() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD")
+            .lot_size(dec!(0.001))
+            .min_quantity(dec!(0.01))
+            .build();
+        let mut events = handle.subscribe();
+
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(100), dec!(0.0105)));
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(100), dec!(0.005)));
+
+        let codes: Vec<_> = drain(&mut events)
+            .iter()
+            .filter_map(|e| match e {
+                EngineEvent::OrderRejected { reason, .. } => Some(reason.code()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(codes, vec!["INVALID_LOT_SIZE", "BELOW_MIN_QUANTITY"]);
+        assert_eq!(engine.order_book.order_count(), 0);
+    }
+
+    #[test]
+    fn test_price_band_follows_last_trade() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD").price_band_pct(dec!(10)).build();
+        let mut events = handle.subscribe();
+
+        // Empty book: anything goes, then the mid-price becomes the reference
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(100), dec!(1)));
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(80), dec!(1)));
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(101), dec!(1)));
+        assert_eq!(engine.order_book.order_count(), 2);
+
+        // A trade at 100 recentres the band there
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(100), dec!(1)));
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(89), dec!(1)));
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(90), dec!(1)));
+
+        let rejected: Vec<_> = drain(&mut events)
+            .into_iter()
+            .filter_map(|e| match e {
+                EngineEvent::OrderRejected { reason, .. } => Some(reason),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            rejected,
+            vec![
+                RejectReason::PriceOutsideBand {
+                    price: dec!(80),
+                    reference: dec!(100),
+                    band_pct: dec!(10)
+                },
+                RejectReason::PriceOutsideBand {
+                    price: dec!(89),
+                    reference: dec!(100),
+                    band_pct: dec!(10)
+                },
+            ]
+        );
+        assert_eq!(engine.order_book.best_bid(), Some(dec!(90)));
+    }
+
+    fn breaker_engine(policy: HaltPolicy) -> (MatchingEngine, EngineHandle, Arc<ManualClock>) {
+        let (_, _, clock) = manual_engine();
+        let (engine, handle) = EngineBuilder::new("BTC/USD")
+            .clock(clock.clone())
+            .circuit_breaker(CircuitBreakerConfig {
+                max_move_pct: dec!(5),
+                window: chrono::Duration::seconds(60),
+                cooldown: chrono::Duration::seconds(30),
+                policy,
+            })
+            .build();
+        (engine, handle, clock)
+    }
+
+    #[test]
+    fn test_circuit_breaker_halts_and_resumes() {
+        let (mut engine, handle, clock) = breaker_engine(HaltPolicy::Reject);
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(100), dec!(1)));
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(110), dec!(1)));
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(99), dec!(4)));
+        let mut events = handle.subscribe();
+
+        // Sweeping both asks prints 100 then 110: a 10% move
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(110), dec!(2)));
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(99), dec!(1)));
+
+        let events = drain(&mut events);
+        assert_eq!(trades(&events).len(), 2);
+        assert!(events.iter().any(|e| matches!(e, EngineEvent::TradingHalted { last_price, .. } if *last_price == dec!(110))));
+        assert!(events.iter().any(|e| matches!(
+            e,
+            EngineEvent::OrderRejected { reason: RejectReason::TradingHalted, .. }
+        )));
+        // Resting orders stay on the book through the halt
+        assert_eq!(engine.order_book.bid_depth(10), vec![(dec!(99), dec!(4), 1)]);
+
+        clock.advance(chrono::Duration::seconds(30));
+        let mut events = handle.subscribe();
+        engine.process_command(EngineCommand::ExpireOrders);
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(99), dec!(1)));
+
+        let events = drain(&mut events);
+        assert!(matches!(events[0], EngineEvent::TradingResumed));
+        assert_eq!(trades(&events).len(), 1);
+    }
+
+    #[test]
+    fn test_circuit_breaker_queues_orders_until_resume() {
+        let (mut engine, handle, clock) = breaker_engine(HaltPolicy::Queue);
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(100), dec!(1)));
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(110), dec!(1)));
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(110), dec!(2)));
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(108), dec!(1)));
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(108), dec!(1)));
+        assert_eq!(engine.order_book.order_count(), 0);
+
+        clock.advance(chrono::Duration::seconds(30));
+        let mut events = handle.subscribe();
+        engine.process_command(EngineCommand::ExpireOrders);
+
+        let events = drain(&mut events);
+        assert!(matches!(events[0], EngineEvent::TradingResumed));
+        assert_eq!(trades(&events)[0].price, dec!(108));
+        assert_eq!(engine.order_book.order_count(), 0);
+    }
+
+    #[test]
+    fn test_pro_rata_engine_respects_lot_size() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD")
+            .matching_algorithm(MatchingAlgorithm::ProRata)
+            .lot_size(dec!(0.1))
+            .build();
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(100), dec!(1)));
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(100), dec!(2)));
+        let mut events = handle.subscribe();
+
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(100), dec!(1)));
+
+        let quantities: Vec<_> = trades(&drain(&mut events)).iter().map(|t| t.quantity).collect();
+        assert_eq!(quantities, vec![dec!(0.3), dec!(0.7)]);
+    }
+
+    #[test]
+    fn test_call_auction_uncrosses_at_single_price() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD").build();
+        engine.process_command(EngineCommand::StartAuction);
+        let mut events = handle.subscribe();
+
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(102), dec!(3)));
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(100), dec!(4)));
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(101), dec!(2)));
+        engine.process_order(OrderRequest::market(Side::Buy, dec!(1)));
+
+        let collected = drain(&mut events);
+        assert!(trades(&collected).is_empty());
+        let indicative: Vec<_> = collected
+            .iter()
+            .filter_map(|e| match e {
+                EngineEvent::AuctionIndicative { price, volume } => Some((*price, *volume)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            indicative,
+            vec![
+                (None, dec!(0)),
+                (Some(dec!(100)), dec!(3)),
+                (Some(dec!(100)), dec!(4)),
+            ]
+        );
+        assert!(collected.iter().any(|e| matches!(
+            e,
+            EngineEvent::OrderRejected { reason: RejectReason::AuctionOrderNotAllowed, .. }
+        )));
+
+        engine.process_command(EngineCommand::EndAuction);
+
+        let events = drain(&mut events);
+        let auction_trades = trades(&events);
+        assert!(auction_trades.iter().all(|t| t.price == dec!(100)));
+        assert_eq!(auction_trades.iter().map(|t| t.quantity).sum::<Decimal>(), dec!(4));
+        assert!(events.iter().any(|e| matches!(
+            e,
+            EngineEvent::AuctionEnded { price: Some(p), volume } if *p == dec!(100) && *volume == dec!(4)
+        )));
+        assert_eq!(engine.order_book.bid_depth(10), vec![(dec!(101), dec!(1), 1)]);
+        assert!(engine.order_book.best_ask().is_none());
+
+        // Continuous matching is back
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(101), dec!(1)));
+        assert_eq!(engine.order_book.order_count(), 0);
+    }
+
+    /// The book is sound and its best bid is below its best ask
+    fn assert_uncrossed(engine: &MatchingEngine) {
+        assert_eq!(engine.order_book.validate(), Ok(()));
+        if let (Some(bid), Some(ask)) = (engine.order_book.best_bid(), engine.order_book.best_ask()) {
+            assert!(bid < ask, "book crossed at {bid} / {ask}");
+        }
+    }
+
+    #[test]
+    fn test_no_path_onto_the_book_leaves_it_crossed() {
+        let (mut engine, _handle) = EngineBuilder::new("BTC/USD").build();
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(101), dec!(1)));
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(103), dec!(1)));
+
+        // Repricing a bid through the ask matches it before the rest rests
+        let bid = OrderRequest::new(Side::Buy, dec!(99), dec!(3));
+        let bid_id = engine.order_ids.peek();
+        engine.process_order(bid);
+        engine.process_command(modify(bid_id, Some(dec!(102)), None));
+        assert_uncrossed(&engine);
+        assert_eq!(engine.order_book.bid_depth(10), vec![(dec!(102), dec!(2), 1)]);
+
+        // So does a stop limit released through the ask
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(104), dec!(1)));
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(105), dec!(2)).with_stop_price(dec!(103)));
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(103), dec!(1)));
+        assert_uncrossed(&engine);
+        assert_eq!(engine.order_book.best_bid(), Some(dec!(105)));
+        assert!(engine.order_book.best_ask().is_none());
+
+        // And a quote whose bid crosses someone else's ask
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(106), dec!(1)));
+        let quote = QuoteRequest::new("mm", dec!(107), dec!(2), dec!(108), dec!(1));
+        engine.process_command(EngineCommand::Quote(quote));
+        assert_uncrossed(&engine);
+        assert_eq!(engine.order_book.best_bid(), Some(dec!(107)));
+
+        // An auction collects crossing orders, and ending it uncrosses them
+        engine.process_command(EngineCommand::StartAuction);
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(100), dec!(5)));
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(109), dec!(1)));
+        assert!(engine.order_book.best_bid() >= engine.order_book.best_ask());
+        engine.process_command(EngineCommand::EndAuction);
+        assert_uncrossed(&engine);
+    }
+
+    #[test]
+    fn test_timestamps_come_from_engine_clock() {
+        use chrono::TimeZone;
+
+        let start = chrono::Utc.with_ymd_and_hms(2030, 1, 1, 0, 0, 0).unwrap();
+        let clock = Arc::new(ManualClock::new(start));
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD").clock(clock.clone()).build();
+        let mut events = handle.subscribe();
+
+        engine.process_order(OrderRequest::new(Side::Sell, dec!(100), dec!(1)));
+        clock.advance(chrono::Duration::seconds(5));
+        engine.process_order(OrderRequest::new(Side::Buy, dec!(100), dec!(1)));
+
+        let events = drain(&mut events);
+        let fills = trades(&events);
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].timestamp, start + chrono::Duration::seconds(5));
+    }
+
+    #[test]
+    fn test_deterministic_replay_yields_identical_output() {
+        let epoch = DateTime::UNIX_EPOCH;
+        let resting = OrderRequest::new(Side::Sell, dec!(101), dec!(3)).with_owner("alice");
+        let expiring =
+            OrderRequest::new(Side::Buy, dec!(95), dec!(2)).with_expires_at(epoch + chrono::Duration::milliseconds(4));
+        let commands = [
+            EngineCommand::Submit(resting),
+            EngineCommand::Submit(expiring),
+            EngineCommand::Quote(QuoteRequest::new("mm", dec!(99), dec!(2), dec!(102), dec!(2))),
+            // The first order; its replacement and the trades get new ids
+            modify(1, Some(dec!(100)), None),
+            EngineCommand::Submit(OrderRequest::new(Side::Buy, dec!(102), dec!(4))),
+            EngineCommand::ExpireOrders,
+            EngineCommand::Submit(OrderRequest::market(Side::Sell, dec!(1))),
+        ];
+        // The recorded input, as the write-ahead log stores it
+        let log: Vec<String> = commands
+            .iter()
+            .map(|c| serde_json::to_string(&LoggedCommand::of(c).unwrap()).unwrap())
+            .collect();
+
+        let run = |builder: EngineBuilder| {
+            let (mut engine, handle) = builder.build();
+            let mut events = handle.subscribe();
+            for line in &log {
+                engine.process_command(serde_json::from_str::<LoggedCommand>(line).unwrap().into_command().unwrap());
+            }
+            let events = drain(&mut events);
+            let trades = serde_json::to_string(&trades(&events)).unwrap();
+            (trades, format!("{events:#?}"))
+        };
+
+        let (trades, events) = run(EngineBuilder::new("BTC/USD").deterministic(7));
+        assert_eq!(trades.matches("\"taker_order_id\"").count(), 3);
+        assert!(events.contains("Expired"));
+        assert_eq!(run(EngineBuilder::new("BTC/USD").deterministic(7)), (trades.clone(), events));
+
+        // The seed only picks the epoch: the same ids, known by other UUIDs
+        assert_eq!(run(EngineBuilder::new("BTC/USD").deterministic(8)).0, trades);
+        let epoch = |seed| EngineBuilder::new("BTC/USD").deterministic(seed).build().1.ids;
+        assert_eq!(epoch(7), epoch(7));
+        assert_ne!(epoch(7).uuid(1), epoch(8).uuid(1));
+        // Real time changes every timestamp
+        assert_ne!(run(EngineBuilder::new("BTC/USD")).0, trades);
+    }
+
+    #[tokio::test]
+    async fn test_batch_publishes_the_book_once_and_every_trade() {
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD").build();
+        let mut events = handle.subscribe();
+        let (reply, mut ack) = oneshot::channel();
+        let mut batch: Vec<_> = (0..5)
+            .map(|i| EngineCommand::Submit(OrderRequest::new(Side::Sell, dec!(100) + Decimal::from(i), dec!(1))))
+            .collect();
+        batch.push(EngineCommand::SubmitConfirmed {
+            request: OrderRequest::new(Side::Buy, dec!(102), dec!(2)),
+            reply,
+        });
+        batch.push(EngineCommand::Submit(OrderRequest::new(Side::Buy, dec!(102), dec!(2))));
+
+        engine.process_batch(batch);
+        let events = drain(&mut events);
+        assert_eq!(trades(&events).len(), 3);
+        let updates: Vec<_> = events
+            .iter()
+            .filter_map(|e| match e {
+                EngineEvent::OrderBookUpdate(book) => Some(&**book),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].best_bid, Some(dec!(102)));
+        assert!(matches!(events.last(), Some(EngineEvent::OrderBookUpdate(_))));
+        // The confirmation is sent after the update, with the state showing it
+        assert_eq!(ack.try_recv().unwrap().unwrap().filled_quantity, dec!(2));
+        assert_eq!(handle.current_state.borrow().best_ask, Some(dec!(103)));
+
+        // A lone command is a batch of one and publishes straight away
+        let mut events = handle.subscribe();
+        engine.process_command(EngineCommand::Cancel {
+            order_id: u64::MAX,
+        });
+        engine.process_command(EngineCommand::Submit(OrderRequest::new(Side::Sell, dec!(110), dec!(1))));
+        assert_eq!(drain(&mut events).iter().filter(|e| matches!(e, EngineEvent::OrderBookUpdate(_))).count(), 1);
+    }
+
+    fn book_updates(events: &[EngineEvent]) -> Vec<&OrderBookSnapshot> {
+        events
+            .iter()
+            .filter_map(|e| match e {
+                EngineEvent::OrderBookUpdate(book) => Some(&**book),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_min_update_interval_holds_book_updates_but_not_trades() {
+        let clock = Arc::new(ManualClock::new(chrono::Utc::now()));
+        let start = clock.now();
+        let (mut engine, handle) = EngineBuilder::new("BTC/USD")
+            .clock(clock.clone())
+            .depth_levels(2)
+            .min_update_interval(Duration::from_secs(1))
+            .build();
+        let mut events = handle.subscribe();
+        for price in [dec!(101), dec!(102), dec!(103)] {
+            engine.process_command(EngineCommand::Submit(OrderRequest::new(Side::Sell, price, dec!(1))));
+        }
+        engine.process_command(EngineCommand::Submit(OrderRequest::new(Side::Buy, dec!(101), dec!(1))));
+
+        // Only the first update went out; the trade did too
+        let sent = drain(&mut events);
+        assert_eq!(trades(&sent).len(), 1);
+        let updates = book_updates(&sent);
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].ask_depth, vec![(dec!(101), dec!(1), 1)]);
+
+        // The next command after the interval first sends the held update,
+        // timed when it fell due, with the book as it was then
+        clock.advance(chrono::Duration::seconds(3));
+        engine.process_command(EngineCommand::Cancel { order_id: u64::MAX });
+        let sent = drain(&mut events);
+        let updates = book_updates(&sent);
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].timestamp, start + chrono::Duration::seconds(1));
+        assert_eq!(updates[0].ask_depth, vec![(dec!(102), dec!(1), 1), (dec!(103), dec!(1), 1)]);
+
+        // Settings change at runtime and show in the stats
+        engine.process_command(EngineCommand::SetBookUpdates {
+            depth_levels: Some(1),
+            min_update_interval: Some(Duration::ZERO),
+        });
+        engine.process_command(EngineCommand::Submit(OrderRequest::new(Side::Sell, dec!(101.5), dec!(1))));
+        let sent = drain(&mut events);
+        let depths: Vec<_> = book_updates(&sent).iter().map(|book| book.ask_depth.clone()).collect();
+        assert_eq!(depths, [vec![(dec!(102), dec!(1), 1)], vec![(dec!(101.5), dec!(1), 1)]]);
+        let stats = engine.stats();
+        assert_eq!((stats.depth_levels, stats.min_update_interval), (1, Duration::ZERO));
+    }
+
+    #[tokio::test]
+    async fn test_held_book_update_is_sent_without_further_orders() {
+        let clock = Arc::new(ManualClock::new(chrono::Utc::now()));
+        let (engine, handle) = EngineBuilder::new("BTC/USD")
+            .clock(clock)
+            .min_update_interval(Duration::from_millis(50))
+            .build();
+        let mut events = handle.subscribe();
+        tokio::spawn(engine.run());
+        for price in [dec!(101), dec!(102)] {
+            handle.submit_order_confirmed(OrderRequest::new(Side::Sell, price, dec!(1))).await.unwrap().unwrap();
+        }
+
+        let mut depths = Vec::new();
+        while depths.len() < 2 {
+            let event = timeout(Duration::from_millis(500), events.recv()).await.unwrap().unwrap();
+            if let EngineEvent::OrderBookUpdate(book) = event.event {
+                depths.push(book.ask_depth.len());
+            }
+        }
+        assert_eq!(depths, [1, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_heartbeats_show_liveness_without_taking_event_numbers() {
+        let interval = Duration::from_millis(10);
+        let (engine, handle) = EngineBuilder::new("BTC/USD").heartbeat_interval(interval).build();
+        let mut events = handle.subscribe();
+        tokio::spawn(engine.run());
+        handle.submit_order_confirmed(OrderRequest::new(Side::Buy, dec!(99), dec!(1))).await.unwrap().unwrap();
+
+        let mut last_seq = 0;
+        let mut beats = Vec::new();
+        while beats.last().is_none_or(|&(_, orders)| orders == 0) {
+            let event = timeout(Duration::from_millis(500), events.recv()).await.unwrap().unwrap();
+            match event.event {
+                EngineEvent::Heartbeat { seq, orders_processed, .. } => {
+                    assert_eq!(event.seq, last_seq);
+                    beats.push((seq, orders_processed));
+                }
+                _ => last_seq = event.seq,
             }
-            _ => panic!("Expected Trade event"),
         }
+        assert!(beats.iter().map(|&(seq, _)| seq).eq(1..=beats.len() as u64));
+        assert_eq!(beats.last(), Some(&(beats.len() as u64, 1)));
+        assert!(handle.is_live());
+
+        // An engine that never runs stops counting as live once its
+        // heartbeats are overdue
+        let (_idle, idle) = EngineBuilder::new("BTC/USD").heartbeat_interval(interval).build();
+        assert!(idle.is_live());
+        tokio::time::sleep(interval * STALE_HEARTBEATS).await;
+        assert!(!idle.is_live());
+    }
+
+    #[test]
+    fn test_uptime_follows_the_engine_clock() {
+        let start = Utc::now();
+        let clock = Arc::new(ManualClock::new(start));
+        let (engine, _handle) = EngineBuilder::new("BTC/USD").clock(clock.clone()).build();
+        assert_eq!(engine.stats().uptime, Duration::ZERO);
+        clock.advance(chrono::Duration::hours(2));
+        assert_eq!(engine.stats().uptime, Duration::from_secs(2 * 3600));
+        // Time set back doesn't make the engine younger
+        clock.set(start);
+        assert_eq!(engine.stats().uptime, Duration::from_secs(2 * 3600));
+    }
+
+    #[tokio::test]
+    async fn test_panicked_engine_is_rebuilt_from_its_log() {
+        use crate::engine::wal::{FsyncPolicy, WalWriter};
+
+        for mode in [EngineMode::Task, EngineMode::Thread { core: None }] {
+            let dir = ScratchDir::new("recover");
+            let builder = {
+                let dir = dir.to_path_buf();
+                move || {
+                    let (wal, records) = WalWriter::open(&dir, "BTC/USD", FsyncPolicy::Never).unwrap();
+                    EngineBuilder::new("BTC/USD").mode(mode).write_ahead_log(wal, records)
+                }
+            };
+            let (engine, handle) = builder().recover_with(builder.clone()).build();
+            let mut events = handle.subscribe();
+            tokio::spawn(engine.run());
+
+            let maker = OrderRequest::new(Side::Sell, dec!(101), dec!(1)).with_owner("maker");
+            assert!(matches!(handle.submit_order_confirmed(maker).await, Some(Ok(_))));
+            // The second command logged panics the engine; its caller hears nothing
+            let poison = OrderRequest::new(Side::Buy, dec!(99), dec!(1)).with_owner(PANIC_OWNER);
+            assert!(handle.submit_order_confirmed(poison).await.is_none());
+            let recovered = loop {
+                let event = timeout(Duration::from_secs(1), events.recv()).await.unwrap().unwrap();
+                if let EngineEvent::Recovered { reason, voided_record } = event.event {
+                    break (reason, voided_record);
+                }
+            };
+            assert!(recovered.0.contains(PANIC_OWNER));
+            assert_eq!(recovered.1, Some(2));
+
+            // The same handle reaches the rebuilt engine, which kept the maker
+            assert!(handle.is_live());
+            let taker = OrderRequest::new(Side::Buy, dec!(101), dec!(1)).with_owner("taker");
+            assert!(matches!(handle.submit_order_confirmed(taker).await, Some(Ok(_))));
+            let stats = handle.stats().await.unwrap();
+            assert_eq!((stats.trades.count, stats.order_count), (1, 0));
+            handle.shutdown().await.unwrap();
+
+            // A restart from the log skips the voided command too
+            let (restored, _) = builder().build();
+            assert_eq!(restored.stats().trades.count, 1);
+        }
+    }
+
+    #[test]
+    fn test_order_latency_by_match_outcome() {
+        let (mut engine, _handle) = EngineBuilder::new("BTC/USD").build();
+        for price in [dec!(101), dec!(102)] {
+            engine.process_command(EngineCommand::Submit(OrderRequest::new(Side::Sell, price, dec!(2))));
+        }
+        // One price, then two
+        engine.process_command(EngineCommand::Submit(OrderRequest::new(Side::Buy, dec!(101), dec!(1))));
+        engine.process_command(EngineCommand::Submit(OrderRequest::new(Side::Buy, dec!(102), dec!(2))));
+        // Refused before matching, so not timed
+        let crossing = OrderRequest::new(Side::Buy, dec!(102), dec!(1)).with_post_only(true);
+        engine.process_command(EngineCommand::Submit(crossing));
+
+        let latency = engine.stats().latency;
+        let counts = (latency.rested.count, latency.matched.count, latency.swept.count);
+        assert_eq!(counts, (2, 1, 1));
+        assert!(latency.swept.max_ns > 0 && latency.swept.p50_ns <= latency.swept.max_ns);
+
+        let (reply, queried) = oneshot::channel();
+        engine.process_command(EngineCommand::QueryLatency { reply });
+        assert_eq!(queried.blocking_recv().unwrap(), latency);
     }
 }
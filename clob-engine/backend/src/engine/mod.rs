@@ -1,9 +1,61 @@
 //! Engine module - Core matching engine and order book logic.
 
+mod affinity;
+pub mod channel;
+pub mod clock;
+#[cfg(test)]
+mod conformance;
+pub mod depth_diff;
+pub mod engines;
+pub mod expiry;
+pub mod halt;
+pub mod ids;
+pub mod journal;
+pub mod ladder;
+pub mod latency;
 pub mod matcher;
 pub mod order;
 pub mod order_book;
+#[cfg(test)]
+mod proptests;
+pub mod rate_limit;
+pub mod registry;
+pub mod rolling;
+#[cfg(test)]
+pub(crate) mod scratch;
+pub mod snapshot;
+pub mod stops;
+pub mod symbol;
+pub mod ticks;
+pub mod wal;
 
-pub use matcher::{EngineBuilder, EngineEvent, EngineHandle, MatchingEngine};
-pub use order::{Order, OrderRequest, OrderStatus, Side, Trade};
-pub use order_book::OrderBook;
+pub use channel::{CommandSender, EngineMode, EngineThreads, ENGINE_THREADS_VAR};
+pub use clock::{Clock, LogicalClock, ManualClock, SharedClock, SystemClock};
+pub use depth_diff::{compute_depth_diff, DepthDiff, DepthSnapshot, LevelChange};
+pub use engines::{url_key, EngineRegistry};
+pub use expiry::run_expiry_sweeper;
+pub use halt::{CircuitBreakerConfig, HaltPolicy};
+pub use ids::{IdEpoch, IdSource, OrderIds, RandomIds, SeededIds};
+pub use journal::{JournalReceiver, JOURNAL_BLOCK, JOURNAL_CAPACITY};
+pub use latency::{LatencyHistogram, LatencySummary, MatchKind, OrderLatency};
+pub use matcher::{
+    BookLimitPolicy, CancelAck, CancelReason, EngineBuilder, EngineCommand, EngineEvent, EngineHandle,
+    InvariantPolicy, MatchingEngine, OrderListing, OrderState, QueueDepth, Rebuild, RejectReason, SequencedEvent,
+    SubmitAck, TradeTotals,
+};
+pub use order::{
+    Fill, FillError, Order, OrderId, OrderRequest, OrderStatus, OrderType, QuoteRequest, Side, StpPolicy, TimeInForce,
+    Trade, TradeId,
+};
+pub use order_book::{
+    depth_checksum, notional_depth, Bbo, BookInvariantError, LevelView, MatchOutcome, MatchResult, MatchingAlgorithm,
+    OrderBook, PriceImpact, ReduceError,
+};
+pub use rate_limit::{InvalidRateLimit, RateLimit, RateLimits, RATE_LIMIT_VAR};
+pub use registry::{RegistryError, SymbolRegistry};
+pub use rolling::{PastTrade, RollingWindow, WindowSummary};
+pub use snapshot::{run_snapshotter, snapshot_path, EngineSnapshot, SnapshotError, SNAPSHOT_DIR_VAR};
+pub use stops::StopBook;
+pub use symbol::SymbolConfig;
+pub use ticks::{PriceGrid, TickError, Ticks};
+pub use wal::{prune_wal, FsyncPolicy, LoggedCommand, WalError, WalRecord, WalWriter, WAL_FSYNC_VAR};
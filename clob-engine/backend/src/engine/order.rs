@@ -1,12 +1,26 @@
 //! Core order type definitions for the CLOB engine.
 
+use crate::engine::clock::{Clock, SystemClock};
 use chrono::{DateTime, Utc};
 use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
-use uuid::Uuid;
+use utoipa::ToSchema;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Engine-assigned order id: the arrival sequence the order got on reaching
+/// the engine, so a later order always has a larger id
+pub type OrderId = u64;
+
+/// Engine-assigned trade id, counting up from 1 in execution order
+pub type TradeId = u64;
+
+/// Next id for an order built outside an engine, as tests and benchmarks
+/// do, so a book driven directly still sees distinct ids. Starts well clear
+/// of the ids an engine assigns.
+static NEXT_UNMANAGED_ID: AtomicU64 = AtomicU64::new(1 << 48);
 
 /// Order side - Buy or Sell
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum Side {
     Buy,
@@ -22,8 +36,76 @@ impl std::fmt::Display for Side {
     }
 }
 
+/// Order type - how the order's price is treated when matching
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum OrderType {
+    /// Executes at the limit price or better, remainder rests
+    #[default]
+    Limit,
+    /// Executes against whatever liquidity exists, remainder is discarded
+    Market,
+}
+
+/// Time in force - how long an order stays working
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum TimeInForce {
+    /// Good till cancelled: the remainder rests indefinitely
+    #[default]
+    Gtc,
+    /// Immediate or cancel: fill what is available now, cancel the rest
+    Ioc,
+    /// Fill or kill: fill the whole quantity immediately or nothing at all
+    Fok,
+    /// Good till date: rests until `expires_at`
+    Gtd,
+}
+
+impl TimeInForce {
+    /// Whether an unfilled remainder may rest on the book
+    pub fn rests(&self) -> bool {
+        matches!(self, TimeInForce::Gtc | TimeInForce::Gtd)
+    }
+}
+
+impl std::fmt::Display for TimeInForce {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TimeInForce::Gtc => write!(f, "gtc"),
+            TimeInForce::Ioc => write!(f, "ioc"),
+            TimeInForce::Fok => write!(f, "fok"),
+            TimeInForce::Gtd => write!(f, "gtd"),
+        }
+    }
+}
+
+/// What happens when an incoming order meets a resting order of the same owner
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum StpPolicy {
+    /// Cancel the incoming order's remainder
+    CancelNewest,
+    /// Cancel the resting order and keep matching behind it
+    #[default]
+    CancelOldest,
+    /// Reduce both orders by the smaller open quantity without trading;
+    /// whichever (or both) is left with nothing is cancelled
+    DecrementBoth,
+}
+
+impl std::fmt::Display for StpPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StpPolicy::CancelNewest => write!(f, "cancel_newest"),
+            StpPolicy::CancelOldest => write!(f, "cancel_oldest"),
+            StpPolicy::DecrementBoth => write!(f, "decrement_both"),
+        }
+    }
+}
+
 /// Order status in the book
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum OrderStatus {
     Open,
@@ -32,73 +114,266 @@ pub enum OrderStatus {
     Cancelled,
 }
 
+/// Why [`Order::fill`] left an order as it was
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum FillError {
+    #[error("order {order_id} cannot fill a non-positive quantity {quantity}")]
+    NonPositive { order_id: OrderId, quantity: Decimal },
+    #[error("order {order_id} cannot fill {quantity} with only {remaining} open")]
+    Overfill {
+        order_id: OrderId,
+        quantity: Decimal,
+        remaining: Decimal,
+    },
+}
+
 /// A limit order in the order book
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Order {
-    /// Unique order identifier
-    pub id: Uuid,
+    /// Order identifier, unique within its engine
+    pub id: OrderId,
+    /// Participant that owns the order; orders with the same owner never trade
+    /// with each other
+    #[serde(default)]
+    pub owner_id: Option<String>,
+    /// Submitter's own id for the order, unique among the owner's open orders
+    #[serde(default)]
+    pub client_order_id: Option<String>,
     /// Buy or Sell
     pub side: Side,
-    /// Limit price
+    /// Limit price (ignored for market orders)
     pub price: Decimal,
+    /// Limit or market
+    #[serde(default)]
+    pub order_type: OrderType,
+    /// Trigger price for stop orders; the order is held off the book until it fires
+    #[serde(default)]
+    pub stop_price: Option<Decimal>,
+    /// How long the order stays working
+    #[serde(default)]
+    pub time_in_force: TimeInForce,
+    /// Reject rather than take liquidity when this order reaches the book
+    #[serde(default)]
+    pub post_only: bool,
+    /// Rest without ever showing in depth
+    #[serde(default)]
+    pub hidden: bool,
+    /// Self-trade prevention policy when this order takes liquidity; the
+    /// engine default applies when unset
+    #[serde(default)]
+    pub stp_policy: Option<StpPolicy>,
+    /// Good-till-date expiry; the order is removed from the book once reached
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Furthest a market order may trade from the reference price
+    #[serde(default)]
+    pub max_slippage: Option<Decimal>,
+    /// Worst price a market order may trade at, fixed by the engine from
+    /// `max_slippage` when the order reaches the book
+    #[serde(default)]
+    pub protection_price: Option<Decimal>,
     /// Original quantity
     pub quantity: Decimal,
     /// Remaining unfilled quantity
     pub remaining_quantity: Decimal,
-    /// Order creation timestamp (used for time priority)
+    /// Time the order reached the book, for display and expiry
     pub timestamp: DateTime<Utc>,
+    /// Arrival sequence assigned by the engine, the authoritative time
+    /// priority. It starts out as the order's id and moves on each time the
+    /// order joins the book afresh, as a released stop does. Zero until
+    /// assigned.
+    #[serde(default)]
+    pub sequence: u64,
     /// Current order status
     pub status: OrderStatus,
 }
 
 impl Order {
-    /// Create a new order with the given parameters
+    /// Create a new order with the given parameters, outside any engine. It
+    /// gets an id of its own, unique within the process, and is stamped by
+    /// the system clock; an engine's orders are made by `from_request` at
+    /// its own clock's time.
     pub fn new(side: Side, price: Decimal, quantity: Decimal) -> Self {
+        let id = NEXT_UNMANAGED_ID.fetch_add(1, Ordering::Relaxed);
+        Self::with_id(id, side, price, quantity, SystemClock.now())
+    }
+
+    fn with_id(id: OrderId, side: Side, price: Decimal, quantity: Decimal, timestamp: DateTime<Utc>) -> Self {
         Self {
-            id: Uuid::new_v4(),
+            id,
+            owner_id: None,
+            client_order_id: None,
             side,
             price,
+            order_type: OrderType::Limit,
+            stop_price: None,
+            time_in_force: TimeInForce::Gtc,
+            post_only: false,
+            hidden: false,
+            stp_policy: None,
+            expires_at: None,
+            max_slippage: None,
+            protection_price: None,
             quantity,
             remaining_quantity: quantity,
-            timestamp: Utc::now(),
+            timestamp,
+            sequence: 0,
             status: OrderStatus::Open,
         }
     }
 
+    /// Create the order `id` from a submission request, accepted at
+    /// `timestamp`. Prices and quantity are normalized so 100.10 and 100.1
+    /// share a level.
+    pub fn from_request(id: OrderId, request: &OrderRequest, timestamp: DateTime<Utc>) -> Self {
+        let (price, quantity) = (request.price.normalize(), request.quantity.normalize());
+        let mut order = Self::with_id(id, request.side, price, quantity, timestamp);
+        order.owner_id = request.owner_id.clone();
+        order.client_order_id = request.client_order_id.clone();
+        order.order_type = request.order_type;
+        order.stop_price = request.stop_price.map(|p| p.normalize());
+        order.time_in_force = request.time_in_force;
+        order.post_only = request.post_only;
+        order.hidden = request.hidden;
+        order.stp_policy = request.stp_policy;
+        order.expires_at = request.expires_at;
+        order.max_slippage = request.max_slippage;
+        order
+    }
+
     /// Check if this order can match with another order
     pub fn can_match(&self, other: &Order) -> bool {
         match (self.side, other.side) {
-            (Side::Buy, Side::Sell) => self.price >= other.price,
-            (Side::Sell, Side::Buy) => self.price <= other.price,
+            (Side::Buy, Side::Sell) => self.crosses(other.price),
+            (Side::Sell, Side::Buy) => self.crosses(other.price),
             _ => false, // Same side orders can't match
         }
     }
 
-    /// Fill this order by the given quantity
-    pub fn fill(&mut self, qty: Decimal) {
+    /// Whether an unfilled remainder of this order may rest on the book
+    pub fn can_rest(&self) -> bool {
+        self.order_type == OrderType::Limit && self.time_in_force.rests()
+    }
+
+    /// Open quantity shown in depth: none for a hidden order
+    pub fn displayed_quantity(&self) -> Decimal {
+        if self.hidden {
+            Decimal::ZERO
+        } else {
+            self.remaining_quantity
+        }
+    }
+
+    /// Whether both orders belong to the same known participant
+    pub fn same_owner(&self, other: &Order) -> bool {
+        self.owner_id.is_some() && self.owner_id == other.owner_id
+    }
+
+    /// Check if this order is willing to trade at the given opposing price
+    pub fn crosses(&self, price: Decimal) -> bool {
+        let limit = match self.order_type {
+            OrderType::Limit => self.price,
+            OrderType::Market => match self.protection_price {
+                Some(protection_price) => protection_price,
+                None => return true,
+            },
+        };
+        match self.side {
+            Side::Buy => limit >= price,
+            Side::Sell => limit <= price,
+        }
+    }
+
+    /// Fill this order by the given quantity. A fill of nothing, or of more
+    /// than is open (a double fill), is refused and leaves the order as it was.
+    pub fn fill(&mut self, qty: Decimal) -> Result<(), FillError> {
+        self.check_fill(qty)?;
         self.remaining_quantity -= qty;
         if self.remaining_quantity.is_zero() {
             self.status = OrderStatus::Filled;
         } else {
             self.status = OrderStatus::PartiallyFilled;
         }
+        Ok(())
+    }
+
+    /// Fill this order and its counterparty by `qty` together: neither
+    /// changes unless both can take the fill
+    pub fn fill_with(&mut self, counterparty: &mut Order, qty: Decimal) -> Result<(), FillError> {
+        counterparty.check_fill(qty)?;
+        self.fill(qty)?;
+        counterparty.fill(qty)
+    }
+
+    fn check_fill(&self, qty: Decimal) -> Result<(), FillError> {
+        if qty <= Decimal::ZERO {
+            return Err(FillError::NonPositive {
+                order_id: self.id,
+                quantity: qty,
+            });
+        }
+        if qty > self.remaining_quantity {
+            return Err(FillError::Overfill {
+                order_id: self.id,
+                quantity: qty,
+                remaining: self.remaining_quantity,
+            });
+        }
+        Ok(())
+    }
+
+    /// Whether the order is left with an open quantity smaller than one lot,
+    /// which could never match and is cancelled instead of resting
+    pub fn has_residual(&self, lot_size: Option<Decimal>) -> bool {
+        lot_size.is_some_and(|lot| self.remaining_quantity > Decimal::ZERO && self.remaining_quantity < lot)
+    }
+
+    /// Take `qty` off the order without a fill, as self-trade prevention does.
+    /// The original quantity shrinks too, so running fill totals stay right.
+    pub fn decrement(&mut self, qty: Decimal) {
+        self.quantity -= qty;
+        self.remaining_quantity -= qty;
     }
 
     /// Check if this order is fully filled
     pub fn is_filled(&self) -> bool {
         self.remaining_quantity.is_zero()
     }
+
+    /// Check if this order's expiry has been reached
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
 }
 
 /// A trade execution between two orders
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Trade {
-    /// Unique trade identifier
-    pub id: Uuid,
+    /// Trade identifier, unique within its engine; zero until the engine
+    /// publishes the trade
+    pub id: TradeId,
     /// The aggressive (taker) order ID
-    pub taker_order_id: Uuid,
+    pub taker_order_id: OrderId,
     /// The passive (maker) order ID
-    pub maker_order_id: Uuid,
+    pub maker_order_id: OrderId,
+    /// Participant that owns the taker order
+    #[serde(default)]
+    pub taker_owner_id: Option<String>,
+    /// Participant that owns the maker order
+    #[serde(default)]
+    pub maker_owner_id: Option<String>,
+    /// Client order id the taker was submitted with
+    #[serde(default)]
+    pub taker_client_order_id: Option<String>,
+    /// Client order id the maker was submitted with
+    #[serde(default)]
+    pub maker_client_order_id: Option<String>,
+    /// Engine arrival sequence of the taker order
+    #[serde(default)]
+    pub taker_sequence: u64,
+    /// Engine arrival sequence of the maker order
+    #[serde(default)]
+    pub maker_sequence: u64,
     /// Execution price (maker's price)
     pub price: Decimal,
     /// Executed quantity
@@ -110,22 +385,79 @@ pub struct Trade {
 }
 
 impl Trade {
-    /// Create a new trade
+    /// Create a new trade executed at `timestamp`
     pub fn new(
-        taker_order_id: Uuid,
-        maker_order_id: Uuid,
+        taker_order_id: OrderId,
+        maker_order_id: OrderId,
         price: Decimal,
         quantity: Decimal,
         taker_side: Side,
+        timestamp: DateTime<Utc>,
     ) -> Self {
         Self {
-            id: Uuid::new_v4(),
+            id: 0,
             taker_order_id,
             maker_order_id,
+            taker_owner_id: None,
+            maker_owner_id: None,
+            taker_client_order_id: None,
+            maker_client_order_id: None,
+            taker_sequence: 0,
+            maker_sequence: 0,
             price,
             quantity,
             taker_side,
-            timestamp: Utc::now(),
+            timestamp,
+        }
+    }
+
+    /// Create a trade between two orders, carrying their owners and client ids
+    pub fn between(taker: &Order, maker: &Order, price: Decimal, quantity: Decimal, timestamp: DateTime<Utc>) -> Self {
+        Self {
+            taker_owner_id: taker.owner_id.clone(),
+            maker_owner_id: maker.owner_id.clone(),
+            taker_client_order_id: taker.client_order_id.clone(),
+            maker_client_order_id: maker.client_order_id.clone(),
+            taker_sequence: taker.sequence,
+            maker_sequence: maker.sequence,
+            ..Self::new(taker.id, maker.id, price, quantity, taker.side, timestamp)
+        }
+    }
+}
+
+/// One order's side of a trade, with its running totals
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Fill {
+    pub order_id: OrderId,
+    #[serde(default)]
+    pub owner_id: Option<String>,
+    #[serde(default)]
+    pub client_order_id: Option<String>,
+    /// Engine arrival sequence of the order
+    #[serde(default)]
+    pub sequence: u64,
+    /// Quantity executed in this fill
+    pub fill_qty: Decimal,
+    /// Quantity executed over the order's life, including this fill
+    pub cumulative_filled: Decimal,
+    /// Quantity still open after this fill
+    pub remaining: Decimal,
+    /// Status after this fill
+    pub status: OrderStatus,
+}
+
+impl Fill {
+    /// Record `order` having just been filled for `fill_qty`
+    pub fn of(order: &Order, fill_qty: Decimal) -> Self {
+        Self {
+            order_id: order.id,
+            owner_id: order.owner_id.clone(),
+            client_order_id: order.client_order_id.clone(),
+            sequence: order.sequence,
+            fill_qty,
+            cumulative_filled: order.quantity - order.remaining_quantity,
+            remaining: order.remaining_quantity,
+            status: order.status,
         }
     }
 }
@@ -133,9 +465,189 @@ impl Trade {
 /// Request to submit a new order
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OrderRequest {
+    /// Id reserved for the order before it was queued; the engine takes
+    /// the next one itself when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<OrderId>,
+    /// Submitting participant
+    #[serde(default)]
+    pub owner_id: Option<String>,
+    /// Submitter's own id, echoed on acknowledgements and fills
+    #[serde(default)]
+    pub client_order_id: Option<String>,
     pub side: Side,
     pub price: Decimal,
     pub quantity: Decimal,
+    #[serde(default)]
+    pub order_type: OrderType,
+    /// Hold the order until a trade prints at or through this price
+    #[serde(default)]
+    pub stop_price: Option<Decimal>,
+    /// Defaults to good-till-cancelled
+    #[serde(default)]
+    pub time_in_force: TimeInForce,
+    /// Reject instead of taking liquidity if the order would cross the spread
+    #[serde(default)]
+    pub post_only: bool,
+    /// Rest without ever showing in depth
+    #[serde(default)]
+    pub hidden: bool,
+    /// Self-trade prevention policy; the engine default when unset
+    #[serde(default)]
+    pub stp_policy: Option<StpPolicy>,
+    /// Good-till-date expiry
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Market orders only: stop sweeping levels more than this far from the
+    /// reference price and cancel the remainder
+    #[serde(default)]
+    pub max_slippage: Option<Decimal>,
+    /// Exempt the order from the pre-trade size limits; set by internal
+    /// flow such as the simulator, never by API clients
+    #[serde(skip)]
+    pub skip_risk_checks: bool,
+}
+
+impl OrderRequest {
+    /// Create a plain limit order request
+    pub fn new(side: Side, price: Decimal, quantity: Decimal) -> Self {
+        Self {
+            id: None,
+            owner_id: None,
+            client_order_id: None,
+            side,
+            price,
+            quantity,
+            order_type: OrderType::Limit,
+            stop_price: None,
+            time_in_force: TimeInForce::Gtc,
+            post_only: false,
+            hidden: false,
+            stp_policy: None,
+            expires_at: None,
+            max_slippage: None,
+            skip_risk_checks: false,
+        }
+    }
+
+    /// Create a market order request
+    pub fn market(side: Side, quantity: Decimal) -> Self {
+        Self {
+            order_type: OrderType::Market,
+            ..Self::new(side, Decimal::ZERO, quantity)
+        }
+    }
+
+    /// Strip trailing zeros from the price, quantity and stop price
+    pub fn normalized(mut self) -> Self {
+        self.price = self.price.normalize();
+        self.quantity = self.quantity.normalize();
+        self.stop_price = self.stop_price.map(|p| p.normalize());
+        self
+    }
+
+    /// Attach a stop trigger price to the request.
+    /// Market requests become stop orders, limit requests become stop-limit orders.
+    pub fn with_stop_price(mut self, stop_price: Decimal) -> Self {
+        self.stop_price = Some(stop_price);
+        self
+    }
+
+    /// Submit on behalf of a participant
+    pub fn with_owner(mut self, owner_id: impl Into<String>) -> Self {
+        self.owner_id = Some(owner_id.into());
+        self
+    }
+
+    /// Tag the order with the submitter's own id
+    pub fn with_client_order_id(mut self, client_order_id: impl Into<String>) -> Self {
+        self.client_order_id = Some(client_order_id.into());
+        self
+    }
+
+    /// Set the time in force
+    pub fn with_time_in_force(mut self, time_in_force: TimeInForce) -> Self {
+        self.time_in_force = time_in_force;
+        self
+    }
+
+    /// Expire the order at the given time if it is still resting
+    pub fn with_expires_at(mut self, expires_at: DateTime<Utc>) -> Self {
+        self.expires_at = Some(expires_at);
+        self
+    }
+
+    /// Limit how far a market order may sweep from the reference price
+    pub fn with_max_slippage(mut self, max_slippage: Decimal) -> Self {
+        self.max_slippage = Some(max_slippage);
+        self
+    }
+
+    /// Keep the order out of depth
+    pub fn with_hidden(mut self, hidden: bool) -> Self {
+        self.hidden = hidden;
+        self
+    }
+
+    /// Choose what happens if the order meets one of its owner's resting orders
+    pub fn with_stp_policy(mut self, stp_policy: StpPolicy) -> Self {
+        self.stp_policy = Some(stp_policy);
+        self
+    }
+
+    /// Mark the request as post-only (maker-only)
+    pub fn with_post_only(mut self, post_only: bool) -> Self {
+        self.post_only = post_only;
+        self
+    }
+}
+
+/// A market maker's two-sided quote, replacing the owner's previous quote
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuoteRequest {
+    /// Quoting participant
+    pub owner_id: String,
+    pub bid_price: Decimal,
+    pub bid_qty: Decimal,
+    pub ask_price: Decimal,
+    pub ask_qty: Decimal,
+    /// Ids reserved for the bid and ask before the quote was queued; the
+    /// engine takes the next two itself when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub order_ids: Option<[OrderId; 2]>,
+}
+
+impl QuoteRequest {
+    /// Create a quote request
+    pub fn new(
+        owner_id: impl Into<String>,
+        bid_price: Decimal,
+        bid_qty: Decimal,
+        ask_price: Decimal,
+        ask_qty: Decimal,
+    ) -> Self {
+        Self {
+            owner_id: owner_id.into(),
+            bid_price,
+            bid_qty,
+            ask_price,
+            ask_qty,
+            order_ids: None,
+        }
+    }
+
+    /// The bid and ask as plain good-till-cancelled limit order requests
+    pub fn orders(&self) -> [OrderRequest; 2] {
+        let [bid_id, ask_id] = self.order_ids.map_or([None; 2], |ids| ids.map(Some));
+        let side = |id, side, price, quantity| OrderRequest {
+            id,
+            ..OrderRequest::new(side, price, quantity).with_owner(self.owner_id.clone())
+        };
+        [
+            side(bid_id, Side::Buy, self.bid_price, self.bid_qty),
+            side(ask_id, Side::Sell, self.ask_price, self.ask_qty),
+        ]
+    }
 }
 
 #[cfg(test)]
@@ -170,17 +682,156 @@ mod tests {
         assert!(!buy1.can_match(&buy2));
     }
 
+    #[test]
+    fn test_order_request_post_only_defaults_to_false() {
+        let req: OrderRequest =
+            serde_json::from_str(r#"{"side":"buy","price":"100","quantity":"1"}"#).unwrap();
+        assert!(!req.post_only);
+
+        let req: OrderRequest = serde_json::from_str(
+            r#"{"side":"sell","price":"100","quantity":"1","post_only":true}"#,
+        )
+        .unwrap();
+        assert!(req.post_only);
+    }
+
+    #[test]
+    fn test_market_order_crosses_any_price() {
+        let mut buy = Order::new(Side::Buy, dec!(0), dec!(1));
+        buy.order_type = OrderType::Market;
+        let sell = Order::new(Side::Sell, dec!(1000000), dec!(1));
+
+        assert!(buy.can_match(&sell));
+        assert!(buy.crosses(dec!(1000000)));
+    }
+
+    #[test]
+    fn test_order_from_request_keeps_stop_fields() {
+        let req = OrderRequest::market(Side::Sell, dec!(2)).with_stop_price(dec!(95));
+        let accepted_at = Utc::now();
+        let order = Order::from_request(7, &req, accepted_at);
+        assert_eq!(order.id, 7);
+        assert_eq!(order.timestamp, accepted_at);
+        assert_eq!(order.order_type, OrderType::Market);
+        assert_eq!(order.stop_price, Some(dec!(95)));
+        assert_eq!(order.remaining_quantity, dec!(2));
+    }
+
+    #[test]
+    fn test_order_expiry_is_inclusive() {
+        let now = Utc::now();
+        let req = OrderRequest::new(Side::Buy, dec!(100), dec!(1)).with_expires_at(now);
+        let order = Order::from_request(1, &req, now - chrono::Duration::seconds(1));
+
+        assert!(!order.is_expired(now - chrono::Duration::milliseconds(1)));
+        assert!(order.is_expired(now));
+        assert!(!Order::new(Side::Buy, dec!(100), dec!(1)).is_expired(now));
+    }
+
+    #[test]
+    fn test_time_in_force_defaults_to_gtc() {
+        let req: OrderRequest =
+            serde_json::from_str(r#"{"side":"buy","price":"100","quantity":"1"}"#).unwrap();
+        assert_eq!(req.time_in_force, TimeInForce::Gtc);
+
+        let order: Order = serde_json::from_value(serde_json::json!({
+            "id": 1,
+            "side": "sell",
+            "price": "100",
+            "quantity": "1",
+            "remaining_quantity": "1",
+            "timestamp": Utc::now(),
+            "status": "open",
+        }))
+        .unwrap();
+        assert_eq!(order.time_in_force, TimeInForce::Gtc);
+    }
+
+    #[test]
+    fn test_time_in_force_serde_round_trip() {
+        for (tif, text) in [
+            (TimeInForce::Gtc, "\"gtc\""),
+            (TimeInForce::Ioc, "\"ioc\""),
+            (TimeInForce::Fok, "\"fok\""),
+            (TimeInForce::Gtd, "\"gtd\""),
+        ] {
+            assert_eq!(serde_json::to_string(&tif).unwrap(), text);
+            assert_eq!(serde_json::from_str::<TimeInForce>(text).unwrap(), tif);
+            assert_eq!(format!("\"{}\"", tif), text);
+        }
+
+        let req = OrderRequest::new(Side::Buy, dec!(100), dec!(1)).with_time_in_force(TimeInForce::Fok);
+        let json = serde_json::to_string(&req).unwrap();
+        let back: OrderRequest = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.time_in_force, TimeInForce::Fok);
+
+        let order = Order::from_request(1, &req, Utc::now());
+        let back: Order = serde_json::from_str(&serde_json::to_string(&order).unwrap()).unwrap();
+        assert_eq!(back.time_in_force, TimeInForce::Fok);
+    }
+
+    #[test]
+    fn test_time_in_force_rejects_unknown_value() {
+        assert!(serde_json::from_str::<TimeInForce>("\"day\"").is_err());
+        assert!(serde_json::from_str::<OrderRequest>(
+            r#"{"side":"buy","price":"100","quantity":"1","time_in_force":"forever"}"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_only_gtc_and_gtd_limit_orders_rest() {
+        let limit = |tif| Order::from_request(
+            1,
+            &OrderRequest::new(Side::Buy, dec!(100), dec!(1)).with_time_in_force(tif),
+            Utc::now(),
+        );
+        assert!(limit(TimeInForce::Gtc).can_rest());
+        assert!(limit(TimeInForce::Gtd).can_rest());
+        assert!(!limit(TimeInForce::Ioc).can_rest());
+        assert!(!limit(TimeInForce::Fok).can_rest());
+        assert!(!Order::from_request(1, &OrderRequest::market(Side::Buy, dec!(1)), Utc::now()).can_rest());
+    }
+
     #[test]
     fn test_order_fill() {
         let mut order = Order::new(Side::Buy, dec!(100), dec!(10));
         
-        order.fill(dec!(5));
+        order.fill(dec!(5)).unwrap();
         assert_eq!(order.remaining_quantity, dec!(5));
         assert_eq!(order.status, OrderStatus::PartiallyFilled);
         
-        order.fill(dec!(5));
+        order.fill(dec!(5)).unwrap();
         assert_eq!(order.remaining_quantity, dec!(0));
         assert_eq!(order.status, OrderStatus::Filled);
         assert!(order.is_filled());
     }
+
+    #[test]
+    fn test_double_fill_is_refused() {
+        let mut order = Order::new(Side::Sell, dec!(100), dec!(10));
+        order.fill(dec!(10)).unwrap();
+
+        // The same fill applied twice would drive the order negative
+        assert_eq!(
+            order.fill(dec!(10)),
+            Err(FillError::Overfill {
+                order_id: order.id,
+                quantity: dec!(10),
+                remaining: dec!(0),
+            })
+        );
+        assert!(matches!(order.fill(dec!(-1)), Err(FillError::NonPositive { .. })));
+        assert_eq!((order.remaining_quantity, order.status), (dec!(0), OrderStatus::Filled));
+
+        // Paired fills leave both orders untouched when either side refuses
+        let mut taker = Order::new(Side::Buy, dec!(100), dec!(4));
+        assert!(taker.fill_with(&mut order, dec!(4)).is_err());
+        assert_eq!((taker.remaining_quantity, taker.status), (dec!(4), OrderStatus::Open));
+        let mut maker = Order::new(Side::Sell, dec!(100), dec!(10));
+        assert!(taker.fill_with(&mut maker, dec!(5)).is_err());
+        assert_eq!(maker.remaining_quantity, dec!(10));
+        taker.fill_with(&mut maker, dec!(4)).unwrap();
+        assert_eq!((taker.remaining_quantity, maker.remaining_quantity), (dec!(0), dec!(6)));
+    }
 }
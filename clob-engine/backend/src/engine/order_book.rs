@@ -1,257 +1,3134 @@
-//! Order book implementation using BTreeMap for price levels.
+//! Order book implementation: one `Ladder` of price levels per side.
+//!
+//! Levels are keyed by integer tick counts on the book's `PriceGrid`;
+//! `Decimal` prices appear only in what the book reports. Resting orders
+//! live in one slab owned by the book, each linked to its neighbours in its
+//! level's queue, so any order can be unlinked in constant time. A slot
+//! freed by a fill or cancel is reused by the next order to rest.
 
-use crate::engine::order::{Order, Side, Trade};
+use crate::engine::ladder::Ladder;
+use crate::engine::order::{Fill, FillError, Order, OrderId, OrderType, Side, StpPolicy, Trade};
+use crate::engine::ticks::{PriceGrid, Ticks};
+use chrono::{DateTime, Utc};
 use rust_decimal::Decimal;
-use std::collections::{BTreeMap, VecDeque};
+use slab::Slab;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Write;
+
+/// A resting order and its neighbours in its level's queue
+#[derive(Debug)]
+pub struct QueuedOrder {
+    pub order: Order,
+    /// Slab key of the order ahead of this one
+    prev: Option<usize>,
+    /// Slab key of the order behind this one
+    next: Option<usize>,
+}
 
 /// A price level in the order book containing orders at that price
 #[derive(Debug, Default)]
 pub struct PriceLevel {
-    /// Orders at this price level, ordered by time (FIFO)
-    pub orders: VecDeque<Order>,
+    /// Price of every order here
+    pub price: Decimal,
+    /// Slab key of the oldest order here, the first to fill
+    head: Option<usize>,
+    /// Slab key of the newest order here
+    tail: Option<usize>,
+    /// Number of orders queued here
+    len: usize,
+    /// Number of those that are not hidden, the only ones counted in depth
+    displayed_len: usize,
     /// Total quantity at this price level
     pub total_quantity: Decimal,
+    /// Quantity of the non-hidden orders, the only part shown in depth
+    pub displayed_quantity: Decimal,
 }
 
 impl PriceLevel {
-    pub fn new() -> Self {
-        Self::default()
+    pub fn new(price: Decimal) -> Self {
+        Self {
+            price,
+            ..Self::default()
+        }
+    }
+
+    /// Store an order in `orders` behind the others at this price.
+    /// Returns its slab key.
+    pub fn add_order(&mut self, orders: &mut Slab<QueuedOrder>, order: Order) -> usize {
+        self.total_quantity += order.remaining_quantity;
+        self.displayed_quantity += order.displayed_quantity();
+        let key = orders.insert(QueuedOrder {
+            order,
+            prev: self.tail,
+            next: None,
+        });
+        match self.tail {
+            Some(tail) => orders[tail].next = Some(key),
+            None => self.head = Some(key),
+        }
+        self.tail = Some(key);
+        self.len += 1;
+        if !orders[key].order.hidden {
+            self.displayed_len += 1;
+        }
+        key
+    }
+
+    /// Slab key of the front order (oldest by time priority)
+    pub fn front(&self) -> Option<usize> {
+        self.head
+    }
+
+    /// Slab key of the back order (newest, the last to fill)
+    pub fn back(&self) -> Option<usize> {
+        self.tail
+    }
+
+    /// Remove the front order and free its slot
+    pub fn pop_front(&mut self, orders: &mut Slab<QueuedOrder>) -> Option<Order> {
+        let key = self.head?;
+        Some(self.unlink(orders, key))
+    }
+
+    /// Remove the order at `key`, wherever it sits in the queue, and free its
+    /// slot. `key` must belong to this level; `None` if its slot is vacant.
+    pub fn remove(&mut self, orders: &mut Slab<QueuedOrder>, key: usize) -> Option<Order> {
+        orders.contains(key).then(|| self.unlink(orders, key))
+    }
+
+    /// Shrink an order's open quantity without moving it in the queue.
+    /// Returns false if `new_remaining` is not a reduction.
+    pub fn reduce(&mut self, orders: &mut Slab<QueuedOrder>, key: usize, new_remaining: Decimal) -> bool {
+        let order = &mut orders[key].order;
+        if new_remaining <= Decimal::ZERO || new_remaining > order.remaining_quantity {
+            return false;
+        }
+        let delta = order.remaining_quantity - new_remaining;
+        order.quantity -= delta;
+        order.remaining_quantity = new_remaining;
+        let hidden = order.hidden;
+        self.deduct(delta, hidden);
+        true
+    }
+
+    /// Take `qty` that left an order here off the level's totals, off the
+    /// displayed total too unless the order is hidden
+    pub fn deduct(&mut self, qty: Decimal, hidden: bool) {
+        self.total_quantity = deducted(self.price, "total", self.total_quantity, qty);
+        if !hidden {
+            self.displayed_quantity = deducted(self.price, "displayed", self.displayed_quantity, qty);
+        }
+    }
+
+    /// Remove every order matching `predicate`, keeping the rest in queue order
+    pub fn remove_where(
+        &mut self,
+        orders: &mut Slab<QueuedOrder>,
+        mut predicate: impl FnMut(&Order) -> bool,
+    ) -> Vec<Order> {
+        let mut removed = Vec::new();
+        let mut cursor = self.head;
+        while let Some(key) = cursor {
+            cursor = orders[key].next;
+            if predicate(&orders[key].order) {
+                removed.push(self.unlink(orders, key));
+            }
+        }
+        removed
+    }
+
+    /// Slab keys of the orders at this level in time priority
+    pub fn keys<'a>(&self, orders: &'a Slab<QueuedOrder>) -> impl Iterator<Item = usize> + 'a {
+        std::iter::successors(self.head, |&key| orders[key].next)
+    }
+
+    /// The orders at this level in time priority
+    pub fn iter<'a>(&self, orders: &'a Slab<QueuedOrder>) -> impl Iterator<Item = &'a Order> + 'a {
+        self.keys(orders).map(|key| &orders[key].order)
+    }
+
+    /// Check if this price level is empty
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Get the number of orders at this level
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether every order here is hidden, so the level is left out of depth
+    pub fn is_hidden(&self) -> bool {
+        self.displayed_quantity.is_zero()
+    }
+
+    /// This level's summary, without its orders
+    pub fn view(&self) -> LevelView {
+        LevelView {
+            price: self.price,
+            total_quantity: self.total_quantity,
+            displayed_quantity: self.displayed_quantity,
+            order_count: self.len,
+            displayed_count: self.displayed_len,
+        }
+    }
+
+    /// Whether the links from `head` reach exactly `len` orders, each
+    /// pointing back at the one before, ending at `tail`
+    fn links_intact(&self, orders: &Slab<QueuedOrder>) -> bool {
+        let (mut prev, mut count) = (None, 0);
+        let mut cursor = self.head;
+        while let Some(key) = cursor {
+            let Some(node) = orders.get(key) else {
+                return false;
+            };
+            if node.prev != prev || count == self.len {
+                return false;
+            }
+            (prev, cursor, count) = (Some(key), node.next, count + 1);
+        }
+        prev == self.tail && count == self.len
+    }
+
+    /// Take the order at `key` out of the queue, free its slot and take its
+    /// quantity off the totals
+    fn unlink(&mut self, orders: &mut Slab<QueuedOrder>, key: usize) -> Order {
+        let QueuedOrder { order, prev, next } = orders.remove(key);
+        match prev {
+            Some(prev) => orders[prev].next = next,
+            None => self.head = next,
+        }
+        match next {
+            Some(next) => orders[next].prev = prev,
+            None => self.tail = prev,
+        }
+        self.len -= 1;
+        if !order.hidden {
+            self.displayed_len -= 1;
+        }
+        self.deduct(order.remaining_quantity, order.hidden);
+        order
+    }
+}
+
+/// `total - qty` for a level total, clamped at zero. Only a bookkeeping bug
+/// elsewhere can take a total below zero; it is logged loudly rather than
+/// left to poison every depth broadcast after it.
+fn deducted(price: Decimal, total_name: &str, total: Decimal, qty: Decimal) -> Decimal {
+    match total.checked_sub(qty) {
+        Some(left) if left >= Decimal::ZERO => left,
+        _ => {
+            tracing::error!(
+                price = %price,
+                total = %total,
+                qty = %qty,
+                "Level {total_name} quantity would go negative; clamped to zero"
+            );
+            Decimal::ZERO
+        }
+    }
+}
+
+/// Best displayed bid and ask, each with the quantity displayed at it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Bbo {
+    /// Best displayed bid price and quantity
+    pub bid: Option<(Decimal, Decimal)>,
+    /// Best displayed ask price and quantity
+    pub ask: Option<(Decimal, Decimal)>,
+}
+
+/// Read-only summary of one price level, as [`OrderBook::iter_bids`] and
+/// [`OrderBook::iter_asks`] yield them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LevelView {
+    pub price: Decimal,
+    /// Open quantity of every order at the level, hidden ones included
+    pub total_quantity: Decimal,
+    /// Open quantity of the level's displayed orders
+    pub displayed_quantity: Decimal,
+    /// Orders resting at the level
+    pub order_count: usize,
+    /// Orders at the level that are not hidden
+    pub displayed_count: usize,
+}
+
+impl LevelView {
+    /// Whether every order here is hidden, so the level is left out of depth
+    pub fn is_hidden(&self) -> bool {
+        self.displayed_quantity.is_zero()
+    }
+}
+
+/// Price, displayed quantity and displayed order count of the levels that
+/// show in depth
+fn displayed(levels: impl Iterator<Item = LevelView>) -> impl Iterator<Item = (Decimal, Decimal, usize)> {
+    levels
+        .filter(|level| !level.is_hidden())
+        .map(|level| (level.price, level.displayed_quantity, level.displayed_count))
+}
+
+/// Merge consecutive levels whose prices `bucket` maps to the same bucket,
+/// keeping at most `levels` buckets
+fn bucketed(
+    depth: impl Iterator<Item = (Decimal, Decimal, usize)>,
+    bucket: impl Fn(Decimal) -> Decimal,
+    levels: usize,
+) -> Vec<(Decimal, Decimal, usize)> {
+    let mut buckets: Vec<(Decimal, Decimal, usize)> = Vec::new();
+    for (price, displayed_quantity, displayed_count) in depth {
+        let bucket = bucket(price).normalize();
+        if let Some((_, quantity, count)) = buckets.last_mut().filter(|(last, _, _)| *last == bucket) {
+            *quantity += displayed_quantity;
+            *count += displayed_count;
+        } else if buckets.len() == levels {
+            break;
+        } else {
+            buckets.push((bucket, displayed_quantity, displayed_count));
+        }
+    }
+    buckets
+}
+
+/// How an incoming order's quantity is shared among the orders at a price level
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MatchingAlgorithm {
+    /// Strict price-time priority: oldest order fills first
+    #[default]
+    Fifo,
+    /// Fills split in proportion to resting size, remainders to the
+    /// largest (then oldest) orders
+    ProRata,
+}
+
+/// Everything that happened while matching one incoming order
+#[derive(Debug, Default)]
+pub struct MatchOutcome {
+    /// Trades generated, in execution order
+    pub trades: Vec<Trade>,
+    /// Taker then maker fill for each trade, in the same order as `trades`
+    pub fills: Vec<[Fill; 2]>,
+    /// Resting orders removed by self-trade prevention instead of trading
+    pub self_trade_cancelled: Vec<Order>,
+    /// Resting orders reduced in place by decrement-both, as they now stand
+    pub self_trade_decremented: Vec<Order>,
+    /// Quantity taken off the incoming order by self-trade prevention
+    pub taker_self_trade: Decimal,
+    /// Resting orders a partial fill left with less than one lot, removed
+    pub residual_cancelled: Vec<Order>,
+    /// Sub-lot remainder of the incoming order discarded instead of resting
+    pub taker_residual: Decimal,
+    /// A fill refused because it would have driven an order negative;
+    /// matching stopped there and the incoming remainder did not rest
+    pub fill_refused: Option<FillError>,
+}
+
+impl MatchOutcome {
+    /// Empty every list, keeping their capacity for the next order
+    pub fn clear(&mut self) {
+        self.trades.clear();
+        self.fills.clear();
+        self.self_trade_cancelled.clear();
+        self.self_trade_decremented.clear();
+        self.taker_self_trade = Decimal::ZERO;
+        self.residual_cancelled.clear();
+        self.taker_residual = Decimal::ZERO;
+        self.fill_refused = None;
+    }
+}
+
+/// What matching did to one incoming order, as [`OrderBook::match_order`]
+/// reports it
+#[derive(Debug, Clone)]
+pub struct MatchResult {
+    /// Id of the incoming order
+    pub taker_order_id: OrderId,
+    /// Trades generated, in execution order
+    pub trades: Vec<Trade>,
+    /// Quantity traded across all of `trades`
+    pub filled_quantity: Decimal,
+    /// Quantity-weighted mean price of `trades`; `None` if nothing traded
+    pub average_price: Option<Decimal>,
+    /// Quantity left open after matching, whether it now rests or was discarded
+    pub remaining_quantity: Decimal,
+    /// Whether the remainder joined the book
+    pub rested: bool,
+}
+
+impl MatchResult {
+    /// Summarise `trades` for an order that arrived with `arrived_with`
+    /// open, after self-trade prevention took `self_trade` off it
+    pub fn new(
+        taker_order_id: OrderId,
+        trades: Vec<Trade>,
+        arrived_with: Decimal,
+        self_trade: Decimal,
+        rested: bool,
+    ) -> Self {
+        let filled_quantity: Decimal = trades.iter().map(|t| t.quantity).sum();
+        let average_price = (!filled_quantity.is_zero()).then(|| {
+            let notional: Decimal = trades.iter().map(|t| t.price * t.quantity).sum();
+            (notional / filled_quantity).normalize()
+        });
+        Self {
+            taker_order_id,
+            trades,
+            filled_quantity,
+            average_price,
+            remaining_quantity: arrived_with - filled_quantity - self_trade,
+            rested,
+        }
+    }
+}
+
+/// What taking a given quantity from the displayed book would cost, as
+/// [`OrderBook::vwap_for_quantity`] estimates it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PriceImpact {
+    /// Quantity-weighted average price over `fillable`; `None` if nothing is
+    pub vwap: Option<Decimal>,
+    /// Price of the furthest level the quantity reaches
+    pub worst_price: Option<Decimal>,
+    /// How much of the requested quantity the displayed book covers
+    pub fillable: Decimal,
+    /// Whether `fillable` is the whole requested quantity
+    pub complete: bool,
+}
+
+/// Why [`OrderBook::reduce_order`] left an order as it was
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ReduceError {
+    #[error("order {0} is not resting on the book")]
+    UnknownOrder(OrderId),
+    #[error("cannot increase order quantity from {quantity} to {new_quantity}")]
+    Increase { quantity: Decimal, new_quantity: Decimal },
+    #[error("quantity {new_quantity} leaves nothing open once {filled} has filled")]
+    BelowFilled { filled: Decimal, new_quantity: Decimal },
+}
+
+/// A broken structural guarantee of the book, found by [`OrderBook::validate`]
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum BookInvariantError {
+    #[error("{side} level {price} has total {total} but its orders sum to {sum}")]
+    LevelTotalMismatch {
+        side: Side,
+        price: Decimal,
+        total: Decimal,
+        sum: Decimal,
+    },
+    #[error("{side} level {price} displays {displayed} but its visible orders sum to {sum}")]
+    DisplayedMismatch {
+        side: Side,
+        price: Decimal,
+        displayed: Decimal,
+        sum: Decimal,
+    },
+    #[error("{side} level {price} counts {counted} displayed orders but holds {actual}")]
+    DisplayedCountMismatch {
+        side: Side,
+        price: Decimal,
+        counted: usize,
+        actual: usize,
+    },
+    #[error("{side} level {price} has no orders")]
+    EmptyLevel { side: Side, price: Decimal },
+    #[error("{side} level {price} has a broken queue")]
+    BrokenQueue { side: Side, price: Decimal },
+    #[error("{side} ladder keeps a level in its tree that belongs in its window")]
+    LadderOutOfSync { side: Side },
+    #[error("order {order_id} ({side} {order_price}) rests on the {level_side} level {price}")]
+    MisplacedOrder {
+        order_id: OrderId,
+        side: Side,
+        order_price: Decimal,
+        level_side: Side,
+        price: Decimal,
+    },
+    #[error("order {order_id} has remaining quantity {remaining}")]
+    NonPositiveRemaining { order_id: OrderId, remaining: Decimal },
+    #[error("index tracks {indexed} orders but {resting} are resting")]
+    IndexOutOfSync { indexed: usize, resting: usize },
+    #[error("slab stores {stored} orders but {resting} are resting")]
+    SlabOutOfSync { stored: usize, resting: usize },
+    #[error("{side} side counts {counted} orders but {resting} are resting")]
+    SideCountOutOfSync { side: Side, counted: usize, resting: usize },
+    #[error("best bid {best_bid} crosses best ask {best_ask}")]
+    CrossedBook { best_bid: Decimal, best_ask: Decimal },
+    #[error("cached best {side} is {cached:?} but the book's is {actual:?}")]
+    StaleTopOfBook {
+        side: Side,
+        cached: Option<Decimal>,
+        actual: Option<Decimal>,
+    },
+}
+
+/// Lookups over the resting orders, updated whenever an order joins or leaves a level
+#[derive(Debug, Default)]
+struct OrderIndex {
+    /// Every resting order id mapped to its level and slab key
+    levels: HashMap<OrderId, (Side, Ticks, usize)>,
+    /// Resting orders with an expiry, earliest first
+    expiries: BTreeSet<(DateTime<Utc>, OrderId)>,
+    /// Resting orders carrying a client order id, keyed by owner and client id
+    client_ids: HashMap<(Option<String>, String), OrderId>,
+    /// Number of resting orders per owner; owners with none are absent
+    open_by_owner: HashMap<String, usize>,
+    /// Number of resting bids
+    open_bids: usize,
+    /// Number of resting asks
+    open_asks: usize,
+}
+
+impl OrderIndex {
+    fn open_on(&mut self, side: Side) -> &mut usize {
+        match side {
+            Side::Buy => &mut self.open_bids,
+            Side::Sell => &mut self.open_asks,
+        }
+    }
+
+    fn insert(&mut self, order: &Order, ticks: Ticks, key: usize) {
+        self.levels.insert(order.id, (order.side, ticks, key));
+        *self.open_on(order.side) += 1;
+        if let Some(expires_at) = order.expires_at {
+            self.expiries.insert((expires_at, order.id));
+        }
+        if let Some(client_order_id) = &order.client_order_id {
+            self.client_ids
+                .insert((order.owner_id.clone(), client_order_id.clone()), order.id);
+        }
+        if let Some(owner_id) = &order.owner_id {
+            *self.open_by_owner.entry(owner_id.clone()).or_default() += 1;
+        }
+    }
+
+    fn remove(&mut self, order: &Order) {
+        if self.levels.remove(&order.id).is_some() {
+            *self.open_on(order.side) -= 1;
+        }
+        if let Some(expires_at) = order.expires_at {
+            self.expiries.remove(&(expires_at, order.id));
+        }
+        if let Some(client_order_id) = &order.client_order_id {
+            let key = (order.owner_id.clone(), client_order_id.clone());
+            if self.client_ids.get(&key) == Some(&order.id) {
+                self.client_ids.remove(&key);
+            }
+        }
+        if let Some(owner_id) = &order.owner_id {
+            if let Some(count) = self.open_by_owner.get_mut(owner_id) {
+                *count -= 1;
+                if *count == 0 {
+                    self.open_by_owner.remove(owner_id);
+                }
+            }
+        }
+    }
+}
+
+/// Decimal places the microprice is rounded to
+pub const MICROPRICE_SCALE: u32 = 8;
+
+/// Decimal places the book imbalance is rounded to
+pub const IMBALANCE_SCALE: u32 = 4;
+
+/// Levels per side covered by the book checksum
+pub const CHECKSUM_LEVELS: usize = 10;
+
+const CRC32: crc::Crc<u32> = crc::Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
+
+/// CRC-32 (IEEE, as in zlib) of depth in canonical form, for clients to
+/// check their copy of the book against.
+///
+/// The canonical form interleaves the sides level by level, best first:
+/// bid 1, ask 1, bid 2, ask 2, and so on. Each level is written
+/// `price:quantity` and levels are joined with `:`; once one side runs out
+/// the other continues alone. Prices and quantities are plain decimals with
+/// trailing zeros stripped and no exponent, so `100.50` is written `100.5`
+/// and `2.000` is `2`. An empty book is the empty string, checksum 0.
+/// Order counts are not part of it.
+///
+/// Bids `[(100.5, 2), (100, 1)]` and asks `[(101, 0.25)]` give
+/// `100.5:2:101:0.25:100:1`, checksum 3109731841.
+pub fn depth_checksum(bids: &[(Decimal, Decimal, usize)], asks: &[(Decimal, Decimal, usize)]) -> u32 {
+    interleaved_checksum(bids.iter().copied(), asks.iter().copied())
+}
+
+/// Levels of depth with the notional each one holds, price × quantity,
+/// rounded to `scale` decimal places. Products already within `scale` are
+/// left as they are, so a 0.01-tick book keeps `100.50 × 2 = 201.00`.
+pub fn notional_depth(depth: &[(Decimal, Decimal, usize)], scale: u32) -> Vec<(Decimal, Decimal, Decimal)> {
+    depth
+        .iter()
+        .map(|&(price, quantity, _)| (price, quantity, (price * quantity).round_dp(scale)))
+        .collect()
+}
+
+/// [`depth_checksum`] of two sides' levels as they are walked
+fn interleaved_checksum(
+    mut bids: impl Iterator<Item = (Decimal, Decimal, usize)>,
+    mut asks: impl Iterator<Item = (Decimal, Decimal, usize)>,
+) -> u32 {
+    let mut digest = CRC32.digest();
+    let mut field = String::new();
+    loop {
+        let (bid, ask) = (bids.next(), asks.next());
+        if bid.is_none() && ask.is_none() {
+            return digest.finalize();
+        }
+        for (price, quantity, _) in [bid, ask].into_iter().flatten() {
+            for value in [price, quantity] {
+                // `field` still holds the previous value, if there was one
+                if !field.is_empty() {
+                    digest.update(b":");
+                }
+                field.clear();
+                write!(field, "{}", value.normalize()).expect("writing to a String cannot fail");
+                digest.update(field.as_bytes());
+            }
+        }
+    }
+}
+
+/// The central limit order book
+#[derive(Debug)]
+pub struct OrderBook {
+    /// Buy orders by price in ticks; the best is the last key
+    bids: Ladder<PriceLevel>,
+
+    /// Sell orders by price in ticks; the best is the first key
+    asks: Ladder<PriceLevel>,
+
+    /// Highest bid, kept in step with `bids`
+    top_bid: Option<Ticks>,
+
+    /// Lowest ask, kept in step with `asks`
+    top_ask: Option<Ticks>,
+
+    /// Every resting order, addressed by the keys the levels link
+    orders: Slab<QueuedOrder>,
+
+    /// Converts prices to and from the tick counts the levels are keyed by
+    grid: PriceGrid,
+
+    /// Lookups kept in step with the resting orders
+    index: OrderIndex,
+
+    /// Allocation strategy within a price level
+    algorithm: MatchingAlgorithm,
+
+    /// Quantity step pro-rata allocations are rounded down to; partial fills
+    /// leaving less than this open are swept off the book
+    lot_size: Option<Decimal>,
+
+    /// Self-trade prevention for incoming orders that don't choose their own
+    stp_policy: StpPolicy,
+
+    /// Decimal places notional depth is rounded to; the grid tick's when unset
+    quote_scale: Option<u32>,
+    
+    /// Symbol for this order book
+    pub symbol: String,
+}
+
+impl OrderBook {
+    /// Create a new order book for the given symbol
+    pub fn new(symbol: impl Into<String>) -> Self {
+        Self {
+            bids: Ladder::new(Side::Buy),
+            asks: Ladder::new(Side::Sell),
+            top_bid: None,
+            top_ask: None,
+            orders: Slab::new(),
+            grid: PriceGrid::default(),
+            index: OrderIndex::default(),
+            algorithm: MatchingAlgorithm::default(),
+            lot_size: None,
+            stp_policy: StpPolicy::default(),
+            quote_scale: None,
+            symbol: symbol.into(),
+        }
+    }
+
+    /// Use a different allocation strategy within price levels
+    pub fn with_algorithm(mut self, algorithm: MatchingAlgorithm) -> Self {
+        self.algorithm = algorithm;
+        self
+    }
+
+    /// Round pro-rata allocations to multiples of `lot_size` and sweep
+    /// sub-lot residuals
+    pub fn with_lot_size(mut self, lot_size: Option<Decimal>) -> Self {
+        self.lot_size = lot_size;
+        self
+    }
+
+    /// Key levels on a grid of this tick instead of the default eight decimal
+    /// places. Every price the book is given must lie on it.
+    pub fn with_price_grid(mut self, grid: PriceGrid) -> Self {
+        self.grid = grid;
+        self
+    }
+
+    /// Round notional depth to `scale` decimal places, the symbol's quote
+    /// precision, instead of the price grid's
+    pub fn with_quote_scale(mut self, scale: u32) -> Self {
+        self.quote_scale = Some(scale);
+        self
+    }
+
+    /// Decimal places notional depth is rounded to
+    pub fn quote_scale(&self) -> u32 {
+        self.quote_scale.unwrap_or_else(|| self.grid.tick().scale())
+    }
+
+    /// Self-trade prevention applied to orders without a policy of their own
+    pub fn with_stp_policy(mut self, stp_policy: StpPolicy) -> Self {
+        self.stp_policy = stp_policy;
+        self
+    }
+
+    /// Get the best bid price (highest buy price)
+    pub fn best_bid(&self) -> Option<Decimal> {
+        self.top_bid.map(|ticks| self.grid.to_price(ticks))
+    }
+
+    /// Get the best ask price (lowest sell price)
+    pub fn best_ask(&self) -> Option<Decimal> {
+        self.top_ask.map(|ticks| self.grid.to_price(ticks))
+    }
+
+    /// Best bid with displayed quantity; hidden-only levels are skipped
+    pub fn best_visible_bid(&self) -> Option<Decimal> {
+        self.visible_bid_level().map(|l| l.price)
+    }
+
+    /// Best ask with displayed quantity; hidden-only levels are skipped
+    pub fn best_visible_ask(&self) -> Option<Decimal> {
+        self.visible_ask_level().map(|l| l.price)
+    }
+
+    /// Best displayed bid and ask with their displayed quantities. Reads
+    /// the top level of each side unless it holds only hidden orders.
+    pub fn bbo(&self) -> Bbo {
+        let touch = |level: &PriceLevel| (level.price, level.displayed_quantity);
+        Bbo {
+            bid: self.visible_bid_level().map(touch),
+            ask: self.visible_ask_level().map(touch),
+        }
+    }
+
+    fn visible_bid_level(&self) -> Option<&PriceLevel> {
+        let top = &self.bids[&self.top_bid?];
+        if !top.is_hidden() {
+            return Some(top);
+        }
+        self.bids.values().rev().find(|l| !l.is_hidden())
+    }
+
+    fn visible_ask_level(&self) -> Option<&PriceLevel> {
+        let top = &self.asks[&self.top_ask?];
+        if !top.is_hidden() {
+            return Some(top);
+        }
+        self.asks.values().find(|l| !l.is_hidden())
+    }
+
+    /// Get the spread between best bid and ask
+    pub fn spread(&self) -> Option<Decimal> {
+        match (self.best_bid(), self.best_ask()) {
+            (Some(bid), Some(ask)) => Some(ask - bid),
+            _ => None,
+        }
+    }
+
+    /// Halfway between the best displayed bid and ask; `None` if either side
+    /// shows nothing
+    pub fn mid_price(&self) -> Option<Decimal> {
+        let (bid, ask) = self.best_visible_bid().zip(self.best_visible_ask())?;
+        Some(((bid + ask) / Decimal::TWO).normalize())
+    }
+
+    /// Mid price weighted towards the side with less displayed size at the
+    /// top: `(bid_qty × ask + ask_qty × bid) / (bid_qty + ask_qty)`, rounded
+    /// to `MICROPRICE_SCALE` places. `None` if either side shows nothing.
+    pub fn microprice(&self) -> Option<Decimal> {
+        let bid_level = self.visible_bid_level()?;
+        let ask_level = self.visible_ask_level()?;
+        let (bid, bid_qty) = (bid_level.price, bid_level.displayed_quantity);
+        let (ask, ask_qty) = (ask_level.price, ask_level.displayed_quantity);
+        let weighted = (bid_qty * ask + ask_qty * bid) / (bid_qty + ask_qty);
+        Some(weighted.round_dp(MICROPRICE_SCALE).normalize())
+    }
+
+    /// Checksum of the best `CHECKSUM_LEVELS` displayed levels of each side;
+    /// see `depth_checksum` for the canonical form
+    pub fn checksum(&self) -> u32 {
+        interleaved_checksum(
+            displayed(self.iter_bids()).take(CHECKSUM_LEVELS),
+            displayed(self.iter_asks()).take(CHECKSUM_LEVELS),
+        )
+    }
+
+    /// Displayed volume imbalance over the best `levels` levels of each side:
+    /// `(bid_volume − ask_volume) / (bid_volume + ask_volume)`, from -1 (asks
+    /// only) to 1 (bids only), rounded to `IMBALANCE_SCALE` places. `None`
+    /// if the book shows nothing.
+    pub fn imbalance(&self, levels: usize) -> Option<Decimal> {
+        let bids: Decimal = displayed(self.iter_bids()).take(levels).map(|(_, q, _)| q).sum();
+        let asks: Decimal = displayed(self.iter_asks()).take(levels).map(|(_, q, _)| q).sum();
+        let total = bids + asks;
+        (!total.is_zero()).then(|| ((bids - asks) / total).round_dp(IMBALANCE_SCALE).normalize())
+    }
+
+    /// Check whether an order at this price would immediately match the opposing side
+    pub fn would_cross(&self, side: Side, price: Decimal) -> bool {
+        match side {
+            Side::Buy => self.top_ask.is_some_and(|ask| self.grid.floor_ticks(price) >= ask),
+            Side::Sell => self.top_bid.is_some_and(|bid| self.grid.ceil_ticks(price) <= bid),
+        }
+    }
+
+    /// Worst opposing level, in ticks, `order` will trade at: its limit, or
+    /// its protection price rounded onto the grid, or no bound at all
+    fn limit_ticks(&self, order: &Order) -> Ticks {
+        let limit = match order.order_type {
+            OrderType::Limit => Some(order.price),
+            OrderType::Market => order.protection_price,
+        };
+        match (order.side, limit) {
+            (Side::Buy, Some(limit)) => self.grid.floor_ticks(limit),
+            (Side::Sell, Some(limit)) => self.grid.ceil_ticks(limit),
+            (Side::Buy, None) => Ticks::MAX,
+            (Side::Sell, None) => Ticks::MIN,
+        }
+    }
+
+    /// Quantity the opposing side could fill for this order right now, capped at
+    /// its remaining quantity
+    pub fn fillable_quantity(&self, order: &Order) -> Decimal {
+        let limit = self.limit_ticks(order);
+        let levels: Box<dyn Iterator<Item = &PriceLevel>> = match order.side {
+            Side::Buy => Box::new(self.asks.range(..=limit).map(|(_, l)| l)),
+            Side::Sell => Box::new(self.bids.range(limit..).rev().map(|(_, l)| l)),
+        };
+
+        let stp_policy = order.stp_policy.unwrap_or(self.stp_policy);
+        let mut fillable = Decimal::ZERO;
+        for level in levels {
+            if fillable >= order.remaining_quantity {
+                break;
+            }
+            if stp_policy == StpPolicy::CancelOldest {
+                // Own orders are cancelled by self-trade prevention, not filled
+                let own: Decimal = level
+                    .iter(&self.orders)
+                    .filter(|o| o.same_owner(order))
+                    .map(|o| o.remaining_quantity)
+                    .sum();
+                fillable += level.total_quantity - own;
+                continue;
+            }
+
+            // Under the other policies the first own order eats into the
+            // incoming order, so nothing behind it counts
+            let ahead: Decimal = match self.algorithm {
+                MatchingAlgorithm::Fifo => level
+                    .iter(&self.orders)
+                    .take_while(|o| !o.same_owner(order))
+                    .map(|o| o.remaining_quantity)
+                    .sum(),
+                MatchingAlgorithm::ProRata if level.iter(&self.orders).any(|o| o.same_owner(order)) => Decimal::ZERO,
+                MatchingAlgorithm::ProRata => level.total_quantity,
+            };
+            fillable += ahead;
+            if ahead < level.total_quantity {
+                break;
+            }
+        }
+        fillable.min(order.remaining_quantity)
+    }
+
+    /// Get the bid side depth: each level's price, displayed quantity and
+    /// number of displayed orders
+    pub fn bid_depth(&self, levels: usize) -> Vec<(Decimal, Decimal, usize)> {
+        displayed(self.iter_bids()).take(levels).collect()
+    }
+
+    /// Get the ask side depth: each level's price, displayed quantity and
+    /// number of displayed orders
+    pub fn ask_depth(&self, levels: usize) -> Vec<(Decimal, Decimal, usize)> {
+        displayed(self.iter_asks()).take(levels).collect()
+    }
+
+    /// Bid depth with each level's notional, see [`notional_depth`]
+    pub fn bid_depth_notional(&self, levels: usize) -> Vec<(Decimal, Decimal, Decimal)> {
+        notional_depth(&self.bid_depth(levels), self.quote_scale())
+    }
+
+    /// Ask depth with each level's notional, see [`notional_depth`]
+    pub fn ask_depth_notional(&self, levels: usize) -> Vec<(Decimal, Decimal, Decimal)> {
+        notional_depth(&self.ask_depth(levels), self.quote_scale())
+    }
+
+    /// Every bid level, hidden or not, in the order a sell matches them:
+    /// highest price first
+    pub fn iter_bids(&self) -> impl DoubleEndedIterator<Item = LevelView> + '_ {
+        self.bids.values().rev().map(PriceLevel::view)
+    }
+
+    /// Every ask level, hidden or not, in the order a buy matches them:
+    /// lowest price first
+    pub fn iter_asks(&self) -> impl DoubleEndedIterator<Item = LevelView> + '_ {
+        self.asks.values().map(PriceLevel::view)
+    }
+
+    /// Displayed depth on one side, best first, each level paired with the
+    /// quantity at it and every better level: the running total a depth
+    /// chart plots
+    pub fn cumulative_depth(&self, side: Side, levels: usize) -> Vec<(Decimal, Decimal)> {
+        let depth = match side {
+            Side::Buy => self.bid_depth(levels),
+            Side::Sell => self.ask_depth(levels),
+        };
+        let mut total = Decimal::ZERO;
+        depth
+            .into_iter()
+            .map(|(price, quantity, _)| {
+                total += quantity;
+                (price, total)
+            })
+            .collect()
+    }
+
+    /// Displayed depth on one side, best first, each level paired with the
+    /// notional at it and every better level
+    pub fn cumulative_notional(&self, side: Side, levels: usize) -> Vec<(Decimal, Decimal)> {
+        let depth = match side {
+            Side::Buy => self.bid_depth_notional(levels),
+            Side::Sell => self.ask_depth_notional(levels),
+        };
+        let mut total = Decimal::ZERO;
+        depth
+            .into_iter()
+            .map(|(price, _, notional)| {
+                total += notional;
+                (price, total)
+            })
+            .collect()
+    }
+
+    /// Displayed quantity on one side priced no more than `distance` from
+    /// that side's best displayed price, or zero on an empty side. This is
+    /// what an order of the opposite side could take from the visible book
+    /// before moving the price by `distance`.
+    pub fn liquidity_within(&self, side: Side, distance: Decimal) -> Decimal {
+        let (levels, within): (Box<dyn Iterator<Item = &PriceLevel>>, _) = match side {
+            Side::Buy => {
+                let Some(touch) = self.best_visible_bid() else {
+                    return Decimal::ZERO;
+                };
+                (Box::new(self.bids.values().rev()), touch - distance)
+            }
+            Side::Sell => {
+                let Some(touch) = self.best_visible_ask() else {
+                    return Decimal::ZERO;
+                };
+                (Box::new(self.asks.values()), touch + distance)
+            }
+        };
+        levels
+            .take_while(|level| match side {
+                Side::Buy => level.price >= within,
+                Side::Sell => level.price <= within,
+            })
+            .map(|level| level.displayed_quantity)
+            .sum()
     }
 
-    /// Add an order to this price level
-    pub fn add_order(&mut self, order: Order) {
-        self.total_quantity += order.remaining_quantity;
-        self.orders.push_back(order);
+    /// Expected average price of taking `quantity` with an order on `side`,
+    /// walking the opposing displayed levels from the touch.
+    ///
+    /// A book thinner than `quantity` reports what it has, with `complete`
+    /// false. Hidden orders, lot sizes and self-trade prevention are not
+    /// considered, so an actual order may do better or worse.
+    pub fn vwap_for_quantity(&self, side: Side, quantity: Decimal) -> PriceImpact {
+        let levels: Box<dyn Iterator<Item = &PriceLevel>> = match side {
+            Side::Buy => Box::new(self.asks.values()),
+            Side::Sell => Box::new(self.bids.values().rev()),
+        };
+
+        let mut fillable = Decimal::ZERO;
+        let mut notional = Decimal::ZERO;
+        let mut worst_price = None;
+        for level in levels.filter(|level| !level.is_hidden()) {
+            if fillable >= quantity {
+                break;
+            }
+            let take = level.displayed_quantity.min(quantity - fillable);
+            fillable += take;
+            notional += take * level.price;
+            worst_price = Some(level.price);
+        }
+        PriceImpact {
+            vwap: (!fillable.is_zero()).then(|| (notional / fillable).normalize()),
+            worst_price,
+            fillable,
+            complete: fillable >= quantity,
+        }
+    }
+
+    /// Displayed depth on one side grouped into price buckets `bucket_size`
+    /// wide, best first, at most `levels` buckets.
+    ///
+    /// Buckets sit on multiples of `bucket_size`. Bids round down and asks
+    /// round up, so a bucket never shows a better price than the orders in
+    /// it: with $1 buckets a bid at 100.00 or 100.75 lands in 100, an ask at
+    /// 100.25 in 101.
+    pub fn aggregated_depth(&self, side: Side, bucket_size: Decimal, levels: usize) -> Vec<(Decimal, Decimal, usize)> {
+        match side {
+            Side::Buy => bucketed(displayed(self.iter_bids()), |p| (p / bucket_size).floor() * bucket_size, levels),
+            Side::Sell => bucketed(displayed(self.iter_asks()), |p| (p / bucket_size).ceil() * bucket_size, levels),
+        }
+    }
+
+    /// Add an order to the book (no matching, just insertion)
+    ///
+    /// The order must not cross the other side; an order that might goes
+    /// through [`match_order_outcome`](Self::match_order_outcome), which
+    /// rests whatever is left. Debug builds assert this.
+    ///
+    /// # Panics
+    ///
+    /// If the order's price is not on the book's grid. The engine rejects
+    /// such prices before they get here.
+    pub fn add_order(&mut self, order: Order) {
+        debug_assert!(
+            !self.would_cross(order.side, order.price),
+            "{} at {} would cross the book; match it instead",
+            order.side,
+            order.price
+        );
+        self.add_auction_order(order);
+    }
+
+    /// Rest an order without matching, even across the spread, as a call
+    /// auction collects orders. [`uncross`](Self::uncross) resolves the crossing.
+    ///
+    /// # Panics
+    ///
+    /// If the order's price is not on the book's grid.
+    pub fn add_auction_order(&mut self, order: Order) {
+        let ticks = self
+            .grid
+            .to_ticks(order.price)
+            .unwrap_or_else(|e| panic!("cannot rest {} on a {} grid: {e}", order.price, self.grid.tick()));
+        let book = match order.side {
+            Side::Buy => &mut self.bids,
+            Side::Sell => &mut self.asks,
+        };
+
+        let (side, price) = (order.side, self.grid.to_price(ticks));
+        let level = book.get_or_insert_with(ticks, || PriceLevel::new(price));
+        let key = level.add_order(&mut self.orders, order);
+        self.index.insert(&self.orders[key].order, ticks, key);
+        self.level_added(side, ticks);
+    }
+
+    /// Move the cached top of `side` to a newly added level if it is better
+    fn level_added(&mut self, side: Side, price: Ticks) {
+        match side {
+            Side::Buy if self.top_bid.is_none_or(|top| price > top) => self.top_bid = Some(price),
+            Side::Sell if self.top_ask.is_none_or(|top| price < top) => self.top_ask = Some(price),
+            _ => {}
+        }
+    }
+
+    /// Re-read the cached top of `side` from its tree if the level just
+    /// removed was the top
+    fn level_removed(&mut self, side: Side, price: Ticks) {
+        match side {
+            Side::Buy if self.top_bid == Some(price) => self.top_bid = self.bids.last_key(),
+            Side::Sell if self.top_ask == Some(price) => self.top_ask = self.asks.first_key(),
+            _ => {}
+        }
+    }
+
+    /// Match an incoming order against the book
+    /// Returns the trades generated and what became of the order
+    pub fn match_order(&mut self, incoming: Order) -> MatchResult {
+        let (id, arrived_with) = (incoming.id, incoming.remaining_quantity);
+        let outcome = self.match_order_outcome(incoming);
+        let rested = self.index.levels.contains_key(&id);
+        MatchResult::new(id, outcome.trades, arrived_with, outcome.taker_self_trade, rested)
+    }
+
+    /// Match an incoming order against the book, reporting trades and what
+    /// self-trade prevention did.
+    ///
+    /// A resting order owned by the incoming order's owner is never traded
+    /// against. The incoming order's `StpPolicy` (else the book's) decides
+    /// whether the resting order is cancelled, the incoming remainder is
+    /// cancelled, or both are reduced by the overlap.
+    pub fn match_order_outcome(&mut self, incoming: Order) -> MatchOutcome {
+        let mut outcome = MatchOutcome::default();
+        self.match_order_into(incoming, &mut outcome);
+        outcome
+    }
+
+    /// [`match_order_outcome`](Self::match_order_outcome) into a caller's
+    /// outcome, cleared first, so a caller matching order after order can
+    /// reuse its lists instead of allocating new ones each time
+    pub fn match_order_into(&mut self, mut incoming: Order, outcome: &mut MatchOutcome) {
+        outcome.clear();
+        let stp_policy = incoming.stp_policy.unwrap_or(self.stp_policy);
+        let limit = self.limit_ticks(&incoming);
+
+        // Get the opposing book
+        let opposing_book = match incoming.side {
+            Side::Buy => &mut self.asks,
+            Side::Sell => &mut self.bids,
+        };
+        let orders = &mut self.orders;
+
+        // Whether any opposing level emptied
+        let mut swept = false;
+
+        // Keep matching while possible
+        'matching: loop {
+            if incoming.is_filled() {
+                break;
+            }
+
+            // Get the best opposing price
+            let best_price = match incoming.side {
+                Side::Buy => opposing_book.first_key(),
+                Side::Sell => opposing_book.last_key(),
+            };
+
+            let best_price = match best_price {
+                Some(p) => p,
+                None => break, // No orders on opposing side
+            };
+
+            // Check if prices cross
+            let crosses = match incoming.side {
+                Side::Buy => best_price <= limit,
+                Side::Sell => best_price >= limit,
+            };
+            if !crosses {
+                break;
+            }
+
+            // Get the price level
+            let level = opposing_book.get_mut(&best_price).unwrap();
+
+            match self.algorithm {
+                MatchingAlgorithm::Fifo => {
+                    // Match against orders at this level in time priority
+                    while !incoming.is_filled() && !level.is_empty() {
+                        let key = level.front().unwrap();
+                        let (maker_remaining, self_trade) = {
+                            let maker = &orders[key].order;
+                            (maker.remaining_quantity, maker.same_owner(&incoming))
+                        };
+
+                        if self_trade {
+                            match stp_policy {
+                                StpPolicy::CancelOldest => {
+                                    if let Some(maker) = level.pop_front(orders) {
+                                        self.index.remove(&maker);
+                                        outcome.self_trade_cancelled.push(maker);
+                                    }
+                                }
+                                StpPolicy::CancelNewest => {
+                                    outcome.taker_self_trade += incoming.remaining_quantity;
+                                    incoming.decrement(incoming.remaining_quantity);
+                                    break 'matching;
+                                }
+                                StpPolicy::DecrementBoth => {
+                                    let overlap = incoming.remaining_quantity.min(maker_remaining);
+                                    incoming.decrement(overlap);
+                                    outcome.taker_self_trade += overlap;
+                                    if overlap == maker_remaining {
+                                        if let Some(maker) = level.pop_front(orders) {
+                                            self.index.remove(&maker);
+                                            outcome.self_trade_cancelled.push(maker);
+                                        }
+                                    } else if level.reduce(orders, key, maker_remaining - overlap) {
+                                        // A sub-lot sliver could never trade, so it goes like a fill's would
+                                        if orders[key].order.has_residual(self.lot_size) {
+                                            if let Some(maker) = level.pop_front(orders) {
+                                                self.index.remove(&maker);
+                                                outcome.residual_cancelled.push(maker);
+                                            }
+                                        } else {
+                                            outcome.self_trade_decremented.push(orders[key].order.clone());
+                                        }
+                                    }
+                                }
+                            }
+                            continue;
+                        }
+
+                        // Calculate fill quantity
+                        let fill_qty = incoming.remaining_quantity.min(maker_remaining);
+
+                        // Create trade (execute at maker's price, at the taker's arrival time)
+                        let trade = Trade::between(
+                            &incoming,
+                            &orders[key].order,
+                            level.price, // Trade at the maker's price
+                            fill_qty,
+                            incoming.timestamp,
+                        );
+
+                        // Update both orders, then the level
+                        let maker = &mut orders[key].order;
+                        if let Err(error) = incoming.fill_with(maker, fill_qty) {
+                            tracing::error!(%error, "Fill refused; matching stopped");
+                            outcome.fill_refused = Some(error);
+                            break 'matching;
+                        }
+                        outcome.fills.push([Fill::of(&incoming, fill_qty), Fill::of(maker, fill_qty)]);
+                        level.deduct(fill_qty, maker.hidden);
+
+                        // Remove filled maker order, or one left with an unmatchable sliver
+                        if maker.is_filled() {
+                            if let Some(maker) = level.pop_front(orders) {
+                                self.index.remove(&maker);
+                            }
+                        } else if maker.has_residual(self.lot_size) {
+                            if let Some(maker) = level.pop_front(orders) {
+                                self.index.remove(&maker);
+                                outcome.residual_cancelled.push(maker);
+                            }
+                        }
+
+                        outcome.trades.push(trade);
+                    }
+                }
+                MatchingAlgorithm::ProRata => {
+                    // Own orders never share in the allocation
+                    let own: Vec<(usize, Decimal)> = level
+                        .keys(orders)
+                        .map(|key| (key, &orders[key].order))
+                        .filter(|(_, o)| o.same_owner(&incoming))
+                        .map(|(key, o)| (key, o.remaining_quantity))
+                        .collect();
+                    if !own.is_empty() && stp_policy == StpPolicy::CancelNewest {
+                        outcome.taker_self_trade += incoming.remaining_quantity;
+                        incoming.decrement(incoming.remaining_quantity);
+                        break 'matching;
+                    }
+                    for (key, maker_remaining) in own {
+                        let overlap = incoming.remaining_quantity.min(maker_remaining);
+                        if stp_policy == StpPolicy::DecrementBoth {
+                            if overlap.is_zero() {
+                                break;
+                            }
+                            incoming.decrement(overlap);
+                            outcome.taker_self_trade += overlap;
+                            if overlap < maker_remaining {
+                                level.reduce(orders, key, maker_remaining - overlap);
+                                if orders[key].order.has_residual(self.lot_size) {
+                                    if let Some(maker) = level.remove(orders, key) {
+                                        self.index.remove(&maker);
+                                        outcome.residual_cancelled.push(maker);
+                                    }
+                                } else {
+                                    outcome.self_trade_decremented.push(orders[key].order.clone());
+                                }
+                                continue;
+                            }
+                        }
+                        if let Some(maker) = level.remove(orders, key) {
+                            self.index.remove(&maker);
+                            outcome.self_trade_cancelled.push(maker);
+                        }
+                    }
+
+                    // From the makers themselves: a level total clamped after a
+                    // bookkeeping bug must not leave them unallocated
+                    let makers: Vec<&Order> = level.iter(orders).collect();
+                    let available: Decimal = makers.iter().map(|o| o.remaining_quantity).sum();
+                    let fill_total = incoming.remaining_quantity.min(available);
+                    let allocations = pro_rata_allocations(&makers, fill_total, self.lot_size);
+                    let keys: Vec<usize> = level.keys(orders).collect();
+                    let mut filled = Decimal::ZERO;
+                    for (key, fill_qty) in keys.into_iter().zip(allocations) {
+                        if fill_qty.is_zero() {
+                            continue;
+                        }
+                        let maker = &mut orders[key].order;
+                        let trade = Trade::between(&incoming, maker, level.price, fill_qty, incoming.timestamp);
+                        if let Err(error) = incoming.fill_with(maker, fill_qty) {
+                            tracing::error!(%error, "Fill refused; matching stopped");
+                            outcome.fill_refused = Some(error);
+                            break;
+                        }
+                        outcome.trades.push(trade);
+                        outcome.fills.push([Fill::of(&incoming, fill_qty), Fill::of(maker, fill_qty)]);
+                        level.deduct(fill_qty, maker.hidden);
+                        filled += fill_qty;
+                    }
+
+                    // Remove filled makers and sweep sub-lot residuals
+                    for maker in level.remove_where(orders, Order::is_filled) {
+                        self.index.remove(&maker);
+                    }
+                    let lot_size = self.lot_size;
+                    for maker in level.remove_where(orders, |o| o.has_residual(lot_size)) {
+                        self.index.remove(&maker);
+                        outcome.residual_cancelled.push(maker);
+                    }
+
+                    // A pass that filled nothing would only repeat itself
+                    if filled.is_zero() && !level.is_empty() {
+                        if let (false, None, Some(maker)) =
+                            (incoming.is_filled(), &outcome.fill_refused, level.iter(orders).next())
+                        {
+                            let error = FillError::NonPositive { order_id: maker.id, quantity: Decimal::ZERO };
+                            tracing::error!(%error, "Pro-rata pass filled nothing; matching stopped");
+                            outcome.fill_refused = Some(error);
+                        }
+                        break 'matching;
+                    }
+                }
+            }
+
+            // Remove empty price level
+            if level.is_empty() {
+                opposing_book.remove(&best_price);
+                swept = true;
+            }
+            if outcome.fill_refused.is_some() {
+                break;
+            }
+        }
+
+        // Levels only ever leave from the top, so one refresh covers them all
+        if swept {
+            match incoming.side {
+                Side::Buy => self.top_ask = self.asks.first_key(),
+                Side::Sell => self.top_bid = self.bids.last_key(),
+            }
+        }
+
+        // If the order may rest and has remaining quantity, add to book.
+        // Market, IOC and FOK remainders are discarded, as is a sub-lot
+        // residual or what is left after a refused fill, which could cross.
+        if !incoming.is_filled() && incoming.can_rest() && outcome.fill_refused.is_none() {
+            if incoming.has_residual(self.lot_size) {
+                outcome.taker_residual = incoming.remaining_quantity;
+            } else {
+                self.add_order(incoming);
+            }
+        }
+    }
+
+    /// Price and volume a call auction would uncross at right now.
+    ///
+    /// The price maximises executable volume; ties go to the smallest
+    /// imbalance between demand and supply, then to the price nearest
+    /// `reference`, then to the lower price. `None` if the book isn't crossed.
+    pub fn indicative_uncross(&self, reference: Option<Decimal>) -> Option<(Decimal, Decimal)> {
+        if self.best_bid().zip(self.best_ask()).is_none_or(|(bid, ask)| bid < ask) {
+            return None;
+        }
+
+        let mut best: Option<(Decimal, Decimal, Decimal)> = None; // (price, volume, imbalance)
+        let candidates: BTreeSet<Ticks> = self.bids.keys().chain(self.asks.keys()).collect();
+        for ticks in candidates {
+            let demand: Decimal = self.bids.range(ticks..).map(|(_, l)| l.total_quantity).sum();
+            let supply: Decimal = self.asks.range(..=ticks).map(|(_, l)| l.total_quantity).sum();
+            let volume = demand.min(supply);
+            if volume.is_zero() {
+                continue;
+            }
+            let imbalance = (demand - supply).abs();
+            let price = self.grid.to_price(ticks);
+            let better = match best {
+                None => true,
+                Some((best_price, best_volume, best_imbalance)) => {
+                    (volume, -imbalance) > (best_volume, -best_imbalance)
+                        || (volume == best_volume
+                            && imbalance == best_imbalance
+                            && reference.is_some_and(|r| (price - r).abs() < (best_price - r).abs()))
+                }
+            };
+            if better {
+                best = Some((price, volume, imbalance));
+            }
+        }
+
+        best.map(|(price, volume, _)| (price, volume))
+    }
+
+    /// Run a call auction: cross every bid at or above the equilibrium price
+    /// with every ask at or below it, all at that single price.
+    ///
+    /// Orders are taken in price-time priority on each side. Each trade's
+    /// taker is whichever of the pair has the later sequence.
+    pub fn uncross(&mut self, reference: Option<Decimal>, timestamp: DateTime<Utc>) -> MatchOutcome {
+        let mut outcome = MatchOutcome::default();
+        let Some((price, _)) = self.indicative_uncross(reference) else {
+            return outcome;
+        };
+        let price_ticks = self.grid.floor_ticks(price);
+
+        while let (Some(bid_price), Some(ask_price)) = (self.top_bid, self.top_ask) {
+            if bid_price < price_ticks || ask_price > price_ticks {
+                break;
+            }
+            let bid_level = self.bids.get_mut(&bid_price).unwrap();
+            let ask_level = self.asks.get_mut(&ask_price).unwrap();
+            let (bid, ask) = self
+                .orders
+                .get2_mut(bid_level.front().unwrap(), ask_level.front().unwrap())
+                .map(|(bid, ask)| (&mut bid.order, &mut ask.order))
+                .unwrap();
+
+            let removed = if bid.same_owner(ask) {
+                // Self-trade prevention: drop the newer of the two
+                if bid.sequence >= ask.sequence {
+                    bid_level.pop_front(&mut self.orders)
+                } else {
+                    ask_level.pop_front(&mut self.orders)
+                }
+            } else {
+                let quantity = bid.remaining_quantity.min(ask.remaining_quantity);
+                let bid_is_taker = bid.sequence >= ask.sequence;
+                let (taker, maker) = if bid_is_taker { (&*bid, &*ask) } else { (&*ask, &*bid) };
+                let trade = Trade::between(taker, maker, price, quantity, timestamp);
+                if let Err(error) = bid.fill_with(ask, quantity) {
+                    tracing::error!(%error, "Auction fill refused; uncross stopped");
+                    outcome.fill_refused = Some(error);
+                    break;
+                }
+                outcome.trades.push(trade);
+                let (bid_fill, ask_fill) = (Fill::of(bid, quantity), Fill::of(ask, quantity));
+                outcome
+                    .fills
+                    .push(if bid_is_taker { [bid_fill, ask_fill] } else { [ask_fill, bid_fill] });
+                bid_level.deduct(quantity, bid.hidden);
+                ask_level.deduct(quantity, ask.hidden);
+                None
+            };
+
+            if let Some(order) = removed {
+                self.index.remove(&order);
+                outcome.self_trade_cancelled.push(order);
+            }
+            self.remove_filled_front(Side::Buy, bid_price, &mut outcome);
+            self.remove_filled_front(Side::Sell, ask_price, &mut outcome);
+        }
+
+        outcome
+    }
+
+    /// Pop the front order at a level if it has filled or is down to a
+    /// sub-lot residual, dropping the level if it empties
+    fn remove_filled_front(&mut self, side: Side, price: Ticks, outcome: &mut MatchOutcome) {
+        let book = match side {
+            Side::Buy => &mut self.bids,
+            Side::Sell => &mut self.asks,
+        };
+        let Some(level) = book.get_mut(&price) else {
+            return;
+        };
+        let front = level.front().map(|key| &self.orders[key].order);
+        if front.is_some_and(|o| o.is_filled()) {
+            if let Some(order) = level.pop_front(&mut self.orders) {
+                self.index.remove(&order);
+            }
+        } else if front.is_some_and(|o| o.has_residual(self.lot_size)) {
+            if let Some(order) = level.pop_front(&mut self.orders) {
+                self.index.remove(&order);
+                outcome.residual_cancelled.push(order);
+            }
+        }
+        if level.is_empty() {
+            book.remove(&price);
+            self.level_removed(side, price);
+        }
+    }
+
+    /// Remove every resting order whose expiry is at or before `now`.
+    /// Returns the removed orders, earliest expiry first.
+    pub fn expire_orders(&mut self, now: DateTime<Utc>) -> Vec<Order> {
+        let mut expired = Vec::new();
+
+        while let Some(&(expires_at, order_id)) = self.index.expiries.first() {
+            if expires_at > now {
+                break;
+            }
+            self.index.expiries.pop_first();
+            if let Some(order) = self.remove_from_level(order_id) {
+                expired.push(order);
+            }
+        }
+
+        expired
+    }
+
+    /// Earliest expiry among resting orders, if any has one
+    pub fn next_expiry(&self) -> Option<DateTime<Utc>> {
+        self.index.expiries.first().map(|&(expires_at, _)| expires_at)
+    }
+
+    /// Remove a resting order by id.
+    /// Returns `None` if the order is not on the book (unknown, filled or already removed).
+    pub fn cancel_order(&mut self, order_id: OrderId) -> Option<Order> {
+        self.remove_from_level(order_id)
+    }
+
+    /// Remove every resting order belonging to `owner_id`, optionally on one
+    /// side only, in a single pass over the levels.
+    /// Returns the removed orders, bids before asks, each in price-time order.
+    pub fn cancel_owned(&mut self, owner_id: &str, side: Option<Side>) -> Vec<Order> {
+        let mut removed = Vec::new();
+        for (book_side, book) in [(Side::Buy, &mut self.bids), (Side::Sell, &mut self.asks)] {
+            if side.is_some_and(|s| s != book_side) {
+                continue;
+            }
+            let mut prices: Vec<Ticks> = book.keys().collect();
+            if book_side == Side::Buy {
+                prices.reverse();
+            }
+            for ticks in prices {
+                let level = book.get_mut(&ticks).unwrap();
+                for order in level.remove_where(&mut self.orders, |o| o.owner_id.as_deref() == Some(owner_id)) {
+                    self.index.remove(&order);
+                    removed.push(order);
+                }
+            }
+            book.retain(|_, level| !level.is_empty());
+        }
+        self.top_bid = self.bids.last_key();
+        self.top_ask = self.asks.first_key();
+        removed
+    }
+
+    /// Shrink a resting order to `new_quantity` in total, filled part
+    /// included, without moving it in its level's queue, and return it as
+    /// it now stands. The quantity may not grow, and must leave some of the
+    /// order open: cutting it to what has already filled is a cancel.
+    pub fn reduce_order(&mut self, order_id: OrderId, new_quantity: Decimal) -> Result<Order, ReduceError> {
+        let &(side, price, key) = self
+            .index
+            .levels
+            .get(&order_id)
+            .ok_or(ReduceError::UnknownOrder(order_id))?;
+        let order = &self.orders[key].order;
+        let filled = order.quantity - order.remaining_quantity;
+        if new_quantity > order.quantity {
+            return Err(ReduceError::Increase {
+                quantity: order.quantity,
+                new_quantity,
+            });
+        }
+        if new_quantity <= filled {
+            return Err(ReduceError::BelowFilled { filled, new_quantity });
+        }
+
+        let book = match side {
+            Side::Buy => &mut self.bids,
+            Side::Sell => &mut self.asks,
+        };
+        let level = book.get_mut(&price).ok_or(ReduceError::UnknownOrder(order_id))?;
+        level.reduce(&mut self.orders, key, new_quantity - filled);
+        Ok(self.orders[key].order.clone())
+    }
+
+    /// Id of the resting order an owner submitted under `client_order_id`
+    pub fn find_client_order(&self, owner_id: Option<&str>, client_order_id: &str) -> Option<OrderId> {
+        self.index
+            .client_ids
+            .get(&(owner_id.map(str::to_owned), client_order_id.to_owned()))
+            .copied()
+    }
+
+    /// Number of orders `owner_id` has resting on the book
+    pub fn open_order_count(&self, owner_id: &str) -> usize {
+        self.index.open_by_owner.get(owner_id).copied().unwrap_or(0)
+    }
+
+    /// Number of orders resting on `side`
+    pub fn side_order_count(&self, side: Side) -> usize {
+        match side {
+            Side::Buy => self.index.open_bids,
+            Side::Sell => self.index.open_asks,
+        }
+    }
+
+    /// Price of the level farthest from the touch on `side`
+    pub fn farthest_price(&self, side: Side) -> Option<Decimal> {
+        let ticks = match side {
+            Side::Buy => self.bids.first_key(),
+            Side::Sell => self.asks.last_key(),
+        }?;
+        Some(self.grid.to_price(ticks))
+    }
+
+    /// Remove the newest order at the level farthest from the touch on
+    /// `side`, to make room for better-priced liquidity. The best level is
+    /// never touched: `None` when it is the only level on the side.
+    pub fn evict_farthest(&mut self, side: Side) -> Option<Order> {
+        let book = match side {
+            Side::Buy => &self.bids,
+            Side::Sell => &self.asks,
+        };
+        if book.len() < 2 {
+            return None;
+        }
+        let level = match side {
+            Side::Buy => book.values().next(),
+            Side::Sell => book.values().next_back(),
+        }?;
+        let order_id = self.orders[level.back()?].order.id;
+        self.remove_from_level(order_id)
+    }
+
+    /// Look up a resting order by id
+    pub fn get_order(&self, order_id: OrderId) -> Option<&Order> {
+        let &(_, _, key) = self.index.levels.get(&order_id)?;
+        self.orders.get(key).map(|node| &node.order)
+    }
+
+    /// Remove a resting order from its level, dropping the level if it empties
+    fn remove_from_level(&mut self, order_id: OrderId) -> Option<Order> {
+        let (side, price, key) = *self.index.levels.get(&order_id)?;
+        let book = match side {
+            Side::Buy => &mut self.bids,
+            Side::Sell => &mut self.asks,
+        };
+
+        let level = book.get_mut(&price)?;
+        let order = level.remove(&mut self.orders, key)?;
+        if level.is_empty() {
+            book.remove(&price);
+            self.level_removed(side, price);
+        }
+        self.index.remove(&order);
+        Some(order)
+    }
+
+    /// Check the book's structural invariants: level totals match their
+    /// orders, no level is empty, every resting order sits on its own side and
+    /// price with quantity left, the index and the slab hold exactly the
+    /// resting orders, the cached best prices match the trees, and the best
+    /// bid is below the best ask.
+    ///
+    /// The crossing check runs last, so a crossed but otherwise sound book
+    /// (as during a call auction) reports only `CrossedBook`.
+    pub fn validate(&self) -> Result<(), BookInvariantError> {
+        // Count from the levels themselves; `order_count` reads the index
+        let resting: usize = self.bids.values().chain(self.asks.values()).map(PriceLevel::len).sum();
+        for (level_side, book) in [(Side::Buy, &self.bids), (Side::Sell, &self.asks)] {
+            if !book.is_consistent() {
+                return Err(BookInvariantError::LadderOutOfSync { side: level_side });
+            }
+            for (ticks, level) in book.iter() {
+                let price = level.price;
+                if level.is_empty() {
+                    return Err(BookInvariantError::EmptyLevel { side: level_side, price });
+                }
+                // Everything below walks the queue, so check it ends first
+                if !level.links_intact(&self.orders) {
+                    return Err(BookInvariantError::BrokenQueue { side: level_side, price });
+                }
+                let sum: Decimal = level.iter(&self.orders).map(|o| o.remaining_quantity).sum();
+                if sum != level.total_quantity {
+                    return Err(BookInvariantError::LevelTotalMismatch {
+                        side: level_side,
+                        price,
+                        total: level.total_quantity,
+                        sum,
+                    });
+                }
+                let shown: Decimal = level.iter(&self.orders).map(Order::displayed_quantity).sum();
+                if shown != level.displayed_quantity {
+                    return Err(BookInvariantError::DisplayedMismatch {
+                        side: level_side,
+                        price,
+                        displayed: level.displayed_quantity,
+                        sum: shown,
+                    });
+                }
+                let visible = level.iter(&self.orders).filter(|o| !o.hidden).count();
+                if visible != level.displayed_len {
+                    return Err(BookInvariantError::DisplayedCountMismatch {
+                        side: level_side,
+                        price,
+                        counted: level.displayed_len,
+                        actual: visible,
+                    });
+                }
+                for key in level.keys(&self.orders) {
+                    let order = &self.orders[key].order;
+                    if order.side != level_side || order.price != price {
+                        return Err(BookInvariantError::MisplacedOrder {
+                            order_id: order.id,
+                            side: order.side,
+                            order_price: order.price,
+                            level_side,
+                            price,
+                        });
+                    }
+                    if order.remaining_quantity <= Decimal::ZERO {
+                        return Err(BookInvariantError::NonPositiveRemaining {
+                            order_id: order.id,
+                            remaining: order.remaining_quantity,
+                        });
+                    }
+                    if self.index.levels.get(&order.id) != Some(&(level_side, ticks, key)) {
+                        return Err(BookInvariantError::IndexOutOfSync {
+                            indexed: self.index.levels.len(),
+                            resting,
+                        });
+                    }
+                }
+            }
+        }
+
+        if self.index.levels.len() != resting {
+            return Err(BookInvariantError::IndexOutOfSync {
+                indexed: self.index.levels.len(),
+                resting,
+            });
+        }
+        if self.orders.len() != resting {
+            return Err(BookInvariantError::SlabOutOfSync {
+                stored: self.orders.len(),
+                resting,
+            });
+        }
+        for (side, book) in [(Side::Buy, &self.bids), (Side::Sell, &self.asks)] {
+            let counted = self.side_order_count(side);
+            let resting = book.values().map(PriceLevel::len).sum();
+            if counted != resting {
+                return Err(BookInvariantError::SideCountOutOfSync { side, counted, resting });
+            }
+        }
+
+        for (side, cached, actual) in [
+            (Side::Buy, self.top_bid, self.bids.last_key()),
+            (Side::Sell, self.top_ask, self.asks.first_key()),
+        ] {
+            if cached != actual {
+                return Err(BookInvariantError::StaleTopOfBook {
+                    side,
+                    cached: cached.map(|t| self.grid.to_price(t)),
+                    actual: actual.map(|t| self.grid.to_price(t)),
+                });
+            }
+        }
+
+        if let (Some(best_bid), Some(best_ask)) = (self.best_bid(), self.best_ask()) {
+            if best_bid >= best_ask {
+                return Err(BookInvariantError::CrossedBook { best_bid, best_ask });
+            }
+        }
+        Ok(())
+    }
+
+    /// Get total number of orders in the book
+    pub fn order_count(&self) -> usize {
+        self.index.levels.len()
+    }
+
+    /// Every resting order, bids then asks, each level in time priority.
+    /// Adding them to an empty book in this order rebuilds the same queues.
+    pub fn resting_orders(&self) -> impl Iterator<Item = &Order> + '_ {
+        self.bids
+            .values()
+            .chain(self.asks.values())
+            .flat_map(|level| level.iter(&self.orders))
+    }
+}
+
+/// Split `fill` across `orders` in proportion to their remaining size.
+///
+/// Each share is rounded down to `unit` (or the finest scale among the
+/// quantities when there is no lot size); what rounding leaves over goes to
+/// the largest orders first, oldest first among equals, so the shares always
+/// sum to exactly `fill`.
+fn pro_rata_allocations(orders: &[&Order], fill: Decimal, unit: Option<Decimal>) -> Vec<Decimal> {
+    let total: Decimal = orders.iter().map(|o| o.remaining_quantity).sum();
+    if fill >= total {
+        return orders.iter().map(|o| o.remaining_quantity).collect();
+    }
+
+    let unit = unit.unwrap_or_else(|| {
+        let scale = orders
+            .iter()
+            .map(|o| o.remaining_quantity.scale())
+            .fold(fill.scale(), u32::max);
+        Decimal::new(1, scale)
+    });
+
+    let mut allocations: Vec<Decimal> = orders
+        .iter()
+        .map(|o| ((fill * o.remaining_quantity / total) / unit).floor() * unit)
+        .map(|share| share.min(fill))
+        .collect();
+
+    let mut leftover = fill - allocations.iter().copied().sum::<Decimal>();
+    let mut by_size: Vec<usize> = (0..orders.len()).collect();
+    // Stable sort keeps time priority among equal sizes
+    by_size.sort_by(|a, b| orders[*b].remaining_quantity.cmp(&orders[*a].remaining_quantity));
+    for i in by_size {
+        if leftover <= Decimal::ZERO {
+            break;
+        }
+        let extra = leftover.min(orders[i].remaining_quantity - allocations[i]);
+        allocations[i] += extra;
+        leftover -= extra;
+    }
+
+    allocations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::order::{OrderType, TimeInForce};
+    use rust_decimal_macros::dec;
+
+    /// Tick count of a price on the default grid the test books use
+    fn ticks(price: Decimal) -> Ticks {
+        PriceGrid::default().to_ticks(price).unwrap()
+    }
+
+    #[test]
+    fn test_empty_order_book() {
+        let book = OrderBook::new("BTC/USD");
+        assert!(book.best_bid().is_none());
+        assert!(book.best_ask().is_none());
+        assert!(book.spread().is_none());
+    }
+
+    #[test]
+    fn test_add_orders() {
+        let mut book = OrderBook::new("BTC/USD");
+        
+        book.add_order(Order::new(Side::Buy, dec!(100), dec!(10)));
+        book.add_order(Order::new(Side::Buy, dec!(99), dec!(5)));
+        book.add_order(Order::new(Side::Sell, dec!(101), dec!(8)));
+        
+        assert_eq!(book.best_bid(), Some(dec!(100)));
+        assert_eq!(book.best_ask(), Some(dec!(101)));
+        assert_eq!(book.spread(), Some(dec!(1)));
+        assert_eq!(book.validate(), Ok(()));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "would cross the book")]
+    fn test_add_order_refuses_to_cross() {
+        let mut book = OrderBook::new("BTC/USD");
+        book.add_order(Order::new(Side::Sell, dec!(100), dec!(1)));
+        book.add_order(Order::new(Side::Buy, dec!(100), dec!(1)));
+    }
+
+    #[test]
+    fn test_cancel_order() {
+        let mut book = OrderBook::new("BTC/USD");
+        let first = Order::new(Side::Buy, dec!(100), dec!(10));
+        let second = Order::new(Side::Buy, dec!(100), dec!(5));
+        let (first_id, second_id) = (first.id, second.id);
+        book.add_order(first);
+        book.add_order(second);
+
+        let cancelled = book.cancel_order(second_id).unwrap();
+        assert_eq!(cancelled.remaining_quantity, dec!(5));
+        assert_eq!(book.bid_depth(10), vec![(dec!(100), dec!(10), 1)]);
+
+        // Unknown and already-cancelled ids leave the level alone
+        assert!(book.cancel_order(second_id).is_none());
+        assert!(book.cancel_order(u64::MAX).is_none());
+        assert_eq!(book.bid_depth(10), vec![(dec!(100), dec!(10), 1)]);
+
+        // Cancelling the last order drops the level
+        book.cancel_order(first_id).unwrap();
+        assert!(book.best_bid().is_none());
+        assert_eq!(book.order_count(), 0);
+        assert_eq!(book.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_cancel_after_fill_is_noop() {
+        let mut book = OrderBook::new("BTC/USD");
+        let maker = Order::new(Side::Sell, dec!(100), dec!(2));
+        let maker_id = maker.id;
+        book.add_order(maker);
+        book.add_order(Order::new(Side::Sell, dec!(100), dec!(3)));
+
+        book.match_order(Order::new(Side::Buy, dec!(100), dec!(2)));
+
+        assert!(book.get_order(maker_id).is_none());
+        assert!(book.cancel_order(maker_id).is_none());
+        assert_eq!(book.ask_depth(10), vec![(dec!(100), dec!(3), 1)]);
+        assert_eq!(book.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_reduce_order_keeps_priority() {
+        let mut book = OrderBook::new("BTC/USD");
+        let first = Order::new(Side::Sell, dec!(100), dec!(5));
+        let first_id = first.id;
+        book.add_order(first);
+        book.add_order(Order::new(Side::Sell, dec!(100), dec!(4)));
+
+        let reduced = book.reduce_order(first_id, dec!(2)).unwrap();
+        assert_eq!((reduced.quantity, reduced.remaining_quantity), (dec!(2), dec!(2)));
+        assert_eq!(book.ask_depth(10), vec![(dec!(100), dec!(6), 2)]);
+
+        // Increases and non-positive sizes are not reductions
+        assert_eq!(
+            book.reduce_order(first_id, dec!(3)).unwrap_err(),
+            ReduceError::Increase {
+                quantity: dec!(2),
+                new_quantity: dec!(3)
+            }
+        );
+        assert!(book.reduce_order(first_id, dec!(0)).is_err());
+        let unknown = u64::MAX;
+        assert_eq!(book.reduce_order(unknown, dec!(1)).unwrap_err(), ReduceError::UnknownOrder(unknown));
+        assert_eq!(book.ask_depth(10), vec![(dec!(100), dec!(6), 2)]);
+
+        // The reduced order still trades first
+        let trades = book.match_order(Order::new(Side::Buy, dec!(100), dec!(3))).trades;
+        assert_eq!(trades[0].maker_order_id, first_id);
+        assert_eq!(trades[0].quantity, dec!(2));
+        assert_eq!(book.ask_depth(10), vec![(dec!(100), dec!(3), 1)]);
+        assert_eq!(book.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_reduce_partially_filled_order_in_place() {
+        let mut book = OrderBook::new("BTC/USD");
+        let orders: Vec<Order> = [dec!(5), dec!(4), dec!(3)]
+            .into_iter()
+            .map(|quantity| Order::new(Side::Sell, dec!(100), quantity))
+            .collect();
+        let ids: Vec<OrderId> = orders.iter().map(|o| o.id).collect();
+        for order in orders {
+            book.add_order(order);
+        }
+        book.match_order(Order::new(Side::Buy, dec!(100), dec!(2)));
+
+        // Two of the first order's five have filled, so it can shrink to 3 but not to 2
+        for below in [dec!(2), dec!(1)] {
+            assert_eq!(
+                book.reduce_order(ids[0], below).unwrap_err(),
+                ReduceError::BelowFilled {
+                    filled: dec!(2),
+                    new_quantity: below
+                }
+            );
+        }
+        let reduced = book.reduce_order(ids[0], dec!(3)).unwrap();
+        assert_eq!((reduced.quantity, reduced.remaining_quantity), (dec!(3), dec!(1)));
+        book.reduce_order(ids[1], dec!(1)).unwrap();
+        assert_eq!(book.ask_depth(10), vec![(dec!(100), dec!(5), 3)]);
+
+        // Both reduced orders keep their places ahead of the third
+        let trades = book.match_order(Order::new(Side::Buy, dec!(100), dec!(3))).trades;
+        let makers: Vec<(OrderId, Decimal)> = trades.iter().map(|t| (t.maker_order_id, t.quantity)).collect();
+        assert_eq!(makers, vec![(ids[0], dec!(1)), (ids[1], dec!(1)), (ids[2], dec!(1))]);
+        assert_eq!(book.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_evict_farthest_takes_the_newest_far_order_and_spares_the_touch() {
+        let mut book = OrderBook::new("BTC/USD");
+        let orders = [
+            Order::new(Side::Sell, dec!(101), dec!(1)),
+            Order::new(Side::Sell, dec!(103), dec!(2)),
+            Order::new(Side::Sell, dec!(103), dec!(3)),
+            Order::new(Side::Buy, dec!(99), dec!(4)),
+        ];
+        let ids: Vec<_> = orders.iter().map(|o| o.id).collect();
+        for order in orders {
+            book.add_order(order);
+        }
+        assert_eq!((book.side_order_count(Side::Sell), book.side_order_count(Side::Buy)), (3, 1));
+        assert_eq!(book.farthest_price(Side::Sell), Some(dec!(103)));
+
+        assert_eq!(book.evict_farthest(Side::Sell).map(|o| o.id), Some(ids[2]));
+        assert_eq!(book.evict_farthest(Side::Sell).map(|o| o.id), Some(ids[1]));
+        // Only the touch is left on either side
+        assert!(book.evict_farthest(Side::Sell).is_none());
+        assert!(book.evict_farthest(Side::Buy).is_none());
+
+        assert_eq!(book.ask_depth(10), vec![(dec!(101), dec!(1), 1)]);
+        assert!(book.get_order(ids[1]).is_none() && book.get_order(ids[2]).is_none());
+        assert_eq!((book.side_order_count(Side::Sell), book.order_count()), (1, 2));
+        assert_eq!(book.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_double_fill_never_drives_the_book_negative() {
+        let mut book = OrderBook::new("BTC/USD");
+        let maker = Order::new(Side::Sell, dec!(100), dec!(5));
+        let maker_id = maker.id;
+        book.add_order(maker);
+
+        // A bug fills the resting order outside matching, leaving it on the book
+        let key = book.index.levels[&maker_id].2;
+        book.orders[key].order.fill(dec!(5)).unwrap();
+
+        // Matching it again is refused instead of taking the level below zero
+        let taker = Order::new(Side::Buy, dec!(100), dec!(5));
+        let taker_id = taker.id;
+        let outcome = book.match_order_outcome(taker);
+        assert!(outcome.trades.is_empty() && outcome.fills.is_empty());
+        assert!(matches!(outcome.fill_refused, Some(FillError::NonPositive { order_id, .. }) if order_id == maker_id));
+        // The taker does not rest against the level it could not fill from
+        assert!(book.get_order(taker_id).is_none());
+        assert_eq!(book.ask_depth(10), vec![(dec!(100), dec!(5), 1)]);
+    }
+
+    #[test]
+    fn test_level_totals_clamp_at_zero() {
+        let mut book = OrderBook::new("BTC/USD");
+        let orders = [Order::new(Side::Buy, dec!(99), dec!(1)), Order::new(Side::Buy, dec!(99), dec!(1))];
+        let ids: Vec<_> = orders.iter().map(|o| o.id).collect();
+        for order in orders {
+            book.add_order(order);
+        }
+
+        // An order grown behind the level's back would take more off it than it holds
+        let key = book.index.levels[&ids[0]].2;
+        book.orders[key].order.remaining_quantity = dec!(10);
+        book.cancel_order(ids[0]);
+        let level = book.iter_bids().next().unwrap();
+        assert_eq!((level.total_quantity, level.displayed_quantity), (dec!(0), dec!(0)));
+        assert_eq!(level.order_count, 1);
+    }
+
+    #[test]
+    fn test_pro_rata_match_against_a_clamped_level_terminates() {
+        let (done, finished) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            let mut book = OrderBook::new("BTC/USD").with_algorithm(MatchingAlgorithm::ProRata);
+            book.add_order(Order::new(Side::Sell, dec!(101), dec!(1)));
+            book.add_order(Order::new(Side::Sell, dec!(101), dec!(1)));
+            book.asks.get_mut(&ticks(dec!(101))).unwrap().total_quantity = Decimal::ZERO;
+
+            let outcome = book.match_order_outcome(Order::new(Side::Buy, dec!(101), dec!(1)));
+            done.send(outcome.trades.iter().map(|t| t.quantity).sum::<Decimal>()).unwrap();
+        });
+        let traded = finished.recv_timeout(std::time::Duration::from_secs(5)).expect("matching never returned");
+        assert_eq!(traded, dec!(1));
+    }
+
+    #[test]
+    fn test_pro_rata_match_terminates_against_an_emptied_maker() {
+        let (done, finished) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            let mut book = OrderBook::new("BTC/USD").with_algorithm(MatchingAlgorithm::ProRata);
+            let maker = Order::new(Side::Sell, dec!(101), dec!(1));
+            let maker_id = maker.id;
+            book.add_order(maker);
+            // Emptied behind the level's back, which still counts it
+            let key = book.index.levels[&maker_id].2;
+            book.orders[key].order.remaining_quantity = Decimal::ZERO;
+
+            let taker = Order::new(Side::Buy, dec!(101), dec!(1));
+            let taker_id = taker.id;
+            let outcome = book.match_order_outcome(taker);
+            done.send((outcome, book, maker_id, taker_id)).unwrap();
+        });
+        let (outcome, book, maker_id, taker_id) =
+            finished.recv_timeout(std::time::Duration::from_secs(5)).expect("matching never returned");
+        // Nothing trades with it, and it no longer holds the level
+        assert!(outcome.trades.is_empty() && outcome.fill_refused.is_none());
+        assert!(book.get_order(maker_id).is_none());
+        assert_eq!(book.bid_depth(10), vec![(dec!(101), dec!(1), 1)]);
+        assert!(book.get_order(taker_id).is_some() && book.best_ask().is_none());
+    }
+
+    #[test]
+    fn test_cancel_owned_keeps_levels_and_index_consistent() {
+        let mut book = OrderBook::new("BTC/USD");
+        let owned = |side, price, qty, owner: &str| {
+            let mut order = Order::new(side, price, qty);
+            order.owner_id = Some(owner.to_string());
+            order
+        };
+        let orders = [
+            owned(Side::Buy, dec!(99), dec!(1), "alice"),
+            owned(Side::Buy, dec!(99), dec!(2), "bob"),
+            owned(Side::Buy, dec!(98), dec!(3), "alice"),
+            owned(Side::Sell, dec!(101), dec!(4), "alice"),
+            owned(Side::Sell, dec!(102), dec!(5), "bob"),
+        ];
+        let ids: Vec<_> = orders.iter().map(|o| o.id).collect();
+        for order in orders {
+            book.add_order(order);
+        }
+
+        let removed: Vec<_> = book.cancel_owned("alice", Some(Side::Buy)).iter().map(|o| o.id).collect();
+        assert_eq!(removed, vec![ids[0], ids[2]]);
+        // The emptied level is gone and the shared level's total is corrected
+        assert_eq!(book.bid_depth(10), vec![(dec!(99), dec!(2), 1)]);
+        assert_eq!(book.ask_depth(10), vec![(dec!(101), dec!(4), 1), (dec!(102), dec!(5), 1)]);
+        assert!(book.get_order(ids[0]).is_none() && book.get_order(ids[2]).is_none());
+
+        let removed: Vec<_> = book.cancel_owned("alice", None).iter().map(|o| o.id).collect();
+        assert_eq!(removed, vec![ids[3]]);
+        assert_eq!(book.order_count(), 2);
+        assert!(book.cancel_order(ids[3]).is_none());
+        assert!(book.cancel_owned("nobody", None).is_empty());
+        assert_eq!(book.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_validate_reports_corruption() {
+        let mut book = OrderBook::new("BTC/USD");
+        book.add_order(Order::new(Side::Buy, dec!(99), dec!(2)));
+        book.add_order(Order::new(Side::Sell, dec!(101), dec!(1)));
+        assert_eq!(book.validate(), Ok(()));
+
+        book.bids.get_mut(&ticks(dec!(99))).unwrap().total_quantity = dec!(3);
+        assert!(matches!(
+            book.validate(),
+            Err(BookInvariantError::LevelTotalMismatch { total, sum, .. }) if total == dec!(3) && sum == dec!(2)
+        ));
+        book.bids.get_mut(&ticks(dec!(99))).unwrap().total_quantity = dec!(2);
+
+        book.asks.insert(ticks(dec!(105)), PriceLevel::new(dec!(105)));
+        assert!(matches!(book.validate(), Err(BookInvariantError::EmptyLevel { side: Side::Sell, .. })));
+        book.asks.remove(&ticks(dec!(105)));
+
+        // Bypass the index to rest an order it doesn't know about
+        let level = book.asks.get_mut(&ticks(dec!(101))).unwrap();
+        let key = level.add_order(&mut book.orders, Order::new(Side::Sell, dec!(101), dec!(1)));
+        assert!(matches!(book.validate(), Err(BookInvariantError::IndexOutOfSync { indexed: 2, resting: 3 })));
+
+        // Cut the queue short behind its first order
+        let head = book.asks[&ticks(dec!(101))].front().unwrap();
+        book.orders[head].next = None;
+        assert_eq!(
+            book.validate(),
+            Err(BookInvariantError::BrokenQueue { side: Side::Sell, price: dec!(101) })
+        );
+        book.orders[head].next = Some(key);
+
+        // Store an order no level queues
+        let mut book = OrderBook::new("BTC/USD");
+        book.add_order(Order::new(Side::Buy, dec!(99), dec!(2)));
+        book.orders.insert(QueuedOrder {
+            order: Order::new(Side::Buy, dec!(99), dec!(1)),
+            prev: None,
+            next: None,
+        });
+        assert_eq!(book.validate(), Err(BookInvariantError::SlabOutOfSync { stored: 2, resting: 1 }));
+    }
+
+    #[test]
+    fn test_cancels_anywhere_in_a_deep_level_keep_time_priority() {
+        let mut book = OrderBook::new("BTC/USD");
+        let orders: Vec<Order> = (0..5_000).map(|_| Order::new(Side::Sell, dec!(100), dec!(1))).collect();
+        for order in &orders {
+            book.add_order(order.clone());
+        }
+
+        // Cancel the head, the tail and every third order in between
+        let cancelled: Vec<usize> = (0..orders.len()).filter(|i| i % 3 == 0 || *i == orders.len() - 1).collect();
+        for &i in &cancelled {
+            assert_eq!(book.cancel_order(orders[i].id).map(|o| o.id), Some(orders[i].id));
+        }
+        assert_eq!(book.validate(), Ok(()));
+
+        let survivors: Vec<OrderId> = (0..orders.len())
+            .filter(|i| !cancelled.contains(i))
+            .map(|i| orders[i].id)
+            .collect();
+        let trades = book.match_order(Order::new(Side::Buy, dec!(100), Decimal::from(survivors.len()))).trades;
+        assert_eq!(trades.iter().map(|t| t.maker_order_id).collect::<Vec<_>>(), survivors);
+        assert_eq!(book.order_count(), 0);
+    }
+
+    #[test]
+    fn test_emptied_touch_leaves_depth() {
+        let mut book = OrderBook::new("BTC/USD");
+        book.add_order(Order::new(Side::Buy, dec!(99), dec!(5)));
+        for _ in 0..3 {
+            book.add_order(Order::new(Side::Buy, dec!(100), dec!(1)));
+            book.match_order(Order::new(Side::Sell, dec!(100), dec!(1)));
+            assert_eq!(book.best_bid(), Some(dec!(99)));
+            assert_eq!(book.bid_depth(10), vec![(dec!(99), dec!(5), 1)]);
+            assert_eq!(book.validate(), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_freed_slots_are_reused() {
+        let mut book = OrderBook::new("BTC/USD");
+        let first: Vec<Order> = (0..100).map(|i| Order::new(Side::Buy, dec!(90) + Decimal::from(i % 5), dec!(1))).collect();
+        for order in &first {
+            book.add_order(order.clone());
+        }
+        let capacity = book.orders.capacity();
+        let slot = |book: &OrderBook, id| book.index.levels[&id].2;
+        let freed = slot(&book, first[42].id);
+
+        // A cancel frees a slot for the very next order to rest
+        book.cancel_order(first[42].id);
+        let next = Order::new(Side::Buy, dec!(91), dec!(1));
+        book.add_order(next.clone());
+        assert_eq!(slot(&book, next.id), freed);
+
+        // Fills free theirs too: sweep the book and refill it
+        book.match_order(Order::new(Side::Sell, dec!(90), dec!(100)));
+        assert_eq!(book.order_count(), 0);
+        for i in 0..100 {
+            book.add_order(Order::new(Side::Sell, dec!(100) + Decimal::from(i % 5), dec!(1)));
+        }
+        assert_eq!(book.orders.capacity(), capacity);
+        assert_eq!(book.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_validate_reports_stale_top_of_book() {
+        let mut book = OrderBook::new("BTC/USD");
+        book.add_order(Order::new(Side::Sell, dec!(101), dec!(1)));
+
+        assert_eq!(book.validate(), Ok(()));
+
+        // Bypass the trees to point the cache at a level that isn't there
+        book.top_ask = Some(ticks(dec!(100)));
+        assert_eq!(
+            book.validate(),
+            Err(BookInvariantError::StaleTopOfBook {
+                side: Side::Sell,
+                cached: Some(dec!(100)),
+                actual: Some(dec!(101)),
+            })
+        );
+    }
+
+    #[test]
+    fn test_index_survives_adds_fills_and_cancels() {
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(11);
+        let mut book = OrderBook::new("BTC/USD");
+        let mut ids = Vec::new();
+        for step in 0..30_000 {
+            let side = if rng.gen_bool(0.5) { Side::Buy } else { Side::Sell };
+            let price = Decimal::from(rng.gen_range(90..=110));
+            let order = Order::new(side, price, Decimal::from(rng.gen_range(1..=10)));
+            match rng.gen_range(0..3) {
+                0 if !book.would_cross(side, price) => {
+                    ids.push(order.id);
+                    book.add_order(order);
+                }
+                1 => {
+                    ids.push(order.id);
+                    book.match_order(order);
+                }
+                _ if !ids.is_empty() => {
+                    let id = ids.swap_remove(rng.gen_range(0..ids.len()));
+                    let resting = book.get_order(id).map(|o| o.id);
+                    assert_eq!(book.cancel_order(id).map(|o| o.id), resting);
+                    assert!(book.get_order(id).is_none());
+                }
+                _ => {}
+            }
+            // A full validate walks every level, so only do it now and then
+            if step % 500 == 0 {
+                assert_eq!(book.validate(), Ok(()), "step {step}");
+            }
+        }
+        assert_eq!(book.validate(), Ok(()));
+        let resting = ids.iter().filter(|id| book.get_order(**id).is_some()).count();
+        assert_eq!(resting, book.order_count());
+    }
+
+    #[test]
+    fn test_drifting_touch_never_loses_or_duplicates_orders() {
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+        use std::collections::BTreeMap as Model;
+
+        let mut rng = StdRng::seed_from_u64(23);
+        let mut book = OrderBook::new("BTC/USD").with_price_grid(PriceGrid::new(dec!(0.01)));
+        let mut resting: Model<OrderId, Decimal> = Model::new();
+        let mut mid: i64 = 1_000_000;
+        for step in 0..3_000 {
+            // Mostly a tick or two, sometimes a jump many windows away
+            mid += if rng.gen_bool(0.02) { rng.gen_range(-20_000..=20_000) } else { rng.gen_range(-2..=2) };
+            if resting.is_empty() || rng.gen_bool(0.6) {
+                let price = Decimal::new(mid + rng.gen_range(-1_000..=1_000), 2);
+                let order = Order::new(Side::Buy, price, Decimal::from(rng.gen_range(1..=5)));
+                resting.insert(order.id, order.remaining_quantity);
+                book.add_order(order);
+            } else {
+                let id = *resting.keys().nth(rng.gen_range(0..resting.len())).unwrap();
+                assert_eq!(book.cancel_order(id).map(|o| o.id), Some(id), "step {step}");
+                resting.remove(&id);
+            }
+            assert_eq!(book.validate(), Ok(()), "step {step}");
+            assert_eq!(book.order_count(), resting.len());
+        }
+
+        let on_book: Model<OrderId, Decimal> = book
+            .bids
+            .values()
+            .flat_map(|level| level.iter(&book.orders))
+            .map(|o| (o.id, o.remaining_quantity))
+            .collect();
+        assert_eq!(on_book, resting);
+
+        // One sweep takes every order exactly once
+        let total: Decimal = resting.values().sum();
+        let trades = book.match_order(Order::new(Side::Sell, dec!(0.01), total)).trades;
+        assert_eq!(trades.len(), resting.len());
+        assert!(book.bids.is_empty());
+    }
+
+    #[test]
+    fn test_top_of_book_cache_tracks_every_mutation() {
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut book = OrderBook::new("BTC/USD").with_lot_size(Some(dec!(1)));
+        let mut ids = Vec::new();
+        for step in 0..2_000 {
+            let side = if rng.gen_bool(0.5) { Side::Buy } else { Side::Sell };
+            let price = Decimal::from(rng.gen_range(95..=105));
+            let quantity = Decimal::from(rng.gen_range(1..=5));
+            match rng.gen_range(0..5) {
+                0 | 1 => {
+                    let mut order = Order::new(side, price, quantity);
+                    order.owner_id = Some(format!("user-{}", rng.gen_range(0..4)));
+                    ids.push(order.id);
+                    book.match_order_outcome(order);
+                }
+                2 if !book.would_cross(side, price) => book.add_order(Order::new(side, price, quantity)),
+                3 if !ids.is_empty() => {
+                    book.cancel_order(ids.swap_remove(rng.gen_range(0..ids.len())));
+                }
+                4 => {
+                    book.cancel_owned(&format!("user-{}", rng.gen_range(0..4)), Some(side));
+                }
+                _ => {}
+            }
+            assert_eq!(book.validate(), Ok(()), "step {step}");
+        }
+    }
+
+    #[test]
+    fn test_validate_reports_crossed_book() {
+        let mut book = OrderBook::new("BTC/USD");
+        // Auctions add without matching, which is the only way to cross
+        book.add_auction_order(Order::new(Side::Buy, dec!(101), dec!(1)));
+        book.add_auction_order(Order::new(Side::Sell, dec!(100), dec!(1)));
+        assert_eq!(
+            book.validate(),
+            Err(BookInvariantError::CrossedBook {
+                best_bid: dec!(101),
+                best_ask: dec!(100)
+            })
+        );
+
+        book.uncross(None, Utc::now());
+        assert_eq!(book.validate(), Ok(()));
+    }
+
+    fn hidden(side: Side, price: Decimal, quantity: Decimal) -> Order {
+        let mut order = Order::new(side, price, quantity);
+        order.hidden = true;
+        order
+    }
+
+    #[test]
+    fn test_hidden_orders_match_but_stay_out_of_depth() {
+        let mut book = OrderBook::new("BTC/USD");
+        book.add_order(hidden(Side::Sell, dec!(100), dec!(3)));
+        book.add_order(hidden(Side::Sell, dec!(101), dec!(1)));
+        book.add_order(Order::new(Side::Sell, dec!(101), dec!(2)));
+
+        // The hidden-only level is invisible, the mixed one shows only its visible part
+        assert_eq!(book.ask_depth(10), vec![(dec!(101), dec!(2), 1)]);
+        assert_eq!(book.best_visible_ask(), Some(dec!(101)));
+        assert_eq!(book.best_ask(), Some(dec!(100)));
+        assert!(book.would_cross(Side::Buy, dec!(100)));
+
+        // Matching sees everything, in price-time priority
+        let trades = book.match_order(Order::new(Side::Buy, dec!(101), dec!(5))).trades;
+        let fills: Vec<_> = trades.iter().map(|t| (t.price, t.quantity)).collect();
+        assert_eq!(fills, vec![(dec!(100), dec!(3)), (dec!(101), dec!(1)), (dec!(101), dec!(1))]);
+        assert_eq!(book.ask_depth(10), vec![(dec!(101), dec!(1), 1)]);
+        assert_eq!(book.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_self_trade_prevention_cancels_resting() {
+        let mut book = OrderBook::new("BTC/USD");
+        let owned = |side, price, qty, owner: &str| {
+            let mut order = Order::new(side, price, qty);
+            order.owner_id = Some(owner.to_string());
+            order
+        };
+        let mine_a = owned(Side::Sell, dec!(100), dec!(1), "alice");
+        let theirs_a = owned(Side::Sell, dec!(100), dec!(2), "bob");
+        let mine_b = owned(Side::Sell, dec!(100), dec!(1), "alice");
+        let theirs_b = owned(Side::Sell, dec!(101), dec!(2), "carol");
+        let mine_c = owned(Side::Sell, dec!(101), dec!(5), "alice");
+        let cancelled_ids = vec![mine_a.id, mine_b.id, mine_c.id];
+        let (theirs_a_id, theirs_b_id) = (theirs_a.id, theirs_b.id);
+        for order in [mine_a, theirs_a, mine_b, theirs_b, mine_c] {
+            book.add_order(order);
+        }
+
+        let outcome = book.match_order_outcome(owned(Side::Buy, dec!(101), dec!(5), "alice"));
+
+        let makers: Vec<_> = outcome.trades.iter().map(|t| (t.maker_order_id, t.quantity)).collect();
+        assert_eq!(makers, vec![(theirs_a_id, dec!(2)), (theirs_b_id, dec!(2))]);
+        let cancelled: Vec<_> = outcome.self_trade_cancelled.iter().map(|o| o.id).collect();
+        assert_eq!(cancelled, cancelled_ids);
+
+        // Remainder rests; no stale ask levels or index entries are left behind
+        assert!(book.best_ask().is_none());
+        assert_eq!(book.bid_depth(10), vec![(dec!(101), dec!(1), 1)]);
+        assert!(cancelled_ids.iter().all(|id| book.cancel_order(*id).is_none()));
+        assert_eq!(book.validate(), Ok(()));
+    }
+
+    fn owned(side: Side, price: Decimal, qty: Decimal, owner: &str, stp_policy: Option<StpPolicy>) -> Order {
+        let mut order = Order::new(side, price, qty);
+        order.owner_id = Some(owner.to_string());
+        order.stp_policy = stp_policy;
+        order
+    }
+
+    #[test]
+    fn test_cancel_newest_stops_at_own_order() {
+        let mut book = OrderBook::new("BTC/USD");
+        let theirs = owned(Side::Sell, dec!(100), dec!(1), "bob", None);
+        let mine = owned(Side::Sell, dec!(100), dec!(2), "alice", None);
+        let (theirs_id, mine_id) = (theirs.id, mine.id);
+        book.add_order(theirs);
+        book.add_order(mine);
+        book.add_order(owned(Side::Sell, dec!(101), dec!(5), "carol", None));
+
+        let taker = owned(Side::Buy, dec!(101), dec!(4), "alice", Some(StpPolicy::CancelNewest));
+        let outcome = book.match_order_outcome(taker);
+
+        let makers: Vec<_> = outcome.trades.iter().map(|t| (t.maker_order_id, t.quantity)).collect();
+        assert_eq!(makers, vec![(theirs_id, dec!(1))]);
+        assert_eq!(outcome.taker_self_trade, dec!(3));
+        assert!(outcome.self_trade_cancelled.is_empty());
+
+        // The resting order is untouched and the remainder never rests
+        assert_eq!(book.get_order(mine_id).map(|o| o.remaining_quantity), Some(dec!(2)));
+        assert!(book.best_bid().is_none());
+        assert_eq!(book.ask_depth(10), vec![(dec!(100), dec!(2), 1), (dec!(101), dec!(5), 1)]);
+        assert_eq!(book.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_decrement_both_reduces_larger_resting_order() {
+        let mut book = OrderBook::new("BTC/USD").with_stp_policy(StpPolicy::DecrementBoth);
+        let mine = owned(Side::Sell, dec!(100), dec!(5), "alice", None);
+        let theirs = owned(Side::Sell, dec!(100), dec!(1), "bob", None);
+        let mine_id = mine.id;
+        book.add_order(mine);
+        book.add_order(theirs);
+
+        let outcome = book.match_order_outcome(owned(Side::Buy, dec!(100), dec!(2), "alice", None));
+
+        // No trade; the resting order keeps its place with the overlap taken off once
+        assert!(outcome.trades.is_empty());
+        assert!(outcome.self_trade_cancelled.is_empty());
+        assert_eq!(outcome.taker_self_trade, dec!(2));
+        let reduced: Vec<_> = outcome.self_trade_decremented.iter().map(|o| (o.id, o.remaining_quantity)).collect();
+        assert_eq!(reduced, vec![(mine_id, dec!(3))]);
+        assert_eq!(book.ask_depth(10), vec![(dec!(100), dec!(4), 2)]);
+        assert_eq!(book.get_order(mine_id).map(|o| o.quantity), Some(dec!(3)));
+        assert!(book.best_bid().is_none());
+        assert_eq!(book.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_decrement_both_cancels_sub_lot_remainder() {
+        for algorithm in [MatchingAlgorithm::Fifo, MatchingAlgorithm::ProRata] {
+            let mut book = OrderBook::new("BTC/USD")
+                .with_algorithm(algorithm)
+                .with_lot_size(Some(dec!(1)))
+                .with_stp_policy(StpPolicy::DecrementBoth);
+            let mine = owned(Side::Sell, dec!(100), dec!(2), "alice", None);
+            let mine_id = mine.id;
+            book.add_order(mine);
+
+            let outcome = book.match_order_outcome(owned(Side::Buy, dec!(100), dec!(1.5), "alice", None));
+
+            // The 0.5 left on the resting order is below a lot, so it is cancelled rather than decremented
+            assert!(outcome.trades.is_empty());
+            assert_eq!(outcome.taker_self_trade, dec!(1.5));
+            assert!(outcome.self_trade_decremented.is_empty());
+            let swept: Vec<_> = outcome.residual_cancelled.iter().map(|o| (o.id, o.remaining_quantity)).collect();
+            assert_eq!(swept, vec![(mine_id, dec!(0.5))], "{algorithm:?}");
+            assert!(book.get_order(mine_id).is_none());
+            assert!(book.ask_depth(10).is_empty());
+            assert_eq!(book.validate(), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_decrement_both_keeps_matching_larger_incoming_order() {
+        let mut book = OrderBook::new("BTC/USD").with_stp_policy(StpPolicy::DecrementBoth);
+        let mine = owned(Side::Sell, dec!(100), dec!(2), "alice", None);
+        let theirs = owned(Side::Sell, dec!(100), dec!(1), "bob", None);
+        let (mine_id, theirs_id) = (mine.id, theirs.id);
+        book.add_order(mine);
+        book.add_order(theirs);
+
+        let taker = owned(Side::Buy, dec!(100), dec!(5), "alice", None);
+        let taker_id = taker.id;
+        let outcome = book.match_order_outcome(taker);
+
+        let cancelled: Vec<_> = outcome.self_trade_cancelled.iter().map(|o| o.id).collect();
+        assert_eq!(cancelled, vec![mine_id]);
+        assert_eq!(outcome.taker_self_trade, dec!(2));
+        let makers: Vec<_> = outcome.trades.iter().map(|t| (t.maker_order_id, t.quantity)).collect();
+        assert_eq!(makers, vec![(theirs_id, dec!(1))]);
+        // Fill totals are measured against the decremented size
+        assert_eq!(outcome.fills[0][0].cumulative_filled, dec!(1));
+        assert_eq!(outcome.fills[0][0].remaining, dec!(2));
+
+        let rested = book.get_order(taker_id).unwrap();
+        assert_eq!((rested.quantity, rested.remaining_quantity), (dec!(3), dec!(2)));
+        assert!(book.best_ask().is_none());
+        assert_eq!(book.bid_depth(10), vec![(dec!(100), dec!(2), 1)]);
+        assert_eq!(book.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_decrement_both_equal_sizes_removes_both() {
+        let mut book = OrderBook::new("BTC/USD");
+        let mut hidden = owned(Side::Sell, dec!(100), dec!(2), "alice", None);
+        hidden.hidden = true;
+        book.add_order(hidden);
+        book.add_order(owned(Side::Sell, dec!(101), dec!(1), "bob", None));
+
+        let taker = owned(Side::Buy, dec!(101), dec!(2), "alice", Some(StpPolicy::DecrementBoth));
+        let outcome = book.match_order_outcome(taker);
+
+        assert_eq!(outcome.self_trade_cancelled.len(), 1);
+        assert_eq!(outcome.taker_self_trade, dec!(2));
+        assert!(outcome.trades.is_empty());
+        assert!(book.best_bid().is_none());
+        assert_eq!(book.best_ask(), Some(dec!(101)));
+        assert_eq!(book.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_fillable_quantity_stops_at_own_order_unless_cancel_oldest() {
+        let mut book = OrderBook::new("BTC/USD");
+        book.add_order(owned(Side::Sell, dec!(100), dec!(1), "bob", None));
+        book.add_order(owned(Side::Sell, dec!(100), dec!(1), "alice", None));
+        book.add_order(owned(Side::Sell, dec!(101), dec!(3), "bob", None));
+
+        let oldest = owned(Side::Buy, dec!(101), dec!(4), "alice", None);
+        assert_eq!(book.fillable_quantity(&oldest), dec!(4));
+        for policy in [StpPolicy::CancelNewest, StpPolicy::DecrementBoth] {
+            let taker = owned(Side::Buy, dec!(101), dec!(4), "alice", Some(policy));
+            assert_eq!(book.fillable_quantity(&taker), dec!(1));
+        }
     }
 
-    /// Remove the front order (oldest by time priority)
-    pub fn pop_front(&mut self) -> Option<Order> {
-        if let Some(order) = self.orders.pop_front() {
-            self.total_quantity -= order.remaining_quantity;
-            Some(order)
-        } else {
-            None
+    fn pro_rata_book() -> (OrderBook, Vec<OrderId>) {
+        let mut book = OrderBook::new("BTC/USD").with_algorithm(MatchingAlgorithm::ProRata);
+        let mut ids = Vec::new();
+        for qty in [dec!(1.0), dec!(3.0), dec!(6.0)] {
+            let order = Order::new(Side::Sell, dec!(100), qty);
+            ids.push(order.id);
+            book.add_order(order);
         }
+        (book, ids)
     }
 
-    /// Get mutable reference to front order
-    pub fn front_mut(&mut self) -> Option<&mut Order> {
-        self.orders.front_mut()
+    #[test]
+    fn test_pro_rata_splits_by_size() {
+        let (mut book, ids) = pro_rata_book();
+
+        let trades = book.match_order(Order::new(Side::Buy, dec!(100), dec!(5))).trades;
+
+        let fills: Vec<_> = trades.iter().map(|t| (t.maker_order_id, t.quantity)).collect();
+        assert_eq!(fills, vec![(ids[0], dec!(0.5)), (ids[1], dec!(1.5)), (ids[2], dec!(3))]);
+        assert_eq!(book.ask_depth(10), vec![(dec!(100), dec!(5), 3)]);
     }
 
-    /// Check if this price level is empty
-    pub fn is_empty(&self) -> bool {
-        self.orders.is_empty()
+    #[test]
+    fn test_pro_rata_remainder_goes_to_largest() {
+        let (book, _) = pro_rata_book();
+        let mut book = book.with_lot_size(Some(dec!(1)));
+
+        // 7 × (1, 3, 6) / 10 = (0.7, 2.1, 4.2) → (0, 2, 4) + 1 to the largest
+        let trades = book.match_order(Order::new(Side::Buy, dec!(100), dec!(7))).trades;
+
+        let quantities: Vec<_> = trades.iter().map(|t| t.quantity).collect();
+        assert_eq!(quantities, vec![dec!(2), dec!(5)]);
+        assert_eq!(book.ask_depth(10), vec![(dec!(100), dec!(3), 3)]);
+        assert_eq!(book.order_count(), 3);
+        assert_eq!(book.validate(), Ok(()));
     }
 
-    /// Get the number of orders at this level
-    pub fn len(&self) -> usize {
-        self.orders.len()
+    #[test]
+    fn test_pro_rata_remainder_tie_goes_to_oldest() {
+        let mut book = OrderBook::new("BTC/USD")
+            .with_algorithm(MatchingAlgorithm::ProRata)
+            .with_lot_size(Some(dec!(1)));
+        let first = Order::new(Side::Sell, dec!(100), dec!(2));
+        let first_id = first.id;
+        book.add_order(first);
+        book.add_order(Order::new(Side::Sell, dec!(100), dec!(2)));
+
+        let trades = book.match_order(Order::new(Side::Buy, dec!(100), dec!(3))).trades;
+
+        assert_eq!(trades[0].maker_order_id, first_id);
+        assert_eq!(trades[0].quantity, dec!(2));
+        assert_eq!(trades[1].quantity, dec!(1));
+        assert_eq!(book.validate(), Ok(()));
     }
-}
 
-/// The central limit order book
-#[derive(Debug)]
-pub struct OrderBook {
-    /// Buy orders: highest price first (descending)
-    /// Key is negated price for reverse ordering
-    bids: BTreeMap<Decimal, PriceLevel>,
-    
-    /// Sell orders: lowest price first (ascending)
-    asks: BTreeMap<Decimal, PriceLevel>,
-    
-    /// Symbol for this order book
-    pub symbol: String,
-}
+    #[test]
+    fn test_pro_rata_fills_always_sum_to_incoming() {
+        let mut sizes = [dec!(0.007), dec!(1.3), dec!(0.25), dec!(4.1), dec!(0.333)].into_iter().cycle();
+        for incoming in [dec!(0.001), dec!(0.999), dec!(2.5), dec!(5.989)] {
+            let mut book = OrderBook::new("BTC/USD").with_algorithm(MatchingAlgorithm::ProRata);
+            for _ in 0..5 {
+                book.add_order(Order::new(Side::Sell, dec!(100), sizes.next().unwrap()));
+            }
 
-impl OrderBook {
-    /// Create a new order book for the given symbol
-    pub fn new(symbol: impl Into<String>) -> Self {
-        Self {
-            bids: BTreeMap::new(),
-            asks: BTreeMap::new(),
-            symbol: symbol.into(),
+            let trades = book.match_order(Order::new(Side::Buy, dec!(100), incoming)).trades;
+
+            let filled: Decimal = trades.iter().map(|t| t.quantity).sum();
+            assert_eq!(filled, incoming);
+            assert_eq!(book.ask_depth(1)[0].1, dec!(5.99) - incoming);
+            assert_eq!(book.validate(), Ok(()));
         }
     }
 
-    /// Get the best bid price (highest buy price)
-    pub fn best_bid(&self) -> Option<Decimal> {
-        self.bids.keys().next_back().copied()
+    #[test]
+    fn test_pro_rata_sweeps_levels() {
+        let (mut book, _) = pro_rata_book();
+        book.add_order(Order::new(Side::Sell, dec!(101), dec!(4)));
+
+        let trades = book.match_order(Order::new(Side::Buy, dec!(101), dec!(12))).trades;
+
+        assert_eq!(trades.iter().map(|t| t.quantity).sum::<Decimal>(), dec!(12));
+        assert_eq!(book.ask_depth(10), vec![(dec!(101), dec!(2), 1)]);
+        assert_eq!(book.order_count(), 1);
     }
 
-    /// Get the best ask price (lowest sell price)
-    pub fn best_ask(&self) -> Option<Decimal> {
-        self.asks.keys().next().copied()
+    #[test]
+    fn test_pro_rata_decrement_both_before_allocating() {
+        let mut book = OrderBook::new("BTC/USD")
+            .with_algorithm(MatchingAlgorithm::ProRata)
+            .with_stp_policy(StpPolicy::DecrementBoth);
+        book.add_order(owned(Side::Sell, dec!(100), dec!(1), "bob", None));
+        book.add_order(owned(Side::Sell, dec!(100), dec!(2), "alice", None));
+        book.add_order(owned(Side::Sell, dec!(100), dec!(3), "bob", None));
+
+        let outcome = book.match_order_outcome(owned(Side::Buy, dec!(100), dec!(4), "alice", None));
+
+        // The own order absorbs 2 of the 4; the other 2 are shared 1:3 among bob's orders
+        assert_eq!(outcome.self_trade_cancelled.len(), 1);
+        assert_eq!(outcome.taker_self_trade, dec!(2));
+        assert_eq!(outcome.trades.iter().map(|t| t.quantity).sum::<Decimal>(), dec!(2));
+        assert_eq!(book.ask_depth(1), vec![(dec!(100), dec!(2), 2)]);
+        assert!(book.best_bid().is_none());
+        assert_eq!(book.validate(), Ok(()));
+
+        // Cancel-newest leaves the level alone
+        let taker = owned(Side::Buy, dec!(100), dec!(1), "bob", Some(StpPolicy::CancelNewest));
+        let outcome = book.match_order_outcome(taker);
+        assert!(outcome.trades.is_empty());
+        assert_eq!(outcome.taker_self_trade, dec!(1));
+        assert_eq!(book.ask_depth(1), vec![(dec!(100), dec!(2), 2)]);
+        assert_eq!(book.validate(), Ok(()));
     }
 
-    /// Get the spread between best bid and ask
-    pub fn spread(&self) -> Option<Decimal> {
-        match (self.best_bid(), self.best_ask()) {
-            (Some(bid), Some(ask)) => Some(ask - bid),
-            _ => None,
-        }
+    #[test]
+    fn test_indicative_uncross_maximises_volume() {
+        let mut book = OrderBook::new("BTC/USD");
+        assert!(book.indicative_uncross(None).is_none());
+
+        book.add_auction_order(Order::new(Side::Buy, dec!(102), dec!(3)));
+        book.add_auction_order(Order::new(Side::Buy, dec!(101), dec!(2)));
+        book.add_auction_order(Order::new(Side::Buy, dec!(99), dec!(5)));
+        book.add_auction_order(Order::new(Side::Sell, dec!(100), dec!(4)));
+        book.add_auction_order(Order::new(Side::Sell, dec!(101), dec!(2)));
+        book.add_auction_order(Order::new(Side::Sell, dec!(103), dec!(5)));
+
+        // At 101: demand 5, supply 6 → 5; at 100: demand 5, supply 4 → 4
+        assert_eq!(book.indicative_uncross(None), Some((dec!(101), dec!(5))));
     }
 
-    /// Get the bid side depth (price levels and quantities)
-    pub fn bid_depth(&self, levels: usize) -> Vec<(Decimal, Decimal)> {
-        self.bids
-            .iter()
-            .rev()
-            .take(levels)
-            .map(|(price, level)| (*price, level.total_quantity))
-            .collect()
+    #[test]
+    fn test_indicative_uncross_tie_breaks() {
+        let mut book = OrderBook::new("BTC/USD");
+        book.add_auction_order(Order::new(Side::Buy, dec!(102), dec!(2)));
+        book.add_auction_order(Order::new(Side::Sell, dec!(100), dec!(2)));
+
+        // Equal volume and imbalance at 100 and 102
+        assert_eq!(book.indicative_uncross(None), Some((dec!(100), dec!(2))));
+        assert_eq!(book.indicative_uncross(Some(dec!(110))), Some((dec!(102), dec!(2))));
     }
 
-    /// Get the ask side depth (price levels and quantities)
-    pub fn ask_depth(&self, levels: usize) -> Vec<(Decimal, Decimal)> {
-        self.asks
-            .iter()
-            .take(levels)
-            .map(|(price, level)| (*price, level.total_quantity))
-            .collect()
+    #[test]
+    fn test_uncross_trades_at_single_price() {
+        let mut book = OrderBook::new("BTC/USD");
+        book.add_auction_order(Order::new(Side::Buy, dec!(102), dec!(3)));
+        book.add_auction_order(Order::new(Side::Buy, dec!(101), dec!(2)));
+        book.add_auction_order(Order::new(Side::Buy, dec!(99), dec!(5)));
+        book.add_auction_order(Order::new(Side::Sell, dec!(100), dec!(4)));
+        book.add_auction_order(Order::new(Side::Sell, dec!(101), dec!(2)));
+        book.add_auction_order(Order::new(Side::Sell, dec!(103), dec!(5)));
+
+        let outcome = book.uncross(None, Utc::now());
+
+        assert!(outcome.trades.iter().all(|t| t.price == dec!(101)));
+        assert_eq!(outcome.trades.iter().map(|t| t.quantity).sum::<Decimal>(), dec!(5));
+        assert_eq!(book.best_bid(), Some(dec!(99)));
+        assert_eq!(book.ask_depth(10), vec![(dec!(101), dec!(1), 1), (dec!(103), dec!(5), 1)]);
+        assert!(book.indicative_uncross(None).is_none());
+        assert_eq!(book.validate(), Ok(()));
     }
 
-    /// Add an order to the book (no matching, just insertion)
-    pub fn add_order(&mut self, order: Order) {
-        let book = match order.side {
-            Side::Buy => &mut self.bids,
-            Side::Sell => &mut self.asks,
-        };
+    #[test]
+    fn test_uncross_taker_is_later_sequence() {
+        let mut book = OrderBook::new("BTC/USD");
+        let now = Utc::now();
+        let mut ask = Order::new(Side::Sell, dec!(100), dec!(1));
+        let mut bid = Order::new(Side::Buy, dec!(100), dec!(1));
+        // Same timestamp; the ask arrived later
+        (ask.timestamp, ask.sequence, bid.timestamp, bid.sequence) = (now, 2, now, 1);
+        let ask_id = ask.id;
+        book.add_auction_order(ask);
+        book.add_auction_order(bid);
 
-        book.entry(order.price)
-            .or_insert_with(PriceLevel::new)
-            .add_order(order);
+        let outcome = book.uncross(None, now);
+
+        assert_eq!(outcome.trades[0].taker_order_id, ask_id);
+        assert_eq!(outcome.trades[0].taker_side, Side::Sell);
+        assert_eq!((outcome.trades[0].taker_sequence, outcome.trades[0].maker_sequence), (2, 1));
     }
 
-    /// Match an incoming order against the book
-    /// Returns a vector of trades generated
-    pub fn match_order(&mut self, mut incoming: Order) -> Vec<Trade> {
-        let mut trades = Vec::new();
+    #[test]
+    fn test_sub_lot_residuals_are_swept() {
+        let mut book = OrderBook::new("BTC/USD").with_lot_size(Some(dec!(0.01)));
+        let sliver = Order::new(Side::Sell, dec!(100), dec!(1.0000001));
+        let sliver_id = sliver.id;
+        book.add_order(sliver);
 
-        // Get the opposing book
-        let opposing_book = match incoming.side {
-            Side::Buy => &mut self.asks,
-            Side::Sell => &mut self.bids,
-        };
+        let outcome = book.match_order_outcome(Order::new(Side::Buy, dec!(100), dec!(1)));
 
-        // Keep matching while possible
-        loop {
-            if incoming.is_filled() {
-                break;
-            }
+        assert_eq!(outcome.trades[0].quantity, dec!(1));
+        let swept: Vec<_> = outcome.residual_cancelled.iter().map(|o| (o.id, o.remaining_quantity)).collect();
+        assert_eq!(swept, vec![(sliver_id, dec!(0.0000001))]);
+        assert!(book.best_ask().is_none());
+        assert!(book.get_order(sliver_id).is_none());
+        assert_eq!(book.validate(), Ok(()));
 
-            // Get the best opposing price
-            let best_price = match incoming.side {
-                Side::Buy => opposing_book.keys().next().copied(),
-                Side::Sell => opposing_book.keys().next_back().copied(),
-            };
+        // A taker's own sliver never rests
+        book.add_order(Order::new(Side::Sell, dec!(100), dec!(2)));
+        let outcome = book.match_order_outcome(Order::new(Side::Buy, dec!(100), dec!(2.005)));
+        assert_eq!(outcome.taker_residual, dec!(0.005));
+        assert_eq!(book.order_count(), 0);
+        assert_eq!(book.validate(), Ok(()));
+    }
 
-            let best_price = match best_price {
-                Some(p) => p,
-                None => break, // No orders on opposing side
-            };
+    #[test]
+    fn test_residual_sweep_leaves_level_total_at_zero() {
+        let mut book = OrderBook::new("BTC/USD").with_lot_size(Some(dec!(0.01)));
+        let sliver = Order::new(Side::Sell, dec!(100), dec!(1.004));
+        let whole = Order::new(Side::Sell, dec!(100), dec!(1));
+        let whole_id = whole.id;
+        book.add_order(sliver);
+        book.add_order(whole);
 
-            // Check if prices cross
-            let prices_cross = match incoming.side {
-                Side::Buy => incoming.price >= best_price,
-                Side::Sell => incoming.price <= best_price,
-            };
+        let outcome = book.match_order_outcome(Order::new(Side::Buy, dec!(100), dec!(1)));
 
-            if !prices_cross {
-                break;
-            }
+        assert_eq!(outcome.residual_cancelled.len(), 1);
+        let level = book.asks.get(&ticks(dec!(100))).unwrap();
+        assert_eq!(level.total_quantity, dec!(1));
+        assert_eq!(level.displayed_quantity, dec!(1));
+        assert_eq!(level.iter(&book.orders).next().map(|o| o.id), Some(whole_id));
 
-            // Get the price level
-            let level = opposing_book.get_mut(&best_price).unwrap();
+        // Filling all but a sliver of the last order empties the level outright
+        let outcome = book.match_order_outcome(Order::new(Side::Buy, dec!(100), dec!(0.996)));
+        assert_eq!(outcome.residual_cancelled[0].remaining_quantity, dec!(0.004));
+        assert!(book.asks.is_empty());
+        assert_eq!(book.validate(), Ok(()));
+    }
 
-            // Match against orders at this level
-            while !incoming.is_filled() && !level.is_empty() {
-                // Get the front order info without holding the borrow
-                let (maker_id, maker_remaining) = {
-                    let maker = level.orders.front().unwrap();
-                    (maker.id, maker.remaining_quantity)
-                };
+    #[test]
+    fn test_pro_rata_residuals_are_swept() {
+        let mut book = OrderBook::new("BTC/USD")
+            .with_algorithm(MatchingAlgorithm::ProRata)
+            .with_lot_size(Some(dec!(1)));
+        book.add_order(Order::new(Side::Sell, dec!(100), dec!(2.5)));
+        book.add_order(Order::new(Side::Sell, dec!(100), dec!(7.5)));
 
-                // Calculate fill quantity
-                let fill_qty = incoming.remaining_quantity.min(maker_remaining);
-
-                // Create trade (execute at maker's price)
-                let trade = Trade::new(
-                    incoming.id,
-                    maker_id,
-                    best_price, // Trade at the maker's price
-                    fill_qty,
-                    incoming.side,
-                );
-
-                // Update incoming order
-                incoming.fill(fill_qty);
-
-                // Update maker order and level
-                {
-                    let maker = level.orders.front_mut().unwrap();
-                    maker.fill(fill_qty);
-                }
-                level.total_quantity -= fill_qty;
+        // Whole-lot allocations leave 0.5 on each order
+        let outcome = book.match_order_outcome(Order::new(Side::Buy, dec!(100), dec!(9)));
 
-                // Remove filled maker order
-                let maker_filled = level.orders.front().map(|o| o.is_filled()).unwrap_or(false);
-                if maker_filled {
-                    level.orders.pop_front();
-                }
+        assert_eq!(outcome.trades.iter().map(|t| t.quantity).sum::<Decimal>(), dec!(9));
+        assert_eq!(outcome.residual_cancelled.len(), 2);
+        assert!(book.asks.is_empty());
+        assert_eq!(book.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_bbo_carries_displayed_size_at_the_touch() {
+        let mut book = OrderBook::new("BTC/USD");
+        assert_eq!(book.bbo(), Bbo::default());
 
-                trades.push(trade);
+        book.add_order(Order::new(Side::Buy, dec!(99), dec!(2)));
+        book.add_order(Order::new(Side::Buy, dec!(99), dec!(1.5)));
+        book.add_order(hidden(Side::Buy, dec!(99), dec!(7)));
+        book.add_order(Order::new(Side::Sell, dec!(102), dec!(4)));
+        assert_eq!(
+            book.bbo(),
+            Bbo {
+                bid: Some((dec!(99), dec!(3.5))),
+                ask: Some((dec!(102), dec!(4))),
             }
+        );
 
-            // Remove empty price level
-            if level.is_empty() {
-                match incoming.side {
-                    Side::Buy => opposing_book.remove(&best_price),
-                    Side::Sell => opposing_book.remove(&best_price),
-                };
+        // A hidden-only top level is passed over
+        book.add_order(hidden(Side::Sell, dec!(101), dec!(5)));
+        assert_eq!(book.bbo().ask, Some((dec!(102), dec!(4))));
+        assert_eq!(book.bbo().bid, book.bid_depth(1).first().map(|&(p, q, _)| (p, q)));
+    }
+
+    #[test]
+    fn test_depth_counts_only_displayed_orders() {
+        let mut book = OrderBook::new("BTC/USD");
+        let first = Order::new(Side::Buy, dec!(99), dec!(1));
+        let first_id = first.id;
+        book.add_order(first);
+        book.add_order(hidden(Side::Buy, dec!(99), dec!(10)));
+        for _ in 0..3 {
+            book.add_order(Order::new(Side::Buy, dec!(99), dec!(1)));
+        }
+        book.add_order(Order::new(Side::Buy, dec!(98), dec!(100)));
+        assert_eq!(
+            book.bid_depth(10),
+            vec![(dec!(99), dec!(4), 4), (dec!(98), dec!(100), 1)]
+        );
+
+        // The hidden order is next in the queue, then one displayed order fills
+        book.cancel_order(first_id);
+        book.match_order(Order::new(Side::Sell, dec!(99), dec!(11.5)));
+        assert_eq!(book.bid_depth(1), vec![(dec!(99), dec!(1.5), 2)]);
+        assert_eq!(book.iter_bids().next().map(|l| l.order_count), Some(2));
+        assert_eq!(book.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_level_iterators_follow_matching_priority() {
+        let mut book = OrderBook::new("BTC/USD");
+        for (side, price, quantity) in [
+            (Side::Buy, dec!(99), dec!(2)),
+            (Side::Buy, dec!(100), dec!(1)),
+            (Side::Buy, dec!(100), dec!(3)),
+            (Side::Buy, dec!(97), dec!(4)),
+            (Side::Sell, dec!(103), dec!(5)),
+            (Side::Sell, dec!(101), dec!(3)),
+        ] {
+            book.add_order(Order::new(side, price, quantity));
+        }
+        book.add_order(hidden(Side::Buy, dec!(98), dec!(9)));
+        book.add_order(hidden(Side::Sell, dec!(101), dec!(2)));
+
+        let bids: Vec<LevelView> = book.iter_bids().collect();
+        assert_eq!(
+            bids.iter().map(|l| (l.price, l.total_quantity, l.order_count)).collect::<Vec<_>>(),
+            vec![
+                (dec!(100), dec!(4), 2),
+                (dec!(99), dec!(2), 1),
+                (dec!(98), dec!(9), 1),
+                (dec!(97), dec!(4), 1),
+            ]
+        );
+        assert!(bids[2].is_hidden());
+        let asks: Vec<LevelView> = book.iter_asks().collect();
+        assert_eq!(asks[0].total_quantity, dec!(5));
+        assert_eq!(asks[0].displayed_quantity, dec!(3));
+        assert_eq!(asks.iter().map(|l| l.price).collect::<Vec<_>>(), vec![dec!(101), dec!(103)]);
+
+        // Sweeping each side trades its levels in exactly the iterated order
+        for (side, levels) in [(Side::Sell, bids), (Side::Buy, asks)] {
+            let total = levels.iter().map(|l| l.total_quantity).sum();
+            let mut sweep = Order::new(side, dec!(0), total);
+            sweep.order_type = OrderType::Market;
+            let trades = book.match_order(sweep).trades;
+            let mut traded: Vec<(Decimal, Decimal)> = Vec::new();
+            for trade in trades {
+                match traded.last_mut() {
+                    Some((price, quantity)) if *price == trade.price => *quantity += trade.quantity,
+                    _ => traded.push((trade.price, trade.quantity)),
+                }
             }
+            assert_eq!(traded, levels.iter().map(|l| (l.price, l.total_quantity)).collect::<Vec<_>>());
         }
+        assert_eq!(book.iter_bids().count() + book.iter_asks().count(), 0);
+    }
 
-        // If incoming order has remaining quantity, add to book
-        if !incoming.is_filled() {
-            self.add_order(incoming);
+    #[test]
+    fn test_cumulative_depth_runs_from_the_touch() {
+        let mut book = OrderBook::new("BTC/USD");
+        for (side, price, quantity) in [
+            (Side::Buy, dec!(100), dec!(1)),
+            (Side::Buy, dec!(99), dec!(2)),
+            (Side::Buy, dec!(97), dec!(4)),
+            (Side::Sell, dec!(101), dec!(3)),
+            (Side::Sell, dec!(103), dec!(5)),
+        ] {
+            book.add_order(Order::new(side, price, quantity));
         }
+        book.add_order(hidden(Side::Buy, dec!(98), dec!(9)));
 
-        trades
+        assert_eq!(
+            book.cumulative_depth(Side::Buy, 10),
+            vec![(dec!(100), dec!(1)), (dec!(99), dec!(3)), (dec!(97), dec!(7))]
+        );
+        assert_eq!(book.cumulative_depth(Side::Sell, 1), vec![(dec!(101), dec!(3))]);
+        assert_eq!(
+            book.cumulative_depth(Side::Sell, 10),
+            vec![(dec!(101), dec!(3)), (dec!(103), dec!(8))]
+        );
+        assert!(OrderBook::new("BTC/USD").cumulative_depth(Side::Buy, 10).is_empty());
     }
 
-    /// Get total number of orders in the book
-    pub fn order_count(&self) -> usize {
-        let bid_count: usize = self.bids.values().map(|l| l.len()).sum();
-        let ask_count: usize = self.asks.values().map(|l| l.len()).sum();
-        bid_count + ask_count
+    #[test]
+    fn test_depth_notional_is_capped_to_the_quote_scale() {
+        let mut book = OrderBook::new("BTC/USD").with_price_grid(PriceGrid::new(dec!(0.01)));
+        for (side, price, quantity) in [
+            (Side::Buy, dec!(100.50), dec!(2)),
+            (Side::Buy, dec!(99.99), dec!(0.123456)),
+            (Side::Sell, dec!(101.25), dec!(0.5)),
+        ] {
+            book.add_order(Order::new(side, price, quantity));
+        }
+
+        assert_eq!(
+            book.bid_depth_notional(10),
+            vec![(dec!(100.50), dec!(2), dec!(201.00)), (dec!(99.99), dec!(0.123456), dec!(12.34))]
+        );
+        // Halves round to even at the quote scale
+        assert_eq!(book.ask_depth_notional(10), vec![(dec!(101.25), dec!(0.5), dec!(50.62))]);
+        assert_eq!(
+            book.cumulative_notional(Side::Buy, 10),
+            vec![(dec!(100.50), dec!(201.00)), (dec!(99.99), dec!(213.34))]
+        );
+
+        let book = book.with_quote_scale(4);
+        assert_eq!(book.ask_depth_notional(1), vec![(dec!(101.25), dec!(0.5), dec!(50.625))]);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use rust_decimal_macros::dec;
+    #[test]
+    fn test_liquidity_within_distance_of_the_touch() {
+        let mut book = OrderBook::new("BTC/USD");
+        for (side, price, quantity) in [
+            (Side::Buy, dec!(100), dec!(1)),
+            (Side::Buy, dec!(99.50), dec!(2)),
+            (Side::Buy, dec!(98), dec!(4)),
+            (Side::Sell, dec!(101), dec!(3)),
+            (Side::Sell, dec!(101.25), dec!(5)),
+        ] {
+            book.add_order(Order::new(side, price, quantity));
+        }
+        // Hidden quantity neither counts nor sets the touch
+        book.add_order(hidden(Side::Buy, dec!(99.75), dec!(9)));
+        book.add_order(hidden(Side::Sell, dec!(100.50), dec!(9)));
+
+        assert_eq!(book.liquidity_within(Side::Buy, dec!(0)), dec!(1));
+        // Levels exactly `distance` away are included
+        assert_eq!(book.liquidity_within(Side::Buy, dec!(0.5)), dec!(3));
+        assert_eq!(book.liquidity_within(Side::Buy, dec!(1.99)), dec!(3));
+        assert_eq!(book.liquidity_within(Side::Buy, dec!(2)), dec!(7));
+        assert_eq!(book.liquidity_within(Side::Sell, dec!(0.1)), dec!(3));
+        assert_eq!(book.liquidity_within(Side::Sell, dec!(1000)), dec!(8));
+
+        // An empty side, and one with only hidden orders, has none
+        let mut empty = OrderBook::new("BTC/USD");
+        assert_eq!(empty.liquidity_within(Side::Sell, dec!(1000)), dec!(0));
+        empty.add_order(hidden(Side::Sell, dec!(101), dec!(1)));
+        assert_eq!(empty.liquidity_within(Side::Sell, dec!(1000)), dec!(0));
+    }
 
     #[test]
-    fn test_empty_order_book() {
-        let book = OrderBook::new("BTC/USD");
-        assert!(book.best_bid().is_none());
-        assert!(book.best_ask().is_none());
-        assert!(book.spread().is_none());
+    fn test_vwap_for_quantity_walks_the_opposing_side() {
+        let mut book = OrderBook::new("BTC/USD");
+        for (side, price, quantity) in [
+            (Side::Sell, dec!(101), dec!(2)),
+            (Side::Sell, dec!(102), dec!(3)),
+            (Side::Buy, dec!(100), dec!(1)),
+            (Side::Buy, dec!(99), dec!(1)),
+        ] {
+            book.add_order(Order::new(side, price, quantity));
+        }
+        book.add_order(hidden(Side::Sell, dec!(100.50), dec!(9)));
+
+        // Within the touch; the hidden ask in front of it is not counted
+        let impact = book.vwap_for_quantity(Side::Buy, dec!(1.5));
+        assert_eq!(impact.vwap, Some(dec!(101)));
+        assert_eq!(impact.worst_price, Some(dec!(101)));
+        assert!(impact.complete);
+
+        // Into the second level: (2 * 101 + 2 * 102) / 4
+        let impact = book.vwap_for_quantity(Side::Buy, dec!(4));
+        assert_eq!(
+            impact,
+            PriceImpact {
+                vwap: Some(dec!(101.5)),
+                worst_price: Some(dec!(102)),
+                fillable: dec!(4),
+                complete: true,
+            }
+        );
+
+        // Thinner than asked: report what there is
+        let impact = book.vwap_for_quantity(Side::Sell, dec!(5));
+        assert_eq!(impact.vwap, Some(dec!(99.5)));
+        assert_eq!(impact.worst_price, Some(dec!(99)));
+        assert_eq!(impact.fillable, dec!(2));
+        assert!(!impact.complete);
+
+        // Nothing to take
+        let impact = OrderBook::new("BTC/USD").vwap_for_quantity(Side::Buy, dec!(1));
+        assert_eq!(impact.vwap, None);
+        assert_eq!(impact.worst_price, None);
+        assert_eq!(impact.fillable, dec!(0));
+        assert!(!impact.complete);
     }
 
     #[test]
-    fn test_add_orders() {
+    fn test_aggregated_depth_buckets_on_multiples() {
         let mut book = OrderBook::new("BTC/USD");
-        
-        book.add_order(Order::new(Side::Buy, dec!(100), dec!(10)));
-        book.add_order(Order::new(Side::Buy, dec!(99), dec!(5)));
-        book.add_order(Order::new(Side::Sell, dec!(101), dec!(8)));
-        
-        assert_eq!(book.best_bid(), Some(dec!(100)));
-        assert_eq!(book.best_ask(), Some(dec!(101)));
-        assert_eq!(book.spread(), Some(dec!(1)));
+        for (side, price, quantity) in [
+            (Side::Buy, dec!(100.00), dec!(1)),
+            (Side::Buy, dec!(100.75), dec!(2)),
+            (Side::Buy, dec!(99.99), dec!(3)),
+            (Side::Buy, dec!(97.50), dec!(4)),
+            (Side::Sell, dec!(101.00), dec!(1)),
+            (Side::Sell, dec!(101.25), dec!(2)),
+            (Side::Sell, dec!(102.00), dec!(3)),
+        ] {
+            book.add_order(Order::new(side, price, quantity));
+        }
+        book.add_order(hidden(Side::Buy, dec!(98.50), dec!(9)));
+
+        // A bid exactly on a boundary stays in its own bucket
+        assert_eq!(
+            book.aggregated_depth(Side::Buy, dec!(1), 10),
+            vec![(dec!(100), dec!(3), 2), (dec!(99), dec!(3), 1), (dec!(97), dec!(4), 1)]
+        );
+        assert_eq!(
+            book.aggregated_depth(Side::Sell, dec!(1), 10),
+            vec![(dec!(101), dec!(1), 1), (dec!(102), dec!(5), 2)]
+        );
+        assert_eq!(
+            book.aggregated_depth(Side::Buy, dec!(0.5), 2),
+            vec![(dec!(100.5), dec!(2), 1), (dec!(100), dec!(1), 1)]
+        );
+        assert_eq!(book.aggregated_depth(Side::Sell, dec!(0.01), 10), book.ask_depth(10));
+    }
+
+    #[test]
+    fn test_mid_and_microprice() {
+        let mut book = OrderBook::new("BTC/USD");
+        book.add_order(Order::new(Side::Buy, dec!(100), dec!(1)));
+        assert_eq!((book.mid_price(), book.microprice()), (None, None));
+
+        book.add_order(Order::new(Side::Sell, dec!(101), dec!(3)));
+        assert_eq!(book.mid_price(), Some(dec!(100.5)));
+        // Thin bid, heavy ask: the microprice leans towards the bid
+        assert_eq!(book.microprice(), Some(dec!(100.25)));
+
+        // Hidden size and hidden-only levels don't count
+        book.add_order(hidden(Side::Buy, dec!(100.5), dec!(5)));
+        book.add_order(hidden(Side::Sell, dec!(101), dec!(5)));
+        assert_eq!(book.microprice(), Some(dec!(100.25)));
+
+        book.add_order(Order::new(Side::Buy, dec!(100), dec!(2)));
+        assert_eq!(book.microprice(), Some(dec!(100.5)));
+        book.add_order(Order::new(Side::Buy, dec!(100), dec!(1)));
+        let microprice = book.microprice().unwrap();
+        assert_eq!(microprice, dec!(100.57142857));
+        assert!(microprice.scale() <= MICROPRICE_SCALE);
+    }
+
+    #[test]
+    fn test_imbalance_over_top_levels() {
+        let mut book = OrderBook::new("BTC/USD");
+        assert_eq!(book.imbalance(5), None);
+
+        book.add_order(Order::new(Side::Buy, dec!(100), dec!(1)));
+        assert_eq!(book.imbalance(5), Some(dec!(1)));
+        book.add_order(Order::new(Side::Sell, dec!(101), dec!(2)));
+        book.add_order(Order::new(Side::Sell, dec!(102), dec!(1)));
+        assert_eq!(book.imbalance(1), Some(dec!(-0.3333)));
+        assert_eq!(book.imbalance(5), Some(dec!(-0.5)));
+
+        // Hidden size is not part of the signal
+        book.add_order(hidden(Side::Buy, dec!(100), dec!(10)));
+        assert_eq!(book.imbalance(5), Some(dec!(-0.5)));
+
+        let mut asks_only = OrderBook::new("BTC/USD");
+        asks_only.add_order(Order::new(Side::Sell, dec!(101), dec!(2)));
+        assert_eq!(asks_only.imbalance(5), Some(dec!(-1)));
+    }
+
+    #[test]
+    fn test_checksum_test_vector() {
+        let mut book = OrderBook::new("BTC/USD");
+        assert_eq!(book.checksum(), 0);
+
+        // Scale doesn't matter: 100.50 and 100.5 are written the same way
+        book.add_order(Order::new(Side::Buy, dec!(100.50), dec!(2.000)));
+        book.add_order(Order::new(Side::Buy, dec!(100), dec!(1)));
+        book.add_order(Order::new(Side::Sell, dec!(101), dec!(0.25)));
+        book.add_order(hidden(Side::Sell, dec!(100.75), dec!(9)));
+        assert_eq!(book.checksum(), CRC32.checksum(b"100.5:2:101:0.25:100:1"));
+        assert_eq!(book.checksum(), 3109731841);
+
+        // Only the best ten levels of each side count
+        let before = book.checksum();
+        book.add_order(Order::new(Side::Buy, dec!(99), dec!(1)));
+        assert_ne!(book.checksum(), before);
+        for i in 1..=8 {
+            book.add_order(Order::new(Side::Buy, dec!(99) - Decimal::from(i), dec!(1)));
+        }
+        let full = book.checksum();
+        book.add_order(Order::new(Side::Buy, dec!(50), dec!(1)));
+        assert_eq!(book.checksum(), full);
+    }
+
+    #[test]
+    fn test_would_cross() {
+        let mut book = OrderBook::new("BTC/USD");
+        assert!(!book.would_cross(Side::Buy, dec!(1000)));
+        assert!(!book.would_cross(Side::Sell, dec!(1)));
+
+        book.add_order(Order::new(Side::Buy, dec!(99), dec!(1)));
+        book.add_order(Order::new(Side::Sell, dec!(101), dec!(1)));
+
+        assert!(book.would_cross(Side::Buy, dec!(101)));
+        assert!(!book.would_cross(Side::Buy, dec!(100)));
+        assert!(book.would_cross(Side::Sell, dec!(99)));
+        assert!(!book.would_cross(Side::Sell, dec!(100)));
+        assert_eq!(book.validate(), Ok(()));
     }
 
     #[test]
@@ -263,12 +3140,13 @@ mod tests {
         
         // Submit a matching buy order
         let buy = Order::new(Side::Buy, dec!(100), dec!(10));
-        let trades = book.match_order(buy);
+        let trades = book.match_order(buy).trades;
         
         assert_eq!(trades.len(), 1);
         assert_eq!(trades[0].quantity, dec!(10));
         assert_eq!(trades[0].price, dec!(100));
         assert!(book.best_ask().is_none()); // Sell order fully filled
+        assert_eq!(book.validate(), Ok(()));
     }
 
     #[test]
@@ -280,7 +3158,7 @@ mod tests {
         
         // Submit a buy order for 5 units
         let buy = Order::new(Side::Buy, dec!(100), dec!(5));
-        let trades = book.match_order(buy);
+        let trades = book.match_order(buy).trades;
         
         assert_eq!(trades.len(), 1);
         assert_eq!(trades[0].quantity, dec!(5));
@@ -288,7 +3166,8 @@ mod tests {
         // 5 units remaining on the sell side
         let depth = book.ask_depth(10);
         assert_eq!(depth.len(), 1);
-        assert_eq!(depth[0], (dec!(100), dec!(5)));
+        assert_eq!(depth[0], (dec!(100), dec!(5), 1));
+        assert_eq!(book.validate(), Ok(()));
     }
 
     #[test]
@@ -305,10 +3184,11 @@ mod tests {
         
         // Buy should match with first (older) order
         let buy = Order::new(Side::Buy, dec!(100), dec!(5));
-        let trades = book.match_order(buy);
+        let trades = book.match_order(buy).trades;
         
         assert_eq!(trades.len(), 1);
         assert_eq!(trades[0].maker_order_id, sell1_id); // First order matched
+        assert_eq!(book.validate(), Ok(()));
     }
 
     #[test]
@@ -320,11 +3200,179 @@ mod tests {
         
         // Buy at 100 should not match
         let buy = Order::new(Side::Buy, dec!(100), dec!(10));
-        let trades = book.match_order(buy);
+        let trades = book.match_order(buy).trades;
         
         assert!(trades.is_empty());
         assert_eq!(book.best_bid(), Some(dec!(100))); // Buy added to book
         assert_eq!(book.best_ask(), Some(dec!(101))); // Sell still there
+        assert_eq!(book.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_market_order_sweeps_and_never_rests() {
+        let mut book = OrderBook::new("BTC/USD");
+
+        book.add_order(Order::new(Side::Sell, dec!(100), dec!(5)));
+        book.add_order(Order::new(Side::Sell, dec!(150), dec!(5)));
+
+        let mut buy = Order::new(Side::Buy, dec!(0), dec!(20));
+        buy.order_type = OrderType::Market;
+        let trades = book.match_order(buy).trades;
+
+        assert_eq!(trades.len(), 2);
+        assert_eq!(trades[1].price, dec!(150));
+        assert!(book.best_ask().is_none());
+        assert!(book.best_bid().is_none()); // Unfilled 10 discarded
+        assert_eq!(book.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_expire_orders_removes_from_level() {
+        let mut book = OrderBook::new("BTC/USD");
+        let now = Utc::now();
+
+        let keep = Order::new(Side::Buy, dec!(100), dec!(3));
+        let mut expiring = Order::new(Side::Buy, dec!(100), dec!(2));
+        expiring.expires_at = Some(now);
+        let mut later = Order::new(Side::Buy, dec!(99), dec!(1));
+        later.expires_at = Some(now + chrono::Duration::seconds(1));
+        let expiring_id = expiring.id;
+
+        book.add_order(expiring);
+        book.add_order(keep);
+        book.add_order(later);
+
+        let expired = book.expire_orders(now);
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].id, expiring_id);
+        assert_eq!(book.bid_depth(10), vec![(dec!(100), dec!(3), 1), (dec!(99), dec!(1), 1)]);
+
+        // Expiring the last order at a level removes the level
+        let expired = book.expire_orders(now + chrono::Duration::seconds(1));
+        assert_eq!(expired.len(), 1);
+        assert_eq!(book.bid_depth(10), vec![(dec!(100), dec!(3), 1)]);
+        assert_eq!(book.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_filled_order_leaves_expiry_index() {
+        let mut book = OrderBook::new("BTC/USD");
+        let now = Utc::now();
+
+        let mut sell = Order::new(Side::Sell, dec!(100), dec!(1));
+        sell.expires_at = Some(now);
+        book.add_order(sell);
+        book.match_order(Order::new(Side::Buy, dec!(100), dec!(1)));
+
+        assert!(book.index.expiries.is_empty());
+        assert!(book.expire_orders(now).is_empty());
+        assert_eq!(book.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_ioc_remainder_does_not_rest() {
+        let mut book = OrderBook::new("BTC/USD");
+        book.add_order(Order::new(Side::Sell, dec!(100), dec!(2)));
+
+        let mut buy = Order::new(Side::Buy, dec!(100), dec!(5));
+        buy.time_in_force = TimeInForce::Ioc;
+        let trades = book.match_order(buy).trades;
+
+        assert_eq!(trades.len(), 1);
+        assert_eq!(book.order_count(), 0);
+        assert_eq!(book.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_match_result_summarises_fills() {
+        let mut book = OrderBook::new("BTC/USD");
+        book.add_order(Order::new(Side::Sell, dec!(100), dec!(1)));
+        book.add_order(Order::new(Side::Sell, dec!(102), dec!(3)));
+
+        let buy = Order::new(Side::Buy, dec!(102), dec!(6));
+        let buy_id = buy.id;
+        let result = book.match_order(buy);
+
+        assert_eq!(result.taker_order_id, buy_id);
+        assert_eq!(result.trades.len(), 2);
+        assert_eq!(result.filled_quantity, dec!(4));
+        // (100 * 1 + 102 * 3) / 4, not the mean of the two prices
+        assert_eq!(result.average_price, Some(dec!(101.5)));
+        assert_eq!(result.remaining_quantity, dec!(2));
+        assert!(result.rested);
+        assert_eq!(book.get_order(buy_id).unwrap().remaining_quantity, dec!(2));
+
+        // Nothing to trade against: no average, the whole order rests
+        let result = book.match_order(Order::new(Side::Buy, dec!(99), dec!(1)));
+        assert!(result.trades.is_empty());
+        assert_eq!(result.filled_quantity, dec!(0));
+        assert_eq!(result.average_price, None);
+        assert_eq!(result.remaining_quantity, dec!(1));
+        assert!(result.rested);
+    }
+
+    #[test]
+    fn test_match_into_reuses_a_cleared_outcome() {
+        let mut book = OrderBook::new("BTC/USD");
+        book.add_order(Order::new(Side::Sell, dec!(100), dec!(1)));
+        book.add_order(Order::new(Side::Sell, dec!(101), dec!(1)));
+        let mut outcome = MatchOutcome::default();
+
+        book.match_order_into(Order::new(Side::Buy, dec!(101), dec!(2)), &mut outcome);
+        assert_eq!(outcome.trades.len(), 2);
+        assert_eq!(outcome.fills.len(), 2);
+        let capacity = outcome.trades.capacity();
+
+        // Nothing left to cross: the previous order's trades are gone, the space is not
+        book.match_order_into(Order::new(Side::Buy, dec!(101), dec!(1)), &mut outcome);
+        assert!(outcome.trades.is_empty());
+        assert!(outcome.fills.is_empty());
+        assert_eq!(outcome.trades.capacity(), capacity);
+        assert_eq!(book.bid_depth(10), vec![(dec!(101), dec!(1), 1)]);
+    }
+
+    #[test]
+    fn test_match_result_for_discarded_remainders() {
+        let mut book = OrderBook::new("BTC/USD");
+        book.add_order(Order::new(Side::Sell, dec!(100.10), dec!(1)));
+        book.add_order(Order::new(Side::Sell, dec!(100.25), dec!(2)));
+
+        let mut buy = Order::new(Side::Buy, dec!(101), dec!(5));
+        buy.time_in_force = TimeInForce::Ioc;
+        let result = book.match_order(buy);
+
+        assert_eq!(result.filled_quantity, dec!(3));
+        assert_eq!(result.average_price, Some(dec!(100.2)));
+        assert_eq!(result.remaining_quantity, dec!(2));
+        assert!(!result.rested);
+
+        // Quantity removed by self-trade prevention is not left open
+        let mut sell = Order::new(Side::Sell, dec!(100), dec!(2));
+        sell.owner_id = Some("alice".to_string());
+        book.add_order(sell);
+        let mut buy = Order::new(Side::Buy, dec!(100), dec!(5));
+        buy.owner_id = Some("alice".to_string());
+        buy.stp_policy = Some(StpPolicy::DecrementBoth);
+        let result = book.match_order(buy);
+
+        assert_eq!(result.filled_quantity, dec!(0));
+        assert_eq!(result.remaining_quantity, dec!(3));
+        assert!(result.rested);
+        assert_eq!(book.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_fillable_quantity_respects_limit() {
+        let mut book = OrderBook::new("BTC/USD");
+        book.add_order(Order::new(Side::Sell, dec!(100), dec!(2)));
+        book.add_order(Order::new(Side::Sell, dec!(101), dec!(3)));
+        book.add_order(Order::new(Side::Sell, dec!(102), dec!(4)));
+
+        assert_eq!(book.fillable_quantity(&Order::new(Side::Buy, dec!(101), dec!(10))), dec!(5));
+        assert_eq!(book.fillable_quantity(&Order::new(Side::Buy, dec!(102), dec!(6))), dec!(6));
+        assert_eq!(book.fillable_quantity(&Order::new(Side::Buy, dec!(99), dec!(1))), dec!(0));
+        assert_eq!(book.fillable_quantity(&Order::new(Side::Sell, dec!(1), dec!(1))), dec!(0));
+        assert_eq!(book.validate(), Ok(()));
     }
 
     #[test]
@@ -338,12 +3386,13 @@ mod tests {
         
         // Buy order that sweeps through multiple levels
         let buy = Order::new(Side::Buy, dec!(102), dec!(12));
-        let trades = book.match_order(buy);
+        let trades = book.match_order(buy).trades;
         
         assert_eq!(trades.len(), 3);
         assert_eq!(trades[0].price, dec!(100)); // Best price first
         assert_eq!(trades[1].price, dec!(101));
         assert_eq!(trades[2].price, dec!(102));
         assert_eq!(trades[2].quantity, dec!(2)); // Partial fill at last level
+        assert_eq!(book.validate(), Ok(()));
     }
 }
@@ -0,0 +1,241 @@
+//! Property tests for the order book's matching invariants.
+//!
+//! Each case replays a random sequence of submissions (limit, hidden, IOC
+//! and market, both sides, around one price), cancels and size reductions
+//! against a fresh `OrderBook`, and checks after every step that:
+//!
+//! - every order's fills, resting remainder and cancelled or discarded
+//!   quantity add up to what was submitted;
+//! - every trade executes at the maker's price, within the taker's limit;
+//! - the book never crosses and passes `OrderBook::validate`, so level
+//!   totals equal the sum of their orders' remainders;
+//! - under FIFO, no order that traded had an earlier order resting at the
+//!   same price still waiting behind it.
+//!
+//! A failing case is shrunk to a minimal sequence of steps.
+
+use crate::engine::order::{Order, OrderId, OrderType, Side, TimeInForce, Trade};
+use crate::engine::order_book::{MatchingAlgorithm, OrderBook};
+use proptest::prelude::*;
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy)]
+enum Kind {
+    Limit,
+    Hidden,
+    Ioc,
+    Market,
+}
+
+#[derive(Debug, Clone)]
+enum Step {
+    /// A new order priced `price` tenths (99.0 to 101.0) for `quantity` tenths
+    Submit {
+        side: Side,
+        price: i64,
+        quantity: i64,
+        kind: Kind,
+    },
+    /// Cancel an earlier order, picked by `pick` modulo the number submitted
+    Cancel { pick: usize },
+    /// Cut an earlier order's open quantity to `quantity` tenths, if that is
+    /// a reduction
+    Reduce { pick: usize, quantity: i64 },
+}
+
+fn step() -> impl Strategy<Value = Step> {
+    let side = prop_oneof![Just(Side::Buy), Just(Side::Sell)];
+    let kind = prop_oneof![
+        6 => Just(Kind::Limit),
+        2 => Just(Kind::Hidden),
+        1 => Just(Kind::Ioc),
+        1 => Just(Kind::Market),
+    ];
+    prop_oneof![
+        6 => (side, 990..=1010i64, 1..=50i64, kind).prop_map(|(side, price, quantity, kind)| Step::Submit {
+            side,
+            price,
+            quantity,
+            kind,
+        }),
+        2 => any::<usize>().prop_map(|pick| Step::Cancel { pick }),
+        1 => (any::<usize>(), 1..=50i64).prop_map(|(pick, quantity)| Step::Reduce { pick, quantity }),
+    ]
+}
+
+/// What the test knows about one submitted order
+struct Submitted {
+    id: OrderId,
+    side: Side,
+    price: Decimal,
+    market: bool,
+    quantity: Decimal,
+    /// Traded, as taker or maker
+    filled: Decimal,
+    /// Cancelled, reduced away or discarded instead of resting
+    closed: Decimal,
+}
+
+/// Every order submitted so far, in arrival order
+#[derive(Default)]
+struct Ledger {
+    orders: Vec<Submitted>,
+    arrival: HashMap<OrderId, usize>,
+}
+
+impl Ledger {
+    fn pick(&mut self, pick: usize) -> Option<&mut Submitted> {
+        let len = self.orders.len();
+        (len > 0).then(|| &mut self.orders[pick % len])
+    }
+
+    fn resting(&self, book: &OrderBook, order: &Submitted) -> Decimal {
+        book.get_order(order.id).map_or(Decimal::ZERO, |o| o.remaining_quantity)
+    }
+}
+
+fn apply(book: &mut OrderBook, ledger: &mut Ledger, step: &Step) -> Result<Vec<Trade>, TestCaseError> {
+    match *step {
+        Step::Submit {
+            side,
+            price,
+            quantity,
+            kind,
+        } => {
+            let quantity = Decimal::new(quantity, 1);
+            let mut order = Order::new(side, Decimal::new(price, 1), quantity);
+            match kind {
+                Kind::Limit => {}
+                Kind::Hidden => order.hidden = true,
+                Kind::Ioc => order.time_in_force = TimeInForce::Ioc,
+                Kind::Market => {
+                    order.order_type = OrderType::Market;
+                    order.price = Decimal::ZERO;
+                }
+            }
+            ledger.arrival.insert(order.id, ledger.orders.len());
+            ledger.orders.push(Submitted {
+                id: order.id,
+                side,
+                price: order.price,
+                market: order.order_type == OrderType::Market,
+                quantity,
+                filled: Decimal::ZERO,
+                closed: Decimal::ZERO,
+            });
+
+            let result = book.match_order(order);
+            prop_assert_eq!(result.filled_quantity + result.remaining_quantity, quantity);
+            if !result.rested {
+                ledger.orders.last_mut().unwrap().closed = result.remaining_quantity;
+            }
+            Ok(result.trades)
+        }
+        Step::Cancel { pick } => {
+            if let Some(order) = ledger.pick(pick) {
+                if let Some(cancelled) = book.cancel_order(order.id) {
+                    order.closed += cancelled.remaining_quantity;
+                }
+            }
+            Ok(Vec::new())
+        }
+        Step::Reduce { pick, quantity } => {
+            let quantity = Decimal::new(quantity, 1);
+            let Some(id) = ledger.pick(pick).map(|o| o.id) else {
+                return Ok(Vec::new());
+            };
+            let Some((total, before)) = book.get_order(id).map(|o| (o.quantity, o.remaining_quantity)) else {
+                return Ok(Vec::new());
+            };
+            let filled = total - before;
+            match book.reduce_order(id, filled + quantity) {
+                Ok(reduced) => {
+                    prop_assert_eq!(reduced.remaining_quantity, quantity);
+                    ledger.pick(pick).unwrap().closed += before - quantity;
+                }
+                Err(_) => prop_assert!(quantity > before, "refused to reduce {} to {}", before, quantity),
+            }
+            Ok(Vec::new())
+        }
+    }
+}
+
+fn check(book: &OrderBook, ledger: &mut Ledger, trades: &[Trade], fifo: bool) -> Result<(), TestCaseError> {
+    for trade in trades {
+        let maker = &ledger.orders[ledger.arrival[&trade.maker_order_id]];
+        prop_assert_eq!(trade.price, maker.price, "trade away from the maker's price");
+        let taker = &ledger.orders[ledger.arrival[&trade.taker_order_id]];
+        if !taker.market {
+            match taker.side {
+                Side::Buy => prop_assert!(trade.price <= taker.price, "buy traded above its limit"),
+                Side::Sell => prop_assert!(trade.price >= taker.price, "sell traded below its limit"),
+            }
+        }
+
+        if fifo {
+            let (side, price, arrived) = (maker.side, maker.price, ledger.arrival[&trade.maker_order_id]);
+            let skipped = ledger.orders[..arrived]
+                .iter()
+                .find(|o| o.side == side && o.price == price && !o.market && book.get_order(o.id).is_some());
+            prop_assert!(skipped.is_none(), "a later order at {} traded ahead of an earlier one", price);
+        }
+
+        for id in [trade.maker_order_id, trade.taker_order_id] {
+            let arrived = ledger.arrival[&id];
+            ledger.orders[arrived].filled += trade.quantity;
+        }
+    }
+
+    for order in &ledger.orders {
+        let resting = ledger.resting(book, order);
+        prop_assert_eq!(
+            order.filled + order.closed + resting,
+            order.quantity,
+            "quantity of {} not conserved",
+            order.id
+        );
+    }
+
+    if let (Some(bid), Some(ask)) = (book.best_bid(), book.best_ask()) {
+        prop_assert!(bid < ask, "book crossed at {} / {}", bid, ask);
+    }
+    prop_assert_eq!(book.validate(), Ok(()));
+
+    // The levels hold exactly the resting orders' remainders
+    for (side, levels) in [(Side::Buy, book.iter_bids().collect::<Vec<_>>()), (Side::Sell, book.iter_asks().collect())] {
+        let on_levels: Decimal = levels.iter().map(|l| l.total_quantity).sum();
+        let resting: Decimal = ledger
+            .orders
+            .iter()
+            .filter(|o| o.side == side)
+            .map(|o| ledger.resting(book, o))
+            .sum();
+        prop_assert_eq!(on_levels, resting);
+    }
+    Ok(())
+}
+
+fn run(algorithm: MatchingAlgorithm, steps: &[Step]) -> Result<(), TestCaseError> {
+    let mut book = OrderBook::new("BTC/USD").with_algorithm(algorithm);
+    let mut ledger = Ledger::default();
+    for step in steps {
+        let trades = apply(&mut book, &mut ledger, step)?;
+        check(&book, &mut ledger, &trades, algorithm == MatchingAlgorithm::Fifo)?;
+    }
+    Ok(())
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(256))]
+
+    #[test]
+    fn fifo_matching_keeps_its_invariants(steps in prop::collection::vec(step(), 1..200)) {
+        run(MatchingAlgorithm::Fifo, &steps)?;
+    }
+
+    #[test]
+    fn pro_rata_matching_keeps_its_invariants(steps in prop::collection::vec(step(), 1..200)) {
+        run(MatchingAlgorithm::ProRata, &steps)?;
+    }
+}
@@ -0,0 +1,228 @@
+//! Limits on how fast each owner may send orders to the engine.
+//!
+//! Every limited owner has a token bucket holding up to `burst` orders and
+//! refilling at `per_second`: an order takes a token, and one arriving at an
+//! empty bucket is rejected as `RATE_LIMITED` without touching the book.
+//! Owners take their own limit if one is set, else the default; orders with
+//! no owner are never limited. Buckets are timed by the engine's clock, so a
+//! replayed log limits the same orders, and start full after a restart.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// Environment variable holding the default limit, as `per_second` or
+/// `per_second/burst`; unset leaves owners unlimited
+pub const RATE_LIMIT_VAR: &str = "CLOB_RATE_LIMIT";
+
+/// Owners whose buckets fit before the map first grows
+const PRESIZED_OWNERS: usize = 1024;
+
+/// How fast one owner may send orders
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RateLimit {
+    /// Orders a second the bucket refills by
+    pub per_second: u32,
+    /// Most orders the bucket holds, sent at once after a quiet spell
+    pub burst: u32,
+}
+
+impl RateLimit {
+    /// `per_second` orders a second, and a second's worth at once
+    pub fn per_second(per_second: u32) -> Self {
+        Self {
+            per_second,
+            burst: per_second,
+        }
+    }
+
+    pub fn with_burst(mut self, burst: u32) -> Self {
+        self.burst = burst;
+        self
+    }
+}
+
+/// A `CLOB_RATE_LIMIT` value that is not `per_second` or `per_second/burst`
+#[derive(Debug, thiserror::Error)]
+#[error("invalid rate limit {0:?}: expected orders per second, optionally followed by /burst")]
+pub struct InvalidRateLimit(String);
+
+impl FromStr for RateLimit {
+    type Err = InvalidRateLimit;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidRateLimit(s.to_string());
+        let (per_second, burst) = match s.split_once('/') {
+            Some((per_second, burst)) => (per_second, Some(burst)),
+            None => (s, None),
+        };
+        let per_second = per_second.trim().parse().map_err(|_| invalid())?;
+        let limit = match burst {
+            Some(burst) => RateLimit::per_second(per_second).with_burst(burst.trim().parse().map_err(|_| invalid())?),
+            None => RateLimit::per_second(per_second),
+        };
+        if limit.per_second == 0 || limit.burst == 0 {
+            return Err(invalid());
+        }
+        Ok(limit)
+    }
+}
+
+/// The limits an engine applies, as saved in its snapshots
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RateLimits {
+    /// Limit for owners without one of their own; `None` leaves them unlimited
+    pub default: Option<RateLimit>,
+    /// Limits set for single owners
+    pub owners: HashMap<String, RateLimit>,
+}
+
+impl RateLimits {
+    /// The limit `owner_id` is held to, if any
+    pub fn limit_for(&self, owner_id: &str) -> Option<RateLimit> {
+        self.owners.get(owner_id).copied().or(self.default)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.default.is_none() && self.owners.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    refilled_at: DateTime<Utc>,
+}
+
+/// Token buckets for every owner that has sent a limited order
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    limits: RateLimits,
+    buckets: HashMap<String, Bucket>,
+}
+
+impl RateLimiter {
+    pub fn new(limits: RateLimits) -> Self {
+        Self {
+            limits,
+            buckets: HashMap::with_capacity(PRESIZED_OWNERS),
+        }
+    }
+
+    pub fn limits(&self) -> &RateLimits {
+        &self.limits
+    }
+
+    /// Set `owner_id`'s limit, or the default when `None`; a `None` limit
+    /// removes it. Buckets it governs start again full under the new limit.
+    pub fn set(&mut self, owner_id: Option<String>, limit: Option<RateLimit>) {
+        match owner_id {
+            Some(owner_id) => {
+                self.buckets.remove(&owner_id);
+                match limit {
+                    Some(limit) => self.limits.owners.insert(owner_id, limit),
+                    None => self.limits.owners.remove(&owner_id),
+                };
+            }
+            None => {
+                self.limits.default = limit;
+                let owners = &self.limits.owners;
+                self.buckets.retain(|owner_id, _| owners.contains_key(owner_id));
+            }
+        }
+    }
+
+    /// Take `orders` tokens from `owner_id`'s bucket at `now`; `Err` with
+    /// its limit when the bucket holds fewer, taking none
+    pub fn try_take(&mut self, owner_id: &str, orders: u32, now: DateTime<Utc>) -> Result<(), RateLimit> {
+        if self.limits.is_empty() {
+            return Ok(());
+        }
+        let Some(limit) = self.limits.limit_for(owner_id) else {
+            return Ok(());
+        };
+        // Only an owner's first order allocates, for its key
+        if !self.buckets.contains_key(owner_id) {
+            let full = Bucket {
+                tokens: f64::from(limit.burst),
+                refilled_at: now,
+            };
+            self.buckets.insert(owner_id.to_string(), full);
+        }
+        let bucket = self.buckets.get_mut(owner_id).expect("bucket was just added");
+
+        if now > bucket.refilled_at {
+            let elapsed = (now - bucket.refilled_at).num_microseconds().unwrap_or(i64::MAX) as f64 / 1e6;
+            bucket.tokens = (bucket.tokens + elapsed * f64::from(limit.per_second)).min(f64::from(limit.burst));
+            bucket.refilled_at = now;
+        }
+        let wanted = f64::from(orders);
+        if bucket.tokens < wanted {
+            return Err(limit);
+        }
+        bucket.tokens -= wanted;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+
+    #[test]
+    fn test_bucket_holds_a_burst_and_refills_at_the_rate() {
+        let start = Utc.with_ymd_and_hms(2030, 1, 1, 0, 0, 0).unwrap();
+        let limit = RateLimit::per_second(10).with_burst(3);
+        let mut limiter = RateLimiter::new(RateLimits {
+            default: Some(limit),
+            ..Default::default()
+        });
+
+        for _ in 0..3 {
+            assert!(limiter.try_take("alice", 1, start).is_ok());
+        }
+        assert_eq!(limiter.try_take("alice", 1, start), Err(limit));
+        // Each owner has a bucket of its own
+        assert!(limiter.try_take("bob", 1, start).is_ok());
+
+        // A token every 100ms, never more than the burst
+        assert!(limiter.try_take("alice", 1, start + Duration::milliseconds(50)).is_err());
+        assert!(limiter.try_take("alice", 1, start + Duration::milliseconds(100)).is_ok());
+        assert!(limiter.try_take("alice", 1, start + Duration::milliseconds(150)).is_err());
+        let later = start + Duration::seconds(60);
+        assert!(limiter.try_take("alice", 3, later).is_ok());
+        assert!(limiter.try_take("alice", 1, later).is_err());
+    }
+
+    #[test]
+    fn test_owner_limits_override_the_default() {
+        let now = Utc.with_ymd_and_hms(2030, 1, 1, 0, 0, 0).unwrap();
+        let mut limiter = RateLimiter::new(RateLimits::default());
+        assert!(limiter.try_take("alice", 1000, now).is_ok());
+
+        limiter.set(None, Some(RateLimit::per_second(1)));
+        limiter.set(Some("mm".to_string()), Some(RateLimit::per_second(100)));
+        assert!(limiter.try_take("alice", 1, now).is_ok());
+        assert!(limiter.try_take("alice", 1, now).is_err());
+        assert!(limiter.try_take("mm", 100, now).is_ok());
+
+        // Lifting the default leaves the owner's own limit in place
+        limiter.set(None, None);
+        assert!(limiter.try_take("alice", 1000, now).is_ok());
+        assert!(limiter.try_take("mm", 1, now).is_err());
+        limiter.set(Some("mm".to_string()), None);
+        assert!(limiter.try_take("mm", 1000, now).is_ok());
+        assert_eq!(limiter.limits(), &RateLimits::default());
+    }
+
+    #[test]
+    fn test_parses_rate_and_burst() {
+        assert_eq!("50".parse::<RateLimit>().unwrap(), RateLimit::per_second(50));
+        assert_eq!("50/200".parse::<RateLimit>().unwrap(), RateLimit::per_second(50).with_burst(200));
+        assert!("0".parse::<RateLimit>().is_err());
+        assert!("50/".parse::<RateLimit>().is_err());
+        assert!("fast".parse::<RateLimit>().is_err());
+    }
+}
@@ -0,0 +1,256 @@
+//! Per-symbol configurations loaded at startup.
+//!
+//! Built-in defaults come first, then an optional TOML file named by
+//! `CLOB_SYMBOLS_FILE`, then `CLOB_<SYMBOL>__<FIELD>` environment variables
+//! (e.g. `CLOB_BTC_USD__TICK_SIZE=0.05`). Later sources override earlier ones
+//! field by field.
+
+use crate::engine::symbol::SymbolConfig;
+use rust_decimal::Decimal;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+/// Environment variable naming the optional TOML file
+pub const SYMBOLS_FILE_VAR: &str = "CLOB_SYMBOLS_FILE";
+
+/// Prefix of per-field environment overrides
+const ENV_PREFIX: &str = "CLOB_";
+
+/// Why the symbol configuration could not be loaded
+#[derive(Debug, thiserror::Error)]
+pub enum RegistryError {
+    #[error("cannot read {path}: {source}")]
+    Io { path: String, source: std::io::Error },
+    #[error("invalid TOML: {0}")]
+    Toml(String),
+    #[error("unknown symbol {0}")]
+    UnknownSymbol(String),
+    #[error("unknown field {field} for {symbol}")]
+    UnknownField { symbol: String, field: String },
+    #[error("invalid value {value:?} for {symbol}.{field}")]
+    InvalidValue { symbol: String, field: String, value: String },
+}
+
+/// Trading rules for every configured symbol, keyed by symbol name
+#[derive(Debug, Clone, Default)]
+pub struct SymbolRegistry {
+    symbols: BTreeMap<String, SymbolConfig>,
+}
+
+impl SymbolRegistry {
+    /// The built-in symbols
+    pub fn defaults() -> Self {
+        let mut registry = Self::default();
+        registry.insert(SymbolConfig {
+            price_scale: Some(2),
+            quantity_scale: Some(4),
+            tick_size: Some(Decimal::new(1, 2)), // 0.01
+            lot_size: Some(Decimal::new(1, 4)),  // 0.0001
+            ..SymbolConfig::new("BTC/USD")
+        });
+        registry
+    }
+
+    /// Defaults, then the TOML file and environment overrides, if any
+    pub fn load() -> Result<Self, RegistryError> {
+        let mut registry = Self::defaults();
+        if let Ok(path) = std::env::var(SYMBOLS_FILE_VAR) {
+            let source = std::fs::read_to_string(&path).map_err(|source| RegistryError::Io { path, source })?;
+            registry.apply_toml(&source)?;
+        }
+        registry.apply_env(std::env::vars())?;
+        Ok(registry)
+    }
+
+    /// Add or replace a symbol's configuration
+    pub fn insert(&mut self, config: SymbolConfig) {
+        self.symbols.insert(config.symbol.clone(), config);
+    }
+
+    /// Configuration for one symbol
+    pub fn get(&self, symbol: &str) -> Option<&SymbolConfig> {
+        self.symbols.get(symbol)
+    }
+
+    /// Every configuration, in symbol order
+    pub fn iter(&self) -> impl Iterator<Item = &SymbolConfig> {
+        self.symbols.values()
+    }
+
+    /// Apply a TOML document of `[symbols."BTC/USD"]` tables. Symbols not
+    /// yet known are added; fields left out keep their current value.
+    ///
+    /// ```toml
+    /// [symbols."ETH/USD"]
+    /// tick_size = "0.05"
+    /// lot_size = "0.001"
+    /// price_scale = 2
+    /// taker_fee_bps = "2.5"
+    /// ```
+    pub fn apply_toml(&mut self, source: &str) -> Result<(), RegistryError> {
+        let document = source
+            .parse::<toml_edit::DocumentMut>()
+            .map_err(|e| RegistryError::Toml(e.to_string()))?;
+        let Some(item) = document.get("symbols") else {
+            return Ok(());
+        };
+        let symbols = item
+            .as_table_like()
+            .ok_or_else(|| RegistryError::Toml("`symbols` must be a table".to_string()))?;
+
+        for (symbol, table) in symbols.iter() {
+            let table = table
+                .as_table_like()
+                .ok_or_else(|| RegistryError::Toml(format!("`symbols.{symbol}` must be a table")))?;
+            let config = self
+                .symbols
+                .entry(symbol.to_string())
+                .or_insert_with(|| SymbolConfig::new(symbol));
+            for (field, value) in table.iter() {
+                let value = match value.as_value() {
+                    Some(toml_edit::Value::String(s)) => s.value().clone(),
+                    Some(toml_edit::Value::Integer(i)) => i.value().to_string(),
+                    Some(toml_edit::Value::Float(f)) => f.value().to_string(),
+                    _ => format!("<{}>", value.type_name()),
+                };
+                set_field(config, field, &value)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply `CLOB_<SYMBOL>__<FIELD>` overrides, where `<SYMBOL>` is the
+    /// symbol name upper-cased with every other character replaced by `_`.
+    /// Only symbols already configured can be overridden.
+    pub fn apply_env(&mut self, vars: impl IntoIterator<Item = (String, String)>) -> Result<(), RegistryError> {
+        for (name, value) in vars {
+            let Some((symbol_key, field)) = name.strip_prefix(ENV_PREFIX).and_then(|rest| rest.split_once("__"))
+            else {
+                continue;
+            };
+            let config = self
+                .symbols
+                .values_mut()
+                .find(|c| env_key(&c.symbol) == symbol_key)
+                .ok_or_else(|| RegistryError::UnknownSymbol(symbol_key.to_string()))?;
+            set_field(config, &field.to_lowercase(), &value)?;
+        }
+        Ok(())
+    }
+}
+
+/// Environment form of a symbol name: "BTC/USD" becomes "BTC_USD"
+fn env_key(symbol: &str) -> String {
+    symbol
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+        .collect()
+}
+
+/// Set one named field from its text form
+fn set_field(config: &mut SymbolConfig, field: &str, value: &str) -> Result<(), RegistryError> {
+    let invalid = || RegistryError::InvalidValue {
+        symbol: config.symbol.clone(),
+        field: field.to_string(),
+        value: value.to_string(),
+    };
+    let decimal = || {
+        Decimal::from_str(value.trim())
+            .ok()
+            .filter(|d| !d.is_sign_negative())
+            .ok_or_else(invalid)
+    };
+    let scale = || value.trim().parse::<u32>().map_err(|_| invalid());
+
+    match field {
+        "tick_size" => config.tick_size = Some(decimal()?),
+        "lot_size" => config.lot_size = Some(decimal()?),
+        "price_scale" => config.price_scale = Some(scale()?),
+        "quantity_scale" => config.quantity_scale = Some(scale()?),
+        "min_quantity" => config.min_quantity = Some(decimal()?),
+        "min_notional" => config.min_notional = Some(decimal()?),
+        "price_band_pct" => config.price_band_pct = Some(decimal()?),
+        "max_quantity" => config.max_quantity = Some(decimal()?),
+        "max_notional" => config.max_notional = Some(decimal()?),
+        "maker_fee_bps" => config.maker_fee_bps = decimal()?,
+        "taker_fee_bps" => config.taker_fee_bps = decimal()?,
+        _ => {
+            return Err(RegistryError::UnknownField {
+                symbol: config.symbol.clone(),
+                field: field.to_string(),
+            })
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn vars(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn test_toml_adds_symbols_and_overrides_fields() {
+        let mut registry = SymbolRegistry::defaults();
+        registry
+            .apply_toml(
+                r#"
+                [symbols."BTC/USD"]
+                tick_size = "0.5"
+                taker_fee_bps = 2.5
+
+                [symbols."ETH/USD"]
+                lot_size = "0.001"
+                price_scale = 2
+                "#,
+            )
+            .unwrap();
+
+        let btc = registry.get("BTC/USD").unwrap();
+        assert_eq!(btc.tick_size, Some(dec!(0.5)));
+        assert_eq!(btc.lot_size, Some(dec!(0.0001)));
+        assert_eq!(btc.taker_fee_bps, dec!(2.5));
+        let eth = registry.get("ETH/USD").unwrap();
+        assert_eq!((eth.lot_size, eth.price_scale, eth.tick_size), (Some(dec!(0.001)), Some(2), None));
+        assert_eq!(registry.iter().count(), 2);
+    }
+
+    #[test]
+    fn test_env_overrides_known_symbols() {
+        let mut registry = SymbolRegistry::defaults();
+        registry
+            .apply_env(vars(&[
+                ("CLOB_BTC_USD__MIN_NOTIONAL", "10"),
+                ("CLOB_BTC_USD__quantity_scale", "6"),
+                ("CLOB_LOG_LEVEL", "debug"),
+                ("PATH", "/usr/bin"),
+            ]))
+            .unwrap();
+
+        let btc = registry.get("BTC/USD").unwrap();
+        assert_eq!(btc.min_notional, Some(dec!(10)));
+        assert_eq!(btc.quantity_scale, Some(6));
+    }
+
+    #[test]
+    fn test_bad_overrides_are_errors() {
+        let mut registry = SymbolRegistry::defaults();
+        assert!(matches!(
+            registry.apply_env(vars(&[("CLOB_DOGE_USD__TICK_SIZE", "1")])),
+            Err(RegistryError::UnknownSymbol(_))
+        ));
+        assert!(matches!(
+            registry.apply_env(vars(&[("CLOB_BTC_USD__TICK", "1")])),
+            Err(RegistryError::UnknownField { .. })
+        ));
+        let err = registry
+            .apply_toml("[symbols.\"BTC/USD\"]\nlot_size = \"-1\"")
+            .unwrap_err();
+        assert_eq!(err.to_string(), "invalid value \"-1\" for BTC/USD.lot_size");
+        assert!(matches!(registry.apply_toml("symbols = ["), Err(RegistryError::Toml(_))));
+    }
+}
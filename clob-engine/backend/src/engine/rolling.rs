@@ -0,0 +1,197 @@
+//! Trading over the last 24 hours, for the ticker and statistics.
+//!
+//! Trades are summed into one bucket per minute of engine time, so the
+//! window costs at most 1440 buckets however busy the book, and moves on a
+//! minute at a time. Busted trades are taken back out of their bucket's
+//! volume and count; the bucket's prices are left as they were printed.
+//!
+//! The window is not saved in snapshots. An engine given the journaled
+//! trades of the last day on startup, with `EngineBuilder::trade_history`,
+//! carries on with the same window it had before the restart.
+
+use crate::engine::order::Trade;
+use chrono::{DateTime, Duration, Utc};
+use rust_decimal::Decimal;
+use std::collections::VecDeque;
+use uuid::Uuid;
+
+/// Minutes the window covers
+const WINDOW_MINUTES: i64 = 24 * 60;
+
+/// How long the window is
+pub fn window() -> Duration {
+    Duration::minutes(WINDOW_MINUTES)
+}
+
+/// A trade as read back from the journal
+#[derive(Debug, Clone, PartialEq)]
+pub struct PastTrade {
+    /// The trade's UUID, as journaled
+    pub id: Uuid,
+    pub price: Decimal,
+    pub quantity: Decimal,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// One minute's trading
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Bucket {
+    /// Minutes since the Unix epoch
+    minute: i64,
+    open: Decimal,
+    high: Decimal,
+    low: Decimal,
+    close: Decimal,
+    volume: Decimal,
+    notional: Decimal,
+    count: u64,
+}
+
+/// Trading over the window, as of one moment
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize)]
+pub struct WindowSummary {
+    /// First price in the window; `None` without trades
+    pub open: Option<Decimal>,
+    pub high: Option<Decimal>,
+    pub low: Option<Decimal>,
+    /// Most recent price
+    pub last: Option<Decimal>,
+    /// Base quantity traded
+    pub volume: Decimal,
+    /// Quote value traded, price times quantity
+    pub notional: Decimal,
+    pub count: u64,
+}
+
+/// Per-minute trading over the last 24 hours
+#[derive(Debug, Clone, Default)]
+pub struct RollingWindow {
+    /// Oldest minute first
+    buckets: VecDeque<Bucket>,
+}
+
+impl RollingWindow {
+    /// Add a trade printed at `timestamp`
+    pub fn record(&mut self, price: Decimal, quantity: Decimal, timestamp: DateTime<Utc>) {
+        let minute = minute_of(timestamp);
+        self.prune(minute);
+        let notional = price * quantity;
+        match self.position(minute) {
+            Ok(index) => {
+                let latest = index == self.buckets.len() - 1;
+                let bucket = &mut self.buckets[index];
+                bucket.high = bucket.high.max(price);
+                bucket.low = bucket.low.min(price);
+                // Trades arrive in time order, except history loaded behind a live print
+                if latest {
+                    bucket.close = price;
+                }
+                bucket.volume += quantity;
+                bucket.notional += notional;
+                bucket.count += 1;
+            }
+            // Older than the window
+            Err(0) if self.buckets.front().is_some_and(|front| front.minute - minute >= WINDOW_MINUTES) => {}
+            Err(index) => self.buckets.insert(
+                index,
+                Bucket {
+                    minute,
+                    open: price,
+                    high: price,
+                    low: price,
+                    close: price,
+                    volume: quantity,
+                    notional,
+                    count: 1,
+                },
+            ),
+        }
+    }
+
+    /// Add a live trade
+    pub fn add(&mut self, trade: &Trade) {
+        self.record(trade.price, trade.quantity, trade.timestamp);
+    }
+
+    /// Take a busted trade back out, if its minute is still in the window
+    pub fn remove(&mut self, trade: &Trade) {
+        if let Ok(index) = self.position(minute_of(trade.timestamp)) {
+            let bucket = &mut self.buckets[index];
+            bucket.volume -= trade.quantity;
+            bucket.notional -= trade.price * trade.quantity;
+            bucket.count = bucket.count.saturating_sub(1);
+        }
+    }
+
+    /// Trading in the 24 hours up to `now`
+    pub fn summary(&self, now: DateTime<Utc>) -> WindowSummary {
+        let since = minute_of(now) - WINDOW_MINUTES;
+        let mut summary = WindowSummary::default();
+        for bucket in self.buckets.iter().filter(|bucket| bucket.minute > since) {
+            summary.open.get_or_insert(bucket.open);
+            summary.high = Some(summary.high.map_or(bucket.high, |high| high.max(bucket.high)));
+            summary.low = Some(summary.low.map_or(bucket.low, |low| low.min(bucket.low)));
+            summary.last = Some(bucket.close);
+            summary.volume += bucket.volume;
+            summary.notional += bucket.notional;
+            summary.count += bucket.count;
+        }
+        summary
+    }
+
+    /// Drop the minutes that have left the window as of `minute`
+    fn prune(&mut self, minute: i64) {
+        while self.buckets.front().is_some_and(|front| minute - front.minute >= WINDOW_MINUTES) {
+            self.buckets.pop_front();
+        }
+    }
+
+    fn position(&self, minute: i64) -> Result<usize, usize> {
+        self.buckets.binary_search_by_key(&minute, |bucket| bucket.minute)
+    }
+}
+
+fn minute_of(timestamp: DateTime<Utc>) -> i64 {
+    timestamp.timestamp().div_euclid(60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_summary_covers_the_last_day() {
+        let start = Utc.with_ymd_and_hms(2030, 1, 1, 0, 0, 0).unwrap();
+        let mut window = RollingWindow::default();
+        window.record(dec!(100), dec!(1), start);
+        window.record(dec!(104), dec!(2), start + Duration::seconds(30));
+        window.record(dec!(98), dec!(1), start + Duration::hours(2));
+        window.record(dec!(101), dec!(1), start + Duration::hours(23));
+
+        let summary = window.summary(start + Duration::hours(23));
+        assert_eq!((summary.open, summary.last), (Some(dec!(100)), Some(dec!(101))));
+        assert_eq!((summary.high, summary.low), (Some(dec!(104)), Some(dec!(98))));
+        assert_eq!((summary.volume, summary.notional, summary.count), (dec!(5), dec!(507), 4));
+
+        // A day on, the first minute has left the window
+        let summary = window.summary(start + Duration::hours(24));
+        assert_eq!((summary.open, summary.high, summary.count), (Some(dec!(98)), Some(dec!(101)), 2));
+        assert_eq!(window.summary(start + Duration::hours(48)), WindowSummary::default());
+    }
+
+    #[test]
+    fn test_history_fills_in_behind_live_trades() {
+        let now = Utc.with_ymd_and_hms(2030, 1, 2, 0, 0, 0).unwrap();
+        let mut window = RollingWindow::default();
+        window.record(dec!(100), dec!(1), now);
+        // Loaded after the live print: earlier minutes, and one too old to count
+        window.record(dec!(90), dec!(1), now - Duration::hours(1));
+        window.record(dec!(80), dec!(1), now - Duration::hours(25));
+
+        let summary = window.summary(now);
+        assert_eq!((summary.open, summary.last, summary.low), (Some(dec!(90)), Some(dec!(100)), Some(dec!(90))));
+        assert_eq!(summary.count, 2);
+    }
+}
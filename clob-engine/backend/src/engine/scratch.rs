@@ -0,0 +1,48 @@
+//! Temporary directories for tests that write files.
+
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// A new, empty directory under the system's temporary one, removed with
+/// everything in it when dropped, even by a failing test
+#[derive(Debug)]
+pub struct ScratchDir(PathBuf);
+
+impl ScratchDir {
+    /// A directory named `clob-{name}-` and a random suffix
+    pub fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("clob-{name}-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        Self(dir)
+    }
+}
+
+impl Deref for ScratchDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ScratchDir;
+
+    #[test]
+    fn test_scratch_dir_is_removed_with_its_files() {
+        let dir = ScratchDir::new("scratch");
+        std::fs::write(dir.join("file"), "contents").unwrap();
+        let path = dir.to_path_buf();
+        assert!(path.is_dir());
+        drop(dir);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,438 @@
+//! Engine state saved across restarts.
+//!
+//! A snapshot holds everything needed to carry on where the engine left
+//! off: the resting orders in time priority, pending stops, orders queued
+//! during a halt, and the sequence counters, so event numbers and arrival
+//! priority continue rather than restart. It is taken inside the engine
+//! task, between commands, so it is always consistent with the events
+//! already broadcast.
+//!
+//! Snapshots are saved as one JSON file per symbol, periodically by
+//! `run_snapshotter` and once more after the final drain on shutdown. The
+//! write-ahead log kept beside them covers the commands since. Snapshots
+//! saved when orders and trades had UUID ids are numbered on loading.
+
+use crate::engine::engines::url_key;
+use crate::engine::ids::IdEpoch;
+use crate::engine::matcher::{EngineCommand, EngineHandle};
+use crate::engine::order::{Order, OrderId, Trade, TradeId};
+use crate::engine::rate_limit::RateLimits;
+use crate::engine::wal::prune_wal;
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+/// Environment variable naming the directory snapshots are kept in
+pub const SNAPSHOT_DIR_VAR: &str = "CLOB_SNAPSHOT_DIR";
+
+/// Why a snapshot could not be saved or loaded
+#[derive(Debug, thiserror::Error)]
+pub enum SnapshotError {
+    #[error("cannot access {path}: {source}")]
+    Io { path: String, source: std::io::Error },
+    #[error("invalid snapshot {path}: {source}")]
+    Json { path: String, source: serde_json::Error },
+}
+
+/// One engine's state at a point between commands
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EngineSnapshot {
+    pub symbol: String,
+    /// Sequence of the last event broadcast
+    pub event_sequence: u64,
+    /// Sequence of the last book update sent
+    pub book_sequence: u64,
+    /// Arrival sequence the next order to reach the engine will get
+    pub next_sequence: u64,
+    /// Id the next order will get; the arrival sequence when absent, as
+    /// orders once took theirs as their id
+    #[serde(default)]
+    pub next_order_id: Option<OrderId>,
+    /// Id the next trade will get
+    #[serde(default)]
+    pub next_trade_id: TradeId,
+    /// Epoch the engine's UUIDs are made from; a fresh one when absent
+    #[serde(default)]
+    pub id_epoch: Option<IdEpoch>,
+    /// Sequence of the last write-ahead log record applied; replay starts
+    /// after it
+    #[serde(default)]
+    pub wal_sequence: u64,
+    /// Resting orders, as listed by `OrderBook::resting_orders`
+    pub resting: Vec<Order>,
+    /// Stop orders waiting for their trigger, each trigger in arrival order
+    pub stops: Vec<Order>,
+    /// Orders held during a halt, oldest first
+    pub halted_orders: Vec<Order>,
+    /// End of the halt those orders wait for
+    pub halted_until: Option<DateTime<Utc>>,
+    pub last_trade_price: Option<Decimal>,
+    /// Trades made so far, less any busted, and the quantity and notional
+    /// they traded
+    #[serde(default)]
+    pub trade_count: u64,
+    #[serde(default)]
+    pub traded_volume: Decimal,
+    #[serde(default)]
+    pub traded_notional: Decimal,
+    pub in_auction: bool,
+    /// Order ids of each owner's current quote, bid then ask
+    pub quotes: HashMap<String, (OrderId, OrderId)>,
+    /// Owners whose kill switch is engaged
+    pub blocked_owners: Vec<String>,
+    /// Rate limits as last changed; the builder's when absent
+    #[serde(default)]
+    pub rate_limits: Option<RateLimits>,
+    /// Most recent trades, oldest first, so they can still be busted
+    pub recent_trades: Vec<Trade>,
+    /// Book update settings as last changed; the builder's when absent
+    #[serde(default)]
+    pub depth_levels: Option<usize>,
+    #[serde(default)]
+    pub min_update_interval: Option<Duration>,
+    /// Engine time the last book update was sent, for its minimum interval
+    #[serde(default)]
+    pub last_book_published: Option<DateTime<Utc>>,
+    /// When the snapshot was taken, by the engine's clock
+    pub taken_at: DateTime<Utc>,
+}
+
+impl EngineSnapshot {
+    /// Write the snapshot to `path`, replacing any earlier one only once the
+    /// new one is completely written
+    pub fn save(&self, path: &Path) -> Result<(), SnapshotError> {
+        let io_error = |source| SnapshotError::Io {
+            path: path.display().to_string(),
+            source,
+        };
+        let json = serde_json::to_vec(self).map_err(|source| SnapshotError::Json {
+            path: path.display().to_string(),
+            source,
+        })?;
+        let partial = path.with_extension("json.partial");
+        std::fs::write(&partial, json).map_err(io_error)?;
+        std::fs::rename(&partial, path).map_err(io_error)
+    }
+
+    /// Save the snapshot as its symbol's file in `dir`, then delete the
+    /// write-ahead log segments it makes redundant
+    pub fn save_in(&self, dir: &Path) -> Result<(), SnapshotError> {
+        self.save(&snapshot_path(dir, &self.symbol))?;
+        if let Err(e) = prune_wal(dir, &self.symbol, self.wal_sequence) {
+            tracing::warn!("Cannot prune the write-ahead log: {e}");
+        }
+        Ok(())
+    }
+
+    /// Read the snapshot saved at `path`; `None` if there is none yet
+    pub fn load(path: &Path) -> Result<Option<Self>, SnapshotError> {
+        let json = match std::fs::read(path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(source) => {
+                return Err(SnapshotError::Io {
+                    path: path.display().to_string(),
+                    source,
+                })
+            }
+        };
+        let json_error = |source| SnapshotError::Json {
+            path: path.display().to_string(),
+            source,
+        };
+        let mut snapshot = serde_json::from_slice(&json).map_err(json_error)?;
+        upgrade_ids(&mut snapshot);
+        serde_json::from_value(snapshot).map(Some).map_err(json_error)
+    }
+}
+
+/// Number the orders and trades of a snapshot saved when the engine named
+/// them by UUID. Orders take their arrival sequence as their id, or the
+/// next one if they were queued by a halt before getting one, and trades
+/// count up from 1. A quote whose orders are gone keeps order 0, which no
+/// order has.
+fn upgrade_ids(snapshot: &mut Value) {
+    if !snapshot.is_object() || snapshot.get("next_trade_id").is_some() {
+        return;
+    }
+    let mut next_sequence = snapshot["next_sequence"].as_u64().unwrap_or(1);
+    let mut ids = HashMap::new();
+    for list in ["resting", "stops", "halted_orders"] {
+        let Some(orders) = snapshot.get_mut(list).and_then(Value::as_array_mut) else {
+            continue;
+        };
+        for order in orders {
+            let Some(uuid) = order["id"].as_str().map(str::to_owned) else {
+                continue;
+            };
+            let id = match order["sequence"].as_u64() {
+                Some(sequence) if sequence != 0 => sequence,
+                _ => {
+                    next_sequence += 1;
+                    next_sequence - 1
+                }
+            };
+            order["id"] = id.into();
+            ids.insert(uuid, id);
+        }
+    }
+
+    let mut next_trade_id: TradeId = 1;
+    if let Some(trades) = snapshot.get_mut("recent_trades").and_then(Value::as_array_mut) {
+        for trade in trades.iter_mut().filter(|trade| trade.is_object()) {
+            trade["id"] = next_trade_id.into();
+            next_trade_id += 1;
+            // Orders no longer live are known by the sequence they traded at
+            for side in ["taker", "maker"] {
+                let key = format!("{side}_order_id");
+                let id = trade[&key]
+                    .as_str()
+                    .and_then(|uuid| ids.get(uuid).copied())
+                    .or_else(|| trade[format!("{side}_sequence")].as_u64())
+                    .unwrap_or(0);
+                trade[key] = id.into();
+            }
+        }
+    }
+    if let Some(quotes) = snapshot.get_mut("quotes").and_then(Value::as_object_mut) {
+        for id in quotes.values_mut().filter_map(Value::as_array_mut).flatten() {
+            *id = id.as_str().and_then(|uuid| ids.get(uuid).copied()).unwrap_or(0).into();
+        }
+    }
+    snapshot["next_sequence"] = next_sequence.into();
+    snapshot["next_trade_id"] = next_trade_id.into();
+}
+
+/// File in `dir` holding `symbol`'s snapshot: "BTC/USD" is `BTC-USD.json`
+pub fn snapshot_path(dir: &Path, symbol: &str) -> PathBuf {
+    dir.join(format!("{}.json", url_key(symbol)))
+}
+
+/// Save the engine's state in `dir` every `every`. Exits as soon as the
+/// engine stops accepting commands, so a shutdown's final snapshot is
+/// never overwritten by an older one.
+pub async fn run_snapshotter(handle: EngineHandle, dir: PathBuf, every: Duration) {
+    let mut interval = tokio::time::interval(every);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    // The first tick is immediate; there is nothing new to save yet
+    interval.tick().await;
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = handle.command_tx.closed() => {
+                tracing::debug!("Engine closed, snapshotter exiting");
+                break;
+            }
+        }
+
+        let (reply, snapshot) = oneshot::channel();
+        if handle.command_tx.send(EngineCommand::Snapshot { reply }).await.is_err() {
+            tracing::debug!("Engine closed, snapshotter exiting");
+            break;
+        }
+        let Ok(snapshot) = snapshot.await else {
+            break;
+        };
+        if let Err(e) = snapshot.save_in(&dir) {
+            tracing::warn!("Engine snapshot failed: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::clock::ManualClock;
+    use crate::engine::matcher::{EngineBuilder, EngineEvent};
+    use crate::engine::order::{OrderRequest, Side};
+    use crate::engine::scratch::ScratchDir;
+    use rust_decimal_macros::dec;
+    use std::sync::Arc;
+    use uuid::Uuid;
+
+    fn sell(price: Decimal, quantity: Decimal, owner: &str) -> OrderRequest {
+        OrderRequest::new(Side::Sell, price, quantity).with_owner(owner)
+    }
+
+    #[test]
+    fn test_missing_snapshot_loads_as_none() {
+        let dir = ScratchDir::new("snapshot-missing");
+        assert!(EngineSnapshot::load(&snapshot_path(&dir, "BTC/USD")).unwrap().is_none());
+        assert_eq!(snapshot_path(&dir, "BTC/USD"), dir.join("BTC-USD.json"));
+
+        std::fs::write(dir.join("BTC-USD.json"), "{").unwrap();
+        assert!(matches!(
+            EngineSnapshot::load(&dir.join("BTC-USD.json")),
+            Err(SnapshotError::Json { .. })
+        ));
+    }
+
+    #[test]
+    fn test_snapshot_with_uuid_ids_is_numbered_on_loading() {
+        let dir = ScratchDir::new("snapshot-upgrade");
+        let path = snapshot_path(&dir, "BTC/USD");
+        let (mut engine, _handle) = EngineBuilder::new("BTC/USD").build();
+        engine.process_command(EngineCommand::Submit(sell(dec!(101), dec!(2), "maker")));
+        engine.process_command(EngineCommand::Submit(
+            OrderRequest::new(Side::Buy, dec!(101), dec!(1)).with_owner("taker"),
+        ));
+
+        // As saved before the engine numbered its ids
+        let mut legacy = serde_json::to_value(engine.snapshot()).unwrap();
+        let maker = Uuid::new_v4().to_string();
+        legacy["resting"][0]["id"] = maker.clone().into();
+        legacy["recent_trades"][0]["id"] = Uuid::new_v4().to_string().into();
+        legacy["recent_trades"][0]["maker_order_id"] = maker.clone().into();
+        legacy["recent_trades"][0]["taker_order_id"] = Uuid::new_v4().to_string().into();
+        legacy["quotes"] = serde_json::json!({ "mm": [maker, Uuid::new_v4().to_string()] });
+        for key in ["next_trade_id", "id_epoch"] {
+            legacy.as_object_mut().unwrap().remove(key);
+        }
+        std::fs::write(&path, legacy.to_string()).unwrap();
+
+        let snapshot = EngineSnapshot::load(&path).unwrap().unwrap();
+        assert_eq!(snapshot.resting[0].id, snapshot.resting[0].sequence);
+        let trade = &snapshot.recent_trades[0];
+        assert_eq!((trade.id, trade.maker_order_id), (1, snapshot.resting[0].id));
+        // The filled taker is gone: it is known by its sequence
+        assert_eq!(trade.taker_order_id, trade.taker_sequence);
+        assert_eq!(snapshot.quotes["mm"], (snapshot.resting[0].id, 0));
+        assert_eq!(snapshot.next_trade_id, 2);
+        assert_eq!(snapshot.id_epoch, None);
+
+        // A current snapshot loads as saved
+        engine.snapshot().save(&path).unwrap();
+        let current = EngineSnapshot::load(&path).unwrap().unwrap();
+        assert_eq!(current.id_epoch, engine.snapshot().id_epoch);
+    }
+
+    #[tokio::test]
+    async fn test_snapshotter_saves_and_exits_on_shutdown() {
+        let dir = ScratchDir::new("snapshot-periodic");
+        let path = snapshot_path(&dir, "BTC/USD");
+        let (engine, handle) = EngineBuilder::new("BTC/USD").build();
+        tokio::spawn(engine.run());
+        handle.submit_order(sell(dec!(101), dec!(1), "maker")).await.unwrap();
+
+        let snapshotter = tokio::spawn(run_snapshotter(handle.clone(), dir.to_path_buf(), Duration::from_millis(10)));
+        let saved = tokio::time::timeout(Duration::from_secs(1), async {
+            loop {
+                if let Some(snapshot) = EngineSnapshot::load(&path).unwrap() {
+                    break snapshot;
+                }
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(saved.resting.len(), 1);
+
+        // Waiting out a long interval would hold up the final save
+        let idle = tokio::spawn(run_snapshotter(handle.clone(), dir.to_path_buf(), Duration::from_secs(3600)));
+        handle.shutdown().await.unwrap();
+        for task in [snapshotter, idle] {
+            tokio::time::timeout(Duration::from_millis(500), task).await.unwrap().unwrap();
+        }
+    }
+
+    /// What the engine did after the restart point: trades as (maker,
+    /// taker, price, quantity, maker sequence) and the event numbers
+    type Continuation = (Vec<(OrderId, OrderId, Decimal, Decimal, u64)>, Vec<u64>);
+
+    async fn continue_with(handle: &EngineHandle, orders: &[OrderRequest]) -> (Continuation, EngineSnapshot) {
+        let mut events = handle.subscribe();
+        for order in orders {
+            handle.submit_order(order.clone()).await.unwrap();
+        }
+        let last = handle.shutdown().await.unwrap();
+
+        let (mut trades, mut seqs) = (Vec::new(), Vec::new());
+        while let Ok(event) = events.try_recv() {
+            seqs.push(event.seq);
+            if let EngineEvent::Trade(t) = event.event {
+                trades.push((t.maker_order_id, t.taker_order_id, t.price, t.quantity, t.maker_sequence));
+            }
+        }
+        ((trades, seqs), last)
+    }
+
+    /// The same orders go to an engine that runs straight through and to
+    /// one restarted from a snapshot halfway: after the restart both must
+    /// trade the same makers in the same order and number events alike
+    #[tokio::test]
+    async fn test_restart_from_snapshot_continues_identically() {
+        let start = Utc::now();
+        let dir = ScratchDir::new("snapshot-restart");
+        let path = snapshot_path(&dir, "BTC/USD");
+
+        // Three makers at one price, a deeper level, a stop and a partial
+        // fill of the first maker, so a remainder is carried over
+        let mut setup: Vec<_> = ["first", "second", "third"]
+            .into_iter()
+            .map(|owner| sell(dec!(101), dec!(1), owner))
+            .collect();
+        setup.push(sell(dec!(102), dec!(2), "deep"));
+        setup.push(OrderRequest::new(Side::Buy, dec!(105), dec!(1)).with_stop_price(dec!(102)).with_owner("stopper"));
+        setup.push(OrderRequest::new(Side::Buy, dec!(101), dec!(0.5)).with_owner("taker"));
+        // Sweeps 101 in priority order, then trips the stop at 102
+        let after = [
+            OrderRequest::new(Side::Buy, dec!(102), dec!(3)).with_owner("taker"),
+            sell(dec!(101), dec!(1), "late"),
+        ];
+
+        let (engine, straight) = EngineBuilder::new("BTC/USD").clock(Arc::new(ManualClock::new(start))).build();
+        tokio::spawn(engine.run());
+        for order in &setup {
+            straight.submit_order(order.clone()).await.unwrap();
+        }
+        let at_restart = straight.snapshot().await.unwrap();
+        let (expected, expected_end) = continue_with(&straight, &after).await;
+
+        let (engine, first) = EngineBuilder::new("BTC/USD").clock(Arc::new(ManualClock::new(start))).build();
+        tokio::spawn(engine.run());
+        for order in &setup {
+            first.submit_order(order.clone()).await.unwrap();
+        }
+        first.shutdown().await.unwrap().save(&path).unwrap();
+
+        let snapshot = EngineSnapshot::load(&path).unwrap().unwrap();
+        assert_eq!(snapshot.resting.len(), 4);
+        assert_eq!(snapshot.resting[0].remaining_quantity, dec!(0.5));
+        assert_eq!(snapshot.stops.len(), 1);
+        // One book update past the straight run's: the shutdown's final book
+        assert_eq!(snapshot.book_sequence, at_restart.book_sequence + 1);
+        let book_sequence = snapshot.book_sequence;
+        let (engine, restored) = EngineBuilder::new("BTC/USD")
+            .clock(Arc::new(ManualClock::new(start)))
+            .restore_from(snapshot)
+            .build();
+        // Readers see the restored book before the engine takes a command
+        assert_eq!(restored.current_state.borrow().best_ask, Some(dec!(101)));
+        assert_eq!(restored.current_state.borrow().sequence, book_sequence);
+        tokio::spawn(engine.run());
+        let (continued, end) = continue_with(&restored, &after).await;
+
+        let makers: Vec<_> = expected.0.iter().map(|&(maker, ..)| maker).collect();
+        // Both engines numbered the setup orders from 1 as they arrived
+        assert_eq!(makers, [1, 2, 3, 4, 4]);
+        assert_eq!(continued.0, expected.0);
+        // Numbering carries on past the first run's final book and Stopped
+        assert_eq!(continued.1.first(), Some(&(at_restart.event_sequence + 3)));
+        assert_eq!(continued.1.len(), expected.1.len());
+
+        let resting = |s: &EngineSnapshot| {
+            s.resting
+                .iter()
+                .map(|o| (o.id, o.remaining_quantity, o.sequence))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(resting(&end), resting(&expected_end));
+        assert_eq!(end.next_sequence, expected_end.next_sequence);
+    }
+}
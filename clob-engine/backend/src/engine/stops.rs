@@ -0,0 +1,190 @@
+//! Pending stop orders waiting for a trade to print through their trigger price.
+
+use crate::engine::order::{Order, OrderId, Side};
+use rust_decimal::Decimal;
+use std::collections::{BTreeMap, VecDeque};
+
+/// Stop orders held off the book, keyed by trigger price
+#[derive(Debug, Default)]
+pub struct StopBook {
+    /// Buy stops fire when a trade prints at or above the trigger
+    buy_stops: BTreeMap<Decimal, VecDeque<Order>>,
+    /// Sell stops fire when a trade prints at or below the trigger
+    sell_stops: BTreeMap<Decimal, VecDeque<Order>>,
+}
+
+impl StopBook {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Hold a stop order until it is triggered.
+    /// Orders without a stop price are ignored.
+    pub fn insert(&mut self, order: Order) {
+        let Some(stop_price) = order.stop_price else {
+            return;
+        };
+
+        let book = match order.side {
+            Side::Buy => &mut self.buy_stops,
+            Side::Sell => &mut self.sell_stops,
+        };
+
+        book.entry(stop_price).or_default().push_back(order);
+    }
+
+    /// Check whether a stop on this side at this trigger would fire given a trade range
+    pub fn is_triggered(side: Side, stop_price: Decimal, low: Decimal, high: Decimal) -> bool {
+        match side {
+            Side::Buy => high >= stop_price,
+            Side::Sell => low <= stop_price,
+        }
+    }
+
+    /// Remove and return every stop triggered by trades printed within `[low, high]`.
+    ///
+    /// Buy stops come out lowest trigger first, sell stops highest trigger first,
+    /// each level in arrival order.
+    pub fn take_triggered(&mut self, low: Decimal, high: Decimal) -> Vec<Order> {
+        let mut triggered = Vec::new();
+
+        while let Some(entry) = self.buy_stops.first_entry() {
+            if !Self::is_triggered(Side::Buy, *entry.key(), low, high) {
+                break;
+            }
+            triggered.extend(entry.remove());
+        }
+
+        while let Some(entry) = self.sell_stops.last_entry() {
+            if !Self::is_triggered(Side::Sell, *entry.key(), low, high) {
+                break;
+            }
+            triggered.extend(entry.remove());
+        }
+
+        triggered
+    }
+
+    /// Every pending stop, buy side first
+    pub fn iter(&self) -> impl Iterator<Item = &Order> {
+        self.buy_stops.values().chain(self.sell_stops.values()).flatten()
+    }
+
+    /// Remove a pending stop by id
+    pub fn remove(&mut self, order_id: OrderId) -> Option<Order> {
+        for book in [&mut self.buy_stops, &mut self.sell_stops] {
+            let found = book.iter().find_map(|(price, queue)| {
+                queue.iter().position(|o| o.id == order_id).map(|i| (*price, i))
+            });
+            if let Some((price, position)) = found {
+                let queue = book.get_mut(&price)?;
+                let order = queue.remove(position);
+                if queue.is_empty() {
+                    book.remove(&price);
+                }
+                return order;
+            }
+        }
+        None
+    }
+
+    /// Remove every pending stop belonging to `owner_id`, optionally on one side only
+    pub fn remove_owned(&mut self, owner_id: &str, side: Option<Side>) -> Vec<Order> {
+        let mut removed = Vec::new();
+        for (book_side, book) in [(Side::Buy, &mut self.buy_stops), (Side::Sell, &mut self.sell_stops)] {
+            if side.is_some_and(|s| s != book_side) {
+                continue;
+            }
+            book.retain(|_, queue| {
+                let (owned, kept) = queue
+                    .drain(..)
+                    .partition::<Vec<_>, _>(|o| o.owner_id.as_deref() == Some(owner_id));
+                removed.extend(owned);
+                queue.extend(kept);
+                !queue.is_empty()
+            });
+        }
+        removed
+    }
+
+    /// Total number of pending stop orders
+    pub fn len(&self) -> usize {
+        let buys: usize = self.buy_stops.values().map(|q| q.len()).sum();
+        let sells: usize = self.sell_stops.values().map(|q| q.len()).sum();
+        buys + sells
+    }
+
+    /// Check if there are no pending stops
+    pub fn is_empty(&self) -> bool {
+        self.buy_stops.is_empty() && self.sell_stops.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::order::OrderType;
+    use rust_decimal_macros::dec;
+
+    fn stop(side: Side, stop_price: Decimal) -> Order {
+        let mut order = Order::new(side, dec!(0), dec!(1));
+        order.order_type = OrderType::Market;
+        order.stop_price = Some(stop_price);
+        order
+    }
+
+    #[test]
+    fn test_buy_stop_triggers_at_or_above() {
+        let mut stops = StopBook::new();
+        stops.insert(stop(Side::Buy, dec!(105)));
+
+        assert!(stops.take_triggered(dec!(104), dec!(104.99)).is_empty());
+        assert_eq!(stops.take_triggered(dec!(105), dec!(105)).len(), 1);
+        assert!(stops.is_empty());
+    }
+
+    #[test]
+    fn test_sell_stop_triggers_at_or_below() {
+        let mut stops = StopBook::new();
+        stops.insert(stop(Side::Sell, dec!(95)));
+
+        assert!(stops.take_triggered(dec!(95.01), dec!(100)).is_empty());
+        assert_eq!(stops.take_triggered(dec!(90), dec!(100)).len(), 1);
+    }
+
+    #[test]
+    fn test_trigger_order() {
+        let mut stops = StopBook::new();
+        let first = stop(Side::Buy, dec!(102));
+        let second = stop(Side::Buy, dec!(102));
+        let lower = stop(Side::Buy, dec!(101));
+        let (first_id, second_id, lower_id) = (first.id, second.id, lower.id);
+        stops.insert(first);
+        stops.insert(second);
+        stops.insert(lower);
+        stops.insert(stop(Side::Buy, dec!(110)));
+
+        let fired: Vec<_> = stops.take_triggered(dec!(100), dec!(103)).iter().map(|o| o.id).collect();
+        assert_eq!(fired, vec![lower_id, first_id, second_id]);
+        assert_eq!(stops.len(), 1);
+    }
+
+    #[test]
+    fn test_remove_pending_stop() {
+        let mut stops = StopBook::new();
+        let order = stop(Side::Sell, dec!(95));
+        let id = order.id;
+        stops.insert(order);
+
+        assert_eq!(stops.remove(id).map(|o| o.id), Some(id));
+        assert!(stops.remove(id).is_none());
+        assert!(stops.is_empty());
+    }
+
+    #[test]
+    fn test_non_stop_order_ignored() {
+        let mut stops = StopBook::new();
+        stops.insert(Order::new(Side::Buy, dec!(100), dec!(1)));
+        assert!(stops.is_empty());
+    }
+}
@@ -0,0 +1,509 @@
+//! Per-symbol trading rules checked before an order reaches the book.
+
+use crate::engine::matcher::RejectReason;
+use crate::engine::order::{OrderRequest, OrderType, QuoteRequest};
+use crate::engine::ticks::{PriceGrid, TickError};
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// Static trading rules for one symbol
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct SymbolConfig {
+    /// Symbol name, e.g. "BTC/USD"
+    pub symbol: String,
+    /// Most decimal places a price may have, ignoring trailing zeros
+    pub price_scale: Option<u32>,
+    /// Most decimal places a quantity may have, ignoring trailing zeros
+    pub quantity_scale: Option<u32>,
+    /// Smallest allowed price increment; `None` accepts any price
+    pub tick_size: Option<Decimal>,
+    /// Quantity step; quantities must be a multiple of it
+    pub lot_size: Option<Decimal>,
+    /// Smallest accepted order quantity
+    pub min_quantity: Option<Decimal>,
+    /// Smallest accepted price × quantity for priced orders
+    pub min_notional: Option<Decimal>,
+    /// Maximum distance, in percent, a limit price may sit from the reference price
+    pub price_band_pct: Option<Decimal>,
+    /// Largest accepted order quantity
+    pub max_quantity: Option<Decimal>,
+    /// Largest accepted price × quantity for priced orders
+    pub max_notional: Option<Decimal>,
+    /// Fee on resting (maker) fills, in basis points of notional
+    pub maker_fee_bps: Decimal,
+    /// Fee on aggressing (taker) fills, in basis points of notional
+    pub taker_fee_bps: Decimal,
+}
+
+impl SymbolConfig {
+    /// Create a config with no trading rules
+    pub fn new(symbol: impl Into<String>) -> Self {
+        Self {
+            symbol: symbol.into(),
+            price_scale: None,
+            quantity_scale: None,
+            tick_size: None,
+            lot_size: None,
+            min_quantity: None,
+            min_notional: None,
+            price_band_pct: None,
+            max_quantity: None,
+            max_notional: None,
+            maker_fee_bps: Decimal::ZERO,
+            taker_fee_bps: Decimal::ZERO,
+        }
+    }
+
+    /// Grid the order book keys prices on: the tick size, else one unit of
+    /// the price scale, else `PriceGrid::DEFAULT_TICK`
+    pub fn price_grid(&self) -> PriceGrid {
+        match (self.tick_size, self.price_scale) {
+            (Some(tick_size), _) => PriceGrid::new(tick_size),
+            (None, Some(scale)) => PriceGrid::new(Decimal::new(1, scale)),
+            (None, None) => PriceGrid::default(),
+        }
+    }
+
+    /// Decimal places of the quote currency, which notional figures are
+    /// rounded to: the price scale, else the price grid tick's
+    pub fn quote_scale(&self) -> u32 {
+        self.price_scale.unwrap_or_else(|| self.price_grid().tick().scale())
+    }
+
+    /// Check that every price the request carries lies on the price grid and
+    /// is small enough to count in ticks
+    pub fn check_price_grid(&self, request: &OrderRequest) -> Result<(), RejectReason> {
+        let mut prices = request.stop_price.into_iter().collect::<Vec<_>>();
+        if request.order_type == OrderType::Limit {
+            prices.push(request.price);
+        }
+        let grid = self.price_grid();
+        // Ticks are counted by division, which ignores scale, so 100.1 and 100.10 both pass a 0.01 tick
+        for price in prices {
+            match grid.to_ticks(price) {
+                Ok(_) => {}
+                Err(TickError::OffGrid) => {
+                    return Err(RejectReason::InvalidTickSize {
+                        price,
+                        tick_size: grid.tick(),
+                    })
+                }
+                Err(TickError::OutOfRange) => return Err(RejectReason::PriceOutOfRange { price }),
+            }
+        }
+        Ok(())
+    }
+
+    /// Check an incoming request against the symbol rules
+    pub fn validate(&self, request: &OrderRequest) -> Result<(), RejectReason> {
+        self.check_scale(request)?;
+        self.check_price_grid(request)?;
+        if let Some(min_quantity) = self.min_quantity {
+            if request.quantity < min_quantity {
+                return Err(RejectReason::BelowMinQuantity {
+                    quantity: request.quantity,
+                    min_quantity,
+                });
+            }
+        }
+        if let Some(lot_size) = self.lot_size {
+            if !(request.quantity % lot_size).is_zero() {
+                return Err(RejectReason::InvalidLotSize {
+                    quantity: request.quantity,
+                    lot_size,
+                });
+            }
+        }
+        // Market orders have no price to value them at until they trade
+        if let Some(min_notional) = self.min_notional {
+            if request.order_type == OrderType::Limit {
+                let notional = request.price * request.quantity;
+                if notional < min_notional {
+                    return Err(RejectReason::BelowMinNotional {
+                        notional,
+                        min_notional,
+                    });
+                }
+            }
+        }
+        if request.skip_risk_checks {
+            return Ok(());
+        }
+        self.check_risk_limits(request)
+    }
+
+    /// Check that prices and the quantity carry no more decimal places than
+    /// allowed. Trailing zeros don't count, so 100.10 passes a scale of 1.
+    pub fn check_scale(&self, request: &OrderRequest) -> Result<(), RejectReason> {
+        if let Some(max_scale) = self.price_scale {
+            let mut prices = request.stop_price.into_iter().collect::<Vec<_>>();
+            if request.order_type == OrderType::Limit {
+                prices.push(request.price);
+            }
+            if let Some(price) = prices.into_iter().find(|p| p.normalize().scale() > max_scale) {
+                return Err(RejectReason::PriceScaleExceeded { price, max_scale });
+            }
+        }
+        if let Some(max_scale) = self.quantity_scale {
+            if request.quantity.normalize().scale() > max_scale {
+                return Err(RejectReason::QuantityScaleExceeded {
+                    quantity: request.quantity,
+                    max_scale,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Check the pre-trade size limits. Like the minimum, the notional limit
+    /// only applies to orders with a limit price.
+    pub fn check_risk_limits(&self, request: &OrderRequest) -> Result<(), RejectReason> {
+        if let Some(max_quantity) = self.max_quantity {
+            if request.quantity > max_quantity {
+                return Err(RejectReason::MaxQuantityExceeded {
+                    quantity: request.quantity,
+                    max_quantity,
+                });
+            }
+        }
+        if let Some(max_notional) = self.max_notional {
+            if request.order_type == OrderType::Limit {
+                let notional = request.price * request.quantity;
+                if notional > max_notional {
+                    return Err(RejectReason::MaxNotionalExceeded {
+                        notional,
+                        max_notional,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Price the band is centred on: the last trade, else the mid-price, else
+    /// the only populated side. `None` on an empty book with no trades.
+    pub fn reference_price(
+        last_trade: Option<Decimal>,
+        best_bid: Option<Decimal>,
+        best_ask: Option<Decimal>,
+    ) -> Option<Decimal> {
+        last_trade.or(match (best_bid, best_ask) {
+            (Some(bid), Some(ask)) => Some((bid + ask) / Decimal::TWO),
+            (bid, ask) => bid.or(ask),
+        })
+    }
+
+    /// Check a limit order against the price band around `reference`.
+    /// Stops are not checked until they trigger, and nothing is checked
+    /// without a reference price.
+    pub fn check_price_band(&self, request: &OrderRequest, reference: Option<Decimal>) -> Result<(), RejectReason> {
+        let (Some(band_pct), Some(reference)) = (self.price_band_pct, reference) else {
+            return Ok(());
+        };
+        if request.order_type != OrderType::Limit || request.stop_price.is_some() {
+            return Ok(());
+        }
+        // |price - reference| / reference > band%, kept exact by cross-multiplying
+        if (request.price - reference).abs() * Decimal::ONE_HUNDRED > band_pct * reference {
+            return Err(RejectReason::PriceOutsideBand {
+                price: request.price,
+                reference,
+                band_pct,
+            });
+        }
+        Ok(())
+    }
+
+    /// Check both sides of a quote, which must also not cross each other
+    pub fn validate_quote(&self, quote: &QuoteRequest, reference: Option<Decimal>) -> Result<(), RejectReason> {
+        if quote.bid_price >= quote.ask_price {
+            return Err(RejectReason::QuoteWouldSelfCross {
+                bid_price: quote.bid_price,
+                ask_price: quote.ask_price,
+            });
+        }
+        quote
+            .orders()
+            .iter()
+            .try_for_each(|request| self.validate(request).and_then(|_| self.check_price_band(request, reference)))
+    }
+
+    /// Smallest quantity a limit order at `price` may have and still pass
+    /// the minimum quantity, minimum notional and lot size rules
+    pub fn min_quantity_at(&self, price: Decimal) -> Decimal {
+        let mut quantity = self.min_quantity.unwrap_or(Decimal::ZERO);
+        if let Some(min_notional) = self.min_notional {
+            if price > Decimal::ZERO {
+                let needed = (min_notional / price)
+                    .round_dp_with_strategy(8, RoundingStrategy::AwayFromZero);
+                quantity = quantity.max(needed);
+            }
+        }
+        match self.lot_size {
+            Some(lot_size) => (quantity / lot_size).ceil() * lot_size,
+            None => quantity,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::order::Side;
+    use rust_decimal_macros::dec;
+
+    fn config(tick_size: Decimal) -> SymbolConfig {
+        SymbolConfig {
+            tick_size: Some(tick_size),
+            ..SymbolConfig::new("BTC/USD")
+        }
+    }
+
+    #[test]
+    fn test_tick_size_ignores_scale() {
+        let config = config(dec!(0.01));
+        for price in [dec!(100), dec!(100.1), dec!(100.10), dec!(100.100), dec!(0.01)] {
+            assert!(config.validate(&OrderRequest::new(Side::Buy, price, dec!(1))).is_ok(), "{price}");
+        }
+    }
+
+    #[test]
+    fn test_off_tick_price_rejected() {
+        let config = config(dec!(0.01));
+        let err = config
+            .validate(&OrderRequest::new(Side::Buy, dec!(100.000000001), dec!(1)))
+            .unwrap_err();
+        assert_eq!(
+            err,
+            RejectReason::InvalidTickSize {
+                price: dec!(100.000000001),
+                tick_size: dec!(0.01)
+            }
+        );
+        assert!(err.to_string().contains("0.01"));
+
+        // Stop triggers are held to the same grid; market prices are not checked
+        let stop = OrderRequest::market(Side::Sell, dec!(1)).with_stop_price(dec!(95.005));
+        assert!(config.validate(&stop).is_err());
+        assert!(config.validate(&OrderRequest::market(Side::Sell, dec!(1))).is_ok());
+    }
+
+    #[test]
+    fn test_scale_limits_ignore_trailing_zeros() {
+        let config = SymbolConfig {
+            price_scale: Some(2),
+            quantity_scale: Some(4),
+            ..SymbolConfig::new("BTC/USD")
+        };
+        for (price, qty) in [(dec!(100.10), dec!(1.0000)), (dec!(100.100000), dec!(0.12340)), (dec!(100), dec!(1))] {
+            assert!(config.validate(&OrderRequest::new(Side::Buy, price, qty)).is_ok(), "{price} {qty}");
+        }
+
+        let err = config
+            .validate(&OrderRequest::new(Side::Buy, dec!(100.123456789012345678), dec!(1)))
+            .unwrap_err();
+        assert_eq!(err.code(), "PRICE_SCALE_EXCEEDED");
+        assert!(err.to_string().contains("2 decimal places"), "{err}");
+        assert_eq!(
+            config.validate(&OrderRequest::new(Side::Buy, dec!(100), dec!(0.00001))).unwrap_err(),
+            RejectReason::QuantityScaleExceeded {
+                quantity: dec!(0.00001),
+                max_scale: 4
+            }
+        );
+
+        // Stop triggers count as prices; a market order's placeholder price does not
+        let stop = OrderRequest::market(Side::Sell, dec!(1)).with_stop_price(dec!(95.001));
+        assert_eq!(config.validate(&stop).unwrap_err().code(), "PRICE_SCALE_EXCEEDED");
+        let mut market = OrderRequest::market(Side::Sell, dec!(1));
+        market.price = dec!(0.001);
+        assert!(config.validate(&market).is_ok());
+    }
+
+    #[test]
+    fn test_price_grid_falls_back_to_scale_then_default() {
+        let scaled = SymbolConfig {
+            price_scale: Some(2),
+            ..SymbolConfig::new("BTC/USD")
+        };
+        assert_eq!(scaled.price_grid().tick(), dec!(0.01));
+        assert_eq!(config(dec!(0.5)).price_grid().tick(), dec!(0.5));
+
+        let unruled = SymbolConfig::new("BTC/USD");
+        assert_eq!(unruled.price_grid(), PriceGrid::default());
+        assert!(unruled.validate(&OrderRequest::new(Side::Buy, dec!(100.12345678), dec!(1))).is_ok());
+        assert_eq!(
+            unruled
+                .validate(&OrderRequest::new(Side::Buy, dec!(100.123456789), dec!(1)))
+                .unwrap_err()
+                .code(),
+            "INVALID_TICK_SIZE"
+        );
+        assert_eq!(
+            unruled
+                .validate(&OrderRequest::new(Side::Buy, dec!(1000000000000), dec!(1)))
+                .unwrap_err()
+                .code(),
+            "PRICE_OUT_OF_RANGE"
+        );
+    }
+
+    #[test]
+    fn test_non_decimal_tick() {
+        let config = config(dec!(0.5));
+        assert!(config.validate(&OrderRequest::new(Side::Sell, dec!(101.5), dec!(1))).is_ok());
+        assert!(config.validate(&OrderRequest::new(Side::Sell, dec!(101.25), dec!(1))).is_err());
+    }
+
+    #[test]
+    fn test_lot_size_and_min_quantity() {
+        let config = SymbolConfig {
+            lot_size: Some(dec!(0.001)),
+            min_quantity: Some(dec!(0.01)),
+            ..SymbolConfig::new("BTC/USD")
+        };
+        let order = |qty| OrderRequest::new(Side::Buy, dec!(100), qty);
+
+        assert!(config.validate(&order(dec!(0.01))).is_ok());
+        assert!(config.validate(&order(dec!(1.2340))).is_ok());
+        assert_eq!(
+            config.validate(&order(dec!(0.0105))).unwrap_err(),
+            RejectReason::InvalidLotSize {
+                quantity: dec!(0.0105),
+                lot_size: dec!(0.001)
+            }
+        );
+        assert_eq!(
+            config.validate(&order(dec!(0.009))).unwrap_err().code(),
+            "BELOW_MIN_QUANTITY"
+        );
+
+        let lot_only = SymbolConfig {
+            lot_size: Some(dec!(0.001)),
+            ..SymbolConfig::new("BTC/USD")
+        };
+        assert_eq!(lot_only.validate(&order(dec!(0.0005))).unwrap_err().code(), "INVALID_LOT_SIZE");
+    }
+
+    #[test]
+    fn test_min_notional() {
+        let config = SymbolConfig {
+            min_notional: Some(dec!(10)),
+            ..SymbolConfig::new("BTC/USD")
+        };
+
+        assert!(config.validate(&OrderRequest::new(Side::Buy, dec!(100), dec!(0.1))).is_ok());
+        let err = config
+            .validate(&OrderRequest::new(Side::Buy, dec!(100), dec!(0.0999)))
+            .unwrap_err();
+        assert_eq!(
+            err,
+            RejectReason::BelowMinNotional {
+                notional: dec!(9.99),
+                min_notional: dec!(10)
+            }
+        );
+        assert!(err.to_string().contains("9.99"));
+        assert!(config.validate(&OrderRequest::market(Side::Buy, dec!(0.0001))).is_ok());
+    }
+
+    #[test]
+    fn test_max_quantity_and_notional_boundaries() {
+        let config = SymbolConfig {
+            max_quantity: Some(dec!(10)),
+            max_notional: Some(dec!(500)),
+            ..SymbolConfig::new("BTC/USD")
+        };
+
+        // Exactly at either limit passes
+        assert!(config.validate(&OrderRequest::new(Side::Buy, dec!(10), dec!(10))).is_ok());
+        assert!(config.validate(&OrderRequest::new(Side::Buy, dec!(100), dec!(5))).is_ok());
+        assert_eq!(
+            config.validate(&OrderRequest::new(Side::Buy, dec!(1), dec!(10.0001))).unwrap_err(),
+            RejectReason::MaxQuantityExceeded {
+                quantity: dec!(10.0001),
+                max_quantity: dec!(10)
+            }
+        );
+        let err = config
+            .validate(&OrderRequest::new(Side::Sell, dec!(100.01), dec!(5)))
+            .unwrap_err();
+        assert_eq!(err.code(), "MAX_NOTIONAL_EXCEEDED");
+        assert!(err.to_string().contains("500.05"));
+
+        // Market orders are only held to the quantity limit
+        assert!(config.validate(&OrderRequest::market(Side::Buy, dec!(10))).is_ok());
+        assert_eq!(
+            config.validate(&OrderRequest::market(Side::Buy, dec!(11))).unwrap_err().code(),
+            "MAX_QTY_EXCEEDED"
+        );
+
+        // Trusted flow can opt out of the risk limits, but not the symbol rules
+        let mut bypass = OrderRequest::new(Side::Buy, dec!(100), dec!(50));
+        bypass.skip_risk_checks = true;
+        assert!(config.validate(&bypass).is_ok());
+    }
+
+    #[test]
+    fn test_min_quantity_at_satisfies_every_rule() {
+        let config = SymbolConfig {
+            lot_size: Some(dec!(0.001)),
+            min_quantity: Some(dec!(0.002)),
+            min_notional: Some(dec!(1)),
+            ..SymbolConfig::new("BTC/USD")
+        };
+
+        for price in [dec!(3), dec!(95.37), dec!(1000), dec!(0.5)] {
+            let quantity = config.min_quantity_at(price);
+            assert!(config.validate(&OrderRequest::new(Side::Buy, price, quantity)).is_ok(), "{price}");
+        }
+        assert_eq!(config.min_quantity_at(dec!(3)), dec!(0.334));
+        assert_eq!(config.min_quantity_at(dec!(1000)), dec!(0.002));
+        assert_eq!(SymbolConfig::new("BTC/USD").min_quantity_at(dec!(100)), Decimal::ZERO);
+    }
+
+    #[test]
+    fn test_reference_price_fallbacks() {
+        let reference = SymbolConfig::reference_price;
+        assert_eq!(reference(Some(dec!(101)), Some(dec!(99)), Some(dec!(100))), Some(dec!(101)));
+        assert_eq!(reference(None, Some(dec!(99)), Some(dec!(100))), Some(dec!(99.5)));
+        assert_eq!(reference(None, None, Some(dec!(100))), Some(dec!(100)));
+        assert_eq!(reference(None, None, None), None);
+    }
+
+    #[test]
+    fn test_price_band() {
+        let config = SymbolConfig {
+            price_band_pct: Some(dec!(5)),
+            ..SymbolConfig::new("BTC/USD")
+        };
+        let limit = |price| OrderRequest::new(Side::Buy, price, dec!(1));
+
+        assert!(config.check_price_band(&limit(dec!(105)), Some(dec!(100))).is_ok());
+        assert!(config.check_price_band(&limit(dec!(95)), Some(dec!(100))).is_ok());
+        assert_eq!(
+            config.check_price_band(&limit(dec!(105.01)), Some(dec!(100))).unwrap_err(),
+            RejectReason::PriceOutsideBand {
+                price: dec!(105.01),
+                reference: dec!(100),
+                band_pct: dec!(5)
+            }
+        );
+        assert!(config.check_price_band(&limit(dec!(94.99)), Some(dec!(100))).is_err());
+
+        // Bootstrap: nothing to compare against yet
+        assert!(config.check_price_band(&limit(dec!(1000000)), None).is_ok());
+        // Market orders and pending stops are not banded
+        assert!(config.check_price_band(&OrderRequest::market(Side::Buy, dec!(1)), Some(dec!(100))).is_ok());
+        let stop_limit = limit(dec!(200)).with_stop_price(dec!(190));
+        assert!(config.check_price_band(&stop_limit, Some(dec!(100))).is_ok());
+    }
+
+    #[test]
+    fn test_no_rules_accepts_anything() {
+        let config = SymbolConfig::new("BTC/USD");
+        // Only the book's default eight-place grid constrains the price
+        assert!(config.validate(&OrderRequest::new(Side::Buy, dec!(100.00000001), dec!(0.000000001))).is_ok());
+    }
+}
@@ -0,0 +1,109 @@
+//! Integer tick prices for the order book's hot path.
+//!
+//! The book keys its levels by whole numbers of ticks so matching compares
+//! and orders `i64`s instead of `Decimal`s. Prices are converted once on the
+//! way in and back only where a `Decimal` is reported.
+
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+
+/// A price as a whole number of ticks
+pub type Ticks = i64;
+
+/// Why a price has no exact tick count
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum TickError {
+    #[error("price is not a multiple of the tick")]
+    OffGrid,
+    #[error("price is too large to count in ticks")]
+    OutOfRange,
+}
+
+/// The prices a book can hold: every whole multiple of one tick
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PriceGrid {
+    tick: Decimal,
+}
+
+impl PriceGrid {
+    /// Grid for symbols with neither a tick size nor a price scale: eight
+    /// decimal places
+    pub const DEFAULT_TICK: Decimal = Decimal::from_parts(1, 0, 0, false, 8);
+
+    /// Grid with the given tick, which must be positive
+    pub fn new(tick: Decimal) -> Self {
+        assert!(tick > Decimal::ZERO, "tick must be positive, got {tick}");
+        Self { tick: tick.normalize() }
+    }
+
+    /// Size of one tick
+    pub fn tick(&self) -> Decimal {
+        self.tick
+    }
+
+    /// Exact tick count of a price on the grid
+    pub fn to_ticks(&self, price: Decimal) -> Result<Ticks, TickError> {
+        let ticks = price.checked_div(self.tick).ok_or(TickError::OutOfRange)?;
+        if !ticks.fract().is_zero() {
+            return Err(TickError::OffGrid);
+        }
+        ticks.to_i64().ok_or(TickError::OutOfRange)
+    }
+
+    /// Tick count of the highest grid price at or below `price`
+    pub fn floor_ticks(&self, price: Decimal) -> Ticks {
+        self.saturating(price, Decimal::floor)
+    }
+
+    /// Tick count of the lowest grid price at or above `price`
+    pub fn ceil_ticks(&self, price: Decimal) -> Ticks {
+        self.saturating(price, Decimal::ceil)
+    }
+
+    /// The price `ticks` ticks above zero, without trailing zeros
+    pub fn to_price(&self, ticks: Ticks) -> Decimal {
+        (Decimal::from(ticks) * self.tick).normalize()
+    }
+
+    fn saturating(&self, price: Decimal, round: impl Fn(&Decimal) -> Decimal) -> Ticks {
+        match price.checked_div(self.tick) {
+            Some(ticks) => round(&ticks).to_i64().unwrap_or(if ticks.is_sign_negative() { Ticks::MIN } else { Ticks::MAX }),
+            None if price.is_sign_negative() => Ticks::MIN,
+            None => Ticks::MAX,
+        }
+    }
+}
+
+impl Default for PriceGrid {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_TICK)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_round_trip_ignores_scale() {
+        let grid = PriceGrid::new(dec!(0.01));
+        assert_eq!(grid.to_ticks(dec!(100.5)), Ok(10_050));
+        assert_eq!(grid.to_ticks(dec!(100.500)), Ok(10_050));
+        assert_eq!(grid.to_price(10_050).to_string(), "100.5");
+        assert_eq!(grid.to_ticks(dec!(100.005)), Err(TickError::OffGrid));
+        assert_eq!(PriceGrid::new(dec!(0.5)).to_ticks(dec!(101.25)), Err(TickError::OffGrid));
+    }
+
+    #[test]
+    fn test_out_of_range_and_rounding() {
+        let grid = PriceGrid::default();
+        assert_eq!(grid.to_ticks(dec!(100000000000)), Err(TickError::OutOfRange));
+        assert_eq!(grid.to_ticks(dec!(0.000000001)), Err(TickError::OffGrid));
+
+        let grid = PriceGrid::new(dec!(0.25));
+        assert_eq!((grid.floor_ticks(dec!(100.3)), grid.ceil_ticks(dec!(100.3))), (401, 402));
+        assert_eq!((grid.floor_ticks(dec!(100.5)), grid.ceil_ticks(dec!(100.5))), (402, 402));
+        assert_eq!(grid.ceil_ticks(Decimal::MAX), Ticks::MAX);
+    }
+}
@@ -0,0 +1,709 @@
+//! Write-ahead log of the commands that change engine state.
+//!
+//! Every state-changing command is appended, one JSON line per record,
+//! before the engine processes it. A record carries the time the engine
+//! processed the command at and the epoch its ids were known by, so
+//! replaying the log on top of the last snapshot rebuilds exactly the same
+//! book: same timestamps, same order and trade ids, same events.
+//!
+//! The log is kept as segments named by their first record's sequence,
+//! `BTC-USD-00000000000000000001.wal`. A segment is closed once it passes
+//! its size limit, and closed segments whose records a saved snapshot
+//! already covers are deleted by `prune_wal`.
+//!
+//! A command that panicked the engine is voided by a later record, so that
+//! neither the supervisor's rebuild nor a restart replays it into the same
+//! panic.
+//!
+//! Logs from before the engine numbered its own ids name orders and trades
+//! by UUID and no longer parse: shut the engine down cleanly, so its
+//! snapshot covers every record, and delete the segments before upgrading.
+
+use crate::engine::engines::url_key;
+use crate::engine::ids::IdEpoch;
+use crate::engine::matcher::EngineCommand;
+use crate::engine::order::{OrderId, OrderRequest, QuoteRequest, Side, TradeId};
+use crate::engine::rate_limit::RateLimit;
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use tokio::sync::oneshot;
+
+/// Environment variable choosing the fsync policy: `always`, `never` or
+/// a number of records between syncs
+pub const WAL_FSYNC_VAR: &str = "CLOB_WAL_FSYNC";
+
+/// Size past which a segment is closed and a new one started
+pub const DEFAULT_SEGMENT_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Why the log could not be read or written
+#[derive(Debug, thiserror::Error)]
+pub enum WalError {
+    #[error("cannot access {path}: {source}")]
+    Io { path: String, source: std::io::Error },
+    #[error("invalid record at {path}:{line}: {source}")]
+    Json {
+        path: String,
+        line: usize,
+        source: serde_json::Error,
+    },
+    #[error("invalid fsync policy {0:?}")]
+    InvalidFsync(String),
+}
+
+/// When appended records are forced to disk. Every record is written to
+/// the file before its command runs, so a crash of the process alone loses
+/// nothing; the policy decides how much a crash of the machine can.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FsyncPolicy {
+    /// Sync after every record
+    #[default]
+    Always,
+    /// Sync after every `n` records, and when a segment is closed
+    Every(u32),
+    /// Leave flushing to the operating system
+    Never,
+}
+
+impl FromStr for FsyncPolicy {
+    type Err = WalError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "always" => Ok(Self::Always),
+            "never" => Ok(Self::Never),
+            n => match n.parse() {
+                Ok(0) | Err(_) => Err(WalError::InvalidFsync(s.to_string())),
+                Ok(n) => Ok(Self::Every(n)),
+            },
+        }
+    }
+}
+
+/// A command as logged: the state-changing `EngineCommand`s, without
+/// their reply channels
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LoggedCommand {
+    /// A submission, confirmed or not
+    Submit(OrderRequest),
+    /// A cancel, confirmed or not; only confirmed ones name an owner
+    Cancel {
+        order_id: OrderId,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        owner_id: Option<String>,
+    },
+    CancelAll {
+        owner_id: String,
+        side: Option<Side>,
+    },
+    Quote(QuoteRequest),
+    KillSwitch {
+        owner_id: String,
+    },
+    ReleaseKillSwitch {
+        owner_id: String,
+    },
+    BustTrade {
+        trade_id: TradeId,
+    },
+    Modify {
+        order_id: OrderId,
+        new_price: Option<Decimal>,
+        new_quantity: Option<Decimal>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        new_order_id: Option<OrderId>,
+    },
+    ExpireOrders,
+    StartAuction,
+    EndAuction,
+    SetBookUpdates {
+        depth_levels: Option<usize>,
+        min_update_interval: Option<Duration>,
+    },
+    SetRateLimit {
+        owner_id: Option<String>,
+        limit: Option<RateLimit>,
+    },
+    /// Record `seq` is not to be replayed: its command panicked the engine
+    Void {
+        seq: u64,
+    },
+}
+
+impl LoggedCommand {
+    /// The logged form of `command`; `None` for queries and shutdown,
+    /// which change nothing a replay needs
+    pub fn of(command: &EngineCommand) -> Option<Self> {
+        Some(match command {
+            EngineCommand::Submit(request) | EngineCommand::SubmitConfirmed { request, .. } => {
+                Self::Submit(request.clone())
+            }
+            EngineCommand::Cancel { order_id } => Self::Cancel {
+                order_id: *order_id,
+                owner_id: None,
+            },
+            EngineCommand::CancelConfirmed { order_id, owner_id, .. } => Self::Cancel {
+                order_id: *order_id,
+                owner_id: owner_id.clone(),
+            },
+            EngineCommand::CancelAll { owner_id, side } => Self::CancelAll {
+                owner_id: owner_id.clone(),
+                side: *side,
+            },
+            EngineCommand::Quote(quote) => Self::Quote(quote.clone()),
+            EngineCommand::KillSwitch { owner_id } => Self::KillSwitch {
+                owner_id: owner_id.clone(),
+            },
+            EngineCommand::ReleaseKillSwitch { owner_id } => Self::ReleaseKillSwitch {
+                owner_id: owner_id.clone(),
+            },
+            EngineCommand::BustTrade { trade_id } => Self::BustTrade { trade_id: *trade_id },
+            EngineCommand::Modify {
+                order_id,
+                new_price,
+                new_quantity,
+                new_order_id,
+            } => Self::Modify {
+                order_id: *order_id,
+                new_price: *new_price,
+                new_quantity: *new_quantity,
+                new_order_id: *new_order_id,
+            },
+            EngineCommand::ExpireOrders => Self::ExpireOrders,
+            EngineCommand::StartAuction => Self::StartAuction,
+            EngineCommand::EndAuction => Self::EndAuction,
+            EngineCommand::SetBookUpdates {
+                depth_levels,
+                min_update_interval,
+            } => Self::SetBookUpdates {
+                depth_levels: *depth_levels,
+                min_update_interval: *min_update_interval,
+            },
+            EngineCommand::SetRateLimit { owner_id, limit } => Self::SetRateLimit {
+                owner_id: owner_id.clone(),
+                limit: *limit,
+            },
+            EngineCommand::GroupedBook { .. }
+            | EngineCommand::DeepBook { .. }
+            | EngineCommand::Liquidity { .. }
+            | EngineCommand::PriceImpact { .. }
+            | EngineCommand::QueryLatency { .. }
+            | EngineCommand::QueryStats { .. }
+            | EngineCommand::QueryWindow { .. }
+            | EngineCommand::QueryOrder { .. }
+            | EngineCommand::ListOrders { .. }
+            | EngineCommand::Snapshot { .. }
+            | EngineCommand::Shutdown { .. } => return None,
+        })
+    }
+
+    /// The command to process again on replay; `None` for a void record,
+    /// which changes nothing itself
+    pub fn into_command(self) -> Option<EngineCommand> {
+        Some(match self {
+            Self::Submit(request) => EngineCommand::Submit(request),
+            Self::Cancel {
+                order_id,
+                owner_id: None,
+            } => EngineCommand::Cancel { order_id },
+            // Nobody waits on a replayed cancel, but its owner still decides it
+            Self::Cancel { order_id, owner_id } => EngineCommand::CancelConfirmed {
+                order_id,
+                owner_id,
+                reply: oneshot::channel().0,
+            },
+            Self::CancelAll { owner_id, side } => EngineCommand::CancelAll { owner_id, side },
+            Self::Quote(quote) => EngineCommand::Quote(quote),
+            Self::KillSwitch { owner_id } => EngineCommand::KillSwitch { owner_id },
+            Self::ReleaseKillSwitch { owner_id } => EngineCommand::ReleaseKillSwitch { owner_id },
+            Self::BustTrade { trade_id } => EngineCommand::BustTrade { trade_id },
+            Self::Modify {
+                order_id,
+                new_price,
+                new_quantity,
+                new_order_id,
+            } => EngineCommand::Modify {
+                order_id,
+                new_price,
+                new_quantity,
+                new_order_id,
+            },
+            Self::ExpireOrders => EngineCommand::ExpireOrders,
+            Self::StartAuction => EngineCommand::StartAuction,
+            Self::EndAuction => EngineCommand::EndAuction,
+            Self::SetBookUpdates {
+                depth_levels,
+                min_update_interval,
+            } => EngineCommand::SetBookUpdates {
+                depth_levels,
+                min_update_interval,
+            },
+            Self::SetRateLimit { owner_id, limit } => EngineCommand::SetRateLimit { owner_id, limit },
+            Self::Void { .. } => return None,
+        })
+    }
+}
+
+/// One logged command, with what the engine needs to process it again
+/// exactly as it did the first time
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WalRecord {
+    /// Position in the log, from 1
+    pub seq: u64,
+    /// Engine time the command was processed at
+    pub at: DateTime<Utc>,
+    /// Epoch of the engine that processed it
+    pub epoch: IdEpoch,
+    /// Processed in the same batch as the record before it, so the book
+    /// was published only once for both
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub batched: bool,
+    pub command: LoggedCommand,
+}
+
+/// Appends records to the current segment, rotating by size
+#[derive(Debug)]
+pub struct WalWriter {
+    dir: PathBuf,
+    key: String,
+    fsync: FsyncPolicy,
+    max_segment_bytes: u64,
+    /// Open segment, created on the first append after a rotation
+    segment: Option<(PathBuf, File)>,
+    segment_bytes: u64,
+    unsynced: u32,
+    next_seq: u64,
+}
+
+impl WalWriter {
+    /// Open `symbol`'s log in `dir`, returning a writer that carries on
+    /// after the last record and every record logged so far, oldest first.
+    /// Appends always start a new segment, so a record torn by a crash is
+    /// never appended to.
+    pub fn open(dir: &Path, symbol: &str, fsync: FsyncPolicy) -> Result<(Self, Vec<WalRecord>), WalError> {
+        let key = url_key(symbol);
+        let segments = segments(dir, &key)?;
+        let mut records = Vec::new();
+        for (i, (_, path)) in segments.iter().enumerate() {
+            read_segment(path, i + 1 == segments.len(), &mut records)?;
+        }
+        let next_seq = records
+            .last()
+            .map(|r| r.seq + 1)
+            .max(segments.last().map(|&(start, _)| start))
+            .unwrap_or(1);
+
+        let writer = Self {
+            dir: dir.to_path_buf(),
+            key,
+            fsync,
+            max_segment_bytes: DEFAULT_SEGMENT_BYTES,
+            segment: None,
+            segment_bytes: 0,
+            unsynced: 0,
+            next_seq,
+        };
+        Ok((writer, records))
+    }
+
+    /// Close segments once they pass `bytes`
+    pub fn with_segment_bytes(mut self, bytes: u64) -> Self {
+        self.max_segment_bytes = bytes;
+        self
+    }
+
+    /// Sequence the next record will get
+    pub fn next_seq(&self) -> u64 {
+        self.next_seq
+    }
+
+    /// Number the next record `seq` or later, as when a snapshot has
+    /// already covered records this log no longer holds
+    pub fn skip_to(&mut self, seq: u64) {
+        if seq > self.next_seq {
+            self.close_segment();
+            self.next_seq = seq;
+        }
+    }
+
+    /// Append one command, `batched` with the one before, synced as the
+    /// policy says. Returns its sequence.
+    pub fn append(
+        &mut self,
+        at: DateTime<Utc>,
+        epoch: IdEpoch,
+        batched: bool,
+        command: LoggedCommand,
+    ) -> Result<u64, WalError> {
+        let record = WalRecord {
+            seq: self.next_seq,
+            at,
+            epoch,
+            batched,
+            command,
+        };
+        if self.segment_bytes >= self.max_segment_bytes {
+            self.close_segment();
+        }
+        let (path, file) = match &mut self.segment {
+            Some(segment) => segment,
+            None => {
+                let path = segment_path(&self.dir, &self.key, record.seq);
+                let file = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&path)
+                    .map_err(|source| io_error(&path, source))?;
+                self.segment_bytes = 0;
+                self.segment.insert((path, file))
+            }
+        };
+
+        let mut line = serde_json::to_vec(&record).expect("records always serialize");
+        line.push(b'\n');
+        file.write_all(&line).map_err(|source| io_error(path, source))?;
+        self.segment_bytes += line.len() as u64;
+
+        self.unsynced += 1;
+        let sync = match self.fsync {
+            FsyncPolicy::Always => true,
+            FsyncPolicy::Every(n) => self.unsynced >= n,
+            FsyncPolicy::Never => false,
+        };
+        if sync {
+            file.sync_data().map_err(|source| io_error(path, source))?;
+            self.unsynced = 0;
+        }
+
+        self.next_seq += 1;
+        Ok(record.seq)
+    }
+
+    /// Sync and close the open segment; the next append starts another
+    fn close_segment(&mut self) {
+        if let Some((path, file)) = self.segment.take() {
+            if self.fsync != FsyncPolicy::Never && self.unsynced > 0 {
+                if let Err(e) = file.sync_data() {
+                    tracing::error!("Cannot sync {}: {e}", path.display());
+                }
+            }
+            self.unsynced = 0;
+        }
+    }
+}
+
+impl Drop for WalWriter {
+    fn drop(&mut self) {
+        self.close_segment();
+    }
+}
+
+/// Delete `symbol`'s closed segments whose records all have sequences up
+/// to `through`, as covered by a saved snapshot. The newest segment is
+/// always kept. Returns how many were deleted.
+pub fn prune_wal(dir: &Path, symbol: &str, through: u64) -> Result<usize, WalError> {
+    let segments = segments(dir, &url_key(symbol))?;
+    let mut pruned = 0;
+    for pair in segments.windows(2) {
+        let ((_, path), (next_start, _)) = (&pair[0], &pair[1]);
+        if *next_start > through + 1 {
+            break;
+        }
+        std::fs::remove_file(path).map_err(|source| io_error(path, source))?;
+        pruned += 1;
+    }
+    Ok(pruned)
+}
+
+fn segment_path(dir: &Path, key: &str, start: u64) -> PathBuf {
+    dir.join(format!("{key}-{start:020}.wal"))
+}
+
+/// `key`'s segments in `dir`, by first sequence
+fn segments(dir: &Path, key: &str) -> Result<Vec<(u64, PathBuf)>, WalError> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(source) => return Err(io_error(dir, source)),
+    };
+    let prefix = format!("{key}-");
+    let mut segments = Vec::new();
+    for entry in entries {
+        let path = entry.map_err(|source| io_error(dir, source))?.path();
+        let start = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_prefix(&prefix)?.strip_suffix(".wal")?.parse().ok());
+        if let Some(start) = start {
+            segments.push((start, path));
+        }
+    }
+    segments.sort();
+    Ok(segments)
+}
+
+/// Read one segment's records into `records`. A final line that does not
+/// parse in the newest segment is a write cut short by a crash: its command
+/// never ran, so it is dropped.
+fn read_segment(path: &Path, newest: bool, records: &mut Vec<WalRecord>) -> Result<(), WalError> {
+    let file = File::open(path).map_err(|source| io_error(path, source))?;
+    let mut lines = BufReader::new(file).lines().enumerate().peekable();
+    while let Some((i, line)) = lines.next() {
+        let line = line.map_err(|source| io_error(path, source))?;
+        match serde_json::from_str(&line) {
+            Ok(record) => records.push(record),
+            Err(_) if newest && lines.peek().is_none() => {
+                tracing::warn!("Dropping torn record at the end of {}", path.display());
+            }
+            Err(source) => {
+                return Err(WalError::Json {
+                    path: path.display().to_string(),
+                    line: i + 1,
+                    source,
+                })
+            }
+        }
+    }
+    Ok(())
+}
+
+fn io_error(path: &Path, source: std::io::Error) -> WalError {
+    WalError::Io {
+        path: path.display().to_string(),
+        source,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::clock::ManualClock;
+    use crate::engine::matcher::{EngineBuilder, MatchingEngine};
+    use crate::engine::rolling::PastTrade;
+    use crate::engine::scratch::ScratchDir;
+    use crate::engine::snapshot::EngineSnapshot;
+    use chrono::TimeZone;
+    use rust_decimal_macros::dec;
+    use std::sync::Arc;
+    use uuid::Uuid;
+
+    fn segment_starts(dir: &Path) -> Vec<u64> {
+        segments(dir, "BTC-USD").unwrap().into_iter().map(|(start, _)| start).collect()
+    }
+
+    /// An engine logging to `dir`, replaying whatever the log already holds
+    fn logged_engine(dir: &Path, clock: Arc<ManualClock>, snapshot: Option<EngineSnapshot>) -> MatchingEngine {
+        let (wal, records) = WalWriter::open(dir, "BTC/USD", FsyncPolicy::Never).unwrap();
+        let builder = EngineBuilder::new("BTC/USD").clock(clock).write_ahead_log(wal, records);
+        let builder = match snapshot {
+            Some(snapshot) => builder.restore_from(snapshot),
+            None => builder,
+        };
+        builder.build().0
+    }
+
+    /// The engine's state, minus when it was read
+    fn state(engine: &MatchingEngine) -> serde_json::Value {
+        let mut state = serde_json::to_value(engine.snapshot()).unwrap();
+        state["taken_at"] = serde_json::Value::Null;
+        state
+    }
+
+    #[test]
+    fn test_fsync_policy_parsing() {
+        assert_eq!("always".parse::<FsyncPolicy>().unwrap(), FsyncPolicy::Always);
+        assert_eq!("NEVER".parse::<FsyncPolicy>().unwrap(), FsyncPolicy::Never);
+        assert_eq!("100".parse::<FsyncPolicy>().unwrap(), FsyncPolicy::Every(100));
+        assert!("0".parse::<FsyncPolicy>().is_err());
+        assert!("sometimes".parse::<FsyncPolicy>().is_err());
+    }
+
+    #[test]
+    fn test_segments_rotate_and_reopen_after_the_last_record() {
+        let dir = ScratchDir::new("wal-rotate");
+        let at = Utc.with_ymd_and_hms(2030, 1, 1, 0, 0, 0).unwrap();
+        let (wal, records) = WalWriter::open(&dir, "BTC/USD", FsyncPolicy::Every(2)).unwrap();
+        assert!(records.is_empty());
+        // Small enough that every record closes its segment
+        let mut wal = wal.with_segment_bytes(1);
+        for _ in 0..3 {
+            wal.append(at, IdEpoch::new(7), false, LoggedCommand::StartAuction).unwrap();
+        }
+        drop(wal);
+        assert_eq!(segment_starts(&dir), [1, 2, 3]);
+
+        let (wal, records) = WalWriter::open(&dir, "BTC/USD", FsyncPolicy::Always).unwrap();
+        assert_eq!(records.iter().map(|r| r.seq).collect::<Vec<_>>(), [1, 2, 3]);
+        assert_eq!(wal.next_seq(), 4);
+
+        // Segments wholly covered go; the newest always stays
+        assert_eq!(prune_wal(&dir, "BTC/USD", 1).unwrap(), 1);
+        assert_eq!(segment_starts(&dir), [2, 3]);
+        assert_eq!(prune_wal(&dir, "BTC/USD", 10).unwrap(), 1);
+        assert_eq!(segment_starts(&dir), [3]);
+    }
+
+    #[test]
+    fn test_torn_last_record_is_dropped() {
+        let dir = ScratchDir::new("wal-torn");
+        let at = Utc.with_ymd_and_hms(2030, 1, 1, 0, 0, 0).unwrap();
+        let (mut wal, _) = WalWriter::open(&dir, "BTC/USD", FsyncPolicy::Always).unwrap();
+        wal.append(at, IdEpoch::new(7), false, LoggedCommand::StartAuction).unwrap();
+        drop(wal);
+        let path = segment_path(&dir, "BTC-USD", 1);
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(br#"{"seq":2,"at":"2030-"#).unwrap();
+
+        let (wal, records) = WalWriter::open(&dir, "BTC/USD", FsyncPolicy::Always).unwrap();
+        assert_eq!(records.len(), 1);
+        // Numbering carries on from the record that made it, in a fresh segment
+        assert_eq!(wal.next_seq(), 2);
+
+        // Damage anywhere but the end of the newest segment is an error
+        std::fs::write(segment_path(&dir, "BTC-USD", 5), "").unwrap();
+        assert!(matches!(
+            WalWriter::open(&dir, "BTC/USD", FsyncPolicy::Always),
+            Err(WalError::Json { line: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn test_replay_rebuilds_the_engine_after_a_crash() {
+        let dir = ScratchDir::new("wal-replay");
+        let start = Utc.with_ymd_and_hms(2030, 1, 1, 0, 0, 0).unwrap();
+        let clock = Arc::new(ManualClock::new(start));
+        let mut engine = logged_engine(&dir, clock.clone(), None);
+
+        let maker = OrderRequest::new(Side::Sell, dec!(101), dec!(2)).with_owner("maker");
+        // The first order a fresh engine numbers
+        let maker_id = 1;
+        let commands = [
+            EngineCommand::Submit(maker),
+            EngineCommand::Submit(OrderRequest::new(Side::Sell, dec!(102), dec!(1)).with_owner("maker")),
+            EngineCommand::Submit(OrderRequest::new(Side::Buy, dec!(101), dec!(0.5)).with_owner("taker")),
+            // Refused on replay too: the order is not the taker's
+            EngineCommand::CancelConfirmed {
+                order_id: maker_id,
+                owner_id: Some("taker".into()),
+                reply: tokio::sync::oneshot::channel().0,
+            },
+            // A replacement order gets a new id
+            EngineCommand::Modify {
+                order_id: maker_id,
+                new_price: Some(dec!(100)),
+                new_quantity: None,
+                new_order_id: None,
+            },
+            EngineCommand::Submit(
+                OrderRequest::new(Side::Buy, dec!(99), dec!(1))
+                    .with_owner("expiring")
+                    .with_expires_at(start + chrono::Duration::seconds(75)),
+            ),
+            EngineCommand::KillSwitch { owner_id: "taker".into() },
+        ];
+        for command in commands {
+            clock.advance(chrono::Duration::seconds(10));
+            engine.process_command(command);
+        }
+        // Past the expiry: the sweep runs and is logged
+        clock.advance(chrono::Duration::seconds(10));
+        engine.process_command(EngineCommand::ExpireOrders);
+        // Nothing left to expire: not logged
+        engine.process_command(EngineCommand::ExpireOrders);
+        // Queries aren't logged either
+        engine.process_command(EngineCommand::PriceImpact {
+            side: Side::Buy,
+            quantity: dec!(1),
+            reply: tokio::sync::oneshot::channel().0,
+        });
+
+        let expected = state(&engine);
+        assert_eq!(expected["wal_sequence"], 8);
+        assert_eq!(expected["recent_trades"].as_array().unwrap().len(), 1);
+        // The crash: the engine is gone without a shutdown or snapshot
+        drop(engine);
+
+        // A clock hours later: replay uses the logged times, not its own
+        let later = Arc::new(ManualClock::new(start + chrono::Duration::hours(5)));
+        let mut restored = logged_engine(&dir, later.clone(), None);
+        assert_eq!(state(&restored), expected);
+
+        // New commands carry on the log's numbering
+        restored.process_command(EngineCommand::Cancel { order_id: maker_id });
+        assert_eq!(restored.snapshot().wal_sequence, 9);
+        drop(restored);
+
+        // Restoring a snapshot replays only what came after it
+        let mut snapshot_engine = logged_engine(&dir, later.clone(), None);
+        let snapshot = snapshot_engine.snapshot();
+        snapshot_engine.process_command(EngineCommand::StartAuction);
+        let expected = state(&snapshot_engine);
+        drop(snapshot_engine);
+        prune_wal(&dir, "BTC/USD", snapshot.wal_sequence).unwrap();
+        let restored = logged_engine(&dir, later, Some(snapshot));
+        assert_eq!(state(&restored), expected);
+        assert_eq!(restored.snapshot().wal_sequence, 10);
+    }
+
+    #[test]
+    fn test_trade_history_skips_trades_the_log_replays() {
+        let dir = ScratchDir::new("wal-history");
+        let start = Utc.with_ymd_and_hms(2030, 1, 1, 0, 0, 0).unwrap();
+        let clock = Arc::new(ManualClock::new(start));
+        let mut engine = logged_engine(&dir, clock.clone(), None);
+        engine.process_command(EngineCommand::Submit(OrderRequest::new(Side::Sell, dec!(100), dec!(1))));
+        engine.process_command(EngineCommand::Submit(OrderRequest::new(Side::Buy, dec!(100), dec!(1))));
+        drop(engine);
+
+        // The journal holds a trade from before the log, and the one it replays
+        let journaled = |price, quantity, timestamp| PastTrade {
+            id: Uuid::new_v4(),
+            price,
+            quantity,
+            timestamp,
+        };
+        let history = vec![
+            journaled(dec!(90), dec!(2), start - chrono::Duration::hours(1)),
+            journaled(dec!(100), dec!(1), start),
+        ];
+        let (wal, records) = WalWriter::open(&dir, "BTC/USD", FsyncPolicy::Never).unwrap();
+        let (restored, _) = EngineBuilder::new("BTC/USD")
+            .clock(clock)
+            .trade_history(history)
+            .write_ahead_log(wal, records)
+            .build();
+        let last_24h = restored.stats().last_24h;
+        assert_eq!((last_24h.count, last_24h.volume), (2, dec!(3)));
+        assert_eq!((last_24h.open, last_24h.last), (Some(dec!(90)), Some(dec!(100))));
+    }
+
+    #[test]
+    fn test_replay_ends_batches_where_they_ended() {
+        let dir = ScratchDir::new("wal-batches");
+        let clock = Arc::new(ManualClock::new(Utc.with_ymd_and_hms(2030, 1, 1, 0, 0, 0).unwrap()));
+        let mut engine = logged_engine(&dir, clock.clone(), None);
+        engine.process_batch([
+            EngineCommand::Submit(OrderRequest::new(Side::Sell, dec!(101), dec!(1))),
+            EngineCommand::Submit(OrderRequest::new(Side::Sell, dec!(102), dec!(1))),
+            // The batch ends on a command that isn't logged
+            EngineCommand::PriceImpact {
+                side: Side::Buy,
+                quantity: dec!(1),
+                reply: tokio::sync::oneshot::channel().0,
+            },
+        ]);
+        engine.process_batch([EngineCommand::Submit(OrderRequest::new(Side::Buy, dec!(101), dec!(1)))]);
+        let expected = state(&engine);
+        assert_eq!(expected["book_sequence"], 2);
+        drop(engine);
+
+        let restored = logged_engine(&dir, clock, None);
+        assert_eq!(state(&restored), expected);
+    }
+}
@@ -0,0 +1,13 @@
+//! CLOB Engine - High-Frequency Order Matching Engine
+//!
+//! A Central Limit Order Book implementation in Rust with:
+//! - Single-threaded matching engine (no locks in hot path)
+//! - Async order ingestion via Tokio channels
+//! - Real-time WebSocket market data streaming
+//! - Async trade persistence to PostgreSQL
+
+pub mod api;
+pub mod broadcast;
+pub mod engine;
+pub mod persistence;
+pub mod simulation;
@@ -1,25 +1,19 @@
-//! CLOB Engine - High-Frequency Order Matching Engine
-//!
-//! A Central Limit Order Book implementation in Rust with:
-//! - Single-threaded matching engine (no locks in hot path)
-//! - Async order ingestion via Tokio channels
-//! - Real-time WebSocket market data streaming
-//! - Async trade persistence to PostgreSQL
-
-mod api;
-mod broadcast;
-mod engine;
-mod persistence;
-mod simulation;
-
-use api::{get_order_book, health_check, run_simulation, submit_order, ws_handler};
-use axum::{
-    routing::{get, post},
-    Router,
+//! CLOB Engine server binary.
+
+use clob_backend::api::{self, KeyStore, Throttle};
+use clob_backend::engine::{
+    rolling, run_snapshotter, snapshot_path, EngineRegistry, EngineSnapshot, EngineThreads,
+    FsyncPolicy, RateLimit, SymbolRegistry, WalWriter, ENGINE_THREADS_VAR, RATE_LIMIT_VAR, SNAPSHOT_DIR_VAR,
+    WAL_FSYNC_VAR,
+};
+use clob_backend::persistence::{
+    start_mock_journaler, CandleArchive, OrderArchive, TradeArchive, TradeJournaler, DEFAULT_TRADE_HISTORY_LIMIT,
+    TRADE_HISTORY_LIMIT_VAR,
 };
-use engine::EngineBuilder;
-use persistence::start_mock_journaler;
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tower_http::cors::{Any, CorsLayer};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
@@ -36,19 +30,135 @@ async fn main() {
 
     tracing::info!("Starting CLOB Engine...");
 
-    // Build the matching engine
-    let (engine, handle) = EngineBuilder::new("BTC/USD").build();
-    let handle = Arc::new(handle);
+    // Trading rules: built-in defaults, then CLOB_SYMBOLS_FILE and env overrides
+    let symbols = SymbolRegistry::load().expect("invalid symbol configuration");
+
+    // Test builds run on a manual clock driven through /api/test/clock,
+    // shared by every engine
+    #[cfg(feature = "test-util")]
+    let clock: clob_backend::engine::SharedClock =
+        Arc::new(clob_backend::engine::ManualClock::new(chrono::Utc::now()));
+
+    // Engine state is saved here, with the write-ahead log of the commands
+    // since, and picked up again on the next start
+    let snapshot_dir = PathBuf::from(std::env::var(SNAPSHOT_DIR_VAR).unwrap_or_else(|_| "snapshots".to_string()));
+    std::fs::create_dir_all(&snapshot_dir).expect("cannot create the snapshot directory");
+    let fsync: FsyncPolicy = match std::env::var(WAL_FSYNC_VAR) {
+        Ok(policy) => policy.parse().expect("invalid fsync policy"),
+        Err(_) => FsyncPolicy::default(),
+    };
+
+    // Engines run as tasks unless given threads, pinned in symbol order
+    let threads: EngineThreads = match std::env::var(ENGINE_THREADS_VAR) {
+        Ok(threads) => threads.parse().expect("invalid engine threads"),
+        Err(_) => EngineThreads::default(),
+    };
+
+    // Orders each owner may send per engine, unlimited unless set
+    let rate_limit: Option<RateLimit> =
+        std::env::var(RATE_LIMIT_VAR).ok().map(|limit| limit.parse().expect("invalid rate limit"));
+
+    // Trades are journaled to PostgreSQL when DATABASE_URL is set, and the
+    // last day of them starts each engine's 24h statistics
+    let journaler = match std::env::var("DATABASE_URL") {
+        Ok(url) => {
+            let journaler = TradeJournaler::new(&url).await.expect("cannot connect to the database");
+            journaler.run_migrations().await.expect("database migrations failed");
+            Some(journaler)
+        }
+        Err(_) => None,
+    };
+    let mut histories = HashMap::new();
+    if let Some(journaler) = &journaler {
+        let limit = match std::env::var(TRADE_HISTORY_LIMIT_VAR) {
+            Ok(limit) => limit.parse().expect("invalid trade history limit"),
+            Err(_) => DEFAULT_TRADE_HISTORY_LIMIT,
+        };
+        let since = chrono::Utc::now() - rolling::window();
+        for config in symbols.iter() {
+            let trades =
+                journaler.trade_history(&config.symbol, since, limit).await.expect("cannot load trade history");
+            histories.insert(config.symbol.clone(), trades);
+        }
+    }
+    let histories = Mutex::new(histories);
+    // Orders that have left their engine, and trades and candles older than
+    // it keeps, are looked up in the journal
+    let reader = journaler.as_ref().map(|journaler| Arc::new(journaler.reader()));
+
+    // One matching engine per configured symbol, each resuming from its
+    // last snapshot and replaying the log recorded after it. An engine that
+    // panics is built the same way again, and carries on where it was.
+    let configured = symbols.clone();
+    let dir = snapshot_dir.clone();
+    let mut engines = EngineRegistry::start_supervised(&symbols, move |builder| {
+        let (symbols, snapshot_dir) = (&configured, &dir);
+        let index = symbols.iter().position(|config| config.symbol == builder.symbol()).unwrap_or_default();
+        let builder = builder.max_open_orders(200).mode(threads.mode(index)).journal(snapshot_dir);
+        let builder = match rate_limit {
+            Some(limit) => builder.rate_limit(limit),
+            None => builder,
+        };
+        #[cfg(feature = "test-util")]
+        let builder = builder.clock(clock.clone());
+        let builder = match EngineSnapshot::load(&snapshot_path(snapshot_dir, builder.symbol()))
+            .expect("unreadable snapshot")
+        {
+            Some(snapshot) => {
+                tracing::info!(
+                    symbol = %snapshot.symbol,
+                    orders = snapshot.resting.len(),
+                    taken_at = %snapshot.taken_at,
+                    "Restoring engine from snapshot"
+                );
+                builder.restore_from(snapshot)
+            }
+            None => builder,
+        };
+        // Only the first build loads history: a rebuilt engine keeps its own
+        let history = histories.lock().unwrap().remove(builder.symbol()).unwrap_or_default();
+        let (wal, records) =
+            WalWriter::open(snapshot_dir, builder.symbol(), fsync).expect("unreadable write-ahead log");
+        builder.trade_history(history).write_ahead_log(wal, records)
+    });
+
+    // API keys are checked when CLOB_API_KEYS_FILE names a key file
+    let keys = KeyStore::load().expect("invalid API key configuration");
+    match &keys {
+        Some(keys) => tracing::info!(keys = keys.len(), "API keys required"),
+        None => tracing::warn!("No API key file; every endpoint is open"),
+    }
 
-    // Spawn the matching engine task
-    tokio::spawn(engine.run());
+    // Requests each client may send, by API key or else by address
+    let throttle = Throttle::from_env().expect("invalid HTTP rate limit");
 
-    // Start mock journaler (use TradeJournaler for real DB)
-    // To use real PostgreSQL:
-    // let journaler = TradeJournaler::new(&std::env::var("DATABASE_URL").unwrap()).await.unwrap();
-    // journaler.run_migrations().await.unwrap();
-    // tokio::spawn(journaler.run((*handle).clone()));
-    let _journaler_shutdown = start_mock_journaler((*handle).clone());
+    // The symbol also served at the unprefixed paths
+    let symbol = std::env::var("CLOB_SYMBOL").unwrap_or_else(|_| "BTC/USD".to_string());
+    if !engines.set_default(&symbol) {
+        panic!("symbol {symbol} is not configured");
+    }
+
+    // Engines sweep good-till-date orders off their books every tick. On
+    // the manual clock, sweep as soon as it is moved too.
+    #[cfg(feature = "test-util")]
+    for handle in engines.iter() {
+        tokio::spawn(clob_backend::engine::run_expiry_sweeper((**handle).clone(), Duration::from_secs(3600)));
+    }
+
+    // Periodically save every engine's state, so restarts replay little
+    let snapshotters: Vec<_> = engines
+        .iter()
+        .map(|handle| tokio::spawn(run_snapshotter((**handle).clone(), snapshot_dir.clone(), SNAPSHOT_INTERVAL)))
+        .collect();
+
+    // Journal to the database, or only log trades without one
+    let (_journaler_shutdown, journaler) = match journaler {
+        Some(journaler) => (None, tokio::spawn(journaler.run(engines.clone()))),
+        None => {
+            let (shutdown, task) = start_mock_journaler(&engines);
+            (Some(shutdown), task)
+        }
+    };
 
     // CORS configuration
     let cors = CorsLayer::new()
@@ -57,21 +167,75 @@ async fn main() {
         .allow_headers(Any);
 
     // Build the router
-    let app = Router::new()
-        // REST API
-        .route("/api/health", get(health_check))
-        .route("/api/orders", post(submit_order))
-        .route("/api/orderbook", get(get_order_book))
-        .route("/api/simulation", post(run_simulation))
-        // WebSocket
-        .route("/ws/market", get(ws_handler))
-        .layer(cors)
-        .with_state(handle);
+    let engines = Arc::new(engines);
+    let app = api::registry_router(engines.clone());
+    let app = match reader {
+        Some(reader) => app
+            .layer(axum::Extension(reader.clone() as Arc<dyn OrderArchive>))
+            .layer(axum::Extension(reader.clone() as Arc<dyn TradeArchive>))
+            .layer(axum::Extension(reader as Arc<dyn CandleArchive>)),
+        None => app,
+    };
+    let app = match keys {
+        Some(keys) => app.layer(axum::Extension(Arc::new(keys))),
+        None => app,
+    };
+    let app = match throttle {
+        Some(throttle) => app.layer(axum::Extension(Arc::new(throttle))),
+        None => app,
+    };
+    let app = app.layer(cors);
 
     // Start the server
     let addr = "0.0.0.0:3000";
     tracing::info!("Server listening on {}", addr);
 
+    // On SIGTERM or ctrl-c: drain the engines' queues, save their final
+    // state, let the journaler flush what they emitted, then stop serving.
+    // Feeds close themselves once their engine has stopped.
+    let shutdown = async move {
+        shutdown_signal().await;
+        tracing::info!("Shutdown requested, draining engines");
+        let final_states = engines.shutdown().await;
+        // Let periodic snapshots already being written finish first, so
+        // they can't replace the final ones
+        futures::future::join_all(snapshotters).await;
+        for snapshot in final_states {
+            if let Err(e) = snapshot.save_in(&snapshot_dir) {
+                tracing::error!("Final snapshot failed: {e}");
+            }
+        }
+        let _ = journaler.await;
+        tracing::info!("Engines stopped, state saved and journal flushed");
+    };
+
     let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    // Peer addresses tell apart clients without API keys
+    let app = app.into_make_service_with_connect_info::<std::net::SocketAddr>();
+    axum::serve(listener, app).with_graceful_shutdown(shutdown).await.unwrap();
+}
+
+/// How often each engine's state is saved while running
+const SNAPSHOT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Resolves on ctrl-c, or on SIGTERM where there is one
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c().await.expect("cannot listen for ctrl-c");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("cannot listen for SIGTERM")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
 }
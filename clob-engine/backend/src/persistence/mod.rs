@@ -2,4 +2,7 @@
 
 pub mod postgres;
 
-pub use postgres::{start_mock_journaler, TradeJournaler};
+pub use postgres::{
+    start_mock_journaler, ArchivedOrder, CandleArchive, CandleRecord, JournalReader, OrderArchive, TradeArchive,
+    TradeCursor, TradeJournaler, TradeKey, TradeRecord, DEFAULT_TRADE_HISTORY_LIMIT, TRADE_HISTORY_LIMIT_VAR,
+};
@@ -1,15 +1,45 @@
 //! PostgreSQL persistence for trade journaling.
 
-use crate::engine::{EngineEvent, EngineHandle, Trade};
+use crate::engine::{
+    EngineEvent, EngineRegistry, IdEpoch, OrderId, OrderStatus, PastTrade, SequencedEvent, Side, Trade,
+};
+use chrono::{DateTime, SubsecRound, Utc};
+use futures::future::BoxFuture;
+use futures::StreamExt;
+use rust_decimal::Decimal;
 use sqlx::postgres::PgPoolOptions;
 use sqlx::PgPool;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+/// Environment variable holding the most journaled trades an engine loads
+/// on startup for its 24-hour statistics
+pub const TRADE_HISTORY_LIMIT_VAR: &str = "TRADE_HISTORY_LIMIT";
+
+/// Most journaled trades an engine loads on startup, unless set
+pub const DEFAULT_TRADE_HISTORY_LIMIT: usize = 500_000;
+
+/// Trades read from the journal per query while loading history
+const TRADE_HISTORY_PAGE: i64 = 5_000;
 
 /// Trade journaler that persists trades to PostgreSQL asynchronously
 pub struct TradeJournaler {
     pool: PgPool,
-    buffer: Vec<Trade>,
+    /// Trades awaiting a flush, with their symbol and the sequence number
+    /// of their event
+    buffer: Vec<(Arc<str>, u64, Arc<Trade>)>,
+    /// Order lifecycle events awaiting a flush, each with the number of
+    /// trades buffered before it, so the orders table sees them in order
+    order_events: Vec<(usize, OrderEvent)>,
+    /// Each symbol's engine epoch, so ids are journaled as the UUIDs
+    /// clients know them by
+    ids: HashMap<String, IdEpoch>,
     buffer_size: usize,
     flush_interval: Duration,
 }
@@ -25,6 +55,8 @@ impl TradeJournaler {
         Ok(Self {
             pool,
             buffer: Vec::with_capacity(100),
+            order_events: Vec::with_capacity(100),
+            ids: HashMap::new(),
             buffer_size: 100,
             flush_interval: Duration::from_millis(100),
         })
@@ -36,12 +68,19 @@ impl TradeJournaler {
             r#"
             CREATE TABLE IF NOT EXISTS trades (
                 id UUID PRIMARY KEY,
+                symbol TEXT,
                 taker_order_id UUID NOT NULL,
                 maker_order_id UUID NOT NULL,
+                taker_owner_id TEXT,
+                maker_owner_id TEXT,
                 price DECIMAL NOT NULL,
                 quantity DECIMAL NOT NULL,
                 taker_side VARCHAR(4) NOT NULL,
-                timestamp TIMESTAMPTZ NOT NULL DEFAULT NOW()
+                timestamp TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+                busted BOOLEAN NOT NULL DEFAULT FALSE,
+                taker_sequence BIGINT,
+                maker_sequence BIGINT,
+                event_sequence BIGINT
             )
             "#,
         )
@@ -52,6 +91,7 @@ impl TradeJournaler {
             r#"
             CREATE TABLE IF NOT EXISTS orders (
                 id UUID PRIMARY KEY,
+                owner_id TEXT,
                 side VARCHAR(4) NOT NULL,
                 price DECIMAL NOT NULL,
                 quantity DECIMAL NOT NULL,
@@ -64,6 +104,58 @@ impl TradeJournaler {
         .execute(&self.pool)
         .await?;
 
+        // Tables created before owners were tracked
+        sqlx::query(
+            r#"
+            ALTER TABLE trades
+                ADD COLUMN IF NOT EXISTS symbol TEXT,
+                ADD COLUMN IF NOT EXISTS taker_owner_id TEXT,
+                ADD COLUMN IF NOT EXISTS maker_owner_id TEXT,
+                ADD COLUMN IF NOT EXISTS busted BOOLEAN NOT NULL DEFAULT FALSE,
+                ADD COLUMN IF NOT EXISTS taker_sequence BIGINT,
+                ADD COLUMN IF NOT EXISTS maker_sequence BIGINT,
+                ADD COLUMN IF NOT EXISTS event_sequence BIGINT
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            ALTER TABLE orders
+                ADD COLUMN IF NOT EXISTS owner_id TEXT,
+                ADD COLUMN IF NOT EXISTS symbol TEXT,
+                ADD COLUMN IF NOT EXISTS client_order_id TEXT,
+                ADD COLUMN IF NOT EXISTS updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Audit trail of each order's lifecycle, in event order
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS order_events (
+                symbol TEXT NOT NULL,
+                event_sequence BIGINT NOT NULL,
+                order_id UUID NOT NULL,
+                kind VARCHAR(20) NOT NULL,
+                owner_id TEXT,
+                client_order_id TEXT,
+                price DECIMAL,
+                remaining_quantity DECIMAL,
+                reason TEXT,
+                recorded_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_order_events_order ON order_events(order_id, event_sequence)")
+            .execute(&self.pool)
+            .await?;
+
         // Create indexes for common queries
         sqlx::query(
             "CREATE INDEX IF NOT EXISTS idx_trades_timestamp ON trades(timestamp DESC)",
@@ -71,13 +163,34 @@ impl TradeJournaler {
         .execute(&self.pool)
         .await?;
 
+        // Paging through one symbol's history on startup
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_trades_symbol_timestamp ON trades(symbol, timestamp, id)")
+            .execute(&self.pool)
+            .await?;
+
+        // Paging through one symbol's history by trade cursor, for clients
+        sqlx::query("DROP INDEX IF EXISTS idx_trades_symbol_sequence")
+            .execute(&self.pool)
+            .await?;
+        sqlx::query(
+            r#"
+            CREATE INDEX IF NOT EXISTS idx_trades_symbol_cursor
+            ON trades(symbol, timestamp DESC, (COALESCE(event_sequence, 0)) DESC, id DESC)
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
         tracing::info!("Database migrations completed");
         Ok(())
     }
 
-    /// Start the journaler background task
-    pub async fn run(mut self, handle: EngineHandle) {
-        let mut events = handle.subscribe();
+    /// Start the journaler background task over the journal of every
+    /// engine in `engines`. The journals are lossless: while the journaler
+    /// falls behind, the engines wait for it or spill to disk.
+    pub async fn run(mut self, engines: EngineRegistry) {
+        let mut events = engines.journal();
+        self.ids = engines.iter().map(|handle| (handle.config.symbol.clone(), handle.ids)).collect();
         let mut flush_interval = tokio::time::interval(self.flush_interval);
 
         tracing::info!("Trade journaler started");
@@ -85,131 +198,786 @@ impl TradeJournaler {
         loop {
             tokio::select! {
                 // Receive trade events
-                result = events.recv() => {
+                result = events.next() => {
                     match result {
-                        Ok(EngineEvent::Trade(trade)) => {
-                            self.buffer.push(trade);
+                        Some(SequencedEvent { seq, symbol, event: EngineEvent::Trade(trade) }) => {
+                            self.buffer.push((symbol, seq, trade));
                             if self.buffer.len() >= self.buffer_size {
                                 self.flush().await;
                             }
                         }
-                        Ok(_) => {} // Ignore non-trade events
-                        Err(tokio::sync::broadcast::error::RecvError::Closed) => {
-                            tracing::info!("Engine channel closed, flushing and exiting");
+                        Some(SequencedEvent { symbol, event: EngineEvent::TradeBusted(trade), .. }) => {
+                            // The trade may still be buffered
                             self.flush().await;
-                            break;
+                            if let Err(e) = self.mark_busted(&symbol, trade.id).await {
+                                tracing::error!("Failed to mark trade {} busted: {}", trade.id, e);
+                            }
                         }
-                        Err(tokio::sync::broadcast::error::RecvError::Lagged(n)) => {
-                            tracing::warn!("Journaler lagged behind by {} messages", n);
+                        Some(event) => {
+                            if let Some(order_event) = OrderEvent::of(&event) {
+                                self.order_events.push((self.buffer.len(), order_event));
+                                if self.order_events.len() >= self.buffer_size {
+                                    self.flush().await;
+                                }
+                            }
+                        }
+                        None => {
+                            tracing::info!("Engine channels closed, flushing and exiting");
+                            self.flush().await;
+                            break;
                         }
                     }
                 }
                 // Periodic flush
                 _ = flush_interval.tick() => {
-                    if !self.buffer.is_empty() {
-                        self.flush().await;
-                    }
+                    self.flush().await;
                 }
             }
         }
     }
 
-    /// Flush buffered trades to the database
+    /// Flush buffered trades and order events to the database, in the
+    /// order the engines emitted them
     async fn flush(&mut self) {
-        if self.buffer.is_empty() {
+        let order_events = std::mem::take(&mut self.order_events);
+        if self.buffer.is_empty() && order_events.is_empty() {
             return;
         }
 
-        let trades: Vec<Trade> = self.buffer.drain(..).collect();
+        let mut trades = std::mem::take(&mut self.buffer).into_iter();
         let count = trades.len();
-
-        // Batch insert trades
-        for trade in trades {
-            if let Err(e) = self.insert_trade(&trade).await {
-                tracing::error!("Failed to persist trade {}: {}", trade.id, e);
-                // In production, you'd want retry logic or a dead letter queue
+        let mut written = 0;
+        for (trades_before, event) in order_events {
+            for (symbol, seq, trade) in trades.by_ref().take(trades_before - written) {
+                self.persist_trade(&symbol, seq, &trade).await;
+            }
+            written = trades_before;
+            if let Err(e) = self.insert_order_event(&event).await {
+                tracing::error!("Failed to persist {} event for order {}: {}", event.kind, event.order_id, e);
+            }
+            if let Err(e) = self.apply_order_event(&event).await {
+                tracing::error!("Failed to update order {} for its {} event: {}", event.order_id, event.kind, e);
             }
         }
+        for (symbol, seq, trade) in trades {
+            self.persist_trade(&symbol, seq, &trade).await;
+        }
 
         tracing::debug!("Flushed {} trades to database", count);
     }
 
-    /// Insert a single trade
-    async fn insert_trade(&self, trade: &Trade) -> Result<(), sqlx::Error> {
+    /// Insert a trade and count it against both orders' fills, logging
+    /// rather than returning a failure
+    async fn persist_trade(&self, symbol: &str, seq: u64, trade: &Trade) {
+        if let Err(e) = self.insert_trade(symbol, seq, trade).await {
+            tracing::error!("Failed to persist trade {}: {}", trade.id, e);
+            // In production, you'd want retry logic or a dead letter queue
+        }
+        for order_id in [trade.taker_order_id, trade.maker_order_id] {
+            if let Err(e) = self.record_fill(symbol, order_id, trade.quantity).await {
+                tracing::error!("Failed to record trade {} against order {}: {}", trade.id, order_id, e);
+            }
+        }
+    }
+
+    /// The UUID `symbol`'s engine gives order or trade `id`
+    fn uuid(&self, symbol: &str, id: u64) -> uuid::Uuid {
+        // Every symbol journaled has an engine in the registry
+        self.ids[symbol].uuid(id)
+    }
+
+    /// Insert a single trade, broadcast as event `seq` of `symbol`
+    async fn insert_trade(&self, symbol: &str, seq: u64, trade: &Trade) -> Result<(), sqlx::Error> {
         sqlx::query(
             r#"
-            INSERT INTO trades (id, taker_order_id, maker_order_id, price, quantity, taker_side, timestamp)
-            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            INSERT INTO trades (id, taker_order_id, maker_order_id, taker_owner_id, maker_owner_id,
+                                price, quantity, taker_side, timestamp, taker_sequence, maker_sequence,
+                                event_sequence, symbol)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)
             "#,
         )
-        .bind(trade.id)
-        .bind(trade.taker_order_id)
-        .bind(trade.maker_order_id)
+        .bind(self.uuid(symbol, trade.id))
+        .bind(self.uuid(symbol, trade.taker_order_id))
+        .bind(self.uuid(symbol, trade.maker_order_id))
+        .bind(&trade.taker_owner_id)
+        .bind(&trade.maker_owner_id)
         .bind(trade.price)
         .bind(trade.quantity)
         .bind(trade.taker_side.to_string())
         .bind(trade.timestamp)
+        .bind(trade.taker_sequence as i64)
+        .bind(trade.maker_sequence as i64)
+        .bind(seq as i64)
+        .bind(symbol)
         .execute(&self.pool)
         .await?;
 
         Ok(())
     }
 
-    /// Get recent trades from the database
-    #[allow(dead_code)]
-    pub async fn get_recent_trades(&self, limit: i64) -> Result<Vec<TradeRecord>, sqlx::Error> {
-        let trades = sqlx::query_as::<_, TradeRecord>(
+    /// Insert a single order lifecycle event
+    async fn insert_order_event(&self, event: &OrderEvent) -> Result<(), sqlx::Error> {
+        sqlx::query(
             r#"
-            SELECT id, taker_order_id, maker_order_id, price, quantity, taker_side, timestamp
-            FROM trades
-            ORDER BY timestamp DESC
-            LIMIT $1
+            INSERT INTO order_events (symbol, event_sequence, order_id, kind, owner_id, client_order_id,
+                                      price, remaining_quantity, reason)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
             "#,
         )
-        .bind(limit)
-        .fetch_all(&self.pool)
+        .bind(&*event.symbol)
+        .bind(event.seq as i64)
+        .bind(self.uuid(&event.symbol, event.order_id))
+        .bind(event.kind)
+        .bind(&event.owner_id)
+        .bind(&event.client_order_id)
+        .bind(event.price)
+        .bind(event.remaining_quantity)
+        .bind(event.reason)
+        .execute(&self.pool)
         .await?;
+        Ok(())
+    }
 
+    /// Bring an order's row in `orders` up to date with a lifecycle event.
+    /// Accepted orders get a row; an order refused after acceptance never
+    /// worked, so loses it again.
+    async fn apply_order_event(&self, event: &OrderEvent) -> Result<(), sqlx::Error> {
+        let order_id = self.uuid(&event.symbol, event.order_id);
+        match event.kind {
+            "accepted" => {
+                sqlx::query(
+                    r#"
+                    INSERT INTO orders (id, symbol, owner_id, client_order_id, side, price, quantity,
+                                        created_at, updated_at)
+                    VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $8)
+                    ON CONFLICT (id) DO NOTHING
+                    "#,
+                )
+                .bind(order_id)
+                .bind(&*event.symbol)
+                .bind(&event.owner_id)
+                .bind(&event.client_order_id)
+                .bind(event.side.map(|side| side.to_string()))
+                .bind(event.price)
+                .bind(event.quantity)
+                .bind(event.timestamp)
+                .execute(&self.pool)
+                .await?;
+            }
+            "rejected" => {
+                sqlx::query("DELETE FROM orders WHERE id = $1").bind(order_id).execute(&self.pool).await?;
+            }
+            "cancelled" => {
+                sqlx::query("UPDATE orders SET status = $2, updated_at = NOW() WHERE id = $1")
+                    .bind(order_id)
+                    .bind(status_name(OrderStatus::Cancelled))
+                    .execute(&self.pool)
+                    .await?;
+            }
+            "modified" => match event.new_order_id {
+                // Shrunk in place: the fills so far stand
+                Some(new_order_id) if new_order_id == event.order_id => {
+                    sqlx::query(
+                        r#"
+                        UPDATE orders SET price = $2, quantity = filled_quantity + $3, updated_at = NOW()
+                        WHERE id = $1
+                        "#,
+                    )
+                    .bind(order_id)
+                    .bind(event.price)
+                    .bind(event.remaining_quantity)
+                    .execute(&self.pool)
+                    .await?;
+                }
+                // Replaced: the original was cancelled, and a fresh order
+                // takes over its details
+                Some(new_order_id) => {
+                    sqlx::query(
+                        r#"
+                        INSERT INTO orders (id, symbol, owner_id, client_order_id, side, price, quantity,
+                                            created_at, updated_at)
+                        SELECT $2, symbol, owner_id, client_order_id, side, $3, $4, NOW(), NOW()
+                        FROM orders WHERE id = $1
+                        ON CONFLICT (id) DO NOTHING
+                        "#,
+                    )
+                    .bind(order_id)
+                    .bind(self.uuid(&event.symbol, new_order_id))
+                    .bind(event.price)
+                    .bind(event.remaining_quantity)
+                    .execute(&self.pool)
+                    .await?;
+                }
+                None => {}
+            },
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Add `quantity` to an order's fills, filling it once nothing is left
+    async fn record_fill(&self, symbol: &str, order_id: OrderId, quantity: Decimal) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            r#"
+            UPDATE orders
+            SET filled_quantity = filled_quantity + $2,
+                status = CASE WHEN filled_quantity + $2 >= quantity THEN $3 ELSE $4 END,
+                updated_at = NOW()
+            WHERE id = $1
+            "#,
+        )
+        .bind(self.uuid(symbol, order_id))
+        .bind(quantity)
+        .bind(status_name(OrderStatus::Filled))
+        .bind(status_name(OrderStatus::PartiallyFilled))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Reads of what this journaler has written: orders no longer in their
+    /// engine, and trade history
+    pub fn reader(&self) -> JournalReader {
+        JournalReader { pool: self.pool.clone() }
+    }
+
+    /// Flag a journaled trade as busted, keeping the row for audit
+    async fn mark_busted(&self, symbol: &str, trade_id: u64) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE trades SET busted = TRUE WHERE id = $1")
+            .bind(self.uuid(symbol, trade_id))
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// `symbol`'s most recent `limit` trades that were not busted, from
+    /// `before` back when given, newest first. Pass the last trade of one
+    /// page as the next page's cursor to walk back through the history.
+    pub async fn get_recent_trades(
+        &self,
+        symbol: &str,
+        before: Option<TradeCursor>,
+        limit: i64,
+    ) -> Result<Vec<TradeRecord>, sqlx::Error> {
+        recent_trades(&self.pool, symbol, before, limit).await
+    }
+
+    /// `symbol`'s trades since `since` that were not busted, oldest first.
+    /// Read a page at a time from the latest back, keyed on time then id so
+    /// no page repeats or skips a trade, and stopping at `limit` trades:
+    /// past that the oldest are left out, with a warning.
+    pub async fn trade_history(
+        &self,
+        symbol: &str,
+        since: DateTime<Utc>,
+        limit: usize,
+    ) -> Result<Vec<PastTrade>, sqlx::Error> {
+        let mut trades = Vec::new();
+        let mut before: Option<(DateTime<Utc>, Uuid)> = None;
+        loop {
+            if trades.len() >= limit {
+                tracing::warn!(symbol, limit, "Trade history truncated; 24h statistics leave out the oldest trades");
+                break;
+            }
+            let page = TRADE_HISTORY_PAGE.min((limit - trades.len()) as i64);
+            let rows = sqlx::query_as::<_, (Uuid, Decimal, Decimal, DateTime<Utc>)>(
+                r#"
+                SELECT id, price, quantity, timestamp
+                FROM trades
+                WHERE symbol = $1 AND NOT busted AND timestamp >= $2
+                  AND ($3::TIMESTAMPTZ IS NULL OR (timestamp, id) < ($3, $4))
+                ORDER BY timestamp DESC, id DESC
+                LIMIT $5
+                "#,
+            )
+            .bind(symbol)
+            .bind(since)
+            .bind(before.map(|(timestamp, _)| timestamp))
+            .bind(before.map(|(_, id)| id))
+            .bind(page)
+            .fetch_all(&self.pool)
+            .await?;
+
+            let full = rows.len() as i64 == page;
+            trades.extend(rows.into_iter().map(|(id, price, quantity, timestamp)| PastTrade {
+                id,
+                price,
+                quantity,
+                timestamp,
+            }));
+            match trades.last() {
+                Some(last) if full => before = Some((last.timestamp, last.id)),
+                _ => break,
+            }
+        }
+        trades.reverse();
         Ok(trades)
     }
 }
 
-/// Trade record from the database
+/// A step in an order's lifecycle, as journaled to `order_events`
+#[derive(Debug, Clone, PartialEq)]
+struct OrderEvent {
+    symbol: Arc<str>,
+    /// Sequence number of the engine event
+    seq: u64,
+    order_id: OrderId,
+    kind: &'static str,
+    owner_id: Option<String>,
+    client_order_id: Option<String>,
+    price: Option<Decimal>,
+    remaining_quantity: Option<Decimal>,
+    /// Why a cancelled order was removed or a rejected one refused, as its code
+    reason: Option<&'static str>,
+    /// Side, original quantity and arrival time of an accepted order
+    side: Option<Side>,
+    quantity: Option<Decimal>,
+    timestamp: Option<DateTime<Utc>>,
+    /// The order a modification left, itself unless it was replaced
+    new_order_id: Option<OrderId>,
+}
+
+impl OrderEvent {
+    /// The lifecycle step an engine event records, if it is one
+    fn of(event: &SequencedEvent) -> Option<Self> {
+        let record = |order_id, kind| Self {
+            symbol: event.symbol.clone(),
+            seq: event.seq,
+            order_id,
+            kind,
+            owner_id: None,
+            client_order_id: None,
+            price: None,
+            remaining_quantity: None,
+            reason: None,
+            side: None,
+            quantity: None,
+            timestamp: None,
+            new_order_id: None,
+        };
+        match &event.event {
+            EngineEvent::OrderAccepted {
+                order_id,
+                client_order_id,
+                owner_id,
+                side,
+                price,
+                quantity,
+                timestamp,
+            } => Some(Self {
+                owner_id: owner_id.clone(),
+                client_order_id: client_order_id.clone(),
+                price: Some(*price),
+                side: Some(*side),
+                quantity: Some(*quantity),
+                timestamp: Some(*timestamp),
+                ..record(*order_id, "accepted")
+            }),
+            EngineEvent::OrderRejected {
+                order_id,
+                client_order_id,
+                reason,
+            } => Some(Self {
+                client_order_id: client_order_id.clone(),
+                reason: Some(reason.code()),
+                ..record(*order_id, "rejected")
+            }),
+            EngineEvent::OrderRested {
+                order_id,
+                price,
+                remaining_quantity,
+            } => Some(Self {
+                price: Some(*price),
+                remaining_quantity: Some(*remaining_quantity),
+                ..record(*order_id, "rested")
+            }),
+            EngineEvent::OrderCancelled {
+                order_id,
+                remaining_quantity,
+                reason,
+            } => Some(Self {
+                remaining_quantity: Some(*remaining_quantity),
+                reason: Some(reason.code()),
+                ..record(*order_id, "cancelled")
+            }),
+            EngineEvent::OrderModified {
+                order_id,
+                new_order_id,
+                price,
+                remaining_quantity,
+            } => Some(Self {
+                price: Some(*price),
+                remaining_quantity: Some(*remaining_quantity),
+                new_order_id: Some(*new_order_id),
+                ..record(*order_id, "modified")
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// How an order status is written to the `orders` table: as it serializes
+fn status_name(status: OrderStatus) -> &'static str {
+    match status {
+        OrderStatus::Open => "open",
+        OrderStatus::PartiallyFilled => "partiallyfilled",
+        OrderStatus::Filled => "filled",
+        OrderStatus::Cancelled => "cancelled",
+    }
+}
+
+/// An order as last recorded by the journal
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArchivedOrder {
+    pub order_id: Uuid,
+    pub owner_id: Option<String>,
+    pub client_order_id: Option<String>,
+    pub side: Side,
+    /// Zero for market orders
+    pub price: Decimal,
+    /// Original quantity, less any in-place reductions
+    pub quantity: Decimal,
+    pub filled_quantity: Decimal,
+    pub status: OrderStatus,
+    /// When the order reached its engine
+    pub created_at: DateTime<Utc>,
+    /// When the journal last changed the order
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Where a page of trade history starts: it holds the trades strictly
+/// before this point
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TradeCursor {
+    /// Before this trade
+    Trade(TradeKey),
+    /// Before this time
+    Time(DateTime<Utc>),
+}
+
+/// Where a trade falls in the history: by time, then event sequence, then
+/// id. Each engine numbers its events from 1 again, so the sequence only
+/// orders trades made at the same instant, and the id settles the rest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TradeKey {
+    /// Kept to the microsecond, as the journal keeps it
+    pub timestamp: DateTime<Utc>,
+    pub sequence: u64,
+    pub id: Uuid,
+}
+
+impl TradeKey {
+    pub fn new(timestamp: DateTime<Utc>, sequence: u64, id: Uuid) -> Self {
+        Self {
+            timestamp: timestamp.trunc_subsecs(6),
+            sequence,
+            id,
+        }
+    }
+}
+
+/// Written `{microseconds since the epoch}_{sequence}_{id}`
+impl fmt::Display for TradeKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}_{}_{}", self.timestamp.timestamp_micros(), self.sequence, self.id)
+    }
+}
+
+impl FromStr for TradeKey {
+    type Err = ();
+
+    fn from_str(key: &str) -> Result<Self, ()> {
+        let mut parts = key.splitn(3, '_');
+        let mut next = || parts.next().ok_or(());
+        let micros = next()?.parse().map_err(drop)?;
+        let sequence = next()?.parse().map_err(drop)?;
+        let id = next()?.parse().map_err(drop)?;
+        let timestamp = DateTime::from_timestamp_micros(micros).ok_or(())?;
+        Ok(Self::new(timestamp, sequence, id))
+    }
+}
+
+/// `symbol`'s most recent `limit` trades in `pool` that were not busted,
+/// from `before` back, newest first. Keyed on time, event sequence and id
+/// together, which no two trades share, so pages neither repeat nor skip
+/// one however many engines wrote them. Trades journaled before sequences
+/// were recorded count as sequence 0.
+async fn recent_trades(
+    pool: &PgPool,
+    symbol: &str,
+    before: Option<TradeCursor>,
+    limit: i64,
+) -> Result<Vec<TradeRecord>, sqlx::Error> {
+    let (key, time) = match before {
+        Some(TradeCursor::Trade(key)) => (Some(key), None),
+        Some(TradeCursor::Time(time)) => (None, Some(time)),
+        None => (None, None),
+    };
+    sqlx::query_as::<_, TradeRecord>(
+        r#"
+        SELECT id, symbol, taker_order_id, maker_order_id, taker_owner_id, maker_owner_id,
+               price, quantity, taker_side, timestamp, busted, taker_sequence, maker_sequence,
+               event_sequence
+        FROM trades
+        WHERE symbol = $1 AND NOT busted
+          AND ($2::TIMESTAMPTZ IS NULL OR (timestamp, COALESCE(event_sequence, 0), id) < ($2, $3, $4))
+          AND ($5::TIMESTAMPTZ IS NULL OR timestamp < $5)
+        ORDER BY timestamp DESC, COALESCE(event_sequence, 0) DESC, id DESC
+        LIMIT $6
+        "#,
+    )
+    .bind(symbol)
+    .bind(key.map(|key| key.timestamp))
+    .bind(key.map(|key| key.sequence as i64))
+    .bind(key.map(|key| key.id))
+    .bind(time)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+}
+
+/// Orders that have left their engine, looked up by the UUID clients know
+/// them by. The journaler's database is one; tests stand in their own.
+pub trait OrderArchive: Send + Sync {
+    /// `symbol`'s order `order_id`, if it was ever recorded
+    fn find_order<'a>(
+        &'a self,
+        symbol: &'a str,
+        order_id: Uuid,
+    ) -> BoxFuture<'a, Result<Option<ArchivedOrder>, sqlx::Error>>;
+}
+
+/// Trades older than an engine keeps in memory, newest first. The
+/// journaler's database is one; tests stand in their own.
+pub trait TradeArchive: Send + Sync {
+    /// `symbol`'s most recent `limit` trades that were not busted, from
+    /// `before` back when given
+    fn recent_trades<'a>(
+        &'a self,
+        symbol: &'a str,
+        before: Option<TradeCursor>,
+        limit: usize,
+    ) -> BoxFuture<'a, Result<Vec<TradeRecord>, sqlx::Error>>;
+}
+
+/// Read access to the tables the trade journaler keeps
+#[derive(Debug, Clone)]
+pub struct JournalReader {
+    pool: PgPool,
+}
+
+impl TradeArchive for JournalReader {
+    fn recent_trades<'a>(
+        &'a self,
+        symbol: &'a str,
+        before: Option<TradeCursor>,
+        limit: usize,
+    ) -> BoxFuture<'a, Result<Vec<TradeRecord>, sqlx::Error>> {
+        Box::pin(recent_trades(&self.pool, symbol, before, limit as i64))
+    }
+}
+
+impl OrderArchive for JournalReader {
+    fn find_order<'a>(
+        &'a self,
+        symbol: &'a str,
+        order_id: Uuid,
+    ) -> BoxFuture<'a, Result<Option<ArchivedOrder>, sqlx::Error>> {
+        Box::pin(async move {
+            let row = sqlx::query_as::<_, OrderRow>(
+                r#"
+                SELECT id, owner_id, client_order_id, side, price, quantity, filled_quantity, status,
+                       created_at, updated_at
+                FROM orders
+                WHERE id = $1 AND symbol = $2
+                "#,
+            )
+            .bind(order_id)
+            .bind(symbol)
+            .fetch_optional(&self.pool)
+            .await?;
+            row.map(OrderRow::into_archived).transpose()
+        })
+    }
+}
+
+/// One interval's trading, aggregated from the journaled trades
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct CandleRecord {
+    /// Start of the interval, a multiple of its length since the epoch
+    pub start: DateTime<Utc>,
+    pub open: Decimal,
+    pub high: Decimal,
+    pub low: Decimal,
+    pub close: Decimal,
+    pub volume: Decimal,
+    pub trades: i64,
+}
+
+/// Candles older than an engine keeps in memory. The journaler's database
+/// is one; tests stand in their own.
+pub trait CandleArchive: Send + Sync {
+    /// `symbol`'s candles `interval_secs` long that start from `from` and
+    /// end by `to`, made of the trades that were not busted, oldest first.
+    /// Intervals without trades are left out.
+    fn candles<'a>(
+        &'a self,
+        symbol: &'a str,
+        interval_secs: i64,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> BoxFuture<'a, Result<Vec<CandleRecord>, sqlx::Error>>;
+}
+
+impl CandleArchive for JournalReader {
+    fn candles<'a>(
+        &'a self,
+        symbol: &'a str,
+        interval_secs: i64,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> BoxFuture<'a, Result<Vec<CandleRecord>, sqlx::Error>> {
+        Box::pin(
+            sqlx::query_as::<_, CandleRecord>(
+                r#"
+                SELECT to_timestamp(floor(extract(epoch FROM timestamp) / $2) * $2) AS start,
+                       (array_agg(price ORDER BY timestamp, COALESCE(event_sequence, 0)))[1] AS open,
+                       MAX(price) AS high,
+                       MIN(price) AS low,
+                       (array_agg(price ORDER BY timestamp DESC, COALESCE(event_sequence, 0) DESC))[1] AS close,
+                       SUM(quantity) AS volume,
+                       COUNT(*) AS trades
+                FROM trades
+                WHERE symbol = $1 AND NOT busted AND timestamp >= $3 AND timestamp < $4
+                GROUP BY 1
+                ORDER BY 1
+                "#,
+            )
+            .bind(symbol)
+            .bind(interval_secs)
+            .bind(from)
+            .bind(to)
+            .fetch_all(&self.pool),
+        )
+    }
+}
+
+/// A row of the `orders` table
 #[derive(Debug, sqlx::FromRow)]
+struct OrderRow {
+    id: Uuid,
+    owner_id: Option<String>,
+    client_order_id: Option<String>,
+    side: String,
+    price: Decimal,
+    quantity: Decimal,
+    filled_quantity: Decimal,
+    status: String,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+}
+
+impl OrderRow {
+    fn into_archived(self) -> Result<ArchivedOrder, sqlx::Error> {
+        Ok(ArchivedOrder {
+            order_id: self.id,
+            owner_id: self.owner_id,
+            client_order_id: self.client_order_id,
+            side: decode("side", self.side)?,
+            price: self.price,
+            quantity: self.quantity,
+            filled_quantity: self.filled_quantity,
+            status: decode("status", self.status)?,
+            created_at: self.created_at,
+            updated_at: self.updated_at,
+        })
+    }
+}
+
+/// A `side` or `status` column, read back by the name it serializes as
+fn decode<T: serde::de::DeserializeOwned>(column: &str, value: String) -> Result<T, sqlx::Error> {
+    serde_json::from_value(serde_json::Value::String(value)).map_err(|e| sqlx::Error::ColumnDecode {
+        index: column.to_string(),
+        source: Box::new(e),
+    })
+}
+
+/// Trade record from the database
+#[derive(Debug, Clone, sqlx::FromRow)]
 pub struct TradeRecord {
     pub id: uuid::Uuid,
+    /// Absent on rows journaled before symbols were recorded
+    pub symbol: Option<String>,
     pub taker_order_id: uuid::Uuid,
     pub maker_order_id: uuid::Uuid,
+    pub taker_owner_id: Option<String>,
+    pub maker_owner_id: Option<String>,
     pub price: rust_decimal::Decimal,
     pub quantity: rust_decimal::Decimal,
     pub taker_side: String,
     pub timestamp: chrono::DateTime<chrono::Utc>,
+    pub busted: bool,
+    /// Engine arrival sequences; absent on rows journaled before they were recorded
+    pub taker_sequence: Option<i64>,
+    pub maker_sequence: Option<i64>,
+    /// Sequence number of the trade's engine event
+    pub event_sequence: Option<i64>,
+}
+
+impl TradeRecord {
+    /// Where the trade falls in the history
+    pub fn key(&self) -> TradeKey {
+        TradeKey::new(self.timestamp, self.event_sequence.unwrap_or_default() as u64, self.id)
+    }
 }
 
-/// Start a mock journaler that just logs trades (for testing without DB)
-pub fn start_mock_journaler(handle: EngineHandle) -> mpsc::Sender<()> {
+/// Start a mock journaler that just logs trades (for testing without DB),
+/// taking the engines' journals as the real one does. It runs until told
+/// to stop or until every engine has stopped.
+pub fn start_mock_journaler(engines: &EngineRegistry) -> (mpsc::Sender<()>, JoinHandle<()>) {
     let (shutdown_tx, mut shutdown_rx) = mpsc::channel::<()>(1);
-    let mut events = handle.subscribe();
+    let mut events = engines.journal();
 
-    tokio::spawn(async move {
+    let task = tokio::spawn(async move {
         tracing::info!("Mock trade journaler started (no database)");
 
         loop {
             tokio::select! {
-                result = events.recv() => {
+                result = events.next() => {
                     match result {
-                        Ok(EngineEvent::Trade(trade)) => {
+                        Some(SequencedEvent { seq, symbol, event: EngineEvent::Trade(trade) }) => {
                             tracing::info!(
+                                seq,
+                                symbol = %symbol,
                                 trade_id = %trade.id,
                                 price = %trade.price,
                                 quantity = %trade.quantity,
                                 side = %trade.taker_side,
+                                taker_owner = ?trade.taker_owner_id,
+                                maker_owner = ?trade.maker_owner_id,
                                 "Trade executed (mock journaler)"
                             );
                         }
-                        Ok(_) => {}
-                        Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
-                        Err(_) => continue,
+                        Some(SequencedEvent { seq, symbol, event: EngineEvent::TradeBusted(trade) }) => {
+                            tracing::warn!(
+                                seq,
+                                symbol = %symbol,
+                                trade_id = %trade.id,
+                                "Trade busted (mock journaler)"
+                            );
+                        }
+                        Some(event) => {
+                            if let Some(order_event) = OrderEvent::of(&event) {
+                                tracing::debug!(
+                                    seq = order_event.seq,
+                                    symbol = %order_event.symbol,
+                                    order_id = %order_event.order_id,
+                                    kind = order_event.kind,
+                                    "Order event (mock journaler)"
+                                );
+                            }
+                        }
+                        None => break,
                     }
                 }
                 _ = shutdown_rx.recv() => {
@@ -220,5 +988,121 @@ pub fn start_mock_journaler(handle: EngineHandle) -> mpsc::Sender<()> {
         }
     });
 
-    shutdown_tx
+    (shutdown_tx, task)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use rust_decimal_macros::dec;
+
+    /// Database the journal tests run against; they are skipped without one
+    const TEST_DATABASE_VAR: &str = "TEST_DATABASE_URL";
+
+    /// A journaler over the test database with a symbol of its own, so runs
+    /// never see each other's rows
+    async fn test_journaler() -> Option<(TradeJournaler, String)> {
+        let Ok(url) = std::env::var(TEST_DATABASE_VAR) else {
+            eprintln!("{TEST_DATABASE_VAR} is not set; skipping");
+            return None;
+        };
+        let mut journaler = TradeJournaler::new(&url).await.unwrap();
+        journaler.run_migrations().await.unwrap();
+        let (epoch, _) = Uuid::new_v4().as_u64_pair();
+        let symbol = format!("TEST-{epoch:x}");
+        journaler.ids.insert(symbol.clone(), IdEpoch::from(epoch));
+        Some((journaler, symbol))
+    }
+
+    /// Trade `seq`, made at one of few distinct times so many share one
+    fn trade(seq: u64, start: DateTime<Utc>) -> Trade {
+        let at = start + chrono::Duration::milliseconds((seq / 10) as i64);
+        let mut trade = Trade::new(2 * seq, 2 * seq + 1, dec!(100), Decimal::from(seq), Side::Buy, at);
+        trade.id = seq;
+        trade
+    }
+
+    #[tokio::test]
+    async fn test_trade_pages_reassemble_the_history() {
+        let Some((mut journaler, symbol)) = test_journaler().await else {
+            return;
+        };
+        let start = Utc::now();
+        let mut expected = Vec::new();
+        for seq in 1..=3000 {
+            journaler.insert_trade(&symbol, seq, &trade(seq, start)).await.unwrap();
+            if seq % 7 == 0 {
+                journaler.mark_busted(&symbol, seq).await.unwrap();
+            } else {
+                expected.push(journaler.uuid(&symbol, seq));
+            }
+        }
+        // A restarted engine numbers its events from 1 again, under ids of its own
+        let restarted = start + chrono::Duration::seconds(1);
+        journaler.ids.insert(symbol.clone(), IdEpoch::from(Uuid::new_v4().as_u64_pair().0));
+        for seq in 1..=1000 {
+            journaler.insert_trade(&symbol, seq, &trade(seq, restarted)).await.unwrap();
+            expected.push(journaler.uuid(&symbol, seq));
+        }
+        expected.reverse();
+
+        let mut seen = Vec::new();
+        let mut before = None;
+        loop {
+            let page = journaler.get_recent_trades(&symbol, before, 500).await.unwrap();
+            seen.extend(page.iter().map(|record| record.id));
+            if page.len() < 500 {
+                break;
+            }
+            before = page.last().map(|record| TradeCursor::Trade(record.key()));
+            // Trades keep arriving while the history is read
+            if seen.len() == 500 {
+                for seq in 1001..=1100 {
+                    journaler.insert_trade(&symbol, seq, &trade(seq, restarted)).await.unwrap();
+                }
+            }
+        }
+        assert_eq!(seen, expected);
+
+        let cutoff = trade(1000, start).timestamp;
+        let earlier = journaler.get_recent_trades(&symbol, Some(TradeCursor::Time(cutoff)), 5).await.unwrap();
+        let seqs: Vec<_> = earlier.iter().map(|record| record.event_sequence.unwrap()).collect();
+        assert_eq!(seqs, [999, 998, 997, 996, 995]);
+    }
+
+    #[tokio::test]
+    async fn test_candles_aggregate_the_journal() {
+        let Some((journaler, symbol)) = test_journaler().await else {
+            return;
+        };
+        let minute = Utc.with_ymd_and_hms(2030, 1, 1, 0, 0, 0).unwrap();
+        let at = |ms| minute + chrono::Duration::milliseconds(ms);
+        let trades = [(1, 59_999, dec!(101)), (2, 0, dec!(100)), (3, 30_000, dec!(104)), (4, 60_000, dec!(99))];
+        for (seq, ms, price) in trades {
+            let mut trade = Trade::new(2 * seq, 2 * seq + 1, price, Decimal::from(seq), Side::Buy, at(ms));
+            trade.id = seq;
+            journaler.insert_trade(&symbol, seq, &trade).await.unwrap();
+        }
+        // A busted trade counts towards nothing
+        let mut busted = Trade::new(20, 21, dec!(500), dec!(5), Side::Buy, at(10_000));
+        busted.id = 5;
+        journaler.insert_trade(&symbol, 5, &busted).await.unwrap();
+        journaler.mark_busted(&symbol, 5).await.unwrap();
+
+        let candles = journaler.reader().candles(&symbol, 60, minute, at(120_000)).await.unwrap();
+        let rows: Vec<_> = candles
+            .iter()
+            .map(|c| (c.start, c.open, c.high, c.low, c.close, c.volume, c.trades))
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                (minute, dec!(100), dec!(104), dec!(100), dec!(101), dec!(6), 3),
+                (at(60_000), dec!(99), dec!(99), dec!(99), dec!(99), dec!(4), 1),
+            ]
+        );
+        let later = journaler.reader().candles(&symbol, 60, at(60_000), at(120_000)).await.unwrap();
+        assert_eq!(later.len(), 1);
+    }
 }
@@ -1,15 +1,16 @@
 //! Performance simulation and metrics tracking.
 
-use crate::engine::{EngineHandle, OrderRequest, Side};
+use crate::engine::{EngineHandle, OrderLatency, OrderRequest, Side, TradeTotals};
 use rand::Rng;
 use rust_decimal::Decimal;
 use serde::Serialize;
+use utoipa::ToSchema;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
 
 /// Performance metrics tracked during simulation
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, ToSchema)]
 pub struct PerformanceMetrics {
     pub orders_submitted: u64,
     pub trades_executed: u64,
@@ -20,6 +21,9 @@ pub struct PerformanceMetrics {
     pub simulation_duration_ms: u64,
     pub current_spread: Option<String>,
     pub total_volume_traded: String,
+    /// The engine's own processing time per order, since it started. The
+    /// latencies above only cover handing each order to its queue.
+    pub engine_latency: Option<OrderLatency>,
 }
 
 impl Default for PerformanceMetrics {
@@ -34,6 +38,7 @@ impl Default for PerformanceMetrics {
             simulation_duration_ms: 0,
             current_spread: None,
             total_volume_traded: "0".to_string(),
+            engine_latency: None,
         }
     }
 }
@@ -46,6 +51,11 @@ pub struct SimulationConfig {
     pub min_quantity: Decimal,
     pub max_quantity: Decimal,
     pub delay_between_orders_us: u64,
+    /// Number of simulated participants orders are spread across
+    pub num_owners: u32,
+    /// Exempt generated orders from the engine's max quantity and max
+    /// notional limits, so stress runs aren't throttled by risk settings
+    pub bypass_risk_limits: bool,
 }
 
 impl Default for SimulationConfig {
@@ -57,6 +67,8 @@ impl Default for SimulationConfig {
             min_quantity: Decimal::new(100, 4), // 0.0100
             max_quantity: Decimal::new(10000, 4), // 1.0000
             delay_between_orders_us: 100, // 100 microseconds between orders
+            num_owners: 10,
+            bypass_risk_limits: false,
         }
     }
 }
@@ -80,6 +92,7 @@ impl Simulator {
         use rand::SeedableRng;
         let mut rng = rand::rngs::StdRng::from_entropy();
         let start_time = Instant::now();
+        let traded_before = self.traded().await;
         let mut latencies = Vec::with_capacity(config.num_orders as usize);
         
         // Reset metrics
@@ -104,7 +117,7 @@ impl Simulator {
             };
 
             // Random price around base price
-            let price_offset_range = (-500..=500); // Fixed range instead of using mantissa
+            let price_offset_range = -500..=500; // Fixed range instead of using mantissa
             let price_offset = Decimal::new(
                 rng.gen_range(price_offset_range),
                 config.price_variance.scale(),
@@ -112,17 +125,17 @@ impl Simulator {
             let price = config.base_price + price_offset;
 
             // Random quantity
-            let quantity_range = (100..=10000); // Fixed range
+            let quantity_range = 100..=10000; // Fixed range
             let quantity = Decimal::new(
                 rng.gen_range(quantity_range),
                 config.max_quantity.scale(),
             );
+            // Stay within the symbol rules so generated orders aren't all rejected
+            let quantity = quantity.max(self.handle.config.min_quantity_at(price));
 
-            let order = OrderRequest {
-                side,
-                price,
-                quantity,
-            };
+            let owner = format!("sim-{}", rng.gen_range(0..config.num_owners.max(1)));
+            let mut order = OrderRequest::new(side, price, quantity).with_owner(owner);
+            order.skip_risk_checks = config.bypass_risk_limits;
 
             // Measure order submission latency
             let order_start = Instant::now();
@@ -143,6 +156,8 @@ impl Simulator {
         }
 
         let total_duration = start_time.elapsed();
+        // Queued behind every order, so the engine has matched them all
+        let traded_after = self.traded().await;
         
         // Calculate metrics
         let avg_latency_us = latencies.iter().sum::<u64>() as f64 / latencies.len() as f64;
@@ -151,22 +166,25 @@ impl Simulator {
         let throughput_per_sec = config.num_orders as f64 / total_duration.as_secs_f64();
 
         // Get current order book state
-        let snapshot = self.handle.current_state.read().await;
-        let current_spread = match (snapshot.best_bid, snapshot.best_ask) {
-            (Some(bid), Some(ask)) => Some((ask - bid).to_string()),
-            _ => None,
+        let current_spread = {
+            let snapshot = self.handle.current_state.borrow();
+            match (snapshot.best_bid, snapshot.best_ask) {
+                (Some(bid), Some(ask)) => Some((ask - bid).to_string()),
+                _ => None,
+            }
         };
 
         let final_metrics = PerformanceMetrics {
             orders_submitted: config.num_orders,
-            trades_executed: 0, // Would need to track from events
+            trades_executed: traded_after.count - traded_before.count,
             avg_latency_us,
             min_latency_us,
             max_latency_us,
             throughput_per_sec,
             simulation_duration_ms: total_duration.as_millis() as u64,
             current_spread,
-            total_volume_traded: "0".to_string(), // Would need to track from events
+            total_volume_traded: (traded_after.volume - traded_before.volume).to_string(),
+            engine_latency: self.handle.order_latency().await,
         };
 
         // Update shared metrics
@@ -186,8 +204,118 @@ impl Simulator {
         final_metrics
     }
 
+    /// The engine's trade totals, or none traded if it has stopped
+    async fn traded(&self) -> TradeTotals {
+        self.handle.stats().await.map(|stats| stats.trades).unwrap_or_default()
+    }
+
     /// Get current metrics
     pub async fn get_metrics(&self) -> PerformanceMetrics {
         self.metrics.read().await.clone()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{EngineBuilder, EngineEvent};
+    use rust_decimal_macros::dec;
+
+    #[tokio::test]
+    async fn test_generated_orders_meet_min_notional() {
+        let (engine, handle) = EngineBuilder::new("BTC/USD")
+            .lot_size(dec!(0.0001))
+            .min_notional(dec!(50))
+            .event_buffer_size(10_000)
+            .build();
+        let mut events = handle.subscribe();
+        tokio::spawn(engine.run());
+        let simulator = Simulator::new(Arc::new(handle.clone()));
+
+        simulator
+            .run_simulation(SimulationConfig {
+                num_orders: 200,
+                delay_between_orders_us: 0,
+                ..Default::default()
+            })
+            .await;
+        // Let the engine drain its queue
+        handle.cancel_order(0).await.unwrap();
+
+        loop {
+            match events.recv().await.unwrap().event {
+                EngineEvent::OrderRejected { reason, .. } => panic!("order rejected: {reason}"),
+                EngineEvent::CancelRejected { .. } => break,
+                _ => {}
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_simulated_trades_carry_owners() {
+        let (engine, handle) = EngineBuilder::new("BTC/USD").event_buffer_size(10_000).build();
+        let mut events = handle.subscribe();
+        tokio::spawn(engine.run());
+        let simulator = Simulator::new(Arc::new(handle.clone()));
+
+        let metrics = simulator
+            .run_simulation(SimulationConfig {
+                num_orders: 200,
+                delay_between_orders_us: 0,
+                num_owners: 3,
+                ..Default::default()
+            })
+            .await;
+        handle.cancel_order(0).await.unwrap();
+
+        let (mut trades, mut volume) = (0, Decimal::ZERO);
+        loop {
+            match events.recv().await.unwrap().event {
+                EngineEvent::Trade(trade) => {
+                    trades += 1;
+                    volume += trade.quantity;
+                    let (taker, maker) = (trade.taker_owner_id.as_ref().unwrap(), trade.maker_owner_id.as_ref().unwrap());
+                    assert!(taker.starts_with("sim-") && maker.starts_with("sim-"));
+                    // Self-trade prevention keeps a participant off both sides
+                    assert_ne!(taker, maker);
+                }
+                EngineEvent::CancelRejected { .. } => break,
+                _ => {}
+            }
+        }
+        assert!(trades > 0);
+        // The metrics count the same trades as the feed
+        assert_eq!(metrics.trades_executed, trades);
+        assert_eq!(metrics.total_volume_traded, volume.to_string());
+    }
+
+    #[tokio::test]
+    async fn test_simulation_can_bypass_risk_limits() {
+        // Every generated quantity is above the limit
+        let (engine, handle) = EngineBuilder::new("BTC/USD")
+            .max_quantity(dec!(0.001))
+            .event_buffer_size(10_000)
+            .build();
+        let mut events = handle.subscribe();
+        tokio::spawn(engine.run());
+        let simulator = Simulator::new(Arc::new(handle.clone()));
+
+        simulator
+            .run_simulation(SimulationConfig {
+                num_orders: 50,
+                delay_between_orders_us: 0,
+                bypass_risk_limits: true,
+                ..Default::default()
+            })
+            .await;
+        handle.cancel_order(0).await.unwrap();
+
+        loop {
+            match events.recv().await.unwrap().event {
+                EngineEvent::OrderRejected { reason, .. } => panic!("order rejected: {reason}"),
+                EngineEvent::CancelRejected { .. } => break,
+                _ => {}
+            }
+        }
+    }
+}